@@ -0,0 +1,96 @@
+// 协议转换器基准 (cargo bench --features bench --bench converters)
+// 纯 CPU 计算，不启动运行时；每项先预热，再按固定时长取多轮平均，输出每次调用的耗时
+use antigravity_protocol::claude::{ClaudeRequest, GeminiResponse};
+use antigravity_protocol::openai::OpenAIRequest;
+use antigravity_tools_lib::bench::converters::{
+    transform_claude_request_in, transform_openai_request, transform_openai_response, transform_response,
+};
+use serde_json::{json, Value};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const WARMUP: Duration = Duration::from_millis(300);
+const MEASURE: Duration = Duration::from_secs(2);
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    let warmup_end = Instant::now() + WARMUP;
+    while Instant::now() < warmup_end {
+        f();
+    }
+    let started = Instant::now();
+    let mut iterations: u64 = 0;
+    while started.elapsed() < MEASURE {
+        for _ in 0..64 {
+            f();
+        }
+        iterations += 64;
+    }
+    let per_iter = started.elapsed().as_nanos() as f64 / iterations as f64;
+    println!("{:<40} {:>12.0} ns/iter ({} iterations)", name, per_iter, iterations);
+}
+
+fn conversation(turns: usize) -> Vec<Value> {
+    let filler = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20);
+    (0..turns)
+        .flat_map(|turn| {
+            [
+                json!({"role": "user", "content": format!("Question {}: {}", turn, filler)}),
+                json!({"role": "assistant", "content": format!("Answer {}: {}", turn, filler)}),
+            ]
+        })
+        .chain(std::iter::once(json!({"role": "user", "content": "Summarize."})))
+        .collect()
+}
+
+fn gemini_response() -> Value {
+    json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": [
+                {"text": "Let me look that up.", "thought": true, "thoughtSignature": "c2lnbmF0dXJl"},
+                {"text": "Here is the answer to your question, with enough words to be realistic."},
+                {"functionCall": {"name": "search_docs", "args": {"query": "streaming"}}}
+            ]},
+            "finishReason": "STOP",
+            "index": 0
+        }],
+        "usageMetadata": {"promptTokenCount": 1200, "candidatesTokenCount": 64, "totalTokenCount": 1264},
+        "modelVersion": "gemini-2.5-flash",
+        "responseId": "bench-response"
+    })
+}
+
+fn main() {
+    let claude_request: ClaudeRequest = serde_json::from_value(json!({
+        "model": "claude-sonnet-4-5",
+        "max_tokens": 1024,
+        "system": "You are a concise assistant.",
+        "tools": [{
+            "name": "search_docs",
+            "description": "Search the documentation",
+            "input_schema": {"type": "object", "properties": {"query": {"type": "string"}}, "required": ["query"]}
+        }],
+        "messages": conversation(10)
+    }))
+    .expect("claude request");
+    bench("claude::transform_claude_request_in", || {
+        black_box(transform_claude_request_in(black_box(&claude_request), "bench-project").ok());
+    });
+
+    let openai_request: OpenAIRequest = serde_json::from_value(json!({
+        "model": "gemini-2.5-flash",
+        "messages": conversation(10)
+    }))
+    .expect("openai request");
+    bench("openai::transform_openai_request", || {
+        black_box(transform_openai_request(black_box(&openai_request), "bench-project", "gemini-2.5-flash"));
+    });
+
+    let raw_response = gemini_response();
+    let typed_response: GeminiResponse = serde_json::from_value(raw_response.clone()).expect("gemini response");
+    bench("claude::transform_response", || {
+        black_box(transform_response(black_box(&typed_response), 1200, &[]).ok());
+    });
+    bench("openai::transform_openai_response", || {
+        black_box(transform_openai_response(black_box(&raw_response)));
+    });
+}
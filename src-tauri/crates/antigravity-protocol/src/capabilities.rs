@@ -0,0 +1,422 @@
+// 请求能力识别 (各协议共用)
+// 按模型名与工具列表判定请求类型：普通对话、联网搜索、图像生成
+
+use serde_json::{json, Value};
+
+/// Request configuration after grounding resolution
+#[derive(Debug, Clone)]
+pub struct RequestConfig {
+    /// The request type: "agent", "web_search", or "image_gen"
+    pub request_type: String,
+    /// Whether to inject the googleSearch tool
+    pub inject_google_search: bool,
+    /// The final model name (with suffixes stripped)
+    pub final_model: String,
+    /// Image generation configuration (if request_type is image_gen)
+    pub image_config: Option<Value>,
+}
+
+pub fn resolve_request_config(
+    original_model: &str, 
+    mapped_model: &str,
+    tools: &Option<Vec<Value>>
+) -> RequestConfig {
+    // 1. Image Generation Check (Priority)
+    if mapped_model.starts_with("gemini-3-pro-image") {
+        let (image_config, parsed_base_model) = parse_image_config(original_model);
+        
+        return RequestConfig {
+            request_type: "image_gen".to_string(),
+            inject_google_search: false,
+            final_model: parsed_base_model, 
+            image_config: Some(image_config),
+        };
+    }
+
+    // 检测是否有联网工具定义 (内置功能调用)
+    let has_networking_tool = detects_networking_tool(tools);
+    // 检测是否包含非联网工具 (如 MCP 本地工具)
+    let _has_non_networking = contains_non_networking_tool(tools);
+
+    // Strip -online suffix from original model if present (to detect networking intent)
+    let is_online_suffix = original_model.ends_with("-online");
+    
+    // High-quality grounding allowlist (Only for models known to support search and be relatively 'safe')
+    let _is_high_quality_model = mapped_model == "gemini-2.5-flash"
+        || mapped_model == "gemini-1.5-pro"
+        || mapped_model.starts_with("gemini-1.5-pro-")
+        || mapped_model.starts_with("gemini-2.5-flash-")
+        || mapped_model.starts_with("gemini-2.0-flash")
+        || mapped_model.starts_with("gemini-3-")
+        || mapped_model.contains("claude-3-5-sonnet")
+        || mapped_model.contains("claude-3-opus")
+        || mapped_model.contains("claude-sonnet")
+        || mapped_model.contains("claude-opus")
+        || mapped_model.contains("claude-4");
+
+    // Determine if we should enable networking
+    // [FIX] 禁用基于模型的自动联网逻辑，防止图像请求被联网搜索结果覆盖。
+    // 仅在用户显式请求联网时启用：1) -online 后缀 2) 携带联网工具定义
+    let enable_networking = is_online_suffix || has_networking_tool;
+
+    // The final model to send upstream should be the MAPPED model, 
+    // but if searching, we MUST ensure the model name is one the backend associates with search.
+    // Based on ref_Antigravity2Api practice, we force a stable search model for search requests.
+    let mut final_model = mapped_model.trim_end_matches("-online").to_string();
+    if enable_networking {
+        // If it's a thinking model (which doesn't support tools) or a Claude-style alias, 
+        // fallback to gemini-2.5-flash which is the standard workhorse for search.
+        if final_model.contains("thinking") || !final_model.starts_with("gemini-") {
+            final_model = "gemini-2.5-flash".to_string();
+        }
+    }
+
+    RequestConfig {
+        request_type: if enable_networking {
+            "web_search".to_string()
+        } else {
+            "agent".to_string()
+        },
+        inject_google_search: enable_networking,
+        final_model,
+        image_config: None,
+    }
+}
+
+/// Parse image configuration from model name suffixes
+/// Returns (image_config, clean_model_name)
+fn parse_image_config(model_name: &str) -> (Value, String) {
+    let mut aspect_ratio = "1:1";
+    let _image_size = "1024x1024"; // Default, not explicitly sent unless 4k/hd
+
+    if model_name.contains("-21x9") || model_name.contains("-21-9") { aspect_ratio = "21:9"; }
+    else if model_name.contains("-16x9") || model_name.contains("-16-9") { aspect_ratio = "16:9"; }
+    else if model_name.contains("-9x16") || model_name.contains("-9-16") { aspect_ratio = "9:16"; }
+    else if model_name.contains("-4x3") || model_name.contains("-4-3") { aspect_ratio = "4:3"; }
+    else if model_name.contains("-3x4") || model_name.contains("-3-4") { aspect_ratio = "3:4"; }
+    else if model_name.contains("-1x1") || model_name.contains("-1-1") { aspect_ratio = "1:1"; }
+
+    let is_hd = model_name.contains("-4k") || model_name.contains("-hd");
+    let is_2k = model_name.contains("-2k");
+    let image_size = if is_hd {
+        Some("4K")
+    } else if is_2k {
+        Some("2K")
+    } else {
+        None
+    };
+
+    // The upstream model must be EXACTLY "gemini-3-pro-image"
+    (build_image_config(aspect_ratio, image_size), "gemini-3-pro-image".to_string())
+}
+
+fn build_image_config(aspect_ratio: &str, image_size: Option<&str>) -> Value {
+    crate::gemini::ImageConfig {
+        aspect_ratio: aspect_ratio.to_string(),
+        image_size: image_size.map(str::to_string),
+    }
+    .to_value()
+}
+
+/// 将请求切换为图像生成模式 (OpenAI / Claude / Gemini 转换共用)
+/// 图像模型不支持工具与系统提示，且会拒绝 thinkingConfig / responseMimeType / responseModalities
+pub fn apply_image_config(inner_request: &mut Value, image_config: Value) {
+    let Some(obj) = inner_request.as_object_mut() else {
+        return;
+    };
+    obj.remove("tools");
+    obj.remove("toolConfig");
+    obj.remove("systemInstruction");
+
+    let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
+    if let Some(gen_obj) = gen_config.as_object_mut() {
+        gen_obj.remove("thinkingConfig");
+        gen_obj.remove("responseMimeType");
+        gen_obj.remove("responseModalities"); // Cherry Studio sends this, might conflict
+        gen_obj.insert("imageConfig".to_string(), image_config);
+    }
+}
+
+/// Map OpenAI Images API `size` / `quality` onto the same imageConfig used for model suffixes.
+/// The aspect ratio is the supported ratio closest to WxH; "auto" or unparsable sizes fall back to 1:1.
+pub fn image_config_from_openai(size: &str, quality: &str) -> Value {
+    const RATIOS: [(&str, f64); 6] = [
+        ("21:9", 21.0 / 9.0),
+        ("16:9", 16.0 / 9.0),
+        ("4:3", 4.0 / 3.0),
+        ("1:1", 1.0),
+        ("3:4", 3.0 / 4.0),
+        ("9:16", 9.0 / 16.0),
+    ];
+
+    let dims = size
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)))
+        .filter(|(w, h)| *w > 0 && *h > 0);
+
+    let aspect_ratio = match dims {
+        Some((w, h)) => {
+            let ratio = w as f64 / h as f64;
+            RATIOS
+                .iter()
+                .min_by(|a, b| (a.1 - ratio).abs().total_cmp(&(b.1 - ratio).abs()))
+                .map(|r| r.0)
+                .unwrap_or("1:1")
+        }
+        None => "1:1",
+    };
+
+    let longest = dims.map(|(w, h)| w.max(h)).unwrap_or(0);
+    let image_size = if matches!(quality, "hd" | "high") || longest >= 3840 {
+        Some("4K")
+    } else if longest >= 2048 {
+        Some("2K")
+    } else {
+        None
+    };
+
+    build_image_config(aspect_ratio, image_size)
+}
+
+/// Inject current googleSearch tool and ensure no duplicate legacy search tools
+/// 根据扩展名推断图片 MIME 类型 (忽略 URL 查询参数)，未知时回退为 image/jpeg
+pub fn guess_image_mime(path: &str) -> &'static str {
+    let path = path.split(['?', '#']).next().unwrap_or(path).to_lowercase();
+    if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".gif") {
+        "image/gif"
+    } else if path.ends_with(".webp") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+pub fn inject_google_search_tool(body: &mut Value) {
+    if let Some(obj) = body.as_object_mut() {
+        let tools_entry = obj.entry("tools").or_insert_with(|| json!([]));
+        if let Some(tools_arr) = tools_entry.as_array_mut() {
+            // [安全校验] 如果数组中已经包含 functionDeclarations，严禁注入 googleSearch
+            // 因为 Gemini v1internal 不支持在一次请求中混用 search 和 functions
+            let has_functions = tools_arr.iter().any(|t| {
+                t.as_object().is_some_and(|o| o.contains_key("functionDeclarations"))
+            });
+
+            if has_functions {
+                tracing::debug!("Skipping googleSearch injection due to existing functionDeclarations");
+                return;
+            }
+
+            // 首先清理掉已存在的 googleSearch 或 googleSearchRetrieval，以防重复产生冲突
+            tools_arr.retain(|t| {
+                if let Some(o) = t.as_object() {
+                    !(o.contains_key("googleSearch") || o.contains_key("googleSearchRetrieval"))
+                } else {
+                    true
+                }
+            });
+
+            // 注入统一的 googleSearch (v1internal 规范)
+            tools_arr.push(json!({
+                "googleSearch": {}
+            }));
+        }
+    }
+}
+
+/// 深度迭代清理客户端发送的 `[undefined]` 脏字符串，防止 Gemini 接口校验失败
+pub fn deep_clean_undefined(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            // 移除值为 "[undefined]" 的键
+            map.retain(|_, v| {
+                if let Some(s) = v.as_str() {
+                    s != "[undefined]"
+                } else {
+                    true
+                }
+            });
+            // 递归处理嵌套
+            for v in map.values_mut() {
+                deep_clean_undefined(v);
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr.iter_mut() {
+                deep_clean_undefined(v);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Detects if the tool list contains a request for networking/web search.
+/// Supported keywords: "web_search", "google_search", "web_search_20250305"
+pub fn detects_networking_tool(tools: &Option<Vec<Value>>) -> bool {
+    if let Some(list) = tools {
+        for tool in list {
+            // 1. 直发风格 (Claude/Simple OpenAI/Anthropic Builtin/Vertex): { "name": "..." } 或 { "type": "..." }
+            if let Some(n) = tool.get("name").and_then(|v| v.as_str()) {
+                if n == "web_search" || n == "google_search" || n == "web_search_20250305" || n == "google_search_retrieval" {
+                    return true;
+                }
+            }
+
+            if let Some(t) = tool.get("type").and_then(|v| v.as_str()) {
+                if t == "web_search_20250305" || t == "google_search" || t == "web_search" || t == "google_search_retrieval" {
+                    return true;
+                }
+            }
+
+            // 2. OpenAI 嵌套风格: { "type": "function", "function": { "name": "..." } }
+            if let Some(func) = tool.get("function") {
+                if let Some(n) = func.get("name").and_then(|v| v.as_str()) {
+                    let keywords = ["web_search", "google_search", "web_search_20250305", "google_search_retrieval"];
+                    if keywords.contains(&n) {
+                        return true;
+                    }
+                }
+            }
+
+            // 3. Gemini 原生风格: { "functionDeclarations": [ { "name": "..." } ] }
+            if let Some(decls) = tool.get("functionDeclarations").and_then(|v| v.as_array()) {
+                for decl in decls {
+                    if let Some(n) = decl.get("name").and_then(|v| v.as_str()) {
+                        if n == "web_search" || n == "google_search" || n == "google_search_retrieval" {
+                            return true;
+                        }
+                    }
+                }
+            }
+
+            // 4. Gemini googleSearch 声明 (含 googleSearchRetrieval 变体)
+            if tool.get("googleSearch").is_some() || tool.get("googleSearchRetrieval").is_some() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// 探测是否包含非联网相关的本地函数工具
+pub fn contains_non_networking_tool(tools: &Option<Vec<Value>>) -> bool {
+    if let Some(list) = tools {
+        for tool in list {
+            let mut is_networking = false;
+            
+            // 简单逻辑：如果它是一个函数声明且名字不是联网关键词，则视为非联网工具
+            if let Some(n) = tool.get("name").and_then(|v| v.as_str()) {
+                 let keywords = ["web_search", "google_search", "web_search_20250305", "google_search_retrieval"];
+                 if keywords.contains(&n) { is_networking = true; }
+            } else if let Some(func) = tool.get("function") {
+                 if let Some(n) = func.get("name").and_then(|v| v.as_str()) {
+                     let keywords = ["web_search", "google_search", "web_search_20250305", "google_search_retrieval"];
+                     if keywords.contains(&n) { is_networking = true; }
+                 }
+            } else if tool.get("googleSearch").is_some() || tool.get("googleSearchRetrieval").is_some() {
+                is_networking = true;
+            } else if tool.get("functionDeclarations").is_some() {
+                // 如果是 Gemini 风格的 functionDeclarations，进去看一眼
+                if let Some(decls) = tool.get("functionDeclarations").and_then(|v| v.as_array()) {
+                    for decl in decls {
+                        if let Some(n) = decl.get("name").and_then(|v| v.as_str()) {
+                            let keywords = ["web_search", "google_search", "google_search_retrieval"];
+                            if !keywords.contains(&n) {
+                                return true; // 发现本地函数
+                            }
+                        }
+                    }
+                }
+                is_networking = true; // 即使全是联网，外层也标记为联网
+            }
+
+            if !is_networking {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_high_quality_model_auto_grounding() {
+        // Auto-grounding is currently disabled by default due to conflict with image gen
+        let config = resolve_request_config("gpt-4o", "gemini-2.5-flash", &None);
+        assert_eq!(config.request_type, "agent");
+        assert!(!config.inject_google_search);
+    }
+
+    #[test]
+    fn test_gemini_native_tool_detection() {
+        let tools = Some(vec![json!({
+            "functionDeclarations": [
+                { "name": "web_search", "parameters": {} }
+            ]
+        })]);
+        assert!(detects_networking_tool(&tools));
+    }
+
+    #[test]
+    fn test_online_suffix_force_grounding() {
+        let config = resolve_request_config("gemini-3-flash-online", "gemini-3-flash", &None);
+        assert_eq!(config.request_type, "web_search");
+        assert!(config.inject_google_search);
+        assert_eq!(config.final_model, "gemini-3-flash");
+    }
+
+    #[test]
+    fn test_default_no_grounding() {
+        let config = resolve_request_config("claude-sonnet", "gemini-3-flash", &None);
+        assert_eq!(config.request_type, "agent");
+        assert!(!config.inject_google_search);
+    }
+
+    #[test]
+    fn test_image_model_excluded() {
+        let config = resolve_request_config("gemini-3-pro-image", "gemini-3-pro-image", &None);
+        assert_eq!(config.request_type, "image_gen");
+        assert!(!config.inject_google_search);
+    }
+
+    #[test]
+    fn test_image_2k_and_ultrawide_config() {
+        // Test 2K
+        let (config_2k, _) = parse_image_config("gemini-3-pro-image-2k");
+        assert_eq!(config_2k["imageSize"], "2K");
+
+        // Test 21:9
+        let (config_21x9, _) = parse_image_config("gemini-3-pro-image-21x9");
+        assert_eq!(config_21x9["aspectRatio"], "21:9");
+
+        // Test Combined (if logic allows, though suffix parsing is greedy)
+         let (config_combined, _) = parse_image_config("gemini-3-pro-image-2k-21x9");
+         assert_eq!(config_combined["imageSize"], "2K");
+         assert_eq!(config_combined["aspectRatio"], "21:9");
+
+         // Test 4K + 21:9
+         let (config_4k_wide, _) = parse_image_config("gemini-3-pro-image-4k-21x9");
+         assert_eq!(config_4k_wide["imageSize"], "4K");
+         assert_eq!(config_4k_wide["aspectRatio"], "21:9");
+    }
+
+    #[test]
+    fn test_image_config_from_openai_size_and_quality() {
+        let config = image_config_from_openai("1024x1024", "standard");
+        assert_eq!(config, json!({"aspectRatio": "1:1"}));
+
+        assert_eq!(image_config_from_openai("1792x1024", "standard")["aspectRatio"], "16:9");
+        assert_eq!(image_config_from_openai("1024x1792", "standard")["aspectRatio"], "9:16");
+        assert_eq!(image_config_from_openai("1792x768", "standard")["aspectRatio"], "21:9");
+        assert_eq!(image_config_from_openai("1280x960", "standard")["aspectRatio"], "4:3");
+        assert_eq!(image_config_from_openai("auto", "standard")["aspectRatio"], "1:1");
+
+        assert_eq!(image_config_from_openai("1024x1024", "hd")["imageSize"], "4K");
+        assert_eq!(image_config_from_openai("2048x2048", "standard")["imageSize"], "2K");
+        assert_eq!(image_config_from_openai("3840x2160", "standard"), json!({"aspectRatio": "16:9", "imageSize": "4K"}));
+    }
+}
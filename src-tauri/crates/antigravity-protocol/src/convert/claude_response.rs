@@ -0,0 +1,565 @@
+// Claude 非流式响应转换 (Gemini → Claude)
+// 对应 NonStreamingProcessor
+
+use super::ResponseOptions;
+use crate::claude::*;
+use crate::media::{render_inline_data, InlineMediaMode};
+use crate::usage::{estimate_text_tokens, estimated_claude_usage, to_claude_usage};
+use crate::warnings::Warnings;
+
+/// 找出输出末尾命中的客户端停止序列 (取最长匹配)
+/// Gemini 只返回 finishReason=STOP、不指明命中的序列，且通常会剔除该序列；
+/// 仅当序列仍出现在输出末尾时才能还原 stop_reason = "stop_sequence"
+pub fn matched_stop_sequence<'a>(text: &str, stop_sequences: &'a [String]) -> Option<&'a String> {
+    stop_sequences
+        .iter()
+        .filter(|s| !s.is_empty() && text.ends_with(s.as_str()))
+        .max_by_key(|s| s.len())
+}
+
+/// 8 位随机 ID (tool_use id / 消息 id 兜底)
+pub(crate) fn random_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_string()
+}
+
+/// 非流式响应处理器
+pub struct NonStreamingProcessor {
+    content_blocks: Vec<ContentBlock>,
+    text_builder: String,
+    thinking_builder: String,
+    thinking_signature: Option<String>,
+    trailing_signature: Option<String>,
+    has_tool_call: bool,
+    // [NEW] 上游缺失 usageMetadata 时使用的输入 Token 估算值
+    estimated_input_tokens: u32,
+    // 客户端 stop_sequences，用于还原 stop_reason = "stop_sequence"
+    stop_sequences: Vec<String>,
+    // 非图片 inlineData 的输出方式
+    inline_media: InlineMediaMode,
+}
+
+impl Default for NonStreamingProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NonStreamingProcessor {
+    pub fn new() -> Self {
+        Self {
+            content_blocks: Vec::new(),
+            text_builder: String::new(),
+            thinking_builder: String::new(),
+            thinking_signature: None,
+            trailing_signature: None,
+            has_tool_call: false,
+            estimated_input_tokens: 0,
+            stop_sequences: Vec::new(),
+            inline_media: InlineMediaMode::default(),
+        }
+    }
+
+    /// 按输出内容估算 output_tokens
+    fn estimate_output_tokens(&self) -> u32 {
+        let total: u64 = self
+            .content_blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text { text } => estimate_text_tokens(text),
+                ContentBlock::Thinking { thinking, .. } => estimate_text_tokens(thinking),
+                ContentBlock::ToolUse { name, input, .. } => {
+                    estimate_text_tokens(name) + estimate_text_tokens(&input.to_string())
+                }
+                _ => 0,
+            })
+            .sum();
+        total.min(u32::MAX as u64) as u32
+    }
+
+    /// 处理 Gemini 响应并转换为 Claude 响应
+    pub fn process(&mut self, gemini_response: &GeminiResponse, warnings: &mut Warnings) -> ClaudeResponse {
+        // 获取 parts
+        let empty_parts = vec![];
+        let parts = gemini_response
+            .candidates
+            .as_ref()
+            .and_then(|c| c.first())
+            .and_then(|candidate| candidate.content.as_ref())
+            .map(|content| &content.parts)
+            .unwrap_or(&empty_parts);
+
+        // 处理所有 parts
+        for part in parts {
+            self.process_part(part, warnings);
+        }
+
+        // 处理 grounding(web search) -> 转换为 server_tool_use / web_search_tool_result
+        if let Some(candidate) = gemini_response.candidates.as_ref().and_then(|c| c.first()) {
+            if let Some(grounding) = &candidate.grounding_metadata {
+                self.process_grounding(grounding);
+            }
+        }
+
+        // 刷新剩余内容
+        self.flush_thinking();
+        self.flush_text();
+
+        // 处理 trailingSignature (空 text 带签名)
+        if let Some(signature) = self.trailing_signature.take() {
+            self.content_blocks.push(ContentBlock::Thinking {
+                thinking: String::new(),
+                signature: Some(signature),
+                cache_control: None,
+            });
+        }
+
+        // 构建响应
+        self.build_response(gemini_response)
+    }
+
+    /// 处理单个 part
+    fn process_part(&mut self, part: &GeminiPart, warnings: &mut Warnings) {
+        let signature = part.thought_signature.clone();
+
+        // 1. FunctionCall 处理
+        if let Some(fc) = &part.function_call {
+            self.flush_thinking();
+            self.flush_text();
+
+            // 处理 trailingSignature (B4/C3 场景)
+            if let Some(trailing_sig) = self.trailing_signature.take() {
+                self.content_blocks.push(ContentBlock::Thinking {
+                    thinking: String::new(),
+                    signature: Some(trailing_sig),
+                    cache_control: None,
+                });
+            }
+
+            self.has_tool_call = true;
+
+            // 生成 tool_use id
+            let tool_id = fc.id.clone().unwrap_or_else(|| {
+                format!("{}-{}", fc.name, random_id())
+            });
+
+            let mut tool_use = ContentBlock::ToolUse {
+                id: tool_id,
+                name: fc.name.clone(),
+                input: fc.args.clone().unwrap_or(serde_json::json!({})),
+                signature: None,
+                cache_control: None,
+            };
+
+            // 只使用 FC 自己的签名
+            if let ContentBlock::ToolUse { signature: sig, .. } = &mut tool_use {
+                *sig = signature;
+            }
+
+            self.content_blocks.push(tool_use);
+            return;
+        }
+
+        // 2. Text 处理
+        if let Some(text) = &part.text {
+            if part.thought.unwrap_or(false) {
+                // Thinking part
+                self.flush_text();
+
+                // 处理 trailingSignature
+                if let Some(trailing_sig) = self.trailing_signature.take() {
+                    self.flush_thinking();
+                    self.content_blocks.push(ContentBlock::Thinking {
+                        thinking: String::new(),
+                        signature: Some(trailing_sig),
+                        cache_control: None,
+                    });
+                }
+
+                self.thinking_builder.push_str(text);
+                if signature.is_some() {
+                    self.thinking_signature = signature;
+                }
+            } else {
+                // 普通 Text
+                if text.is_empty() {
+                    // 空 text 带签名 - 暂存到 trailingSignature
+                    if signature.is_some() {
+                        self.trailing_signature = signature;
+                    }
+                    return;
+                }
+
+                self.flush_thinking();
+
+                // 处理之前的 trailingSignature
+                if let Some(trailing_sig) = self.trailing_signature.take() {
+                    self.flush_text();
+                    self.content_blocks.push(ContentBlock::Thinking {
+                        thinking: String::new(),
+                        signature: Some(trailing_sig),
+                        cache_control: None,
+                    });
+                }
+
+                self.text_builder.push_str(text);
+
+                // 非空 text 带签名 - 立即刷新并输出空 thinking 块
+                if let Some(sig) = signature {
+                    self.flush_text();
+                    self.content_blocks.push(ContentBlock::Thinking {
+                        thinking: String::new(),
+                        signature: Some(sig),
+                        cache_control: None,
+                    });
+                }
+            }
+        }
+
+        // 3. InlineData 处理 (按 mimeType 渲染为图片 / 链接)
+        if let Some(img) = &part.inline_data {
+            self.flush_thinking();
+
+            if let Some(markdown) = render_inline_data(self.inline_media, &img.mime_type, &img.data, warnings) {
+                self.text_builder.push_str(&markdown);
+                self.flush_text();
+            }
+        }
+    }
+
+    /// 处理 Grounding 元数据 (Web Search 结果)
+    fn process_grounding(&mut self, grounding: &GroundingMetadata) {
+        let mut grounding_text = String::new();
+
+        // 1. 处理搜索词
+        if let Some(queries) = &grounding.web_search_queries {
+            if !queries.is_empty() {
+                grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
+                grounding_text.push_str(&queries.join(", "));
+            }
+        }
+
+        // 2. 处理来源链接 (Chunks)
+        if let Some(chunks) = &grounding.grounding_chunks {
+            let mut links = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                if let Some(web) = &chunk.web {
+                    let title = web.title.as_deref().unwrap_or("网页来源");
+                    let uri = web.uri.as_deref().unwrap_or("#");
+                    links.push(format!("[{}] [{}]({})", i + 1, title, uri));
+                }
+            }
+
+            if !links.is_empty() {
+                grounding_text.push_str("\n\n**🌐 来源引文：**\n");
+                grounding_text.push_str(&links.join("\n"));
+            }
+        }
+
+        if !grounding_text.is_empty() {
+            // 在常规内容前后刷新并插入文本
+            self.flush_thinking();
+            self.flush_text();
+            self.text_builder.push_str(&grounding_text);
+            self.flush_text();
+        }
+    }
+
+    /// 刷新 text builder
+    fn flush_text(&mut self) {
+        if self.text_builder.is_empty() {
+            return;
+        }
+
+        self.content_blocks.push(ContentBlock::Text {
+            text: self.text_builder.clone(),
+        });
+        self.text_builder.clear();
+    }
+
+    /// 刷新 thinking builder
+    fn flush_thinking(&mut self) {
+        // 如果既没有内容也没有签名，直接返回
+        if self.thinking_builder.is_empty() && self.thinking_signature.is_none() {
+            return;
+        }
+
+        let thinking = self.thinking_builder.clone();
+        let signature = self.thinking_signature.take();
+
+        self.content_blocks.push(ContentBlock::Thinking {
+            thinking,
+            signature,
+            cache_control: None,
+        });
+        self.thinking_builder.clear();
+    }
+
+    /// 构建最终响应
+    fn build_response(&self, gemini_response: &GeminiResponse) -> ClaudeResponse {
+        let finish_reason = gemini_response
+            .candidates
+            .as_ref()
+            .and_then(|c| c.first())
+            .and_then(|candidate| candidate.finish_reason.as_deref());
+
+        // 命中的停止序列仍在输出末尾时，按 Claude 语义从正文中去除
+        let mut content = self.content_blocks.clone();
+        let mut stop_sequence = None;
+        if !self.has_tool_call && finish_reason == Some("STOP") {
+            if let Some(ContentBlock::Text { text }) = content.last_mut() {
+                if let Some(stop) = matched_stop_sequence(text, &self.stop_sequences) {
+                    text.truncate(text.len() - stop.len());
+                    stop_sequence = Some(stop.clone());
+                }
+            }
+        }
+
+        let stop_reason = if self.has_tool_call {
+            "tool_use"
+        } else if finish_reason == Some("MAX_TOKENS") {
+            "max_tokens"
+        } else if stop_sequence.is_some() {
+            "stop_sequence"
+        } else {
+            "end_turn"
+        };
+
+        let usage = match gemini_response.usage_metadata.as_ref() {
+            Some(u) => {
+                let mut usage = to_claude_usage(u);
+                if u.prompt_token_count.is_none() {
+                    usage.input_tokens = self.estimated_input_tokens;
+                }
+                if u.candidates_token_count.is_none() {
+                    usage.output_tokens = self.estimate_output_tokens();
+                }
+                usage
+            }
+            None => estimated_claude_usage(self.estimated_input_tokens, self.estimate_output_tokens()),
+        };
+
+        ClaudeResponse {
+            id: gemini_response
+                .response_id
+                .clone()
+                .unwrap_or_else(|| format!("msg_{}", random_id())),
+            type_: "message".to_string(),
+            role: "assistant".to_string(),
+            model: gemini_response.model_version.clone().unwrap_or_default(),
+            content,
+            stop_reason: stop_reason.to_string(),
+            stop_sequence,
+            usage,
+        }
+    }
+}
+
+/// 转换 Gemini 响应为 Claude 响应 (公共接口)
+/// `estimated_input_tokens` 为请求侧估算值，仅在上游未返回 usageMetadata 时使用
+/// `stop_sequences` 为客户端请求中的停止序列
+pub fn transform_response(
+    gemini_response: &GeminiResponse,
+    estimated_input_tokens: u32,
+    stop_sequences: &[String],
+    options: &ResponseOptions,
+    warnings: &mut Warnings,
+) -> Result<ClaudeResponse, String> {
+    let mut processor = NonStreamingProcessor::new();
+    processor.estimated_input_tokens = estimated_input_tokens;
+    processor.stop_sequences = stop_sequences.to_vec();
+    processor.inline_media = options.inline_media;
+    Ok(processor.process(gemini_response, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warnings::codes;
+
+    fn convert(gemini_resp: &GeminiResponse, stop_sequences: &[String]) -> Result<ClaudeResponse, String> {
+        transform_response(gemini_resp, 0, stop_sequences, &ResponseOptions::default(), &mut Warnings::new())
+    }
+
+    #[test]
+    fn test_matched_stop_sequence() {
+        let stops = vec!["END".to_string(), "D".to_string(), String::new()];
+        assert_eq!(matched_stop_sequence("THE END", &stops).map(String::as_str), Some("END"));
+        assert!(matched_stop_sequence("nothing", &stops).is_none());
+    }
+
+    #[test]
+    fn test_strict_inline_media_records_warning() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"inlineData": {"mimeType": "application/pdf", "data": "JVBE"}}]},
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+        let options = ResponseOptions {
+            inline_media: InlineMediaMode::Strict,
+            ..ResponseOptions::default()
+        };
+        let mut warnings = Warnings::new();
+        let claude_resp = transform_response(&gemini_resp, 0, &[], &options, &mut warnings).unwrap();
+        assert!(claude_resp.content.is_empty());
+        assert!(claude_resp.id.starts_with("msg_"));
+        assert_eq!(warnings.as_slice()[0].code, codes::INLINE_MEDIA_DROPPED);
+    }
+
+    #[test]
+    fn test_inline_audio_is_not_rendered_as_image() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        {"inlineData": {"mimeType": "image/jpeg", "data": "/9j/"}},
+                        {"inlineData": {"mimeType": "audio/mpeg", "data": "SUQz"}}
+                    ]
+                },
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+
+        let claude_resp = convert(&gemini_resp, &[]).unwrap();
+        let text: String = claude_resp
+            .content
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(text.contains("![image](data:image/jpeg;base64,/9j/)"));
+        assert!(text.contains("[audio (audio/mpeg)](data:audio/mpeg;base64,SUQz)"));
+    }
+
+    #[test]
+    fn test_simple_text_response() {
+        let gemini_resp = GeminiResponse {
+            candidates: Some(vec![Candidate {
+                content: Some(GeminiContent {
+                    role: "model".to_string(),
+                    parts: vec![GeminiPart {
+                        text: Some("Hello, world!".to_string()),
+                        thought: None,
+                        thought_signature: None,
+                        function_call: None,
+                        function_response: None,
+                        inline_data: None,
+                    }],
+                }),
+                finish_reason: Some("STOP".to_string()),
+                index: Some(0),
+                grounding_metadata: None,
+            }]),
+            usage_metadata: Some(UsageMetadata {
+                prompt_token_count: Some(10),
+                candidates_token_count: Some(5),
+                total_token_count: Some(15),
+                cached_content_token_count: None,
+            }),
+            model_version: Some("gemini-2.5-pro".to_string()),
+            response_id: Some("resp_123".to_string()),
+        };
+
+        let result = convert(&gemini_resp, &[]);
+        assert!(result.is_ok());
+
+        let claude_resp = result.unwrap();
+        assert_eq!(claude_resp.role, "assistant");
+        assert_eq!(claude_resp.stop_reason, "end_turn");
+        assert_eq!(claude_resp.content.len(), 1);
+
+        match &claude_resp.content[0] {
+            ContentBlock::Text { text } => {
+                assert_eq!(text, "Hello, world!");
+            }
+            _ => panic!("Expected Text block"),
+        }
+    }
+
+    #[test]
+    fn test_thinking_with_signature() {
+        let gemini_resp = GeminiResponse {
+            candidates: Some(vec![Candidate {
+                content: Some(GeminiContent {
+                    role: "model".to_string(),
+                    parts: vec![
+                        GeminiPart {
+                            text: Some("Let me think...".to_string()),
+                            thought: Some(true),
+                            thought_signature: Some("sig123".to_string()),
+                            function_call: None,
+                            function_response: None,
+                            inline_data: None,
+                        },
+                        GeminiPart {
+                            text: Some("The answer is 42".to_string()),
+                            thought: None,
+                            thought_signature: None,
+                            function_call: None,
+                            function_response: None,
+                            inline_data: None,
+                        },
+                    ],
+                }),
+                finish_reason: Some("STOP".to_string()),
+                index: Some(0),
+                grounding_metadata: None,
+            }]),
+            usage_metadata: None,
+            model_version: Some("gemini-2.5-pro".to_string()),
+            response_id: Some("resp_456".to_string()),
+        };
+
+        let result = convert(&gemini_resp, &[]);
+        assert!(result.is_ok());
+
+        let claude_resp = result.unwrap();
+        assert_eq!(claude_resp.content.len(), 2);
+
+        match &claude_resp.content[0] {
+            ContentBlock::Thinking {
+                thinking,
+                signature,
+                ..
+            } => {
+                assert_eq!(thinking, "Let me think...");
+                assert_eq!(signature.as_deref(), Some("sig123"));
+            }
+            _ => panic!("Expected Thinking block"),
+        }
+
+        match &claude_resp.content[1] {
+            ContentBlock::Text { text } => {
+                assert_eq!(text, "The answer is 42");
+            }
+            _ => panic!("Expected Text block"),
+        }
+    }
+
+    #[test]
+    fn test_client_stop_sequence_is_reported_and_stripped() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Thought: done\nObservation:"}]},
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+
+        let stops = vec!["END".to_string(), "Observation:".to_string()];
+        let claude_resp = convert(&gemini_resp, &stops).unwrap();
+        assert_eq!(claude_resp.stop_reason, "stop_sequence");
+        assert_eq!(claude_resp.stop_sequence.as_deref(), Some("Observation:"));
+        assert!(matches!(&claude_resp.content[0], ContentBlock::Text { text } if text == "Thought: done\n"));
+
+        // 上游已剔除停止序列时无法判断是否命中，保持 end_turn
+        let claude_resp = convert(&gemini_resp, &["END".to_string()]).unwrap();
+        assert_eq!(claude_resp.stop_reason, "end_turn");
+        assert!(claude_resp.stop_sequence.is_none());
+    }
+}
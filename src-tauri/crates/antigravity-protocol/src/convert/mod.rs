@@ -0,0 +1,61 @@
+// 协议转换 (OpenAI / Claude <-> Gemini v1internal，非流式)
+// 转换函数只读取显式传入的选项，不访问进程级配置或签名缓存：
+// - 请求侧：safetySettings、默认 thinking 预算、可回填的 thoughtSignature
+// - 响应侧：是否输出思维链、inlineData 输出方式
+// 转换期间的非致命修正写入调用方传入的 Warnings。
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::gemini::default_safety_settings;
+use crate::media::InlineMediaMode;
+
+pub mod claude_request;
+pub mod claude_response;
+pub mod openai_request;
+pub mod openai_response;
+
+/// 客户端开启 thinking 但未给出 budget_tokens 时的默认预算
+pub const DEFAULT_THINKING_BUDGET: u32 = 8191;
+
+/// 请求转换选项
+#[derive(Debug, Clone)]
+pub struct RequestOptions {
+    /// 写入上游请求的 safetySettings
+    pub safety_settings: Value,
+    /// Claude 请求开启 thinking 但未给出 budget_tokens 时使用的预算
+    pub default_thinking_budget: u32,
+    /// 最近一次响应中的 thoughtSignature (工具调用缺少签名时回填)
+    pub thought_signature: Option<String>,
+    /// tool_use id -> thoughtSignature (Claude 工具调用的签名缓存)
+    pub tool_signatures: HashMap<String, String>,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self {
+            safety_settings: default_safety_settings(),
+            default_thinking_budget: DEFAULT_THINKING_BUDGET,
+            thought_signature: None,
+            tool_signatures: HashMap::new(),
+        }
+    }
+}
+
+/// 响应转换选项
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseOptions {
+    /// OpenAI 响应是否输出 reasoning_content
+    pub emit_reasoning: bool,
+    /// 非图片 inlineData 的输出方式
+    pub inline_media: InlineMediaMode,
+}
+
+impl Default for ResponseOptions {
+    fn default() -> Self {
+        Self {
+            emit_reasoning: true,
+            inline_media: InlineMediaMode::default(),
+        }
+    }
+}
@@ -0,0 +1,708 @@
+// OpenAI → Gemini 请求转换
+use serde_json::{json, Value};
+
+use super::RequestOptions;
+use crate::capabilities;
+use crate::gemini::{GenerationConfig, SystemInstruction, V1InternalRequest, PENALTY_RANGE};
+use crate::openai::*;
+use crate::warnings::{codes, Warnings};
+
+/// n > 1 需要上游支持 candidateCount：图像模型与 thinking 模型只能生成单个候选
+pub fn validate_candidate_count(request: &OpenAIRequest, model: &str) -> Result<(), String> {
+    let n = request.candidate_count();
+    if n <= 1 {
+        return Ok(());
+    }
+    let m = model.to_lowercase();
+    if m.contains("image") || m.contains("thinking") {
+        return Err(format!(
+            "n={} is not supported for model '{}': image and thinking models return a single candidate. \
+             Send n=1, or issue {} separate requests.",
+            n, model, n
+        ));
+    }
+    Ok(())
+}
+
+/// 惩罚参数超出上游范围时截断到边界并记录警告 (不拒绝请求)
+fn clamp_penalty(field: &str, value: Option<f64>, warnings: &mut Warnings) -> Option<f64> {
+    let value = value?;
+    if PENALTY_RANGE.contains(&value) {
+        return Some(value);
+    }
+    let clamped = value.clamp(*PENALTY_RANGE.start(), *PENALTY_RANGE.end());
+    warnings.record(
+        codes::SAMPLING_ADJUSTED,
+        format!("{}={} is outside the supported range, clamped to {}", field, value, clamped),
+        Some(field.to_string()),
+    );
+    Some(clamped)
+}
+
+/// 转换 OpenAI Chat Completions 请求为 Gemini v1internal 请求体
+pub fn transform_openai_request(
+    request: &OpenAIRequest,
+    project_id: &str,
+    mapped_model: &str,
+    options: &RequestOptions,
+    warnings: &mut Warnings,
+) -> Value {
+    // 将 OpenAI 工具转为 Value 数组以便探测
+    let tools_val = request.tools.clone();
+
+    // Resolve grounding config
+    let config = capabilities::resolve_request_config(&request.model, mapped_model, &tools_val);
+
+    tracing::debug!("[Debug] OpenAI Request: original='{}', mapped='{}', type='{}', has_image_config={}", 
+        request.model, mapped_model, config.request_type, config.image_config.is_some());
+    
+    // 1. 提取所有 System Message 并注入补丁
+    let system_instructions: Vec<String> = request.messages.iter()
+        .filter(|msg| msg.role == "system")
+        .filter_map(|msg| {
+            msg.content.as_ref().map(|c| match c {
+                OpenAIContent::String(s) => s.clone(),
+                OpenAIContent::Array(blocks) => {
+                    blocks.iter().filter_map(|b| {
+                        if let OpenAIContentBlock::Text { text } = b {
+                            Some(text.clone())
+                        } else {
+                            None
+                        }
+                    }).collect::<Vec<_>>().join("\n")
+                }
+            })
+        })
+        .collect();
+
+
+
+    // Pre-scan to map tool_call_id to function name (for Codex)
+    let mut tool_id_to_name = std::collections::HashMap::new();
+    for msg in &request.messages {
+        if let Some(tool_calls) = &msg.tool_calls {
+            for call in tool_calls {
+                let name = &call.function.name;
+                let final_name = if name == "local_shell_call" { "shell" } else { name };
+                tool_id_to_name.insert(call.id.clone(), final_name.to_string());
+            }
+        }
+    }
+
+    // 回填最近一次响应的 thoughtSignature (PR #93 支持)
+    let global_thought_sig = options.thought_signature.as_ref();
+    if let Some(sig) = global_thought_sig {
+        tracing::debug!("使用最近一次响应的 thoughtSignature (长度: {})", sig.len());
+    }
+
+    // 2. 构建 Gemini contents (过滤掉 system)
+    let contents: Vec<Value> = request
+        .messages
+        .iter()
+        .filter(|msg| msg.role != "system")
+        .map(|msg| {
+            let role = match msg.role.as_str() {
+                "assistant" => "model",
+                "tool" | "function" => "user", 
+                _ => &msg.role,
+            };
+
+            let mut parts = Vec::new();
+            
+            // Handle content (multimodal or text)
+            if let Some(content) = &msg.content {
+                match content {
+                    OpenAIContent::String(s) => {
+                        if !s.is_empty() {
+                            parts.push(json!({"text": s}));
+                        }
+                    }
+                    OpenAIContent::Array(blocks) => {
+                        for block in blocks {
+                            match block {
+                                OpenAIContentBlock::Text { text } => {
+                                    parts.push(json!({"text": text}));
+                                }
+                                OpenAIContentBlock::ImageUrl { image_url } => {
+                                    if let Some(part) = image_url_to_part(&image_url.url) {
+                                        parts.push(part);
+                                    }
+                                }
+                                OpenAIContentBlock::Unsupported => {}
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Handle tool calls (assistant message)
+            if let Some(tool_calls) = &msg.tool_calls {
+                for tc in tool_calls.iter() {
+                    /* 暂时移除：防止 Codex CLI 界面碎片化
+                    if index == 0 && parts.is_empty() {
+                         if mapped_model.contains("gemini-3") {
+                              parts.push(json!({"text": "Thinking Process: Determining necessary tool actions."}));
+                         }
+                    }
+                    */
+
+                    let args = serde_json::from_str::<Value>(&tc.function.arguments).unwrap_or(json!({}));
+                    let mut func_call_part = json!({
+                        "functionCall": {
+                            "name": if tc.function.name == "local_shell_call" { "shell" } else { &tc.function.name },
+                            "args": args,
+                            "id": tc.id
+                        }
+                    });
+
+                    // [修复] 为该消息内的所有工具调用注入 thoughtSignature (PR #114 优化)
+                    if let Some(sig) = global_thought_sig {
+                        func_call_part["thoughtSignature"] = json!(sig);
+                    }
+
+                    parts.push(func_call_part);
+                }
+            }
+
+            // Handle tool response
+            if msg.role == "tool" || msg.role == "function" {
+                let name = msg.name.as_deref().unwrap_or("unknown");
+                let final_name = if name == "local_shell_call" { "shell" } 
+                                else if let Some(id) = &msg.tool_call_id { tool_id_to_name.get(id).map(|s| s.as_str()).unwrap_or(name) }
+                                else { name };
+
+                let content_val = match &msg.content {
+                    Some(OpenAIContent::String(s)) => s.clone(),
+                    Some(OpenAIContent::Array(blocks)) => blocks.iter().filter_map(|b| if let OpenAIContentBlock::Text { text } = b { Some(text.clone()) } else { None }).collect::<Vec<_>>().join("\n"),
+                    None => "".to_string()
+                };
+
+                let mut func_resp = json!({
+                    "name": final_name,
+                    "response": { "result": content_val }
+                });
+                // [NEW] 回传 tool_call_id，保证多次同名调用时上游能正确配对
+                if let Some(id) = &msg.tool_call_id {
+                    func_resp["id"] = json!(id);
+                }
+                parts.push(json!({ "functionResponse": func_resp }));
+            }
+
+            json!({ "role": role, "parts": parts })
+        })
+        .collect();
+
+    // [PR #合并] 合并连续相同角色的消息 (Gemini 强制要求 user/model 交替)
+    let mut merged_contents: Vec<Value> = Vec::new();
+    for msg in contents {
+        if let Some(last) = merged_contents.last_mut() {
+            if last["role"] == msg["role"] {
+                // 合并 parts
+                if let (Some(last_parts), Some(msg_parts)) = (last["parts"].as_array_mut(), msg["parts"].as_array()) {
+                    last_parts.extend(msg_parts.iter().cloned());
+                    continue;
+                }
+            }
+        }
+        merged_contents.push(msg);
+    }
+    let contents = merged_contents;
+
+    // 3. 构建请求体
+    // 采样参数仅在客户端指定时透传，模型默认值由 sampling 模块按模型家族补充
+    let mut gen_config = GenerationConfig::new()
+        .max_output_tokens(request.max_tokens.unwrap_or(64000))
+        .temperature(request.temperature.map(f64::from))
+        .top_p(request.top_p.map(f64::from))
+        .top_k(request.top_k)
+        .presence_penalty(clamp_penalty("presence_penalty", request.presence_penalty, warnings))
+        .frequency_penalty(clamp_penalty("frequency_penalty", request.frequency_penalty, warnings));
+    if request.n.is_some() {
+        gen_config = gen_config.candidate_count(request.candidate_count());
+    }
+
+    let stop_sequences = request.stop_sequences();
+    if !stop_sequences.is_empty() {
+        gen_config = gen_config.stop_sequences(stop_sequences);
+    }
+
+    if let Some(fmt) = &request.response_format {
+        if fmt.wants_json() {
+            gen_config.response_mime_type = Some("application/json".to_string());
+        }
+        // 无效 schema 已在 handler 中以 400 拒绝，这里只处理可转换的情况
+        if let Ok(Some(schema)) = fmt.gemini_schema() {
+            gen_config.response_schema = Some(schema);
+        }
+    }
+
+    let mut inner_request = json!({
+        "contents": contents,
+        "generationConfig": gen_config.to_value(),
+        "safetySettings": options.safety_settings.clone(),
+    });
+
+    // 深度清理 [undefined] 字符串 (Cherry Studio 等客户端常见注入)
+    capabilities::deep_clean_undefined(&mut inner_request);
+
+    // 4. Handle Tools (Merged Cleaning)
+    if let Some(tools) = &request.tools {
+        let mut function_declarations: Vec<Value> = Vec::new();
+        for tool in tools.iter() {
+            let mut gemini_func = if let Some(func) = tool.get("function") {
+                func.clone()
+            } else {
+                let mut func = tool.clone();
+                if let Some(obj) = func.as_object_mut() {
+                    obj.remove("type");
+                    obj.remove("strict");
+                    obj.remove("additionalProperties");
+                }
+                func
+            };
+
+            if let Some(name) = gemini_func.get("name").and_then(|v| v.as_str()) {
+                // 跳过内置联网工具名称，避免重复定义
+                if name == "web_search" || name == "google_search" || name == "web_search_20250305" {
+                    continue;
+                }
+                
+                if name == "local_shell_call" {
+                    if let Some(obj) = gemini_func.as_object_mut() {
+                        obj.insert("name".to_string(), json!("shell"));
+                    }
+                }
+            }
+
+            // [NEW CRITICAL FIX] 清除函数定义根层级的非法字段 (解决报错持久化)
+            if let Some(obj) = gemini_func.as_object_mut() {
+                obj.remove("format");
+                obj.remove("strict");
+                obj.remove("additionalProperties");
+                obj.remove("type"); // [NEW] Gemini 不支持在 FunctionDeclaration 根层级出现 type: "function"
+            }
+
+            if let Some(params) = gemini_func.get_mut("parameters") {
+                // [DEEP FIX] 统一调用公共库清洗：展开 $ref 并剔除所有层级的 format/definitions
+                crate::json_schema::clean_json_schema(params);
+
+                // Gemini v1internal 要求：
+                // 1. type 必须是大写 (OBJECT, STRING 等)
+                // 2. 根对象必须有 "type": "OBJECT"
+                if let Some(params_obj) = params.as_object_mut() {
+                    if !params_obj.contains_key("type") {
+                        params_obj.insert("type".to_string(), json!("OBJECT"));
+                    }
+                }
+                
+                // 递归转换 type 为大写 (符合 Protobuf 定义)
+                enforce_uppercase_types(params);
+            }
+            function_declarations.push(gemini_func);
+        }
+        
+        if !function_declarations.is_empty() {
+            inner_request["tools"] = json!([{ "functionDeclarations": function_declarations }]);
+
+            // [NEW] tool_choice -> toolConfig.functionCallingConfig
+            // 未指定时与 OpenAI 默认行为一致 (auto)，由模型自行决定是否调用工具
+            inner_request["toolConfig"] = request
+                .tool_choice
+                .as_ref()
+                .and_then(build_tool_config)
+                .unwrap_or_else(|| json!({ "functionCallingConfig": { "mode": "AUTO" } }));
+        }
+    }
+    
+    if !system_instructions.is_empty() {
+        inner_request["systemInstruction"] =
+            SystemInstruction::from_text(system_instructions.join("\n\n")).to_value();
+    }
+    
+    if config.inject_google_search {
+        capabilities::inject_google_search_tool(&mut inner_request);
+    }
+
+    if let Some(image_config) = config.image_config {
+        capabilities::apply_image_config(&mut inner_request, image_config);
+    }
+
+    V1InternalRequest::new(project_id, &config.final_model, &config.request_type, inner_request)
+        .with_request_id_prefix("openai")
+        .into_value()
+}
+
+/// 将 OpenAI tool_choice 转换为 Gemini toolConfig
+/// "auto" -> AUTO, "none" -> NONE, "required" -> ANY, 指定函数 -> ANY + allowedFunctionNames
+fn build_tool_config(tool_choice: &Value) -> Option<Value> {
+    let (mode, allowed) = match tool_choice {
+        Value::String(s) => match s.as_str() {
+            "auto" => ("AUTO", None),
+            "none" => ("NONE", None),
+            "required" => ("ANY", None),
+            _ => return None,
+        },
+        Value::Object(obj) => {
+            let name = obj
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(|n| n.as_str())?;
+            let name = if name == "local_shell_call" { "shell" } else { name };
+            ("ANY", Some(vec![name.to_string()]))
+        }
+        _ => return None,
+    };
+
+    let mut config = json!({ "mode": mode });
+    if let Some(names) = allowed {
+        config["allowedFunctionNames"] = json!(names);
+    }
+    Some(json!({ "functionCallingConfig": config }))
+}
+
+fn enforce_uppercase_types(value: &mut Value) {
+    if let Value::Object(map) = value {
+        if let Some(Value::String(s)) = map.get_mut("type") {
+            *s = s.to_uppercase();
+        }
+        if let Some(Value::Object(props)) = map.get_mut("properties") {
+            for v in props.values_mut() {
+                enforce_uppercase_types(v);
+            }
+        }
+        if let Some(items) = map.get_mut("items") {
+             enforce_uppercase_types(items);
+        }
+    } else if let Value::Array(arr) = value {
+        for item in arr {
+            enforce_uppercase_types(item);
+        }
+    }
+}
+
+/// 将 OpenAI image_url 转为 Gemini part
+/// - data: URL -> inlineData
+/// - http(s) URL -> fileData
+/// - 本地文件 (file:// 或路径) -> 读取后转 inlineData
+fn image_url_to_part(url: &str) -> Option<Value> {
+    if let Some(rest) = url.strip_prefix("data:") {
+        let (mime_part, data) = rest.split_once(',')?;
+        let mime_type = mime_part.split(';').next().filter(|m| !m.is_empty()).unwrap_or("image/jpeg");
+        return Some(json!({
+            "inlineData": { "mimeType": mime_type, "data": data }
+        }));
+    }
+
+    if url.starts_with("http") {
+        return Some(json!({
+            "fileData": { "fileUri": url, "mimeType": capabilities::guess_image_mime(url) }
+        }));
+    }
+
+    // [NEW] 处理本地文件路径 (file:// 或 Windows/Unix 路径)
+    let file_path = if url.starts_with("file://") {
+        // 移除 file:// 前缀
+        #[cfg(target_os = "windows")]
+        { url.trim_start_matches("file:///").replace('/', "\\") }
+        #[cfg(not(target_os = "windows"))]
+        { url.trim_start_matches("file://").to_string() }
+    } else {
+        url.to_string()
+    };
+
+    tracing::debug!("[OpenAI-Request] Reading local image: {}", file_path);
+
+    // 读取文件并转换为 base64
+    match std::fs::read(&file_path) {
+        Ok(file_bytes) => {
+            use base64::Engine as _;
+            let b64 = base64::engine::general_purpose::STANDARD.encode(&file_bytes);
+            tracing::debug!("[OpenAI-Request] Successfully loaded image: {} ({} bytes)", file_path, file_bytes.len());
+            Some(json!({
+                "inlineData": { "mimeType": capabilities::guess_image_mime(&file_path), "data": b64 }
+            }))
+        }
+        Err(_) => {
+            tracing::debug!("[OpenAI-Request] Failed to read local image: {}", file_path);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
+        transform_openai_request(request, project_id, mapped_model, &RequestOptions::default(), &mut Warnings::new())
+    }
+
+    #[test]
+    fn test_transform_openai_request_multimodal() {
+        let req = OpenAIRequest {
+            model: "gpt-4-vision".to_string(),
+            messages: vec![OpenAIMessage {
+                role: "user".to_string(),
+                content: Some(OpenAIContent::Array(vec![
+                    OpenAIContentBlock::Text { text: "What is in this image?".to_string() },
+                    OpenAIContentBlock::ImageUrl { image_url: OpenAIImageUrl { 
+                        url: "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==".to_string(),
+                        detail: None 
+                    } }
+                ])),
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            }],
+            stream: false,
+            stream_options: None,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            stop: None,
+            response_format: None,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            n: None,
+            instructions: None,
+            input: None,
+            prompt: None,
+        };
+
+        let result = convert(&req, "test-v", "gemini-1.5-flash");
+        let parts = &result["request"]["contents"][0]["parts"];
+        assert_eq!(parts.as_array().unwrap().len(), 2);
+        assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
+        assert_eq!(parts[1]["inlineData"]["mimeType"].as_str().unwrap(), "image/png");
+    }
+
+    #[test]
+    fn test_upstream_body_snapshot() {
+        // 与改用类型化构造前的请求体逐字节一致 (requestId 除外)
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "hi"}
+            ],
+            "temperature": 0.7,
+            "top_p": 0.9,
+            "stop": "END",
+            "response_format": {"type": "json_object"}
+        }))
+        .unwrap();
+
+        let mut result = convert(&req, "proj", "gemini-2.5-flash");
+        assert!(result["requestId"].as_str().unwrap().starts_with("openai-"));
+        result.as_object_mut().unwrap().remove("requestId");
+        let request = result.as_object_mut().unwrap().remove("request").unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"model":"gemini-2.5-flash","project":"proj","requestType":"agent","userAgent":"antigravity"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&request["generationConfig"]).unwrap(),
+            serde_json::to_string(&json!({
+                "maxOutputTokens": 64000,
+                "temperature": 0.7f32,
+                "topP": 0.9f32,
+                "stopSequences": ["END"],
+                "responseMimeType": "application/json"
+            }))
+            .unwrap()
+        );
+        assert_eq!(request["systemInstruction"], json!({"parts": [{"text": "be brief"}]}));
+        assert_eq!(request["safetySettings"].as_array().unwrap().len(), 5);
+        assert_eq!(request["safetySettings"][0], json!({"category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF"}));
+    }
+
+    #[test]
+    fn test_image_url_parts_from_json_request() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Compare these"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.webp?size=large", "detail": "high"}}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let result = convert(&req, "test-v", "gemini-2.5-flash");
+        let parts = result["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/png");
+        assert_eq!(parts[1]["inlineData"]["data"], "iVBORw0KGgo=");
+        assert_eq!(parts[2]["fileData"]["fileUri"], "https://example.com/cat.webp?size=large");
+        assert_eq!(parts[2]["fileData"]["mimeType"], "image/webp");
+    }
+
+    #[test]
+    fn test_mixed_text_and_image_parts_keep_order() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "before"},
+                    {"type": "image_url", "image_url": {"url": "data:image/gif;base64,R0lGOD="}},
+                    {"type": "text", "text": "after"}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let result = convert(&req, "test-v", "gemini-2.5-flash");
+        let parts = result["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert_eq!(parts[0]["text"], "before");
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/gif");
+        assert_eq!(parts[2]["text"], "after");
+    }
+
+    #[test]
+    fn test_transform_openai_request_response_format() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Extract the city"}],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "city",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}},
+                        "required": ["city"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let result = convert(&req, "test-v", "gemini-2.5-flash");
+        let gen_config = &result["request"]["generationConfig"];
+        assert_eq!(gen_config["responseMimeType"], "application/json");
+        assert_eq!(
+            gen_config["responseSchema"],
+            json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]})
+        );
+
+        let json_object: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "response_format": {"type": "json_object"}
+        }))
+        .unwrap();
+        let result = convert(&json_object, "test-v", "gemini-2.5-flash");
+        assert_eq!(result["request"]["generationConfig"]["responseMimeType"], "application/json");
+        assert!(result["request"]["generationConfig"].get("responseSchema").is_none());
+    }
+
+    #[test]
+    fn test_sampling_fields_passthrough() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "top_k": 20,
+            "presence_penalty": 0.5,
+            "frequency_penalty": -3.5
+        }))
+        .unwrap();
+        let result = convert(&req, "test-v", "gemini-3-flash");
+        let config = &result["request"]["generationConfig"];
+        assert_eq!(config["topK"], 20);
+        assert_eq!(config["presencePenalty"], 0.5);
+        // 超出范围时截断而不是报错
+        assert_eq!(config["frequencyPenalty"], -2.0);
+
+        let plain: OpenAIRequest =
+            serde_json::from_value(json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]})).unwrap();
+        let result = convert(&plain, "test-v", "gemini-3-flash");
+        let config = result["request"]["generationConfig"].as_object().unwrap();
+        assert!(!config.contains_key("topK") && !config.contains_key("presencePenalty"));
+    }
+
+    #[test]
+    fn test_candidate_count() {
+        let req = |n: Value| -> OpenAIRequest {
+            serde_json::from_value(json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}], "n": n}))
+                .unwrap()
+        };
+
+        let result = convert(&req(json!(3)), "test-v", "gemini-3-flash");
+        assert_eq!(result["request"]["generationConfig"]["candidateCount"], 3);
+        // 超出上限时截断
+        let result = convert(&req(json!(9)), "test-v", "gemini-3-flash");
+        assert_eq!(result["request"]["generationConfig"]["candidateCount"], 4);
+        let result = convert(&req(Value::Null), "test-v", "gemini-3-flash");
+        assert!(result["request"]["generationConfig"].get("candidateCount").is_none());
+
+        assert!(validate_candidate_count(&req(json!(2)), "gemini-3-flash").is_ok());
+        assert!(validate_candidate_count(&req(json!(1)), "gemini-3-pro-image").is_ok());
+        assert!(validate_candidate_count(&req(json!(2)), "gemini-3-pro-image").is_err());
+        let err = validate_candidate_count(&req(json!(2)), "claude-sonnet-4-5-thinking").unwrap_err();
+        assert!(err.contains("n=2"));
+    }
+
+    #[test]
+    fn test_transform_openai_request_tools_round_trip() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"}
+            ],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }
+            }],
+            "tool_choice": {"type": "function", "function": {"name": "get_weather"}}
+        }))
+        .unwrap();
+
+        let result = convert(&req, "test-v", "gemini-2.5-flash");
+        let inner = &result["request"];
+
+        let decl = &inner["tools"][0]["functionDeclarations"][0];
+        assert_eq!(decl["name"], "get_weather");
+        assert_eq!(decl["parameters"]["type"], "OBJECT");
+        assert_eq!(decl["parameters"]["properties"]["city"]["type"], "STRING");
+
+        let calling = &inner["toolConfig"]["functionCallingConfig"];
+        assert_eq!(calling["mode"], "ANY");
+        assert_eq!(calling["allowedFunctionNames"][0], "get_weather");
+
+        let contents = inner["contents"].as_array().unwrap();
+        let has_call = contents.iter().any(|c| {
+            c["parts"].as_array().unwrap().iter().any(|p| p["functionCall"]["args"]["city"] == "Paris")
+        });
+        let has_response = contents.iter().any(|c| {
+            c["parts"].as_array().unwrap().iter().any(|p| p["functionResponse"]["name"] == "get_weather")
+        });
+        assert!(has_call && has_response);
+
+        // 未指定 tool_choice 时默认 AUTO
+        let mut req = req;
+        req.tool_choice = None;
+        let result = convert(&req, "test-v", "gemini-2.5-flash");
+        assert_eq!(result["request"]["toolConfig"]["functionCallingConfig"]["mode"], "AUTO");
+    }
+}
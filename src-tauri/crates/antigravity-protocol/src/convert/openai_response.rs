@@ -0,0 +1,515 @@
+// Gemini → OpenAI 非流式响应转换
+use serde_json::Value;
+
+use super::claude_response::matched_stop_sequence;
+use super::ResponseOptions;
+use crate::media::render_inline_data;
+use crate::openai::*;
+use crate::usage::estimate_text_tokens;
+use crate::warnings::Warnings;
+
+/// Gemini 思维链片段 (thought: true)，其文本不能进入 content
+pub fn is_thought_part(part: &Value) -> bool {
+    part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false)
+}
+
+/// 响应中出现的 thoughtSignature (按出现顺序；Gemini 3 工具调用需要在后续请求中回填)
+pub fn thought_signatures(gemini_response: &Value) -> Vec<&str> {
+    let raw = gemini_response.get("response").unwrap_or(gemini_response);
+    raw.get("candidates")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|candidate| candidate.get("content")?.get("parts")?.as_array())
+        .flatten()
+        .filter_map(|part| {
+            part.get("thoughtSignature")
+                .or(part.get("thought_signature"))
+                .and_then(|s| s.as_str())
+        })
+        .collect()
+}
+
+/// 转换 Gemini 响应为 OpenAI Chat Completions 响应
+pub fn transform_openai_response(
+    gemini_response: &Value,
+    options: &ResponseOptions,
+    warnings: &mut Warnings,
+) -> OpenAIResponse {
+    // 解包 response 字段
+    let raw = gemini_response.get("response").unwrap_or(gemini_response);
+
+    // 每个候选 (candidateCount > 1 时有多个) 生成一个 choice；没有候选时返回空的 choice
+    let candidates = raw.get("candidates").and_then(|c| c.as_array()).map(Vec::as_slice).unwrap_or_default();
+    let choices = if candidates.is_empty() {
+        vec![build_choice(&Value::Null, 0, options, warnings)]
+    } else {
+        candidates
+            .iter()
+            .enumerate()
+            .map(|(position, candidate)| {
+                let index = candidate
+                    .get("index")
+                    .and_then(|v| v.as_u64())
+                    .map_or(position as u32, |v| v as u32);
+                build_choice(candidate, index, options, warnings)
+            })
+            .collect()
+    };
+
+    OpenAIResponse {
+        id: raw
+            .get("responseId")
+            .and_then(|v| v.as_str())
+            .map(|s| format!("chatcmpl-{}", s))
+            .unwrap_or_else(|| format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())),
+        object: "chat.completion".to_string(),
+        created: chrono::Utc::now().timestamp() as u64,
+        model: raw
+            .get("modelVersion")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        choices,
+        usage: raw.get("usageMetadata").map(transform_usage),
+    }
+}
+
+/// 将单个 Gemini 候选转换为 OpenAI choice
+fn build_choice(candidate: &Value, index: u32, options: &ResponseOptions, warnings: &mut Warnings) -> Choice {
+    // 提取 content、reasoning_content 和 tool_calls
+    let mut content_out = String::new();
+    let mut reasoning_out = String::new();
+    let mut tool_calls = Vec::new();
+
+    if let Some(parts) = candidate
+        .get("content")
+        .and_then(|content| content.get("parts"))
+        .and_then(|p| p.as_array())
+    {
+        for part in parts {
+            /* 暂时禁用：思维链/推理部分 (Gemini 2.0+) 避免干扰 Codex CLI 等非推理客户端
+            if let Some(thought) = part.get("thought").and_then(|t| t.as_str()) {
+                if !thought.is_empty() {
+                    content_out.push_str("<thought>\n");
+                    content_out.push_str(thought);
+                    content_out.push_str("\n</thought>\n\n");
+                }
+            }
+            */
+
+            // 文本部分 (thought: true 的思维链片段进入 reasoning_content)
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                if !is_thought_part(part) {
+                    content_out.push_str(text);
+                } else if options.emit_reasoning {
+                    reasoning_out.push_str(text);
+                }
+            }
+
+            // 工具调用部分
+            if let Some(fc) = part.get("functionCall") {
+                let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+                let args = fc
+                    .get("args")
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "{}".to_string());
+                let id = fc
+                    .get("id")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| format!("{}-{}", name, uuid::Uuid::new_v4()));
+
+                tool_calls.push(ToolCall {
+                    id,
+                    r#type: "function".to_string(),
+                    function: ToolFunction {
+                        name: name.to_string(),
+                        arguments: args,
+                    },
+                });
+            }
+
+            // inlineData 处理 (按 mimeType 渲染为图片 / 链接)
+            if let Some(img) = part.get("inlineData") {
+                let mime_type = img
+                    .get("mimeType")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("image/png");
+                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
+                if let Some(markdown) = render_inline_data(options.inline_media, mime_type, data, warnings) {
+                    content_out.push_str(&markdown);
+                }
+            }
+        }
+    }
+
+    // 提取并处理联网搜索引文 (Grounding Metadata)
+    if let Some(grounding) = candidate.get("groundingMetadata") {
+        let mut grounding_text = String::new();
+
+        // 1. 处理搜索词
+        if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
+            let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
+            if !query_list.is_empty() {
+                grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
+                grounding_text.push_str(&query_list.join(", "));
+            }
+        }
+
+        // 2. 处理来源链接 (Chunks)
+        if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
+            let mut links = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                if let Some(web) = chunk.get("web") {
+                    let title = web
+                        .get("title")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("网页来源");
+                    let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
+                    links.push(format!("[{}] [{}]({})", i + 1, title, uri));
+                }
+            }
+
+            if !links.is_empty() {
+                grounding_text.push_str("\n\n**🌐 来源引文：**\n");
+                grounding_text.push_str(&links.join("\n"));
+            }
+        }
+
+        if !grounding_text.is_empty() {
+            content_out.push_str(&grounding_text);
+        }
+    }
+
+    // 提取 finish_reason
+    let finish_reason = candidate
+        .get("finishReason")
+        .and_then(|f| f.as_str())
+        .map(|f| match f {
+            "STOP" => "stop",
+            "MAX_TOKENS" => "length",
+            "SAFETY" => "content_filter",
+            "RECITATION" => "content_filter",
+            _ => "stop",
+        })
+        .unwrap_or("stop");
+
+    // [NEW] 存在工具调用时按 OpenAI 约定返回 "tool_calls"，客户端据此进入工具循环
+    let finish_reason = if !tool_calls.is_empty() && finish_reason == "stop" {
+        "tool_calls"
+    } else {
+        finish_reason
+    };
+
+    Choice {
+        index,
+        message: OpenAIMessage {
+            role: "assistant".to_string(),
+            content: if content_out.is_empty() {
+                None
+            } else {
+                Some(OpenAIContent::String(content_out))
+            },
+            reasoning_content: (!reasoning_out.is_empty()).then_some(reasoning_out),
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            tool_call_id: None,
+            name: None,
+        },
+        finish_reason: Some(finish_reason.to_string()),
+        finish_details: None,
+    }
+}
+
+/// 请求带 stop 时，为因停止而结束的 choice 补充 finish_details
+/// Gemini 不指明命中的序列，仅当序列仍出现在输出末尾时填入 stop
+pub fn annotate_finish_details(response: &mut OpenAIResponse, stop_sequences: &[String]) {
+    if stop_sequences.is_empty() {
+        return;
+    }
+    for choice in response.choices.iter_mut() {
+        if choice.finish_reason.as_deref() != Some("stop") {
+            continue;
+        }
+        let text = match &choice.message.content {
+            Some(OpenAIContent::String(text)) => text.as_str(),
+            _ => "",
+        };
+        choice.finish_details = Some(FinishDetails {
+            kind: "stop".to_string(),
+            stop: matched_stop_sequence(text, stop_sequences).cloned(),
+        });
+    }
+}
+
+/// 转换 Gemini usageMetadata 为 OpenAI usage
+/// thoughtsTokenCount 计入 completion_tokens，并单独列在 reasoning_tokens 中
+/// 明细字段仅在上游实际返回对应计数时输出 (缺失 != 0)
+pub fn transform_usage(usage: &Value) -> OpenAIUsage {
+    let field = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+    let prompt_tokens = field("promptTokenCount").unwrap_or(0);
+    let thoughts = field("thoughtsTokenCount");
+    let cached = field("cachedContentTokenCount");
+    let completion_tokens = field("candidatesTokenCount").unwrap_or(0) + thoughts.unwrap_or(0);
+    let total_tokens = field("totalTokenCount").unwrap_or(prompt_tokens + completion_tokens);
+
+    OpenAIUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens,
+        prompt_tokens_details: cached.map(|cached_tokens| PromptTokensDetails { cached_tokens }),
+        completion_tokens_details: thoughts
+            .map(|reasoning_tokens| CompletionTokensDetails { reasoning_tokens }),
+    }
+}
+
+/// 上游未返回 usageMetadata 时的估算 usage
+/// prompt 使用请求体预估值，completion 按输出文本与工具参数的字符数估算
+pub fn estimate_usage(estimated_prompt_tokens: u64, response: &OpenAIResponse) -> OpenAIUsage {
+    let completion: u64 = response
+        .choices
+        .iter()
+        .map(|choice| {
+            let text = match &choice.message.content {
+                Some(OpenAIContent::String(s)) => estimate_text_tokens(s),
+                _ => 0,
+            };
+            let tools: u64 = choice
+                .message
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|call| estimate_text_tokens(&call.function.name) + estimate_text_tokens(&call.function.arguments))
+                .sum();
+            text + tools
+        })
+        .sum();
+
+    let prompt_tokens = estimated_prompt_tokens.min(u32::MAX as u64) as u32;
+    let completion_tokens = completion.min(u32::MAX as u64) as u32;
+    OpenAIUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens.saturating_add(completion_tokens),
+        prompt_tokens_details: None,
+        completion_tokens_details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn convert(gemini_resp: &Value) -> OpenAIResponse {
+        transform_openai_response(gemini_resp, &ResponseOptions::default(), &mut Warnings::new())
+    }
+
+    #[test]
+    fn test_thought_signatures_in_order() {
+        let gemini_resp = json!({
+            "response": {
+                "candidates": [{
+                    "content": {"parts": [
+                        {"text": "a", "thought": true, "thoughtSignature": "sig-1"},
+                        {"functionCall": {"name": "f", "args": {}}, "thought_signature": "sig-2"}
+                    ]}
+                }]
+            }
+        });
+        assert_eq!(thought_signatures(&gemini_resp), vec!["sig-1", "sig-2"]);
+        assert!(thought_signatures(&json!({"candidates": []})).is_empty());
+    }
+
+    #[test]
+    fn test_inline_data_rendered_by_mime_family() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        {"inlineData": {"mimeType": "image/png", "data": "iVBO"}},
+                        {"inlineData": {"mimeType": "audio/wav", "data": "UklG"}},
+                        {"inlineData": {"mimeType": "video/mp4", "data": "AAAA"}},
+                        {"inlineData": {"mimeType": "application/pdf", "data": "JVBE"}}
+                    ]
+                },
+                "finishReason": "STOP"
+            }]
+        });
+
+        let result = convert(&gemini_resp);
+        let content = match result.choices[0].message.content.as_ref().unwrap() {
+            OpenAIContent::String(s) => s.clone(),
+            _ => panic!("Expected string content"),
+        };
+        assert!(content.contains("![image](data:image/png;base64,iVBO)"));
+        assert!(content.contains("[audio (audio/wav)](data:audio/wav;base64,UklG)"));
+        assert!(!content.contains("![image](data:audio"));
+        assert!(content.contains("[video (video/mp4)](data:video/mp4;base64,AAAA)"));
+        assert!(content.contains("[attachment (application/pdf)]"));
+    }
+
+    #[test]
+    fn test_transform_openai_response() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [{"text": "Hello!"}]
+                },
+                "finishReason": "STOP"
+            }],
+            "modelVersion": "gemini-2.5-pro",
+            "responseId": "resp_123"
+        });
+
+        let result = convert(&gemini_resp);
+        assert_eq!(result.object, "chat.completion");
+
+        let content = match result.choices[0].message.content.as_ref().unwrap() {
+            OpenAIContent::String(s) => s,
+            _ => panic!("Expected string content"),
+        };
+        assert_eq!(content, "Hello!");
+        assert_eq!(result.choices[0].finish_reason, Some("stop".to_string()));
+    }
+
+    #[test]
+    fn test_transform_openai_response_usage_and_thoughts() {
+        let gemini_resp = json!({
+            "response": {
+                "candidates": [{
+                    "content": {
+                        "parts": [
+                            {"text": "Let me think...", "thought": true},
+                            {"text": "Part one. "},
+                            {"text": "Part two."}
+                        ]
+                    },
+                    "finishReason": "MAX_TOKENS"
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 10,
+                    "candidatesTokenCount": 5,
+                    "thoughtsTokenCount": 3,
+                    "totalTokenCount": 18
+                },
+                "responseId": "abc"
+            }
+        });
+
+        let result = convert(&gemini_resp);
+        assert_eq!(result.id, "chatcmpl-abc");
+        match result.choices[0].message.content.as_ref().unwrap() {
+            OpenAIContent::String(s) => assert_eq!(s, "Part one. Part two."),
+            _ => panic!("Expected string content"),
+        }
+        assert_eq!(result.choices[0].message.reasoning_content.as_deref(), Some("Let me think..."));
+
+        // strip_reasoning：思维链既不进 content 也不输出 reasoning_content 字段
+        let stripped = transform_openai_response(
+            &gemini_resp,
+            &ResponseOptions {
+                emit_reasoning: false,
+                ..ResponseOptions::default()
+            },
+            &mut Warnings::new(),
+        );
+        assert!(stripped.choices[0].message.reasoning_content.is_none());
+        let message = serde_json::to_value(&stripped.choices[0].message).unwrap();
+        assert!(message.get("reasoning_content").is_none());
+        assert_eq!(message["content"], "Part one. Part two.");
+        assert_eq!(result.choices[0].finish_reason, Some("length".to_string()));
+
+        let usage = result.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 10);
+        assert_eq!(usage.completion_tokens, 8);
+        assert_eq!(usage.total_tokens, 18);
+        assert_eq!(usage.completion_tokens_details.unwrap().reasoning_tokens, 3);
+        // 未返回 cachedContentTokenCount 时不输出 prompt_tokens_details
+        assert!(usage.prompt_tokens_details.is_none());
+
+        let blocked = json!({"candidates": [{"content": {"parts": []}, "finishReason": "SAFETY"}]});
+        let result = convert(&blocked);
+        assert_eq!(result.choices[0].finish_reason, Some("content_filter".to_string()));
+        assert!(result.usage.is_none());
+    }
+
+    #[test]
+    fn test_estimate_usage_without_metadata() {
+        let gemini_resp = json!({
+            "candidates": [{"content": {"parts": [{"text": "abcdefgh"}]}, "finishReason": "STOP"}]
+        });
+        let result = convert(&gemini_resp);
+        assert!(result.usage.is_none());
+
+        let usage = estimate_usage(20, &result);
+        assert_eq!(usage.prompt_tokens, 20);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 22);
+    }
+
+    #[test]
+    fn test_transform_openai_response_tool_calls() {
+        let gemini_resp = json!({
+            "response": {
+                "candidates": [{
+                    "content": {
+                        "parts": [{"functionCall": {"name": "get_weather", "args": {"city": "Paris"}, "id": "call_1"}}]
+                    },
+                    "finishReason": "STOP"
+                }]
+            }
+        });
+
+        let result = convert(&gemini_resp);
+        let calls = result.choices[0].message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert!(result.choices[0].message.content.is_none());
+        assert_eq!(result.choices[0].finish_reason, Some("tool_calls".to_string()));
+    }
+
+    #[test]
+    fn test_transform_openai_response_multiple_candidates() {
+        let gemini_resp = json!({
+            "candidates": [
+                {"index": 0, "content": {"parts": [{"text": "first"}]}, "finishReason": "STOP"},
+                {"index": 1, "content": {"parts": [{"text": "second"}]}, "finishReason": "MAX_TOKENS"}
+            ]
+        });
+        let result = convert(&gemini_resp);
+        assert_eq!(result.choices.len(), 2);
+        assert_eq!(result.choices[1].index, 1);
+        match result.choices[1].message.content.as_ref().unwrap() {
+            OpenAIContent::String(s) => assert_eq!(s, "second"),
+            _ => panic!("Expected string content"),
+        }
+        assert_eq!(result.choices[1].finish_reason, Some("length".to_string()));
+    }
+
+    #[test]
+    fn test_finish_details_only_with_stop_sequences() {
+        let gemini_resp = json!({
+            "candidates": [
+                {"index": 0, "content": {"parts": [{"text": "answer END"}]}, "finishReason": "STOP"},
+                {"index": 1, "content": {"parts": [{"text": "trimmed"}]}, "finishReason": "STOP"},
+                {"index": 2, "content": {"parts": [{"text": "long"}]}, "finishReason": "MAX_TOKENS"}
+            ]
+        });
+        let mut result = convert(&gemini_resp);
+        annotate_finish_details(&mut result, &[]);
+        assert!(result.choices.iter().all(|c| c.finish_details.is_none()));
+
+        annotate_finish_details(&mut result, &["END".to_string()]);
+        let details = result.choices[0].finish_details.as_ref().unwrap();
+        assert_eq!((details.kind.as_str(), details.stop.as_deref()), ("stop", Some("END")));
+        let json = serde_json::to_value(&result.choices[1]).unwrap();
+        assert_eq!(json["finish_details"], json!({"type": "stop"}));
+        assert!(result.choices[2].finish_details.is_none());
+    }
+}
@@ -0,0 +1,275 @@
+// Gemini v1internal 数据模型
+// 所有上游请求体 (OpenAI / Claude / Gemini 转换、Embedding、图像、探测) 都通过这里的类型构造，
+// 新增字段只需改一处；各协议之间的差异应在调用方显式表达，而不是各自拼 json!。
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 默认安全设置：全部关闭
+/// 上游支持的 safetySettings 类别
+pub const SAFETY_CATEGORIES: [&str; 5] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+    "HARM_CATEGORY_CIVIC_INTEGRITY",
+];
+
+/// 上游支持的 safetySettings 阈值
+pub const SAFETY_THRESHOLDS: [&str; 6] = [
+    "HARM_BLOCK_THRESHOLD_UNSPECIFIED",
+    "BLOCK_LOW_AND_ABOVE",
+    "BLOCK_MEDIUM_AND_ABOVE",
+    "BLOCK_ONLY_HIGH",
+    "BLOCK_NONE",
+    "OFF",
+];
+
+/// v1internal 外层信封
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V1InternalRequest {
+    pub project: String,
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+    pub request: Value,
+    pub model: String,
+    #[serde(rename = "userAgent")]
+    pub user_agent: String,
+    #[serde(rename = "requestType")]
+    pub request_type: String,
+}
+
+impl V1InternalRequest {
+    /// requestId 默认使用 agent- 前缀 (请求上下文中会被 X-Request-ID 覆盖)
+    pub fn new(project_id: &str, model: &str, request_type: &str, request: Value) -> Self {
+        Self {
+            project: project_id.to_string(),
+            request_id: format!("agent-{}", uuid::Uuid::new_v4()),
+            request,
+            model: model.to_string(),
+            user_agent: "antigravity".to_string(),
+            request_type: request_type.to_string(),
+        }
+    }
+
+    /// 使用指定前缀生成 requestId (便于在上游日志中区分来源，如 openai- / embed- / img-)
+    pub fn with_request_id_prefix(mut self, prefix: &str) -> Self {
+        self.request_id = format!("{}-{}", prefix, uuid::Uuid::new_v4());
+        self
+    }
+
+    pub fn into_value(self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TextPart {
+    pub text: String,
+}
+
+/// systemInstruction
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SystemInstruction {
+    pub parts: Vec<TextPart>,
+}
+
+impl SystemInstruction {
+    pub fn from_text(text: impl Into<String>) -> Self {
+        Self {
+            parts: vec![TextPart { text: text.into() }],
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+/// generationConfig.thinkingConfig
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingConfig {
+    pub include_thoughts: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>,
+}
+
+/// generationConfig.imageConfig
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageConfig {
+    pub aspect_ratio: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<String>,
+}
+
+impl ImageConfig {
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+/// presencePenalty / frequencyPenalty 的取值范围 (上游拒绝超出范围的值)
+pub const PENALTY_RANGE: std::ops::RangeInclusive<f64> = -2.0..=2.0;
+
+/// generationConfig (未设置的字段不序列化)
+/// 采样参数使用 f64：客户端传入的 f32 经 f64::from 转换，与此前 json!(f32) 的结果一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_config: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_config: Option<Value>,
+}
+
+impl GenerationConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn candidate_count(mut self, count: u32) -> Self {
+        self.candidate_count = Some(count);
+        self
+    }
+
+    pub fn max_output_tokens(mut self, tokens: u32) -> Self {
+        self.max_output_tokens = Some(tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: Option<f64>) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn top_p(mut self, top_p: Option<f64>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    pub fn top_k(mut self, top_k: Option<u32>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    pub fn presence_penalty(mut self, penalty: Option<f64>) -> Self {
+        self.presence_penalty = penalty;
+        self
+    }
+
+    pub fn frequency_penalty(mut self, penalty: Option<f64>) -> Self {
+        self.frequency_penalty = penalty;
+        self
+    }
+
+    pub fn stop_sequences(mut self, stop: Vec<String>) -> Self {
+        self.stop_sequences = Some(stop);
+        self
+    }
+
+    pub fn thinking(mut self, thinking: ThinkingConfig) -> Self {
+        self.thinking_config = Some(thinking);
+        self
+    }
+
+    pub fn image_config(mut self, image_config: Value) -> Self {
+        self.image_config = Some(image_config);
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+/// 默认 safetySettings (全部 OFF)
+pub fn default_safety_settings() -> Value {
+    Value::Array(
+        SAFETY_CATEGORIES
+            .iter()
+            .map(|category| serde_json::json!({ "category": category, "threshold": "OFF" }))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_envelope_matches_legacy_shape() {
+        let body = V1InternalRequest::new("proj-1", "gemini-2.5-flash", "agent", json!({"contents": []}))
+            .with_request_id_prefix("openai")
+            .into_value();
+        assert!(body["requestId"].as_str().unwrap().starts_with("openai-"));
+
+        let mut expected = json!({
+            "project": "proj-1",
+            "request": {"contents": []},
+            "model": "gemini-2.5-flash",
+            "userAgent": "antigravity",
+            "requestType": "agent"
+        });
+        expected["requestId"] = body["requestId"].clone();
+        assert_eq!(serde_json::to_string(&body).unwrap(), serde_json::to_string(&expected).unwrap());
+    }
+
+    #[test]
+    fn test_generation_config_serialization_is_byte_compatible() {
+        let temp: f32 = 0.3;
+        let config = GenerationConfig::new()
+            .max_output_tokens(64000)
+            .temperature(Some(f64::from(temp)))
+            .top_k(Some(40))
+            .stop_sequences(vec!["[DONE]".to_string()])
+            .thinking(ThinkingConfig {
+                include_thoughts: true,
+                thinking_budget: Some(1024),
+            });
+
+        let mut legacy = json!({"maxOutputTokens": 64000});
+        legacy["temperature"] = json!(temp);
+        legacy["topK"] = json!(40);
+        legacy["stopSequences"] = json!(["[DONE]"]);
+        legacy["thinkingConfig"] = json!({"includeThoughts": true, "thinkingBudget": 1024});
+
+        assert_eq!(
+            serde_json::to_string(&config.to_value()).unwrap(),
+            serde_json::to_string(&legacy).unwrap()
+        );
+        assert_eq!(GenerationConfig::new().to_value(), json!({}));
+    }
+
+    #[test]
+    fn test_system_instruction_and_safety_settings() {
+        assert_eq!(
+            SystemInstruction::from_text("be brief").to_value(),
+            json!({"parts": [{"text": "be brief"}]})
+        );
+        let safety = default_safety_settings();
+        assert_eq!(safety.as_array().unwrap().len(), 5);
+        assert_eq!(safety[4], json!({"category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF"}));
+    }
+}
@@ -0,0 +1,621 @@
+use serde_json::Value;
+
+/// 递归清理 JSON Schema 以符合 Gemini 接口要求
+///
+/// 1. [New] 展开 $ref 和 $defs: 将引用替换为实际定义，解决 Gemini 不支持 $ref 的问题
+/// 2. 移除不支持的字段: $schema, additionalProperties, format, default, uniqueItems, validation fields
+/// 3. 处理联合类型: ["string", "null"] -> "string"
+/// 4. 将 type 字段的值转换为大写 (Gemini v1internal 要求)
+/// 5. 移除数字校验字段: multipleOf, exclusiveMinimum, exclusiveMaximum 等
+pub fn clean_json_schema(value: &mut Value) {
+    // 0. 预处理：展开 $ref (Schema Flattening)
+    if let Value::Object(map) = value {
+        let mut defs = serde_json::Map::new();
+        // 提取 $defs 或 definitions
+        if let Some(Value::Object(d)) = map.remove("$defs") {
+            defs.extend(d);
+        }
+        if let Some(Value::Object(d)) = map.remove("definitions") {
+            defs.extend(d);
+        }
+
+        if !defs.is_empty() {
+            // 递归替换引用
+            flatten_refs(map, &defs);
+        }
+    }
+
+    // 递归清理
+    clean_json_schema_recursive(value);
+}
+
+/// 递归展开 $ref
+fn flatten_refs(map: &mut serde_json::Map<String, Value>, defs: &serde_json::Map<String, Value>) {
+    // 检查并替换 $ref
+    if let Some(Value::String(ref_path)) = map.remove("$ref") {
+        // 解析引用名 (例如 #/$defs/MyType -> MyType)
+        let ref_name = ref_path.split('/').next_back().unwrap_or(&ref_path);
+
+        // 将定义的内容合并到当前 map
+        if let Some(Value::Object(def_map)) = defs.get(ref_name) {
+            for (k, v) in def_map {
+                // 仅当当前 map 没有该 key 时才插入 (避免覆盖)
+                // 但通常 $ref 节点不应该有其他属性
+                map.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+
+            // 递归处理刚刚合并进来的内容中可能包含的 $ref
+            // 注意：这里可能会无限递归如果存在循环引用，但工具定义通常是 DAG
+            flatten_refs(map, defs);
+        }
+    }
+
+    // 遍历子节点
+    for (_, v) in map.iter_mut() {
+        if let Value::Object(child_map) = v {
+            flatten_refs(child_map, defs);
+        } else if let Value::Array(arr) = v {
+            for item in arr {
+                if let Value::Object(item_map) = item {
+                    flatten_refs(item_map, defs);
+                }
+            }
+        }
+    }
+}
+
+fn clean_json_schema_recursive(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            // 1. [CRITICAL] 深度递归处理：必须遍历当前对象的所有字段名对应的 Value
+            // 解决 properties/items 之外的 definitions、anyOf、allOf 等结构的清理
+            for v in map.values_mut() {
+                clean_json_schema_recursive(v);
+            }
+
+            // 2. 收集并处理校验字段 (Migration logic: 将约束降级为描述中的 Hint)
+            let mut constraints = Vec::new();
+
+            // 待迁移的约束黑名单
+            let validation_fields = [
+                ("pattern", "pattern"),
+                ("minLength", "minLen"),
+                ("maxLength", "maxLen"),
+                ("minimum", "min"),
+                ("maximum", "max"),
+                ("minItems", "minItems"),
+                ("maxItems", "maxItems"),
+                ("exclusiveMinimum", "exclMin"),
+                ("exclusiveMaximum", "exclMax"),
+                ("multipleOf", "multipleOf"),
+                ("format", "format"),
+            ];
+
+            for (field, label) in validation_fields {
+                if let Some(val) = map.remove(field) {
+                    // 仅当值是简单类型时才迁移
+                    if val.is_string() || val.is_number() || val.is_boolean() {
+                        constraints.push(format!("{}: {}", label, val));
+                    }
+                }
+            }
+
+            // 3. 将约束信息追加到描述
+            if !constraints.is_empty() {
+                let suffix = format!(" [Constraint: {}]", constraints.join(", "));
+                let desc_val = map
+                    .entry("description".to_string())
+                    .or_insert_with(|| Value::String("".to_string()));
+                if let Value::String(s) = desc_val {
+                    s.push_str(&suffix);
+                }
+            }
+
+            // 4. 彻底物理移除干扰生成的“硬项”黑色名单 (Hard Blacklist)
+            let hard_remove_fields = [
+                "$schema",
+                "additionalProperties",
+                "enumCaseInsensitive",
+                "enumNormalizeWhitespace",
+                "uniqueItems",
+                "default",
+                "const",
+                "examples",
+                "propertyNames",
+                "anyOf",
+                "oneOf",
+                "allOf",
+                "not",
+                "if",
+                "then",
+                "else",
+                "dependencies",
+                "dependentSchemas",
+                "dependentRequired",
+                "cache_control",
+            ];
+            for field in hard_remove_fields {
+                map.remove(field);
+            }
+
+            // [NEW FIX] 确保 required 中的字段一定在 properties 中存在
+            // Gemini 严格校验：required 中的字段如果不在 properties 中定义，会报 INVALID_ARGUMENT
+            // Refactored to avoid double borrow (mutable map vs immutable get("properties"))
+            let valid_prop_keys: Option<std::collections::HashSet<String>> = map
+                .get("properties")
+                .and_then(|p| p.as_object())
+                .map(|obj| obj.keys().cloned().collect());
+
+            if let Some(required_val) = map.get_mut("required") {
+                if let Some(req_arr) = required_val.as_array_mut() {
+                    if let Some(keys) = &valid_prop_keys {
+                        req_arr.retain(|k| {
+                            if let Some(k_str) = k.as_str() {
+                                keys.contains(k_str)
+                            } else {
+                                false
+                            }
+                        });
+                    } else {
+                        // 如果没有 properties，required 应该是空的
+                        req_arr.clear();
+                    }
+                }
+            }
+
+            // 5. 处理 type 字段 (Gemini 要求单字符串且小写)
+            if let Some(type_val) = map.get_mut("type") {
+                match type_val {
+                    Value::String(s) => {
+                        *type_val = Value::String(s.to_lowercase());
+                    }
+                    Value::Array(arr) => {
+                        let mut selected_type = "string".to_string();
+                        for item in arr {
+                            if let Value::String(s) = item {
+                                if s != "null" {
+                                    selected_type = s.to_lowercase();
+                                    break;
+                                }
+                            }
+                        }
+                        *type_val = Value::String(selected_type);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr.iter_mut() {
+                clean_json_schema_recursive(v);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Gemini responseSchema 直接支持的 format
+const GEMINI_SCHEMA_FORMATS: [&str; 6] = ["date-time", "enum", "int32", "int64", "float", "double"];
+
+/// 仅作注释用途、转换时可安全忽略的关键字
+const SCHEMA_ANNOTATIONS: [&str; 8] = [
+    "$schema", "$id", "$comment", "$defs", "definitions", "default", "examples", "deprecated",
+];
+
+/// 将 OpenAI response_format.json_schema 转换为 Gemini responseSchema (OpenAPI 子集)
+///
+/// 与 clean_json_schema (工具参数，尽量降级) 不同，这里生成的是强制输出约束：
+/// 无法表达的特性 (oneOf/allOf/not、条件、元组、开放 additionalProperties、循环引用等) 直接返回错误，
+/// 以免客户端以为约束生效而实际被丢弃。
+pub fn convert_json_schema_to_gemini_schema(schema: &Value) -> Result<Value, String> {
+    let mut defs = serde_json::Map::new();
+    for key in ["$defs", "definitions"] {
+        if let Some(Value::Object(d)) = schema.get(key) {
+            defs.extend(d.clone());
+        }
+    }
+    convert_schema_node(schema, &defs, "#", &mut Vec::new())
+}
+
+/// 判断上游错误是否为 responseSchema 被拒 (部分模型以 500 INTERNAL 而非 400 返回)
+/// 这类错误由请求本身决定，换账号重试没有意义，应按 400 透传上游信息
+pub fn is_response_schema_error(status: u16, error_text: &str) -> bool {
+    if status != 400 && status != 500 {
+        return false;
+    }
+    let lower = error_text.to_lowercase();
+    ["response_schema", "responseschema", "response schema"]
+        .iter()
+        .any(|kw| lower.contains(kw))
+}
+
+fn convert_schema_node(
+    node: &Value,
+    defs: &serde_json::Map<String, Value>,
+    path: &str,
+    ref_stack: &mut Vec<String>,
+) -> Result<Value, String> {
+    let map = node
+        .as_object()
+        .ok_or_else(|| format!("Schema at {} must be an object", path))?;
+
+    // 1. 展开本地 $ref (仅支持 #/$defs/* 与 #/definitions/*，拒绝循环引用)
+    if let Some(ref_val) = map.get("$ref") {
+        let ref_path = ref_val
+            .as_str()
+            .ok_or_else(|| format!("$ref at {} must be a string", path))?;
+        let name = ref_path
+            .strip_prefix("#/$defs/")
+            .or_else(|| ref_path.strip_prefix("#/definitions/"))
+            .ok_or_else(|| format!("Unsupported $ref `{}` at {} (only local $defs are supported)", ref_path, path))?;
+        if ref_stack.iter().any(|r| r == name) {
+            return Err(format!("Recursive $ref `{}` at {} is not supported by Gemini", ref_path, path));
+        }
+        let target = defs
+            .get(name)
+            .ok_or_else(|| format!("Unresolved $ref `{}` at {}", ref_path, path))?;
+        ref_stack.push(name.to_string());
+        let mut resolved = convert_schema_node(target, defs, ref_path, ref_stack)?;
+        ref_stack.pop();
+        if let (Some(desc), Some(obj)) = (map.get("description"), resolved.as_object_mut()) {
+            obj.insert("description".to_string(), desc.clone());
+        }
+        return Ok(resolved);
+    }
+
+    let mut out = serde_json::Map::new();
+    for (key, value) in map {
+        let child_path = format!("{}/{}", path, key);
+        match key.as_str() {
+            "type" => match value {
+                Value::String(t) => {
+                    if t == "null" {
+                        return Err(format!("Standalone `null` type at {} is not supported", path));
+                    }
+                    out.insert("type".to_string(), Value::String(t.to_lowercase()));
+                }
+                Value::Array(types) => {
+                    let non_null: Vec<&str> = types
+                        .iter()
+                        .filter_map(|t| t.as_str())
+                        .filter(|t| *t != "null")
+                        .collect();
+                    if non_null.len() != 1 {
+                        return Err(format!(
+                            "Union type {} at {} is not supported (use anyOf)",
+                            value, path
+                        ));
+                    }
+                    out.insert("type".to_string(), Value::String(non_null[0].to_lowercase()));
+                    if non_null.len() < types.len() {
+                        out.insert("nullable".to_string(), Value::Bool(true));
+                    }
+                }
+                _ => return Err(format!("Invalid `type` at {}", path)),
+            },
+            "properties" => {
+                let props = value
+                    .as_object()
+                    .ok_or_else(|| format!("`properties` at {} must be an object", path))?;
+                let mut converted = serde_json::Map::new();
+                for (name, prop) in props {
+                    converted.insert(
+                        name.clone(),
+                        convert_schema_node(prop, defs, &format!("{}/{}", child_path, name), ref_stack)?,
+                    );
+                }
+                out.insert(key.clone(), Value::Object(converted));
+            }
+            "items" => {
+                if value.is_array() {
+                    return Err(format!("Tuple `items` at {} is not supported", path));
+                }
+                out.insert(key.clone(), convert_schema_node(value, defs, &child_path, ref_stack)?);
+            }
+            "anyOf" => {
+                let variants = value
+                    .as_array()
+                    .ok_or_else(|| format!("`anyOf` at {} must be an array", path))?;
+                let converted = variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| convert_schema_node(v, defs, &format!("{}/{}", child_path, i), ref_stack))
+                    .collect::<Result<Vec<_>, _>>()?;
+                out.insert(key.clone(), Value::Array(converted));
+            }
+            "enum" => {
+                let values = value
+                    .as_array()
+                    .filter(|vals| vals.iter().all(|v| v.is_string()))
+                    .ok_or_else(|| format!("`enum` at {} must contain only strings for Gemini", path))?;
+                out.insert(key.clone(), Value::Array(values.clone()));
+            }
+            "const" => {
+                if !value.is_string() {
+                    return Err(format!("Non-string `const` at {} is not supported", path));
+                }
+                out.insert("enum".to_string(), Value::Array(vec![value.clone()]));
+            }
+            "additionalProperties" => {
+                // Gemini 对象本身不允许额外属性；false 等价，其他取值无法表达
+                if value != &Value::Bool(false) {
+                    return Err(format!(
+                        "`additionalProperties` at {} must be false (open objects are not supported)",
+                        path
+                    ));
+                }
+            }
+            "format" => {
+                let format = value.as_str().unwrap_or_default();
+                if GEMINI_SCHEMA_FORMATS.contains(&format) {
+                    out.insert(key.clone(), value.clone());
+                } else {
+                    // 注释性质的 format (email/uri 等) 转为描述提示
+                    let desc = map.get("description").and_then(|d| d.as_str()).unwrap_or_default();
+                    out.insert(
+                        "description".to_string(),
+                        Value::String(format!("{} [format: {}]", desc, format).trim_start().to_string()),
+                    );
+                }
+            }
+            "description" => {
+                out.entry("description".to_string()).or_insert_with(|| value.clone());
+            }
+            "required" | "nullable" | "title" | "minItems" | "maxItems" | "minimum" | "maximum"
+            | "propertyOrdering" => {
+                out.insert(key.clone(), value.clone());
+            }
+            k if SCHEMA_ANNOTATIONS.contains(&k) => {}
+            other => {
+                return Err(format!(
+                    "Unsupported JSON schema keyword `{}` at {} for Gemini responseSchema",
+                    other, path
+                ));
+            }
+        }
+    }
+
+    if let Some(required) = out.get("required").and_then(|r| r.as_array()) {
+        let props = out.get("properties").and_then(|p| p.as_object());
+        for name in required {
+            let name = name
+                .as_str()
+                .ok_or_else(|| format!("`required` at {} must contain only strings", path))?;
+            if !props.is_some_and(|p| p.contains_key(name)) {
+                return Err(format!("Required property `{}` at {} is not defined in properties", name, path));
+            }
+        }
+    }
+
+    Ok(Value::Object(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_clean_json_schema_draft_2020_12() {
+        let mut schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "properties": {
+                "location": {
+                    "type": "string",
+                    "minLength": 1,
+                    "format": "city"
+                },
+                // 模拟属性名冲突：pattern 是一个 Object 属性，不应被移除
+                "pattern": {
+                    "type": "object",
+                    "properties": {
+                        "regex": { "type": "string", "pattern": "^[a-z]+$" }
+                    }
+                },
+                "unit": {
+                    "type": ["string", "null"],
+                    "default": "celsius"
+                }
+            },
+            "required": ["location"]
+        });
+
+        clean_json_schema(&mut schema);
+
+        // 1. 验证类型保持小写
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["location"]["type"], "string");
+
+        // 2. 验证标准字段被转换并移动到描述 (Advanced Soft-Remove)
+        assert!(schema["properties"]["location"].get("minLength").is_none());
+        assert!(schema["properties"]["location"]["description"]
+            .as_str()
+            .unwrap()
+            .contains("minLen: 1"));
+
+        // 3. 验证名为 "pattern" 的属性未被误删
+        assert!(schema["properties"].get("pattern").is_some());
+        assert_eq!(schema["properties"]["pattern"]["type"], "object");
+
+        // 4. 验证内部的 pattern 校验字段被正确移除并转为描述
+        assert!(schema["properties"]["pattern"]["properties"]["regex"]
+            .get("pattern")
+            .is_none());
+        assert!(
+            schema["properties"]["pattern"]["properties"]["regex"]["description"]
+                .as_str()
+                .unwrap()
+                .contains("pattern: ^[a-z]+$")
+        );
+
+        // 5. 验证联合类型被降级为单一类型 (Protobuf 兼容性)
+        assert_eq!(schema["properties"]["unit"]["type"], "string");
+
+        // 6. 验证元数据字段被移除
+        assert!(schema.get("$schema").is_none());
+    }
+
+    #[test]
+    fn test_type_fallback() {
+        // Test ["string", "null"] -> "string"
+        let mut s1 = json!({"type": ["string", "null"]});
+        clean_json_schema(&mut s1);
+        assert_eq!(s1["type"], "string");
+
+        // Test ["integer", "null"] -> "integer" (and lowercase check if needed, though usually integer)
+        let mut s2 = json!({"type": ["integer", "null"]});
+        clean_json_schema(&mut s2);
+        assert_eq!(s2["type"], "integer");
+    }
+
+    #[test]
+    fn test_flatten_refs() {
+        let mut schema = json!({
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": {
+                        "city": { "type": "string" }
+                    }
+                }
+            },
+            "properties": {
+                "home": { "$ref": "#/$defs/Address" }
+            }
+        });
+
+        clean_json_schema(&mut schema);
+
+        // 验证引用被展开且类型转为小写
+        assert_eq!(schema["properties"]["home"]["type"], "object");
+        assert_eq!(
+            schema["properties"]["home"]["properties"]["city"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_clean_json_schema_missing_required() {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "existing_prop": { "type": "string" }
+            },
+            "required": ["existing_prop", "missing_prop"]
+        });
+
+        clean_json_schema(&mut schema);
+
+        // 验证 missing_prop 被从 required 中移除
+        let required = schema["required"].as_array().unwrap();
+        assert_eq!(required.len(), 1);
+        assert_eq!(required[0].as_str().unwrap(), "existing_prop");
+    }
+
+    #[test]
+    fn test_convert_response_schema() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Full name" },
+                "email": { "type": "string", "format": "email" },
+                "age": { "type": ["integer", "null"] },
+                "kind": { "const": "person" },
+                "address": { "$ref": "#/$defs/Address" },
+                "tags": { "type": "array", "items": { "type": "string", "enum": ["a", "b"] } }
+            },
+            "required": ["name", "address"],
+            "additionalProperties": false,
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                    "required": ["city"],
+                    "additionalProperties": false
+                }
+            }
+        });
+
+        let converted = convert_json_schema_to_gemini_schema(&schema).unwrap();
+        assert_eq!(converted["type"], "object");
+        assert!(converted.get("additionalProperties").is_none());
+        assert!(converted.get("$defs").is_none());
+        assert_eq!(converted["properties"]["age"], json!({"type": "integer", "nullable": true}));
+        assert_eq!(converted["properties"]["kind"]["enum"], json!(["person"]));
+        assert_eq!(converted["properties"]["address"]["properties"]["city"]["type"], "string");
+        assert_eq!(converted["properties"]["email"]["description"], "[format: email]");
+        assert_eq!(converted["properties"]["tags"]["items"]["enum"], json!(["a", "b"]));
+    }
+
+    #[test]
+    fn test_convert_realistic_extraction_schema() {
+        // 典型的结构化抽取 schema：嵌套对象数组 + 枚举 + 可空字段
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "invoice_number": { "type": "string" },
+                "status": { "type": "string", "enum": ["paid", "open", "void"] },
+                "line_items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "description": { "type": "string" },
+                            "quantity": { "type": "integer", "minimum": 1 },
+                            "unit_price": { "type": "number" },
+                            "discount": { "type": ["number", "null"] }
+                        },
+                        "required": ["description", "quantity", "unit_price"],
+                        "additionalProperties": false
+                    }
+                },
+                "issued_at": { "type": "string", "format": "date-time" }
+            },
+            "required": ["invoice_number", "status", "line_items"],
+            "additionalProperties": false
+        });
+
+        let converted = convert_json_schema_to_gemini_schema(&schema).unwrap();
+        assert_eq!(converted["required"], json!(["invoice_number", "status", "line_items"]));
+        assert_eq!(converted["properties"]["status"]["enum"], json!(["paid", "open", "void"]));
+        let item = &converted["properties"]["line_items"]["items"];
+        assert_eq!(item["type"], "object");
+        assert_eq!(item["required"], json!(["description", "quantity", "unit_price"]));
+        assert_eq!(item["properties"]["quantity"], json!({"type": "integer", "minimum": 1}));
+        assert_eq!(item["properties"]["discount"], json!({"type": "number", "nullable": true}));
+        assert!(item.get("additionalProperties").is_none());
+        assert_eq!(converted["properties"]["issued_at"]["format"], "date-time");
+    }
+
+    #[test]
+    fn test_is_response_schema_error() {
+        let body = r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"foo\" at 'generation_config.response_schema'","status":"INVALID_ARGUMENT"}}"#;
+        assert!(is_response_schema_error(400, body));
+        assert!(is_response_schema_error(500, "responseSchema is too complex for this model"));
+        assert!(!is_response_schema_error(429, body));
+        assert!(!is_response_schema_error(400, "temperature must be <= 1"));
+    }
+
+    #[test]
+    fn test_convert_response_schema_rejects_unsupported_features() {
+        let cases = [
+            (json!({"oneOf": [{"type": "string"}, {"type": "integer"}]}), "oneOf"),
+            (json!({"type": "object", "additionalProperties": {"type": "string"}}), "additionalProperties"),
+            (json!({"type": "array", "items": [{"type": "string"}]}), "Tuple"),
+            (json!({"type": ["string", "integer"]}), "Union type"),
+            (json!({"type": "string", "pattern": "^a"}), "pattern"),
+            (json!({"$ref": "https://example.com/schema.json"}), "only local"),
+            (
+                json!({"$defs": {"Node": {"type": "object", "properties": {"next": {"$ref": "#/$defs/Node"}}}}, "$ref": "#/$defs/Node"}),
+                "Recursive",
+            ),
+            (json!({"type": "object", "properties": {}, "required": ["x"]}), "not defined"),
+        ];
+        for (schema, expected) in cases {
+            let err = convert_json_schema_to_gemini_schema(&schema).unwrap_err();
+            assert!(err.contains(expected), "{} -> {}", schema, err);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! 反代协议层公共库 (不依赖 Tauri / axum / tokio)
+//!
+//! 包含三种协议的类型化数据模型、模型路由与非流式转换，可在不启动反代服务的情况下复用：
+//!
+//! - [`openai`]: OpenAI Chat Completions 请求模型 (`OpenAIRequest`、`ResponseFormat` 等)
+//! - [`claude`]: Anthropic Messages API 请求/响应模型 (`ClaudeRequest`、`Message`、`ContentBlock` 等)
+//! - [`gemini`]: Gemini v1internal 上游请求体 (`V1InternalRequest`、`GenerationConfig` 等)
+//! - [`json_schema`]: 工具参数清理 (`clean_json_schema`) 与 `response_format` → `responseSchema` 转换
+//! - [`routing`]: 模型名称映射与路由解析，入口为 `route_model` / `map_claude_model_to_gemini`
+//! - [`capabilities`]: 按模型名与工具列表识别联网搜索 / 图像生成请求 (`resolve_request_config`)
+//! - [`convert`]: OpenAI / Claude ↔ Gemini 非流式请求与响应转换
+//! - [`media`]、[`usage`]: inlineData 渲染与 Token 用量换算
+//! - [`warnings`]: 转换期间记录的非致命修正 (`Warnings`)，是否写入日志或响应由调用方决定
+//!
+//! 转换函数不读取任何进程级状态：safetySettings、thinking 默认预算、签名缓存、
+//! 思维链输出等运行时设置均通过 [`convert::RequestOptions`] / [`convert::ResponseOptions`] 传入。
+//! SSE 流式转换依赖异步运行时，仍由反代应用实现。
+//!
+//! 反代应用通过 `crate::proxy::mappers::*` 与 `crate::proxy::common::*` 原路径重新导出，
+//! 两处只有这一份实现。
+
+pub mod capabilities;
+pub mod claude;
+pub mod convert;
+pub mod gemini;
+pub mod json_schema;
+pub mod media;
+pub mod openai;
+pub mod routing;
+pub mod usage;
+pub mod warnings;
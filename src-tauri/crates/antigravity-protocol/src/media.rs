@@ -0,0 +1,110 @@
+// Gemini inlineData 输出处理 (OpenAI / Claude，流式与非流式共用)
+// 按 mimeType 分类：图片渲染为 Markdown 图片，音视频渲染为下载链接，
+// 其他类型在 extended 模式下保留为附件链接，strict 模式下丢弃并记录警告。
+use serde::{Deserialize, Serialize};
+
+use crate::warnings::{codes, Warnings};
+
+/// 非图片 inlineData 的输出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InlineMediaMode {
+    /// 未知类型保留为附件链接
+    #[default]
+    Extended,
+    /// 未知类型丢弃并记录警告
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+    Video,
+    Other,
+}
+
+pub fn media_kind(mime_type: &str) -> MediaKind {
+    let top = mime_type.split('/').next().unwrap_or("").trim();
+    match top.to_ascii_lowercase().as_str() {
+        "image" => MediaKind::Image,
+        "audio" => MediaKind::Audio,
+        "video" => MediaKind::Video,
+        _ => MediaKind::Other,
+    }
+}
+
+/// 将 inlineData 渲染为追加到文本内容的 Markdown；返回 None 表示丢弃
+pub fn render_inline_data(
+    mode: InlineMediaMode,
+    mime_type: &str,
+    data: &str,
+    warnings: &mut Warnings,
+) -> Option<String> {
+    if data.is_empty() {
+        return None;
+    }
+    let uri = format!("data:{};base64,{}", mime_type, data);
+    match media_kind(mime_type) {
+        MediaKind::Image => Some(format!("![image]({})", uri)),
+        MediaKind::Audio => Some(format!("[audio ({})]({})", mime_type, uri)),
+        MediaKind::Video => Some(format!("[video ({})]({})", mime_type, uri)),
+        MediaKind::Other => match mode {
+            InlineMediaMode::Extended => Some(format!("[attachment ({})]({})", mime_type, uri)),
+            InlineMediaMode::Strict => {
+                warnings.record(
+                    codes::INLINE_MEDIA_DROPPED,
+                    format!(
+                        "inlineData with unsupported mimeType {} dropped ({} bytes base64)",
+                        mime_type,
+                        data.len()
+                    ),
+                    None,
+                );
+                None
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(mode: InlineMediaMode, mime_type: &str, data: &str) -> Option<String> {
+        render_inline_data(mode, mime_type, data, &mut Warnings::new())
+    }
+
+    #[test]
+    fn test_render_by_mime_family() {
+        let mode = InlineMediaMode::Extended;
+        assert_eq!(
+            render(mode, "image/png", "iVBO").as_deref(),
+            Some("![image](data:image/png;base64,iVBO)")
+        );
+        assert_eq!(
+            render(mode, "audio/wav", "UklG").as_deref(),
+            Some("[audio (audio/wav)](data:audio/wav;base64,UklG)")
+        );
+        assert_eq!(
+            render(mode, "video/mp4", "AAAA").as_deref(),
+            Some("[video (video/mp4)](data:video/mp4;base64,AAAA)")
+        );
+        assert_eq!(
+            render(mode, "application/pdf", "JVBE").as_deref(),
+            Some("[attachment (application/pdf)](data:application/pdf;base64,JVBE)")
+        );
+        assert!(render(mode, "image/png", "").is_none());
+    }
+
+    #[test]
+    fn test_strict_mode_drops_unknown_types_only() {
+        let mode = InlineMediaMode::Strict;
+        let mut warnings = Warnings::new();
+        assert!(render_inline_data(mode, "application/pdf", "JVBE", &mut warnings).is_none());
+        assert_eq!(warnings.as_slice()[0].code, codes::INLINE_MEDIA_DROPPED);
+        assert!(render(mode, "", "JVBE").is_none());
+        assert!(render(mode, "audio/mpeg", "SUQz").is_some());
+        assert!(render(mode, "IMAGE/JPEG", "/9j/").unwrap().starts_with("![image]"));
+    }
+}
@@ -0,0 +1,437 @@
+// OpenAI 数据模型
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 可识别的消息内容片段类型；其余类型 (input_audio、file 等) 解析为 OpenAIContentBlock::Unsupported 并被忽略
+pub const CONTENT_PART_TYPES: &[&str] = &["text", "image_url"];
+
+/// 单次请求最多生成的候选数 (OpenAI `n` -> Gemini candidateCount)
+pub const MAX_CANDIDATES: u8 = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIRequest {
+    pub model: String,
+    #[serde(default)]
+    pub messages: Vec<OpenAIMessage>,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(rename = "max_tokens")]
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    #[serde(rename = "top_p")]
+    pub top_p: Option<f32>,
+    /// 非标准字段 (部分客户端会发送)，映射到 generationConfig.topK
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    pub stop: Option<Value>,
+    pub response_format: Option<ResponseFormat>,
+    #[serde(default)]
+    pub tools: Option<Vec<Value>>,
+    #[serde(rename = "tool_choice")]
+    pub tool_choice: Option<Value>,
+    #[serde(rename = "parallel_tool_calls")]
+    pub parallel_tool_calls: Option<bool>,
+    /// 独立候选数 (超出 1..=MAX_CANDIDATES 时截断)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u8>,
+    // Codex proprietary fields
+    pub instructions: Option<String>,
+    pub input: Option<Value>,
+}
+
+impl OpenAIRequest {
+    /// 实际请求的候选数 (未指定时为 1)
+    pub fn candidate_count(&self) -> u32 {
+        u32::from(self.n.unwrap_or(1).clamp(1, MAX_CANDIDATES))
+    }
+
+    /// 归一化后的 stop (OpenAI 允许字符串或字符串数组)
+    pub fn stop_sequences(&self) -> Vec<String> {
+        match &self.stop {
+            Some(Value::String(stop)) => vec![stop.clone()],
+            Some(Value::Array(stops)) => stops.iter().filter_map(|s| s.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// 流式选项 (stream_options)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// 为 true 时在 [DONE] 前追加一个仅包含 usage 的 chunk
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// Legacy Completions 的 prompt：字符串或字符串数组
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LegacyPrompt {
+    Text(String),
+    Batch(Vec<String>),
+}
+
+/// Legacy Completions 请求 (/v1/completions)
+/// 转换为只含一条 user 消息的 Chat 请求后复用 Chat 管线，响应再整形为 `choices[].text`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyCompletionRequest {
+    pub model: String,
+    pub prompt: LegacyPrompt,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<Value>,
+}
+
+impl LegacyPrompt {
+    /// 数组形式按换行拼接为一段提示
+    pub fn text(&self) -> String {
+        match self {
+            LegacyPrompt::Text(s) => s.clone(),
+            LegacyPrompt::Batch(items) => items.join("\n"),
+        }
+    }
+}
+
+impl LegacyCompletionRequest {
+    pub fn into_chat_request(self) -> OpenAIRequest {
+        OpenAIRequest {
+            model: self.model,
+            messages: vec![OpenAIMessage {
+                role: "user".to_string(),
+                content: Some(OpenAIContent::String(self.prompt.text())),
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            }],
+            prompt: None,
+            stream: self.stream,
+            stream_options: self.stream_options,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            stop: self.stop,
+            response_format: None,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            n: None,
+            instructions: None,
+            input: None,
+        }
+    }
+}
+
+/// OpenAI Embeddings 请求 (/v1/embeddings)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIEmbeddingRequest {
+    pub model: String,
+    /// 字符串或字符串数组 (Token 数组输入不支持)
+    pub input: Value,
+    #[serde(default)]
+    pub encoding_format: Option<String>,
+    #[serde(default)]
+    pub dimensions: Option<u32>,
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFormat {
+    pub r#type: String,
+    /// type 为 json_schema 时的结构定义
+    #[serde(default)]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub schema: Option<Value>,
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+impl ResponseFormat {
+    /// 是否要求 JSON 输出 (json_object / json_schema)
+    pub fn wants_json(&self) -> bool {
+        matches!(self.r#type.as_str(), "json_object" | "json_schema")
+    }
+
+    /// 转换为 Gemini responseSchema；text / json_object 返回 None，无法表达的 schema 返回错误
+    /// 是否携带 json_schema (上游据此生成 responseSchema)
+    pub fn has_schema(&self) -> bool {
+        self.r#type == "json_schema"
+    }
+
+    pub fn gemini_schema(&self) -> Result<Option<Value>, String> {
+        match self.r#type.as_str() {
+            "text" | "json_object" => Ok(None),
+            "json_schema" => {
+                let schema = self
+                    .json_schema
+                    .as_ref()
+                    .and_then(|s| s.schema.as_ref())
+                    .ok_or("response_format.json_schema.schema is required")?;
+                crate::json_schema::convert_json_schema_to_gemini_schema(schema)
+                    .map(Some)
+                    .map_err(|e| format!("Invalid response_format.json_schema: {}", e))
+            }
+            other => Err(format!("Unsupported response_format type: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OpenAIContent {
+    String(String),
+    Array(Vec<OpenAIContentBlock>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum OpenAIContentBlock {
+    #[serde(rename = "text")]
+    Text {
+        text: String,
+    },
+    #[serde(rename = "image_url")]
+    ImageUrl {
+        image_url: OpenAIImageUrl,
+    },
+    /// 未识别的片段类型：解析时接受，转换时忽略
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenAIImageUrl {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIMessage {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<OpenAIContent>,
+    /// 思维链文本 (DeepSeek / OpenRouter 约定)，仅出现在响应中
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    /// 部分客户端回传历史 tool_calls 时省略 type
+    #[serde(default = "default_tool_call_type")]
+    pub r#type: String,
+    pub function: ToolFunction,
+}
+
+fn default_tool_call_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolFunction {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<Choice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenAIUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PromptTokensDetails {
+    pub cached_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompletionTokensDetails {
+    pub reasoning_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub index: u32,
+    pub message: OpenAIMessage,
+    pub finish_reason: Option<String>,
+    /// 请求带 stop 且因停止而结束时输出 (部分旧客户端读取该字段而非 finish_reason)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_details: Option<FinishDetails>,
+}
+
+/// 旧版结束详情：`{"type": "stop", "stop": "<命中的停止序列>"}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FinishDetails {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// 命中的停止序列 (上游未保留该序列时无法确定，省略)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn stop_accepts_string_or_array() {
+        let parse = |stop: Value| -> OpenAIRequest {
+            serde_json::from_value(json!({"model": "gpt-4o", "messages": [], "stop": stop})).unwrap()
+        };
+        assert_eq!(parse(json!("END")).stop_sequences(), vec!["END"]);
+        assert_eq!(parse(json!(["a", 1, "b"])).stop_sequences(), vec!["a", "b"]);
+        assert!(parse(Value::Null).stop_sequences().is_empty());
+    }
+
+    /// 各客户端实际发送的请求体 (节选)，必须全部能解析
+    #[test]
+    fn client_corpus_parses() {
+        let corpus = vec![
+            (
+                "openai-python",
+                json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "developer", "content": "Be terse"}, {"role": "user", "content": "hi"}],
+                    "stream": true,
+                    "stream_options": {"include_usage": true, "include_obfuscation": false},
+                    "store": false,
+                    "metadata": {"project": "demo"},
+                    "reasoning_effort": "low",
+                    "modalities": ["text"],
+                    "service_tier": "auto"
+                }),
+            ),
+            (
+                "librechat",
+                json!({
+                    "model": "gemini-2.5-pro",
+                    "user": "6650a1",
+                    "temperature": 1,
+                    "messages": [{"role": "user", "content": [
+                        {"type": "text", "text": "what is this"},
+                        {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA", "detail": "auto"}}
+                    ]}]
+                }),
+            ),
+            (
+                "cursor",
+                json!({
+                    "model": "gpt-4.1",
+                    "messages": [
+                        {"role": "user", "content": "refactor"},
+                        {"role": "assistant", "content": null, "tool_calls": [{"id": "call_1", "function": {"name": "edit", "arguments": "{}"}}]},
+                        {"role": "tool", "tool_call_id": "call_1", "content": "ok"}
+                    ],
+                    "tools": [{"type": "function", "function": {"name": "edit", "parameters": {"type": "object"}, "strict": true}}],
+                    "parallel_tool_calls": false,
+                    "prediction": {"type": "content", "content": "..."}
+                }),
+            ),
+            (
+                "continue",
+                json!({
+                    "model": "gpt-4o-mini",
+                    "max_completion_tokens": 2048,
+                    "messages": [{"role": "user", "content": [
+                        {"type": "text", "text": "transcribe"},
+                        {"type": "input_audio", "input_audio": {"data": "UklGRg==", "format": "wav"}},
+                        {"type": "file", "file": {"file_id": "file-abc"}}
+                    ]}],
+                    "web_search_options": {"search_context_size": "low"}
+                }),
+            ),
+        ];
+        for (client, body) in corpus {
+            let req = match serde_json::from_value::<OpenAIRequest>(body) {
+                Ok(req) => req,
+                Err(e) => panic!("{} request failed to parse: {}", client, e),
+            };
+            if client == "continue" {
+                let Some(OpenAIContent::Array(parts)) = &req.messages[0].content else {
+                    panic!("expected content parts");
+                };
+                assert_eq!(parts[1], OpenAIContentBlock::Unsupported);
+            }
+            if client == "cursor" {
+                assert_eq!(req.messages[1].tool_calls.as_ref().unwrap()[0].r#type, "function");
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_completion_becomes_single_user_message() {
+        let req: LegacyCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-3.5-turbo-instruct",
+            "prompt": ["def add(a, b):", "    return"],
+            "max_tokens": 16,
+            "stream": true,
+            "stop": ["\n\n"]
+        }))
+        .unwrap();
+
+        let chat = req.into_chat_request();
+        assert_eq!(chat.messages.len(), 1);
+        assert_eq!(chat.messages[0].role, "user");
+        assert_eq!(
+            chat.messages[0].content,
+            Some(OpenAIContent::String("def add(a, b):\n    return".to_string()))
+        );
+        assert!(chat.stream);
+        assert_eq!(chat.max_tokens, Some(16));
+        assert_eq!(chat.stop, Some(json!(["\n\n"])));
+    }
+}
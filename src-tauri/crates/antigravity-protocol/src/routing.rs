@@ -0,0 +1,309 @@
+// 模型名称映射与路由解析 (不含日志；调用方根据 RouteRule 自行记录)
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+fn claude_to_gemini() -> &'static HashMap<&'static str, &'static str> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut m = HashMap::new();
+
+        // 直接支持的模型
+        m.insert("claude-opus-4-5-thinking", "claude-opus-4-5-thinking");
+        m.insert("claude-sonnet-4-5", "claude-sonnet-4-5");
+        m.insert("claude-sonnet-4-5-thinking", "claude-sonnet-4-5-thinking");
+
+        // 别名映射
+        m.insert("claude-sonnet-4-5-20250929", "claude-sonnet-4-5-thinking");
+        m.insert("claude-3-5-sonnet-20241022", "claude-sonnet-4-5");
+        m.insert("claude-3-5-sonnet-20240620", "claude-sonnet-4-5");
+        m.insert("claude-opus-4", "claude-opus-4-5-thinking");
+        m.insert("claude-opus-4-5-20251101", "claude-opus-4-5-thinking");
+        m.insert("claude-haiku-4", "claude-sonnet-4-5");
+        m.insert("claude-3-haiku-20240307", "claude-sonnet-4-5");
+        m.insert("claude-haiku-4-5-20251001", "claude-sonnet-4-5");
+        // OpenAI 协议映射表
+        m.insert("gpt-4", "gemini-2.5-pro");
+        m.insert("gpt-4-turbo", "gemini-2.5-pro");
+        m.insert("gpt-4-turbo-preview", "gemini-2.5-pro");
+        m.insert("gpt-4-0125-preview", "gemini-2.5-pro");
+        m.insert("gpt-4-1106-preview", "gemini-2.5-pro");
+        m.insert("gpt-4-0613", "gemini-2.5-pro");
+
+        m.insert("gpt-4o", "gemini-2.5-pro");
+        m.insert("gpt-4o-2024-05-13", "gemini-2.5-pro");
+        m.insert("gpt-4o-2024-08-06", "gemini-2.5-pro");
+
+        m.insert("gpt-4o-mini", "gemini-2.5-flash");
+        m.insert("gpt-4o-mini-2024-07-18", "gemini-2.5-flash");
+
+        m.insert("gpt-3.5-turbo", "gemini-2.5-flash");
+        m.insert("gpt-3.5-turbo-16k", "gemini-2.5-flash");
+        m.insert("gpt-3.5-turbo-0125", "gemini-2.5-flash");
+        m.insert("gpt-3.5-turbo-1106", "gemini-2.5-flash");
+        m.insert("gpt-3.5-turbo-0613", "gemini-2.5-flash");
+
+        // Gemini 协议映射表
+        m.insert("gemini-2.5-flash-lite", "gemini-2.5-flash-lite");
+        m.insert("gemini-2.5-flash-thinking", "gemini-2.5-flash-thinking");
+        m.insert("gemini-3-pro-low", "gemini-3-pro-low");
+        m.insert("gemini-3-pro-high", "gemini-3-pro-high");
+        m.insert("gemini-3-pro-preview", "gemini-3-pro-preview");
+        m.insert("gemini-2.5-flash", "gemini-2.5-flash");
+        m.insert("gemini-3-flash", "gemini-3-flash");
+        m.insert("gemini-3-pro-image", "gemini-3-pro-image");
+
+        m
+    })
+}
+
+pub fn map_claude_model_to_gemini(input: &str) -> String {
+    // 1. Check exact match in map
+    if let Some(mapped) = claude_to_gemini().get(input) {
+        return mapped.to_string();
+    }
+
+    // 2. Pass-through known prefixes (gemini-, -thinking) to support dynamic suffixes
+    if input.starts_with("gemini-") || input.contains("thinking") {
+        return input.to_string();
+    }
+
+    // 3. Fallback to default
+    "claude-sonnet-4-5".to_string()
+}
+
+/// 获取所有内置支持的模型列表关键字
+pub fn get_supported_models() -> Vec<String> {
+    claude_to_gemini().keys().map(|s| s.to_string()).collect()
+}
+
+/// 获取内置映射表中实际会发往上游的模型 (去重排序，用于账号探测)
+pub fn get_upstream_models() -> Vec<String> {
+    let mut models: Vec<String> = claude_to_gemini().values().map(|s| s.to_string()).collect();
+    models.sort();
+    models.dedup();
+    models
+}
+
+/// 路由命中的规则 (供调用方记录日志)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteRule {
+    /// 自定义精确映射
+    Custom,
+    /// GPT-4 系列映射
+    Gpt4Series,
+    /// GPT-4o / 3.5 系列映射
+    Gpt4oSeries,
+    /// GPT-5 系列映射
+    Gpt5Series,
+    /// GPT-5 未配置时回落到 GPT-4 系列映射
+    Gpt5FallbackToGpt4,
+    /// 非 CLI 请求的原生 Claude 模型直通
+    ClaudePassthrough,
+    /// Haiku 智能降级 (仅 CLI)
+    HaikuDowngrade,
+    /// Anthropic 家族映射
+    AnthropicFamily,
+    /// 旧版 Anthropic 精确映射
+    AnthropicExact,
+    /// 内置映射表 / 默认模型
+    Builtin,
+}
+
+/// 路由解析结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelRoute {
+    pub model: String,
+    pub rule: RouteRule,
+}
+
+impl ModelRoute {
+    fn new(model: impl Into<String>, rule: RouteRule) -> Self {
+        Self { model: model.into(), rule }
+    }
+}
+
+/// 核心模型路由解析引擎
+/// 优先级：Custom Mapping (精确) > Group Mapping (家族) > System Mapping (内置插件)
+///
+/// # 参数
+/// - `apply_claude_family_mapping`: 是否对 Claude 模型应用家族映射
+///   - `true`: CLI 请求，应用家族映射（如 claude-sonnet-4-5 -> gemini-3-pro-high）
+///   - `false`: 非 CLI 请求（如 Cherry Studio），跳过家族映射，直接穿透
+pub fn route_model(
+    original_model: &str,
+    custom_mapping: &HashMap<String, String>,
+    openai_mapping: &HashMap<String, String>,
+    anthropic_mapping: &HashMap<String, String>,
+    apply_claude_family_mapping: bool,
+) -> ModelRoute {
+    // 1. 检查自定义精确映射 (优先级最高)
+    if let Some(target) = custom_mapping.get(original_model) {
+        return ModelRoute::new(target, RouteRule::Custom);
+    }
+
+    let lower_model = original_model.to_lowercase();
+
+    // 2. 检查家族分组映射 (OpenAI 系)
+    // GPT-4 系列 (含 GPT-4 经典, o1, o3 等, 排除 4o/mini/turbo)
+    if (lower_model.starts_with("gpt-4")
+        && !lower_model.contains('o')
+        && !lower_model.contains("mini")
+        && !lower_model.contains("turbo"))
+        || lower_model.starts_with("o1-")
+        || lower_model.starts_with("o3-")
+        || lower_model == "gpt-4"
+    {
+        if let Some(target) = openai_mapping.get("gpt-4-series") {
+            return ModelRoute::new(target, RouteRule::Gpt4Series);
+        }
+    }
+
+    // GPT-4o / 3.5 系列 (均衡与轻量, 含 4o, mini, turbo)
+    if lower_model.contains("4o")
+        || lower_model.starts_with("gpt-3.5")
+        || (lower_model.contains("mini") && !lower_model.contains("gemini"))
+        || lower_model.contains("turbo")
+    {
+        if let Some(target) = openai_mapping.get("gpt-4o-series") {
+            return ModelRoute::new(target, RouteRule::Gpt4oSeries);
+        }
+    }
+
+    // GPT-5 系列 (gpt-5, gpt-5.1, gpt-5.2 等)
+    if lower_model.starts_with("gpt-5") {
+        // 优先使用 gpt-5-series 映射，如果没有则使用 gpt-4-series
+        if let Some(target) = openai_mapping.get("gpt-5-series") {
+            return ModelRoute::new(target, RouteRule::Gpt5Series);
+        }
+        if let Some(target) = openai_mapping.get("gpt-4-series") {
+            return ModelRoute::new(target, RouteRule::Gpt5FallbackToGpt4);
+        }
+    }
+
+    // 3. 检查家族分组映射 (Anthropic 系)
+    if lower_model.starts_with("claude-") {
+        // [CRITICAL] 检查是否应用 Claude 家族映射
+        // 如果是非 CLI 请求（如 Cherry Studio），先检查是否为原生支持的直通模型
+        if !apply_claude_family_mapping {
+            if let Some(mapped) = claude_to_gemini().get(original_model) {
+                if *mapped == original_model {
+                    // 原生支持的直通模型，跳过家族映射
+                    return ModelRoute::new(original_model, RouteRule::ClaudePassthrough);
+                }
+            }
+        }
+
+        // Haiku 智能降级策略：仅在 CLI 模式下将所有 Haiku 模型降级到 gemini-2.5-flash-lite
+        if apply_claude_family_mapping && lower_model.contains("haiku") {
+            return ModelRoute::new("gemini-2.5-flash-lite", RouteRule::HaikuDowngrade);
+        }
+
+        let family_key = if lower_model.contains("4-5") || lower_model.contains("4.5") {
+            "claude-4.5-series"
+        } else if lower_model.contains("3-5") || lower_model.contains("3.5") {
+            "claude-3.5-series"
+        } else {
+            "claude-default"
+        };
+
+        if let Some(target) = anthropic_mapping.get(family_key) {
+            return ModelRoute::new(target, RouteRule::AnthropicFamily);
+        }
+
+        // 兜底兼容旧版精确映射
+        if let Some(target) = anthropic_mapping.get(original_model) {
+            return ModelRoute::new(target, RouteRule::AnthropicExact);
+        }
+    }
+
+    // 4. 下沉到系统默认映射逻辑
+    ModelRoute::new(map_claude_model_to_gemini(original_model), RouteRule::Builtin)
+}
+
+/// 默认 Embedding 模型
+pub const DEFAULT_EMBEDDING_MODEL: &str = "gemini-embedding-001";
+
+/// Embedding 模型路由
+/// 优先级：Custom Mapping (精确) > Gemini 原生 Embedding 模型直通 > 默认模型
+/// (OpenAI 的 text-embedding-3-* / ada-002 等统一落到默认模型)
+pub fn resolve_embedding_model(
+    original_model: &str,
+    custom_mapping: &HashMap<String, String>,
+) -> String {
+    if let Some(target) = custom_mapping.get(original_model) {
+        return target.clone();
+    }
+
+    let lower_model = original_model.to_lowercase();
+    if lower_model.starts_with("gemini-embedding") || lower_model.starts_with("text-embedding-00") {
+        return original_model.to_string();
+    }
+
+    DEFAULT_EMBEDDING_MODEL.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_mapping() {
+        assert_eq!(
+            map_claude_model_to_gemini("claude-3-5-sonnet-20241022"),
+            "claude-sonnet-4-5"
+        );
+        assert_eq!(
+            map_claude_model_to_gemini("claude-opus-4"),
+            "claude-opus-4-5-thinking"
+        );
+        // Test gemini pass-through (should not be caught by "mini" rule)
+        assert_eq!(
+            map_claude_model_to_gemini("gemini-2.5-flash-mini-test"),
+            "gemini-2.5-flash-mini-test"
+        );
+        assert_eq!(
+            map_claude_model_to_gemini("unknown-model"),
+            "claude-sonnet-4-5"
+        );
+    }
+
+    #[test]
+    fn test_route_rules() {
+        let empty = HashMap::new();
+        let mut custom = HashMap::new();
+        custom.insert("my-model".to_string(), "gemini-3-flash".to_string());
+        let mut openai = HashMap::new();
+        openai.insert("gpt-4-series".to_string(), "gemini-3-pro-high".to_string());
+        let mut anthropic = HashMap::new();
+        anthropic.insert("claude-4.5-series".to_string(), "gemini-3-pro-high".to_string());
+
+        let route = route_model("my-model", &custom, &openai, &anthropic, true);
+        assert_eq!(route, ModelRoute::new("gemini-3-flash", RouteRule::Custom));
+
+        let route = route_model("gpt-5.1", &empty, &openai, &anthropic, true);
+        assert_eq!(route.rule, RouteRule::Gpt5FallbackToGpt4);
+        assert_eq!(route.model, "gemini-3-pro-high");
+
+        // 非 CLI 请求：原生支持的 Claude 模型直通
+        let route = route_model("claude-sonnet-4-5", &empty, &openai, &anthropic, false);
+        assert_eq!(route, ModelRoute::new("claude-sonnet-4-5", RouteRule::ClaudePassthrough));
+
+        // CLI 请求：应用家族映射 / Haiku 降级
+        let route = route_model("claude-sonnet-4-5", &empty, &openai, &anthropic, true);
+        assert_eq!(route.rule, RouteRule::AnthropicFamily);
+        let route = route_model("claude-haiku-4", &empty, &openai, &anthropic, true);
+        assert_eq!(route, ModelRoute::new("gemini-2.5-flash-lite", RouteRule::HaikuDowngrade));
+
+        let route = route_model("gemini-2.5-flash", &empty, &empty, &empty, true);
+        assert_eq!(route, ModelRoute::new("gemini-2.5-flash", RouteRule::Builtin));
+    }
+
+    #[test]
+    fn test_embedding_model_mapping() {
+        let mut custom = HashMap::new();
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &custom), DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(resolve_embedding_model("text-embedding-004", &custom), "text-embedding-004");
+        custom.insert("text-embedding-3-small".to_string(), "text-embedding-004".to_string());
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &custom), "text-embedding-004");
+    }
+}
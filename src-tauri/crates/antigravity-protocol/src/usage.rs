@@ -0,0 +1,79 @@
+// Token 用量换算与估算
+
+use crate::claude::{Usage, UsageMetadata};
+
+/// 估算纯文本 Token 数
+/// ASCII 约 4 字符/Token，CJK 等非 ASCII 字符约 1 字符/Token
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let mut ascii = 0u64;
+    let mut non_ascii = 0u64;
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else {
+            non_ascii += 1;
+        }
+    }
+    ascii.div_ceil(4) + non_ascii
+}
+
+/// 从 Gemini UsageMetadata 转换为 Claude Usage
+pub fn to_claude_usage(usage_metadata: &UsageMetadata) -> Usage {
+    let prompt_tokens = usage_metadata.prompt_token_count.unwrap_or(0);
+    let cached = usage_metadata.cached_content_token_count;
+    let cached_tokens = cached.unwrap_or(0);
+
+    Usage {
+        // input_tokens 应该排除缓存的部分
+        input_tokens: prompt_tokens.saturating_sub(cached_tokens),
+        output_tokens: usage_metadata.candidates_token_count.unwrap_or(0),
+        // 缓存统计：仅在上游返回 cachedContentTokenCount 时输出 (缺失 != 0)
+        cache_read_input_tokens: cached,
+        cache_creation_input_tokens: None, // Gemini 不提供此字段
+        server_tool_use: None,
+    }
+}
+
+/// 上游未返回 usageMetadata 时的估算 Usage (按字符数估算，避免返回 0)
+pub fn estimated_claude_usage(input_tokens: u32, output_tokens: u32) -> Usage {
+    Usage {
+        input_tokens,
+        output_tokens,
+        cache_read_input_tokens: None,
+        cache_creation_input_tokens: None,
+        server_tool_use: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_text_tokens() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_to_claude_usage() {
+        let usage = UsageMetadata {
+            prompt_token_count: Some(100),
+            candidates_token_count: Some(50),
+            total_token_count: Some(150),
+            cached_content_token_count: None,
+        };
+
+        let claude_usage = to_claude_usage(&usage);
+        assert_eq!(claude_usage.input_tokens, 100);
+        assert_eq!(claude_usage.output_tokens, 50);
+        assert!(claude_usage.cache_read_input_tokens.is_none());
+
+        let cached = UsageMetadata {
+            cached_content_token_count: Some(0),
+            ..usage
+        };
+        assert_eq!(to_claude_usage(&cached).cache_read_input_tokens, Some(0));
+    }
+}
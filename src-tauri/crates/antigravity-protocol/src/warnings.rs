@@ -0,0 +1,85 @@
+// 非致命转换决策的结构化警告
+// 转换请求 / 响应时被静默修正的内容 (忽略参数、截断预算、清理工具 Schema、图片回退等)
+// 由转换函数写入调用方传入的 Warnings，是否写入日志 / 响应由调用方决定
+use serde::{Deserialize, Serialize};
+
+/// 警告代码 (客户端可按代码过滤)
+pub mod codes {
+    /// 参数不被支持，已忽略
+    pub const UNSUPPORTED_PARAM: &str = "unsupported_param";
+    /// 内容块来源类型不被支持，已丢弃
+    pub const UNSUPPORTED_CONTENT: &str = "unsupported_content";
+    /// 采样参数按模型约束修正或移除
+    pub const SAMPLING_ADJUSTED: &str = "sampling_adjusted";
+    /// thinking 预算超过模型上限，已截断
+    pub const THINKING_BUDGET_CLAMPED: &str = "thinking_budget_clamped";
+    /// 签名无效的 thinking 块已从历史中移除
+    pub const THINKING_BLOCK_DROPPED: &str = "thinking_block_dropped";
+    /// stop_sequences 超出上游上限，已截断
+    pub const STOP_SEQUENCES_TRUNCATED: &str = "stop_sequences_truncated";
+    /// 工具参数 Schema 中不被支持的字段已清理
+    pub const TOOL_SCHEMA_SANITIZED: &str = "tool_schema_sanitized";
+    /// 远程图片下载失败，回退为 fileData 交给上游拉取
+    pub const IMAGE_FETCH_FAILED: &str = "image_fetch_failed";
+    /// 不支持的 inlineData 输出类型已丢弃
+    pub const INLINE_MEDIA_DROPPED: &str = "inline_media_dropped";
+}
+
+/// 单条转换警告
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionWarning {
+    pub code: String,
+    pub message: String,
+    /// 触发警告的请求字段 (如 `messages[2].content[0].source.type`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// 一次转换期间收集的警告 (相同的警告只保留一条)
+#[derive(Debug, Clone, Default)]
+pub struct Warnings(Vec<ConversionWarning>);
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一条警告
+    pub fn record(&mut self, code: &str, message: impl Into<String>, path: Option<String>) {
+        let warning = ConversionWarning {
+            code: code.to_string(),
+            message: message.into(),
+            path,
+        };
+        if !self.0.contains(&warning) {
+            self.0.push(warning);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[ConversionWarning] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<ConversionWarning> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupes_identical_warnings() {
+        let mut warnings = Warnings::new();
+        warnings.record(codes::UNSUPPORTED_PARAM, "ignored", Some("thinking.type".to_string()));
+        warnings.record(codes::UNSUPPORTED_PARAM, "ignored", Some("thinking.type".to_string()));
+        warnings.record(codes::SAMPLING_ADJUSTED, "topK=40 stripped", None);
+        let codes: Vec<_> = warnings.as_slice().iter().map(|w| w.code.as_str()).collect();
+        assert_eq!(codes, vec![codes::UNSUPPORTED_PARAM, codes::SAMPLING_ADJUSTED]);
+    }
+}
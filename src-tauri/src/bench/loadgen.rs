@@ -0,0 +1,533 @@
+// 进程内压测
+// 启动模拟上游与真实的反代服务 (AxumServer，监听 127.0.0.1 随机端口)，按 profile 的并发与请求组合施压，
+// 输出吞吐、延迟分位、峰值内存与每请求分配的 JSON 报告，可直接在不同提交之间 diff。
+// 用法: cargo run --features bench --bin loadgen -- --profile default [--concurrency N] [--requests N]
+//       [--warmup N] [--mix short=8,long=2,stream=5,tool=3,image=1] [--upstream-delay-ms N] [--output report.json]
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::alloc::{peak_rss_bytes, AllocSnapshot};
+use crate::proxy::monitor::ProxyMonitor;
+use crate::proxy::{AxumServer, ProxyConfig, ProxySecurityConfig, TokenManager};
+
+/// 模拟账号数 (均使用远期过期时间与固定 project_id，不触发刷新与项目解析)
+const BENCH_ACCOUNTS: usize = 4;
+
+/// 请求类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestKind {
+    /// 单轮短对话 (OpenAI Chat，非流式)
+    Short,
+    /// 长上下文多轮对话 (OpenAI Chat，非流式)
+    Long,
+    /// 流式对话 (Anthropic Messages)
+    Stream,
+    /// 带工具声明的对话 (Anthropic Messages，上游返回 functionCall)
+    Tool,
+    /// 图像生成 (OpenAI Images)
+    Image,
+}
+
+impl RequestKind {
+    const ALL: [RequestKind; 5] = [Self::Short, Self::Long, Self::Stream, Self::Tool, Self::Image];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Short => "short",
+            Self::Long => "long",
+            Self::Stream => "stream",
+            Self::Tool => "tool",
+            Self::Image => "image",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.name() == name)
+            .ok_or_else(|| format!("未知的请求类型: {} (可选 short/long/stream/tool/image)", name))
+    }
+
+    /// 请求路径与请求体
+    fn request(&self) -> (&'static str, Value) {
+        match self {
+            Self::Short => (
+                "/v1/chat/completions",
+                json!({
+                    "model": "gemini-2.5-flash",
+                    "messages": [{"role": "user", "content": "Say hello in one sentence."}]
+                }),
+            ),
+            Self::Long => {
+                let filler = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40);
+                let mut messages = vec![json!({"role": "system", "content": "You are a concise assistant."})];
+                for turn in 0..20 {
+                    messages.push(json!({"role": "user", "content": format!("Question {}: {}", turn, filler)}));
+                    messages.push(json!({"role": "assistant", "content": format!("Answer {}: {}", turn, filler)}));
+                }
+                messages.push(json!({"role": "user", "content": "Summarize the conversation."}));
+                ("/v1/chat/completions", json!({"model": "gemini-2.5-flash", "messages": messages}))
+            }
+            Self::Stream => (
+                "/v1/messages",
+                json!({
+                    "model": "claude-sonnet-4-5",
+                    "max_tokens": 1024,
+                    "stream": true,
+                    "messages": [{"role": "user", "content": "Write a short paragraph about foxes."}]
+                }),
+            ),
+            Self::Tool => (
+                "/v1/messages",
+                json!({
+                    "model": "claude-sonnet-4-5",
+                    "max_tokens": 1024,
+                    "tools": [{
+                        "name": "search_docs",
+                        "description": "Search the documentation",
+                        "input_schema": {
+                            "type": "object",
+                            "properties": {"query": {"type": "string"}},
+                            "required": ["query"]
+                        }
+                    }],
+                    "messages": [{"role": "user", "content": "Find the docs about streaming."}]
+                }),
+            ),
+            Self::Image => (
+                "/v1/images/generations",
+                json!({"model": "gemini-3-pro-image", "prompt": "A red fox in the snow", "n": 1}),
+            ),
+        }
+    }
+}
+
+/// 压测参数
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub concurrency: usize,
+    pub requests: usize,
+    /// 正式计时前的预热请求数 (不计入报告)
+    pub warmup: usize,
+    /// 请求类型及其权重
+    pub mix: Vec<(RequestKind, u32)>,
+    /// 模拟上游首字节延迟
+    pub upstream_delay: Duration,
+}
+
+impl Profile {
+    /// 内置 profile: smoke (快速冒烟) / default / heavy
+    pub fn named(name: &str) -> Result<Self, String> {
+        let mix = vec![
+            (RequestKind::Short, 8),
+            (RequestKind::Long, 2),
+            (RequestKind::Stream, 5),
+            (RequestKind::Tool, 3),
+            (RequestKind::Image, 1),
+        ];
+        let (concurrency, requests, warmup) = match name {
+            "smoke" => (4, 100, 10),
+            "default" => (16, 2000, 100),
+            "heavy" => (64, 20000, 500),
+            _ => return Err(format!("未知的 profile: {} (可选 smoke/default/heavy)", name)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            concurrency,
+            requests,
+            warmup,
+            mix,
+            upstream_delay: Duration::ZERO,
+        })
+    }
+
+    /// 解析命令行参数 (未指定 --profile 时使用 default)
+    pub fn from_args(args: &[String]) -> Result<(Self, Option<String>), String> {
+        let value_of = |flag: &str| -> Result<Option<&String>, String> {
+            match args.iter().position(|a| a == flag) {
+                Some(idx) => args.get(idx + 1).map(Some).ok_or_else(|| format!("{} 缺少参数值", flag)),
+                None => Ok(None),
+            }
+        };
+        let number = |flag: &str| -> Result<Option<usize>, String> {
+            value_of(flag)?
+                .map(|v| v.parse::<usize>().map_err(|_| format!("{} 需要非负整数: {}", flag, v)))
+                .transpose()
+        };
+
+        let mut profile = Self::named(value_of("--profile")?.map(|s| s.as_str()).unwrap_or("default"))?;
+        if let Some(concurrency) = number("--concurrency")? {
+            profile.concurrency = concurrency.max(1);
+        }
+        if let Some(requests) = number("--requests")? {
+            profile.requests = requests;
+        }
+        if let Some(warmup) = number("--warmup")? {
+            profile.warmup = warmup;
+        }
+        if let Some(delay) = number("--upstream-delay-ms")? {
+            profile.upstream_delay = Duration::from_millis(delay as u64);
+        }
+        if let Some(mix) = value_of("--mix")? {
+            profile.mix = parse_mix(mix)?;
+        }
+        let output = value_of("--output")?.cloned();
+        Ok((profile, output))
+    }
+
+    /// 按权重平滑交错的请求序列 (确定性，相同 profile 每次顺序一致)
+    fn schedule(&self) -> Vec<RequestKind> {
+        let total: i64 = self.mix.iter().map(|(_, w)| *w as i64).sum();
+        let mut current = vec![0i64; self.mix.len()];
+        let mut sequence = Vec::with_capacity(total as usize);
+        for _ in 0..total {
+            for (idx, (_, weight)) in self.mix.iter().enumerate() {
+                current[idx] += *weight as i64;
+            }
+            let Some(best) = (0..current.len()).max_by_key(|&i| (current[i], std::cmp::Reverse(i))) else {
+                break;
+            };
+            current[best] -= total;
+            sequence.push(self.mix[best].0);
+        }
+        sequence
+    }
+}
+
+fn parse_mix(spec: &str) -> Result<Vec<(RequestKind, u32)>, String> {
+    let mut mix = Vec::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, weight) = item
+            .split_once('=')
+            .ok_or_else(|| format!("请求组合格式应为 kind=weight: {}", item))?;
+        let weight = weight
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("权重需要非负整数: {}", item))?;
+        if weight > 0 {
+            mix.push((RequestKind::parse(name.trim())?, weight));
+        }
+    }
+    if mix.is_empty() {
+        return Err("请求组合为空".to_string());
+    }
+    Ok(mix)
+}
+
+/// 单个请求的结果
+struct Sample {
+    kind: RequestKind,
+    latency: Duration,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencySummary {
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KindSummary {
+    pub requests: usize,
+    pub errors: usize,
+    pub latency_ms: LatencySummary,
+    /// 第一个失败请求的错误信息 (便于排查模拟上游与转换器不匹配)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_error: Option<String>,
+}
+
+/// 压测报告
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub profile: String,
+    pub version: &'static str,
+    pub concurrency: usize,
+    pub requests: usize,
+    pub errors: usize,
+    pub duration_ms: f64,
+    pub throughput_rps: f64,
+    pub latency_ms: LatencySummary,
+    pub by_kind: BTreeMap<&'static str, KindSummary>,
+    /// 进程峰值常驻内存 (含模拟上游与压测客户端)
+    pub peak_rss_bytes: Option<u64>,
+    /// 计时区间内每个请求的平均分配次数与字节数 (含模拟上游与压测客户端)
+    pub allocations_per_request: f64,
+    pub allocated_bytes_per_request: f64,
+}
+
+fn round3(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// 最近秩法分位数 (`sorted` 为升序毫秒值)
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarize(latencies: &mut [f64]) -> LatencySummary {
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    let mean = if latencies.is_empty() {
+        0.0
+    } else {
+        latencies.iter().sum::<f64>() / latencies.len() as f64
+    };
+    LatencySummary {
+        mean: round3(mean),
+        p50: round3(percentile(latencies, 0.50)),
+        p95: round3(percentile(latencies, 0.95)),
+        p99: round3(percentile(latencies, 0.99)),
+        max: round3(latencies.last().copied().unwrap_or(0.0)),
+    }
+}
+
+/// 已启动的被测服务
+struct Harness {
+    server: AxumServer,
+    base_url: String,
+    api_key: String,
+    client: reqwest::Client,
+    data_dir: std::path::PathBuf,
+}
+
+impl Harness {
+    async fn start(profile: &Profile) -> Result<Self, String> {
+        let mock = super::mock_upstream::spawn(profile.upstream_delay).await?;
+        crate::proxy::upstream::client::override_base_url(format!("http://{}/v1internal", mock));
+
+        let data_dir = std::env::temp_dir().join(format!("antigravity-loadgen-{}", uuid::Uuid::new_v4().simple()));
+        write_accounts(&data_dir)?;
+        let token_manager = Arc::new(TokenManager::new(data_dir.clone()));
+        token_manager.reload_from_store().await?;
+
+        let mut config = ProxyConfig::default();
+        // 所有请求来自同一个本机地址，入站限流会让压测变成测限流
+        config.rate_limit.enabled = false;
+        let security = ProxySecurityConfig::from_proxy_config(&config);
+        let (server, _handle) = AxumServer::start(
+            "127.0.0.1".to_string(),
+            0,
+            token_manager,
+            config.anthropic_mapping.clone(),
+            config.openai_mapping.clone(),
+            config.custom_mapping.clone(),
+            config.request_timeout,
+            config.model_timeouts.clone(),
+            config.upstream_proxy.clone(),
+            security,
+            config.zai.clone(),
+            Arc::new(ProxyMonitor::new(0, None)),
+            config.backoff.clone(),
+            config.retry_budget.clone(),
+            config.rate_limit.clone(),
+            config.websocket.clone(),
+            config.guard.clone(),
+            config.image_files.clone(),
+            config.prompt_log_mode,
+            config.telemetry.clone(),
+        )
+        .await?;
+
+        let client = reqwest::Client::builder()
+            .pool_max_idle_per_host(profile.concurrency)
+            .timeout(Duration::from_secs(60))
+            .build()
+            .map_err(|e| format!("创建压测客户端失败: {}", e))?;
+        Ok(Self {
+            base_url: format!("http://{}", server.local_addr()),
+            api_key: config.api_key.clone(),
+            server,
+            client,
+            data_dir,
+        })
+    }
+
+    /// 发送一个请求并读完整个响应体 (流式请求的延迟即完整流的耗时)
+    async fn send(&self, kind: RequestKind) -> Sample {
+        let (path, body) = kind.request();
+        let started = Instant::now();
+        let result = async {
+            let response = self
+                .client
+                .post(format!("{}{}", self.base_url, path))
+                .bearer_auth(&self.api_key)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            let status = response.status();
+            let text = response.text().await.map_err(|e| e.to_string())?;
+            if !status.is_success() {
+                return Err(format!("HTTP {}: {}", status.as_u16(), text.chars().take(300).collect::<String>()));
+            }
+            Ok(())
+        }
+        .await;
+        Sample {
+            kind,
+            latency: started.elapsed(),
+            error: result.err(),
+        }
+    }
+
+    /// 以固定并发发送 `count` 个请求
+    async fn drive(self: &Arc<Self>, schedule: &Arc<Vec<RequestKind>>, count: usize, concurrency: usize) -> Vec<Sample> {
+        let next = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..concurrency.min(count.max(1)))
+            .map(|_| {
+                let harness = self.clone();
+                let schedule = schedule.clone();
+                let next = next.clone();
+                tokio::spawn(async move {
+                    let mut samples = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        if idx >= count {
+                            break;
+                        }
+                        samples.push(harness.send(schedule[idx % schedule.len()]).await);
+                    }
+                    samples
+                })
+            })
+            .collect();
+        let mut samples = Vec::with_capacity(count);
+        for worker in workers {
+            if let Ok(mut batch) = worker.await {
+                samples.append(&mut batch);
+            }
+        }
+        samples
+    }
+}
+
+fn write_accounts(data_dir: &std::path::Path) -> Result<(), String> {
+    let accounts_dir = data_dir.join("accounts");
+    std::fs::create_dir_all(&accounts_dir).map_err(|e| format!("创建账号目录失败: {}", e))?;
+    let now = chrono::Utc::now().timestamp();
+    for idx in 0..BENCH_ACCOUNTS {
+        let id = format!("bench-{}", idx);
+        let account = json!({
+            "id": id,
+            "email": format!("bench-{}@example.com", idx),
+            "created_at": now - 30 * 86400,
+            "token": {
+                "access_token": format!("at-{}", id),
+                "refresh_token": "rt",
+                "expires_in": 3600,
+                "expiry_timestamp": now + 30 * 86400,
+                "project_id": "bench-project"
+            }
+        });
+        std::fs::write(accounts_dir.join(format!("{}.json", id)), account.to_string())
+            .map_err(|e| format!("写入账号文件失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 执行一次压测并生成报告
+pub async fn run(profile: &Profile) -> Result<Report, String> {
+    let harness = Arc::new(Harness::start(profile).await?);
+    let schedule = Arc::new(profile.schedule());
+
+    harness.drive(&schedule, profile.warmup, profile.concurrency).await;
+
+    let allocs_before = AllocSnapshot::now();
+    let started = Instant::now();
+    let samples = harness.drive(&schedule, profile.requests, profile.concurrency).await;
+    let elapsed = started.elapsed();
+    let allocs = AllocSnapshot::now().since(&allocs_before);
+
+    let mut all: Vec<f64> = samples.iter().map(|s| s.latency.as_secs_f64() * 1000.0).collect();
+    let mut by_kind = BTreeMap::new();
+    for kind in RequestKind::ALL {
+        let of_kind: Vec<&Sample> = samples.iter().filter(|s| s.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        let mut latencies: Vec<f64> = of_kind.iter().map(|s| s.latency.as_secs_f64() * 1000.0).collect();
+        by_kind.insert(
+            kind.name(),
+            KindSummary {
+                requests: of_kind.len(),
+                errors: of_kind.iter().filter(|s| s.error.is_some()).count(),
+                latency_ms: summarize(&mut latencies),
+                first_error: of_kind.iter().find_map(|s| s.error.clone()),
+            },
+        );
+    }
+
+    let completed = samples.len().max(1) as f64;
+    let report = Report {
+        profile: profile.name.clone(),
+        version: env!("CARGO_PKG_VERSION"),
+        concurrency: profile.concurrency,
+        requests: samples.len(),
+        errors: samples.iter().filter(|s| s.error.is_some()).count(),
+        duration_ms: round3(elapsed.as_secs_f64() * 1000.0),
+        throughput_rps: round3(samples.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON)),
+        latency_ms: summarize(&mut all),
+        by_kind,
+        peak_rss_bytes: peak_rss_bytes(),
+        allocations_per_request: round3(allocs.allocations as f64 / completed),
+        allocated_bytes_per_request: round3(allocs.bytes as f64 / completed),
+    };
+
+    if let Ok(harness) = Arc::try_unwrap(harness) {
+        harness.server.stop_and_wait(Duration::from_secs(5)).await;
+        let _ = std::fs::remove_dir_all(&harness.data_dir);
+    }
+    Ok(report)
+}
+
+/// loadgen 可执行文件入口
+pub fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (profile, output) = Profile::from_args(&args)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("创建运行时失败: {}", e))?;
+    let report = runtime.block_on(run(&profile))?;
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    match output {
+        Some(path) => std::fs::write(&path, json + "\n").map_err(|e| format!("写入报告失败 {}: {}", path, e))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_interleaves_by_weight() {
+        let mut profile = Profile::named("smoke").unwrap();
+        profile.mix = parse_mix("short=3, stream=1,image=0").unwrap();
+        let names: Vec<&str> = profile.schedule().iter().map(|k| k.name()).collect();
+        assert_eq!(names, ["short", "short", "stream", "short"]);
+        assert!(parse_mix("bogus=1").is_err());
+        assert!(parse_mix("short=0").is_err());
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let mut latencies: Vec<f64> = (1..=100).rev().map(|v| v as f64).collect();
+        let summary = summarize(&mut latencies);
+        assert_eq!((summary.p50, summary.p95, summary.p99, summary.max), (50.0, 95.0, 99.0, 100.0));
+        assert_eq!(summarize(&mut []).p99, 0.0);
+    }
+}
@@ -0,0 +1,114 @@
+// 进程内模拟的 v1internal 上游
+// 按请求内容返回固定形态的响应：图像模型返回 inlineData，带工具声明时返回 functionCall，其余返回文本；
+// streamGenerateContent 以 SSE 分块返回。响应内容固定，便于不同提交之间对比
+use axum::{
+    body::{Body, Bytes},
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// 文本响应 (流式时按词拆分为多个事件)
+const REPLY_TEXT: &str = "The quick brown fox jumps over the lazy dog while the proxy converts every chunk between protocols without dropping a single token of the response stream.";
+/// 1x1 PNG
+const REPLY_IMAGE_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+/// 流式响应每个事件包含的词数
+const WORDS_PER_CHUNK: usize = 4;
+
+/// 在 127.0.0.1 的随机端口启动模拟上游，返回监听地址
+/// `delay` 为每个响应首字节前的固定延迟 (模拟上游耗时)
+pub async fn spawn(delay: Duration) -> Result<SocketAddr, String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("模拟上游绑定失败: {}", e))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| format!("获取模拟上游地址失败: {}", e))?;
+    // v1internal 路径形如 /v1internal:generateContent，不适合路由匹配，统一由 fallback 处理
+    let app = Router::new().fallback(move |uri: Uri, body: Bytes| handle(uri, body, delay));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("模拟上游退出: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+async fn handle(uri: Uri, body: Bytes, delay: Duration) -> Response {
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    let method = uri.path().rsplit(':').next().unwrap_or_default();
+    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    match method {
+        "generateContent" => axum::Json(envelope(&request, reply_parts(&request), true)).into_response(),
+        "streamGenerateContent" => stream_response(&request),
+        // countTokens / fetchAvailableModels 等辅助接口
+        _ => axum::Json(json!({})).into_response(),
+    }
+}
+
+/// 根据请求决定响应内容
+fn reply_parts(request: &Value) -> Vec<Value> {
+    let model = request.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+    if model.contains("image") {
+        return vec![json!({"inlineData": {"mimeType": "image/png", "data": REPLY_IMAGE_BASE64}})];
+    }
+    let tool = request
+        .pointer("/request/tools")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.get("functionDeclarations").and_then(|d| d.as_array()))
+        .flatten()
+        .find_map(|d| d.get("name").and_then(|n| n.as_str()));
+    match tool {
+        Some(name) => vec![json!({"functionCall": {"name": name, "args": {"query": "benchmark"}}})],
+        None => vec![json!({"text": REPLY_TEXT})],
+    }
+}
+
+/// v1internal 响应包装；`last` 为 true 时附带 finishReason 与 usageMetadata
+fn envelope(request: &Value, parts: Vec<Value>, last: bool) -> Value {
+    let model = request.get("model").cloned().unwrap_or(Value::Null);
+    let mut candidate = json!({"content": {"role": "model", "parts": parts}, "index": 0});
+    let mut response = json!({"modelVersion": model, "responseId": "bench-response"});
+    if last {
+        candidate["finishReason"] = json!("STOP");
+        let prompt_tokens = request.to_string().len() / 4;
+        response["usageMetadata"] = json!({
+            "promptTokenCount": prompt_tokens,
+            "candidatesTokenCount": 32,
+            "totalTokenCount": prompt_tokens + 32
+        });
+    }
+    response["candidates"] = json!([candidate]);
+    json!({"response": response, "traceId": "bench-trace"})
+}
+
+fn stream_response(request: &Value) -> Response {
+    let parts = reply_parts(request);
+    let mut events: Vec<Value> = Vec::new();
+    if parts.iter().any(|p| p.get("text").is_some()) {
+        let words: Vec<&str> = REPLY_TEXT.split_inclusive(' ').collect();
+        let chunks: Vec<String> = words.chunks(WORDS_PER_CHUNK).map(|c| c.concat()).collect();
+        for (idx, text) in chunks.iter().enumerate() {
+            events.push(envelope(request, vec![json!({"text": text})], idx + 1 == chunks.len()));
+        }
+    } else {
+        events.push(envelope(request, parts, true));
+    }
+
+    // 每个事件单独成块，与真实上游的分块方式一致
+    let chunks = events
+        .into_iter()
+        .map(|e| Ok::<_, std::convert::Infallible>(Bytes::from(format!("data: {}\n\n", e))));
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .body(Body::from_stream(futures::stream::iter(chunks)))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
@@ -0,0 +1,13 @@
+// 压测与性能回归工具 (仅 feature = "bench" 编译，不进入发布构建)
+// - loadgen: 在进程内启动反代服务与模拟上游，按请求组合施压并输出 JSON 报告 (src/bin/loadgen.rs)
+// - alloc: 计数分配器，统计每个请求的分配次数与字节数
+// - converters: 供 benches/converters.rs 调用的纯协议转换函数
+pub mod alloc;
+pub mod loadgen;
+pub mod mock_upstream;
+
+/// 纯协议转换函数 (不访问网络，不依赖运行时)
+pub mod converters {
+    pub use crate::proxy::mappers::claude::{transform_claude_request_in, transform_response};
+    pub use crate::proxy::mappers::openai::{transform_openai_request, transform_openai_response};
+}
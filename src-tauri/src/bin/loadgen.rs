@@ -0,0 +1,12 @@
+// 进程内压测入口 (cargo run --features bench --bin loadgen -- --profile default)
+use antigravity_tools_lib::bench::alloc::CountingAllocator;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    if let Err(e) = antigravity_tools_lib::bench::loadgen::main() {
+        eprintln!("loadgen: {}", e);
+        std::process::exit(1);
+    }
+}
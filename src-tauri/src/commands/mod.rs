@@ -47,8 +47,9 @@ pub async fn add_account(
     let mut account = account;
     let _ = internal_refresh_account_quota(&app, &mut account).await;
 
-    // 6. If proxy is running, reload token pool so changes take effect immediately.
-    let _ = crate::commands::proxy::reload_proxy_accounts(
+    // 6. 反代服务运行中时直接加入账号池，无需重启
+    let _ = crate::commands::proxy::add_proxy_account(
+        account.id.clone(),
         app.state::<crate::commands::proxy::ProxyServiceState>(),
     )
     .await;
@@ -66,6 +67,13 @@ pub async fn delete_account(app: tauri::AppHandle, account_id: String) -> Result
     })?;
     modules::logger::log_info(&format!("账号删除成功: {}", account_id));
 
+    // 反代服务运行中时移出账号池
+    let _ = crate::commands::proxy::remove_proxy_accounts(
+        vec![account_id],
+        app.state::<crate::commands::proxy::ProxyServiceState>(),
+    )
+    .await;
+
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
     Ok(())
@@ -86,6 +94,13 @@ pub async fn delete_accounts(
         e
     })?;
 
+    // 反代服务运行中时移出账号池
+    let _ = crate::commands::proxy::remove_proxy_accounts(
+        account_ids,
+        app.state::<crate::commands::proxy::ProxyServiceState>(),
+    )
+    .await;
+
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
     Ok(())
@@ -241,9 +256,32 @@ pub async fn refresh_all_quotas() -> Result<RefreshStats, String> {
 }
 
 /// 加载配置
+/// 配置文件损坏时返回上次有效配置，并通过事件通知前端
 #[tauri::command]
-pub async fn load_config() -> Result<AppConfig, String> {
-    modules::load_app_config()
+pub async fn load_config(app: tauri::AppHandle) -> Result<AppConfig, String> {
+    let config = modules::reload_app_config()?;
+    emit_config_warning(&app);
+    Ok(config)
+}
+
+/// 获取配置重载告警 (为空表示配置正常)
+#[tauri::command]
+pub async fn get_config_warning() -> Result<Option<String>, String> {
+    Ok(modules::config_warning())
+}
+
+/// 若上次配置重载失败，向前端发送告警事件
+pub fn emit_config_warning(app: &tauri::AppHandle) {
+    if let Some(warning) = modules::config_warning() {
+        let _ = app.emit(modules::config::CONFIG_RELOAD_FAILED_EVENT, warning);
+    }
+}
+
+fn listen_address_changed(
+    running: &crate::proxy::ProxyConfig,
+    updated: &crate::proxy::ProxyConfig,
+) -> bool {
+    running.port != updated.port || running.get_bind_address() != updated.get_bind_address()
 }
 
 /// 保存配置
@@ -253,25 +291,139 @@ pub async fn save_config(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     config: AppConfig,
 ) -> Result<(), String> {
+    crate::proxy::account_schedule::parse_schedules(&config.proxy.account_schedules)?;
+    // 运行中修改监听地址时先校验，避免保存无法生效的配置
+    if let Some(instance) = proxy_state.instance.read().await.as_ref() {
+        if listen_address_changed(&instance.config, &config.proxy) {
+            crate::commands::proxy::check_listen_address(&config.proxy)?;
+        }
+    }
     modules::save_app_config(&config)?;
 
     // 通知托盘配置已更新
     let _ = app.emit("config://updated", ());
+    modules::notifier::configure_notifications(&config.proxy.notifications);
+    let tls_changed = crate::utils::http::configure_upstream_tls(&config.proxy.upstream_tls);
 
     // 热更新正在运行的服务
-    let instance_lock = proxy_state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
+    let mut instance_lock = proxy_state.instance.write().await;
+    if let Some(instance) = instance_lock.as_mut() {
+        // 监听地址或端口变更时原地重新绑定 (映射、账号池与签名缓存保持不变)
+        if listen_address_changed(&instance.config, &config.proxy) {
+            let handle = instance
+                .axum_server
+                .restart(config.proxy.port, &config.proxy.get_bind_address())
+                .await?;
+            instance.server_handle =
+                crate::commands::proxy::watch_server_task(handle, config.proxy.port);
+            instance.config.port = config.proxy.port;
+            instance.config.host = config.proxy.host.clone();
+            instance.config.allow_lan_access = config.proxy.allow_lan_access;
+        }
         // 更新模型映射
         instance.axum_server.update_mapping(&config.proxy).await;
-        // 更新上游代理
+        // 上游 TLS (额外 CA) 变更时重建上游客户端
+        if tls_changed {
+            instance.axum_server.rebuild_upstream().await;
+        }
+        // 更新请求超时与上游代理
         instance
             .axum_server
-            .update_proxy(config.proxy.upstream_proxy.clone())
+            .update_runtime_config(&config.proxy)
             .await;
         // 更新安全策略 (auth)
         instance.axum_server.update_security(&config.proxy).await;
         // 更新 z.ai 配置
         instance.axum_server.update_zai(&config.proxy).await;
+        // 更新重试退避配置
+        instance.axum_server.update_backoff(&config.proxy).await;
+        // 更新对话内容日志模式
+        instance.axum_server.update_prompt_log(&config.proxy).await;
+        instance.axum_server.update_retry_budget(&config.proxy);
+        instance.axum_server.update_websocket(&config.proxy).await;
+        instance.axum_server.update_image_files(&config.proxy).await;
+        instance.axum_server.update_model_timeouts(&config.proxy).await;
+        // 更新入站限流配置
+        instance.axum_server.update_rate_limit(&config.proxy);
+        // 更新入站防护配置
+        instance.axum_server.update_guard(&config.proxy);
+        // 更新链路追踪配置
+        instance.axum_server.update_telemetry(&config.proxy);
+        // 更新账号熔断配置
+        instance
+            .token_manager
+            .update_circuit_breaker_config(config.proxy.circuit_breaker.clone());
+        // 更新账号冷却配置
+        instance
+            .token_manager
+            .update_cooldown_config(config.proxy.cooldown.clone());
+        // 更新新账号磨合期配置
+        instance
+            .token_manager
+            .update_break_in_config(config.proxy.break_in.clone());
+        instance
+            .token_manager
+            .update_maintenance_config(config.proxy.maintenance.clone());
+        // 更新账号每日上限
+        instance
+            .token_manager
+            .update_account_caps(&config.proxy.account_caps);
+        // 更新账号可用时段
+        instance
+            .token_manager
+            .update_account_schedules(&config.proxy.account_schedules);
+        // 更新账号并发限制
+        instance
+            .token_manager
+            .update_concurrency_config(&config.proxy.account_concurrency);
+        // 更新条件模型路由
+        crate::proxy::common::model_routes::configure_model_routes(&config.proxy.model_routes);
+        // 更新流式周期性用量事件
+        crate::proxy::common::stream_usage::configure_stream_usage(&config.proxy.stream_usage);
+        // 更新 SSE 保活间隔
+        crate::proxy::common::sse_keepalive::configure_sse_keepalive(config.proxy.sse_keepalive_secs);
+        // 更新 Prometheus 指标端点
+        crate::proxy::metrics::configure_metrics(&config.proxy.metrics);
+        // 更新网络状态重置检测
+        crate::proxy::upstream::network_reset::configure_network_reset(&config.proxy.network_reset);
+        // 更新 safetySettings
+        crate::proxy::common::safety::configure_safety_settings(&config.proxy.safety_settings);
+        // 更新后台账号健康探测
+        instance
+            .token_manager
+            .update_account_health_config(&config.proxy.account_health_check);
+        // 更新 token 提前刷新时间
+        instance
+            .token_manager
+            .update_token_refresh_config(&config.proxy.token_refresh);
+        // 更新 sessionId 轮换间隔
+        instance
+            .token_manager
+            .update_session_rotation(config.proxy.session_rotation_interval);
+        // 更新 thoughtSignature 缓存配置
+        crate::proxy::mappers::signature_store::configure_signature_cache(&config.proxy.signature_cache);
+        // 更新 inlineData 输出模式
+        crate::proxy::mappers::inline_media::configure_inline_media(&config.proxy.inline_media);
+        // 更新远程图片预处理配置
+        crate::proxy::mappers::openai::vision::configure_vision(&config.proxy.vision);
+        // 更新 thinking 默认预算
+        crate::proxy::mappers::claude::request::configure_thinking(&config.proxy.thinking);
+        // 更新 Message Batches 配置
+        crate::proxy::handlers::claude::configure_message_batches(&config.proxy.message_batches);
+        // 更新 OpenAI 思维链输出配置
+        crate::proxy::mappers::openai::configure_reasoning_output(&config.proxy.openai_reasoning);
+        // 更新响应兼容模式
+        crate::proxy::common::warnings::configure_compat_mode(&config.proxy.compat_mode);
+        // 更新响应 model 上报方式
+        crate::proxy::common::response_model::configure_report_model(&config.proxy.report_model);
+        // 更新流式断线续传配置
+        crate::proxy::mappers::claude::resume::configure_stream_resume(&config.proxy.stream_resume);
+        // 更新调试请求/响应体日志配置
+        crate::proxy::middleware::logging::configure_body_logging(&config.proxy.debug);
+        // 更新访问日志配置
+        crate::proxy::access_log::configure_access_log(&config.proxy.access_log);
+        // 更新日志库维护配置
+        crate::modules::storage_maintenance::configure_storage_maintenance(&config.proxy.storage);
         tracing::debug!("已同步热更新反代服务配置");
     }
 
@@ -336,8 +488,9 @@ pub async fn start_oauth_login(app_handle: tauri::AppHandle) -> Result<Account,
     // 7. 自动触发刷新额度
     let _ = internal_refresh_account_quota(&app_handle, &mut account).await;
 
-    // 8. If proxy is running, reload token pool so changes take effect immediately.
-    let _ = crate::commands::proxy::reload_proxy_accounts(
+    // 8. 反代服务运行中时直接加入账号池，无需重启
+    let _ = crate::commands::proxy::add_proxy_account(
+        account.id.clone(),
         app_handle.state::<crate::commands::proxy::ProxyServiceState>(),
     )
     .await;
@@ -402,8 +555,9 @@ pub async fn complete_oauth_login(app_handle: tauri::AppHandle) -> Result<Accoun
     // 7. 自动触发刷新额度
     let _ = internal_refresh_account_quota(&app_handle, &mut account).await;
 
-    // 8. If proxy is running, reload token pool so changes take effect immediately.
-    let _ = crate::commands::proxy::reload_proxy_accounts(
+    // 8. 反代服务运行中时直接加入账号池，无需重启
+    let _ = crate::commands::proxy::add_proxy_account(
+        account.id.clone(),
         app_handle.state::<crate::commands::proxy::ProxyServiceState>(),
     )
     .await;
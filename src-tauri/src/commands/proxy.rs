@@ -1,4 +1,4 @@
-use tauri::State;
+use tauri::{Emitter, State};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
@@ -16,6 +16,18 @@ pub struct ProxyStatus {
     pub active_accounts: usize,
 }
 
+/// 停止服务时等待在途请求完成的最长时间
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 停止服务期间推送剩余在途请求数
+pub const SHUTDOWN_PROGRESS_EVENT: &str = "proxy://shutdown-progress";
+
+/// 停止进度 (active_requests 为 0 表示已全部完成)
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownProgress {
+    pub active_requests: usize,
+}
+
 /// 反代服务全局状态
 pub struct ProxyServiceState {
     pub instance: Arc<RwLock<Option<ProxyServiceInstance>>>,
@@ -53,6 +65,8 @@ pub async fn start_proxy_service(
         return Err("服务已在运行中".to_string());
     }
 
+    check_listen_address(&config)?;
+
     // Ensure monitor exists
     {
         let mut monitor_lock = state.monitor.write().await;
@@ -76,10 +90,50 @@ pub async fn start_proxy_service(
     let token_manager = Arc::new(TokenManager::new(accounts_dir));
     // 同步 UI 传递的调度配置
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    token_manager.update_circuit_breaker_config(config.circuit_breaker.clone());
+    token_manager.update_cooldown_config(config.cooldown.clone());
+    token_manager.update_break_in_config(config.break_in.clone());
+    token_manager.update_maintenance_config(config.maintenance.clone());
+    token_manager.update_account_caps(&config.account_caps);
+    token_manager.update_account_schedules(&config.account_schedules);
+    token_manager.update_concurrency_config(&config.account_concurrency);
+    token_manager.update_token_refresh_config(&config.token_refresh);
+    token_manager.update_session_rotation(config.session_rotation_interval);
+    token_manager.update_account_health_config(&config.account_health_check);
+    crate::proxy::common::model_routes::configure_model_routes(&config.model_routes);
+    crate::proxy::common::safety::configure_safety_settings(&config.safety_settings);
+    crate::proxy::common::stream_usage::configure_stream_usage(&config.stream_usage);
+    crate::proxy::common::sse_keepalive::configure_sse_keepalive(config.sse_keepalive_secs);
+    crate::proxy::metrics::configure_metrics(&config.metrics);
+    crate::proxy::upstream::network_reset::configure_network_reset(&config.network_reset);
+    crate::proxy::upstream::network_reset::spawn_resume_watch();
+    crate::utils::http::configure_upstream_tls(&config.upstream_tls);
+    crate::proxy::mappers::signature_store::configure_signature_cache(&config.signature_cache);
+    crate::proxy::mappers::inline_media::configure_inline_media(&config.inline_media);
+    crate::proxy::mappers::openai::vision::configure_vision(&config.vision);
+    crate::proxy::mappers::claude::request::configure_thinking(&config.thinking);
+    crate::proxy::handlers::claude::configure_message_batches(&config.message_batches);
+    crate::proxy::mappers::openai::configure_reasoning_output(&config.openai_reasoning);
+    crate::proxy::common::warnings::configure_compat_mode(&config.compat_mode);
+    crate::proxy::common::response_model::configure_report_model(&config.report_model);
+    crate::proxy::mappers::claude::resume::configure_stream_resume(&config.stream_resume);
+    crate::proxy::middleware::logging::configure_body_logging(&config.debug);
+    crate::proxy::access_log::configure_access_log(&config.access_log);
+    crate::modules::storage_maintenance::configure_storage_maintenance(&config.storage);
+    crate::modules::storage_maintenance::spawn_storage_maintenance();
+    crate::modules::notifier::configure_notifications(&config.notifications);
     
     // 3. 加载账号
-    let active_accounts = token_manager.load_accounts().await
+    let active_accounts = token_manager.reload_from_store().await
         .map_err(|e| format!("加载账号失败: {}", e))?;
+    token_manager.spawn_request_count_persistence();
+    // 账号健康分每分钟向 1.0 恢复
+    token_manager.spawn_health_recovery();
+    // 恢复上次运行的配额消耗状态，并定期写盘
+    if let Err(e) = token_manager.restore_quota_state(&config.quota_state) {
+        tracing::warn!("读取配额状态失败，将重新统计: {}", e);
+    }
+    token_manager.spawn_quota_state_persistence(config.quota_state.flush_interval_secs);
     
     if active_accounts == 0 {
         let zai_enabled = config.zai.enabled
@@ -92,22 +146,35 @@ pub async fn start_proxy_service(
     // 启动 Axum 服务器
     let (axum_server, server_handle) =
         match crate::proxy::AxumServer::start(
-            config.get_bind_address().to_string(),
+            config.get_bind_address(),
             config.port,
             token_manager.clone(),
             config.anthropic_mapping.clone(),
             config.openai_mapping.clone(),
             config.custom_mapping.clone(),
             config.request_timeout,
+            config.model_timeouts.clone(),
             config.upstream_proxy.clone(),
             crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
             config.zai.clone(),
             monitor.clone(),
-
+            config.backoff.clone(),
+            config.retry_budget.clone(),
+            config.rate_limit.clone(),
+            config.websocket.clone(),
+            config.guard.clone(),
+            config.image_files.clone(),
+            config.prompt_log_mode,
+            config.telemetry.clone(),
         ).await {
             Ok((server, handle)) => (server, handle),
             Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
         };
+
+    // 后台账号健康探测 (未启用时空转，配置热更新后生效)
+    token_manager.spawn_account_health_checks(axum_server.upstream());
+
+    let server_handle = watch_server_task(server_handle, axum_server.local_addr().port());
     
     // 创建服务实例
     let instance = ProxyServiceInstance {
@@ -120,8 +187,9 @@ pub async fn start_proxy_service(
     *instance_lock = Some(instance);
     
 
-    // 保存配置到全局 AppConfig
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
+    // 保存配置到全局 AppConfig (文件损坏时基于上次有效配置，不影响服务启动)
+    let mut app_config = crate::modules::config::reload_app_config()?;
+    crate::commands::emit_config_warning(&app_handle);
     app_config.proxy = config.clone();
     crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
     
@@ -133,24 +201,82 @@ pub async fn start_proxy_service(
     })
 }
 
+/// 校验监听地址；对外开放时必须启用 API Key 鉴权
+pub(crate) fn check_listen_address(config: &ProxyConfig) -> Result<(), String> {
+    let bind_ip = config.validate_bind_address()?;
+    if !bind_ip.is_loopback() {
+        let security = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
+        if matches!(security.effective_auth_mode(), crate::proxy::ProxyAuthMode::Off)
+            || security.api_key.trim().is_empty()
+        {
+            return Err(format!(
+                "Refusing to listen on non-loopback address {}: enable API key authorization (auth_mode) and set an API key first",
+                bind_ip
+            ));
+        }
+        tracing::warn!(
+            "⚠️ 反代服务将监听非本机地址 {}:{}，其他设备可访问，请妥善保管 API 密钥",
+            bind_ip,
+            config.port
+        );
+    }
+    Ok(())
+}
+
+/// 监视服务器任务：异常退出时发送系统通知
+pub(crate) fn watch_server_task(
+    server_handle: tokio::task::JoinHandle<()>,
+    port: u16,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(e) = server_handle.await {
+            if e.is_panic() {
+                tracing::error!("反代服务器任务异常退出: {}", e);
+                crate::modules::notifier::notify_server_crashed(&format!(
+                    "server task on port {} panicked",
+                    port
+                ));
+            }
+        }
+    })
+}
+
 /// 停止反代服务
+/// 立即停止监听，随后等待在途请求 (含流式响应) 完成，期间通过 SHUTDOWN_PROGRESS_EVENT 推送剩余数量
 #[tauri::command]
 pub async fn stop_proxy_service(
     state: State<'_, ProxyServiceState>,
+    app_handle: tauri::AppHandle,
 ) -> Result<(), String> {
-    let mut instance_lock = state.instance.write().await;
-    
-    if instance_lock.is_none() {
-        return Err("服务未运行".to_string());
-    }
-    
+    // 先移出实例再等待，等待期间不阻塞状态查询
+    let instance = state
+        .instance
+        .write()
+        .await
+        .take()
+        .ok_or_else(|| "服务未运行".to_string())?;
+
+    let mut in_flight = instance.axum_server.subscribe_in_flight();
+    let progress = tokio::spawn(async move {
+        loop {
+            let active_requests = *in_flight.borrow_and_update();
+            let _ = app_handle.emit(SHUTDOWN_PROGRESS_EVENT, ShutdownProgress { active_requests });
+            if active_requests == 0 || in_flight.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
     // 停止 Axum 服务器
-    if let Some(instance) = instance_lock.take() {
-        instance.axum_server.stop();
-        // 等待服务器任务完成
-        instance.server_handle.await.ok();
-    }
-    
+    instance.axum_server.stop_and_wait(SHUTDOWN_DRAIN_TIMEOUT).await;
+    progress.abort();
+    // 等待服务器任务完成
+    instance.server_handle.await.ok();
+    instance.token_manager.flush_quota_state();
+    // 写回尚未落盘的累计请求数 (同步文件 IO，放到阻塞线程执行)
+    let token_manager = instance.token_manager.clone();
+    let _ = tokio::task::spawn_blocking(move || token_manager.flush_request_counts()).await;
+
     Ok(())
 }
 
@@ -229,6 +355,14 @@ pub async fn clear_proxy_logs(
     Ok(())
 }
 
+/// 获取请求日志库信息 (体积、行数、最近一次维护结果)
+#[tauri::command]
+pub async fn get_proxy_storage_info() -> Result<crate::modules::storage_maintenance::StorageInfo, String> {
+    tokio::task::spawn_blocking(crate::modules::storage_maintenance::get_storage_info)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
 /// 生成 API Key
 #[tauri::command]
 pub fn generate_api_key() -> String {
@@ -244,7 +378,7 @@ pub async fn reload_proxy_accounts(
     
     if let Some(instance) = instance_lock.as_ref() {
         // 重新加载账号
-        let count = instance.token_manager.load_accounts().await
+        let count = instance.token_manager.reload_from_store().await
             .map_err(|e| format!("重新加载账号失败: {}", e))?;
         Ok(count)
     } else {
@@ -252,6 +386,38 @@ pub async fn reload_proxy_accounts(
     }
 }
 
+/// 将单个账号加入运行中的账号池 (返回 false 表示账号已禁用，未加入)
+#[tauri::command]
+pub async fn add_proxy_account(
+    account_id: String,
+    state: State<'_, ProxyServiceState>,
+) -> Result<bool, String> {
+    let instance_lock = state.instance.read().await;
+    match instance_lock.as_ref() {
+        Some(instance) => instance.token_manager.add_account(&account_id).await,
+        None => Err("服务未运行".to_string()),
+    }
+}
+
+/// 将账号移出运行中的账号池 (在途请求不受影响)
+#[tauri::command]
+pub async fn remove_proxy_accounts(
+    account_ids: Vec<String>,
+    state: State<'_, ProxyServiceState>,
+) -> Result<usize, String> {
+    let instance_lock = state.instance.read().await;
+    let Some(instance) = instance_lock.as_ref() else {
+        return Err("服务未运行".to_string());
+    };
+    let mut removed = 0;
+    for account_id in &account_ids {
+        if instance.token_manager.remove_account(account_id).await {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
 /// 更新模型映射表 (热更新)
 #[tauri::command]
 pub async fn update_model_mapping(
@@ -268,7 +434,7 @@ pub async fn update_model_mapping(
     }
     
     // 2. 无论是否运行，都保存到全局配置持久化
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
+    let mut app_config = crate::modules::config::reload_app_config()?;
     app_config.proxy.anthropic_mapping = config.anthropic_mapping;
     app_config.proxy.openai_mapping = config.openai_mapping;
     app_config.proxy.custom_mapping = config.custom_mapping;
@@ -351,12 +517,9 @@ pub async fn fetch_zai_models(
 
     let url = join_base_url(&zai.base_url, "/v1/models");
 
-    let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(request_timeout.max(5)));
-    if upstream_proxy.enabled && !upstream_proxy.url.is_empty() {
-        let proxy = reqwest::Proxy::all(&upstream_proxy.url)
-            .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
-        builder = builder.proxy(proxy);
-    }
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(request_timeout.max(5)));
+    let builder = crate::utils::http::apply_upstream_proxy(builder, Some(&upstream_proxy))
+        .map_err(|e| format!("Invalid upstream proxy: {}", e))?;
     let client = builder
         .build()
         .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
@@ -416,6 +579,71 @@ pub async fn update_proxy_scheduling_config(
     }
 }
 
+/// 获取各账号当前的在途请求数 (email -> 数量)，供仪表盘展示负载
+#[tauri::command]
+pub async fn get_account_load(
+    state: State<'_, ProxyServiceState>,
+) -> Result<std::collections::HashMap<String, usize>, String> {
+    let instance_lock = state.instance.read().await;
+    Ok(instance_lock
+        .as_ref()
+        .map(|instance| instance.token_manager.in_flight_counts())
+        .unwrap_or_default())
+}
+
+/// 获取后台健康探测的账号状态
+#[tauri::command]
+pub async fn get_account_health(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::account_health::AccountHealthStatus>, String> {
+    let instance_lock = state.instance.read().await;
+    Ok(instance_lock
+        .as_ref()
+        .map(|instance| instance.token_manager.account_health().snapshot())
+        .unwrap_or_default())
+}
+
+/// 获取各账号当日用量统计
+#[tauri::command]
+pub async fn get_account_usage_stats(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::quota_state::AccountUsageStats>, String> {
+    let instance_lock = state.instance.read().await;
+    Ok(instance_lock
+        .as_ref()
+        .map(|instance| instance.token_manager.usage_stats())
+        .unwrap_or_default())
+}
+
+/// 清零单个账号的当日用量统计
+#[tauri::command]
+pub async fn reset_account_usage_stats(
+    state: State<'_, ProxyServiceState>,
+    email: String,
+) -> Result<bool, String> {
+    let instance_lock = state.instance.read().await;
+    match instance_lock.as_ref() {
+        Some(instance) => Ok(instance.token_manager.reset_usage_stats(&email)),
+        None => Err("服务未运行".to_string()),
+    }
+}
+
+/// 读取访问日志最近的 N 条记录 (按时间先后，默认 200 条)
+#[tauri::command]
+pub async fn tail_access_log(limit: Option<usize>) -> Result<Vec<crate::proxy::access_log::AccessLogEntry>, String> {
+    let limit = limit.unwrap_or(200).min(5000);
+    tokio::task::spawn_blocking(move || crate::proxy::access_log::tail(limit))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// 按当前上游代理与 TLS 配置检查到上游的 TLS 连接 (证书是否受信任、签发者)
+#[tauri::command]
+pub async fn check_upstream_tls() -> Result<crate::utils::http::TlsCheckReport, String> {
+    let config = crate::modules::config::current_app_config()?;
+    Ok(crate::utils::http::check_upstream_tls(Some(&config.proxy.upstream_proxy)).await)
+}
+
 /// 清除所有会话粘性绑定
 #[tauri::command]
 pub async fn clear_proxy_session_bindings(
@@ -430,3 +658,38 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+
+/// 主动探测指定账号可访问的模型 (结果写入账号 × 模型缓存)
+#[tauri::command]
+pub async fn probe_account_models(
+    state: State<'_, ProxyServiceState>,
+    email: String,
+) -> Result<Vec<crate::proxy::model_access::ProbeResult>, String> {
+    // 仅在持有读锁期间取出句柄，避免长时间探测阻塞服务启停
+    let (token_manager, upstream) = {
+        let instance_lock = state.instance.read().await;
+        match instance_lock.as_ref() {
+            Some(instance) => (instance.token_manager.clone(), instance.axum_server.upstream()),
+            None => return Err("服务未运行".to_string()),
+        }
+    };
+
+    let ctx = crate::proxy::maintenance::MaintenanceContext::new(&token_manager, &upstream);
+    crate::proxy::model_access::probe_account_models(&ctx, &email)
+        .await
+        .map_err(String::from)
+}
+
+/// 取消正在进行的模型探测
+#[tauri::command]
+pub async fn cancel_account_model_probe(
+    state: State<'_, ProxyServiceState>,
+    email: String,
+) -> Result<bool, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.model_access().cancel_probe(&email))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
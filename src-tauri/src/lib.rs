@@ -3,9 +3,11 @@ mod modules;
 mod commands;
 mod utils;
 mod proxy;  // 反代服务模块
+#[cfg(feature = "bench")]
+pub mod bench; // 压测与性能回归工具 (仅开发使用)
 pub mod error;
 
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 use modules::logger;
 use tracing::{info, error};
 
@@ -42,12 +44,20 @@ pub fn run() {
             info!("Setup starting...");
             modules::tray::create_tray(app.handle())?;
             info!("Tray created");
+            modules::notifier::init(app.handle().clone());
             
             // 自动启动反代服务
             let handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
-                // 加载配置
-                if let Ok(config) = modules::config::load_app_config() {
+                // 加载配置 (同时建立 last-known-good 缓存)
+                let loaded = modules::config::reload_app_config();
+                if let Err(e) = &loaded {
+                    error!("加载配置失败: {}", e);
+                    let _ = handle.emit(modules::config::CONFIG_RELOAD_FAILED_EVENT, e.clone());
+                }
+                if let Ok(config) = loaded {
+                    modules::notifier::configure_notifications(&config.proxy.notifications);
+                    utils::http::configure_upstream_tls(&config.proxy.upstream_tls);
                     if config.proxy.auto_start {
                         let state = handle.state::<commands::proxy::ProxyServiceState>();
                         // 尝试启动服务
@@ -57,6 +67,7 @@ pub fn run() {
                             handle.clone(),
                         ).await {
                             error!("自动启动反代服务失败: {}", e);
+                            modules::notifier::notify_server_crashed(&format!("auto-start failed: {}", e));
                         } else {
                             info!("反代服务自动启动成功");
                         }
@@ -93,6 +104,7 @@ pub fn run() {
             // 配置命令
             commands::load_config,
             commands::save_config,
+            commands::get_config_warning,
             // 新增命令
             commands::prepare_oauth_url,
             commands::start_oauth_login,
@@ -119,13 +131,24 @@ pub fn run() {
             commands::proxy::get_proxy_logs,
             commands::proxy::set_proxy_monitor_enabled,
             commands::proxy::clear_proxy_logs,
+            commands::proxy::get_proxy_storage_info,
             commands::proxy::generate_api_key,
             commands::proxy::reload_proxy_accounts,
+            commands::proxy::add_proxy_account,
+            commands::proxy::remove_proxy_accounts,
             commands::proxy::update_model_mapping,
             commands::proxy::fetch_zai_models,
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::get_account_load,
+            commands::proxy::get_account_health,
+            commands::proxy::get_account_usage_stats,
+            commands::proxy::reset_account_usage_stats,
+            commands::proxy::tail_access_log,
+            commands::proxy::check_upstream_tls,
+            commands::proxy::probe_account_models,
+            commands::proxy::cancel_account_model_probe,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
@@ -29,6 +29,9 @@ pub struct Account {
     pub proxy_disabled_at: Option<i64>,
     pub created_at: i64,
     pub last_used: i64,
+    /// 反代累计成功请求数 (由 TokenManager 维护，用于新账号磨合期)
+    #[serde(default)]
+    pub total_requests: u64,
 }
 
 impl Account {
@@ -48,6 +51,7 @@ impl Account {
             proxy_disabled_at: None,
             created_at: now,
             last_used: now,
+            total_requests: 0,
         }
     }
 
@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde_json;
 use uuid::Uuid;
 
@@ -11,10 +11,18 @@ use std::sync::Mutex;
 /// 全局账号写入锁，防止并发操作导致索引文件损坏
 static ACCOUNT_INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
+/// 账号文件写入锁，串行化单个账号文件的读改写 (应用内的账号更新与代理服务的字段回写)
+static ACCOUNT_FILE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 最近一次去重时发现、需要人工处理的冲突
+static ACCOUNT_CONFLICTS: Lazy<Mutex<Vec<AccountConflict>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
 // ... existing constants ...
 const DATA_DIR: &str = ".antigravity_tools";
 const ACCOUNTS_INDEX: &str = "accounts.json";
 const ACCOUNTS_DIR: &str = "accounts";
+// 去重时被合并的账号文件移入此目录备份，而非直接删除
+const MERGED_ACCOUNTS_DIR: &str = "accounts_merged";
 
 // ... existing functions get_data_dir, get_accounts_dir, load_account_index, save_account_index ...
 /// 获取数据目录路径
@@ -46,7 +54,10 @@ pub fn get_accounts_dir() -> Result<PathBuf, String> {
 
 /// 加载账号索引
 pub fn load_account_index() -> Result<AccountIndex, String> {
-    let data_dir = get_data_dir()?;
+    load_account_index_in(&get_data_dir()?)
+}
+
+fn load_account_index_in(data_dir: &Path) -> Result<AccountIndex, String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     // modules::logger::log_info(&format!("正在加载账号索引: {:?}", index_path)); // Optional: reduce noise
     
@@ -67,7 +78,10 @@ pub fn load_account_index() -> Result<AccountIndex, String> {
 
 /// 保存账号索引 (原子化写入)
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
+    save_account_index_in(&get_data_dir()?, index)
+}
+
+fn save_account_index_in(data_dir: &Path, index: &AccountIndex) -> Result<(), String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
     
@@ -85,7 +99,11 @@ pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
 
 /// 加载账号数据
 pub fn load_account(account_id: &str) -> Result<Account, String> {
-    let accounts_dir = get_accounts_dir()?;
+    load_account_in(&get_data_dir()?, account_id)
+}
+
+fn load_account_in(data_dir: &Path, account_id: &str) -> Result<Account, String> {
+    let accounts_dir = data_dir.join(ACCOUNTS_DIR);
     let account_path = accounts_dir.join(format!("{}.json", account_id));
     
     if !account_path.exists() {
@@ -101,16 +119,51 @@ pub fn load_account(account_id: &str) -> Result<Account, String> {
 
 /// 保存账号数据
 pub fn save_account(account: &Account) -> Result<(), String> {
-    let accounts_dir = get_accounts_dir()?;
+    get_accounts_dir()?;
+    save_account_in(&get_data_dir()?, account)
+}
+
+fn save_account_in(data_dir: &Path, account: &Account) -> Result<(), String> {
+    let _lock = ACCOUNT_FILE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    write_account_in(data_dir, account)
+}
+
+/// 写入账号文件 (调用方需持有 ACCOUNT_FILE_LOCK)
+fn write_account_in(data_dir: &Path, account: &Account) -> Result<(), String> {
+    let accounts_dir = data_dir.join(ACCOUNTS_DIR);
     let account_path = accounts_dir.join(format!("{}.json", account.id));
     
     let content = serde_json::to_string_pretty(account)
         .map_err(|e| format!("序列化账号数据失败: {}", e))?;
     
-    fs::write(&account_path, content)
+    write_file_atomic(&account_path, &content)
         .map_err(|e| format!("保存账号数据失败: {}", e))
 }
 
+/// 先写临时文件再 rename，读取方不会看到写了一半的账号文件
+fn write_file_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// 在账号文件写入锁内修改账号文件的部分字段 (代理服务回写 sessionId / 累计请求数等)
+/// 按原始 JSON 读改写，保留调用方不关心的字段；会做同步文件 IO，异步上下文中应放到 spawn_blocking 执行
+pub fn update_account_file<F>(path: &Path, update: F) -> Result<(), String>
+where
+    F: FnOnce(&mut serde_json::Value),
+{
+    let _lock = ACCOUNT_FILE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut content: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?,
+    )
+    .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+    update(&mut content);
+    let content = serde_json::to_string_pretty(&content)
+        .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+    write_file_atomic(path, &content).map_err(|e| format!("写入文件失败: {}", e))
+}
+
 /// 列出所有账号
 /// 列出所有账号
 pub fn list_accounts() -> Result<Vec<Account>, String> {
@@ -162,8 +215,8 @@ pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Res
     let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
     let mut index = load_account_index()?;
     
-    // 检查是否已存在
-    if index.accounts.iter().any(|s| s.email == email) {
+    // 检查是否已存在 (邮箱大小写/空白不同也视为同一账号)
+    if find_account_id_by_email(&index, &email).is_some() {
         return Err(format!("账号已存在: {}", email));
     }
     
@@ -200,9 +253,7 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
     let mut index = load_account_index()?;
     
     // 先找到账号 ID（如果存在）
-    let existing_account_id = index.accounts.iter()
-        .find(|s| s.email == email)
-        .map(|s| s.id.clone());
+    let existing_account_id = find_account_id_by_email(&index, &email);
     
     if let Some(account_id) = existing_account_id {
         // 更新现有账号
@@ -260,6 +311,192 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
     add_account(email, name, token)
 }
 
+/// 邮箱归一化 (去除首尾空白并转小写)，用于判定重复账号
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// 按归一化邮箱查找已有账号 ID
+fn find_account_id_by_email(index: &AccountIndex, email: &str) -> Option<String> {
+    let target = normalize_email(email);
+    index
+        .accounts
+        .iter()
+        .find(|s| normalize_email(&s.email) == target)
+        .map(|s| s.id.clone())
+}
+
+/// 同一邮箱存在多个账号且 project_id 不一致，无法自动合并
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct AccountConflict {
+    pub email: String,
+    pub account_ids: Vec<String>,
+    pub project_ids: Vec<String>,
+}
+
+/// 重复账号合并结果
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// (保留的账号 ID, 被合并移除的账号 ID)
+    pub merged: Vec<(String, String)>,
+    pub conflicts: Vec<AccountConflict>,
+}
+
+/// 将 other 的元数据并入 primary (primary 已有的字段优先)
+fn merge_account_metadata(primary: &mut Account, other: &Account) {
+    if primary.name.is_none() {
+        primary.name = other.name.clone();
+    }
+    if primary.quota.is_none() {
+        primary.quota = other.quota.clone();
+    }
+    if primary.token.project_id.is_none() {
+        primary.token.project_id = other.token.project_id.clone();
+    }
+    if primary.token.session_id.is_none() {
+        primary.token.session_id = other.token.session_id.clone();
+    }
+    primary.created_at = primary.created_at.min(other.created_at);
+    primary.last_used = primary.last_used.max(other.last_used);
+}
+
+/// 按归一化邮箱合并重复账号
+/// 保留 token 最近刷新的一条 (expiry_timestamp 最大，其次 last_used)，并合并其余条目的元数据；
+/// project_id 互相冲突的分组不合并，记入 conflicts 交由人工处理。
+pub fn merge_duplicate_accounts(accounts: Vec<Account>) -> (Vec<Account>, DedupeReport) {
+    let mut groups: Vec<(String, Vec<Account>)> = Vec::new();
+    for account in accounts {
+        let key = normalize_email(&account.email);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(account),
+            None => groups.push((key, vec![account])),
+        }
+    }
+
+    let mut report = DedupeReport::default();
+    let mut result = Vec::new();
+    for (email, mut group) in groups {
+        if group.len() == 1 {
+            result.append(&mut group);
+            continue;
+        }
+
+        let mut project_ids: Vec<String> = group
+            .iter()
+            .filter_map(|a| a.token.project_id.clone())
+            .collect();
+        project_ids.sort();
+        project_ids.dedup();
+        if project_ids.len() > 1 {
+            report.conflicts.push(AccountConflict {
+                email,
+                account_ids: group.iter().map(|a| a.id.clone()).collect(),
+                project_ids,
+            });
+            result.append(&mut group);
+            continue;
+        }
+
+        let newest = group
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, a)| (a.token.expiry_timestamp, a.last_used, std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let mut primary = group.remove(newest);
+        for other in &group {
+            merge_account_metadata(&mut primary, other);
+            report.merged.push((primary.id.clone(), other.id.clone()));
+        }
+        // 保留条目统一使用归一化邮箱，避免带空白/大小写的写法残留
+        if primary.token.email.is_some() {
+            primary.token.email = Some(email.clone());
+        }
+        primary.email = email;
+        result.push(primary);
+    }
+
+    (result, report)
+}
+
+/// 检测并合并账号目录中的重复账号 (启动加载、批量导入后调用)
+pub fn dedupe_accounts() -> Result<DedupeReport, String> {
+    dedupe_accounts_in(&get_data_dir()?)
+}
+
+/// 在指定数据目录中执行重复账号合并
+pub fn dedupe_accounts_in(data_dir: &Path) -> Result<DedupeReport, String> {
+    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    if !data_dir.join(ACCOUNTS_INDEX).exists() {
+        return Ok(DedupeReport::default());
+    }
+
+    let mut index = load_account_index_in(data_dir)?;
+    let accounts: Vec<Account> = index
+        .accounts
+        .iter()
+        .filter_map(|s| load_account_in(data_dir, &s.id).ok())
+        .collect();
+    let (accounts, report) = merge_duplicate_accounts(accounts);
+
+    for conflict in &report.conflicts {
+        crate::modules::logger::log_warn(&format!(
+            "账号 {} 存在 {} 条记录且 project_id 不一致 ({})，需手动处理",
+            conflict.email,
+            conflict.account_ids.len(),
+            conflict.project_ids.join(", ")
+        ));
+    }
+    if let Ok(mut conflicts) = ACCOUNT_CONFLICTS.lock() {
+        *conflicts = report.conflicts.clone();
+    }
+
+    if report.merged.is_empty() {
+        return Ok(report);
+    }
+
+    for account in accounts
+        .iter()
+        .filter(|a| report.merged.iter().any(|(kept, _)| kept == &a.id))
+    {
+        save_account_in(data_dir, account)?;
+        if let Some(summary) = index.accounts.iter_mut().find(|s| s.id == account.id) {
+            summary.email = account.email.clone();
+            summary.name = account.name.clone();
+            summary.created_at = account.created_at;
+            summary.last_used = account.last_used;
+        }
+    }
+
+    let backup_dir = data_dir.join(MERGED_ACCOUNTS_DIR);
+    for (kept, removed) in &report.merged {
+        index.accounts.retain(|s| &s.id != removed);
+        if index.current_account_id.as_deref() == Some(removed.as_str()) {
+            index.current_account_id = Some(kept.clone());
+        }
+        let file_name = format!("{}.json", removed);
+        let path = data_dir.join(ACCOUNTS_DIR).join(&file_name);
+        if path.exists() {
+            fs::create_dir_all(&backup_dir)
+                .map_err(|e| format!("创建合并备份目录失败: {}", e))?;
+            fs::rename(&path, backup_dir.join(&file_name))
+                .map_err(|e| format!("备份被合并账号文件失败: {}", e))?;
+        }
+        crate::modules::logger::log_info(&format!(
+            "已合并重复账号: {} -> {} (原文件已移至 {})",
+            removed, kept, MERGED_ACCOUNTS_DIR
+        ));
+    }
+
+    save_account_index_in(data_dir, &index)?;
+    Ok(report)
+}
+
+/// 最近一次去重发现的未解决冲突
+pub fn account_conflicts() -> Vec<AccountConflict> {
+    ACCOUNT_CONFLICTS.lock().map(|c| c.clone()).unwrap_or_default()
+}
+
 /// 删除账号
 pub fn delete_account(account_id: &str) -> Result<(), String> {
     let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
@@ -451,9 +688,13 @@ pub fn set_current_account_id(account_id: &str) -> Result<(), String> {
 
 /// 更新账号配额
 pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), String> {
-    let mut account = load_account(account_id)?;
+    let data_dir = get_data_dir()?;
+    // 读改写期间持有文件锁，避免覆盖代理服务同时回写的字段
+    let _lock = ACCOUNT_FILE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut account = load_account_in(&data_dir, account_id)?;
+    crate::modules::notifier::check_quota(&account.email, &quota);
     account.update_quota(quota);
-    save_account(&account)
+    write_account_in(&data_dir, &account)
 }
 
 /// 导出所有账号的 refresh_token
@@ -621,3 +862,141 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
     // fetch_quota 已经处理了 403 错误,这里直接返回结果
     result.map(|(q, _)| q)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: &str, email: &str, expiry: i64, project_id: Option<&str>) -> Account {
+        let mut token = TokenData::new(
+            format!("access-{}", id),
+            format!("refresh-{}", id),
+            3600,
+            Some(email.to_string()),
+            project_id.map(|p| p.to_string()),
+            None,
+        );
+        token.expiry_timestamp = expiry;
+        Account::new(id.to_string(), email.to_string(), token)
+    }
+
+    fn temp_data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag-account-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join(ACCOUNTS_DIR)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_merge_prefers_newest_refresh_and_unions_metadata() {
+        let mut old = account("a", "User@Example.com", 100, Some("proj-1"));
+        old.name = Some("Old Name".to_string());
+        old.created_at = 1;
+        let new = account("b", " user@example.com", 200, None);
+
+        let (accounts, report) = merge_duplicate_accounts(vec![old, new]);
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, "b");
+        assert_eq!(accounts[0].email, "user@example.com");
+        assert_eq!(accounts[0].token.email.as_deref(), Some("user@example.com"));
+        assert_eq!(accounts[0].token.refresh_token, "refresh-b");
+        assert_eq!(accounts[0].token.project_id.as_deref(), Some("proj-1"));
+        assert_eq!(accounts[0].name.as_deref(), Some("Old Name"));
+        assert_eq!(accounts[0].created_at, 1);
+        assert_eq!(report.merged, vec![("b".to_string(), "a".to_string())]);
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_project_ids_are_not_merged() {
+        let (accounts, report) = merge_duplicate_accounts(vec![
+            account("a", "user@example.com", 100, Some("proj-1")),
+            account("b", "USER@example.com", 200, Some("proj-2")),
+            account("c", "other@example.com", 100, None),
+        ]);
+
+        assert_eq!(accounts.len(), 3);
+        assert!(report.merged.is_empty());
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].account_ids, vec!["a", "b"]);
+        assert_eq!(report.conflicts[0].project_ids, vec!["proj-1", "proj-2"]);
+    }
+
+    #[test]
+    fn test_dedupe_after_import_rewrites_index_and_files() {
+        let dir = temp_data_dir();
+        let mut index = AccountIndex::new();
+        for acc in [
+            account("a", "user@example.com", 100, Some("proj-1")),
+            account("b", "User@Example.com", 200, None),
+            account("c", "other@example.com", 100, None),
+        ] {
+            save_account_in(&dir, &acc).unwrap();
+            index.accounts.push(AccountSummary {
+                id: acc.id.clone(),
+                email: acc.email.clone(),
+                name: acc.name.clone(),
+                created_at: acc.created_at,
+                last_used: acc.last_used,
+            });
+        }
+        index.current_account_id = Some("a".to_string());
+        save_account_index_in(&dir, &index).unwrap();
+
+        let report = dedupe_accounts_in(&dir).unwrap();
+        assert_eq!(report.merged, vec![("b".to_string(), "a".to_string())]);
+
+        let index = load_account_index_in(&dir).unwrap();
+        let ids: Vec<_> = index.accounts.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+        assert_eq!(index.current_account_id.as_deref(), Some("b"));
+        assert_eq!(index.accounts[0].email, "user@example.com");
+        assert!(!dir.join(ACCOUNTS_DIR).join("a.json").exists());
+        assert!(dir.join(MERGED_ACCOUNTS_DIR).join("a.json").exists());
+        let kept = load_account_in(&dir, "b").unwrap();
+        assert_eq!(kept.email, "user@example.com");
+        assert_eq!(kept.token.project_id.as_deref(), Some("proj-1"));
+
+        // 再次执行无变化
+        assert!(dedupe_accounts_in(&dir).unwrap().merged.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_update_account_file_keeps_other_fields() {
+        let dir = temp_data_dir();
+        let path = dir.join(ACCOUNTS_DIR).join("a.json");
+        fs::write(&path, r#"{"email":"a@example.com","token":{"refresh_token":"r"},"extra":1}"#).unwrap();
+
+        update_account_file(&path, |content| {
+            content["token"]["session_id"] = serde_json::json!("s-1");
+            content["total_requests"] = serde_json::json!(7);
+        })
+        .unwrap();
+
+        let content: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(content["token"]["session_id"], "s-1");
+        assert_eq!(content["token"]["refresh_token"], "r");
+        assert_eq!(content["total_requests"], 7);
+        assert_eq!(content["extra"], 1);
+        // 临时文件已被 rename 替换
+        assert!(!path.with_extension("json.tmp").exists());
+        assert!(update_account_file(&dir.join(ACCOUNTS_DIR).join("missing.json"), |_| {}).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_hot_add_matches_existing_email_case_insensitively() {
+        let mut index = AccountIndex::new();
+        index.accounts.push(AccountSummary {
+            id: "a".to_string(),
+            email: "user@example.com".to_string(),
+            name: None,
+            created_at: 0,
+            last_used: 0,
+        });
+
+        assert_eq!(find_account_id_by_email(&index, " USER@example.com ").as_deref(), Some("a"));
+        assert!(find_account_id_by_email(&index, "other@example.com").is_none());
+    }
+}
@@ -1,4 +1,7 @@
 use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+use once_cell::sync::Lazy;
 use serde_json;
 
 use crate::models::AppConfig;
@@ -6,30 +9,229 @@ use super::account::get_data_dir;
 
 const CONFIG_FILE: &str = "gui_config.json";
 
-/// 加载应用配置
-pub fn load_app_config() -> Result<AppConfig, String> {
-    let data_dir = get_data_dir()?;
-    let config_path = data_dir.join(CONFIG_FILE);
-    
+/// 配置重载失败时发送的 Tauri 事件
+pub const CONFIG_RELOAD_FAILED_EVENT: &str = "config://reload-failed";
+
+/// 最近一次成功加载/保存的配置 (last-known-good)
+/// 运行期间配置文件被锁定或损坏时，继续使用该配置，避免服务中断
+pub struct ConfigCache {
+    last_good: RwLock<Option<AppConfig>>,
+    warning: RwLock<Option<String>>,
+}
+
+impl ConfigCache {
+    pub fn new() -> Self {
+        Self {
+            last_good: RwLock::new(None),
+            warning: RwLock::new(None),
+        }
+    }
+
+    /// 记录一份有效配置并清除告警
+    pub fn store(&self, config: &AppConfig) {
+        if let Ok(mut guard) = self.last_good.write() {
+            *guard = Some(config.clone());
+        }
+        if let Ok(mut guard) = self.warning.write() {
+            *guard = None;
+        }
+    }
+
+    pub fn last_good(&self) -> Option<AppConfig> {
+        self.last_good.read().ok().and_then(|g| g.clone())
+    }
+
+    /// 当前告警信息 (上次重载失败的原因)
+    pub fn warning(&self) -> Option<String> {
+        self.warning.read().ok().and_then(|g| g.clone())
+    }
+
+    /// 从磁盘重新加载并校验配置
+    /// 失败时若存在 last-known-good 配置则继续使用，并记录告警
+    pub fn reload_from(&self, config_path: &Path) -> Result<AppConfig, String> {
+        match read_config_file(config_path).and_then(|c| validate_app_config(&c).map(|_| c)) {
+            Ok(config) => {
+                self.store(&config);
+                Ok(config)
+            }
+            Err(e) => match self.last_good() {
+                Some(previous) => {
+                    let message = format!("配置重载失败，继续使用上次有效配置: {}", e);
+                    tracing::error!("{}", message);
+                    if let Ok(mut guard) = self.warning.write() {
+                        *guard = Some(message);
+                    }
+                    Ok(previous)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// 获取当前生效配置：优先使用缓存，仅在首次使用时读取磁盘
+    pub fn current_or_load(&self, config_path: &Path) -> Result<AppConfig, String> {
+        match self.last_good() {
+            Some(config) => Ok(config),
+            None => self.reload_from(config_path),
+        }
+    }
+}
+
+impl Default for ConfigCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static CONFIG_CACHE: Lazy<ConfigCache> = Lazy::new(ConfigCache::new);
+
+fn config_path() -> Result<std::path::PathBuf, String> {
+    Ok(get_data_dir()?.join(CONFIG_FILE))
+}
+
+fn read_config_file(config_path: &Path) -> Result<AppConfig, String> {
     if !config_path.exists() {
         return Ok(AppConfig::new());
     }
-    
-    let content = fs::read_to_string(&config_path)
+
+    let content = fs::read_to_string(config_path)
         .map_err(|e| format!("读取配置文件失败: {}", e))?;
-    
+
     serde_json::from_str(&content)
         .map_err(|e| format!("解析配置文件失败: {}", e))
 }
 
+/// 校验配置中会影响反代运行的字段
+pub fn validate_app_config(config: &AppConfig) -> Result<(), String> {
+    let proxy = &config.proxy;
+    if proxy.port == 0 {
+        return Err("反代端口不能为 0".to_string());
+    }
+
+    let upstream = &proxy.upstream_proxy;
+    if upstream.enabled {
+        if upstream.url.trim().is_empty() {
+            return Err("已启用上游代理但代理地址为空".to_string());
+        }
+        reqwest::Proxy::all(&upstream.url)
+            .map_err(|e| format!("无效的上游代理地址 {}: {}", upstream.url, e))?;
+        if upstream.username.is_empty() && !upstream.password.is_empty() {
+            return Err("已设置上游代理密码但用户名为空".to_string());
+        }
+    }
+
+    let backoff = &proxy.backoff;
+    if !backoff.multiplier.is_finite() || backoff.multiplier < 1.0 {
+        return Err(format!("退避倍数必须 >= 1.0，当前为 {}", backoff.multiplier));
+    }
+    if !(0.0..=1.0).contains(&backoff.jitter) {
+        return Err(format!("退避抖动比例必须在 0.0 - 1.0 之间，当前为 {}", backoff.jitter));
+    }
+
+    if proxy.circuit_breaker.failure_threshold == 0 {
+        return Err("熔断阈值必须大于 0".to_string());
+    }
+
+    let ca_bundle = proxy.upstream_tls.extra_ca_bundle.trim();
+    if !ca_bundle.is_empty() {
+        crate::utils::http::load_ca_bundle(ca_bundle)?;
+    }
+
+    if let Some(settings) = &proxy.safety_settings {
+        crate::proxy::common::safety::validate_safety_settings(settings)?;
+    }
+
+    Ok(())
+}
+
+/// 加载应用配置
+pub fn load_app_config() -> Result<AppConfig, String> {
+    read_config_file(&config_path()?)
+}
+
+/// 重新加载配置 (启动/重载时调用)
+/// 文件损坏或被锁定时继续返回上次有效配置，告警可通过 `config_warning` 查询
+pub fn reload_app_config() -> Result<AppConfig, String> {
+    CONFIG_CACHE.reload_from(&config_path()?)
+}
+
+/// 获取当前生效配置 (不重复读取磁盘)
+pub fn current_app_config() -> Result<AppConfig, String> {
+    CONFIG_CACHE.current_or_load(&config_path()?)
+}
+
+/// 上次配置重载失败的告警信息
+pub fn config_warning() -> Option<String> {
+    CONFIG_CACHE.warning()
+}
+
 /// 保存应用配置
 pub fn save_app_config(config: &AppConfig) -> Result<(), String> {
     let data_dir = get_data_dir()?;
     let config_path = data_dir.join(CONFIG_FILE);
-    
+
     let content = serde_json::to_string_pretty(config)
         .map_err(|e| format!("序列化配置失败: {}", e))?;
-    
+
     fs::write(&config_path, content)
-        .map_err(|e| format!("保存配置失败: {}", e))
+        .map_err(|e| format!("保存配置失败: {}", e))?;
+
+    CONFIG_CACHE.store(config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag-config-test-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(CONFIG_FILE)
+    }
+
+    #[test]
+    fn test_corrupted_file_keeps_last_good_config() {
+        let path = temp_config_path("corrupt");
+        let cache = ConfigCache::new();
+
+        let mut config = AppConfig::new();
+        config.proxy.upstream_proxy.enabled = true;
+        config.proxy.upstream_proxy.url = "http://127.0.0.1:7890".to_string();
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+
+        let loaded = cache.reload_from(&path).unwrap();
+        assert_eq!(loaded.proxy.upstream_proxy.url, "http://127.0.0.1:7890");
+        assert!(cache.warning().is_none());
+
+        // 运行中配置文件被写坏：继续使用上次有效配置，并记录告警
+        fs::write(&path, "{ not json").unwrap();
+        let fallback = cache.reload_from(&path).unwrap();
+        assert_eq!(fallback.proxy.upstream_proxy.url, "http://127.0.0.1:7890");
+        assert!(cache.warning().is_some());
+        assert!(cache.current_or_load(&path).unwrap().proxy.upstream_proxy.enabled);
+
+        // 文件修复后告警清除
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+        cache.reload_from(&path).unwrap();
+        assert!(cache.warning().is_none());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_config_without_fallback_fails() {
+        let path = temp_config_path("invalid");
+        let cache = ConfigCache::new();
+
+        let mut config = AppConfig::new();
+        config.proxy.upstream_proxy.enabled = true;
+        config.proxy.upstream_proxy.url = String::new();
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+
+        assert!(cache.reload_from(&path).is_err());
+        assert!(cache.last_good().is_none());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
 }
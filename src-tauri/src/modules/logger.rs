@@ -63,7 +63,9 @@ pub fn init_logger() {
         .unwrap_or_else(|_| EnvFilter::new("info"));
 
     // 5. 初始化全局订阅器 (使用 try_init 避免重复初始化崩溃)
+    //    OTLP 链路追踪采集层直接挂在 Registry 上 (反代配置启用前不采集任何数据)
     let _ = tracing_subscriber::registry()
+        .with(crate::proxy::telemetry::layer())
         .with(filter_layer)
         .with(console_layer)
         .with(file_layer)
@@ -185,6 +185,11 @@ pub async fn import_from_v1() -> Result<Vec<Account>, String> {
     if !found_index {
         return Err("未找到 V1 版本账号数据文件".to_string());
     }
+
+    // 多来源导入后合并同邮箱的重复账号
+    if let Err(e) = account::dedupe_accounts() {
+        crate::modules::logger::log_warn(&format!("重复账号合并失败: {}", e));
+    }
     
     Ok(imported_accounts)
 }
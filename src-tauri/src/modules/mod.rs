@@ -10,6 +10,8 @@ pub mod migration;
 pub mod tray;
 pub mod i18n;
 pub mod proxy_db;
+pub mod storage_maintenance;
+pub mod notifier;
 
 use crate::models;
 
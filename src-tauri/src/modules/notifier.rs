@@ -0,0 +1,322 @@
+// 关键事件系统通知
+// Rust 侧检测事件并限流，通过 CRITICAL_NOTIFICATION_EVENT 交给前端以系统通知弹出。
+// - 同一类型事件在冷却窗口内最多通知一次
+// - quiet 开启或事件类型未启用时不发送
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+
+use crate::models::QuotaData;
+use crate::proxy::config::{NotificationConfig, NotificationKind};
+
+pub const CRITICAL_NOTIFICATION_EVENT: &str = "notification://critical";
+/// 账号池状态变化 (不受通知限流控制，前端据此展示具体原因)
+pub const POOL_STATUS_EVENT: &str = "proxy://pool-status";
+
+/// 发送给前端的通知内容
+#[derive(Debug, Clone, Serialize)]
+pub struct CriticalNotification {
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    /// 相关账号 (如有)
+    pub account: Option<String>,
+    /// 触发通知的指标值 (剩余百分比、错误次数等)
+    pub value: Option<i64>,
+    pub timestamp: i64,
+}
+
+/// 通知限流与 5xx 计数
+pub struct NotificationLimiter {
+    config: NotificationConfig,
+    last_sent: HashMap<NotificationKind, Instant>,
+    upstream_errors: VecDeque<Instant>,
+}
+
+impl NotificationLimiter {
+    pub fn new(config: NotificationConfig) -> Self {
+        Self {
+            config,
+            last_sent: HashMap::new(),
+            upstream_errors: VecDeque::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: NotificationConfig) {
+        self.config = config;
+    }
+
+    /// 判断该类型事件是否可以发送，可以则记录发送时间
+    pub fn try_send(&mut self, kind: NotificationKind, now: Instant) -> bool {
+        if self.config.quiet || !self.config.events.contains(&kind) {
+            return false;
+        }
+        let cooldown = Duration::from_secs(self.config.cooldown_secs);
+        if let Some(last) = self.last_sent.get(&kind) {
+            if now.duration_since(*last) < cooldown {
+                return false;
+            }
+        }
+        self.last_sent.insert(kind, now);
+        true
+    }
+
+    /// 记录一次上游 5xx，返回窗口内累计次数达到阈值时的计数
+    pub fn record_upstream_error(&mut self, now: Instant) -> Option<u32> {
+        let window = Duration::from_secs(self.config.upstream_error_window_secs.max(1));
+        while self
+            .upstream_errors
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            self.upstream_errors.pop_front();
+        }
+        self.upstream_errors.push_back(now);
+
+        let count = self.upstream_errors.len() as u32;
+        (count >= self.config.upstream_error_threshold.max(1)).then_some(count)
+    }
+
+    /// 配额中剩余最少的模型，低于阈值时返回 (模型名, 百分比)
+    pub fn quota_below_threshold<'a>(&self, quota: &'a QuotaData) -> Option<(&'a str, i32)> {
+        quota
+            .models
+            .iter()
+            .filter(|m| m.percentage <= self.config.quota_threshold_percent)
+            .min_by_key(|m| m.percentage)
+            .map(|m| (m.name.as_str(), m.percentage))
+    }
+}
+
+static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
+static LIMITER: Lazy<Mutex<NotificationLimiter>> =
+    Lazy::new(|| Mutex::new(NotificationLimiter::new(NotificationConfig::default())));
+
+/// 注册通知出口 (应用 setup 时调用)
+pub fn init(app: tauri::AppHandle) {
+    let _ = APP_HANDLE.set(app);
+}
+
+/// 应用通知配置 (启动与配置保存时调用)
+pub fn configure_notifications(config: &NotificationConfig) {
+    if let Ok(mut limiter) = LIMITER.lock() {
+        limiter.set_config(config.clone());
+    }
+}
+
+/// 发送关键事件通知 (受限流与 quiet 控制)，返回是否实际发出
+pub fn notify(
+    kind: NotificationKind,
+    title: &str,
+    body: String,
+    account: Option<&str>,
+    value: Option<i64>,
+) -> bool {
+    let allowed = LIMITER
+        .lock()
+        .map(|mut l| l.try_send(kind, Instant::now()))
+        .unwrap_or(false);
+    if !allowed {
+        return false;
+    }
+
+    tracing::warn!("[Notify] {}: {}", title, body);
+    let Some(app) = APP_HANDLE.get() else {
+        return false;
+    };
+    let payload = CriticalNotification {
+        kind,
+        title: title.to_string(),
+        body,
+        account: account.map(str::to_string),
+        value,
+        timestamp: chrono::Utc::now().timestamp(),
+    };
+    app.emit(CRITICAL_NOTIFICATION_EVENT, payload).is_ok()
+}
+
+/// 反代服务异常退出 / 启动失败
+pub fn notify_server_crashed(detail: &str) {
+    notify(
+        NotificationKind::ServerCrashed,
+        "Proxy server stopped",
+        format!("The local proxy is no longer serving requests: {}", detail),
+        None,
+        None,
+    );
+}
+
+/// 账号池中没有可用账号
+pub fn notify_no_healthy_accounts(total: usize, wait_secs: Option<u64>) {
+    let body = match wait_secs {
+        Some(wait) => format!(
+            "All {} account(s) are rate-limited, unhealthy or need re-authorization. Earliest recovery in {}s.",
+            total, wait
+        ),
+        None => "No accounts are loaded in the proxy pool.".to_string(),
+    };
+    notify(
+        NotificationKind::NoHealthyAccounts,
+        "No healthy accounts",
+        body,
+        None,
+        Some(total as i64),
+    );
+}
+
+/// 账号达到每日硬上限，当日不再调度
+pub fn notify_account_capped(email: &str, limit: &str, cap: u64) {
+    notify(
+        NotificationKind::AccountCapped,
+        "Account daily cap reached",
+        format!(
+            "{} reached its daily {} cap ({}) and is paused until the daily rollover",
+            email, limit, cap
+        ),
+        Some(email),
+        Some(cap as i64),
+    );
+}
+
+/// 账号 refresh_token 已失效，已禁用并等待重新授权
+pub fn notify_account_reauth_required(email: &str) {
+    notify(
+        NotificationKind::AccountReauthRequired,
+        "Account needs re-authorization",
+        format!(
+            "{} was disabled because its refresh token was rejected (invalid_grant). Sign in again to restore it.",
+            email
+        ),
+        Some(email),
+        None,
+    );
+}
+
+/// 账号池状态事件；status 为空表示账号池已恢复可用
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatusEvent {
+    pub status: Option<crate::proxy::token_manager::PoolStatus>,
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+    pub timestamp: i64,
+}
+
+/// 推送账号池状态变化
+pub fn emit_pool_status(error: Option<&crate::proxy::token_manager::PoolUnavailable>) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let payload = PoolStatusEvent {
+        status: error.map(|e| e.status.clone()),
+        message: error.map(|e| e.message.clone()),
+        retry_after_secs: error.and_then(|e| e.status.retry_after_secs()),
+        timestamp: chrono::Utc::now().timestamp(),
+    };
+    let _ = app.emit(POOL_STATUS_EVENT, payload);
+}
+
+/// 检查刷新后的配额，低于阈值时通知
+pub fn check_quota(email: &str, quota: &QuotaData) {
+    let low = LIMITER.lock().ok().and_then(|l| {
+        l.quota_below_threshold(quota)
+            .map(|(model, pct)| (model.to_string(), pct))
+    });
+    if let Some((model, percentage)) = low {
+        notify(
+            NotificationKind::QuotaLow,
+            "Quota running low",
+            format!("{}: {} has {}% quota remaining", email, model, percentage),
+            Some(email),
+            Some(percentage as i64),
+        );
+    }
+}
+
+/// 记录上游 5xx，窗口内达到阈值时通知
+pub fn record_upstream_error(email: &str, status: u16) {
+    let reached = LIMITER
+        .lock()
+        .ok()
+        .and_then(|mut l| l.record_upstream_error(Instant::now()));
+    if let Some(count) = reached {
+        notify(
+            NotificationKind::UpstreamErrors,
+            "Repeated upstream errors",
+            format!(
+                "{} upstream 5xx responses in the recent window (last: HTTP {} on {})",
+                count, status, email
+            ),
+            Some(email),
+            Some(count as i64),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(config: NotificationConfig) -> NotificationLimiter {
+        NotificationLimiter::new(config)
+    }
+
+    #[test]
+    fn test_one_notification_per_kind_per_cooldown() {
+        let mut l = limiter(NotificationConfig {
+            cooldown_secs: 60,
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        assert!(l.try_send(NotificationKind::QuotaLow, t0));
+        assert!(!l.try_send(NotificationKind::QuotaLow, t0 + Duration::from_secs(30)));
+        // 其他类型不受影响
+        assert!(l.try_send(NotificationKind::ServerCrashed, t0 + Duration::from_secs(30)));
+        assert!(l.try_send(NotificationKind::QuotaLow, t0 + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_quiet_and_disabled_events() {
+        let mut l = limiter(NotificationConfig {
+            quiet: true,
+            ..Default::default()
+        });
+        assert!(!l.try_send(NotificationKind::ServerCrashed, Instant::now()));
+
+        let mut l = limiter(NotificationConfig {
+            events: vec![NotificationKind::ServerCrashed],
+            ..Default::default()
+        });
+        assert!(!l.try_send(NotificationKind::UpstreamErrors, Instant::now()));
+        assert!(l.try_send(NotificationKind::ServerCrashed, Instant::now()));
+    }
+
+    #[test]
+    fn test_upstream_error_window_threshold() {
+        let mut l = limiter(NotificationConfig {
+            upstream_error_threshold: 3,
+            upstream_error_window_secs: 10,
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        assert_eq!(l.record_upstream_error(t0), None);
+        assert_eq!(l.record_upstream_error(t0 + Duration::from_secs(1)), None);
+        assert_eq!(l.record_upstream_error(t0 + Duration::from_secs(2)), Some(3));
+        // 旧记录滑出窗口
+        assert_eq!(l.record_upstream_error(t0 + Duration::from_secs(12)), None);
+    }
+
+    #[test]
+    fn test_quota_below_threshold_picks_lowest_model() {
+        let l = limiter(NotificationConfig::default());
+        let mut quota = QuotaData::new();
+        quota.add_model("gemini-3-pro".into(), 40, String::new());
+        assert!(l.quota_below_threshold(&quota).is_none());
+
+        quota.add_model("claude-sonnet-4-5".into(), 8, String::new());
+        quota.add_model("gemini-3-flash".into(), 2, String::new());
+        assert_eq!(l.quota_below_threshold(&quota), Some(("gemini-3-flash", 2)));
+    }
+}
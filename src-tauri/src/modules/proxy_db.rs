@@ -1,5 +1,6 @@
 use rusqlite::{params, Connection};
 use std::path::PathBuf;
+use std::time::Duration;
 use crate::proxy::monitor::ProxyRequestLog;
 
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
@@ -7,10 +8,27 @@ pub fn get_proxy_db_path() -> Result<PathBuf, String> {
     Ok(data_dir.join("proxy_logs.db"))
 }
 
+/// 打开日志库 (后台维护期间写入最多等待 5 秒)
+pub(crate) fn open() -> Result<Connection, String> {
+    let conn = Connection::open(get_proxy_db_path()?).map_err(|e| e.to_string())?;
+    conn.busy_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+/// 数据库文件损坏 (需要隔离重建，而不是每次写入都失败)
+pub(crate) fn is_corruption(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt) | Some(rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
 pub fn init_db() -> Result<(), String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-    
+    let conn = open()?;
+    init_schema(&conn).map_err(|e| e.to_string())
+}
+
+pub(crate) fn init_schema(conn: &Connection) -> rusqlite::Result<()> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS request_logs (
             id TEXT PRIMARY KEY,
@@ -23,29 +41,63 @@ pub fn init_db() -> Result<(), String> {
             error TEXT
         )",
         [],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Try to add new columns (ignore errors if they exist)
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN request_body TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN response_body TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN input_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN prompt_size TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN warnings TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN upstream_model TEXT", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
         [],
-    ).map_err(|e| e.to_string())?;
+    )?;
+
+    // 超过保留期的请求行按天/模型汇总到此表 (见 storage_maintenance)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS request_log_daily (
+            day TEXT NOT NULL,
+            model TEXT NOT NULL,
+            request_count INTEGER NOT NULL DEFAULT 0,
+            success_count INTEGER NOT NULL DEFAULT 0,
+            error_count INTEGER NOT NULL DEFAULT 0,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            total_duration INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (day, model)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS storage_meta (key TEXT PRIMARY KEY, value TEXT)",
+        [],
+    )?;
 
     Ok(())
 }
 
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
-    let db_path = get_proxy_db_path()?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = open()?;
+    match insert_log(&conn, log) {
+        Err(e) if is_corruption(&e) => {
+            drop(conn);
+            // 损坏的库隔离后重建再写入一次，避免之后每次写入都失败
+            crate::modules::storage_maintenance::quarantine_and_recreate(&format!("write failed: {}", e))?;
+            insert_log(&open()?, log).map_err(|e| e.to_string())
+        }
+        other => other.map_err(|e| e.to_string()),
+    }
+}
 
+fn insert_log(conn: &Connection, log: &ProxyRequestLog) -> rusqlite::Result<()> {
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, prompt_size, warnings, upstream_model)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
         params![
             log.id,
             log.timestamp,
@@ -59,8 +111,11 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.response_body,
             log.input_tokens,
             log.output_tokens,
+            log.prompt_size.as_ref().and_then(|s| serde_json::to_string(s).ok()),
+            log.warnings.as_ref().and_then(|w| serde_json::to_string(w).ok()),
+            log.upstream_model,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
@@ -70,7 +125,7 @@ pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens
+        "SELECT id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, prompt_size, warnings, upstream_model
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1"
@@ -90,6 +145,15 @@ pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            prompt_size: row
+                .get::<_, Option<String>>(12)
+                .unwrap_or(None)
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            warnings: row
+                .get::<_, Option<String>>(13)
+                .unwrap_or(None)
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            upstream_model: row.get(14).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -122,10 +186,20 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
         |row| row.get(0),
     ).map_err(|e| e.to_string())?;
 
+    // 已汇总 (原始行已删除) 的历史请求同样计入总数
+    let (rolled_total, rolled_success, rolled_error): (u64, u64, u64) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(request_count), 0), COALESCE(SUM(success_count), 0), COALESCE(SUM(error_count), 0)
+             FROM request_log_daily",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap_or((0, 0, 0));
+
     Ok(crate::proxy::monitor::ProxyStats {
-        total_requests,
-        success_count,
-        error_count,
+        total_requests: total_requests + rolled_total,
+        success_count: success_count + rolled_success,
+        error_count: error_count + rolled_error,
     })
 }
 
@@ -133,5 +207,6 @@ pub fn clear_logs() -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
     conn.execute("DELETE FROM request_logs", []).map_err(|e| e.to_string())?;
+    let _ = conn.execute("DELETE FROM request_log_daily", []);
     Ok(())
 }
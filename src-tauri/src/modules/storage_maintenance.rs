@@ -0,0 +1,373 @@
+// 请求日志库 (proxy_logs.db) 定期维护
+// 超过保留期的请求行汇总为按天统计后删除、空闲页过多时整理文件、每周完整性检查；
+// 库文件损坏时隔离并重建，而不是让之后的每次写入都失败
+
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::modules::proxy_db;
+use crate::proxy::config::StorageConfig;
+
+/// 检查是否到期的轮询间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+/// 完整性检查周期 (毫秒)
+const INTEGRITY_CHECK_INTERVAL_MS: i64 = 7 * 24 * 3600 * 1000;
+const STATUS_KEY: &str = "maintenance_status";
+
+static CONFIG: Lazy<RwLock<StorageConfig>> = Lazy::new(|| RwLock::new(StorageConfig::default()));
+static STARTED: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// 隔离重建期间禁止并发隔离 (写入失败与后台维护可能同时发现损坏)
+static QUARANTINE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 最近一次维护的结果 (持久化在 storage_meta 表中)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    pub last_run: Option<i64>,
+    pub last_rows_compacted: u64,
+    pub total_rows_compacted: u64,
+    pub last_vacuum: Option<i64>,
+    pub last_integrity_check: Option<i64>,
+    pub last_integrity_ok: Option<bool>,
+    /// 最近一次被隔离的损坏库文件路径
+    pub last_quarantine: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageInfo {
+    pub path: String,
+    pub size_bytes: u64,
+    pub raw_rows: u64,
+    pub rollup_rows: u64,
+    pub page_count: u64,
+    pub freelist_count: u64,
+    pub fragmentation_percent: f64,
+    pub maintenance_running: bool,
+    pub maintenance: MaintenanceStatus,
+}
+
+/// 应用维护配置 (启动与热更新共用)
+pub fn configure_storage_maintenance(config: &StorageConfig) {
+    if let Ok(mut current) = CONFIG.write() {
+        *current = config.clone();
+    }
+}
+
+fn current_config() -> StorageConfig {
+    CONFIG.read().map(|c| c.clone()).unwrap_or_default()
+}
+
+/// 启动后台维护任务 (进程内只启动一次，维护在阻塞线程池中执行，不占用请求处理线程)
+pub fn spawn_storage_maintenance() {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let config = current_config();
+            if !config.enabled {
+                continue;
+            }
+            let result = tokio::task::spawn_blocking(move || run_if_due(&config)).await;
+            match result {
+                Ok(Ok(Some(status))) => tracing::info!(
+                    "[Storage] 维护完成: 汇总 {} 行, 完整性检查 {:?}",
+                    status.last_rows_compacted,
+                    status.last_integrity_ok
+                ),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => tracing::warn!("[Storage] 维护失败: {}", e),
+                Err(e) => tracing::warn!("[Storage] 维护任务异常: {}", e),
+            }
+        }
+    });
+}
+
+fn run_if_due(config: &StorageConfig) -> Result<Option<MaintenanceStatus>, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let status = load_status(&proxy_db::open()?).unwrap_or_default();
+    let interval_ms = i64::from(config.interval_hours.max(1)) * 3600 * 1000;
+    if status.last_run.is_some_and(|last| now - last < interval_ms) {
+        return Ok(None);
+    }
+    run_maintenance(config).map(Some)
+}
+
+/// 执行一次维护 (同一时间只允许一个维护在跑)
+pub fn run_maintenance(config: &StorageConfig) -> Result<MaintenanceStatus, String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("storage maintenance already running".to_string());
+    }
+    let result = run_maintenance_locked(config);
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+fn run_maintenance_locked(config: &StorageConfig) -> Result<MaintenanceStatus, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let conn = proxy_db::open()?;
+    let mut status = load_status(&conn).unwrap_or_default();
+    status.last_run = Some(now);
+    status.last_error = None;
+
+    // 1. 完整性检查 (每周一次)，损坏时隔离重建后直接结束本轮
+    let integrity_due = status
+        .last_integrity_check
+        .is_none_or(|last| now - last >= INTEGRITY_CHECK_INTERVAL_MS);
+    if integrity_due {
+        let ok = integrity_ok(&conn).unwrap_or_else(|e| {
+            tracing::warn!("[Storage] integrity_check 执行失败: {}", e);
+            !proxy_db::is_corruption(&e)
+        });
+        status.last_integrity_check = Some(now);
+        status.last_integrity_ok = Some(ok);
+        if !ok {
+            drop(conn);
+            quarantine_and_recreate("integrity_check failed")?;
+            let conn = proxy_db::open()?;
+            let mut status = load_status(&conn).unwrap_or_default();
+            status.last_run = Some(now);
+            status.last_integrity_check = Some(now);
+            status.last_integrity_ok = Some(false);
+            save_status(&conn, &status).map_err(|e| e.to_string())?;
+            return Ok(status);
+        }
+    }
+
+    // 2. 超过保留期的原始行汇总为按天统计
+    let cutoff = now - i64::from(config.retention_days) * 24 * 3600 * 1000;
+    match compact_before(&conn, cutoff) {
+        Ok(rows) => {
+            status.last_rows_compacted = rows;
+            status.total_rows_compacted += rows;
+        }
+        Err(e) => status.last_error = Some(format!("compact failed: {}", e)),
+    }
+
+    // 3. 空闲页占比超过阈值时整理文件
+    match fragmentation_percent(&conn) {
+        Ok(percent) if percent > f64::from(config.vacuum_threshold_percent) => {
+            match vacuum(&conn) {
+                Ok(()) => status.last_vacuum = Some(now),
+                Err(e) => status.last_error = Some(format!("vacuum failed: {}", e)),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => status.last_error = Some(format!("page stats failed: {}", e)),
+    }
+
+    save_status(&conn, &status).map_err(|e| e.to_string())?;
+    Ok(status)
+}
+
+/// 将 cutoff 之前的请求行按天/模型累加到 request_log_daily 并删除，返回删除的行数
+fn compact_before(conn: &Connection, cutoff_ms: i64) -> rusqlite::Result<u64> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO request_log_daily
+            (day, model, request_count, success_count, error_count, input_tokens, output_tokens, total_duration)
+         SELECT date(timestamp / 1000, 'unixepoch'), COALESCE(model, ''), COUNT(*),
+                SUM(CASE WHEN status >= 200 AND status < 400 THEN 1 ELSE 0 END),
+                SUM(CASE WHEN status < 200 OR status >= 400 THEN 1 ELSE 0 END),
+                COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COALESCE(SUM(duration), 0)
+         FROM request_logs
+         WHERE timestamp < ?1
+         GROUP BY 1, 2
+         ON CONFLICT(day, model) DO UPDATE SET
+            request_count = request_count + excluded.request_count,
+            success_count = success_count + excluded.success_count,
+            error_count = error_count + excluded.error_count,
+            input_tokens = input_tokens + excluded.input_tokens,
+            output_tokens = output_tokens + excluded.output_tokens,
+            total_duration = total_duration + excluded.total_duration",
+        params![cutoff_ms],
+    )?;
+    let deleted = tx.execute("DELETE FROM request_logs WHERE timestamp < ?1", params![cutoff_ms])?;
+    tx.commit()?;
+    Ok(deleted as u64)
+}
+
+fn page_stats(conn: &Connection) -> rusqlite::Result<(u64, u64)> {
+    let pages: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let free: u64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+    Ok((pages, free))
+}
+
+fn fragmentation_percent(conn: &Connection) -> rusqlite::Result<f64> {
+    let (pages, free) = page_stats(conn)?;
+    if pages == 0 {
+        return Ok(0.0);
+    }
+    Ok(free as f64 * 100.0 / pages as f64)
+}
+
+/// 已是增量模式时只回收空闲页；否则切换为增量模式并完整 VACUUM 一次
+fn vacuum(conn: &Connection) -> rusqlite::Result<()> {
+    let mode: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if mode == 2 {
+        conn.execute_batch("PRAGMA incremental_vacuum;")
+    } else {
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")
+    }
+}
+
+fn integrity_ok(conn: &Connection) -> rusqlite::Result<bool> {
+    let result: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    Ok(result.eq_ignore_ascii_case("ok"))
+}
+
+/// 将损坏的库文件改名隔离 (保留以便排查)，然后重建空库
+pub(crate) fn quarantine_and_recreate(reason: &str) -> Result<(), String> {
+    let _guard = QUARANTINE_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    let path = proxy_db::get_proxy_db_path()?;
+
+    // 等锁期间其它线程可能已经完成重建
+    if let Ok(conn) = proxy_db::open() {
+        if integrity_ok(&conn).unwrap_or(false) {
+            return Ok(());
+        }
+    }
+
+    let suffix = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let quarantined = path.with_extension(format!("db.corrupt-{}", suffix));
+    tracing::error!(
+        "[Storage] 日志库损坏 ({})，隔离为 {:?} 并重建",
+        reason,
+        quarantined
+    );
+    std::fs::rename(&path, &quarantined).map_err(|e| format!("隔离日志库失败: {}", e))?;
+    for ext in ["db-wal", "db-shm"] {
+        let _ = std::fs::remove_file(path.with_extension(ext));
+    }
+
+    proxy_db::init_db()?;
+    let conn = proxy_db::open()?;
+    let status = MaintenanceStatus {
+        last_quarantine: Some(quarantined.to_string_lossy().to_string()),
+        last_error: Some(reason.to_string()),
+        ..Default::default()
+    };
+    save_status(&conn, &status).map_err(|e| e.to_string())
+}
+
+fn load_status(conn: &Connection) -> Option<MaintenanceStatus> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM storage_meta WHERE key = ?1",
+            params![STATUS_KEY],
+            |row| row.get(0),
+        )
+        .optional()
+        .ok()
+        .flatten();
+    value.and_then(|v| serde_json::from_str(&v).ok())
+}
+
+fn save_status(conn: &Connection, status: &MaintenanceStatus) -> rusqlite::Result<()> {
+    let value = serde_json::to_string(status).unwrap_or_default();
+    conn.execute(
+        "INSERT INTO storage_meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![STATUS_KEY, value],
+    )?;
+    Ok(())
+}
+
+/// 日志库体积、行数与最近一次维护结果
+pub fn get_storage_info() -> Result<StorageInfo, String> {
+    let path = proxy_db::get_proxy_db_path()?;
+    let size_bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let conn = proxy_db::open()?;
+    let count = |sql: &str| -> u64 { conn.query_row(sql, [], |row| row.get(0)).unwrap_or(0) };
+    let raw_rows = count("SELECT COUNT(*) FROM request_logs");
+    let rollup_rows = count("SELECT COUNT(*) FROM request_log_daily");
+    let (page_count, freelist_count) = page_stats(&conn).unwrap_or((0, 0));
+
+    Ok(StorageInfo {
+        path: path.to_string_lossy().to_string(),
+        size_bytes,
+        raw_rows,
+        rollup_rows,
+        page_count,
+        freelist_count,
+        fragmentation_percent: fragmentation_percent(&conn).unwrap_or(0.0),
+        maintenance_running: RUNNING.load(Ordering::SeqCst),
+        maintenance: load_status(&conn).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_with_logs(rows: &[(i64, &str, u16)]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        proxy_db::init_schema(&conn).unwrap();
+        for (i, (timestamp, model, status)) in rows.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, input_tokens, output_tokens)
+                 VALUES (?1, ?2, 'POST', '/v1/messages', ?3, 100, ?4, 10, 5)",
+                params![i.to_string(), timestamp, status, model],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    const DAY_MS: i64 = 24 * 3600 * 1000;
+
+    #[test]
+    fn compacts_old_rows_into_daily_rollups() {
+        let conn = db_with_logs(&[
+            (DAY_MS, "gemini-2.5-pro", 200),
+            (DAY_MS + 1000, "gemini-2.5-pro", 429),
+            (2 * DAY_MS, "gemini-2.5-flash", 200),
+            (10 * DAY_MS, "gemini-2.5-pro", 200),
+        ]);
+
+        assert_eq!(compact_before(&conn, 5 * DAY_MS).unwrap(), 3);
+        // 再次汇总同一天的新行时累加而非覆盖
+        conn.execute(
+            "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model) VALUES ('late', ?1, 'POST', '/', 200, 1, 'gemini-2.5-pro')",
+            params![DAY_MS + 2000],
+        )
+        .unwrap();
+        assert_eq!(compact_before(&conn, 5 * DAY_MS).unwrap(), 1);
+
+        let (requests, success, errors, input): (u64, u64, u64, u64) = conn
+            .query_row(
+                "SELECT request_count, success_count, error_count, input_tokens FROM request_log_daily WHERE day = '1970-01-02' AND model = 'gemini-2.5-pro'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((requests, success, errors, input), (3, 2, 1, 20));
+
+        let remaining: u64 = conn
+            .query_row("SELECT COUNT(*) FROM request_logs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 1);
+    }
+
+    #[test]
+    fn status_round_trips_and_integrity_passes() {
+        let conn = db_with_logs(&[]);
+        assert!(load_status(&conn).is_none());
+        let status = MaintenanceStatus {
+            last_run: Some(1),
+            last_rows_compacted: 42,
+            ..Default::default()
+        };
+        save_status(&conn, &status).unwrap();
+        assert_eq!(load_status(&conn).unwrap().last_rows_compacted, 42);
+        assert!(integrity_ok(&conn).unwrap());
+        assert!(fragmentation_percent(&conn).unwrap() >= 0.0);
+    }
+}
@@ -0,0 +1,260 @@
+// 账号级并发限制
+// 同一账号同时承接大量请求更容易触发上游限流，这里为每个账号维护一个信号量：
+// - get_token 只选择仍有空闲名额的账号，选中后取得许可 (AccountPermit)
+// - 许可保存在当前请求上下文中，处理器返回时释放；流式响应由 meter_stream 接管，流结束 (或被丢弃) 时释放
+// - 所有账号满载时按 queue_wait_secs 排队等待，超时返回 429 (Retry-After)
+// 无论是否启用限制，都会统计每个账号的在途请求数，供仪表盘展示负载
+use axum::{extract::Request, middleware::Next, response::Response};
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::proxy::config::AccountConcurrencyConfig;
+
+/// 排队时单次等待的上限 (错过释放通知时也能及时重新检查)
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct AccountSlots {
+    /// None 表示不限制并发
+    semaphore: Mutex<Option<Arc<Semaphore>>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl AccountSlots {
+    fn semaphore(&self) -> Option<Arc<Semaphore>> {
+        self.semaphore.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+}
+
+/// 账号并发许可，Drop 时归还名额并唤醒排队中的请求
+#[derive(Debug)]
+pub struct AccountPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: Arc<AtomicUsize>,
+    released: Arc<Notify>,
+}
+
+impl Drop for AccountPermit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.released.notify_waiters();
+    }
+}
+
+pub struct AccountConcurrency {
+    limit: AtomicUsize,
+    queue_wait_secs: AtomicU64,
+    accounts: DashMap<String, Arc<AccountSlots>>,
+    released: Arc<Notify>,
+}
+
+impl Default for AccountConcurrency {
+    fn default() -> Self {
+        Self::new(&AccountConcurrencyConfig::default())
+    }
+}
+
+impl AccountConcurrency {
+    pub fn new(config: &AccountConcurrencyConfig) -> Self {
+        Self {
+            limit: AtomicUsize::new(config.max_concurrent_per_account),
+            queue_wait_secs: AtomicU64::new(config.queue_wait_secs),
+            accounts: DashMap::new(),
+            released: Arc::new(Notify::new()),
+        }
+    }
+
+    /// 应用新配置；已发出的许可仍计入在途数，但不再占用新信号量的名额
+    pub fn set_config(&self, config: &AccountConcurrencyConfig) {
+        self.limit.store(config.max_concurrent_per_account, Ordering::Relaxed);
+        self.queue_wait_secs.store(config.queue_wait_secs, Ordering::Relaxed);
+        for entry in self.accounts.iter() {
+            *entry.semaphore.lock().unwrap_or_else(|p| p.into_inner()) = self.new_semaphore();
+        }
+        self.released.notify_waiters();
+    }
+
+    pub fn queue_wait(&self) -> Duration {
+        Duration::from_secs(self.queue_wait_secs.load(Ordering::Relaxed))
+    }
+
+    fn new_semaphore(&self) -> Option<Arc<Semaphore>> {
+        match self.limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(Arc::new(Semaphore::new(limit))),
+        }
+    }
+
+    fn slots(&self, email: &str) -> Arc<AccountSlots> {
+        self.accounts
+            .entry(email.to_string())
+            .or_insert_with(|| {
+                Arc::new(AccountSlots {
+                    semaphore: Mutex::new(self.new_semaphore()),
+                    in_flight: Arc::default(),
+                })
+            })
+            .clone()
+    }
+
+    /// 账号是否还有空闲名额
+    pub fn has_free_slot(&self, email: &str) -> bool {
+        self.slots(email)
+            .semaphore()
+            .is_none_or(|s| s.available_permits() > 0)
+    }
+
+    /// 尝试占用一个名额 (账号满载时返回 None)
+    pub fn try_acquire(&self, email: &str) -> Option<AccountPermit> {
+        let slots = self.slots(email);
+        let permit = match slots.semaphore() {
+            Some(semaphore) => Some(semaphore.try_acquire_owned().ok()?),
+            None => None,
+        };
+        slots.in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(AccountPermit {
+            _permit: permit,
+            in_flight: slots.in_flight.clone(),
+            released: self.released.clone(),
+        })
+    }
+
+    /// 等待任一账号释放名额 (最多等待 `timeout`，且不超过轮询间隔)
+    pub async fn wait_for_release(&self, timeout: Duration) {
+        let _ = tokio::time::timeout(timeout.min(RELEASE_POLL_INTERVAL), self.released.notified()).await;
+    }
+
+    /// 单个账号的在途请求数
+    pub fn in_flight(&self, email: &str) -> usize {
+        self.accounts
+            .get(email)
+            .map_or(0, |s| s.in_flight.load(Ordering::Relaxed))
+    }
+
+    /// 所有账号的在途请求数 (email -> 数量)
+    pub fn in_flight_counts(&self) -> HashMap<String, usize> {
+        self.accounts
+            .iter()
+            .map(|e| (e.key().clone(), e.in_flight.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+type PermitSlot = Arc<Mutex<Option<AccountPermit>>>;
+
+tokio::task_local! {
+    static HELD_PERMIT: PermitSlot;
+}
+
+/// 在请求上下文中执行 future：期间 get_token 取得的许可在 future 结束时释放 (已被流接管的除外)
+pub async fn scope_account_permit<F: std::future::Future>(fut: F) -> F::Output {
+    HELD_PERMIT.scope(PermitSlot::default(), fut).await
+}
+
+/// 保存当前请求取得的许可；换号重试时旧许可随之释放。不在请求上下文中时立即释放
+pub fn hold_permit(permit: AccountPermit) {
+    let _ = HELD_PERMIT.try_with(|slot| {
+        *slot.lock().unwrap_or_else(|p| p.into_inner()) = Some(permit);
+    });
+}
+
+/// 取出当前请求持有的许可 (交给流式响应)
+pub fn take_held_permit() -> Option<AccountPermit> {
+    HELD_PERMIT
+        .try_with(|slot| slot.lock().unwrap_or_else(|p| p.into_inner()).take())
+        .ok()
+        .flatten()
+}
+
+/// 许可随流一起存活，流结束或被丢弃时释放
+pub fn hold_for_stream<S: Stream>(stream: S, permit: Option<AccountPermit>) -> impl Stream<Item = S::Item> {
+    stream.map(move |item| {
+        let _held = &permit;
+        item
+    })
+}
+
+pub async fn account_permit_middleware(request: Request, next: Next) -> Response {
+    scope_account_permit(next.run(request)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(limit: usize) -> AccountConcurrency {
+        AccountConcurrency::new(&AccountConcurrencyConfig {
+            max_concurrent_per_account: limit,
+            queue_wait_secs: 0,
+        })
+    }
+
+    #[test]
+    fn permits_are_limited_per_account() {
+        let c = limited(2);
+        let a1 = c.try_acquire("a@example.com").unwrap();
+        let _a2 = c.try_acquire("a@example.com").unwrap();
+        assert!(!c.has_free_slot("a@example.com"));
+        assert!(c.try_acquire("a@example.com").is_none());
+        assert!(c.try_acquire("b@example.com").is_some());
+        assert_eq!(c.in_flight("a@example.com"), 2);
+
+        drop(a1);
+        assert!(c.has_free_slot("a@example.com"));
+        assert_eq!(c.in_flight("a@example.com"), 1);
+    }
+
+    #[test]
+    fn unlimited_still_counts_in_flight() {
+        let c = limited(0);
+        let permits: Vec<_> = (0..20).map(|_| c.try_acquire("a@example.com").unwrap()).collect();
+        assert_eq!(c.in_flight_counts().get("a@example.com"), Some(&20));
+        drop(permits);
+        assert_eq!(c.in_flight("a@example.com"), 0);
+    }
+
+    #[tokio::test]
+    async fn held_permit_released_with_request_or_stream() {
+        let c = limited(1);
+        scope_account_permit(async {
+            hold_permit(c.try_acquire("a@example.com").unwrap());
+            assert_eq!(c.in_flight("a@example.com"), 1);
+        })
+        .await;
+        assert_eq!(c.in_flight("a@example.com"), 0);
+
+        // 流式响应接管许可后，请求上下文结束不释放
+        let stream = scope_account_permit(async {
+            hold_permit(c.try_acquire("a@example.com").unwrap());
+            hold_for_stream(futures::stream::iter(vec![1, 2]), take_held_permit())
+        })
+        .await;
+        assert_eq!(c.in_flight("a@example.com"), 1);
+        assert_eq!(stream.collect::<Vec<_>>().await, vec![1, 2]);
+        assert_eq!(c.in_flight("a@example.com"), 0);
+
+        // 请求上下文之外立即释放
+        hold_permit(c.try_acquire("a@example.com").unwrap());
+        assert_eq!(c.in_flight("a@example.com"), 0);
+    }
+
+    #[tokio::test]
+    async fn wait_for_release_wakes_on_drop() {
+        let c = Arc::new(limited(1));
+        let permit = c.try_acquire("a@example.com").unwrap();
+        let waiter = {
+            let c = c.clone();
+            tokio::spawn(async move {
+                c.wait_for_release(Duration::from_secs(5)).await;
+                c.try_acquire("a@example.com").is_some()
+            })
+        };
+        tokio::task::yield_now().await;
+        drop(permit);
+        assert!(waiter.await.unwrap());
+    }
+}
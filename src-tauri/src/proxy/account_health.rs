@@ -0,0 +1,270 @@
+// 后台账号健康探测
+// 账号失效 (token 被撤销、缺少 project、被封禁) 往往要等真实请求失败才发现，白白消耗一次重试。
+// 启用后由后台任务按 interval_secs 逐个账号发送 1 Token 的 generateContent：
+// - 记录成功与否、延迟与最近一次错误
+// - 连续失败 failure_threshold 次的账号标记为不可用，get_token 跳过
+// - 不可用账号仍参与每轮探测，成功一次即恢复调度
+// 探测属于维护流量 (MaintenanceContext)：用户请求繁忙时跳过本轮，429 不计为失败，也不触发账号冷却
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::proxy::config::AccountHealthCheckConfig;
+use crate::proxy::maintenance::MaintenanceContext;
+use crate::proxy::mappers::gemini::models::{GenerationConfig, V1InternalRequest};
+
+/// 同一轮中相邻两个账号之间的间隔
+const ACCOUNT_PROBE_SPACING: Duration = Duration::from_secs(2);
+/// 两轮探测之间的最小间隔
+const MIN_INTERVAL_SECS: u64 = 30;
+
+/// 单个账号的探测结果
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AccountHealthStatus {
+    pub email: String,
+    /// false 时 get_token 跳过该账号
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// 最近一次探测时间 (Unix 秒)
+    pub last_checked: i64,
+    /// 最近一次成功的时间 (Unix 秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<i64>,
+    /// 最近一次成功探测的耗时
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// 一次探测的结论
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeOutcome {
+    Healthy { latency_ms: u64 },
+    Failed(String),
+    /// 维护预算不足、账号冷却中或上游限流：不影响健康状态
+    Skipped(String),
+}
+
+pub struct AccountHealthChecker {
+    config: RwLock<AccountHealthCheckConfig>,
+    statuses: DashMap<String, AccountHealthStatus>,
+    /// 配置变更时唤醒后台任务，按新的间隔重新计时
+    changed: Notify,
+}
+
+impl Default for AccountHealthChecker {
+    fn default() -> Self {
+        Self::new(AccountHealthCheckConfig::default())
+    }
+}
+
+impl AccountHealthChecker {
+    pub fn new(config: AccountHealthCheckConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            statuses: DashMap::new(),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn update_config(&self, config: AccountHealthCheckConfig) {
+        if let Ok(mut guard) = self.config.write() {
+            *guard = config;
+        }
+        self.changed.notify_waiters();
+    }
+
+    pub fn config(&self) -> AccountHealthCheckConfig {
+        self.config.read().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.config().interval_secs.max(MIN_INTERVAL_SECS))
+    }
+
+    /// 账号是否可参与调度 (关闭探测时全部可用)
+    pub fn is_available(&self, email: &str) -> bool {
+        if !self.config().enabled {
+            return true;
+        }
+        self.statuses.get(email).is_none_or(|s| s.healthy)
+    }
+
+    pub fn record(&self, email: &str, outcome: &ProbeOutcome, now: i64) {
+        let threshold = self.config().failure_threshold.max(1);
+        let mut entry = self
+            .statuses
+            .entry(email.to_string())
+            .or_insert_with(|| AccountHealthStatus {
+                email: email.to_string(),
+                healthy: true,
+                consecutive_failures: 0,
+                last_checked: now,
+                last_success: None,
+                latency_ms: None,
+                last_error: None,
+            });
+        match outcome {
+            ProbeOutcome::Healthy { latency_ms } => {
+                if !entry.healthy {
+                    tracing::info!("[AccountHealth] {} 探测恢复，重新参与调度", email);
+                }
+                entry.healthy = true;
+                entry.consecutive_failures = 0;
+                entry.last_success = Some(now);
+                entry.latency_ms = Some(*latency_ms);
+                entry.last_error = None;
+            }
+            ProbeOutcome::Failed(error) => {
+                entry.consecutive_failures += 1;
+                entry.last_error = Some(error.clone());
+                if entry.healthy && entry.consecutive_failures >= threshold {
+                    tracing::warn!(
+                        "[AccountHealth] {} 连续 {} 次探测失败，暂停调度: {}",
+                        email,
+                        entry.consecutive_failures,
+                        error
+                    );
+                    entry.healthy = false;
+                }
+            }
+            ProbeOutcome::Skipped(_) => return,
+        }
+        entry.last_checked = now;
+    }
+
+    /// 移除已不在账号池中的账号
+    pub fn retain(&self, emails: &HashSet<String>) {
+        self.statuses.retain(|email, _| emails.contains(email));
+    }
+
+    /// 所有账号的探测结果 (按邮箱排序)
+    pub fn snapshot(&self) -> Vec<AccountHealthStatus> {
+        let mut statuses: Vec<_> = self.statuses.iter().map(|e| e.value().clone()).collect();
+        statuses.sort_by(|a, b| a.email.cmp(&b.email));
+        statuses
+    }
+
+    /// 等待下一轮探测 (配置变更时提前返回并重新计时)
+    pub async fn wait_next_round(&self) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(self.interval()) => true,
+            _ = self.changed.notified() => false,
+        }
+    }
+}
+
+/// 向单个账号发送 1 Token 的 generateContent
+pub async fn probe_account(ctx: &MaintenanceContext<'_>, email: &str, model: &str) -> ProbeOutcome {
+    let permit = match ctx.try_acquire() {
+        Ok(permit) => permit,
+        Err(reason) => return ProbeOutcome::Skipped(reason),
+    };
+
+    // 刷新 token / 获取 project_id 失败同样视为账号不可用
+    let (access_token, project_id, account_id) = match ctx.token_by_email(email).await {
+        Ok(token) => token,
+        Err(e) => return ProbeOutcome::Failed(e),
+    };
+    if ctx.is_rate_limited(&account_id) {
+        return ProbeOutcome::Skipped("Account is cooling down".to_string());
+    }
+
+    let body = V1InternalRequest::new(
+        &project_id,
+        model,
+        "agent",
+        json!({
+            "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+            "generationConfig": GenerationConfig::new().max_output_tokens(1).to_value(),
+        }),
+    )
+    .with_request_id_prefix("health")
+    .into_value();
+
+    let started = Instant::now();
+    match ctx.generate_content(&permit, &access_token, body).await {
+        Ok(resp) if resp.status().is_success() => ProbeOutcome::Healthy {
+            latency_ms: started.elapsed().as_millis() as u64,
+        },
+        Ok(resp) => {
+            let status = resp.status().as_u16();
+            let text = resp.text().await.unwrap_or_default();
+            let detail: String = format!("HTTP {}: {}", status, text).chars().take(200).collect();
+            if status == 429 {
+                ProbeOutcome::Skipped(detail)
+            } else {
+                ProbeOutcome::Failed(detail)
+            }
+        }
+        Err(e) => ProbeOutcome::Failed(e),
+    }
+}
+
+/// 依次探测所有账号并记录结果
+pub async fn run_round(ctx: &MaintenanceContext<'_>, checker: &AccountHealthChecker, emails: &[String]) {
+    let model = checker.config().probe_model;
+    for (idx, email) in emails.iter().enumerate() {
+        if idx > 0 {
+            tokio::time::sleep(ACCOUNT_PROBE_SPACING).await;
+        }
+        let outcome = probe_account(ctx, email, &model).await;
+        tracing::debug!("[AccountHealth] {} -> {:?}", email, outcome);
+        checker.record(email, &outcome, chrono::Utc::now().timestamp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(enabled: bool) -> AccountHealthChecker {
+        AccountHealthChecker::new(AccountHealthCheckConfig {
+            enabled,
+            failure_threshold: 2,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_marks_unhealthy_after_threshold_and_recovers() {
+        let c = checker(true);
+        let failed = ProbeOutcome::Failed("HTTP 403: PERMISSION_DENIED".to_string());
+        c.record("a@example.com", &failed, 100);
+        assert!(c.is_available("a@example.com"));
+        c.record("a@example.com", &failed, 200);
+        assert!(!c.is_available("a@example.com"));
+
+        // 限流 / 预算不足不影响状态
+        c.record("a@example.com", &ProbeOutcome::Skipped("shed".to_string()), 300);
+        let status = &c.snapshot()[0];
+        assert_eq!((status.consecutive_failures, status.last_checked), (2, 200));
+
+        c.record("a@example.com", &ProbeOutcome::Healthy { latency_ms: 120 }, 400);
+        assert!(c.is_available("a@example.com"));
+        let status = &c.snapshot()[0];
+        assert_eq!(status.latency_ms, Some(120));
+        assert!(status.last_error.is_none());
+    }
+
+    #[test]
+    fn test_disabled_checker_never_blocks_and_prunes_removed_accounts() {
+        let c = checker(true);
+        let failed = ProbeOutcome::Failed("invalid_grant".to_string());
+        for email in ["a@example.com", "b@example.com"] {
+            c.record(email, &failed, 100);
+            c.record(email, &failed, 200);
+        }
+        c.update_config(AccountHealthCheckConfig::default());
+        assert!(c.is_available("a@example.com"));
+
+        c.retain(&HashSet::from(["b@example.com".to_string()]));
+        let emails: Vec<_> = c.snapshot().into_iter().map(|s| s.email).collect();
+        assert_eq!(emails, vec!["b@example.com"]);
+    }
+}
@@ -0,0 +1,406 @@
+// 账号可用时段
+// 部分账号白天由本人使用，只希望反代在指定时段 (例如夜间) 调度它们。
+// 每个账号可配置若干时段 (HH:MM，可跨越午夜，可限定星期)，任一时段命中即可调度：
+// - 时段外的账号在选择时跳过，不扣健康分、不触发熔断，也不计为不健康
+// - 后台健康探测同样跳过时段外的账号
+// 时段按所在时区的挂钟时间判断，夏令时切换当天时段的实际时长随之变化；
+// 开始时间落在被跳过的那段时间内时，从切换后的第一分钟开始
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, LocalResult, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::proxy::config::{AccountSchedule, AccountSchedulesConfig};
+
+/// 查找下一个时段边界时向后搜索的天数 (覆盖按星期限定的时段)
+const SEARCH_DAYS: i64 = 8;
+/// 本地时间不存在 (夏令时跳过) 时最多向后顺延的分钟数
+const MAX_GAP_MINUTES: i64 = 180;
+
+const ALL_DAYS: u8 = 0b111_1111;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScheduleZone {
+    /// 系统时区 (随夏令时切换)
+    Local,
+    Fixed(FixedOffset),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+    /// 按 Monday = bit 0 的星期掩码，按时段开始的那天计
+    days: u8,
+}
+
+impl Window {
+    fn starts_on(&self, date: chrono::NaiveDate) -> bool {
+        self.days & (1 << date.weekday().num_days_from_monday()) != 0
+    }
+
+    fn crosses_midnight(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// 本地挂钟时间 `at` 所在的这次时段的结束时间 (不在时段内时返回 None)
+    fn end_if_contains(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        let date = at.date();
+        let time = at.time();
+        if !self.crosses_midnight() {
+            return (self.starts_on(date) && time >= self.start && time < self.end)
+                .then(|| date.and_time(self.end));
+        }
+        // start == end 表示全天，结束于次日同一时刻
+        if time >= self.start && self.starts_on(date) {
+            return Some((date + Duration::days(1)).and_time(self.end));
+        }
+        let yesterday = date - Duration::days(1);
+        (time < self.end && self.starts_on(yesterday)).then(|| date.and_time(self.end))
+    }
+}
+
+/// 解析后的账号时段
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    zone: ScheduleZone,
+    windows: Vec<Window>,
+}
+
+/// 账号当前的时段状态 (供管理端点展示)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduleStatus {
+    /// 当前是否处于可用时段
+    pub available: bool,
+    /// 下一次状态切换的时间 (Unix 秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    /// 例如 "off-schedule until 18:00" / "on schedule until Mon 06:00"
+    pub label: String,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    if value == "24:00" {
+        return Ok(NaiveTime::MIN);
+    }
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("invalid time '{}', expected HH:MM", value))
+}
+
+fn parse_weekday(value: &str) -> Result<u32, String> {
+    value
+        .trim()
+        .parse::<Weekday>()
+        .map(|d| d.num_days_from_monday())
+        .map_err(|_| format!("invalid weekday '{}'", value))
+}
+
+/// 解析星期列表，支持单日 ("sat") 与范围 ("mon-fri"、"fri-mon")；为空表示每天
+fn parse_days(days: &[String]) -> Result<u8, String> {
+    if days.is_empty() {
+        return Ok(ALL_DAYS);
+    }
+    let mut mask = 0u8;
+    for item in days {
+        let (from, to) = match item.split_once('-') {
+            Some((from, to)) => (parse_weekday(from)?, parse_weekday(to)?),
+            None => {
+                let day = parse_weekday(item)?;
+                (day, day)
+            }
+        };
+        let mut day = from;
+        loop {
+            mask |= 1 << day;
+            if day == to {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_zone(value: &str) -> Result<ScheduleZone, String> {
+    let value = value.trim();
+    if value.is_empty() || value.eq_ignore_ascii_case("local") {
+        return Ok(ScheduleZone::Local);
+    }
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+        return Ok(ScheduleZone::Fixed(Utc.fix()));
+    }
+    let invalid = || format!("invalid timezone '{}', expected \"local\", \"UTC\" or an offset like \"+08:00\"", value);
+    let offset = value.strip_prefix("UTC").unwrap_or(value);
+    let (sign, rest) = if let Some(rest) = offset.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = offset.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return Err(invalid());
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 14 || minutes > 59 {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .map(ScheduleZone::Fixed)
+        .ok_or_else(invalid)
+}
+
+/// 挂钟时间转换为 UTC：重复出现的时间取较早的一次，不存在的时间顺延到切换后的第一分钟
+fn resolve<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    let mut at = local;
+    for _ in 0..=MAX_GAP_MINUTES {
+        match tz.from_local_datetime(&at) {
+            LocalResult::Single(dt) => return dt.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, _) => return earliest.with_timezone(&Utc),
+            LocalResult::None => at += Duration::minutes(1),
+        }
+    }
+    Utc.from_utc_datetime(&local)
+}
+
+impl Schedule {
+    pub fn parse(config: &AccountSchedule) -> Result<Self, String> {
+        let zone = parse_zone(&config.timezone)?;
+        let windows = config
+            .windows
+            .iter()
+            .map(|w| {
+                Ok(Window {
+                    start: parse_time(&w.start)?,
+                    end: parse_time(&w.end)?,
+                    days: parse_days(&w.days)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { zone, windows })
+    }
+
+    /// 账号在 `now` 是否处于可用时段
+    pub fn is_available_at(&self, now: DateTime<Utc>) -> bool {
+        match self.zone {
+            ScheduleZone::Local => self.current_end(now.with_timezone(&Local).naive_local()).is_some(),
+            ScheduleZone::Fixed(offset) => self.current_end(now.with_timezone(&offset).naive_local()).is_some(),
+        }
+    }
+
+    pub fn status_at(&self, now: DateTime<Utc>) -> ScheduleStatus {
+        match self.zone {
+            ScheduleZone::Local => self.status_in(&Local, now),
+            ScheduleZone::Fixed(offset) => self.status_in(&offset, now),
+        }
+    }
+
+    fn status_in<Tz: TimeZone>(&self, tz: &Tz, now: DateTime<Utc>) -> ScheduleStatus {
+        let local = now.with_timezone(tz).naive_local();
+        let (available, next) = match self.current_end(local) {
+            Some(end) => (true, Some(self.extend_end(end))),
+            None => (false, self.next_start(local)),
+        };
+        let until = next.map(|at| resolve(tz, at));
+        let label = match until {
+            Some(until) => {
+                let wall = until.with_timezone(tz).naive_local();
+                let format = if wall.date() == local.date() { "%H:%M" } else { "%a %H:%M" };
+                let state = if available { "on schedule" } else { "off-schedule" };
+                format!("{} until {}", state, wall.format(format))
+            }
+            None if available => "on schedule".to_string(),
+            None => "off-schedule".to_string(),
+        };
+        ScheduleStatus {
+            available,
+            until: until.map(|t| t.timestamp()),
+            label,
+        }
+    }
+
+    /// 包含 `at` 的时段中最晚的结束时间
+    fn current_end(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.windows.iter().filter_map(|w| w.end_if_contains(at)).max()
+    }
+
+    /// 首尾相接或重叠的时段视为连续可用
+    fn extend_end(&self, mut end: NaiveDateTime) -> NaiveDateTime {
+        for _ in 0..SEARCH_DAYS * 2 {
+            match self.current_end(end) {
+                Some(next) if next > end => end = next,
+                _ => break,
+            }
+        }
+        end
+    }
+
+    /// `at` 之后最近的时段开始时间
+    fn next_start(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..SEARCH_DAYS)
+            .flat_map(|offset| {
+                let date = at.date() + Duration::days(offset);
+                self.windows
+                    .iter()
+                    .filter(move |w| w.starts_on(date))
+                    .map(move |w| date.and_time(w.start))
+            })
+            .filter(|start| *start > at)
+            .min()
+    }
+}
+
+/// 解析所有账号的时段；未配置任何时段的账号不受限制
+pub fn parse_schedules(config: &AccountSchedulesConfig) -> Result<HashMap<String, Schedule>, String> {
+    config
+        .accounts
+        .iter()
+        .filter(|(_, schedule)| !schedule.windows.is_empty())
+        .map(|(email, schedule)| {
+            Schedule::parse(schedule)
+                .map(|parsed| (email.clone(), parsed))
+                .map_err(|e| format!("account_schedules.{}: {}", email, e))
+        })
+        .collect()
+}
+
+/// 时段配置无法解析的账号不参与调度 (宁可闲置，也不在非预期的时间使用)
+pub fn never_available() -> Schedule {
+    Schedule {
+        zone: ScheduleZone::Local,
+        windows: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::ScheduleWindow;
+    use chrono::NaiveDate;
+
+    fn schedule(timezone: &str, windows: &[(&str, &str, &[&str])]) -> Schedule {
+        Schedule::parse(&AccountSchedule {
+            timezone: timezone.to_string(),
+            windows: windows
+                .iter()
+                .map(|(start, end, days)| ScheduleWindow {
+                    start: start.to_string(),
+                    end: end.to_string(),
+                    days: days.iter().map(|d| d.to_string()).collect(),
+                })
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    /// 按欧盟规则切换夏令时的 UTC+1 时区 (2026-03-29 与 2026-10-25 的 01:00 UTC 切换)
+    #[derive(Debug, Clone, Copy)]
+    struct Berlin2026;
+
+    impl Berlin2026 {
+        fn offset_at(utc: NaiveDateTime) -> FixedOffset {
+            let start = NaiveDate::from_ymd_opt(2026, 3, 29).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            let end = NaiveDate::from_ymd_opt(2026, 10, 25).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            let hours = if utc >= start && utc < end { 2 } else { 1 };
+            FixedOffset::east_opt(hours * 3600).unwrap()
+        }
+    }
+
+    impl TimeZone for Berlin2026 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Berlin2026
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let candidates: Vec<FixedOffset> = [1, 2]
+                .into_iter()
+                .map(|h| FixedOffset::east_opt(h * 3600).unwrap())
+                .filter(|o| Self::offset_at(*local - Duration::seconds(o.local_minus_utc() as i64)) == *o)
+                .collect();
+            match candidates[..] {
+                [one] => LocalResult::Single(one),
+                // 重复的一小时：较早的一次为夏令时 (+02:00)
+                [standard, summer] => LocalResult::Ambiguous(summer, standard),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            Self::offset_at(utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset_at(*utc)
+        }
+    }
+
+    #[test]
+    fn test_window_crossing_midnight() {
+        let night = schedule("+08:00", &[("22:00", "06:00", &[])]);
+        // 2026-10-14 (周三) 本地 18:00 -> 时段外，22:00 开始
+        let status = night.status_at(utc("2026-10-14T10:00:00Z"));
+        assert!(!status.available);
+        assert_eq!(status.label, "off-schedule until 22:00");
+        assert_eq!(status.until, Some(utc("2026-10-14T14:00:00Z").timestamp()));
+
+        // 本地 23:30 与次日 05:59 均在时段内，结束于次日 06:00
+        let status = night.status_at(utc("2026-10-14T15:30:00Z"));
+        assert!(status.available);
+        assert_eq!(status.label, "on schedule until Thu 06:00");
+        assert!(night.is_available_at(utc("2026-10-14T21:59:00Z")));
+        assert!(!night.is_available_at(utc("2026-10-14T22:00:00Z")));
+
+        // 限定周五开始的时段：周六凌晨仍可用，周六晚上不可用
+        let friday = schedule("UTC", &[("22:00", "02:00", &["fri"])]);
+        assert!(friday.is_available_at(utc("2026-10-17T01:00:00Z")));
+        assert!(!friday.is_available_at(utc("2026-10-17T23:00:00Z")));
+        assert_eq!(
+            friday.status_at(utc("2026-10-17T23:00:00Z")).label,
+            "off-schedule until Fri 22:00"
+        );
+    }
+
+    #[test]
+    fn test_adjacent_windows_and_days() {
+        let split = schedule("UTC", &[("20:00", "24:00", &["mon-fri"]), ("00:00", "07:00", &[])]);
+        // 周三 21:00 在时段内，两个时段首尾相接，结束于周四 07:00
+        let status = split.status_at(utc("2026-10-14T21:00:00Z"));
+        assert_eq!(status.label, "on schedule until Thu 07:00");
+        // 周六 21:00 不在 mon-fri 内，下一次为周日 00:00
+        assert_eq!(split.status_at(utc("2026-10-17T21:00:00Z")).label, "off-schedule until Sun 00:00");
+
+        assert_eq!(parse_days(&["fri-mon".to_string()]).unwrap(), 0b111_0001);
+        assert!(parse_days(&["someday".to_string()]).is_err());
+        assert!(parse_zone("Europe/Berlin").is_err());
+        assert_eq!(parse_zone("UTC-03:30").unwrap(), ScheduleZone::Fixed(FixedOffset::west_opt(12600).unwrap()));
+    }
+
+    #[test]
+    fn test_dst_transitions_follow_wall_clock() {
+        let s = schedule("local", &[("01:00", "03:00", &[]), ("02:30", "02:45", &["sun"])]);
+
+        // 春季切换 (02:00 -> 03:00)：01:59 CET 在时段内，01:00 UTC 已是 03:00 CEST，时段只持续 1 小时
+        let spring = s.status_in(&Berlin2026, utc("2026-03-29T00:59:00Z"));
+        assert!(spring.available);
+        assert_eq!(spring.until, Some(utc("2026-03-29T01:00:00Z").timestamp()));
+        assert!(!s.status_in(&Berlin2026, utc("2026-03-29T01:00:00Z")).available);
+
+        // 周日 02:30 不存在：前一天晚上查看时，下一次开始时间为切换后的第一刻
+        let only_gap = schedule("local", &[("02:30", "02:45", &["sun"])]);
+        let status = only_gap.status_in(&Berlin2026, utc("2026-03-28T22:00:00Z"));
+        assert_eq!(status.until, Some(utc("2026-03-29T01:00:00Z").timestamp()));
+
+        // 秋季切换 (03:00 -> 02:00)：02:30 出现两次，取较早的一次 (CEST)，时段持续 3 小时
+        let autumn = s.status_in(&Berlin2026, utc("2026-10-24T22:00:00Z"));
+        assert_eq!(autumn.until, Some(utc("2026-10-24T23:00:00Z").timestamp()));
+        assert!(s.status_in(&Berlin2026, utc("2026-10-25T01:30:00Z")).available);
+        assert!(!s.status_in(&Berlin2026, utc("2026-10-25T02:00:00Z")).available);
+    }
+}
@@ -0,0 +1,226 @@
+// 新账号磨合期 (break-in)
+// 新加入的账号立即承接大量流量更容易被风控，磨合期内：
+// - 调度权重降低：轮询到该账号时按 share_percent 的比例放行，其余让给其他账号
+// - 每分钟请求数受限 (max_requests_per_minute)
+// 加入时间超过 hours 或累计成功请求达到 requests (任一先满足) 后毕业，恢复正常轮换。
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::BreakInConfig;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 账号磨合状态 (供账号池概览展示)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BreakInStatus {
+    Graduated,
+    BreakingIn {
+        requests: u64,
+        required_requests: u64,
+        /// 距离按时间毕业的剩余秒数 (未按时间限制时为 None)
+        remaining_secs: Option<u64>,
+    },
+}
+
+impl BreakInStatus {
+    pub fn is_breaking_in(&self) -> bool {
+        matches!(self, Self::BreakingIn { .. })
+    }
+}
+
+/// 计算账号的磨合状态
+/// `added_at` / `now` 为 Unix 秒，`total_requests` 为累计成功请求数
+pub fn break_in_status(config: &BreakInConfig, added_at: i64, total_requests: u64, now: i64) -> BreakInStatus {
+    if !config.enabled || (config.hours == 0 && config.requests == 0) {
+        return BreakInStatus::Graduated;
+    }
+    let age_secs = now.saturating_sub(added_at).max(0) as u64;
+    let hours_secs = config.hours.saturating_mul(3600);
+    let graduated_by_time = config.hours > 0 && age_secs >= hours_secs;
+    let graduated_by_requests = config.requests > 0 && total_requests >= config.requests;
+    if graduated_by_time || graduated_by_requests {
+        return BreakInStatus::Graduated;
+    }
+    BreakInStatus::BreakingIn {
+        requests: total_requests,
+        required_requests: config.requests,
+        remaining_secs: (config.hours > 0).then(|| hours_secs - age_secs),
+    }
+}
+
+/// 调度时对磨合期账号的判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Admit,
+    /// 超出权重份额：本次让给其他账号 (无其他可用账号时仍可使用)
+    Deferred,
+    /// 超出每分钟限额：本次不可使用
+    Throttled,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    credit: u32,
+    window_start: Instant,
+    window_count: u32,
+}
+
+/// 磨合期账号的权重与速率控制
+pub struct BreakInThrottle {
+    config: BreakInConfig,
+    accounts: HashMap<String, ThrottleState>,
+}
+
+impl BreakInThrottle {
+    pub fn new(config: BreakInConfig) -> Self {
+        Self {
+            config,
+            accounts: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BreakInConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BreakInConfig) {
+        self.config = config;
+        self.accounts.clear();
+    }
+
+    /// 轮询选择新账号时调用
+    /// 权重按累积份额计算：每次被轮到累积 share_percent，满 100 放行一次，因此放行比例严格等于 share_percent
+    pub fn admit(&mut self, account_id: &str, now: Instant) -> Admission {
+        let share = self.config.share_percent.min(100);
+        let max_per_minute = self.config.max_requests_per_minute;
+        let state = self.state(account_id, now);
+        if max_per_minute > 0 && state.window_count >= max_per_minute {
+            return Admission::Throttled;
+        }
+
+        state.credit += share;
+        if state.credit < 100 {
+            return Admission::Deferred;
+        }
+        state.credit -= 100;
+        state.window_count += 1;
+        Admission::Admit
+    }
+
+    /// 复用已选账号 (会话粘性 / 60s 锁定窗口) 时调用：只检查每分钟限额，不计权重
+    pub fn admit_reuse(&mut self, account_id: &str, now: Instant) -> Admission {
+        let max_per_minute = self.config.max_requests_per_minute;
+        let state = self.state(account_id, now);
+        if max_per_minute > 0 && state.window_count >= max_per_minute {
+            return Admission::Throttled;
+        }
+        state.window_count += 1;
+        Admission::Admit
+    }
+
+    fn state(&mut self, account_id: &str, now: Instant) -> &mut ThrottleState {
+        let state = self
+            .accounts
+            .entry(account_id.to_string())
+            .or_insert_with(|| ThrottleState {
+                credit: 0,
+                window_start: now,
+                window_count: 0,
+            });
+        if now.duration_since(state.window_start) >= RATE_WINDOW {
+            state.window_start = now;
+            state.window_count = 0;
+        }
+        state
+    }
+
+    /// 无其他可用账号时使用被让出的磨合期账号 (仍计入每分钟限额)
+    pub fn record_fallback(&mut self, account_id: &str) {
+        if let Some(state) = self.accounts.get_mut(account_id) {
+            state.window_count += 1;
+        }
+    }
+
+    /// 每分钟限额已用完时返回距窗口重置的剩余时间
+    pub fn throttled_for(&self, account_id: &str, now: Instant) -> Option<Duration> {
+        let max_per_minute = self.config.max_requests_per_minute;
+        let state = self.accounts.get(account_id)?;
+        let elapsed = now.duration_since(state.window_start);
+        (max_per_minute > 0 && state.window_count >= max_per_minute && elapsed < RATE_WINDOW)
+            .then(|| RATE_WINDOW - elapsed)
+    }
+
+    /// 账号毕业后清理状态
+    pub fn forget(&mut self, account_id: &str) {
+        self.accounts.remove(account_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BreakInConfig {
+        BreakInConfig {
+            enabled: true,
+            hours: 48,
+            requests: 200,
+            share_percent: 25,
+            max_requests_per_minute: 0,
+        }
+    }
+
+    #[test]
+    fn test_graduation_happens_exactly_at_threshold() {
+        let now = 1_700_000_000;
+        let added_at = now - 3600;
+        assert_eq!(
+            break_in_status(&config(), added_at, 199, now),
+            BreakInStatus::BreakingIn {
+                requests: 199,
+                required_requests: 200,
+                remaining_secs: Some(47 * 3600),
+            }
+        );
+        assert_eq!(break_in_status(&config(), added_at, 200, now), BreakInStatus::Graduated);
+
+        // 按时间毕业
+        let added_at = now - 48 * 3600;
+        assert!(break_in_status(&config(), added_at + 1, 0, now).is_breaking_in());
+        assert_eq!(break_in_status(&config(), added_at, 0, now), BreakInStatus::Graduated);
+
+        // 未开启或两个阈值均为 0 时视为已毕业
+        let disabled = BreakInConfig { enabled: false, ..config() };
+        assert_eq!(break_in_status(&disabled, now, 0, now), BreakInStatus::Graduated);
+        let no_thresholds = BreakInConfig { hours: 0, requests: 0, ..config() };
+        assert_eq!(break_in_status(&no_thresholds, now, 0, now), BreakInStatus::Graduated);
+    }
+
+    #[test]
+    fn test_admission_respects_share() {
+        let mut throttle = BreakInThrottle::new(config());
+        let now = Instant::now();
+        let admitted = (0..100)
+            .filter(|_| throttle.admit("new", now) == Admission::Admit)
+            .count();
+        assert_eq!(admitted, 25);
+    }
+
+    #[test]
+    fn test_admission_respects_rate_window() {
+        let mut throttle = BreakInThrottle::new(BreakInConfig {
+            share_percent: 100,
+            max_requests_per_minute: 3,
+            ..config()
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(throttle.admit("new", start), Admission::Admit);
+        }
+        assert_eq!(throttle.admit("new", start), Admission::Throttled);
+        assert_eq!(throttle.admit_reuse("new", start), Admission::Throttled);
+        assert_eq!(throttle.admit_reuse("new", start + RATE_WINDOW), Admission::Admit);
+    }
+}
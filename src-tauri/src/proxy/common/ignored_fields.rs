@@ -0,0 +1,116 @@
+// 请求中被忽略的字段
+// 协议结构体不拒绝未知字段 (Anthropic betas、新版 API 的实验性对象、未识别的内容块类型均可正常解析)，
+// 处理器在反序列化前调用这里，把被忽略的部分记录为结构化警告：
+// - 字段表中未列出的顶层字段 -> unsupported_param
+// - thinking / stream_options 等嵌套对象中的未知键 -> unsupported_param
+// - 未识别的内容块 / 片段类型、非 text 的系统块 -> unsupported_content
+use serde_json::Value;
+
+use crate::proxy::common::warnings::{codes, record_warning};
+use crate::proxy::compat_report;
+use crate::proxy::mappers::claude::models::CONTENT_BLOCK_TYPES;
+use crate::proxy::mappers::openai::models::CONTENT_PART_TYPES;
+
+const THINKING_KEYS: &[&str] = &["type", "budget_tokens"];
+const STREAM_OPTIONS_KEYS: &[&str] = &["include_usage"];
+
+fn record_unknown_keys(body: &Value, field: &str, known: &[&str]) {
+    let Some(obj) = body.get(field).and_then(|v| v.as_object()) else {
+        return;
+    };
+    for key in obj.keys().filter(|k| !known.contains(&k.as_str())) {
+        record_warning(
+            codes::UNSUPPORTED_PARAM,
+            format!("{}.{} is not supported and was ignored", field, key),
+            Some(format!("{}.{}", field, key)),
+        );
+    }
+}
+
+fn record_unknown_blocks(body: &Value, known: &[&str], kind: &str) {
+    let messages = body.get("messages").and_then(|m| m.as_array());
+    for (i, msg) in messages.into_iter().flatten().enumerate() {
+        let blocks = msg.get("content").and_then(|c| c.as_array());
+        for (j, block) in blocks.into_iter().flatten().enumerate() {
+            let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+            if !known.contains(&block_type) {
+                record_warning(
+                    codes::UNSUPPORTED_CONTENT,
+                    format!("{} type '{}' is not supported and was ignored", kind, block_type),
+                    Some(format!("messages[{}].content[{}].type", i, j)),
+                );
+            }
+        }
+    }
+}
+
+fn record_unknown_top_level(fields: Vec<String>) {
+    for field in fields {
+        record_warning(
+            codes::UNSUPPORTED_PARAM,
+            format!("unknown field {} was ignored", field),
+            Some(field),
+        );
+    }
+}
+
+/// Anthropic Messages 请求体
+pub fn record_ignored_claude_fields(body: &Value) {
+    record_unknown_top_level(compat_report::unknown_claude_fields(body));
+    record_unknown_keys(body, "thinking", THINKING_KEYS);
+    record_unknown_blocks(body, CONTENT_BLOCK_TYPES, "content block");
+
+    let system = body.get("system").and_then(|s| s.as_array());
+    for (i, block) in system.into_iter().flatten().enumerate() {
+        let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        if block_type != "text" {
+            record_warning(
+                codes::UNSUPPORTED_CONTENT,
+                format!("system block type '{}' is not supported and was ignored", block_type),
+                Some(format!("system[{}].type", i)),
+            );
+        }
+    }
+}
+
+/// OpenAI Chat Completions 请求体
+pub fn record_ignored_openai_fields(body: &Value) {
+    record_unknown_top_level(compat_report::unknown_openai_fields(body));
+    record_unknown_keys(body, "stream_options", STREAM_OPTIONS_KEYS);
+    record_unknown_blocks(body, CONTENT_PART_TYPES, "content part");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::warnings::collect_warnings;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_records_ignored_nested_fields() {
+        let body = json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "betas": ["interleaved-thinking-2025-05-14"],
+            "thinking": {"type": "enabled", "budget_tokens": 2048, "display": "summarized"},
+            "system": [{"type": "text", "text": "You are helpful"}],
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "hi"},
+                {"type": "search_result", "source": "https://example.com", "title": "x", "content": []}
+            ]}]
+        });
+        let ((), warnings) = collect_warnings(async { record_ignored_claude_fields(&body) }).await;
+        let paths: Vec<_> = warnings.iter().filter_map(|w| w.path.as_deref()).collect();
+        assert_eq!(paths, vec!["betas", "thinking.display", "messages[0].content[1].type"]);
+        assert_eq!(warnings[2].code, codes::UNSUPPORTED_CONTENT);
+
+        let body = json!({
+            "model": "gpt-4o",
+            "stream_options": {"include_usage": true, "include_obfuscation": false},
+            "messages": [{"role": "user", "content": [{"type": "input_audio", "input_audio": {}}]}]
+        });
+        let ((), warnings) = collect_warnings(async { record_ignored_openai_fields(&body) }).await;
+        let paths: Vec<_> = warnings.iter().filter_map(|w| w.path.as_deref()).collect();
+        assert_eq!(paths, vec!["stream_options.include_obfuscation", "messages[0].content[0].type"]);
+    }
+}
@@ -0,0 +1,108 @@
+// 图像 API 本地文件输出
+// response_format=url 且启用本地文件模式时，将生成结果写入数据目录并通过 /v1/images/files/:name 提供
+use base64::Engine as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const IMAGES_DIR: &str = "images";
+
+/// 获取图像输出目录 (不存在时创建)
+pub fn images_dir() -> Result<PathBuf, String> {
+    let dir = crate::modules::account::get_data_dir()?.join(IMAGES_DIR);
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("创建图像目录失败: {}", e))?;
+    }
+    Ok(dir)
+}
+
+fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "png",
+    }
+}
+
+fn mime_for(extension: &str) -> Option<&'static str> {
+    match extension {
+        "png" => Some("image/png"),
+        "jpg" => Some("image/jpeg"),
+        "webp" => Some("image/webp"),
+        "gif" => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// 解码 base64 图像并写入目录，返回文件名
+pub fn save_image(dir: &Path, b64_data: &str, mime_type: &str) -> Result<String, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(b64_data)
+        .map_err(|e| format!("Invalid image data: {}", e))?;
+    let name = format!("{}.{}", uuid::Uuid::new_v4().simple(), extension_for(mime_type));
+    std::fs::write(dir.join(&name), bytes).map_err(|e| format!("写入图像文件失败: {}", e))?;
+    Ok(name)
+}
+
+/// 读取已保存的图像；只接受 save_image 生成的文件名，防止路径穿越
+pub fn read_image(dir: &Path, name: &str) -> Result<(Vec<u8>, &'static str), String> {
+    let (stem, extension) = name.split_once('.').ok_or("Invalid image name")?;
+    let mime_type = mime_for(extension).ok_or("Invalid image name")?;
+    if stem.len() != 32 || !stem.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Invalid image name".to_string());
+    }
+    let bytes = std::fs::read(dir.join(name)).map_err(|_| "Image not found".to_string())?;
+    Ok((bytes, mime_type))
+}
+
+/// 删除超过保留时长的图像文件，返回删除数量
+pub fn prune_images(dir: &Path, retention: Duration, now: SystemTime) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map(|modified| now.duration_since(modified).unwrap_or_default() > retention)
+            .unwrap_or(false);
+        if expired && std::fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        tracing::debug!("[Images] Pruned {} expired image file(s)", removed);
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_read_and_prune_images() {
+        let dir = std::env::temp_dir().join(format!("ag-images-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let data = base64::engine::general_purpose::STANDARD.encode(b"\x89PNG fake");
+        let name = save_image(&dir, &data, "image/png").unwrap();
+        assert!(name.ends_with(".png"));
+
+        let (bytes, mime_type) = read_image(&dir, &name).unwrap();
+        assert_eq!(bytes, b"\x89PNG fake");
+        assert_eq!(mime_type, "image/png");
+
+        assert!(read_image(&dir, "../config.json").is_err());
+        assert!(read_image(&dir, "0123456789abcdef0123456789abcdef.json").is_err());
+        assert!(save_image(&dir, "not base64!", "image/png").is_err());
+
+        assert_eq!(prune_images(&dir, Duration::from_secs(3600), SystemTime::now()), 0);
+        let later = SystemTime::now() + Duration::from_secs(7200);
+        assert_eq!(prune_images(&dir, Duration::from_secs(3600), later), 1);
+        assert!(read_image(&dir, &name).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
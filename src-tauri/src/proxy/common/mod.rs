@@ -3,5 +3,16 @@
 // pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
+pub mod model_routes;
 pub mod utils;
 pub mod json_schema;
+pub mod prompt_size;
+pub mod sampling;
+pub mod prompt_log;
+pub mod image_files;
+pub mod warnings;
+pub mod safety;
+pub mod stream_usage;
+pub mod ignored_fields;
+pub mod sse_keepalive;
+pub mod response_model;
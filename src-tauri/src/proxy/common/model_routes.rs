@@ -0,0 +1,286 @@
+// 条件模型路由
+// 客户端使用的别名 (如 "auto") 按请求特征路由到不同的上游模型：
+// - 特征：估算输入 Token 数 (复用 prompt_size 的本地估算)、是否带工具、是否带图片、是否流式
+// - 规则中的变体按顺序匹配，第一个满足条件的变体生效，其模型再走普通模型映射
+// - 选中的变体写入响应头 X-Upstream-Model 与监控日志；/debug/compat-report 展示命中的谓词
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::proxy::common::prompt_size;
+use crate::proxy::config::{ModelRouteRule, RoutePredicate};
+
+pub const UPSTREAM_MODEL_HEADER: HeaderName = HeaderName::from_static("x-upstream-model");
+
+static RULES: Lazy<RwLock<Vec<ModelRouteRule>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// 应用条件路由规则 (代理启动与配置热更新时调用)
+pub fn configure_model_routes(rules: &[ModelRouteRule]) {
+    if let Ok(mut current) = RULES.write() {
+        *current = rules.to_vec();
+    }
+}
+
+/// 当前生效的条件路由规则
+pub fn current_rules() -> Vec<ModelRouteRule> {
+    RULES.read().map(|r| r.clone()).unwrap_or_default()
+}
+
+/// 参与条件路由判断的请求特征
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RequestTraits {
+    pub prompt_tokens: u64,
+    pub has_tools: bool,
+    pub has_images: bool,
+    pub stream: bool,
+}
+
+impl RequestTraits {
+    /// 从客户端原始请求体提取特征 (OpenAI / Claude / Gemini 通用)
+    /// Gemini 请求体直接使用 prompt_size 的体积统计；其余协议按文本与图片逐项估算
+    pub fn from_client_body(body: &Value, stream: bool) -> Self {
+        let has_tools = ["tools", "functions"]
+            .iter()
+            .any(|k| body.get(*k).and_then(|v| v.as_array()).is_some_and(|a| !a.is_empty()));
+
+        if body.get("contents").is_some() {
+            let breakdown = prompt_size::measure_request(body);
+            return Self {
+                prompt_tokens: breakdown.total_tokens(),
+                has_tools,
+                has_images: breakdown.attachments.contains_key("image")
+                    || breakdown.attachments.contains_key("file_ref"),
+                stream,
+            };
+        }
+
+        let mut traits = Self {
+            has_tools,
+            stream,
+            ..Self::default()
+        };
+        for key in ["system", "instructions", "messages", "prompt", "input"] {
+            if let Some(value) = body.get(key) {
+                traits.measure(value);
+            }
+        }
+        if let Some(tools) = body.get("tools").or_else(|| body.get("functions")) {
+            traits.prompt_tokens += prompt_size::estimate_text_tokens(&tools.to_string());
+        }
+        traits
+    }
+
+    fn measure(&mut self, value: &Value) {
+        match value {
+            Value::String(text) => self.prompt_tokens += prompt_size::estimate_text_tokens(text),
+            Value::Array(items) => items.iter().for_each(|v| self.measure(v)),
+            Value::Object(obj) => {
+                let kind = obj.get("type").and_then(|v| v.as_str()).unwrap_or("");
+                if matches!(kind, "image" | "image_url" | "input_image") {
+                    self.has_images = true;
+                    self.prompt_tokens += prompt_size::IMAGE_TOKENS_PER_TILE;
+                    return;
+                }
+                for key in ["text", "content", "thinking", "input", "arguments"] {
+                    if let Some(v) = obj.get(key) {
+                        self.measure(v);
+                    }
+                }
+                if let Some(calls) = obj.get("tool_calls") {
+                    self.prompt_tokens += prompt_size::estimate_text_tokens(&calls.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 条件路由结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteDecision {
+    pub alias: String,
+    /// 选中变体的目标模型
+    pub model: String,
+    /// 选中变体在规则中的序号 (从 0 开始)
+    pub variant: usize,
+    /// 命中的谓词 (无条件变体为 "default")
+    pub matched: Vec<String>,
+    pub traits: RequestTraits,
+}
+
+/// 判断谓词是否满足，满足时返回逐条命中说明
+fn matches(when: &RoutePredicate, traits: &RequestTraits) -> Option<Vec<String>> {
+    let mut matched = Vec::new();
+    if let Some(min) = when.min_prompt_tokens {
+        if traits.prompt_tokens < min {
+            return None;
+        }
+        matched.push(format!("prompt_tokens {} >= {}", traits.prompt_tokens, min));
+    }
+    if let Some(max) = when.max_prompt_tokens {
+        if traits.prompt_tokens > max {
+            return None;
+        }
+        matched.push(format!("prompt_tokens {} <= {}", traits.prompt_tokens, max));
+    }
+    for (name, expected, actual) in [
+        ("has_tools", when.has_tools, traits.has_tools),
+        ("has_images", when.has_images, traits.has_images),
+        ("stream", when.stream, traits.stream),
+    ] {
+        if let Some(expected) = expected {
+            if expected != actual {
+                return None;
+            }
+            matched.push(format!("{} == {}", name, expected));
+        }
+    }
+    if matched.is_empty() {
+        matched.push("default".to_string());
+    }
+    Some(matched)
+}
+
+/// 按规则为 alias 选择变体；alias 没有规则或没有变体匹配时返回 None
+pub fn select_variant(rules: &[ModelRouteRule], alias: &str, traits: &RequestTraits) -> Option<RouteDecision> {
+    let rule = rules.iter().find(|r| r.alias == alias)?;
+    rule.variants.iter().enumerate().find_map(|(idx, variant)| {
+        matches(&variant.when, traits).map(|matched| RouteDecision {
+            alias: alias.to_string(),
+            model: variant.model.clone(),
+            variant: idx,
+            matched,
+            traits: traits.clone(),
+        })
+    })
+}
+
+/// 为当前请求执行条件路由并记录结果 (仅当 model 配置了规则时才计算请求特征)
+pub fn route_request(model: &str, traits: impl FnOnce() -> RequestTraits) -> Option<RouteDecision> {
+    let rules = RULES.read().ok()?;
+    if !rules.iter().any(|r| r.alias == model) {
+        return None;
+    }
+    let traits = traits();
+    let Some(decision) = select_variant(&rules, model, &traits) else {
+        tracing::info!("[ModelRoute] {}: no variant matched {:?}, using regular mapping", model, traits);
+        return None;
+    };
+    tracing::info!(
+        "[ModelRoute] {} -> {} (variant #{}: {})",
+        model,
+        decision.model,
+        decision.variant,
+        decision.matched.join(", ")
+    );
+    let _ = ROUTED_MODEL.try_with(|slot| {
+        *slot.lock().unwrap() = Some(decision.model.clone());
+    });
+    Some(decision)
+}
+
+/// 请求级条件路由结果 (由 model_route_middleware 写入响应 extensions，供监控日志读取)
+#[derive(Debug, Clone)]
+pub struct RoutedModel(pub String);
+
+type RoutedSlot = Arc<Mutex<Option<String>>>;
+
+tokio::task_local! {
+    static ROUTED_MODEL: RoutedSlot;
+}
+
+/// 为请求建立条件路由上下文，命中时附加 X-Upstream-Model 响应头
+pub async fn model_route_middleware(request: Request, next: Next) -> Response {
+    let slot = RoutedSlot::default();
+    let mut response = ROUTED_MODEL.scope(slot.clone(), next.run(request)).await;
+    let routed = slot.lock().unwrap().take();
+    if let Some(model) = routed {
+        if let Ok(value) = HeaderValue::from_str(&model) {
+            response.headers_mut().insert(UPSTREAM_MODEL_HEADER, value);
+        }
+        response.extensions_mut().insert(RoutedModel(model));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::ModelRouteVariant;
+    use serde_json::json;
+
+    fn auto_rule() -> Vec<ModelRouteRule> {
+        vec![ModelRouteRule {
+            alias: "auto".to_string(),
+            variants: vec![
+                ModelRouteVariant {
+                    model: "gemini-3-pro-high".to_string(),
+                    when: RoutePredicate {
+                        has_images: Some(true),
+                        ..Default::default()
+                    },
+                },
+                ModelRouteVariant {
+                    model: "gemini-3-flash".to_string(),
+                    when: RoutePredicate {
+                        max_prompt_tokens: Some(2000),
+                        has_tools: Some(false),
+                        ..Default::default()
+                    },
+                },
+                ModelRouteVariant {
+                    model: "gemini-3-pro-high".to_string(),
+                    when: RoutePredicate::default(),
+                },
+            ],
+        }]
+    }
+
+    #[test]
+    fn test_first_matching_variant_wins() {
+        let rules = auto_rule();
+        let short = json!({"model": "auto", "messages": [{"role": "user", "content": "hi"}]});
+        let decision = select_variant(&rules, "auto", &RequestTraits::from_client_body(&short, false)).unwrap();
+        assert_eq!(decision.model, "gemini-3-flash");
+        assert_eq!(decision.variant, 1);
+        assert_eq!(decision.matched, vec!["prompt_tokens 1 <= 2000", "has_tools == false"]);
+
+        let long = json!({"model": "auto", "messages": [{"role": "user", "content": "x".repeat(20_000)}]});
+        let decision = select_variant(&rules, "auto", &RequestTraits::from_client_body(&long, true)).unwrap();
+        assert_eq!((decision.variant, decision.matched), (2, vec!["default".to_string()]));
+
+        assert!(select_variant(&rules, "gemini-3-flash", &RequestTraits::default()).is_none());
+    }
+
+    #[test]
+    fn test_traits_across_protocols() {
+        let openai = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "describe"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]}],
+            "tools": [{"type": "function", "function": {"name": "f"}}]
+        });
+        let traits = RequestTraits::from_client_body(&openai, false);
+        assert!(traits.has_images && traits.has_tools);
+        assert!(traits.prompt_tokens > prompt_size::IMAGE_TOKENS_PER_TILE);
+
+        let claude = json!({
+            "system": "be brief",
+            "messages": [{"role": "user", "content": [{"type": "image", "source": {"type": "base64", "data": "AAAA"}}]}]
+        });
+        assert!(RequestTraits::from_client_body(&claude, false).has_images);
+
+        let gemini = json!({"contents": [{"role": "user", "parts": [{"text": "abcd"}]}]});
+        let traits = RequestTraits::from_client_body(&gemini, true);
+        assert_eq!(traits.prompt_tokens, prompt_size::measure_request(&gemini).total_tokens());
+        assert!(!traits.has_images && traits.stream);
+    }
+}
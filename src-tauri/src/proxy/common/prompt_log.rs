@@ -0,0 +1,166 @@
+// 对话内容在日志中的呈现方式 (prompt_log_mode)
+// - preview: 按字符截断后输出原文 (UTF-8 安全)，并保留完整报文的 debug 输出
+// - hash: 只输出首条用户消息规范化 (合并空白) 后的短哈希，跨重启稳定，可用于关联同一提示词的多条日志
+// - off: 不输出对话内容
+// 请求日志 (监控) 只在 preview 模式下保存请求正文；hash 模式只保存提示词哈希，off 模式不保存
+// 访问日志的 prompt_hash 字段在 preview / hash 模式下记录
+// 模式保存在 AppState.prompt_log (可热更新)，由调用方读取后传入
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::proxy::config::PromptLogMode;
+
+/// 短哈希长度 (十六进制字符)
+const HASH_LEN: usize = 12;
+/// off 模式下替代正文的占位
+const OMITTED: &str = "[omitted]";
+
+/// 是否允许输出完整报文 (仅 preview 模式)
+pub fn content_logging_enabled(mode: PromptLogMode) -> bool {
+    mode == PromptLogMode::Preview
+}
+
+/// 规范化后的短哈希：首尾空白去除、连续空白合并为一个空格
+pub fn prompt_hash(text: &str) -> String {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let digest = format!("{:x}", Sha256::digest(normalized.as_bytes()));
+    digest[..HASH_LEN].to_string()
+}
+
+/// 按日志模式呈现一段对话文本
+pub fn describe(mode: PromptLogMode, text: &str, max_chars: usize) -> String {
+    match mode {
+        PromptLogMode::Preview => truncate(text, max_chars),
+        PromptLogMode::Hash => format!("[prompt {}]", prompt_hash(text)),
+        PromptLogMode::Off => OMITTED.to_string(),
+    }
+}
+
+/// 按日志模式呈现整个请求体 (hash 模式优先使用首条用户消息的哈希，便于与其他日志关联)
+pub fn describe_body(mode: PromptLogMode, body: &Value, max_chars: usize) -> String {
+    match mode {
+        PromptLogMode::Preview => truncate(&body.to_string(), max_chars),
+        PromptLogMode::Hash => {
+            let hash = first_user_text(body)
+                .map(|text| prompt_hash(&text))
+                .unwrap_or_else(|| prompt_hash(&body.to_string()));
+            format!("[prompt {}]", hash)
+        }
+        PromptLogMode::Off => OMITTED.to_string(),
+    }
+}
+
+/// 日志记录的提示词哈希 (off 模式或请求中没有用户消息时为 None)
+pub fn prompt_hash_for_log(mode: PromptLogMode, body: &Value) -> Option<String> {
+    if mode == PromptLogMode::Off {
+        return None;
+    }
+    first_user_text(body).map(|text| prompt_hash(&text))
+}
+
+/// 请求日志保存的请求正文：preview 保存原文，hash 只保存 `[prompt <hash>]`，off 不保存
+pub fn request_body_for_log(mode: PromptLogMode, bytes: &[u8]) -> Option<String> {
+    match mode {
+        PromptLogMode::Preview => Some(match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => "[Binary Request Data]".to_string(),
+        }),
+        PromptLogMode::Hash => serde_json::from_slice::<Value>(bytes)
+            .ok()
+            .and_then(|json| prompt_hash_for_log(mode, &json))
+            .map(|hash| format!("[prompt {}]", hash)),
+        PromptLogMode::Off => None,
+    }
+}
+
+/// 提取首条用户消息的文本 (OpenAI / Claude 的 messages，Gemini 的 contents)
+pub fn first_user_text(body: &Value) -> Option<String> {
+    if let Some(messages) = body.get("messages").and_then(|m| m.as_array()) {
+        let message = messages
+            .iter()
+            .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("user"))?;
+        return match message.get("content")? {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(blocks) => join_texts(blocks.iter().filter_map(|b| b.get("text"))),
+            _ => None,
+        };
+    }
+    let contents = body.get("contents").and_then(|c| c.as_array())?;
+    let content = contents
+        .iter()
+        .find(|c| c.get("role").and_then(|r| r.as_str()).unwrap_or("user") == "user")?;
+    let parts = content.get("parts").and_then(|p| p.as_array())?;
+    join_texts(parts.iter().filter_map(|p| p.get("text")))
+}
+
+fn join_texts<'a>(texts: impl Iterator<Item = &'a Value>) -> Option<String> {
+    let texts: Vec<&str> = texts.filter_map(|t| t.as_str()).collect();
+    (!texts.is_empty()).then(|| texts.join(" "))
+}
+
+/// 按字符截断 (不会切断多字节字符)
+fn truncate(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(max_chars).collect();
+    format!("{}... (total {} chars)", head, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hash_ignores_whitespace_differences() {
+        let hash = prompt_hash("  Explain   the\nerror ");
+        assert_eq!(hash.len(), HASH_LEN);
+        assert_eq!(hash, prompt_hash("Explain the error"));
+        assert_ne!(hash, prompt_hash("Explain the errors"));
+        // 固定算法，重启后仍相同
+        assert_eq!(prompt_hash("hello"), "2cf24dba5fb0");
+    }
+
+    #[test]
+    fn test_first_user_text_across_protocols() {
+        let openai = json!({"messages": [
+            {"role": "system", "content": "be brief"},
+            {"role": "user", "content": "first"},
+            {"role": "user", "content": "second"}
+        ]});
+        assert_eq!(first_user_text(&openai).as_deref(), Some("first"));
+
+        let claude = json!({"messages": [{"role": "user", "content": [
+            {"type": "image", "source": {}},
+            {"type": "text", "text": "look"},
+            {"type": "text", "text": "here"}
+        ]}]});
+        assert_eq!(first_user_text(&claude).as_deref(), Some("look here"));
+
+        let gemini = json!({"contents": [{"role": "user", "parts": [{"text": "hi"}]}]});
+        assert_eq!(first_user_text(&gemini).as_deref(), Some("hi"));
+        assert_eq!(first_user_text(&json!({"input": "x"})), None);
+    }
+
+    #[test]
+    fn test_request_body_follows_mode() {
+        let body = br#"{"messages":[{"role":"user","content":"hello"}]}"#;
+        assert_eq!(
+            request_body_for_log(PromptLogMode::Hash, body).as_deref(),
+            Some("[prompt 2cf24dba5fb0]")
+        );
+        assert_eq!(request_body_for_log(PromptLogMode::Off, body), None);
+        assert_eq!(
+            request_body_for_log(PromptLogMode::Preview, body).as_deref(),
+            std::str::from_utf8(body).ok()
+        );
+    }
+
+    #[test]
+    fn test_preview_truncates_on_char_boundary() {
+        assert_eq!(truncate("你好世界", 2), "你好... (total 4 chars)");
+        assert_eq!(truncate("short", 10), "short");
+    }
+}
@@ -0,0 +1,390 @@
+// Prompt 体积预检 (Pre-flight Size Gate)
+// 在发送到上游之前本地估算 Token 数，超出模型上下文窗口时直接拒绝，避免长时间上传后才失败
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// 估算纯文本 Token 数 (实现位于 antigravity-protocol 库)
+pub use antigravity_protocol::usage::estimate_text_tokens;
+
+/// 单张图片 (一个 768x768 tile) 的 Token 成本，参考 Gemini 官方计费规则
+pub const IMAGE_TOKENS_PER_TILE: u64 = 258;
+/// 估算图片 tile 数时使用的单 tile 平均字节数
+const IMAGE_BYTES_PER_TILE: u64 = 150 * 1024;
+/// 单张图片最多按多少 tile 计算
+const IMAGE_MAX_TILES: u64 = 16;
+/// PDF 每页 Token 成本 (Gemini 按页渲染为图片)
+const PDF_TOKENS_PER_PAGE: u64 = 258;
+/// 估算 PDF 页数时使用的单页平均字节数
+const PDF_BYTES_PER_PAGE: u64 = 50 * 1024;
+/// 音视频等其他二进制内容: 每 KB 折算的 Token 数
+const MEDIA_TOKENS_PER_KB: u64 = 8;
+/// 默认上下文窗口 (未知模型)
+const DEFAULT_CONTEXT_WINDOW: u64 = 128_000;
+
+/// 获取模型上下文窗口 (输入 + 输出总上限)
+pub fn get_context_window(model: &str) -> u64 {
+    let m = model.to_lowercase();
+
+    if m.starts_with("claude-") {
+        return 200_000;
+    }
+    if m.contains("image") {
+        // 图像生成模型的输入窗口明显更小
+        return 65_536;
+    }
+    if m.starts_with("gemini-") {
+        return 1_048_576;
+    }
+
+    DEFAULT_CONTEXT_WINDOW
+}
+
+/// base64 数据解码后的近似字节数
+fn decoded_len(data_len: usize) -> u64 {
+    (data_len as u64) * 3 / 4
+}
+
+/// 根据 mimeType 与 base64 数据长度估算二进制内容 Token 数
+fn estimate_inline_data_tokens(mime_type: &str, data_len: usize) -> u64 {
+    let bytes = decoded_len(data_len);
+
+    if mime_type.starts_with("image/") {
+        let tiles = (bytes / IMAGE_BYTES_PER_TILE + 1).min(IMAGE_MAX_TILES);
+        tiles * IMAGE_TOKENS_PER_TILE
+    } else if mime_type == "application/pdf" {
+        let pages = bytes / PDF_BYTES_PER_PAGE + 1;
+        pages * PDF_TOKENS_PER_PAGE
+    } else if mime_type.starts_with("text/") {
+        // 文本类附件按字符估算
+        bytes.div_ceil(4)
+    } else {
+        (bytes / 1024 + 1) * MEDIA_TOKENS_PER_KB
+    }
+}
+
+/// 估算单个 Gemini part 的 Token 数
+fn estimate_part_tokens(part: &Value) -> u64 {
+    let mut total = 0;
+
+    if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+        total += estimate_text_tokens(text);
+    }
+    if let Some(inline) = part.get("inlineData") {
+        let mime = inline.get("mimeType").and_then(|v| v.as_str()).unwrap_or("");
+        let data_len = inline.get("data").and_then(|v| v.as_str()).map(|s| s.len()).unwrap_or(0);
+        total += estimate_inline_data_tokens(mime, data_len);
+    }
+    if part.get("fileData").is_some() {
+        // 远程文件无法得知大小，按单张图片计
+        total += IMAGE_TOKENS_PER_TILE;
+    }
+    if let Some(call) = part.get("functionCall") {
+        total += estimate_text_tokens(&call.to_string());
+    }
+    if let Some(resp) = part.get("functionResponse") {
+        total += estimate_text_tokens(&resp.to_string());
+    }
+
+    total
+}
+
+/// 单个区段的体积：字节数 (文本按 UTF-8，附件按解码后) 与估算 Token 数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionSize {
+    pub bytes: u64,
+    pub tokens: u64,
+}
+
+impl SectionSize {
+    fn add(&mut self, other: SectionSize) {
+        self.bytes += other.bytes;
+        self.tokens += other.tokens;
+    }
+}
+
+/// 某一类附件的体积
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentSize {
+    pub count: u64,
+    pub bytes: u64,
+    pub tokens: u64,
+}
+
+/// 转换后上游请求体的体积构成 (systemInstruction / contents / tools，附件按类型细分)
+///
+/// contents 包含其中的附件，attachments 只是 contents 的细分，不重复计入总量。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptSizeBreakdown {
+    pub system: SectionSize,
+    pub contents: SectionSize,
+    pub tools: SectionSize,
+    /// 附件类型 (image / pdf / audio / video / text / other / file_ref) -> 体积
+    pub attachments: BTreeMap<String, AttachmentSize>,
+}
+
+impl PromptSizeBreakdown {
+    pub fn total_tokens(&self) -> u64 {
+        self.system.tokens + self.contents.tokens + self.tools.tokens
+    }
+
+    fn add_attachment(&mut self, kind: &str, bytes: u64, tokens: u64) {
+        let entry = self.attachments.entry(kind.to_string()).or_default();
+        entry.count += 1;
+        entry.bytes += bytes;
+        entry.tokens += tokens;
+    }
+
+    /// 统计单个 part；附件同时记入 attachments
+    fn measure_part(&mut self, part: &Value) -> SectionSize {
+        let mut size = SectionSize {
+            bytes: 0,
+            tokens: estimate_part_tokens(part),
+        };
+
+        if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+            size.bytes += text.len() as u64;
+        }
+        if let Some(inline) = part.get("inlineData") {
+            let mime = inline.get("mimeType").and_then(|v| v.as_str()).unwrap_or("");
+            let data_len = inline.get("data").and_then(|v| v.as_str()).map(|s| s.len()).unwrap_or(0);
+            let bytes = decoded_len(data_len);
+            size.bytes += bytes;
+            self.add_attachment(attachment_kind(mime), bytes, estimate_inline_data_tokens(mime, data_len));
+        }
+        if part.get("fileData").is_some() {
+            self.add_attachment("file_ref", 0, IMAGE_TOKENS_PER_TILE);
+        }
+        for key in ["functionCall", "functionResponse"] {
+            if let Some(v) = part.get(key) {
+                size.bytes += v.to_string().len() as u64;
+            }
+        }
+
+        size
+    }
+}
+
+/// 按 mimeType 归类附件
+fn attachment_kind(mime_type: &str) -> &'static str {
+    if mime_type.starts_with("image/") {
+        "image"
+    } else if mime_type == "application/pdf" {
+        "pdf"
+    } else if mime_type.starts_with("audio/") {
+        "audio"
+    } else if mime_type.starts_with("video/") {
+        "video"
+    } else if mime_type.starts_with("text/") {
+        "text"
+    } else {
+        "other"
+    }
+}
+
+/// 统计 Gemini 请求体 (支持 v1internal 包装或裸请求) 各区段的体积
+pub fn measure_request(body: &Value) -> PromptSizeBreakdown {
+    let inner = body.get("request").unwrap_or(body);
+    let mut breakdown = PromptSizeBreakdown::default();
+
+    if let Some(contents) = inner.get("contents").and_then(|v| v.as_array()) {
+        for content in contents {
+            if let Some(parts) = content.get("parts").and_then(|v| v.as_array()) {
+                for part in parts {
+                    let size = breakdown.measure_part(part);
+                    breakdown.contents.add(size);
+                }
+            }
+        }
+    }
+
+    if let Some(parts) = inner
+        .get("systemInstruction")
+        .and_then(|s| s.get("parts"))
+        .and_then(|v| v.as_array())
+    {
+        for part in parts {
+            let size = breakdown.measure_part(part);
+            breakdown.system.add(size);
+        }
+    }
+
+    if let Some(tools) = inner.get("tools") {
+        let json = tools.to_string();
+        breakdown.tools = SectionSize {
+            bytes: json.len() as u64,
+            tokens: estimate_text_tokens(&json),
+        };
+    }
+
+    breakdown
+}
+
+/// 预检结果
+#[derive(Debug, Clone)]
+pub struct PromptSizeCheck {
+    pub estimated_tokens: u64,
+    /// 各区段体积 (estimated_tokens 即其 Token 合计)
+    pub breakdown: PromptSizeBreakdown,
+    pub max_output_tokens: u64,
+    pub context_window: u64,
+    /// 可用于输入的 Token 上限 (上下文窗口 - 请求的最大输出)
+    pub input_limit: u64,
+}
+
+impl PromptSizeCheck {
+    pub fn is_allowed(&self) -> bool {
+        self.estimated_tokens <= self.input_limit
+    }
+
+    /// 生成面向客户端的拒绝说明
+    pub fn rejection_message(&self, model: &str) -> String {
+        format!(
+            "Prompt too large for model '{}': estimated {} input tokens, but the limit is {} \
+             (context window {} minus requested max output {}). \
+             Enable auto-trim / context compression in your client, shorten the conversation, \
+             or switch to a model with a larger context window.",
+            model,
+            self.estimated_tokens,
+            self.input_limit,
+            self.context_window,
+            self.max_output_tokens
+        )
+    }
+}
+
+/// 对转换后的上游请求执行体积预检，并记录判定结果
+pub fn check_prompt_size(body: &Value, model: &str, max_output_tokens: Option<u32>) -> PromptSizeCheck {
+    let context_window = get_context_window(model);
+    let max_output_tokens = max_output_tokens.map(|v| v as u64).unwrap_or(0).min(context_window);
+    let breakdown = measure_request(body);
+    let check = PromptSizeCheck {
+        estimated_tokens: breakdown.total_tokens(),
+        breakdown,
+        max_output_tokens,
+        context_window,
+        input_limit: context_window - max_output_tokens,
+    };
+
+    if check.is_allowed() {
+        tracing::debug!(
+            "[PromptGate] pass: model={}, estimate={}, limit={}, system={}B/{}t, contents={}B/{}t, tools={}B/{}t",
+            model,
+            check.estimated_tokens,
+            check.input_limit,
+            check.breakdown.system.bytes,
+            check.breakdown.system.tokens,
+            check.breakdown.contents.bytes,
+            check.breakdown.contents.tokens,
+            check.breakdown.tools.bytes,
+            check.breakdown.tools.tokens
+        );
+    } else {
+        tracing::warn!(
+            "[PromptGate] reject: model={}, estimate={}, limit={} (window={}, max_output={}), system={}B/{}t, contents={}B/{}t, tools={}B/{}t",
+            model,
+            check.estimated_tokens,
+            check.input_limit,
+            check.context_window,
+            check.max_output_tokens,
+            check.breakdown.system.bytes,
+            check.breakdown.system.tokens,
+            check.breakdown.contents.bytes,
+            check.breakdown.contents.tokens,
+            check.breakdown.tools.bytes,
+            check.breakdown.tools.tokens
+        );
+    }
+
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 估算 Gemini 请求体 (支持 v1internal 包装或裸请求) 的输入 Token 数
+    fn estimate_request_tokens(body: &Value) -> u64 {
+        measure_request(body).total_tokens()
+    }
+
+    #[test]
+    fn test_context_window_lookup() {
+        assert_eq!(get_context_window("claude-sonnet-4-5"), 200_000);
+        assert_eq!(get_context_window("gemini-2.5-flash-lite"), 1_048_576);
+        assert_eq!(get_context_window("gemini-3-pro-image"), 65_536);
+        assert_eq!(get_context_window("unknown-model"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_text_estimation() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcd"), 1);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_request_estimation_with_media() {
+        let body = json!({
+            "request": {
+                "systemInstruction": {"parts": [{"text": "abcdabcd"}]},
+                "contents": [{
+                    "role": "user",
+                    "parts": [
+                        {"text": "abcd"},
+                        {"inlineData": {"mimeType": "image/png", "data": "AAAA"}},
+                        {"inlineData": {"mimeType": "application/pdf", "data": "AAAA"}}
+                    ]
+                }]
+            }
+        });
+        // 2 (system) + 1 (text) + 258 (image) + 258 (pdf page)
+        assert_eq!(estimate_request_tokens(&body), 519);
+    }
+
+    #[test]
+    fn test_measure_request_sections() {
+        let body = json!({
+            "request": {
+                "systemInstruction": {"parts": [{"text": "你好abcd"}]},
+                "contents": [{
+                    "role": "user",
+                    "parts": [
+                        {"text": "abcd"},
+                        {"inlineData": {"mimeType": "image/png", "data": "AAAAAAAA"}},
+                        {"inlineData": {"mimeType": "image/jpeg", "data": "AAAA"}},
+                        {"fileData": {"mimeType": "video/mp4", "fileUri": "gs://x"}}
+                    ]
+                }],
+                "tools": [{"functionDeclarations": []}]
+            }
+        });
+        let m = measure_request(&body);
+        // "你好" 为 6 字节 UTF-8
+        assert_eq!(m.system, SectionSize { bytes: 10, tokens: 3 });
+        assert_eq!(m.contents.bytes, 4 + 6 + 3);
+        assert_eq!(m.contents.tokens, 1 + 258 + 258 + 258);
+        assert_eq!(m.tools.bytes, r#"[{"functionDeclarations":[]}]"#.len() as u64);
+        assert_eq!(m.attachments["image"], AttachmentSize { count: 2, bytes: 9, tokens: 516 });
+        assert_eq!(m.attachments["file_ref"].count, 1);
+        assert_eq!(m.total_tokens(), estimate_request_tokens(&body));
+    }
+
+    #[test]
+    fn test_gate_rejects_oversized_prompt() {
+        let huge = "a".repeat(4 * 70_000);
+        let body = json!({"contents": [{"role": "user", "parts": [{"text": huge}]}]});
+
+        let check = check_prompt_size(&body, "gemini-3-pro-image", Some(1000));
+        assert!(!check.is_allowed());
+        assert_eq!(check.input_limit, 65_536 - 1000);
+        let msg = check.rejection_message("gemini-3-pro-image");
+        assert!(msg.contains("70000"));
+        assert!(msg.contains("64536"));
+
+        let ok = check_prompt_size(&body, "gemini-2.5-flash", Some(1000));
+        assert!(ok.is_allowed());
+    }
+}
@@ -0,0 +1,226 @@
+// 响应体中上报的模型名
+// 部分客户端校验 response.model 与请求的模型名完全一致，另一些只记录 model，希望看到上游的真实模型。
+// 上报方式按优先级：X-Report-Model 请求头 > 附加 Key 的 report_model > 全局 report_model。
+// requested 模式下所有响应形态都经过同一个 rewrite_model (由 response_model_middleware 调用)：
+// - 非流式 JSON：OpenAI / Anthropic 顶层 model、Gemini modelVersion
+// - 流式 SSE 的每个 data 事件：OpenAI chunk、Anthropic message_start 的 message.model
+// - 错误体：error.model，以及错误信息中出现的上游模型名
+// 内部日志不受影响，监控日志始终同时记录请求模型与上游模型
+use axum::http::{HeaderMap, HeaderName};
+use bytes::Bytes;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::proxy::config::ReportModel;
+
+/// 单个请求覆盖上报方式 (`requested` / `upstream`)
+pub const REPORT_MODEL_HEADER: HeaderName = HeaderName::from_static("x-report-model");
+
+/// 携带模型名的字段
+const MODEL_FIELDS: &[&str] = &["model", "modelVersion"];
+
+static REPORT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 应用全局上报方式 (代理启动与配置热更新时调用)
+pub fn configure_report_model(mode: &ReportModel) {
+    REPORT_REQUESTED.store(*mode == ReportModel::Requested, Ordering::Relaxed);
+}
+
+fn global_mode() -> ReportModel {
+    if REPORT_REQUESTED.load(Ordering::Relaxed) {
+        ReportModel::Requested
+    } else {
+        ReportModel::Upstream
+    }
+}
+
+fn parse_mode(value: &str) -> Option<ReportModel> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "requested" => Some(ReportModel::Requested),
+        "upstream" => Some(ReportModel::Upstream),
+        _ => None,
+    }
+}
+
+/// 当前请求的上报方式 (需在认证中间件建立的 Key 上下文中调用)
+pub fn effective_mode(headers: &HeaderMap) -> ReportModel {
+    let from_header = headers
+        .get(REPORT_MODEL_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_mode);
+    let from_key = || crate::proxy::key_scope::current_scope().ok().flatten().and_then(|s| s.report_model);
+    from_header.or_else(from_key).unwrap_or_else(global_mode)
+}
+
+fn replace_fields(obj: &mut serde_json::Map<String, Value>, requested: &str) -> bool {
+    let mut changed = false;
+    for field in MODEL_FIELDS {
+        if let Some(value) = obj.get_mut(*field).filter(|v| v.is_string()) {
+            if value != requested {
+                *value = Value::String(requested.to_string());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// 将响应 JSON 中上报的模型名改为 `requested`，返回是否有改动
+/// `upstream` 为已知的上游模型名，错误信息中出现时一并替换
+pub fn rewrite_model(body: &mut Value, requested: &str, upstream: Option<&str>) -> bool {
+    let Some(obj) = body.as_object_mut() else {
+        return false;
+    };
+    let mut changed = replace_fields(obj, requested);
+    // Anthropic message_start / v1internal 包装
+    for nested in ["message", "response"] {
+        if let Some(inner) = obj.get_mut(nested).and_then(|v| v.as_object_mut()) {
+            changed |= replace_fields(inner, requested);
+        }
+    }
+    if let Some(error) = obj.get_mut("error").and_then(|v| v.as_object_mut()) {
+        changed |= replace_fields(error, requested);
+        let upstream = upstream.filter(|u| !u.is_empty() && *u != requested);
+        if let (Some(upstream), Some(Value::String(message))) = (upstream, error.get_mut("message")) {
+            if message.contains(upstream) {
+                *message = message.replace(upstream, requested);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// 流式响应的逐行改写 (事件可能跨 chunk，未完整的行留到下一个 chunk)
+#[derive(Debug)]
+pub struct SseModelRewriter {
+    requested: String,
+    upstream: Option<String>,
+    pending: Vec<u8>,
+}
+
+impl SseModelRewriter {
+    pub fn new(requested: String, upstream: Option<String>) -> Self {
+        Self {
+            requested,
+            upstream,
+            pending: Vec::new(),
+        }
+    }
+
+    /// 输入一个 chunk，返回其中已完整的行 (改写后)
+    pub fn push(&mut self, chunk: &[u8]) -> Option<Bytes> {
+        self.pending.extend_from_slice(chunk);
+        let end = self.pending.iter().rposition(|b| *b == b'\n')? + 1;
+        let complete: Vec<u8> = self.pending.drain(..end).collect();
+        Some(self.rewrite_lines(&complete))
+    }
+
+    /// 流结束时输出剩余内容
+    pub fn finish(&mut self) -> Option<Bytes> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let rest = std::mem::take(&mut self.pending);
+        Some(self.rewrite_lines(&rest))
+    }
+
+    fn rewrite_lines(&self, text: &[u8]) -> Bytes {
+        let Ok(text) = std::str::from_utf8(text) else {
+            return Bytes::copy_from_slice(text);
+        };
+        let mut out = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            match self.rewrite_data_line(line) {
+                Some(rewritten) => out.push_str(&rewritten),
+                None => out.push_str(line),
+            }
+        }
+        Bytes::from(out)
+    }
+
+    fn rewrite_data_line(&self, line: &str) -> Option<String> {
+        let data = line.strip_prefix("data:")?;
+        let ending = &data[data.trim_end().len()..];
+        let mut json: Value = serde_json::from_str(data.trim()).ok()?;
+        rewrite_model(&mut json, &self.requested, self.upstream.as_deref())
+            .then(|| format!("data: {}{}", json, ending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rewritten(mut body: Value) -> Value {
+        rewrite_model(&mut body, "gpt-4o", Some("gemini-3-pro-high"));
+        body
+    }
+
+    #[test]
+    fn test_rewrites_every_non_stream_shape() {
+        // OpenAI chat.completion
+        let body = rewritten(json!({"id": "chatcmpl-1", "object": "chat.completion", "model": "gemini-3-pro-high"}));
+        assert_eq!(body["model"], "gpt-4o");
+        // Anthropic message
+        let body = rewritten(json!({"id": "msg_1", "type": "message", "model": "gemini-3-pro-high", "content": []}));
+        assert_eq!(body["model"], "gpt-4o");
+        // Gemini generateContent
+        let body = rewritten(json!({"candidates": [], "modelVersion": "gemini-3-pro-high"}));
+        assert_eq!(body["modelVersion"], "gpt-4o");
+        // 内容中的同名字段不受影响
+        let body = rewritten(json!({"choices": [{"message": {"content": "model: gemini-3-pro-high"}}], "model": "x"}));
+        assert_eq!(body["choices"][0]["message"]["content"], "model: gemini-3-pro-high");
+    }
+
+    #[test]
+    fn test_rewrites_error_bodies() {
+        let body = rewritten(json!({
+            "type": "error",
+            "error": {"type": "not_found_error", "message": "model gemini-3-pro-high is not available", "model": "gemini-3-pro-high"}
+        }));
+        assert_eq!(body["error"]["message"], "model gpt-4o is not available");
+        assert_eq!(body["error"]["model"], "gpt-4o");
+
+        let mut plain = json!({"error": {"message": "quota exceeded"}});
+        assert!(!rewrite_model(&mut plain, "gpt-4o", None));
+    }
+
+    #[test]
+    fn test_rewrites_stream_events_across_chunks() {
+        let mut rewriter = SseModelRewriter::new("claude-sonnet-4-5".to_string(), None);
+        // Anthropic message_start 跨 chunk
+        let first = rewriter
+            .push(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"model\":\"gemini-3-pro")
+            .unwrap();
+        assert_eq!(&first[..], b"event: message_start\n");
+        let second = rewriter.push(b"-high\",\"content\":[]}}\n\n").unwrap();
+        let text = String::from_utf8(second.to_vec()).unwrap();
+        assert!(text.starts_with("data: {") && text.ends_with("}\n\n"));
+        let json: Value = serde_json::from_str(text.trim().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(json["message"]["model"], "claude-sonnet-4-5");
+
+        // OpenAI chunk、[DONE] 与保活注释
+        let mut rewriter = SseModelRewriter::new("gpt-4o".to_string(), None);
+        let out = rewriter
+            .push(b"data: {\"object\":\"chat.completion.chunk\",\"model\":\"gemini-3-flash\"}\n\n: keepalive\n\ndata: [DONE]\n\n")
+            .unwrap();
+        let text = String::from_utf8(out.to_vec()).unwrap();
+        let (chunk, rest) = text.split_once("\n\n").unwrap();
+        let json: Value = serde_json::from_str(chunk.strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(json, json!({"object": "chat.completion.chunk", "model": "gpt-4o"}));
+        assert_eq!(rest, ": keepalive\n\ndata: [DONE]\n\n");
+        assert!(rewriter.finish().is_none());
+    }
+
+    #[test]
+    fn test_header_overrides_global_mode() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(effective_mode(&headers), ReportModel::Upstream);
+        headers.insert(REPORT_MODEL_HEADER, "Requested".parse().unwrap());
+        assert_eq!(effective_mode(&headers), ReportModel::Requested);
+        headers.insert(REPORT_MODEL_HEADER, "bogus".parse().unwrap());
+        assert_eq!(effective_mode(&headers), ReportModel::Upstream);
+    }
+}
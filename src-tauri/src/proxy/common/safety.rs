@@ -0,0 +1,116 @@
+// 安全过滤阈值
+// 所有请求构建路径 (OpenAI / Claude / 图片生成 / Gemini 原生) 通过 safety_settings() 取得 safetySettings：
+// - 未配置时使用默认值 (全部类别 OFF)
+// - 配置后原样透传，未列出的类别由上游按其默认阈值处理
+// - Gemini 原生请求仅在已配置且客户端未提供 safetySettings 时补上配置值
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::RwLock;
+
+use crate::proxy::config::SafetySetting;
+use crate::proxy::mappers::gemini::models::{default_safety_settings, SAFETY_CATEGORIES, SAFETY_THRESHOLDS};
+
+static CONFIGURED: Lazy<RwLock<Option<Value>>> = Lazy::new(|| RwLock::new(None));
+
+/// 校验类别与阈值是否为上游已知的枚举值 (配置加载时调用)
+pub fn validate_safety_settings(settings: &[SafetySetting]) -> Result<(), String> {
+    for (i, setting) in settings.iter().enumerate() {
+        if !SAFETY_CATEGORIES.contains(&setting.category.as_str()) {
+            return Err(format!(
+                "safety_settings[{}] 未知的类别 {}，可选值: {}",
+                i,
+                setting.category,
+                SAFETY_CATEGORIES.join(", ")
+            ));
+        }
+        if !SAFETY_THRESHOLDS.contains(&setting.threshold.as_str()) {
+            return Err(format!(
+                "safety_settings[{}] 未知的阈值 {}，可选值: {}",
+                i,
+                setting.threshold,
+                SAFETY_THRESHOLDS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 应用 safetySettings 配置 (代理启动与配置热更新时调用)
+pub fn configure_safety_settings(settings: &Option<Vec<SafetySetting>>) {
+    if let Some(settings) = settings {
+        for setting in settings.iter().filter(|s| s.threshold == "BLOCK_NONE") {
+            tracing::warn!(
+                "[Safety] {} 设置为 BLOCK_NONE：上游不会拦截该类别的任何内容",
+                setting.category
+            );
+        }
+    }
+    if let Ok(mut guard) = CONFIGURED.write() {
+        *guard = settings.as_deref().map(to_value);
+    }
+}
+
+fn to_value(settings: &[SafetySetting]) -> Value {
+    Value::Array(
+        settings
+            .iter()
+            .map(|s| json!({ "category": s.category, "threshold": s.threshold }))
+            .collect(),
+    )
+}
+
+fn configured() -> Option<Value> {
+    CONFIGURED.read().ok().and_then(|g| g.clone())
+}
+
+/// 当前生效的 safetySettings
+pub fn safety_settings() -> Value {
+    configured().unwrap_or_else(default_safety_settings)
+}
+
+/// Gemini 原生请求：已配置且客户端未提供 safetySettings 时补上配置值
+pub fn apply_configured_safety_settings(inner_request: &mut Value) {
+    if let Some(obj) = inner_request.as_object_mut() {
+        if obj.contains_key("safetySettings") {
+            return;
+        }
+        if let Some(settings) = configured() {
+            obj.insert("safetySettings".to_string(), settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(category: &str, threshold: &str) -> SafetySetting {
+        SafetySetting {
+            category: category.to_string(),
+            threshold: threshold.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_values() {
+        assert!(validate_safety_settings(&[setting("HARM_CATEGORY_HARASSMENT", "BLOCK_ONLY_HIGH")]).is_ok());
+        let err = validate_safety_settings(&[setting("HARM_CATEGORY_VIOLENCE", "OFF")]).unwrap_err();
+        assert!(err.contains("safety_settings[0]") && err.contains("HARM_CATEGORY_VIOLENCE"));
+        assert!(validate_safety_settings(&[setting("HARM_CATEGORY_HATE_SPEECH", "BLOCK_SOME")]).is_err());
+    }
+
+    #[test]
+    fn test_settings_passed_through_as_configured() {
+        let value = to_value(&[setting("HARM_CATEGORY_DANGEROUS_CONTENT", "BLOCK_NONE")]);
+        assert_eq!(
+            value,
+            json!([{ "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE" }])
+        );
+
+        // 未配置时：转换路径使用默认值，Gemini 原生请求保持原样
+        assert_eq!(safety_settings(), default_safety_settings());
+        let mut native = json!({"contents": []});
+        apply_configured_safety_settings(&mut native);
+        assert!(native.get("safetySettings").is_none());
+    }
+}
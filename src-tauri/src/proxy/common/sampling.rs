@@ -0,0 +1,316 @@
+// 采样参数约束 (temperature / topP / topK)
+// 部分 Thinking 模型会以 400 拒绝特定的采样参数组合，这里在发送前按模型统一修正
+// 客户端未指定时只使用模型家族自身的默认值；家族无默认值则完全省略，交给上游决定
+
+use serde_json::{json, Value};
+
+/// 响应头：记录本次请求中被修正的采样参数
+pub const SAMPLING_ADJUSTED_HEADER: &str = "x-sampling-adjusted";
+
+/// 单个模型的采样约束
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingConstraints {
+    /// temperature 允许的最小/最大值
+    pub temperature_range: (f64, f64),
+    /// 强制 temperature 为固定值 (Claude thinking 要求 temperature = 1)
+    pub fixed_temperature: Option<f64>,
+    /// topP 低于该值时直接移除 (而不是发送后被拒)
+    pub min_top_p: Option<f64>,
+    /// 是否必须移除 topK
+    pub strip_top_k: bool,
+    /// 客户端未指定时使用的 temperature (None 表示省略)
+    pub default_temperature: Option<f64>,
+    /// 客户端未指定时使用的 topP (None 表示省略)
+    pub default_top_p: Option<f64>,
+}
+
+impl Default for SamplingConstraints {
+    fn default() -> Self {
+        Self {
+            temperature_range: (0.0, 2.0),
+            fixed_temperature: None,
+            min_top_p: None,
+            strip_top_k: false,
+            default_temperature: None,
+            default_top_p: None,
+        }
+    }
+}
+
+/// 根据模型名与是否启用 thinking 获取采样约束
+pub fn get_sampling_constraints(model: &str, thinking_enabled: bool) -> SamplingConstraints {
+    let m = model.to_lowercase();
+    let is_thinking = thinking_enabled || m.contains("thinking");
+
+    if m.starts_with("claude-") {
+        if is_thinking {
+            // Anthropic 扩展思考：temperature 必须为 1，top_p 仅允许 [0.95, 1]，不支持 top_k
+            return SamplingConstraints {
+                temperature_range: (0.0, 1.0),
+                fixed_temperature: Some(1.0),
+                min_top_p: Some(0.95),
+                strip_top_k: true,
+                ..Default::default()
+            };
+        }
+        return SamplingConstraints {
+            temperature_range: (0.0, 1.0),
+            ..Default::default()
+        };
+    }
+
+    // 图像生成模型沿用官方推荐的采样默认值
+    if m.starts_with("gemini-") && m.contains("image") {
+        return SamplingConstraints {
+            default_temperature: Some(1.0),
+            default_top_p: Some(0.95),
+            ..Default::default()
+        };
+    }
+
+    SamplingConstraints::default()
+}
+
+/// 对上游请求体 (v1internal 包装或裸请求) 的 generationConfig 应用采样约束
+/// 返回修正说明列表 (为空表示未修改)
+pub fn apply_sampling_constraints(body: &mut Value, model: &str) -> Vec<String> {
+    let inner = if body.get("request").is_some() {
+        &mut body["request"]
+    } else {
+        body
+    };
+
+    let thinking_enabled = inner
+        .get("generationConfig")
+        .and_then(|c| c.get("thinkingConfig"))
+        .is_some();
+    let constraints = get_sampling_constraints(model, thinking_enabled);
+
+    let Some(config) = inner.get_mut("generationConfig").and_then(|c| c.as_object_mut()) else {
+        return Vec::new();
+    };
+
+    // 客户端未指定时补充模型家族默认值 (不计入修正说明)
+    if let Some(temp) = constraints.default_temperature {
+        config.entry("temperature").or_insert_with(|| json!(temp));
+    }
+    if let Some(top_p) = constraints.default_top_p {
+        config.entry("topP").or_insert_with(|| json!(top_p));
+    }
+
+    let mut adjustments = Vec::new();
+
+    if let Some(temp) = config.get("temperature").and_then(|v| v.as_f64()) {
+        let (lo, hi) = constraints.temperature_range;
+        let target = constraints.fixed_temperature.unwrap_or_else(|| temp.clamp(lo, hi));
+        if (target - temp).abs() > f64::EPSILON {
+            config.insert("temperature".to_string(), json!(target));
+            adjustments.push(format!("temperature={}->{}", temp, target));
+        }
+    }
+
+    if let Some(top_p) = config.get("topP").and_then(|v| v.as_f64()) {
+        if constraints.min_top_p.is_some_and(|min| top_p < min) {
+            config.remove("topP");
+            adjustments.push(format!("topP={} stripped", top_p));
+        } else if !(0.0..=1.0).contains(&top_p) {
+            let clamped = top_p.clamp(0.0, 1.0);
+            config.insert("topP".to_string(), json!(clamped));
+            adjustments.push(format!("topP={}->{}", top_p, clamped));
+        }
+    }
+
+    if constraints.strip_top_k {
+        if let Some(top_k) = config.remove("topK") {
+            adjustments.push(format!("topK={} stripped", top_k));
+        }
+    }
+
+    if !adjustments.is_empty() {
+        tracing::info!(
+            "[Sampling] Adjusted params for model {}: {}",
+            model,
+            adjustments.join("; ")
+        );
+    }
+
+    adjustments
+}
+
+/// 将修正说明记录为转换警告 (path 为被修正的 generationConfig 字段)
+pub fn record_sampling_warnings(adjustments: &[String]) {
+    for adjustment in adjustments {
+        let param = adjustment.split('=').next().unwrap_or_default();
+        crate::proxy::common::warnings::record_warning(
+            crate::proxy::common::warnings::codes::SAMPLING_ADJUSTED,
+            adjustment.clone(),
+            Some(format!("generationConfig.{}", param)),
+        );
+    }
+}
+
+/// 将采样参数修正记录写入响应头，便于客户端感知参数被调整
+pub fn attach_sampling_header(resp: &mut axum::response::Response, adjustments: &[String]) {
+    if adjustments.is_empty() {
+        return;
+    }
+    if let Ok(value) = axum::http::HeaderValue::from_str(&adjustments.join("; ")) {
+        resp.headers_mut().insert(SAMPLING_ADJUSTED_HEADER, value);
+    }
+}
+
+/// 判断上游 400 是否由采样参数引起
+/// 这类错误由请求本身决定，换账号重试没有意义
+pub fn is_sampling_param_error(status: u16, error_text: &str) -> bool {
+    if status != 400 {
+        return false;
+    }
+    let lower = error_text.to_lowercase();
+    ["temperature", "top_p", "topp", "top_k", "topk", "penalty"]
+        .iter()
+        .any(|kw| lower.contains(kw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn test_attach_sampling_header() {
+        let mut resp = "ok".into_response();
+        attach_sampling_header(&mut resp, &[]);
+        assert!(resp.headers().get(SAMPLING_ADJUSTED_HEADER).is_none());
+
+        let adjustments = vec!["temperature=1.8->1.0".to_string(), "topK removed".to_string()];
+        attach_sampling_header(&mut resp, &adjustments);
+        assert_eq!(resp.headers()[SAMPLING_ADJUSTED_HEADER], "temperature=1.8->1.0; topK removed");
+    }
+
+    #[test]
+    fn test_clamp_temperature() {
+        let mut body = json!({
+            "request": {"generationConfig": {"temperature": 1.8, "topP": 0.9}}
+        });
+        let adj = apply_sampling_constraints(&mut body, "claude-sonnet-4-5");
+        assert_eq!(adj.len(), 1);
+        assert_eq!(body["request"]["generationConfig"]["temperature"], 1.0);
+        assert_eq!(body["request"]["generationConfig"]["topP"], 0.9);
+
+        // Gemini 模型允许 2.0 以内的 temperature
+        let mut body = json!({"generationConfig": {"temperature": 1.8}});
+        assert!(apply_sampling_constraints(&mut body, "gemini-2.5-pro").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_strip_for_thinking_models() {
+        let mut body = json!({
+            "request": {"generationConfig": {
+                "temperature": 0.2,
+                "topP": 0.5,
+                "topK": 40,
+                "thinkingConfig": {"includeThoughts": true}
+            }}
+        });
+        let adj = apply_sampling_constraints(&mut body, "claude-opus-4-5-thinking");
+        assert_eq!(adj.len(), 3);
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["temperature"], 1.0);
+        assert!(config.get("topP").is_none());
+        assert!(config.get("topK").is_none());
+
+        let (_, warnings) =
+            crate::proxy::common::warnings::collect_warnings(async { record_sampling_warnings(&adj) }).await;
+        let paths: Vec<_> = warnings.iter().filter_map(|w| w.path.as_deref()).collect();
+        assert_eq!(paths, vec!["generationConfig.temperature", "generationConfig.topP", "generationConfig.topK"]);
+        assert!(warnings.iter().all(|w| w.code == crate::proxy::common::warnings::codes::SAMPLING_ADJUSTED));
+    }
+
+    fn openai_config(body: Value, model: &str) -> Value {
+        let req: crate::proxy::mappers::openai::OpenAIRequest = serde_json::from_value(body).unwrap();
+        let mut gemini_body =
+            crate::proxy::mappers::openai::transform_openai_request(&req, "test-project", model);
+        apply_sampling_constraints(&mut gemini_body, model);
+        gemini_body["request"]["generationConfig"].clone()
+    }
+
+    fn claude_config(body: Value) -> Value {
+        let req: crate::proxy::mappers::claude::ClaudeRequest = serde_json::from_value(body).unwrap();
+        let model = req.model.clone();
+        let mut gemini_body =
+            crate::proxy::mappers::claude::transform_claude_request_in(&req, "test-project").unwrap();
+        apply_sampling_constraints(&mut gemini_body, &model);
+        gemini_body["request"]["generationConfig"].clone()
+    }
+
+    #[test]
+    fn test_generation_config_sampling_defaults_openai() {
+        let messages = json!([{"role": "user", "content": "hi"}]);
+
+        // 客户端指定
+        let config = openai_config(
+            json!({"model": "gpt-4o", "messages": messages, "temperature": 0.5, "top_p": 0.75}),
+            "gemini-2.5-flash",
+        );
+        assert_eq!(config["temperature"], 0.5);
+        assert_eq!(config["topP"], 0.75);
+
+        // 模型家族默认值
+        let config = openai_config(
+            json!({"model": "gpt-4o", "messages": messages}),
+            "gemini-3-pro-image",
+        );
+        assert_eq!(config["temperature"], 1.0);
+        assert_eq!(config["topP"], 0.95);
+
+        // 均未指定：完全省略
+        let config = openai_config(
+            json!({"model": "gpt-4o", "messages": messages}),
+            "gemini-2.5-flash-thinking",
+        );
+        assert!(config.get("temperature").is_none());
+        assert!(config.get("topP").is_none());
+    }
+
+    #[test]
+    fn test_generation_config_sampling_defaults_claude() {
+        let base = |model: &str| {
+            json!({
+                "model": model,
+                "max_tokens": 1024,
+                "messages": [{"role": "user", "content": "hi"}]
+            })
+        };
+
+        // 客户端指定
+        let mut body = base("gemini-2.5-flash");
+        body["temperature"] = json!(0.5);
+        body["top_p"] = json!(0.75);
+        let config = claude_config(body);
+        assert_eq!(config["temperature"], 0.5);
+        assert_eq!(config["topP"], 0.75);
+
+        // 模型家族默认值
+        let config = claude_config(base("gemini-3-pro-image"));
+        assert_eq!(config["temperature"], 1.0);
+        assert_eq!(config["topP"], 0.95);
+
+        // thinking 请求未指定采样参数：完全省略
+        let mut body = base("claude-sonnet-4-5-thinking");
+        body["thinking"] = json!({"type": "enabled", "budget_tokens": 4096});
+        let config = claude_config(body);
+        assert!(config.get("thinkingConfig").is_some());
+        assert!(config.get("temperature").is_none());
+        assert!(config.get("topP").is_none());
+    }
+
+    #[test]
+    fn test_sampling_error_is_not_account_shaped() {
+        assert!(is_sampling_param_error(
+            400,
+            r#"{"error":{"message":"temperature and top_p cannot both be specified"}}"#
+        ));
+        assert!(!is_sampling_param_error(400, "Invalid `signature` in thinking block"));
+        assert!(!is_sampling_param_error(429, "temperature"));
+    }
+}
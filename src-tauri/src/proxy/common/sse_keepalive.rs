@@ -0,0 +1,98 @@
+// SSE 保活注释
+// 思考模型的首个 Token 可能在 60 秒以后才到达，超时设置激进的 SSE 客户端会在此期间断开连接。
+// 流建立后，连续 sse_keepalive_secs 秒没有任何输出时插入注释行 `: keepalive` (SSE 规范要求客户端忽略注释)；
+// 每次输出数据都会重新计时，设置为 0 时关闭
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::proxy::config::default_sse_keepalive_secs;
+
+/// 保活注释 (单独成块，不会插入到事件中间)
+pub const KEEPALIVE_COMMENT: &[u8] = b": keepalive\n\n";
+
+static KEEPALIVE_SECS: AtomicU64 = AtomicU64::new(default_sse_keepalive_secs());
+
+/// 应用保活间隔 (代理启动与配置热更新时调用)
+pub fn configure_sse_keepalive(secs: u64) {
+    KEEPALIVE_SECS.store(secs, Ordering::Relaxed);
+}
+
+fn keepalive_interval() -> Option<Duration> {
+    match KEEPALIVE_SECS.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// 按当前配置为 SSE 字节流加上保活注释
+pub fn with_keepalive<S>(stream: S) -> impl Stream<Item = Result<Bytes, String>> + Send
+where
+    S: Stream<Item = Result<Bytes, String>> + Send + 'static,
+{
+    with_keepalive_every(stream, keepalive_interval())
+}
+
+fn with_keepalive_every<S>(stream: S, every: Option<Duration>) -> impl Stream<Item = Result<Bytes, String>> + Send
+where
+    S: Stream<Item = Result<Bytes, String>> + Send + 'static,
+{
+    async_stream::stream! {
+        let mut stream = Box::pin(stream);
+        if let Some(every) = every {
+            let mut ticker = tokio::time::interval_at(Instant::now() + every, every);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    item = stream.next() => match item {
+                        Some(item) => {
+                            ticker.reset();
+                            yield item;
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => yield Ok(Bytes::from_static(KEEPALIVE_COMMENT)),
+                }
+            }
+        } else {
+            while let Some(item) = stream.next().await {
+                yield item;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_comment_emitted_only_while_idle() {
+        let upstream = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            yield Ok::<_, String>(Bytes::from("data: first\n\n"));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            yield Ok(Bytes::from("data: second\n\n"));
+        };
+        let chunks: Vec<_> = with_keepalive_every(upstream, Some(Duration::from_millis(50)))
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+        // 首个数据到达前至少一次保活，数据输出后重新计时
+        let first = chunks.iter().position(|c| c == "data: first\n\n").unwrap();
+        assert!(first >= 1 && chunks[..first].iter().all(|c| c == ": keepalive\n\n"));
+        assert_eq!(chunks[first + 1..], ["data: second\n\n".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_passes_through() {
+        let upstream = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            yield Ok::<_, String>(Bytes::from("data: x\n\n"));
+        };
+        let chunks: Vec<_> = with_keepalive_every(upstream, None).collect().await;
+        assert_eq!(chunks.len(), 1);
+    }
+}
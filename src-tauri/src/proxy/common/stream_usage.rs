@@ -0,0 +1,99 @@
+// 流式响应中的周期性用量事件
+// 长时间运行的 Agent 会话需要实时查看消耗，而不是等到最后的 usage：
+// - OpenAI：插入 delta 为空、携带 usage 的 chunk (仅 extended 兼容模式，strict 客户端不会收到)
+// - Anthropic：额外的 message_delta 事件，usage.output_tokens 为累计值
+// 计数直接取自各转换流程自身的用量统计 (与结尾的 usage 同源)，因此最后一次周期值不会超过最终值
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::StreamUsageConfig;
+
+static CONFIG: Lazy<RwLock<StreamUsageConfig>> = Lazy::new(|| RwLock::new(StreamUsageConfig::default()));
+
+/// 应用周期性用量配置 (代理启动与配置热更新时调用)
+pub fn configure_stream_usage(config: &StreamUsageConfig) {
+    if let Ok(mut guard) = CONFIG.write() {
+        *guard = config.clone();
+    }
+}
+
+/// 单个流的周期性用量触发器
+#[derive(Debug, Clone)]
+pub struct UsageTicker {
+    interval: Option<Duration>,
+    every_output_tokens: u64,
+    last_emit_at: Instant,
+    last_output_tokens: u64,
+}
+
+impl UsageTicker {
+    /// 按当前配置创建 (未启用或未设置任何触发条件时返回 None)
+    pub fn from_config(now: Instant) -> Option<Self> {
+        let config = CONFIG.read().map(|c| c.clone()).unwrap_or_default();
+        Self::new(&config, now)
+    }
+
+    pub fn new(config: &StreamUsageConfig, now: Instant) -> Option<Self> {
+        if !config.enabled || (config.interval_secs == 0 && config.every_output_tokens == 0) {
+            return None;
+        }
+        Some(Self {
+            interval: (config.interval_secs > 0).then(|| Duration::from_secs(config.interval_secs)),
+            every_output_tokens: config.every_output_tokens,
+            last_emit_at: now,
+            last_output_tokens: 0,
+        })
+    }
+
+    /// 输出 Token 累计值有增长且达到时间或数量阈值时返回 true，并记为已输出
+    pub fn should_emit(&mut self, output_tokens: u64, now: Instant) -> bool {
+        if output_tokens <= self.last_output_tokens {
+            return false;
+        }
+        let by_time = self
+            .interval
+            .is_some_and(|interval| now.duration_since(self.last_emit_at) >= interval);
+        let by_tokens = self.every_output_tokens > 0
+            && output_tokens - self.last_output_tokens >= self.every_output_tokens;
+        if !(by_time || by_tokens) {
+            return false;
+        }
+        self.last_emit_at = now;
+        self.last_output_tokens = output_tokens;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(interval_secs: u64, every_output_tokens: u64) -> StreamUsageConfig {
+        StreamUsageConfig {
+            enabled: true,
+            interval_secs,
+            every_output_tokens,
+        }
+    }
+
+    #[test]
+    fn test_ticker_triggers_by_tokens_or_time() {
+        let start = Instant::now();
+        assert!(UsageTicker::new(&StreamUsageConfig::default(), start).is_none());
+        assert!(UsageTicker::new(&config(0, 0), start).is_none());
+
+        let mut by_tokens = UsageTicker::new(&config(0, 10), start).unwrap();
+        assert!(!by_tokens.should_emit(9, start));
+        assert!(by_tokens.should_emit(12, start));
+        assert!(!by_tokens.should_emit(21, start));
+        assert!(by_tokens.should_emit(22, start));
+
+        let mut by_time = UsageTicker::new(&config(5, 0), start).unwrap();
+        assert!(!by_time.should_emit(3, start + Duration::from_secs(4)));
+        assert!(by_time.should_emit(3, start + Duration::from_secs(5)));
+        // 计数未增长时不重复输出
+        assert!(!by_time.should_emit(3, start + Duration::from_secs(20)));
+        assert!(by_time.should_emit(4, start + Duration::from_secs(20)));
+    }
+}
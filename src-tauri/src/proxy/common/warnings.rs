@@ -0,0 +1,133 @@
+// 非致命转换决策的结构化警告
+// 转换请求 / 响应时被静默修正的内容 (忽略参数、截断预算、清理工具 Schema、图片回退等)
+// 统一记录为 warnings (code, message, path)：
+// - 始终写入监控日志记录 (ProxyRequestLog.warnings)
+// - extended 兼容模式下附加到响应：非流式为顶层 `warnings` 数组，流式为结束前的 `warnings` 事件
+// - strict 模式 (默认) 不修改响应体
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::config::CompatMode;
+
+// 警告代码与结构定义位于 antigravity-protocol 库，转换函数写入 Warnings 后由 record_all 汇入当前请求
+pub use antigravity_protocol::warnings::{codes, ConversionWarning, Warnings};
+
+/// 请求级警告列表 (由 warnings_middleware 写入响应 extensions，供监控日志读取)
+#[derive(Debug, Clone, Default)]
+pub struct ConversionWarnings(pub Vec<ConversionWarning>);
+
+type WarningSlot = Arc<Mutex<Vec<ConversionWarning>>>;
+
+tokio::task_local! {
+    static WARNINGS: WarningSlot;
+}
+
+static EXTENDED_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 应用兼容模式 (代理启动与配置热更新时调用)
+pub fn configure_compat_mode(mode: &CompatMode) {
+    EXTENDED_MODE.store(matches!(mode, CompatMode::Extended), Ordering::Relaxed);
+}
+
+/// 是否为 extended 兼容模式 (允许输出协议之外的扩展字段 / 事件)
+pub fn extended_mode() -> bool {
+    EXTENDED_MODE.load(Ordering::Relaxed)
+}
+
+/// 是否在响应体中附加 warnings
+pub fn warnings_in_body() -> bool {
+    extended_mode()
+}
+
+/// 记录一条警告并输出日志 (不在请求上下文中时只输出日志)
+/// 重试 / 账号轮换会重复转换同一请求，相同的警告只保留一条
+pub fn record_warning(code: &str, message: impl Into<String>, path: Option<String>) {
+    let warning = ConversionWarning {
+        code: code.to_string(),
+        message: message.into(),
+        path,
+    };
+    tracing::warn!(
+        "[Warnings] {}{}: {}",
+        warning.code,
+        warning.path.as_deref().map(|p| format!(" @ {}", p)).unwrap_or_default(),
+        warning.message
+    );
+    let _ = WARNINGS.try_with(|slot| {
+        let mut warnings = slot.lock().unwrap();
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    });
+}
+
+/// 记录库转换函数收集的全部警告
+pub fn record_all(warnings: Warnings) {
+    for warning in warnings.into_vec() {
+        record_warning(&warning.code, warning.message, warning.path);
+    }
+}
+
+/// 在独立的警告上下文中执行 future，返回其结果与期间记录的警告
+pub async fn collect_warnings<F: std::future::Future>(fut: F) -> (F::Output, Vec<ConversionWarning>) {
+    let slot = WarningSlot::default();
+    let output = WARNINGS.scope(slot.clone(), fut).await;
+    let warnings = std::mem::take(&mut *slot.lock().unwrap());
+    (output, warnings)
+}
+
+/// 非流式响应：在 JSON 对象顶层插入 warnings 数组
+pub fn attach_to_json(body: &mut Value, warnings: &[ConversionWarning]) {
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("warnings".to_string(), json!(warnings));
+    }
+}
+
+/// 流式响应：结束前追加的 SSE 扩展事件
+pub fn sse_event(warnings: &[ConversionWarning]) -> String {
+    format!(
+        "event: warnings\ndata: {}\n\n",
+        json!({ "type": "warnings", "warnings": warnings })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn collects_and_dedupes_within_request() {
+        let ((), warnings) = collect_warnings(async {
+            record_warning(codes::UNSUPPORTED_PARAM, "ignored", Some("thinking.type".to_string()));
+            record_warning(codes::UNSUPPORTED_PARAM, "ignored", Some("thinking.type".to_string()));
+            tokio::task::yield_now().await;
+            record_warning(codes::SAMPLING_ADJUSTED, "topK=40 stripped", None);
+        })
+        .await;
+        let codes: Vec<_> = warnings.iter().map(|w| w.code.as_str()).collect();
+        assert_eq!(codes, vec![codes::UNSUPPORTED_PARAM, codes::SAMPLING_ADJUSTED]);
+
+        // 请求上下文之外只记录日志
+        record_warning(codes::UNSUPPORTED_PARAM, "outside", None);
+    }
+
+    #[test]
+    fn renders_body_and_stream_forms() {
+        let warnings = vec![ConversionWarning {
+            code: codes::THINKING_BUDGET_CLAMPED.to_string(),
+            message: "clamped".to_string(),
+            path: Some("thinking.budget_tokens".to_string()),
+        }];
+        let mut body = json!({"id": "msg_1"});
+        attach_to_json(&mut body, &warnings);
+        assert_eq!(body["warnings"][0]["code"], "thinking_budget_clamped");
+        assert_eq!(body["warnings"][0]["path"], "thinking.budget_tokens");
+
+        let event = sse_event(&warnings);
+        assert!(event.starts_with("event: warnings\ndata: "));
+        let data: Value = serde_json::from_str(event.lines().nth(1).unwrap().trim_start_matches("data: ")).unwrap();
+        assert_eq!(data["type"], "warnings");
+        assert_eq!(data["warnings"][0]["message"], "clamped");
+    }
+}
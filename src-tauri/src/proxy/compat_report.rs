@@ -0,0 +1,955 @@
+// 兼容性诊断报告 (POST /debug/compat-report)
+// 对客户端粘贴的原始请求体做：反序列化、逐字段与内容类型的支持度分析、模型路由解析、发送前预检，
+// 全程不访问上游，也不占用账号。
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::proxy::common::model_routes::{self, RequestTraits, RouteDecision};
+use crate::proxy::common::{model_mapping, prompt_size, sampling};
+use crate::proxy::config::ModelRouteRule;
+use crate::proxy::mappers::{claude, common_utils, gemini, openai};
+
+/// 报告中使用的占位 project_id (不会发送到上游)
+const REPORT_PROJECT_ID: &str = "compat-report";
+
+/// 字段 / 内容类型的支持程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    /// 原样透传或语义一致
+    Supported,
+    /// 转换为 Gemini 的等价字段
+    Mapped,
+    /// 被接受但不产生任何效果
+    Dropped,
+    /// 请求了代理无法提供的能力
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldReport {
+    pub field: String,
+    pub status: Support,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentReport {
+    /// 例如 "role:system"、"part:image_url"、"block:tool_use"
+    pub kind: String,
+    pub count: usize,
+    pub status: Support,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeserializationReport {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingReport {
+    pub requested_model: String,
+    pub mapped_model: String,
+    pub final_model: String,
+    pub request_type: String,
+    pub inject_google_search: bool,
+    pub claude_family_mapping: bool,
+    /// 条件路由命中的变体与谓词 (requested_model 未配置条件路由或无变体匹配时为空)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conditional_route: Option<RouteDecision>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightReport {
+    pub ok: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub sampling_adjustments: Vec<String>,
+    pub estimated_prompt_tokens: u64,
+    pub input_limit: u64,
+    /// 转换后的 generationConfig (不含消息内容)
+    pub generation_config: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompatReport {
+    pub route: String,
+    pub protocol: &'static str,
+    pub deserialization: DeserializationReport,
+    pub fields: Vec<FieldReport>,
+    pub content: Vec<ContentReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing: Option<RoutingReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<PreflightReport>,
+}
+
+/// 路由解析所需的模型映射表
+pub struct ModelMappings<'a> {
+    pub custom: &'a HashMap<String, String>,
+    pub openai: &'a HashMap<String, String>,
+    pub anthropic: &'a HashMap<String, String>,
+    pub routes: &'a [ModelRouteRule],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Protocol {
+    OpenAIChat,
+    OpenAICompletions,
+    Claude,
+    Gemini(String),
+}
+
+impl Protocol {
+    fn from_route(route: &str) -> Result<Self, String> {
+        let path = route.split('?').next().unwrap_or(route).trim_end_matches('/');
+        match path {
+            "/v1/chat/completions" => Ok(Protocol::OpenAIChat),
+            "/v1/completions" | "/v1/responses" => Ok(Protocol::OpenAICompletions),
+            "/v1/messages" => Ok(Protocol::Claude),
+            _ => {
+                let model = path
+                    .strip_prefix("/v1beta/models/")
+                    .and_then(|rest| rest.split_once(':'))
+                    .filter(|(_, method)| {
+                        *method == "generateContent" || *method == "streamGenerateContent"
+                    })
+                    .map(|(model, _)| model.to_string());
+                model.map(Protocol::Gemini).ok_or_else(|| {
+                    format!(
+                        "Unsupported route for compat report: {} (expected /v1/chat/completions, /v1/completions, /v1/responses, /v1/messages or /v1beta/models/{{model}}:generateContent)",
+                        route
+                    )
+                })
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Protocol::OpenAIChat => "openai_chat",
+            Protocol::OpenAICompletions => "openai_completions",
+            Protocol::Claude => "anthropic_messages",
+            Protocol::Gemini(_) => "gemini",
+        }
+    }
+}
+
+type FieldRule = (&'static str, Support, &'static str);
+
+const OPENAI_FIELDS: &[FieldRule] = &[
+    ("model", Support::Supported, "resolved through model mapping"),
+    ("messages", Support::Mapped, "converted to Gemini contents; system messages become systemInstruction"),
+    ("stream", Support::Supported, ""),
+    ("stream_options", Support::Supported, "include_usage is honoured"),
+    ("max_tokens", Support::Mapped, "-> generationConfig.maxOutputTokens"),
+    ("temperature", Support::Supported, "clamped to the model family's allowed range"),
+    ("top_p", Support::Mapped, "-> generationConfig.topP"),
+    ("stop", Support::Mapped, "-> generationConfig.stopSequences"),
+    ("response_format", Support::Mapped, "-> responseMimeType / responseSchema"),
+    ("tools", Support::Mapped, "-> functionDeclarations (schemas are cleaned)"),
+    ("tool_choice", Support::Mapped, "-> toolConfig.functionCallingConfig"),
+    ("parallel_tool_calls", Support::Dropped, "Gemini decides parallel calls on its own"),
+    ("max_completion_tokens", Support::Dropped, "not read; use max_tokens to cap output"),
+    ("n", Support::Mapped, "-> generationConfig.candidateCount (clamped to 1-4; rejected for image and thinking models)"),
+    ("top_k", Support::Mapped, "-> generationConfig.topK (non-standard)"),
+    ("presence_penalty", Support::Mapped, "-> generationConfig.presencePenalty (clamped to -2.0..2.0)"),
+    ("frequency_penalty", Support::Mapped, "-> generationConfig.frequencyPenalty (clamped to -2.0..2.0)"),
+    ("logit_bias", Support::Unsupported, "token biasing is not available"),
+    ("logprobs", Support::Unsupported, "log probabilities are not returned"),
+    ("top_logprobs", Support::Unsupported, "log probabilities are not returned"),
+    ("seed", Support::Dropped, "sampling seed is not forwarded"),
+    ("user", Support::Dropped, "end-user id is not forwarded"),
+    ("store", Support::Dropped, "stored completions are not supported"),
+    ("metadata", Support::Dropped, "not forwarded"),
+    ("service_tier", Support::Dropped, "not forwarded"),
+    ("reasoning_effort", Support::Dropped, "thinking depth follows the mapped model"),
+    ("modalities", Support::Unsupported, "audio output is not available; images use /v1/images/generations"),
+    ("audio", Support::Unsupported, "audio output is not available"),
+    ("prediction", Support::Dropped, "predicted outputs are not supported"),
+    ("web_search_options", Support::Dropped, "use a -online model or a web_search tool instead"),
+];
+
+const OPENAI_COMPLETIONS_FIELDS: &[FieldRule] = &[
+    ("prompt", Support::Mapped, "converted to a user message"),
+    ("input", Support::Mapped, "Responses/Codex input items converted to messages"),
+    ("instructions", Support::Mapped, "converted to a system message"),
+];
+
+const CLAUDE_FIELDS: &[FieldRule] = &[
+    ("model", Support::Supported, "resolved through model mapping (Claude family mapping for CLI traffic)"),
+    ("messages", Support::Mapped, "converted to Gemini contents"),
+    ("system", Support::Mapped, "-> systemInstruction"),
+    ("tools", Support::Mapped, "-> functionDeclarations; web_search becomes googleSearch"),
+    ("stream", Support::Supported, ""),
+    ("max_tokens", Support::Dropped, "upstream maxOutputTokens is fixed; used only for the pre-flight size check"),
+    ("temperature", Support::Supported, "clamped / fixed per model family"),
+    ("top_p", Support::Mapped, "-> generationConfig.topP"),
+    ("top_k", Support::Mapped, "-> generationConfig.topK"),
+    ("thinking", Support::Mapped, "-> generationConfig.thinkingConfig"),
+    ("metadata", Support::Mapped, "metadata.user_id is reused as the upstream sessionId"),
+    ("stop_sequences", Support::Mapped, "-> generationConfig.stopSequences, merged with the proxy defaults (max 5)"),
+    ("tool_choice", Support::Dropped, "Gemini chooses tools automatically"),
+    ("service_tier", Support::Dropped, "not forwarded"),
+    ("container", Support::Unsupported, "code execution containers are not available"),
+    ("mcp_servers", Support::Unsupported, "remote MCP connectors are not available"),
+];
+
+const GEMINI_FIELDS: &[FieldRule] = &[
+    ("contents", Support::Supported, ""),
+    ("systemInstruction", Support::Supported, ""),
+    ("generationConfig", Support::Supported, ""),
+    ("tools", Support::Supported, "function schemas are cleaned; googleSearch injected for -online models"),
+    ("toolConfig", Support::Supported, ""),
+    ("safetySettings", Support::Supported, ""),
+    ("model", Support::Dropped, "the model is taken from the route"),
+    ("cachedContent", Support::Unsupported, "context caching is not available through v1internal"),
+    ("labels", Support::Dropped, "not forwarded"),
+];
+
+/// 对请求体的顶层字段逐一给出支持度；未知字段视为被忽略
+fn analyze_fields(body: &Value, rules: &[&[FieldRule]]) -> Vec<FieldReport> {
+    let Some(obj) = body.as_object() else {
+        return Vec::new();
+    };
+    obj.keys()
+        .map(|key| {
+            let rule = rules.iter().flat_map(|r| r.iter()).find(|(name, _, _)| name == key);
+            match rule {
+                Some((_, status, note)) => FieldReport {
+                    field: key.clone(),
+                    status: *status,
+                    note: note.to_string(),
+                },
+                None => FieldReport {
+                    field: key.clone(),
+                    status: Support::Dropped,
+                    note: "unknown field, ignored".to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+fn unknown_fields(body: &Value, rules: &[&[FieldRule]]) -> Vec<String> {
+    analyze_fields(body, rules)
+        .into_iter()
+        .filter(|f| !rules.iter().flat_map(|r| r.iter()).any(|(name, _, _)| *name == f.field))
+        .map(|f| f.field)
+        .collect()
+}
+
+/// 字段表中未列出的顶层字段 (Anthropic Messages)
+pub(crate) fn unknown_claude_fields(body: &Value) -> Vec<String> {
+    unknown_fields(body, &[CLAUDE_FIELDS])
+}
+
+/// 字段表中未列出的顶层字段 (OpenAI Chat，含 Codex / Legacy 字段)
+pub(crate) fn unknown_openai_fields(body: &Value) -> Vec<String> {
+    unknown_fields(body, &[OPENAI_FIELDS, OPENAI_COMPLETIONS_FIELDS])
+}
+
+/// 按 kind 聚合内容统计
+#[derive(Default)]
+struct ContentTally(BTreeMap<String, (usize, Support, String)>);
+
+impl ContentTally {
+    fn add(&mut self, kind: String, status: Support, note: &str) {
+        self.0
+            .entry(kind)
+            .or_insert_with(|| (0, status, note.to_string()))
+            .0 += 1;
+    }
+
+    fn into_reports(self) -> Vec<ContentReport> {
+        self.0
+            .into_iter()
+            .map(|(kind, (count, status, note))| ContentReport { kind, count, status, note })
+            .collect()
+    }
+}
+
+fn analyze_openai_content(body: &Value) -> Vec<ContentReport> {
+    let mut tally = ContentTally::default();
+    let messages = body.get("messages").and_then(|m| m.as_array());
+    for msg in messages.into_iter().flatten() {
+        let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("");
+        let (status, note) = match role {
+            "user" => (Support::Supported, ""),
+            "assistant" => (Support::Mapped, "-> model"),
+            "system" => (Support::Mapped, "-> systemInstruction"),
+            "tool" | "function" => (Support::Mapped, "-> functionResponse"),
+            "developer" => (Support::Unsupported, "forwarded as-is and rejected upstream; use role 'system'"),
+            _ => (Support::Unsupported, "unknown role"),
+        };
+        tally.add(format!("role:{}", role), status, note);
+
+        if msg.get("tool_calls").and_then(|t| t.as_array()).is_some_and(|t| !t.is_empty()) {
+            tally.add("tool_calls".to_string(), Support::Mapped, "-> functionCall parts");
+        }
+
+        let parts = msg.get("content").and_then(|c| c.as_array());
+        for part in parts.into_iter().flatten() {
+            let part_type = part.get("type").and_then(|t| t.as_str()).unwrap_or("");
+            let (status, note) = match part_type {
+                "text" => (Support::Supported, ""),
+                "image_url" => {
+                    let url = part
+                        .get("image_url")
+                        .and_then(|i| i.get("url"))
+                        .and_then(|u| u.as_str())
+                        .unwrap_or("");
+                    if url.starts_with("data:") {
+                        (Support::Mapped, "data URL -> inlineData")
+                    } else if url.starts_with("http") {
+                        (Support::Mapped, "remote URL -> fileData")
+                    } else {
+                        (Support::Mapped, "local path read by the proxy -> inlineData")
+                    }
+                }
+                _ => (Support::Unsupported, "content part type is not supported and is ignored (warning recorded)"),
+            };
+            tally.add(format!("part:{}", part_type), status, note);
+        }
+    }
+    tally.into_reports()
+}
+
+fn analyze_claude_content(body: &Value) -> Vec<ContentReport> {
+    let mut tally = ContentTally::default();
+
+    let system_blocks = body.get("system").and_then(|s| s.as_array());
+    for block in system_blocks.into_iter().flatten() {
+        if block.get("cache_control").is_some() {
+            tally.add("cache_control".to_string(), Support::Dropped, "prompt caching hints are ignored");
+        }
+    }
+
+    let messages = body.get("messages").and_then(|m| m.as_array());
+    for msg in messages.into_iter().flatten() {
+        let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("");
+        let (status, note) = match role {
+            "user" => (Support::Supported, ""),
+            "assistant" => (Support::Mapped, "-> model"),
+            _ => (Support::Unsupported, "unknown role"),
+        };
+        tally.add(format!("role:{}", role), status, note);
+
+        let blocks = msg.get("content").and_then(|c| c.as_array());
+        for block in blocks.into_iter().flatten() {
+            let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+            let source_type = block
+                .get("source")
+                .and_then(|s| s.get("type"))
+                .and_then(|t| t.as_str())
+                .unwrap_or("");
+            let (status, note) = match (block_type, source_type) {
+                ("text", _) => (Support::Supported, ""),
+                ("image", "base64") => (Support::Mapped, "-> inlineData"),
+                ("image", "url") => (Support::Mapped, "-> fileData"),
+                ("document", "base64") => (Support::Mapped, "-> inlineData"),
+                ("document", "url") => (Support::Mapped, "-> fileData (application/pdf)"),
+                ("document", "text") => (Support::Mapped, "-> text part"),
+                ("image", _) | ("document", _) => (Support::Unsupported, "source type is ignored"),
+                ("thinking", _) => (Support::Mapped, "-> thought part; signature kept as thoughtSignature"),
+                ("redacted_thinking", _) => (Support::Mapped, "-> placeholder thought text"),
+                ("tool_use", _) => (Support::Mapped, "-> functionCall"),
+                ("tool_result", _) => (Support::Mapped, "-> functionResponse"),
+                ("server_tool_use", _) | ("web_search_tool_result", _) => {
+                    (Support::Dropped, "server tool blocks are not sent back upstream")
+                }
+                _ => (Support::Unsupported, "content block type is not supported and is ignored (warning recorded)"),
+            };
+            tally.add(format!("block:{}", block_type), status, note);
+            if block.get("cache_control").is_some() {
+                tally.add("cache_control".to_string(), Support::Dropped, "prompt caching hints are ignored");
+            }
+        }
+    }
+    tally.into_reports()
+}
+
+fn analyze_gemini_content(body: &Value) -> Vec<ContentReport> {
+    let mut tally = ContentTally::default();
+    let contents = body.get("contents").and_then(|c| c.as_array());
+    for content in contents.into_iter().flatten() {
+        let role = content.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+        tally.add(format!("role:{}", role), Support::Supported, "");
+        let parts = content.get("parts").and_then(|p| p.as_array());
+        for part in parts.into_iter().flatten().filter_map(|p| p.as_object()) {
+            for key in part.keys().filter(|k| *k != "thoughtSignature" && *k != "thought") {
+                tally.add(format!("part:{}", key), Support::Supported, "passed through");
+            }
+        }
+    }
+    tally.into_reports()
+}
+
+fn generation_config_of(body: &Value) -> Value {
+    body.get("request")
+        .and_then(|r| r.get("generationConfig"))
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+fn preflight(
+    mut upstream_body: Value,
+    model: &str,
+    max_output_tokens: Option<u32>,
+    apply_sampling: bool,
+    mut errors: Vec<String>,
+    warnings: Vec<String>,
+) -> PreflightReport {
+    let sampling_adjustments = if apply_sampling {
+        sampling::apply_sampling_constraints(&mut upstream_body, model)
+    } else {
+        Vec::new()
+    };
+    let size = prompt_size::check_prompt_size(&upstream_body, model, max_output_tokens);
+    if !size.is_allowed() {
+        errors.push(size.rejection_message(model));
+    }
+    PreflightReport {
+        ok: errors.is_empty(),
+        errors,
+        warnings,
+        sampling_adjustments,
+        estimated_prompt_tokens: size.estimated_tokens,
+        input_limit: size.input_limit,
+        generation_config: generation_config_of(&upstream_body),
+    }
+}
+
+fn routing_for(
+    requested_model: &str,
+    mappings: &ModelMappings,
+    tools: &Option<Vec<Value>>,
+    allow_family_mapping: bool,
+    traits: RequestTraits,
+) -> RoutingReport {
+    // 与 handler 一致：条件路由选中的变体模型再参与普通模型映射
+    let conditional_route = model_routes::select_variant(mappings.routes, requested_model, &traits);
+    let route_model = conditional_route.as_ref().map_or(requested_model, |d| d.model.as_str());
+    let resolve = |family: bool| {
+        model_mapping::resolve_model_route(
+            route_model,
+            mappings.custom,
+            mappings.openai,
+            mappings.anthropic,
+            family,
+        )
+    };
+    let initial = resolve(false);
+    let config = common_utils::resolve_request_config(requested_model, &initial, tools);
+    // 与 Claude handler 一致：仅 CLI (agent) 请求应用家族映射
+    let family = allow_family_mapping && config.request_type == "agent";
+    let mapped_model = if family { resolve(true) } else { initial };
+    let config = common_utils::resolve_request_config(requested_model, &mapped_model, tools);
+    RoutingReport {
+        requested_model: requested_model.to_string(),
+        mapped_model,
+        final_model: config.final_model,
+        request_type: config.request_type,
+        inject_google_search: config.inject_google_search,
+        claude_family_mapping: family,
+        conditional_route,
+    }
+}
+
+fn report_openai(
+    route: &str,
+    protocol: Protocol,
+    body: &Value,
+    mappings: &ModelMappings,
+) -> CompatReport {
+    let rules: Vec<&[FieldRule]> = if protocol == Protocol::OpenAICompletions {
+        vec![OPENAI_FIELDS, OPENAI_COMPLETIONS_FIELDS]
+    } else {
+        vec![OPENAI_FIELDS]
+    };
+    let mut report = CompatReport {
+        route: route.to_string(),
+        protocol: protocol.name(),
+        deserialization: DeserializationReport { ok: true, error: None },
+        fields: analyze_fields(body, &rules),
+        content: analyze_openai_content(body),
+        routing: None,
+        preflight: None,
+    };
+
+    let req: openai::OpenAIRequest = match serde_json::from_value(body.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            report.deserialization = DeserializationReport {
+                ok: false,
+                error: Some(format!("Invalid request: {}", e)),
+            };
+            return report;
+        }
+    };
+
+    let traits = RequestTraits::from_client_body(body, req.stream);
+    let routing = routing_for(&req.model, mappings, &req.tools, false, traits);
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    if let Some(fmt) = &req.response_format {
+        if let Err(e) = fmt.gemini_schema() {
+            errors.push(e);
+        }
+    }
+    if let Err(e) = openai::validate_candidate_count(&req, &routing.final_model) {
+        errors.push(e);
+    }
+    if req.messages.is_empty() {
+        if protocol == Protocol::OpenAICompletions {
+            warnings.push("prompt/input are converted to messages by the handler; size check uses an empty conversation".to_string());
+        } else {
+            warnings.push("messages is empty; the proxy injects a fallback user message".to_string());
+        }
+    }
+
+    let upstream_body = openai::transform_openai_request(&req, REPORT_PROJECT_ID, &routing.mapped_model);
+    report.preflight = Some(preflight(
+        upstream_body,
+        &routing.mapped_model,
+        req.max_tokens,
+        true,
+        errors,
+        warnings,
+    ));
+    report.routing = Some(routing);
+    report
+}
+
+fn report_claude(route: &str, body: &Value, mappings: &ModelMappings) -> CompatReport {
+    let mut report = CompatReport {
+        route: route.to_string(),
+        protocol: Protocol::Claude.name(),
+        deserialization: DeserializationReport { ok: true, error: None },
+        fields: analyze_fields(body, &[CLAUDE_FIELDS]),
+        content: analyze_claude_content(body),
+        routing: None,
+        preflight: None,
+    };
+
+    let mut req: claude::ClaudeRequest = match serde_json::from_value(body.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            report.deserialization = DeserializationReport {
+                ok: false,
+                error: Some(format!("Invalid request: {}", e)),
+            };
+            return report;
+        }
+    };
+
+    let tools_val: Option<Vec<Value>> = req.tools.as_ref().map(|list| {
+        list.iter()
+            .map(|t| serde_json::to_value(t).unwrap_or(Value::Null))
+            .collect()
+    });
+    let traits = RequestTraits::from_client_body(body, req.stream);
+    let routing = routing_for(&req.model, mappings, &tools_val, true, traits);
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    if req.messages.is_empty() {
+        errors.push("messages must not be empty".to_string());
+    }
+    if req.max_tokens.is_none() {
+        warnings.push("max_tokens is required by the Anthropic API; the proxy tolerates its absence".to_string());
+    }
+
+    req.model = routing.mapped_model.clone();
+    let upstream_body = match claude::transform_claude_request_in(&req, REPORT_PROJECT_ID) {
+        Ok(b) => b,
+        Err(e) => {
+            errors.push(format!("Transform error: {}", e));
+            Value::Null
+        }
+    };
+    report.preflight = Some(preflight(
+        upstream_body,
+        &routing.mapped_model,
+        req.max_tokens,
+        true,
+        errors,
+        warnings,
+    ));
+    report.routing = Some(routing);
+    report
+}
+
+fn report_gemini(route: &str, model: &str, body: &Value, mappings: &ModelMappings) -> CompatReport {
+    let mut report = CompatReport {
+        route: route.to_string(),
+        protocol: "gemini",
+        deserialization: DeserializationReport { ok: true, error: None },
+        fields: analyze_fields(body, &[GEMINI_FIELDS]),
+        content: analyze_gemini_content(body),
+        routing: None,
+        preflight: None,
+    };
+
+    if !body.is_object() {
+        report.deserialization = DeserializationReport {
+            ok: false,
+            error: Some("Invalid request: body must be a JSON object".to_string()),
+        };
+        return report;
+    }
+
+    // 与 Gemini handler 一致：functionDeclarations 展开后参与联网探测
+    let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
+        arr.iter()
+            .flat_map(|entry| match entry.get("functionDeclarations").and_then(|v| v.as_array()) {
+                Some(decls) => decls.clone(),
+                None => vec![entry.clone()],
+            })
+            .collect()
+    });
+    let traits = RequestTraits::from_client_body(body, route.contains("streamGenerateContent"));
+    let routing = routing_for(model, mappings, &tools_val, false, traits);
+
+    let mut errors = Vec::new();
+    if body.get("contents").and_then(|c| c.as_array()).is_none_or(|c| c.is_empty()) {
+        errors.push("contents must be a non-empty array".to_string());
+    }
+    let max_output = body
+        .get("generationConfig")
+        .and_then(|c| c.get("maxOutputTokens"))
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32);
+
+    let upstream_body = gemini::wrap_request(body, REPORT_PROJECT_ID, &routing.mapped_model);
+    report.preflight = Some(preflight(
+        upstream_body,
+        &routing.mapped_model,
+        max_output,
+        false,
+        errors,
+        Vec::new(),
+    ));
+    report.routing = Some(routing);
+    report
+}
+
+/// 生成兼容性报告；body 可以是 JSON 对象，也可以是粘贴的原始 JSON 字符串
+pub fn build_report(route: &str, body: &Value, mappings: &ModelMappings) -> Result<CompatReport, String> {
+    let protocol = Protocol::from_route(route)?;
+
+    let parsed;
+    let body = match body {
+        Value::String(raw) => match serde_json::from_str::<Value>(raw) {
+            Ok(v) => {
+                parsed = v;
+                &parsed
+            }
+            Err(e) => {
+                return Ok(CompatReport {
+                    route: route.to_string(),
+                    protocol: protocol.name(),
+                    deserialization: DeserializationReport {
+                        ok: false,
+                        error: Some(format!("Body is not valid JSON: {}", e)),
+                    },
+                    fields: Vec::new(),
+                    content: Vec::new(),
+                    routing: None,
+                    preflight: None,
+                });
+            }
+        },
+        other => other,
+    };
+
+    Ok(match protocol {
+        Protocol::OpenAIChat | Protocol::OpenAICompletions => report_openai(route, protocol, body, mappings),
+        Protocol::Claude => report_claude(route, body, mappings),
+        Protocol::Gemini(model) => report_gemini(route, &model, body, mappings),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report(route: &str, body: Value) -> CompatReport {
+        let empty = HashMap::new();
+        let mappings = ModelMappings {
+            custom: &empty,
+            openai: &empty,
+            anthropic: &empty,
+            routes: &[],
+        };
+        build_report(route, &body, &mappings).unwrap()
+    }
+
+    fn field_status(report: &CompatReport, field: &str) -> Support {
+        report
+            .fields
+            .iter()
+            .find(|f| f.field == field)
+            .unwrap_or_else(|| panic!("field {} missing from report", field))
+            .status
+    }
+
+    fn content(report: &CompatReport, kind: &str) -> (usize, Support) {
+        let c = report
+            .content
+            .iter()
+            .find(|c| c.kind == kind)
+            .unwrap_or_else(|| panic!("content {} missing from report", kind));
+        (c.count, c.status)
+    }
+
+    #[test]
+    fn test_claude_code_body() {
+        let body = json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "max_tokens": 32000,
+            "stream": true,
+            "temperature": 1,
+            "metadata": {"user_id": "user_abc123_account__session_9f8e7d"},
+            "system": [
+                {"type": "text", "text": "You are Claude Code, Anthropic's official CLI for Claude."},
+                {"type": "text", "text": "Use the tools available to you.", "cache_control": {"type": "ephemeral"}}
+            ],
+            "thinking": {"type": "enabled", "budget_tokens": 10000},
+            "tools": [{
+                "name": "Read",
+                "description": "Reads a file from the local filesystem.",
+                "input_schema": {
+                    "$schema": "http://json-schema.org/draft-07/schema#",
+                    "type": "object",
+                    "properties": {"file_path": {"type": "string"}},
+                    "required": ["file_path"],
+                    "additionalProperties": false
+                }
+            }],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What does main.rs do?", "cache_control": {"type": "ephemeral"}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "I should read the file.", "signature": "EqQBCkYIBxgCKkB..."},
+                    {"type": "tool_use", "id": "toolu_01A", "name": "Read", "input": {"file_path": "src/main.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_01A", "content": "fn main() {}"}
+                ]}
+            ]
+        });
+        let r = report("/v1/messages", body);
+        assert_eq!(r.protocol, "anthropic_messages");
+        assert!(r.deserialization.ok);
+        assert_eq!(field_status(&r, "thinking"), Support::Mapped);
+        assert_eq!(field_status(&r, "max_tokens"), Support::Dropped);
+        assert_eq!(content(&r, "block:tool_use"), (1, Support::Mapped));
+        assert_eq!(content(&r, "block:tool_result"), (1, Support::Mapped));
+        assert_eq!(content(&r, "cache_control"), (2, Support::Dropped));
+
+        let routing = r.routing.as_ref().unwrap();
+        assert_eq!(routing.request_type, "agent");
+        assert!(routing.claude_family_mapping);
+
+        let preflight = r.preflight.as_ref().unwrap();
+        assert!(preflight.ok, "{:?}", preflight.errors);
+        assert!(preflight.generation_config.get("thinkingConfig").is_some());
+    }
+
+    #[test]
+    fn test_langchain_chat_openai_body() {
+        let body = json!({
+            "messages": [
+                {"role": "system", "content": "You are a helpful assistant."},
+                {"role": "user", "content": "Translate 'hello' to French."}
+            ],
+            "model": "gpt-4o-mini",
+            "n": 1,
+            "stream": false,
+            "temperature": 0.7
+        });
+        let r = report("/v1/chat/completions", body);
+        assert!(r.deserialization.ok);
+        assert_eq!(field_status(&r, "n"), Support::Mapped);
+        assert_eq!(field_status(&r, "temperature"), Support::Supported);
+        assert_eq!(content(&r, "role:system"), (1, Support::Mapped));
+
+        let routing = r.routing.as_ref().unwrap();
+        assert_eq!(routing.requested_model, "gpt-4o-mini");
+        assert!(!routing.mapped_model.is_empty());
+        assert!(r.preflight.as_ref().unwrap().ok);
+    }
+
+    #[test]
+    fn test_librechat_body() {
+        let body = json!({
+            "model": "gpt-4o",
+            "temperature": 1,
+            "top_p": 1,
+            "presence_penalty": 0,
+            "frequency_penalty": 0,
+            "user": "6650a1b2c3d4e5f6a7b8c9d0",
+            "stream": true,
+            "stream_options": {"include_usage": true},
+            "messages": [
+                {"role": "system", "content": "Instructions:\nBe concise."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is in this picture?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo=", "detail": "auto"}}
+                ]}
+            ]
+        });
+        let r = report("/v1/chat/completions", body);
+        assert!(r.deserialization.ok);
+        assert_eq!(field_status(&r, "presence_penalty"), Support::Mapped);
+        assert_eq!(field_status(&r, "user"), Support::Dropped);
+        assert_eq!(field_status(&r, "stream_options"), Support::Supported);
+        assert_eq!(content(&r, "part:image_url"), (1, Support::Mapped));
+        assert_eq!(content(&r, "part:text"), (1, Support::Supported));
+        assert!(r.preflight.as_ref().unwrap().ok);
+    }
+
+    #[test]
+    fn test_openai_python_sdk_body() {
+        let body = json!({
+            "model": "gpt-4o-2024-08-06",
+            "messages": [
+                {"role": "developer", "content": "Extract the event."},
+                {"role": "user", "content": "Alice and Bob are going to a science fair on Friday."}
+            ],
+            "max_completion_tokens": 512,
+            "seed": 42,
+            "tool_choice": "auto",
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }
+            }],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "event",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}, "date": {"type": "string"}},
+                        "required": ["name", "date"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        });
+        let r = report("/v1/chat/completions", body);
+        assert!(r.deserialization.ok);
+        assert_eq!(field_status(&r, "max_completion_tokens"), Support::Dropped);
+        assert_eq!(field_status(&r, "response_format"), Support::Mapped);
+        assert_eq!(field_status(&r, "tool_choice"), Support::Mapped);
+        assert_eq!(content(&r, "role:developer"), (1, Support::Unsupported));
+
+        let preflight = r.preflight.as_ref().unwrap();
+        assert!(preflight.ok, "{:?}", preflight.errors);
+        assert_eq!(preflight.generation_config["responseMimeType"], "application/json");
+    }
+
+    #[test]
+    fn test_conditional_route_explained() {
+        use crate::proxy::config::{ModelRouteVariant, RoutePredicate};
+
+        let routes = vec![ModelRouteRule {
+            alias: "auto".to_string(),
+            variants: vec![
+                ModelRouteVariant {
+                    model: "gemini-3-flash".to_string(),
+                    when: RoutePredicate {
+                        max_prompt_tokens: Some(1000),
+                        stream: Some(true),
+                        ..Default::default()
+                    },
+                },
+                ModelRouteVariant {
+                    model: "gemini-3-pro-high".to_string(),
+                    when: RoutePredicate::default(),
+                },
+            ],
+        }];
+        let empty = HashMap::new();
+        let mappings = ModelMappings {
+            custom: &empty,
+            openai: &empty,
+            anthropic: &empty,
+            routes: &routes,
+        };
+        let body = json!({"model": "auto", "stream": true, "messages": [{"role": "user", "content": "hi"}]});
+        let r = build_report("/v1/chat/completions", &body, &mappings).unwrap();
+        let routing = r.routing.unwrap();
+        assert_eq!(routing.mapped_model, "gemini-3-flash");
+        let route = routing.conditional_route.unwrap();
+        assert_eq!(route.variant, 0);
+        assert_eq!(route.matched, vec!["prompt_tokens 1 <= 1000", "stream == true"]);
+
+        let body = json!({"model": "auto", "messages": [{"role": "user", "content": "hi"}]});
+        let r = build_report("/v1/chat/completions", &body, &mappings).unwrap();
+        assert_eq!(r.routing.unwrap().mapped_model, "gemini-3-pro-high");
+    }
+
+    #[test]
+    fn test_deserialization_and_route_errors() {
+        // 不支持的内容类型：解析成功并被忽略
+        let r = report(
+            "/v1/chat/completions",
+            json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": [{"type": "input_audio", "input_audio": {"data": "", "format": "wav"}}]}]
+            }),
+        );
+        assert!(r.deserialization.ok);
+        assert_eq!(content(&r, "part:input_audio"), (1, Support::Unsupported));
+
+        // 结构错误：反序列化失败，但字段分析仍然给出
+        let r = report(
+            "/v1/chat/completions",
+            json!({"model": "gpt-4o", "messages": [{"content": "missing role"}]}),
+        );
+        assert!(!r.deserialization.ok);
+        assert_eq!(content(&r, "role:"), (1, Support::Unsupported));
+        assert!(r.preflight.is_none());
+
+        let r = report("/v1/messages", Value::String("{not json".to_string()));
+        assert!(r.deserialization.error.unwrap().contains("not valid JSON"));
+
+        let empty = HashMap::new();
+        let mappings = ModelMappings {
+            custom: &empty,
+            openai: &empty,
+            anthropic: &empty,
+            routes: &[],
+        };
+        assert!(build_report("/v1/unknown", &json!({}), &mappings).is_err());
+    }
+
+    #[test]
+    fn test_gemini_route() {
+        let r = report(
+            "/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse",
+            json!({
+                "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+                "generationConfig": {"maxOutputTokens": 1024},
+                "cachedContent": "cachedContents/abc"
+            }),
+        );
+        assert_eq!(r.protocol, "gemini");
+        assert_eq!(field_status(&r, "cachedContent"), Support::Unsupported);
+        assert_eq!(r.routing.as_ref().unwrap().requested_model, "gemini-2.5-flash");
+        assert!(r.preflight.as_ref().unwrap().ok);
+    }
+}
@@ -17,6 +17,31 @@ impl Default for ProxyAuthMode {
     }
 }
 
+/// 非图片 inlineData 的输出方式 (实现位于 antigravity-protocol 库)
+pub use antigravity_protocol::media::InlineMediaMode;
+
+/// 响应兼容模式：非致命转换警告是否写入响应体
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatMode {
+    /// 响应体保持协议原样，警告只进入监控日志
+    #[default]
+    Strict,
+    /// 非流式响应附加顶层 warnings 数组，流式响应结束前追加 warnings 事件
+    Extended,
+}
+
+/// 响应体中 model 字段上报的模型名 (内部日志始终同时记录请求模型与上游模型)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportModel {
+    /// 上游实际使用的模型 (如 gemini-3-pro-high)
+    #[default]
+    Upstream,
+    /// 客户端请求的模型名 (部分客户端校验 response.model 与请求一致)
+    Requested,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ZaiDispatchMode {
@@ -128,6 +153,11 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub allow_lan_access: bool,
 
+    /// 监听地址 (IP)，默认 127.0.0.1
+    /// 保持默认且开启 allow_lan_access 时监听 0.0.0.0；填写其他地址 (如 Docker 网卡 IP) 时按该地址监听
+    #[serde(default = "default_host")]
+    pub host: String,
+
     /// Authorization policy for the proxy.
     /// - off: no auth required
     /// - strict: auth required for all routes
@@ -141,6 +171,10 @@ pub struct ProxyConfig {
     
     /// API 密钥
     pub api_key: String,
+
+    /// 附加 API Key (可限制每个 Key 可用的模型)
+    #[serde(default)]
+    pub scoped_keys: ScopedKeysConfig,
     
 
     /// 是否自动启动
@@ -162,6 +196,16 @@ pub struct ProxyConfig {
     #[serde(default = "default_request_timeout")]
     pub request_timeout: u64,
 
+    /// 日志中对话内容的呈现方式 (preview 截断原文 / hash 短哈希 / off 不输出)
+    #[serde(default)]
+    pub prompt_log_mode: PromptLogMode,
+
+    /// 按模型覆盖的上游请求超时 (key: 模型名, value: 秒)
+    /// 思考类模型通常需要更长时间，flash 类模型可以更短；未配置的模型使用全局超时
+    /// 超时只限制等待上游响应头的时间，已开始输出的流式响应不会被中断
+    #[serde(default)]
+    pub model_timeouts: std::collections::HashMap<String, u64>,
+
     /// 是否开启请求日志记录 (监控)
     #[serde(default)]
     pub enable_logging: bool,
@@ -170,6 +214,14 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
 
+    /// 上游 TLS 配置 (企业 SSL 拦截代理环境下追加信任的 CA)
+    #[serde(default)]
+    pub upstream_tls: UpstreamTlsConfig,
+
+    /// 网络状态重置 (休眠唤醒 / 连接突发重置后刷新连接池并暂停冷却惩罚)
+    #[serde(default)]
+    pub network_reset: NetworkResetConfig,
+
     /// z.ai provider configuration (Anthropic-compatible).
     #[serde(default)]
     pub zai: ZaiConfig,
@@ -177,67 +229,1520 @@ pub struct ProxyConfig {
     /// 账号调度配置 (粘性会话/限流重试)
     #[serde(default)]
     pub scheduling: crate::proxy::sticky_config::StickySessionConfig,
+
+    /// 重试退避配置 (两次上游尝试之间的等待)
+    #[serde(default)]
+    pub backoff: BackoffConfig,
+
+    /// 全局重试预算 (所有并发请求共享，防止重试风暴)
+    #[serde(default)]
+    pub retry_budget: RetryBudgetConfig,
+
+    /// 账号熔断配置 (连续 429/403 后暂时跳过该账号)
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    /// 入站请求限流 (按客户端 IP 的令牌桶)
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    /// WebSocket 接口 (/v1/ws)，默认关闭
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+
+    /// 按工具调用 ID 缓存 thoughtSignature 的容量与过期时间
+    #[serde(default)]
+    pub signature_cache: SignatureCacheConfig,
+
+    /// 入站防护 (非 JSON 拒绝、请求头上限、失败过多临时封禁)
+    #[serde(default)]
+    pub guard: GuardConfig,
+
+    /// 图像 API 本地文件输出 (response_format=url 时返回 /v1/images/files 地址)
+    #[serde(default)]
+    pub image_files: ImageFilesConfig,
+
+    /// 响应中 inlineData 的处理方式 (音视频始终输出为链接，未知类型按该模式处理)
+    #[serde(default)]
+    pub inline_media: InlineMediaMode,
+
+    /// 维护类流量 (模型探测等后台任务) 的并发预算与降级阈值
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+
+    /// 账号限流 / 上游故障后的默认冷却时长 (上游未给出 RetryInfo 时使用)
+    #[serde(default)]
+    pub cooldown: CooldownConfig,
+
+    /// 关键事件的系统通知 (服务崩溃、无可用账号、配额不足、上游连续 5xx)
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+
+    /// 新账号磨合期：前 N 小时 / N 次请求内降低调度权重并限速
+    #[serde(default)]
+    pub break_in: BreakInConfig,
+
+    /// OpenTelemetry 链路追踪 (OTLP/HTTP 导出)
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+
+    /// Prometheus 指标端点 (GET /metrics)
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    /// Claude 流式断线续传 (Last-Event-ID)，关闭时重连请求返回 409
+    #[serde(default)]
+    pub stream_resume: StreamResumeConfig,
+
+    /// 调试：记录原始请求/响应体 (TRACE 级别，target = proxy_body)
+    #[serde(default)]
+    pub debug: DebugConfig,
+
+    /// 结构化访问日志 (每个请求一行 JSON，按时间滚动)
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+
+    /// 请求日志库定期维护 (历史行汇总、碎片整理、完整性检查)
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// 账号配额消耗状态持久化 (quota_state.json)
+    #[serde(default)]
+    pub quota_state: QuotaStateConfig,
+
+    /// OpenAI 多模态图片预处理 (远程图片下载后内联)
+    #[serde(default)]
+    pub vision: VisionConfig,
+
+    /// 账号每日请求 / Token 硬上限 (达到后当日不再调度该账号)
+    #[serde(default)]
+    pub account_caps: AccountCapsConfig,
+
+    /// 账号可用时段 (时段外不参与调度)
+    #[serde(default)]
+    pub account_schedules: AccountSchedulesConfig,
+
+    /// Anthropic 协议 extended thinking 映射
+    #[serde(default)]
+    pub thinking: ThinkingBudgetConfig,
+
+    /// Anthropic Message Batches API (/v1/message_batches)
+    #[serde(default)]
+    pub message_batches: MessageBatchConfig,
+
+    /// OpenAI 协议的思维链输出 (reasoning_content)
+    #[serde(default)]
+    pub openai_reasoning: OpenAIReasoningConfig,
+
+    /// 响应兼容模式 (转换警告是否附加到响应体)
+    #[serde(default)]
+    pub compat_mode: CompatMode,
+
+    /// 响应体 model 字段上报上游模型还是请求的模型 (可按附加 Key 或 X-Report-Model 请求头覆盖)
+    #[serde(default)]
+    pub report_model: ReportModel,
+
+    /// 账号并发请求限制
+    #[serde(default)]
+    pub account_concurrency: AccountConcurrencyConfig,
+
+    /// access_token 自动刷新
+    #[serde(default)]
+    pub token_refresh: TokenRefreshConfig,
+
+    /// 条件路由：同一别名按请求特征选择不同的上游模型
+    #[serde(default)]
+    pub model_routes: Vec<ModelRouteRule>,
+
+    /// 自定义 safetySettings (None 时使用默认的全部 OFF)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+
+    /// 后台账号健康探测
+    #[serde(default)]
+    pub account_health_check: AccountHealthCheckConfig,
+
+    /// 流式响应中的周期性用量事件
+    #[serde(default)]
+    pub stream_usage: StreamUsageConfig,
+
+    /// SSE 流无输出超过该秒数时插入 `: keepalive` 注释 (0 关闭)
+    #[serde(default = "default_sse_keepalive_secs")]
+    pub sse_keepalive_secs: u64,
+
+    /// 账号上游 sessionId 使用超过该秒数后在下次调度时更换 (0 关闭)
+    #[serde(default)]
+    pub session_rotation_interval: u64,
 }
 
-/// 上游代理配置
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct UpstreamProxyConfig {
-    /// 是否启用
+/// OpenAI 协议思维链输出配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenAIReasoningConfig {
+    /// 不输出 reasoning_content (兼容遇到未知字段会报错的客户端)
+    #[serde(default)]
+    pub strip_reasoning: bool,
+}
+
+/// Message Batches 配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageBatchConfig {
+    /// 同时处理的条目数上限
+    #[serde(default = "default_batch_max_concurrency")]
+    pub max_concurrency: usize,
+    /// 单个批次最多条目数
+    #[serde(default = "default_batch_max_requests")]
+    pub max_requests: usize,
+}
+
+impl Default for MessageBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: default_batch_max_concurrency(),
+            max_requests: default_batch_max_requests(),
+        }
+    }
+}
+
+fn default_batch_max_concurrency() -> usize {
+    4
+}
+
+fn default_batch_max_requests() -> usize {
+    100
+}
+
+/// 附加 API Key 配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScopedKeysConfig {
+    #[serde(default)]
+    pub keys: Vec<ScopedApiKey>,
+    /// 按路由解析后的模型名校验白名单 (默认按客户端请求的模型名)
+    #[serde(default)]
+    pub check_resolved_model: bool,
+}
+
+/// 单个附加 Key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScopedApiKey {
+    pub key: String,
+    /// 备注名 (日志用)
+    #[serde(default)]
+    pub label: String,
+    /// 允许的模型 (glob，如 `gemini-*-flash*`)，为空表示不限制
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// 覆盖全局 report_model 设置
+    #[serde(default)]
+    pub report_model: Option<ReportModel>,
+}
+
+/// Anthropic thinking → Gemini thinkingConfig 映射配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThinkingBudgetConfig {
+    /// 客户端开启 thinking 但未给出 budget_tokens 时使用的预算
+    #[serde(default = "default_thinking_default_budget")]
+    pub default_budget: u32,
+}
+
+impl Default for ThinkingBudgetConfig {
+    fn default() -> Self {
+        Self {
+            default_budget: default_thinking_default_budget(),
+        }
+    }
+}
+
+fn default_thinking_default_budget() -> u32 {
+    8191
+}
+
+/// 账号并发请求限制
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountConcurrencyConfig {
+    /// 每个账号同时处理的请求数上限 (0 表示不限)
+    #[serde(default)]
+    pub max_concurrent_per_account: usize,
+    /// 所有账号满载时排队等待空闲名额的最长秒数 (0 表示立即返回 429)
+    #[serde(default)]
+    pub queue_wait_secs: u64,
+}
+
+/// 条件路由规则：客户端请求 `alias` 时按顺序匹配 variants，第一个满足条件的变体生效
+/// 全部不匹配时按普通模型映射处理 alias
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelRouteRule {
+    pub alias: String,
+    #[serde(default)]
+    pub variants: Vec<ModelRouteVariant>,
+}
+
+/// 条件路由变体 (when 为空时总是匹配，可作为兜底)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelRouteVariant {
+    /// 选中后参与模型映射的目标模型
+    pub model: String,
+    #[serde(default)]
+    pub when: RoutePredicate,
+}
+
+/// 条件路由谓词，未设置的条件不参与判断，已设置的条件须全部满足
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutePredicate {
+    /// 估算输入 Token 数下限 (含)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_prompt_tokens: Option<u64>,
+    /// 估算输入 Token 数上限 (含)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prompt_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_images: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+/// access_token 自动刷新配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRefreshConfig {
+    /// 距离过期不足该秒数时提前刷新
+    #[serde(default = "default_early_refresh_secs")]
+    pub early_refresh_secs: u64,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            early_refresh_secs: default_early_refresh_secs(),
+        }
+    }
+}
+
+fn default_early_refresh_secs() -> u64 {
+    300
+}
+
+/// 流式响应周期性用量事件配置
+/// OpenAI 流仅在 extended 兼容模式下输出；两个触发条件满足其一即输出
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamUsageConfig {
+    #[serde(default)]
     pub enabled: bool,
-    /// 代理地址 (http://, https://, socks5://)
-    pub url: String,
+    /// 距上次输出达到该秒数 (0 表示不按时间触发)
+    #[serde(default = "default_stream_usage_interval_secs")]
+    pub interval_secs: u64,
+    /// 输出 Token 增长达到该值 (0 表示不按 Token 数触发)
+    #[serde(default)]
+    pub every_output_tokens: u64,
 }
 
-impl Default for ProxyConfig {
+impl Default for StreamUsageConfig {
     fn default() -> Self {
         Self {
             enabled: false,
-            allow_lan_access: false, // 默认仅本机访问，隐私优先
-            auth_mode: ProxyAuthMode::default(),
-            port: 8045,
-            api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
-            auto_start: false,
-            anthropic_mapping: std::collections::HashMap::new(),
-            openai_mapping: std::collections::HashMap::new(),
-            custom_mapping: std::collections::HashMap::new(),
-            request_timeout: default_request_timeout(),
-            enable_logging: false, // 默认关闭，节省性能
-            upstream_proxy: UpstreamProxyConfig::default(),
-            zai: ZaiConfig::default(),
-            scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
+            interval_secs: default_stream_usage_interval_secs(),
+            every_output_tokens: 0,
         }
     }
 }
 
-fn default_request_timeout() -> u64 {
-    120  // 默认 120 秒,原来 60 秒太短
+fn default_stream_usage_interval_secs() -> u64 {
+    10
 }
 
-fn default_zai_base_url() -> String {
-    "https://api.z.ai/api/anthropic".to_string()
+pub const fn default_sse_keepalive_secs() -> u64 {
+    15
 }
 
-fn default_zai_opus_model() -> String {
-    "glm-4.7".to_string()
+/// 后台账号健康探测配置
+/// 定期以维护流量向每个账号发送 1 Token 的 generateContent，连续失败的账号在恢复前不参与调度
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountHealthCheckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 两轮探测之间的间隔
+    #[serde(default = "default_health_check_interval_secs")]
+    pub interval_secs: u64,
+    /// 探测使用的模型
+    #[serde(default = "default_health_check_model")]
+    pub probe_model: String,
+    /// 连续失败多少次后标记为不可用
+    #[serde(default = "default_health_check_failure_threshold")]
+    pub failure_threshold: u32,
 }
 
-fn default_zai_sonnet_model() -> String {
-    "glm-4.7".to_string()
+impl Default for AccountHealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_health_check_interval_secs(),
+            probe_model: default_health_check_model(),
+            failure_threshold: default_health_check_failure_threshold(),
+        }
+    }
 }
 
-fn default_zai_haiku_model() -> String {
-    "glm-4.5-air".to_string()
+fn default_health_check_interval_secs() -> u64 {
+    600
 }
 
-impl ProxyConfig {
-    /// 获取实际的监听地址
-    /// - allow_lan_access = false: 返回 "127.0.0.1"（默认，隐私优先）
-    /// - allow_lan_access = true: 返回 "0.0.0.0"（允许局域网访问）
-    pub fn get_bind_address(&self) -> &str {
-        if self.allow_lan_access {
-            "0.0.0.0"
-        } else {
-            "127.0.0.1"
+fn default_health_check_model() -> String {
+    "gemini-2.5-flash".to_string()
+}
+
+fn default_health_check_failure_threshold() -> u32 {
+    2
+}
+
+/// 账号每日硬上限配置，0 表示不限制
+/// 计数按本地日期，跨日 (本地 0 点) 后自动恢复调度
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountCapsConfig {
+    /// 每个账号每日最多请求数
+    #[serde(default)]
+    pub daily_request_cap: u64,
+    /// 每个账号每日最多消耗的 Token 数 (按上游 usageMetadata.totalTokenCount 累计)
+    #[serde(default)]
+    pub daily_token_cap: u64,
+    /// 按账号邮箱覆盖全局上限
+    #[serde(default)]
+    pub overrides: std::collections::HashMap<String, AccountCapOverride>,
+}
+
+/// 单个账号的上限覆盖，未设置的字段沿用全局值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountCapOverride {
+    #[serde(default)]
+    pub daily_request_cap: Option<u64>,
+    #[serde(default)]
+    pub daily_token_cap: Option<u64>,
+}
+
+impl AccountCapsConfig {
+    /// 账号实际生效的 (请求上限, Token 上限)
+    pub fn caps_for(&self, email: &str) -> (u64, u64) {
+        let overrides = self.overrides.get(email);
+        (
+            overrides
+                .and_then(|o| o.daily_request_cap)
+                .unwrap_or(self.daily_request_cap),
+            overrides
+                .and_then(|o| o.daily_token_cap)
+                .unwrap_or(self.daily_token_cap),
+        )
+    }
+}
+
+/// 账号可用时段配置 (按账号邮箱)，未配置或时段为空的账号全天可用
+/// 时段外的账号不参与调度，但不计为不健康
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountSchedulesConfig {
+    #[serde(default)]
+    pub accounts: std::collections::HashMap<String, AccountSchedule>,
+}
+
+/// 单个账号的可用时段，任一时段命中即可调度
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSchedule {
+    /// "local" (系统时区，随夏令时切换)、"UTC" 或固定偏移 (如 "+08:00")
+    #[serde(default = "default_schedule_timezone")]
+    pub timezone: String,
+    #[serde(default)]
+    pub windows: Vec<ScheduleWindow>,
+}
+
+/// 时段 (HH:MM)，end 早于 start 时跨越午夜，两者相同表示全天
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleWindow {
+    pub start: String,
+    pub end: String,
+    /// 适用的星期，按时段开始的那天计 (如 "sat"、"mon-fri")；为空表示每天
+    #[serde(default)]
+    pub days: Vec<String>,
+}
+
+fn default_schedule_timezone() -> String {
+    "local".to_string()
+}
+
+/// OpenAI image_url 远程图片处理配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VisionConfig {
+    /// 是否由反代下载 http(s) 图片并以 inlineData 发送；关闭时以 fileData 透传 URL
+    #[serde(default)]
+    pub fetch_remote_images: bool,
+    /// 单张图片大小上限 (字节)，超出时回退为 fileData
+    #[serde(default = "default_vision_max_image_bytes")]
+    pub max_image_bytes: usize,
+    /// 单张图片下载超时 (秒)
+    #[serde(default = "default_vision_fetch_timeout_secs")]
+    pub fetch_timeout_secs: u64,
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        Self {
+            fetch_remote_images: false,
+            max_image_bytes: default_vision_max_image_bytes(),
+            fetch_timeout_secs: default_vision_fetch_timeout_secs(),
+        }
+    }
+}
+
+/// 账号配额消耗状态持久化配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaStateConfig {
+    /// 状态文件路径，留空时使用数据目录下的 quota_state.json
+    #[serde(default)]
+    pub path: Option<String>,
+    /// 后台写盘间隔 (秒)
+    #[serde(default = "default_quota_state_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+    /// 新账号 (或配额恢复后) 的估算可用请求数
+    #[serde(default = "default_quota_state_initial_estimate")]
+    pub initial_estimate: i64,
+}
+
+impl Default for QuotaStateConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            flush_interval_secs: default_quota_state_flush_interval_secs(),
+            initial_estimate: default_quota_state_initial_estimate(),
+        }
+    }
+}
+
+/// 请求日志库维护配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default = "default_storage_enabled")]
+    pub enabled: bool,
+    /// 原始请求行保留天数，更早的行按天/模型汇总后删除
+    #[serde(default = "default_storage_retention_days")]
+    pub retention_days: u32,
+    /// 空闲页占比超过该百分比时整理数据库文件
+    #[serde(default = "default_storage_vacuum_threshold_percent")]
+    pub vacuum_threshold_percent: u8,
+    /// 两次维护之间的间隔 (小时)
+    #[serde(default = "default_storage_interval_hours")]
+    pub interval_hours: u32,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: default_storage_retention_days(),
+            vacuum_threshold_percent: default_storage_vacuum_threshold_percent(),
+            interval_hours: default_storage_interval_hours(),
+        }
+    }
+}
+
+/// 调试日志配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugConfig {
+    /// 是否记录请求体与响应体；需配合 RUST_LOG=proxy_body=trace 才会输出
+    #[serde(default)]
+    pub log_bodies: bool,
+    /// 单个请求/响应体 (或流式分片) 最多记录的字节数
+    #[serde(default = "default_debug_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// 记录前替换为 "[REDACTED]" 的 JSON 字段名 (任意层级，忽略大小写)
+    #[serde(default = "default_debug_redact_fields")]
+    pub redact_fields: Vec<String>,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            log_bodies: false,
+            max_body_bytes: default_debug_max_body_bytes(),
+            redact_fields: default_debug_redact_fields(),
         }
     }
 }
+
+/// 访问日志文件的滚动周期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+}
+
+/// 结构化访问日志配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// 保留的日志文件数 (超出时删除最旧的文件)
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: usize,
+    /// 同时记录请求与响应正文 (按 debug.redact_fields 脱敏、debug.max_body_bytes 截断)，仅用于调试
+    #[serde(default)]
+    pub log_bodies: bool,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rotation: LogRotation::default(),
+            max_files: default_access_log_max_files(),
+            log_bodies: false,
+        }
+    }
+}
+
+fn default_access_log_max_files() -> usize {
+    14
+}
+
+/// Claude 流式断线续传配置 (best-effort)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamResumeConfig {
+    /// 开启后生成过程与客户端连接解耦，断开后仍会跑完并缓存事件
+    #[serde(default)]
+    pub enabled: bool,
+    /// 每条消息最多缓存的事件数 (超出时丢弃最早的事件)
+    #[serde(default = "default_stream_resume_max_events")]
+    pub max_events: usize,
+    /// 生成结束后缓存保留时间 (秒)
+    #[serde(default = "default_stream_resume_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for StreamResumeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_events: default_stream_resume_max_events(),
+            ttl_secs: default_stream_resume_ttl_secs(),
+        }
+    }
+}
+
+/// OpenTelemetry 链路追踪配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// 关闭时不创建任何追踪 span
+    #[serde(default)]
+    pub enabled: bool,
+    /// OTLP/HTTP traces 端点 (JSON 编码)
+    #[serde(default = "default_telemetry_endpoint")]
+    pub endpoint: String,
+    /// 附加请求头 (例如采集端的鉴权 token)
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// 上报的 service.name
+    #[serde(default = "default_telemetry_service_name")]
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: default_telemetry_endpoint(),
+            headers: std::collections::HashMap::new(),
+            service_name: default_telemetry_service_name(),
+        }
+    }
+}
+
+/// Prometheus 指标端点配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// 关闭时 /metrics 返回 404 (统计仍在进程内累计)
+    #[serde(default = "default_metrics_enabled")]
+    pub enabled: bool,
+    /// 为 true 时抓取端同样需要 API Key (默认免认证，便于 Prometheus 直接抓取)
+    #[serde(default)]
+    pub require_auth: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_metrics_enabled(),
+            require_auth: false,
+        }
+    }
+}
+
+/// 新账号磨合期配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakInConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 加入后经过多少小时结束磨合 (0 表示不按时间结束)
+    #[serde(default = "default_break_in_hours")]
+    pub hours: u64,
+    /// 累计成功请求达到多少次结束磨合 (0 表示不按请求数结束)
+    #[serde(default = "default_break_in_requests")]
+    pub requests: u64,
+    /// 磨合期账号相对正常账号的调度权重 (百分比)
+    #[serde(default = "default_break_in_share_percent")]
+    pub share_percent: u32,
+    /// 磨合期账号每分钟最多承接的请求数 (0 表示不限)
+    #[serde(default = "default_break_in_max_requests_per_minute")]
+    pub max_requests_per_minute: u32,
+}
+
+impl Default for BreakInConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hours: default_break_in_hours(),
+            requests: default_break_in_requests(),
+            share_percent: default_break_in_share_percent(),
+            max_requests_per_minute: default_break_in_max_requests_per_minute(),
+        }
+    }
+}
+
+/// 账号冷却配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CooldownConfig {
+    /// 429 / RESOURCE_EXHAUSTED 且无法解析重试时间时的冷却 (秒)
+    #[serde(default = "default_cooldown_rate_limit_secs")]
+    pub rate_limit_secs: u64,
+    /// 5xx 软避让的冷却 (秒)
+    #[serde(default = "default_cooldown_server_error_secs")]
+    pub server_error_secs: u64,
+}
+
+impl Default for CooldownConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit_secs: default_cooldown_rate_limit_secs(),
+            server_error_secs: default_cooldown_server_error_secs(),
+        }
+    }
+}
+
+/// 可发送系统通知的关键事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// 反代服务异常退出或启动失败
+    ServerCrashed,
+    /// 账号池中没有可用账号
+    NoHealthyAccounts,
+    /// 账号配额低于阈值
+    QuotaLow,
+    /// 窗口内上游 5xx 次数达到阈值
+    UpstreamErrors,
+    /// 账号达到每日请求 / Token 上限
+    AccountCapped,
+    /// 账号 refresh_token 失效 (invalid_grant)，需要重新授权
+    AccountReauthRequired,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 6] = [
+        NotificationKind::ServerCrashed,
+        NotificationKind::NoHealthyAccounts,
+        NotificationKind::QuotaLow,
+        NotificationKind::UpstreamErrors,
+        NotificationKind::AccountCapped,
+        NotificationKind::AccountReauthRequired,
+    ];
+}
+
+/// 系统通知配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// 全局静默：开启后不发送任何通知
+    #[serde(default)]
+    pub quiet: bool,
+    /// 启用通知的事件类型
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationKind>,
+    /// 同一类型事件的最短通知间隔 (秒)
+    #[serde(default = "default_notification_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// 配额剩余百分比低于该值时通知
+    #[serde(default = "default_notification_quota_threshold_percent")]
+    pub quota_threshold_percent: i32,
+    /// 窗口内上游 5xx 达到该次数时通知
+    #[serde(default = "default_notification_upstream_error_threshold")]
+    pub upstream_error_threshold: u32,
+    /// 上游 5xx 统计窗口 (秒)
+    #[serde(default = "default_notification_upstream_error_window_secs")]
+    pub upstream_error_window_secs: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            events: default_notification_events(),
+            cooldown_secs: default_notification_cooldown_secs(),
+            quota_threshold_percent: default_notification_quota_threshold_percent(),
+            upstream_error_threshold: default_notification_upstream_error_threshold(),
+            upstream_error_window_secs: default_notification_upstream_error_window_secs(),
+        }
+    }
+}
+
+/// 维护类流量配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    /// 同时进行的维护请求上限 (与用户请求互不占用)
+    #[serde(default = "default_maintenance_max_concurrent")]
+    pub max_concurrent: usize,
+    /// 进行中的用户请求达到该值时拒绝新的维护请求
+    #[serde(default = "default_maintenance_shed_above_in_flight")]
+    pub shed_above_in_flight: usize,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_maintenance_max_concurrent(),
+            shed_above_in_flight: default_maintenance_shed_above_in_flight(),
+        }
+    }
+}
+
+/// 图像本地文件输出配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageFilesConfig {
+    /// 是否启用；关闭时 url 格式返回 data URI
+    #[serde(default)]
+    pub enabled: bool,
+    /// 文件保留时长 (秒)，过期文件在下次生成时清理
+    #[serde(default = "default_image_files_retention_secs")]
+    pub retention_secs: u64,
+}
+
+impl Default for ImageFilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_secs: default_image_files_retention_secs(),
+        }
+    }
+}
+
+/// 入站防护配置 (局域网暴露时抵御扫描器)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardConfig {
+    /// 是否启用
+    #[serde(default = "default_guard_enabled")]
+    pub enabled: bool,
+    /// 请求头总大小上限 (字节)，超出返回 431
+    #[serde(default = "default_guard_max_header_bytes")]
+    pub max_header_bytes: usize,
+    /// 时间窗口内允许的失败请求数，达到后封禁该 IP
+    #[serde(default = "default_guard_max_failures")]
+    pub max_failures: u32,
+    /// 失败计数窗口 (秒)
+    #[serde(default = "default_guard_failure_window_secs")]
+    pub failure_window_secs: u64,
+    /// 封禁时长 (秒)
+    #[serde(default = "default_guard_ban_secs")]
+    pub ban_secs: u64,
+    /// 本机回环地址不参与封禁
+    #[serde(default = "default_guard_exempt_loopback")]
+    pub exempt_loopback: bool,
+}
+
+impl Default for GuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_guard_enabled(),
+            max_header_bytes: default_guard_max_header_bytes(),
+            max_failures: default_guard_max_failures(),
+            failure_window_secs: default_guard_failure_window_secs(),
+            ban_secs: default_guard_ban_secs(),
+            exempt_loopback: default_guard_exempt_loopback(),
+        }
+    }
+}
+
+/// thoughtSignature 缓存配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureCacheConfig {
+    /// 最大条目数，超出后淘汰最早写入的条目
+    #[serde(default = "default_signature_cache_max_entries")]
+    pub max_entries: usize,
+    /// 条目过期时间 (秒)
+    #[serde(default = "default_signature_cache_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for SignatureCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: default_signature_cache_max_entries(),
+            ttl_secs: default_signature_cache_ttl_secs(),
+        }
+    }
+}
+
+/// WebSocket 接口配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketConfig {
+    /// 是否启用 /v1/ws
+    #[serde(default)]
+    pub enabled: bool,
+    /// 同时在线的最大连接数
+    #[serde(default = "default_ws_max_connections")]
+    pub max_connections: usize,
+    /// 单个连接最多处理的请求数
+    #[serde(default = "default_ws_max_requests_per_connection")]
+    pub max_requests_per_connection: u32,
+    /// 单条消息最大字节数
+    #[serde(default = "default_ws_max_message_bytes")]
+    pub max_message_bytes: usize,
+    /// 服务端 ping 间隔 (秒)
+    #[serde(default = "default_ws_ping_interval_secs")]
+    pub ping_interval_secs: u64,
+    /// 无任何客户端帧时的空闲超时 (秒)
+    #[serde(default = "default_ws_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_connections: default_ws_max_connections(),
+            max_requests_per_connection: default_ws_max_requests_per_connection(),
+            max_message_bytes: default_ws_max_message_bytes(),
+            ping_interval_secs: default_ws_ping_interval_secs(),
+            idle_timeout_secs: default_ws_idle_timeout_secs(),
+        }
+    }
+}
+
+/// 入站请求限流配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// 是否启用
+    #[serde(default = "default_rate_limit_enabled")]
+    pub enabled: bool,
+    /// 每秒补充的请求数
+    #[serde(default = "default_rate_limit_rps")]
+    pub requests_per_second: f64,
+    /// 突发容量 (令牌桶大小)
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+    /// 携带有效 API Key 的客户端每秒请求数
+    #[serde(default = "default_rate_limit_auth_rps")]
+    pub authenticated_requests_per_second: f64,
+    /// 携带有效 API Key 的客户端突发容量
+    #[serde(default = "default_rate_limit_auth_burst")]
+    pub authenticated_burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_rate_limit_enabled(),
+            requests_per_second: default_rate_limit_rps(),
+            burst: default_rate_limit_burst(),
+            authenticated_requests_per_second: default_rate_limit_auth_rps(),
+            authenticated_burst: default_rate_limit_auth_burst(),
+        }
+    }
+}
+
+/// 账号熔断配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// 连续失败多少次后熔断
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub failure_threshold: u32,
+    /// 熔断持续时间 (秒)，到期后进入半开状态放行一次试探请求
+    #[serde(default = "default_circuit_cooldown_seconds")]
+    pub cooldown_seconds: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_circuit_failure_threshold(),
+            cooldown_seconds: default_circuit_cooldown_seconds(),
+        }
+    }
+}
+
+/// 日志中对话内容的呈现方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptLogMode {
+    /// 截断后的原文
+    #[default]
+    Preview,
+    /// 首条用户消息的短哈希
+    Hash,
+    Off,
+}
+
+/// 重试退避配置
+/// delay = min(initial * multiplier^attempt + jitter, max)，jitter 为计算值的随机比例
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackoffConfig {
+    /// 首次重试前的等待 (毫秒)
+    #[serde(default = "default_backoff_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// 每次重试的放大倍数
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    /// 单次等待上限 (毫秒)
+    #[serde(default = "default_backoff_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// 随机抖动比例 (0.0 - 1.0)
+    #[serde(default = "default_backoff_jitter")]
+    pub jitter: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: default_backoff_initial_delay_ms(),
+            multiplier: default_backoff_multiplier(),
+            max_delay_ms: default_backoff_max_delay_ms(),
+            jitter: default_backoff_jitter(),
+        }
+    }
+}
+
+/// 全局重试预算配置
+/// 每次重试消耗 1 个令牌，每个首次尝试即成功的请求归还 1 个令牌；余额为 0 时不再重试，直接返回 503
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryBudgetConfig {
+    #[serde(default = "default_retry_budget_enabled")]
+    pub enabled: bool,
+    /// 令牌上限
+    #[serde(default = "default_retry_budget_capacity")]
+    pub capacity: u32,
+    /// 启动时的令牌数
+    #[serde(default = "default_retry_budget_initial")]
+    pub initial: u32,
+}
+
+impl Default for RetryBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_retry_budget_enabled(),
+            capacity: default_retry_budget_capacity(),
+            initial: default_retry_budget_initial(),
+        }
+    }
+}
+
+/// 单条安全过滤阈值，原样透传到 Gemini safetySettings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetySetting {
+    /// 如 HARM_CATEGORY_HARASSMENT
+    pub category: String,
+    /// 如 BLOCK_ONLY_HIGH / BLOCK_NONE / OFF
+    pub threshold: String,
+}
+
+/// 上游 TLS 配置
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct UpstreamTlsConfig {
+    /// 额外信任的 CA 证书文件 (PEM，可包含多张)，为空时只使用系统证书
+    #[serde(default)]
+    pub extra_ca_bundle: String,
+    /// 跳过证书校验 (极不安全，仅用于临时排查；启用期间每次构建客户端都会输出错误日志)
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+/// 网络状态重置配置
+/// 以下任一情况视为网络状态已变化 (如笔记本休眠唤醒)：
+/// - 短时间内多个账号连续出现连接重置 (连接池中的旧连接已失效)
+/// - 后台心跳发现两次心跳之间的时间远超预期 (系统曾挂起)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NetworkResetConfig {
+    #[serde(default = "default_network_reset_enabled")]
+    pub enabled: bool,
+    /// 窗口内连接重置次数达到该值时触发
+    #[serde(default = "default_network_reset_burst_failures")]
+    pub burst_failures: u32,
+    /// 且至少涉及这么多个不同账号 (单个账号的连接问题不触发)
+    #[serde(default = "default_network_reset_min_accounts")]
+    pub min_accounts: u32,
+    /// 统计窗口 (秒)
+    #[serde(default = "default_network_reset_burst_window_secs")]
+    pub burst_window_secs: u64,
+    /// 触发后的宽限期 (秒)：期间除 429 外的失败不计入冷却 / 熔断
+    #[serde(default = "default_network_reset_grace_secs")]
+    pub grace_secs: u64,
+    /// 心跳间隔超出预期该秒数时视为系统曾挂起 (0 表示不检测)
+    #[serde(default = "default_network_reset_resume_gap_secs")]
+    pub resume_gap_secs: u64,
+}
+
+impl Default for NetworkResetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_network_reset_enabled(),
+            burst_failures: default_network_reset_burst_failures(),
+            min_accounts: default_network_reset_min_accounts(),
+            burst_window_secs: default_network_reset_burst_window_secs(),
+            grace_secs: default_network_reset_grace_secs(),
+            resume_gap_secs: default_network_reset_resume_gap_secs(),
+        }
+    }
+}
+
+fn default_network_reset_enabled() -> bool {
+    true
+}
+
+fn default_network_reset_burst_failures() -> u32 {
+    3
+}
+
+fn default_network_reset_min_accounts() -> u32 {
+    2
+}
+
+fn default_network_reset_burst_window_secs() -> u64 {
+    10
+}
+
+fn default_network_reset_grace_secs() -> u64 {
+    30
+}
+
+fn default_network_reset_resume_gap_secs() -> u64 {
+    30
+}
+
+/// 上游代理配置
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct UpstreamProxyConfig {
+    /// 是否启用
+    pub enabled: bool,
+    /// 代理地址 (http://, https://, socks5://)
+    pub url: String,
+    /// 代理认证用户名 (为空表示无需认证)
+    #[serde(default)]
+    pub username: String,
+    /// 代理认证密码
+    #[serde(default)]
+    pub password: String,
+    /// 不经过代理的主机 (语法同 NO_PROXY: 域名、IP、CIDR)
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_lan_access: false, // 默认仅本机访问，隐私优先
+            host: default_host(),
+            auth_mode: ProxyAuthMode::default(),
+            port: 8045,
+            api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
+            scoped_keys: ScopedKeysConfig::default(),
+            auto_start: false,
+            anthropic_mapping: std::collections::HashMap::new(),
+            openai_mapping: std::collections::HashMap::new(),
+            custom_mapping: std::collections::HashMap::new(),
+            request_timeout: default_request_timeout(),
+            prompt_log_mode: PromptLogMode::default(),
+            model_timeouts: std::collections::HashMap::new(),
+            enable_logging: false, // 默认关闭，节省性能
+            upstream_proxy: UpstreamProxyConfig::default(),
+            upstream_tls: UpstreamTlsConfig::default(),
+            network_reset: NetworkResetConfig::default(),
+            zai: ZaiConfig::default(),
+            scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
+            backoff: BackoffConfig::default(),
+            retry_budget: RetryBudgetConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            websocket: WebSocketConfig::default(),
+            signature_cache: SignatureCacheConfig::default(),
+            guard: GuardConfig::default(),
+            image_files: ImageFilesConfig::default(),
+            inline_media: InlineMediaMode::default(),
+            maintenance: MaintenanceConfig::default(),
+            cooldown: CooldownConfig::default(),
+            notifications: NotificationConfig::default(),
+            break_in: BreakInConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            metrics: MetricsConfig::default(),
+            stream_resume: StreamResumeConfig::default(),
+            debug: DebugConfig::default(),
+            access_log: AccessLogConfig::default(),
+            storage: StorageConfig::default(),
+            quota_state: QuotaStateConfig::default(),
+            vision: VisionConfig::default(),
+            account_caps: AccountCapsConfig::default(),
+            account_schedules: AccountSchedulesConfig::default(),
+            thinking: ThinkingBudgetConfig::default(),
+            message_batches: MessageBatchConfig::default(),
+            openai_reasoning: OpenAIReasoningConfig::default(),
+            compat_mode: CompatMode::default(),
+            report_model: ReportModel::default(),
+            account_concurrency: AccountConcurrencyConfig::default(),
+            token_refresh: TokenRefreshConfig::default(),
+            model_routes: Vec::new(),
+            safety_settings: None,
+            account_health_check: AccountHealthCheckConfig::default(),
+            stream_usage: StreamUsageConfig::default(),
+            sse_keepalive_secs: default_sse_keepalive_secs(),
+            session_rotation_interval: 0,
+        }
+    }
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+/// 查找上游请求超时：依次匹配给定的模型名 (映射后的模型优先)，均未配置时使用 default_secs
+pub fn resolve_model_timeout(
+    model_timeouts: &std::collections::HashMap<String, u64>,
+    default_secs: u64,
+    models: &[&str],
+) -> std::time::Duration {
+    let secs = models
+        .iter()
+        .find_map(|m| model_timeouts.get(*m).copied())
+        .unwrap_or(default_secs);
+    std::time::Duration::from_secs(secs.max(5))
+}
+
+fn default_request_timeout() -> u64 {
+    120  // 默认 120 秒,原来 60 秒太短
+}
+
+fn default_backoff_initial_delay_ms() -> u64 {
+    500
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_backoff_max_delay_ms() -> u64 {
+    8000
+}
+
+fn default_backoff_jitter() -> f64 {
+    0.2
+}
+
+fn default_retry_budget_enabled() -> bool {
+    true
+}
+
+fn default_retry_budget_capacity() -> u32 {
+    50
+}
+
+fn default_retry_budget_initial() -> u32 {
+    20
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+    3
+}
+
+fn default_circuit_cooldown_seconds() -> u64 {
+    300
+}
+
+fn default_rate_limit_enabled() -> bool {
+    true
+}
+
+fn default_rate_limit_rps() -> f64 {
+    10.0
+}
+
+fn default_rate_limit_burst() -> u32 {
+    20
+}
+
+fn default_rate_limit_auth_rps() -> f64 {
+    50.0
+}
+
+fn default_rate_limit_auth_burst() -> u32 {
+    100
+}
+
+fn default_ws_max_connections() -> usize {
+    16
+}
+
+fn default_ws_max_requests_per_connection() -> u32 {
+    500
+}
+
+fn default_ws_max_message_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_ws_ping_interval_secs() -> u64 {
+    30
+}
+
+fn default_ws_idle_timeout_secs() -> u64 {
+    120
+}
+
+fn default_signature_cache_max_entries() -> usize {
+    1000
+}
+
+fn default_signature_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_guard_enabled() -> bool {
+    true
+}
+
+fn default_guard_max_header_bytes() -> usize {
+    32 * 1024
+}
+
+fn default_guard_max_failures() -> u32 {
+    20
+}
+
+fn default_guard_failure_window_secs() -> u64 {
+    60
+}
+
+fn default_guard_ban_secs() -> u64 {
+    600
+}
+
+fn default_guard_exempt_loopback() -> bool {
+    true
+}
+
+fn default_image_files_retention_secs() -> u64 {
+    24 * 3600
+}
+
+fn default_maintenance_max_concurrent() -> usize {
+    2
+}
+
+fn default_maintenance_shed_above_in_flight() -> usize {
+    8
+}
+
+fn default_break_in_hours() -> u64 {
+    48
+}
+
+fn default_break_in_requests() -> u64 {
+    200
+}
+
+fn default_break_in_share_percent() -> u32 {
+    25
+}
+
+fn default_break_in_max_requests_per_minute() -> u32 {
+    6
+}
+
+fn default_telemetry_endpoint() -> String {
+    "http://127.0.0.1:4318/v1/traces".to_string()
+}
+
+fn default_telemetry_service_name() -> String {
+    "antigravity-proxy".to_string()
+}
+
+fn default_metrics_enabled() -> bool {
+    true
+}
+
+fn default_stream_resume_max_events() -> usize {
+    2000
+}
+
+fn default_stream_resume_ttl_secs() -> u64 {
+    300
+}
+
+fn default_storage_enabled() -> bool {
+    true
+}
+
+fn default_storage_retention_days() -> u32 {
+    30
+}
+
+fn default_storage_vacuum_threshold_percent() -> u8 {
+    20
+}
+
+fn default_storage_interval_hours() -> u32 {
+    24
+}
+
+fn default_quota_state_flush_interval_secs() -> u64 {
+    30
+}
+
+fn default_quota_state_initial_estimate() -> i64 {
+    1000
+}
+
+fn default_vision_max_image_bytes() -> usize {
+    20 * 1024 * 1024
+}
+
+fn default_vision_fetch_timeout_secs() -> u64 {
+    15
+}
+
+fn default_debug_max_body_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_debug_redact_fields() -> Vec<String> {
+    ["access_token", "refresh_token", "api_key", "authorization", "data"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_cooldown_rate_limit_secs() -> u64 {
+    60
+}
+
+fn default_cooldown_server_error_secs() -> u64 {
+    20
+}
+
+fn default_notification_events() -> Vec<NotificationKind> {
+    NotificationKind::ALL.to_vec()
+}
+
+fn default_notification_cooldown_secs() -> u64 {
+    1800
+}
+
+fn default_notification_quota_threshold_percent() -> i32 {
+    10
+}
+
+fn default_notification_upstream_error_threshold() -> u32 {
+    5
+}
+
+fn default_notification_upstream_error_window_secs() -> u64 {
+    300
+}
+
+fn default_zai_base_url() -> String {
+    "https://api.z.ai/api/anthropic".to_string()
+}
+
+fn default_zai_opus_model() -> String {
+    "glm-4.7".to_string()
+}
+
+fn default_zai_sonnet_model() -> String {
+    "glm-4.7".to_string()
+}
+
+fn default_zai_haiku_model() -> String {
+    "glm-4.5-air".to_string()
+}
+
+impl ProxyConfig {
+    /// 获取实际的监听地址
+    /// - host 为默认值且 allow_lan_access = false: 返回 "127.0.0.1"（默认，隐私优先）
+    /// - host 为默认值且 allow_lan_access = true: 返回 "0.0.0.0"（允许局域网访问）
+    /// - 其他 host: 原样返回
+    pub fn get_bind_address(&self) -> String {
+        let host = self.host.trim();
+        if host.is_empty() || host == "127.0.0.1" {
+            if self.allow_lan_access {
+                "0.0.0.0".to_string()
+            } else {
+                "127.0.0.1".to_string()
+            }
+        } else {
+            host.to_string()
+        }
+    }
+
+    /// 校验监听地址，返回解析后的 IP
+    pub fn validate_bind_address(&self) -> Result<std::net::IpAddr, String> {
+        let addr = self.get_bind_address();
+        addr.parse::<std::net::IpAddr>().map_err(|_| {
+            format!(
+                "Invalid proxy host '{}': expected an IP address such as 127.0.0.1 or 0.0.0.0",
+                addr
+            )
+        })
+    }
+
+    /// 监听地址是否对本机以外开放
+    pub fn is_exposed(&self) -> bool {
+        self.validate_bind_address()
+            .map(|ip| !ip.is_loopback())
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_model_timeout_prefers_mapped_model() {
+        let timeouts = std::collections::HashMap::from([
+            ("gemini-2.5-pro".to_string(), 180),
+            ("gpt-4o".to_string(), 90),
+            ("gemini-2.5-flash".to_string(), 1),
+        ]);
+        let secs = |models: &[&str]| resolve_model_timeout(&timeouts, 300, models).as_secs();
+
+        assert_eq!(secs(&["gemini-2.5-pro", "gpt-4o"]), 180);
+        assert_eq!(secs(&["gemini-3-pro-high", "gpt-4o"]), 90);
+        assert_eq!(secs(&["gemini-3-pro-high", "claude-opus-4-5"]), 300);
+        // 过小的值按 5 秒下限处理
+        assert_eq!(secs(&["gemini-2.5-flash"]), 5);
+    }
+
+    #[test]
+    fn test_bind_address_from_host_and_lan_toggle() {
+        let mut config = ProxyConfig::default();
+        assert_eq!(config.get_bind_address(), "127.0.0.1");
+        assert!(!config.is_exposed());
+
+        config.allow_lan_access = true;
+        assert_eq!(config.get_bind_address(), "0.0.0.0");
+        assert!(config.is_exposed());
+
+        config.allow_lan_access = false;
+        config.host = " 172.17.0.1 ".to_string();
+        assert_eq!(config.get_bind_address(), "172.17.0.1");
+        assert!(config.is_exposed());
+
+        config.host = "::1".to_string();
+        assert!(!config.is_exposed());
+
+        config.host = "localhost:8080".to_string();
+        assert!(config.validate_bind_address().unwrap_err().contains("Invalid proxy host"));
+    }
+}
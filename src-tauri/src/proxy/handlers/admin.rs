@@ -0,0 +1,174 @@
+// 管理端点处理器 (账号维护、诊断等)
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use crate::proxy::model_access::ProbeError;
+use crate::proxy::server::AppState;
+
+/// 账号池状态 (限流 + 熔断)
+/// GET /v1/internal/tokens
+pub async fn handle_list_tokens(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "tokens": state.token_manager.token_statuses(),
+    }))
+}
+
+/// 账号健康分 (0.0 ~ 1.0，调度时按健康分加权随机)
+/// GET /admin/health
+pub async fn handle_token_health(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "scores": state.token_manager.token_scores(),
+    }))
+}
+
+/// 后台健康探测的账号状态 (healthy=false 的账号暂停调度)
+/// GET /admin/accounts/health
+pub async fn handle_account_health(State(state): State<AppState>) -> impl IntoResponse {
+    let checker = state.token_manager.account_health();
+    Json(json!({
+        "enabled": checker.config().enabled,
+        "accounts": checker.snapshot(),
+    }))
+}
+
+/// 各账号当日用量统计 (本地 0 点清零，重启后保留)
+/// GET /stats
+pub async fn handle_usage_stats(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "day": crate::proxy::quota_state::today(),
+        "accounts": state.token_manager.usage_stats(),
+    }))
+}
+
+/// 清零单个账号的当日用量统计
+/// DELETE /stats/:email
+pub async fn handle_reset_usage_stats(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !state.token_manager.reset_usage_stats(&email) {
+        return Err((StatusCode::NOT_FOUND, format!("No usage recorded for {}", email)));
+    }
+    Ok(Json(json!({ "reset": email })))
+}
+
+/// 同邮箱但 project_id 不一致、需要人工处理的重复账号
+/// GET /admin/accounts/conflicts
+pub async fn handle_account_conflicts() -> impl IntoResponse {
+    Json(json!({
+        "conflicts": crate::modules::account::account_conflicts(),
+    }))
+}
+
+/// 维护类流量统计 (不计入用户请求统计)
+/// GET /admin/maintenance
+pub async fn handle_maintenance_stats(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "maintenance": state.token_manager.maintenance().stats(),
+    }))
+}
+
+/// 当前被入站防护临时封禁的 IP
+/// GET /admin/bans
+pub async fn handle_list_bans(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "bans": state.guard.bans(std::time::Instant::now()),
+    }))
+}
+
+/// 清空封禁列表
+/// DELETE /admin/bans
+pub async fn handle_clear_bans(State(state): State<AppState>) -> impl IntoResponse {
+    let cleared = state.guard.clear_bans();
+    tracing::info!("[Guard] Cleared {} bans via admin API", cleared);
+    Json(json!({ "cleared": cleared }))
+}
+
+/// 解除单个 IP 的封禁
+/// DELETE /admin/bans/:ip
+pub async fn handle_unban(
+    State(state): State<AppState>,
+    Path(ip): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let addr: std::net::IpAddr = ip
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid IP address: {}", ip)))?;
+    if !state.guard.unban(addr) {
+        return Err((StatusCode::NOT_FOUND, format!("{} is not banned", addr)));
+    }
+    tracing::info!("[Guard] Unbanned {} via admin API", addr);
+    Ok(Json(json!({ "unbanned": addr.to_string() })))
+}
+
+/// 立即更换账号的上游 sessionId
+/// POST /admin/accounts/:email/rotate-session
+pub async fn handle_rotate_session(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let session_id = state
+        .token_manager
+        .rotate_session(&email)
+        .map_err(|e| (StatusCode::NOT_FOUND, e))?;
+    Ok(Json(json!({ "email": email, "session_id": session_id })))
+}
+
+/// 主动探测账号可访问的模型
+/// POST /admin/accounts/:email/probe-models
+pub async fn handle_probe_models(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let ctx = crate::proxy::maintenance::MaintenanceContext::new(&state.token_manager, &state.upstream);
+    let results = crate::proxy::model_access::probe_account_models(&ctx, &email)
+    .await
+    .map_err(|e| {
+        let status = match e {
+            ProbeError::AlreadyRunning { .. } | ProbeError::CoolingDown { .. } => StatusCode::CONFLICT,
+            ProbeError::AccountNotFound { .. } => StatusCode::NOT_FOUND,
+            ProbeError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, e.to_string())
+    })?;
+
+    Ok(Json(json!({
+        "email": email,
+        "models": results,
+        "cache": state.token_manager.model_access().entries_for(&email),
+    })))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CompatReportRequest {
+    /// 目标路由，例如 /v1/chat/completions
+    pub route: String,
+    /// 原始请求体 (JSON 对象或粘贴的 JSON 字符串)
+    pub body: serde_json::Value,
+}
+
+/// 兼容性诊断：对请求体做反序列化、字段支持度、路由与预检分析，不访问上游
+/// POST /debug/compat-report
+pub async fn handle_compat_report(
+    State(state): State<AppState>,
+    Json(req): Json<CompatReportRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let custom = state.custom_mapping.read().await.clone();
+    let openai = state.openai_mapping.read().await.clone();
+    let anthropic = state.anthropic_mapping.read().await.clone();
+    let routes = crate::proxy::common::model_routes::current_rules();
+    let mappings = crate::proxy::compat_report::ModelMappings {
+        custom: &custom,
+        openai: &openai,
+        anthropic: &anthropic,
+        routes: &routes,
+    };
+
+    let report = crate::proxy::compat_report::build_report(&req.route, &req.body, &mappings)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(report))
+}
@@ -14,8 +14,21 @@ use tracing::{debug, error, info};
 
 use crate::proxy::mappers::claude::{
     transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
+    CoalesceConfig, FinalOnlyConfig, stream_error_event,
 };
+use crate::proxy::common::prompt_log;
+use crate::proxy::common::sampling::attach_sampling_header;
+use crate::proxy::mappers::claude::resume;
+use crate::proxy::common::model_routes::{self, RequestTraits};
+use crate::proxy::common::sse_keepalive::with_keepalive;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::cancel::DisconnectAware;
+use crate::proxy::upstream::client::AccountAuth;
+use crate::proxy::upstream::errors::{
+    anthropic_error_response, anthropic_model_not_allowed_response, anthropic_pool_error_response,
+    anthropic_retry_budget_response, UpstreamFailure,
+};
+use crate::proxy::upstream::failover::{with_stream_failover, StreamReopenContext};
 use axum::http::HeaderMap;
 use std::sync::atomic::Ordering;
 
@@ -24,7 +37,11 @@ const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
 
 // ===== Thinking 块处理辅助函数 =====
 
-use crate::proxy::mappers::claude::models::{ContentBlock, Message, MessageContent};
+use crate::proxy::mappers::claude::models::{
+    BatchResult, BatchResultBody, ContentBlock, Message, MessageBatchRequest, MessageContent,
+};
+use crate::proxy::config::MessageBatchConfig;
+use crate::proxy::middleware::request_id::{scope_request, RequestId};
 
 /// 检查 thinking 块是否有有效签名
 fn has_valid_signature(block: &ContentBlock) -> bool {
@@ -55,7 +72,7 @@ fn sanitize_thinking_block(block: ContentBlock) -> ContentBlock {
 fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>) {
     let mut total_filtered = 0;
     
-    for msg in messages.iter_mut() {
+    for (msg_index, msg) in messages.iter_mut().enumerate() {
         // 只处理 assistant 消息
         // [CRITICAL FIX] Handle 'model' role too (Google history usage)
         if msg.role != "assistant" && msg.role != "model" {
@@ -68,7 +85,7 @@ fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>) {
             
             // 过滤并清理
             let mut new_blocks = Vec::new();
-            for block in blocks.drain(..) {
+            for (block_index, block) in blocks.drain(..).enumerate() {
                 if matches!(block, ContentBlock::Thinking { .. }) {
                     // [DEBUG] 强制输出日志
                     if let ContentBlock::Thinking { ref signature, .. } = block {
@@ -81,7 +98,11 @@ fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>) {
                         new_blocks.push(sanitize_thinking_block(block));
                     } else {
                         // 删除无效的 thinking 块
-                        tracing::warn!("[Claude-Handler] Dropping thinking block with invalid/missing signature");
+                        crate::proxy::common::warnings::record_warning(
+                            crate::proxy::common::warnings::codes::THINKING_BLOCK_DROPPED,
+                            "thinking block with invalid or missing signature removed from history",
+                            Some(format!("messages[{}].content[{}]", msg_index, block_index)),
+                        );
                     }
                 } else {
                     new_blocks.push(block);
@@ -145,8 +166,8 @@ enum RetryStrategy {
     FixedDelay(Duration),
     /// 线性退避：base_ms * (attempt + 1)
     LinearBackoff { base_ms: u64 },
-    /// 指数退避：base_ms * 2^attempt，上限 max_ms
-    ExponentialBackoff { base_ms: u64, max_ms: u64 },
+    /// 按配置退避：min(initial * multiplier^attempt + jitter, max)
+    ConfiguredBackoff,
 }
 
 /// 根据错误状态码和错误信息确定重试策略
@@ -156,6 +177,11 @@ fn determine_retry_strategy(
     retried_without_thinking: bool,
 ) -> RetryStrategy {
     match status_code {
+        // [NEW] 400 错误：采样参数被拒，属于请求本身问题，换账号无意义
+        400 if crate::proxy::common::sampling::is_sampling_param_error(status_code, error_text) => {
+            RetryStrategy::NoRetry
+        }
+
         // 400 错误：Thinking 签名失败
         400 if !retried_without_thinking
             && (error_text.contains("Invalid `signature`")
@@ -173,19 +199,13 @@ fn determine_retry_strategy(
                 let actual_delay = delay_ms.saturating_add(200).min(10_000);
                 RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
             } else {
-                // 否则使用线性退避：1s, 2s, 3s
-                RetryStrategy::LinearBackoff { base_ms: 1000 }
+                // 否则按配置退避，避免反复撞上同一限流窗口
+                RetryStrategy::ConfiguredBackoff
             }
         }
 
         // 503 服务不可用 / 529 服务器过载
-        503 | 529 => {
-            // 指数退避：1s, 2s, 4s, 8s
-            RetryStrategy::ExponentialBackoff {
-                base_ms: 1000,
-                max_ms: 8000,
-            }
-        }
+        503 | 529 => RetryStrategy::ConfiguredBackoff,
 
         // 500 服务器内部错误
         500 => {
@@ -202,17 +222,27 @@ fn determine_retry_strategy(
 }
 
 /// 执行退避策略并返回是否应该继续重试
+/// 已是最后一次尝试时只判断是否可重试，不再等待也不计入重试次数
 async fn apply_retry_strategy(
     strategy: RetryStrategy,
     attempt: usize,
+    max_attempts: usize,
     status_code: u16,
     trace_id: &str,
+    backoff: &crate::proxy::config::BackoffConfig,
 ) -> bool {
+    if matches!(strategy, RetryStrategy::NoRetry) {
+        debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
+        return false;
+    }
+    if attempt + 1 >= max_attempts {
+        debug!("[{}] Retryable error {} on the last attempt, not waiting", trace_id, status_code);
+        return true;
+    }
+    crate::proxy::metrics::record_retry(crate::proxy::metrics::retry_reason(status_code));
+
     match strategy {
-        RetryStrategy::NoRetry => {
-            debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
-            false
-        }
+        RetryStrategy::NoRetry => false,
 
         RetryStrategy::FixedDelay(duration) => {
             info!(
@@ -220,7 +250,7 @@ async fn apply_retry_strategy(
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
+                max_attempts,
                 duration.as_millis()
             );
             sleep(duration).await;
@@ -234,24 +264,24 @@ async fn apply_retry_strategy(
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
+                max_attempts,
                 delay_ms
             );
             sleep(Duration::from_millis(delay_ms)).await;
             true
         }
 
-        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
-            let delay_ms = (base_ms * 2_u64.pow(attempt as u32)).min(max_ms);
+        RetryStrategy::ConfiguredBackoff => {
+            let delay = crate::proxy::upstream::retry::backoff_delay(backoff, attempt);
             info!(
-                "[{}] ⏱️  Retry with exponential backoff: status={}, attempt={}/{}, waiting={}ms",
+                "[{}] ⏱️  Retry with configured backoff: status={}, attempt={}/{}, waiting={}ms",
                 trace_id,
                 status_code,
                 attempt + 1,
-                MAX_RETRY_ATTEMPTS,
-                delay_ms
+                max_attempts,
+                delay.as_millis()
             );
-            sleep(Duration::from_millis(delay_ms)).await;
+            sleep(delay).await;
             true
         }
     }
@@ -271,21 +301,46 @@ fn should_rotate_account(status_code: u16) -> bool {
 
 // ===== 退避策略模块结束 =====
 
-/// 处理 Claude messages 请求
-/// 
-/// 处理 Chat 消息请求流程
+/// 处理 Claude messages 请求 (外层为链路追踪根 span)
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(body): Json<Value>,
+) -> Response {
+    let span = crate::proxy::telemetry::request_span(
+        "anthropic",
+        body.get("model").and_then(|v| v.as_str()).unwrap_or(""),
+        body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false),
+        &headers,
+    );
+    crate::proxy::telemetry::instrument_handler(span, handle_messages_inner(State(state), headers, Json(body))).await
+}
+
+/// 处理 Chat 消息请求流程
+async fn handle_messages_inner(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
 ) -> Response {
     tracing::error!(">>> [RED ALERT] handle_messages called! Body JSON len: {}", body.to_string().len());
     
-    // 生成随机 Trace ID 用户追踪
-    let trace_id: String = rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
-        .take(6)
-        .map(char::from)
-        .collect::<String>().to_lowercase();
+    // Trace ID 复用请求的 X-Request-ID，与中间件 span 及上游 requestId 保持一致
+    let trace_id: String = crate::proxy::middleware::current_request_id().unwrap_or_else(|| {
+        rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
+            .take(6)
+            .map(char::from)
+            .collect::<String>()
+            .to_lowercase()
+    });
+    let log_mode = *state.prompt_log.read().await;
+
+    // 断线重连 (Last-Event-ID)：按续传处理或明确拒绝，不能静默重新生成一遍
+    if let Some(last_event_id) = headers
+        .get(resume::LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+    {
+        return resume_stream_response(last_event_id, &trace_id);
+    }
         
     // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
     let zai = state.zai.read().await.clone();
@@ -309,6 +364,14 @@ pub async fn handle_messages(
         }
     };
 
+    // [NEW] 可选的流式增量合并 (extra.min_chunk_chars / extra.flush_interval_ms)，默认关闭
+    let coalesce_config = CoalesceConfig::from_request(&body);
+    // [NEW] 可选的 "仅输出最终答案" 流式模式 (extra.stream_final_only / extra.hide_thinking)
+    let final_only_config = FinalOnlyConfig::from_request(&body);
+
+    // 未知字段 / 块类型不会导致解析失败，这里记录为警告
+    crate::proxy::common::ignored_fields::record_ignored_claude_fields(&body);
+
     // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
         Ok(r) => r,
@@ -326,10 +389,16 @@ pub async fn handle_messages(
         }
     };
 
+    request.drop_unsupported_blocks();
+
     // [CRITICAL FIX] 过滤并修复 Thinking 块签名
     filter_invalid_thinking_blocks(&mut request.messages);
 
     if use_zai {
+        // z.ai 透传不经过模型路由，按请求的模型名校验
+        if let Err(e) = crate::proxy::key_scope::check_model(&request.model, &request.model) {
+            return anthropic_model_not_allowed_response(&e);
+        }
         // 重新序列化修复后的请求体
         let new_body = match serde_json::to_value(&request) {
             Ok(v) => v,
@@ -418,18 +487,12 @@ pub async fn handle_messages(
     debug!("[{}] Message Count: {}", trace_id, request.messages.len());
     debug!("[{}] Has Tools: {}", trace_id, request.tools.is_some());
     debug!("[{}] Has Thinking Config: {}", trace_id, request.thinking.is_some());
-    debug!("[{}] Content Preview: {:.100}...", trace_id, latest_msg);
+    debug!("[{}] Content Preview: {}", trace_id, prompt_log::describe(log_mode, &latest_msg, 100));
     
     // 输出每一条消息的详细信息
     for (idx, msg) in request.messages.iter().enumerate() {
         let content_preview = match &msg.content {
-            crate::proxy::mappers::claude::models::MessageContent::String(s) => {
-                if s.len() > 200 {
-                    format!("{}... (total {} chars)", &s[..200], s.len())
-                } else {
-                    s.clone()
-                }
-            },
+            crate::proxy::mappers::claude::models::MessageContent::String(s) => prompt_log::describe(log_mode, s, 200),
             crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
                 format!("[Array with {} blocks]", arr.len())
             }
@@ -438,7 +501,9 @@ pub async fn handle_messages(
             trace_id, idx, msg.role, content_preview);
     }
     
-    debug!("[{}] Full Claude Request JSON: {}", trace_id, serde_json::to_string_pretty(&request).unwrap_or_default());
+    if prompt_log::content_logging_enabled(log_mode) {
+        debug!("[{}] Full Claude Request JSON: {}", trace_id, serde_json::to_string_pretty(&request).unwrap_or_default());
+    }
     debug!("========== [{}] CLAUDE REQUEST DEBUG END ==========", trace_id);
 
     // 1. 获取 会话 ID (已废弃基于内容的哈希，改用 TokenManager 内部的时间窗口锁定)
@@ -446,22 +511,36 @@ pub async fn handle_messages(
 
     // 2. 获取 UpstreamClient
     let upstream = state.upstream.clone();
+    let backoff = state.backoff.read().await.clone();
     
     // 3. 准备闭包
     let mut request_for_body = request.clone();
     let token_manager = state.token_manager;
     
-    let pool_size = token_manager.len();
+    // 只计算当前可调度的账号，避免在冷却中的账号上浪费重试次数
+    let pool_size = token_manager.available_len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
-    let mut last_error = String::new();
+    // 条件路由：别名按请求特征选择上游模型 (重试时沿用同一结果)
+    let route_model = model_routes::route_request(&request_for_body.model, || {
+        RequestTraits::from_client_body(&serde_json::to_value(&request_for_body).unwrap_or_default(), request_for_body.stream)
+    })
+    .map_or_else(|| request_for_body.model.clone(), |d| d.model);
+
+    let mut last_failure: Option<UpstreamFailure> = None;
     let mut retried_without_thinking = false;
     
     for attempt in 0..max_attempts {
+        // 全局重试预算耗尽时不再重试，直接 503
+        if attempt > 0 && !state.retry_budget.try_withdraw() {
+            tracing::warn!("[{}] Retry budget exhausted, giving up after {} attempt(s)", trace_id, attempt);
+            return anthropic_retry_budget_response();
+        }
+        crate::proxy::telemetry::record_attempt(attempt);
         // 2. 模型路由与配置解析 (提前解析以确定请求类型)
         // 先不应用家族映射，获取初步的 mapped_model
         let initial_mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &request_for_body.model,
+            &route_model,
             &*state.custom_mapping.read().await,
             &*state.openai_mapping.read().await,
             &*state.anthropic_mapping.read().await,
@@ -483,7 +562,7 @@ pub async fn handle_messages(
         let mut mapped_model = if is_cli_request {
             // CLI 请求：重新调用 resolve_model_route，应用家族映射
             crate::proxy::common::model_mapping::resolve_model_route(
-                &request_for_body.model,
+                &route_model,
                 &*state.custom_mapping.read().await,
                 &*state.openai_mapping.read().await,
                 &*state.anthropic_mapping.read().await,
@@ -494,36 +573,17 @@ pub async fn handle_messages(
             initial_mapped_model
         };
 
+        // 附加 Key 的模型白名单 (路由解析后、选择账号前)
+        if let Err(e) = crate::proxy::key_scope::check_model(&request_for_body.model, &mapped_model) {
+            return anthropic_model_not_allowed_response(&e);
+        }
+
         // 0. 尝试提取 session_id 用于粘性调度 (Phase 2/3)
         // 使用 SessionManager 生成稳定的会话指纹
-        let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
+        let session_id_str = crate::proxy::session_manager::SessionManager::session_key_from_headers(&headers)
+            .unwrap_or_else(|| crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body));
         let session_id = Some(session_id_str.as_str());
 
-        let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
-            Ok(t) => t,
-            Err(e) => {
-                let safe_message = if e.contains("invalid_grant") {
-                    "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
-                } else {
-                    e
-                };
-                 return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(json!({
-                        "type": "error",
-                        "error": {
-                            "type": "overloaded_error",
-                            "message": format!("No available accounts: {}", safe_message)
-                        }
-                    }))
-                ).into_response();
-            }
-        };
-
-        info!("✓ Using account: {} (type: {})", email, config.request_type);
-        
-        
         // ===== 【优化】后台任务智能检测与降级 =====
         // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略
         let background_task_type = detect_background_task_type(&request_for_body);
@@ -587,9 +647,12 @@ pub async fn handle_messages(
         // 生成 Trace ID (简单用时间戳后缀)
         // let _trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
 
-        let gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id) {
+        // project 在选定账号后填入
+        let mut gemini_body = match transform_claude_request_in(&request_with_mapped, "") {
             Ok(b) => {
-                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+                if prompt_log::content_logging_enabled(log_mode) {
+                    debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+                }
                 b
             },
             Err(e) => {
@@ -605,22 +668,96 @@ pub async fn handle_messages(
                 ).into_response();
             }
         };
-        
+
+        // [NEW] 按目标模型修正采样参数 (thinking 模型要求 temperature = 1 等)
+        let sampling_adjustments = crate::proxy::common::sampling::apply_sampling_constraints(
+            &mut gemini_body,
+            &request_with_mapped.model,
+        );
+        crate::proxy::common::sampling::record_sampling_warnings(&sampling_adjustments);
+
+        // [NEW] Prompt 体积预检：只依赖请求内容，在选择账号前拒绝 (不占用账号配额与并发)
+        let size_check = crate::proxy::common::prompt_size::check_prompt_size(
+            &gemini_body,
+            &request_with_mapped.model,
+            request_with_mapped.max_tokens,
+        );
+        crate::proxy::metrics::note_prompt_size(&request_with_mapped.model, &size_check.breakdown);
+        if !size_check.is_allowed() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": size_check.rejection_message(&request_with_mapped.model)
+                    }
+                }))
+            ).into_response();
+        }
+        // 上游未返回 usageMetadata 时用于回填 input_tokens
+        let estimated_input_tokens = size_check.estimated_tokens.min(u32::MAX as u64) as u32;
+
+        let force_rotate_token = attempt > 0;
+        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, Some(&request_with_mapped.model), force_rotate_token, session_id).await {
+            Ok(t) => t,
+            // 区分未配置账号 / 全部冷却 / 不健康 / 限速，冷却时附带 Retry-After
+            Err(e) => return anthropic_pool_error_response(&e),
+        };
+
+        info!("✓ Using account: {} (type: {})", email, config.request_type);
+        gemini_body["project"] = json!(project_id);
+
     // 4. 上游调用
     let is_stream = request.stream;
     let method = if is_stream { "streamGenerateContent" } else { "generateContent" };
     let query = if is_stream { Some("alt=sse") } else { None };
 
-    let response = match upstream.call_v1_internal(
+    // 按模型覆盖请求超时 (思考类模型通常需要更长时间)
+    let timeout = crate::proxy::config::resolve_model_timeout(
+        &*state.model_timeouts.read().await,
+        *state.request_timeout.read().await,
+        &[&request_with_mapped.model, &request.model],
+    );
+    let auth = AccountAuth {
+        token_manager: &token_manager,
+        email: &email,
+        access_token: &access_token,
+    };
+    let response = match upstream.call_v1_internal_with_auth_refresh(
+        auth,
         method,
-        &access_token,
         gemini_body,
-        query
+        query,
+        Some(timeout),
     ).await {
             Ok(r) => r,
             Err(e) => {
-                last_error = e.clone();
+                // 上游代理认证失败 (407)：换账号无意义，直接返回可区分的错误类型
+                if crate::proxy::upstream::client::is_proxy_auth_failure(&e) {
+                    return (
+                        StatusCode::BAD_GATEWAY,
+                        Json(json!({
+                            "type": "error",
+                            "error": {
+                                "type": "proxy_authentication_error",
+                                "message": e
+                            }
+                        }))
+                    ).into_response();
+                }
+                let failure = UpstreamFailure::transport(e.clone());
+                // TLS 证书校验失败 (多为 SSL 拦截代理)：换账号无意义，直接返回
+                if matches!(failure, UpstreamFailure::Tls(_)) {
+                    return anthropic_error_response(Some(&failure));
+                }
+                last_failure = Some(failure);
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                // 最后一次尝试失败后不再等待
+                if attempt + 1 < max_attempts {
+                    crate::proxy::metrics::record_retry("network");
+                    crate::proxy::upstream::retry::sleep_backoff(&backoff, attempt).await;
+                }
                 continue;
             }
         };
@@ -629,27 +766,69 @@ pub async fn handle_messages(
         
         // 成功
         if status.is_success() {
+            token_manager.report_success(&email);
+            token_manager.bind_session(&session_id_str, &email).await;
+            if attempt == 0 {
+                state.retry_budget.deposit();
+            }
             // 处理流式响应
             if request.stream {
-                let stream = response.bytes_stream();
-                let gemini_stream = Box::pin(stream);
-                let claude_stream = create_claude_sse_stream(gemini_stream, trace_id, email);
+                // 首个内容 token 之前断流时，在剩余重试次数内换号续流
+                let reopen = StreamReopenContext {
+                    token_manager: token_manager.clone(),
+                    upstream: upstream.clone(),
+                    request_type: config.request_type.clone(),
+                    model: request_with_mapped.model.clone(),
+                    session_id: session_id_str.clone(),
+                    timeout,
+                    trace_id: trace_id.clone(),
+                }
+                .into_reopen({
+                    let request = request_with_mapped.clone();
+                    move |project_id: &str| {
+                        let mut body = transform_claude_request_in(&request, project_id)?;
+                        crate::proxy::common::sampling::apply_sampling_constraints(&mut body, &request.model);
+                        Ok(body)
+                    }
+                });
+                let gemini_stream = with_stream_failover(
+                    Box::pin(token_manager.meter_stream(&email, response.bytes_stream())),
+                    max_attempts - attempt - 1,
+                    reopen,
+                );
+                let claude_stream = create_claude_sse_stream(
+                    gemini_stream,
+                    trace_id.clone(),
+                    email,
+                    estimated_input_tokens,
+                    coalesce_config.clone(),
+                    final_only_config.clone(),
+                    request_with_mapped.stop_sequences.clone().unwrap_or_default(),
+                );
 
                 // 转换为 Bytes stream
-                let sse_stream = claude_stream.map(|result| -> Result<Bytes, std::io::Error> {
+                let sse_stream = claude_stream.map(|result| -> Result<Bytes, String> {
                     match result {
                         Ok(bytes) => Ok(bytes),
-                        Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+                        Err(e) => Ok(stream_error_event(&e)),
                     }
                 });
 
-                return Response::builder()
+                // 开启续传时生成与客户端连接解耦 (断开后继续生成并缓存)，否则断开即中止上游
+                let body = if resume::store().is_enabled() {
+                    Body::from_stream(with_keepalive(resume::store().spawn_resumable(Box::pin(sse_stream))))
+                } else {
+                    Body::from_stream(with_keepalive(DisconnectAware::new(Box::pin(sse_stream), trace_id.clone())))
+                };
+                let mut resp = Response::builder()
                     .status(StatusCode::OK)
                     .header(header::CONTENT_TYPE, "text/event-stream")
                     .header(header::CACHE_CONTROL, "no-cache")
                     .header(header::CONNECTION, "keep-alive")
-                    .body(Body::from_stream(sse_stream))
+                    .body(body)
                     .unwrap();
+                attach_sampling_header(&mut resp, &sampling_adjustments);
+                return resp;
             } else {
                 // 处理非流式响应
                 let bytes = match response.bytes().await {
@@ -658,14 +837,17 @@ pub async fn handle_messages(
                 };
                 
                 // Debug print
-                if let Ok(text) = String::from_utf8(bytes.to_vec()) {
-                    debug!("Upstream Response for Claude request: {}", text);
+                if prompt_log::content_logging_enabled(log_mode) {
+                    if let Ok(text) = String::from_utf8(bytes.to_vec()) {
+                        debug!("Upstream Response for Claude request: {}", text);
+                    }
                 }
 
                 let gemini_resp: Value = match serde_json::from_slice(&bytes) {
                     Ok(v) => v,
                     Err(e) => return (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response(),
                 };
+                token_manager.record_response_tokens(&email, &gemini_resp);
 
                 // 解包 response 字段（v1internal 格式）
                 let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
@@ -677,7 +859,11 @@ pub async fn handle_messages(
                 };
                 
                 // 转换
-                let claude_response = match transform_response(&gemini_response) {
+                let claude_response = match transform_response(
+                    &gemini_response,
+                    estimated_input_tokens,
+                    request_with_mapped.stop_sequences.as_deref().unwrap_or_default(),
+                ) {
                     Ok(r) => r,
                     Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Transform error: {}", e)).into_response(),
                 };
@@ -698,7 +884,9 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return Json(claude_response).into_response();
+                let mut resp = Json(claude_response).into_response();
+                attach_sampling_header(&mut resp, &sampling_adjustments);
+                return resp;
             }
         }
         
@@ -708,13 +896,30 @@ pub async fn handle_messages(
         
         // 2. 获取错误文本并转移 Response 所有权
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_failure = Some(UpstreamFailure::status(status_code, error_text.clone(), retry_after.clone()));
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
         
         // 3. 标记限流状态（用于 UI 显示）
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
             token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
         }
+        token_manager.model_access().learn_from_error(&email, &request_with_mapped.model, status_code, &error_text);
+        token_manager.report_failure(&email, status_code);
+
+        // [NEW] 采样参数错误：以 Anthropic 格式直接返回，不进行重试/轮换
+        if crate::proxy::common::sampling::is_sampling_param_error(status_code, &error_text) {
+            error!("[{}] Upstream rejected sampling params, not retrying: {}", trace_id, error_text);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": error_text
+                    }
+                }))
+            ).into_response();
+        }
 
         // 4. 处理 400 错误 (Thinking 签名失效)
         // 由于已经主动过滤,这个错误应该很少发生
@@ -762,7 +967,7 @@ pub async fn handle_messages(
             
             // 使用统一退避策略
             let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
-            if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
+            if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &backoff).await {
                 continue;
             }
         }
@@ -776,7 +981,7 @@ pub async fn handle_messages(
         let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
         
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
+        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &backoff).await {
             // 判断是否需要轮换账号
             if !should_rotate_account(status_code) {
                 debug!("[{}] Keeping same account for status {} (server-side issue)", trace_id, status_code);
@@ -785,17 +990,43 @@ pub async fn handle_messages(
         } else {
             // 不可重试的错误，直接返回
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, error_text).into_response();
+            return anthropic_error_response(last_failure.as_ref());
         }
     }
     
-    (StatusCode::TOO_MANY_REQUESTS, Json(json!({
-        "type": "error",
-        "error": {
-            "type": "overloaded_error",
-            "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
+    // 所有尝试均失败：按最后一次上游失败映射 Anthropic 错误类型 (429 附带 Retry-After)
+    tracing::warn!("[{}] All {} attempts failed", trace_id, max_attempts);
+    anthropic_error_response(last_failure.as_ref())
+}
+
+/// 处理带 Last-Event-ID 的重连请求：续传缓存中的事件，无法续传时返回 409
+fn resume_stream_response(last_event_id: &str, trace_id: &str) -> Response {
+    match resume::store().resume(last_event_id) {
+        Ok(stream) => {
+            info!("[{}] Resuming stream after event {}", trace_id, last_event_id);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/event-stream")
+                .header(header::CACHE_CONTROL, "no-cache")
+                .header(header::CONNECTION, "keep-alive")
+                .body(Body::from_stream(with_keepalive(stream)))
+                .unwrap()
         }
-    }))).into_response()
+        Err(e) => {
+            info!("[{}] Rejected reconnect with Last-Event-ID {}: {:?}", trace_id, last_event_id, e);
+            (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": e.message()
+                    }
+                })),
+            )
+                .into_response()
+        }
+    }
 }
 
 /// 列出可用模型
@@ -807,6 +1038,8 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
         &state.custom_mapping,
         &state.anthropic_mapping,
     ).await;
+    // 附加 Key 只列出其可用的模型
+    let model_ids = super::common::filter_listed_models(&state, model_ids).await;
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
         json!({
@@ -823,7 +1056,8 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
-/// 计算 tokens (占位符)
+/// 计算 tokens: POST /v1/messages/count_tokens
+/// 转换为 Gemini contents 后调用上游 countTokens，只统计输入 Token
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -833,6 +1067,11 @@ pub async fn handle_count_tokens(
     let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
 
     if zai_enabled {
+        // z.ai 透传不经过模型路由，按请求的模型名校验
+        let model = body.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+        if let Err(e) = crate::proxy::key_scope::check_model(model, model) {
+            return anthropic_model_not_allowed_response(&e);
+        }
         return crate::proxy::providers::zai_anthropic::forward_anthropic_json(
             &state,
             axum::http::Method::POST,
@@ -843,13 +1082,191 @@ pub async fn handle_count_tokens(
         .await;
     }
 
+    let request: ClaudeRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)).into_response(),
+    };
+
+    // 与 handle_messages 相同的模型路由：CLI 请求应用 Claude 家族映射
+    let tools_val: Option<Vec<Value>> = request.tools.as_ref().map(|list| {
+        list.iter().map(|t| serde_json::to_value(t).unwrap_or(json!({}))).collect()
+    });
+    let initial_mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &request.model,
+        &*state.custom_mapping.read().await,
+        &*state.openai_mapping.read().await,
+        &*state.anthropic_mapping.read().await,
+        false,
+    );
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(&request.model, &initial_mapped_model, &tools_val);
+    let mapped_model = if config.request_type == "agent" {
+        crate::proxy::common::model_mapping::resolve_model_route(
+            &request.model,
+            &*state.custom_mapping.read().await,
+            &*state.openai_mapping.read().await,
+            &*state.anthropic_mapping.read().await,
+            true,
+        )
+    } else {
+        initial_mapped_model
+    };
+    if let Err(e) = crate::proxy::key_scope::check_model(&request.model, &mapped_model) {
+        return anthropic_model_not_allowed_response(&e);
+    }
+
+    let mut request_with_mapped = request;
+    request_with_mapped.model = mapped_model;
+    let counted = super::common::count_input_tokens(&state, &config.request_type, &request_with_mapped.model, |project_id| {
+        transform_claude_request_in(&request_with_mapped, project_id)
+    })
+    .await;
+
+    match counted {
+        Ok(input_tokens) => Json(json!({ "input_tokens": input_tokens })).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+static BATCH_CONFIG: once_cell::sync::Lazy<std::sync::RwLock<MessageBatchConfig>> =
+    once_cell::sync::Lazy::new(|| std::sync::RwLock::new(MessageBatchConfig::default()));
+
+/// 应用 Message Batches 配置 (代理启动与配置热更新时调用)
+pub fn configure_message_batches(config: &MessageBatchConfig) {
+    if let Ok(mut current) = BATCH_CONFIG.write() {
+        *current = config.clone();
+    }
+}
+
+fn batch_invalid_request(message: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "message": message
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Anthropic Message Batches API: POST /v1/message_batches
+/// 同步处理：每个条目独立走 /v1/messages 的完整管线 (含 429 时的账号轮换重试)，
+/// 单个条目失败不影响其他条目，全部完成后一次性返回结果
+pub async fn handle_message_batches(
+    State(state): State<AppState>,
+    mut headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let batch: MessageBatchRequest = match serde_json::from_value(body) {
+        Ok(b) => b,
+        Err(e) => return batch_invalid_request(format!("Invalid batch body: {}", e)),
+    };
+    let config = BATCH_CONFIG.read().map(|c| c.clone()).unwrap_or_default();
+    if batch.requests.is_empty() {
+        return batch_invalid_request("requests must not be empty".to_string());
+    }
+    if batch.requests.len() > config.max_requests {
+        return batch_invalid_request(format!(
+            "Too many requests in batch: {} (max {})",
+            batch.requests.len(),
+            config.max_requests
+        ));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if let Some(dup) = batch.requests.iter().find(|item| !seen.insert(item.custom_id.as_str())) {
+        return batch_invalid_request(format!("Duplicate custom_id: {}", dup.custom_id));
+    }
+
+    // 条目不是断线重连
+    headers.remove(resume::LAST_EVENT_ID_HEADER);
+    let batch_id = crate::proxy::middleware::current_request_id()
+        .unwrap_or_else(|| RequestId::generate().as_str().to_string());
+    let model_scope = crate::proxy::key_scope::current_scope();
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(config.max_concurrency.max(1)));
+    info!("[Batch] {} 条目开始处理 (并发 {})", batch.requests.len(), config.max_concurrency.max(1));
+
+    let custom_ids: Vec<String> = batch.requests.iter().map(|item| item.custom_id.clone()).collect();
+    let tasks = batch.requests.into_iter().enumerate().map(|(index, item)| {
+        let state = state.clone();
+        let headers = headers.clone();
+        let semaphore = semaphore.clone();
+        // 每个条目使用独立的请求 ID (上游 requestId 不能在条目间复用)
+        let request_id = RequestId(format!("{}-{}", batch_id, index));
+        let fut = scope_request(
+            request_id,
+            None,
+            crate::proxy::key_scope::with_captured_scope(model_scope.clone(), async move {
+                let _permit = semaphore.acquire_owned().await;
+                crate::proxy::account_concurrency::scope_account_permit(run_batch_item(state, headers, item.params)).await
+            }),
+        );
+        tokio::spawn(fut)
+    });
+    let outcomes = futures::future::join_all(tasks).await;
+
+    let results: Vec<BatchResult> = custom_ids
+        .into_iter()
+        .zip(outcomes)
+        .map(|(custom_id, outcome)| BatchResult {
+            custom_id,
+            result: outcome.unwrap_or_else(|e| BatchResultBody::Errored {
+                error: json!({ "type": "api_error", "message": format!("Batch item task failed: {}", e) }),
+            }),
+        })
+        .collect();
+    let succeeded = results
+        .iter()
+        .filter(|r| matches!(r.result, BatchResultBody::Succeeded { .. }))
+        .count();
+    let errored = results.len() - succeeded;
+    info!("[Batch] 处理完成: {} 成功, {} 失败", succeeded, errored);
+
     Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
+        "id": format!("msgbatch_{}", batch_id),
+        "type": "message_batch",
+        "processing_status": "ended",
+        "request_counts": {
+            "processing": 0,
+            "succeeded": succeeded,
+            "errored": errored,
+            "canceled": 0,
+            "expired": 0
+        },
+        "results": results
     }))
     .into_response()
 }
 
+/// 以非流式请求执行单个批处理条目
+async fn run_batch_item(state: AppState, headers: HeaderMap, mut params: ClaudeRequest) -> BatchResultBody {
+    params.stream = false;
+    let body = match serde_json::to_value(&params) {
+        Ok(v) => v,
+        Err(e) => {
+            return BatchResultBody::Errored {
+                error: json!({ "type": "invalid_request_error", "message": e.to_string() }),
+            }
+        }
+    };
+    let response = handle_messages(State(state), headers, Json(body)).await;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    let value = serde_json::from_slice::<Value>(&bytes).unwrap_or_else(|_| {
+        json!({ "type": "api_error", "message": String::from_utf8_lossy(&bytes) })
+    });
+    if status.is_success() {
+        BatchResultBody::Succeeded { message: value }
+    } else {
+        BatchResultBody::Errored {
+            error: value.get("error").cloned().unwrap_or(value),
+        }
+    }
+}
+
 // 移除已失效的简单单元测试，后续将补全完整的集成测试
 /*
 #[cfg(test)]
@@ -860,6 +1277,17 @@ mod tests {
     async fn test_handle_list_models() {
         // handle_list_models 现在需要 AppState，此处跳过旧的单元测试
     }
+
+    #[tokio::test]
+    async fn test_reconnect_rejected_when_resume_disabled() {
+        // 默认未开启续传：带 Last-Event-ID 的重连必须明确拒绝而不是重新生成
+        let resp = resume_stream_response("msg_1:3", "trace");
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["type"], "invalid_request_error");
+        assert!(json["error"]["message"].as_str().unwrap().contains("Last-Event-ID"));
+    }
 }
 */
 
@@ -1010,3 +1438,21 @@ fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
         BackgroundTaskType::ContextCompression => "gemini-2.5-flash",   // 复杂压缩
     }
 }
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_last_attempt_does_not_back_off() {
+        let backoff = crate::proxy::config::BackoffConfig {
+            initial_delay_ms: 60_000,
+            max_delay_ms: 60_000,
+            ..Default::default()
+        };
+        // 最后一次尝试：可重试的错误立即返回，不再等待
+        let last = apply_retry_strategy(RetryStrategy::ConfiguredBackoff, 2, 3, 503, "t", &backoff);
+        assert!(tokio::time::timeout(Duration::from_secs(1), last).await.unwrap());
+        assert!(!apply_retry_strategy(RetryStrategy::NoRetry, 0, 3, 404, "t", &backoff).await);
+    }
+}
@@ -49,3 +49,58 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+/// 按当前请求 Key 的模型白名单过滤模型列表 (/v1/models 等)
+/// 按解析后的模型名校验时，逐个解析路由后再判断
+pub async fn filter_listed_models(state: &AppState, model_ids: Vec<String>) -> Vec<String> {
+    let scope = match crate::proxy::key_scope::current_scope() {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return model_ids,
+        // 缺少作用域上下文时不暴露任何模型
+        Err(_) => return Vec::new(),
+    };
+    if !scope.check_resolved_model {
+        return model_ids.into_iter().filter(|id| scope.check(id, id).is_ok()).collect();
+    }
+    let custom_mapping = state.custom_mapping.read().await;
+    let openai_mapping = state.openai_mapping.read().await;
+    let anthropic_mapping = state.anthropic_mapping.read().await;
+    model_ids
+        .into_iter()
+        .filter(|id| {
+            let resolved = crate::proxy::common::model_mapping::resolve_model_route(
+                id,
+                &custom_mapping,
+                &openai_mapping,
+                &anthropic_mapping,
+                false,
+            );
+            scope.check(id, &resolved).is_ok()
+        })
+        .collect()
+}
+
+/// 统计输入 Token (countTokens 端点共用)
+/// 按 request_type 选择一个账号 (跳过已知无权访问 `model` 的账号) 后只请求一次，不进入重试循环：账号不可用或上游失败时返回 503
+/// `build_body` 根据账号的 project_id 构造 generateContent 请求体
+pub async fn count_input_tokens<F>(
+    state: &AppState,
+    request_type: &str,
+    model: &str,
+    build_body: F,
+) -> Result<u64, (StatusCode, String)>
+where
+    F: FnOnce(&str) -> Result<Value, String>,
+{
+    let (access_token, project_id, email) = state
+        .token_manager
+        .get_token(request_type, Some(model), false, None)
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
+    let body = build_body(&project_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    state.upstream.count_tokens(&access_token, &body).await.map_err(|e| {
+        tracing::warn!("countTokens failed on account {}: {}", email, e);
+        (StatusCode::SERVICE_UNAVAILABLE, format!("countTokens failed: {}", e))
+    })
+}
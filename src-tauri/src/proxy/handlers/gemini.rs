@@ -1,19 +1,68 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Json, Path}, http::{HeaderMap, StatusCode}, response::{IntoResponse, Response}};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::common::model_routes::{self, RequestTraits};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
+use crate::proxy::upstream::client::AccountAuth;
  
 const MAX_RETRY_ATTEMPTS: usize = 3;
  
+/// 将上游 v1internal SSE 行还原为原生 Gemini SSE 事件 (去掉 response 包装)
+/// 非 data 行与无法解析的行原样透传
+fn unwrap_sse_line(line: &str) -> String {
+    let Some(json_part) = line.strip_prefix("data:").map(str::trim) else {
+        // Non-data lines (comments, etc.)
+        return format!("{}\n\n", line);
+    };
+    if json_part == "[DONE]" {
+        return "data: [DONE]\n\n".to_string();
+    }
+    match serde_json::from_str::<Value>(json_part) {
+        Ok(mut json) => {
+            // Unwrap v1internal response wrapper
+            let inner = json.get_mut("response").map(|v| v.take()).unwrap_or(json);
+            format!("data: {}\n\n", serde_json::to_string(&inner).unwrap_or_default())
+        }
+        Err(e) => {
+            debug!("[Gemini-SSE] JSON parse error: {}, passing raw line", e);
+            format!("{}\n\n", line)
+        }
+    }
+}
+
 /// 处理 generateContent 和 streamGenerateContent
 /// 路径参数: model_name, method (e.g. "gemini-pro", "generateContent")
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>
+) -> Response {
+    let (model, method) = model_action
+        .rsplit_once(':')
+        .unwrap_or((model_action.as_str(), "generateContent"));
+    let span = crate::proxy::telemetry::request_span(
+        "gemini",
+        model,
+        method == "streamGenerateContent",
+        &headers,
+    );
+    let handler = async move {
+        handle_generate_inner(State(state), Path(model_action), headers, Json(body))
+            .await
+            .into_response()
+    };
+    crate::proxy::telemetry::instrument_handler(span, handler).await
+}
+
+async fn handle_generate_inner(
+    State(state): State<AppState>,
+    Path(model_action): Path<String>,
+    headers: HeaderMap,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
@@ -34,15 +83,26 @@ pub async fn handle_generate(
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
+    // 只计算当前可调度的账号，避免在冷却中的账号上浪费重试次数
+    let pool_size = token_manager.available_len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     
+    // 条件路由：别名按请求特征选择上游模型 (重试时沿用同一结果)
+    let route_model = model_routes::route_request(&model_name, || RequestTraits::from_client_body(&body, is_stream))
+        .map_or_else(|| model_name.clone(), |d| d.model);
+
     let mut last_error = String::new();
 
     for attempt in 0..max_attempts {
+        // 全局重试预算耗尽时不再重试，直接 503
+        if attempt > 0 && !state.retry_budget.try_withdraw() {
+            tracing::warn!("Retry budget exhausted, giving up after {} attempt(s)", attempt);
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "Retry budget exhausted, retry shortly".to_string()));
+        }
+        crate::proxy::telemetry::record_attempt(attempt);
         // 3. 模型路由与配置解析
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &model_name,
+            &route_model,
             &*state.custom_mapping.read().await,
             &*state.openai_mapping.read().await,
             &*state.anthropic_mapping.read().await,
@@ -63,12 +123,33 @@ pub async fn handle_generate(
 
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&model_name, &mapped_model, &tools_val);
 
+        // 附加 Key 的模型白名单 (路由解析后、选择账号前)
+        if let Err(e) = crate::proxy::key_scope::check_model(&model_name, &mapped_model) {
+            return Err((StatusCode::FORBIDDEN, e.message()));
+        }
+
         // 4. 获取 Token (使用准确的 request_type)
         // 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+        let session_id = SessionManager::session_key_from_headers(&headers)
+            .unwrap_or_else(|| SessionManager::extract_gemini_session_id(&body, &model_name));
+
+        // 5. 包装请求 (project 在选定账号后填入)
+        let mut wrapped_body = wrap_request(&body, "", &mapped_model);
+
+        // [NEW] Prompt 体积预检：只依赖请求内容，在选择账号前拒绝 (不占用账号配额与并发)
+        let max_output = body
+            .get("generationConfig")
+            .and_then(|c| c.get("maxOutputTokens"))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32);
+        let size_check = crate::proxy::common::prompt_size::check_prompt_size(&wrapped_body, &mapped_model, max_output);
+        crate::proxy::metrics::note_prompt_size(&mapped_model, &size_check.breakdown);
+        if !size_check.is_allowed() {
+            return Err((StatusCode::BAD_REQUEST, size_check.rejection_message(&mapped_model)));
+        }
 
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, Some(&mapped_model), attempt > 0, Some(&session_id)).await {
             Ok(t) => t,
             Err(e) => {
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
@@ -76,19 +157,33 @@ pub async fn handle_generate(
         };
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
-
-        // 5. 包装请求 (project injection)
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        wrapped_body["project"] = json!(project_id);
 
         // 5. 上游调用
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
+        let timeout = crate::proxy::config::resolve_model_timeout(
+            &*state.model_timeouts.read().await,
+            *state.request_timeout.read().await,
+            &[&mapped_model, &model_name],
+        );
+        let auth = AccountAuth {
+            token_manager: &token_manager,
+            email: &email,
+            access_token: &access_token,
+        };
         let response = match upstream
-            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
+            .call_v1_internal_with_auth_refresh(auth, upstream_method, wrapped_body, query_string, Some(timeout))
             .await {
                 Ok(r) => r,
                 Err(e) => {
+                    // 上游代理认证失败 (407) / TLS 证书校验失败：换账号无意义，直接返回
+                    if crate::proxy::upstream::client::is_proxy_auth_failure(&e)
+                        || crate::proxy::upstream::client::is_tls_failure(&e)
+                    {
+                        return Err((StatusCode::BAD_GATEWAY, e));
+                    }
                     last_error = e.clone();
                     debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
                     continue;
@@ -97,6 +192,11 @@ pub async fn handle_generate(
 
         let status = response.status();
         if status.is_success() {
+            token_manager.report_success(&email);
+            token_manager.bind_session(&session_id, &email).await;
+            if attempt == 0 {
+                state.retry_budget.deposit();
+            }
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
@@ -104,7 +204,7 @@ pub async fn handle_generate(
                 use bytes::{Bytes, BytesMut};
                 use futures::StreamExt;
                 
-                let mut response_stream = response.bytes_stream();
+                let mut response_stream = Box::pin(token_manager.meter_stream(&email, response.bytes_stream()));
                 let mut buffer = BytesMut::new();
 
                 let stream = async_stream::stream! {
@@ -118,33 +218,7 @@ pub async fn handle_generate(
                                     if let Ok(line_str) = std::str::from_utf8(&line_raw) {
                                         let line = line_str.trim();
                                         if line.is_empty() { continue; }
-                                        
-                                        if line.starts_with("data: ") {
-                                            let json_part = line.trim_start_matches("data: ").trim();
-                                            if json_part == "[DONE]" {
-                                                yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
-                                                continue;
-                                            }
-                                            
-                                            match serde_json::from_str::<Value>(json_part) {
-                                                Ok(mut json) => {
-                                                    // Unwrap v1internal response wrapper
-                                                    if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
-                                                        let new_line = format!("data: {}\n\n", serde_json::to_string(&inner).unwrap_or_default());
-                                                        yield Ok::<Bytes, String>(Bytes::from(new_line));
-                                                    } else {
-                                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&json).unwrap_or_default())));
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    debug!("[Gemini-SSE] JSON parse error: {}, passing raw line", e);
-                                                    yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                                }
-                                            }
-                                        } else {
-                                            // Non-data lines (comments, etc.)
-                                            yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                        }
+                                        yield Ok::<Bytes, String>(Bytes::from(unwrap_sse_line(line)));
                                     } else {
                                         // Non-UTF8 data? Just pass it through or skip
                                         debug!("[Gemini-SSE] Non-UTF8 line encountered");
@@ -160,7 +234,12 @@ pub async fn handle_generate(
                     }
                 };
                 
-                let body = Body::from_stream(stream);
+                let body = Body::from_stream(crate::proxy::common::sse_keepalive::with_keepalive(
+                    crate::proxy::upstream::cancel::DisconnectAware::new(
+                        Box::pin(stream),
+                        crate::proxy::middleware::current_request_id().unwrap_or_else(|| "gemini".to_string()),
+                    ),
+                ));
                 return Ok(Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
@@ -174,6 +253,7 @@ pub async fn handle_generate(
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            token_manager.record_response_tokens(&email, &gemini_resp);
 
             let unwrapped = unwrap_response(&gemini_resp);
             return Ok(Json(unwrapped).into_response());
@@ -184,11 +264,13 @@ pub async fn handle_generate(
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+        token_manager.report_failure(&email, status_code);
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
             // 记录限流信息 (全局同步)
             token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+            token_manager.model_access().learn_from_error(&email, &mapped_model, status_code, &error_text);
 
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
             if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
@@ -217,6 +299,8 @@ pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl In
         &state.custom_mapping,
         &state.anthropic_mapping,
     ).await;
+    // 附加 Key 只列出其可用的模型
+    let model_ids = super::common::filter_listed_models(&state, model_ids).await;
 
     // 转换为 Gemini API 格式
     let models: Vec<_> = model_ids.into_iter().map(|id| {
@@ -227,7 +311,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl In
             "description": "",
             "inputTokenLimit": 128000,
             "outputTokenLimit": 8192,
-            "supportedGenerationMethods": ["generateContent", "countTokens"],
+            "supportedGenerationMethods": ["generateContent", "streamGenerateContent", "countTokens"],
             "temperature": 1.0,
             "topP": 0.95,
             "topK": 64
@@ -244,10 +328,41 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
     }))
 }
 
-pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
+pub async fn handle_count_tokens(State(state): State<AppState>, Path(model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &model_name,
+        &*state.custom_mapping.read().await,
+        &*state.openai_mapping.read().await,
+        &*state.anthropic_mapping.read().await,
+        false,
+    );
+    // 附加 Key 的模型白名单 (选择账号前)
+    if let Err(e) = crate::proxy::key_scope::check_model(&model_name, &mapped_model) {
+        return Err((StatusCode::FORBIDDEN, e.message()));
+    }
     let model_group = "gemini";
-    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None).await
+    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, Some(&mapped_model), false, None).await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
     
     Ok(Json(json!({"totalTokens": 0})))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap_sse_line_strips_envelope() {
+        let line = r#"data: {"response":{"candidates":[{"content":{"parts":[{"text":"Hi"}]}}]},"traceId":"t1"}"#;
+        let out = unwrap_sse_line(line);
+        let json: Value = serde_json::from_str(out.trim_start_matches("data: ").trim()).unwrap();
+        assert_eq!(json["candidates"][0]["content"]["parts"][0]["text"], "Hi");
+        assert!(json.get("traceId").is_none());
+        assert!(out.ends_with("\n\n"));
+
+        // 已是原生格式 / 非 data 行 / 非法 JSON 原样透传
+        assert_eq!(unwrap_sse_line(r#"data: {"candidates":[]}"#), "data: {\"candidates\":[]}\n\n");
+        assert_eq!(unwrap_sse_line(": keep-alive"), ": keep-alive\n\n");
+        assert_eq!(unwrap_sse_line("data: {oops"), "data: {oops\n\n");
+    }
+}
@@ -16,14 +16,10 @@ fn build_client(
     upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
     timeout_secs: u64,
 ) -> Result<reqwest::Client, String> {
-    let mut builder = reqwest::Client::builder()
+    let builder = reqwest::Client::builder()
         .timeout(Duration::from_secs(timeout_secs.max(5)));
-
-    if upstream_proxy.enabled && !upstream_proxy.url.is_empty() {
-        let proxy = reqwest::Proxy::all(&upstream_proxy.url)
-            .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
-        builder = builder.proxy(proxy);
-    }
+    let builder = crate::utils::http::apply_upstream_proxy(builder, Some(&upstream_proxy))
+        .map_err(|e| format!("Invalid upstream proxy: {}", e))?;
 
     builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
 }
@@ -59,7 +55,8 @@ async fn forward_mcp(
     }
 
     let upstream_proxy = state.upstream_proxy.read().await.clone();
-    let client = match build_client(upstream_proxy, state.request_timeout) {
+    let request_timeout = *state.request_timeout.read().await;
+    let client = match build_client(upstream_proxy, request_timeout) {
         Ok(c) => c,
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     };
@@ -334,7 +331,7 @@ async fn handle_vision_post(state: AppState, headers: HeaderMap, body: Body) ->
 
             let zai = state.zai.read().await.clone();
             let upstream_proxy = state.upstream_proxy.read().await.clone();
-            let timeout = state.request_timeout;
+            let timeout = *state.request_timeout.read().await;
 
             match crate::proxy::zai_vision_tools::call_tool(
                 &zai,
@@ -1,24 +1,95 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::Json,
+    extract::Path,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
 use base64::Engine as _;
 use serde_json::{json, Value};
+use std::path::PathBuf;
 use tracing::{debug, error, info}; // Import Engine trait for encode method
 
 use crate::proxy::mappers::openai::{
-    transform_openai_request, transform_openai_response, OpenAIRequest,
+    annotate_finish_details, estimate_usage, transform_openai_request, transform_openai_response, validate_candidate_count,
+    LegacyCompletionRequest, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::common::prompt_log;
+use crate::proxy::common::sampling::attach_sampling_header;
+use crate::proxy::common::image_files;
+use crate::proxy::common::model_routes::{self, RequestTraits};
+use crate::proxy::common::safety::safety_settings;
+use crate::proxy::common::sse_keepalive::with_keepalive;
+use crate::proxy::mappers::gemini::models::{GenerationConfig, V1InternalRequest};
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::cancel::DisconnectAware;
+use crate::proxy::upstream::client::AccountAuth;
+use crate::proxy::upstream::errors::{
+    openai_error_response, openai_model_not_allowed_response, openai_pool_error_response,
+    openai_retry_budget_response, UpstreamFailure,
+};
+use crate::proxy::upstream::failover::{with_stream_failover, StreamReopenContext};
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
+
+/// 上游拒绝 responseSchema 时 (400，部分模型为 500) 按 400 透传上游信息，不再换号重试
+fn response_schema_rejection(req: &OpenAIRequest, status: u16, error_text: &str) -> Option<Response> {
+    let uses_schema = req.response_format.as_ref().is_some_and(|f| f.has_schema());
+    if !uses_schema || !crate::proxy::common::json_schema::is_response_schema_error(status, error_text) {
+        return None;
+    }
+    error!("Upstream rejected response_format schema ({}): {}", status, error_text);
+    Some(openai_error_response(Some(&UpstreamFailure::status(400, error_text, None))))
+}
+
+/// response_format 中无法转换为 Gemini responseSchema 的特性直接以 400 拒绝
+fn validate_response_format(req: &OpenAIRequest) -> Result<(), (StatusCode, String)> {
+    match &req.response_format {
+        Some(fmt) => fmt
+            .gemini_schema()
+            .map(|_| ())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e)),
+        None => Ok(()),
+    }
+}
 use crate::proxy::session_manager::SessionManager;
 
+/// 处理 Chat Completions 请求 (外层为链路追踪根 span)
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let span = crate::proxy::telemetry::request_span(
+        "openai",
+        body.get("model").and_then(|v| v.as_str()).unwrap_or(""),
+        body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false),
+        &headers,
+    );
+    let handler = async move {
+        match handle_chat_completions_inner(State(state), headers, Json(body)).await {
+            Ok(resp) => resp.into_response(),
+            // 本地错误同样使用 OpenAI 错误结构
+            Err((status, message)) => {
+                crate::proxy::upstream::errors::openai_error_for_status(status, message)
+            }
+        }
+    };
+    crate::proxy::telemetry::instrument_handler(span, handler).await
+}
+
+async fn handle_chat_completions_inner(
+    State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    crate::proxy::common::ignored_fields::record_ignored_openai_fields(&body);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    validate_response_format(&openai_req)?;
+    let log_mode = *state.prompt_log.read().await;
 
     // Safety: Ensure messages is not empty
     if openai_req.messages.is_empty() {
@@ -30,6 +101,7 @@ pub async fn handle_chat_completions(
                 content: Some(crate::proxy::mappers::openai::OpenAIContent::String(
                     " ".to_string(),
                 )),
+                reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
@@ -38,18 +110,36 @@ pub async fn handle_chat_completions(
 
     debug!("Received OpenAI request for model: {}", openai_req.model);
 
+    // 远程图片按配置下载并内联 (在重试循环外只做一次)
+    let upstream_proxy = state.upstream_proxy.read().await.clone();
+    crate::proxy::mappers::openai::vision::inline_remote_images(&mut openai_req, Some(&upstream_proxy)).await;
+
     // 1. 获取 UpstreamClient (Clone handle)
     let upstream = state.upstream.clone();
+    let backoff = state.backoff.read().await.clone();
     let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
+    // 只计算当前可调度的账号，避免在冷却中的账号上浪费重试次数
+    let pool_size = token_manager.available_len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
-    let mut last_error = String::new();
+    // 条件路由：别名按请求特征选择上游模型 (重试时沿用同一结果)
+    let route_model = model_routes::route_request(&openai_req.model, || {
+        RequestTraits::from_client_body(&serde_json::to_value(&openai_req).unwrap_or_default(), openai_req.stream)
+    })
+    .map_or_else(|| openai_req.model.clone(), |d| d.model);
+
+    let mut last_failure: Option<UpstreamFailure> = None;
 
     for attempt in 0..max_attempts {
+        // 全局重试预算耗尽时不再重试，直接 503
+        if attempt > 0 && !state.retry_budget.try_withdraw() {
+            tracing::warn!("Retry budget exhausted, giving up after {} attempt(s)", attempt);
+            return Ok(openai_retry_budget_response());
+        }
+        crate::proxy::telemetry::record_attempt(attempt);
         // 2. 预解析模型路由与配置
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
+            &route_model,
             &*state.custom_mapping.read().await,
             &*state.openai_mapping.read().await,
             &*state.anthropic_mapping.read().await,
@@ -65,34 +155,61 @@ pub async fn handle_chat_completions(
             &mapped_model,
             &tools_val,
         );
+        validate_candidate_count(&openai_req, &config.final_model).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+        // 附加 Key 的模型白名单 (路由解析后、选择账号前)
+        if let Err(e) = crate::proxy::key_scope::check_model(&openai_req.model, &mapped_model) {
+            return Ok(openai_model_not_allowed_response(&e));
+        }
 
         // 3. 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+        let session_id = SessionManager::session_key_from_headers(&headers)
+            .unwrap_or_else(|| SessionManager::extract_openai_session_id(&openai_req));
+
+        // 4. 转换请求 (project 在选定账号后填入)
+        let mut gemini_body = transform_openai_request(&openai_req, "", &mapped_model);
 
-        // 4. 获取 Token (使用准确的 request_type)
+        // [NEW] 按目标模型修正采样参数 (thinking 模型会拒绝部分 temperature/top_p 组合)
+        let sampling_adjustments = crate::proxy::common::sampling::apply_sampling_constraints(
+            &mut gemini_body,
+            &mapped_model,
+        );
+        crate::proxy::common::sampling::record_sampling_warnings(&sampling_adjustments);
+
+        // [New] 打印转换后的报文 (Gemini Body) 供调试 (仅 preview 日志模式)
+        if prompt_log::content_logging_enabled(log_mode) {
+            if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
+                debug!("[OpenAI-Request] Transformed Gemini Body:\n{}", body_json);
+            }
+        }
+
+        // [NEW] Prompt 体积预检：只依赖请求内容，在选择账号前拒绝 (不占用账号配额与并发)
+        let size_check = crate::proxy::common::prompt_size::check_prompt_size(
+            &gemini_body,
+            &mapped_model,
+            openai_req.max_tokens,
+        );
+        crate::proxy::metrics::note_prompt_size(&mapped_model, &size_check.breakdown);
+        if !size_check.is_allowed() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                size_check.rejection_message(&mapped_model),
+            ));
+        }
+
+        // 获取 Token (使用准确的 request_type)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
         let (access_token, project_id, email) = match token_manager
-            .get_token(&config.request_type, attempt > 0, Some(&session_id))
+            .get_token(&config.request_type, Some(&mapped_model), attempt > 0, Some(&session_id))
             .await
         {
             Ok(t) => t,
-            Err(e) => {
-                return Err((
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    format!("Token error: {}", e),
-                ));
-            }
+            // 区分未配置账号 / 全部冷却 / 不健康 / 限速，冷却时附带 Retry-After
+            Err(e) => return Ok(openai_pool_error_response(&e)),
         };
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
-
-        // 4. 转换请求
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
-
-        // [New] 打印转换后的报文 (Gemini Body) 供调试
-        if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
-            debug!("[OpenAI-Request] Transformed Gemini Body:\n{}", body_json);
-        }
+        gemini_body["project"] = json!(project_id);
 
         // 5. 发送请求
         let list_response = openai_req.stream;
@@ -103,25 +220,55 @@ pub async fn handle_chat_completions(
         };
         let query_string = if list_response { Some("alt=sse") } else { None };
 
+        // 按模型覆盖请求超时 (思考类模型通常需要更长时间)
+        let timeout = crate::proxy::config::resolve_model_timeout(
+            &*state.model_timeouts.read().await,
+            *state.request_timeout.read().await,
+            &[&mapped_model, &openai_req.model],
+        );
+        let auth = AccountAuth {
+            token_manager: &token_manager,
+            email: &email,
+            access_token: &access_token,
+        };
         let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+            .call_v1_internal_with_auth_refresh(auth, method, gemini_body, query_string, Some(timeout))
             .await
         {
             Ok(r) => r,
             Err(e) => {
-                last_error = e.clone();
+                // 上游代理认证失败 (407)：换账号无意义，直接返回
+                if crate::proxy::upstream::client::is_proxy_auth_failure(&e) {
+                    return Err((StatusCode::BAD_GATEWAY, e));
+                }
+                let failure = UpstreamFailure::transport(e.clone());
+                // TLS 证书校验失败 (多为 SSL 拦截代理)：换账号无意义，直接返回
+                if matches!(failure, UpstreamFailure::Tls(_)) {
+                    return Ok(openai_error_response(Some(&failure)));
+                }
+                last_failure = Some(failure);
                 debug!(
                     "OpenAI Request failed on attempt {}/{}: {}",
                     attempt + 1,
                     max_attempts,
                     e
                 );
+                // 最后一次尝试失败后不再等待
+                if attempt + 1 < max_attempts {
+                    crate::proxy::metrics::record_retry("network");
+                    crate::proxy::upstream::retry::sleep_backoff(&backoff, attempt).await;
+                }
                 continue;
             }
         };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.report_success(&email);
+            token_manager.bind_session(&session_id, &email).await;
+            if attempt == 0 {
+                state.retry_budget.deposit();
+            }
             // 5. 处理流式 vs 非流式
             if list_response {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
@@ -129,34 +276,79 @@ pub async fn handle_chat_completions(
                 use axum::response::Response;
                 // Removed redundant StreamExt
 
-                let gemini_stream = response.bytes_stream();
-                let openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                let body = Body::from_stream(openai_stream);
+                // 首个内容 token 之前断流时，在剩余重试次数内换号续流
+                let reopen = StreamReopenContext {
+                    token_manager: token_manager.clone(),
+                    upstream: upstream.clone(),
+                    request_type: config.request_type.clone(),
+                    model: mapped_model.clone(),
+                    session_id: session_id.clone(),
+                    timeout,
+                    trace_id: crate::proxy::middleware::current_request_id()
+                        .unwrap_or_else(|| "openai".to_string()),
+                }
+                .into_reopen({
+                    let request = openai_req.clone();
+                    let mapped_model = mapped_model.clone();
+                    move |project_id: &str| {
+                        let mut body = transform_openai_request(&request, project_id, &mapped_model);
+                        crate::proxy::common::sampling::apply_sampling_constraints(&mut body, &mapped_model);
+                        Ok(body)
+                    }
+                });
+                let gemini_stream = with_stream_failover(
+                    Box::pin(token_manager.meter_stream(&email, response.bytes_stream())),
+                    max_attempts - attempt - 1,
+                    reopen,
+                );
+                let include_usage = openai_req
+                    .stream_options
+                    .as_ref()
+                    .is_some_and(|o| o.include_usage);
+                let openai_stream = create_openai_sse_stream(
+                    gemini_stream,
+                    openai_req.model.clone(),
+                    include_usage,
+                );
+                let body = Body::from_stream(with_keepalive(DisconnectAware::new(
+                    openai_stream,
+                    crate::proxy::middleware::current_request_id().unwrap_or_else(|| "openai".to_string()),
+                )));
 
-                return Ok(Response::builder()
+                let mut resp = Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response();
+                attach_sampling_header(&mut resp, &sampling_adjustments);
+                return Ok(resp);
             }
 
             let gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
-
-            let openai_response = transform_openai_response(&gemini_resp);
-            return Ok(Json(openai_response).into_response());
+            token_manager.record_response_tokens(&email, &gemini_resp);
+
+            let mut openai_response = transform_openai_response(&gemini_resp);
+            annotate_finish_details(&mut openai_response, &openai_req.stop_sequences());
+            // [NEW] 上游缺少 usageMetadata 时按字符数估算
+            if openai_response.usage.is_none() {
+                openai_response.usage =
+                    Some(estimate_usage(size_check.estimated_tokens, &openai_response));
+            }
+            let mut resp = Json(openai_response).into_response();
+            attach_sampling_header(&mut resp, &sampling_adjustments);
+            return Ok(resp);
         }
 
         // 处理特定错误并重试
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_failure = Some(UpstreamFailure::status(status_code, error_text.clone(), retry_after.clone()));
 
         // [New] 打印错误报文日志
         tracing::error!(
@@ -164,6 +356,21 @@ pub async fn handle_chat_completions(
             status_code,
             error_text
         );
+        token_manager.report_failure(&email, status_code);
+
+        // 结构化输出 schema 被拒同样由请求本身决定
+        if let Some(resp) = response_schema_rejection(&openai_req, status_code, &error_text) {
+            return Ok(resp);
+        }
+
+        // [NEW] 采样参数错误由请求本身决定，直接返回，不消耗其他账号
+        if crate::proxy::common::sampling::is_sampling_param_error(status_code, &error_text) {
+            error!(
+                "OpenAI Upstream rejected sampling params on account {}, not retrying: {}",
+                email, error_text
+            );
+            return Ok(openai_error_response(last_failure.as_ref()));
+        }
 
         // 429/529/503 智能处理
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
@@ -181,7 +388,10 @@ pub async fn handle_chat_completions(
                     max_attempts,
                     actual_delay
                 );
-                tokio::time::sleep(tokio::time::Duration::from_millis(actual_delay)).await;
+                if attempt + 1 < max_attempts {
+                    crate::proxy::metrics::record_retry(crate::proxy::metrics::retry_reason(status_code));
+                    tokio::time::sleep(tokio::time::Duration::from_millis(actual_delay)).await;
+                }
                 continue;
             }
 
@@ -193,10 +403,10 @@ pub async fn handle_chat_completions(
                     attempt + 1,
                     max_attempts
                 );
-                return Err((status, error_text));
+                return Ok(openai_error_response(last_failure.as_ref()));
             }
 
-            // 3. 其他限流或服务器过载情况，轮换账号
+            // 3. 其他限流或服务器过载情况，退避后轮换账号
             tracing::warn!(
                 "OpenAI Upstream {} on {} attempt {}/{}, rotating account",
                 status_code,
@@ -204,11 +414,16 @@ pub async fn handle_chat_completions(
                 attempt + 1,
                 max_attempts
             );
+            if attempt + 1 < max_attempts {
+                crate::proxy::metrics::record_retry(crate::proxy::metrics::retry_reason(status_code));
+                crate::proxy::upstream::retry::sleep_backoff(&backoff, attempt).await;
+            }
             continue;
         }
 
         // 只有 403 (权限/地区限制) 和 401 (认证失效) 触发账号轮换
         if status_code == 403 || status_code == 401 {
+            token_manager.model_access().learn_from_error(&email, &mapped_model, status_code, &error_text);
             tracing::warn!(
                 "OpenAI Upstream {} on account {} attempt {}/{}, rotating account",
                 status_code,
@@ -216,6 +431,10 @@ pub async fn handle_chat_completions(
                 attempt + 1,
                 max_attempts
             );
+            if attempt + 1 < max_attempts {
+                crate::proxy::metrics::record_retry(crate::proxy::metrics::retry_reason(status_code));
+                crate::proxy::upstream::retry::sleep_backoff(&backoff, attempt).await;
+            }
             continue;
         }
 
@@ -224,25 +443,24 @@ pub async fn handle_chat_completions(
             "OpenAI Upstream non-retryable error {} on account {}: {}",
             status_code, email, error_text
         );
-        return Err((status, error_text));
+        return Ok(openai_error_response(last_failure.as_ref()));
     }
 
-    // 所有尝试均失败
-    Err((
-        StatusCode::TOO_MANY_REQUESTS,
-        format!("All accounts exhausted. Last error: {}", last_error),
-    ))
+    // 所有尝试均失败：按最后一次上游失败映射状态码 (429 附带 Retry-After，超时 504)
+    Ok(openai_error_response(last_failure.as_ref()))
 }
 
 /// 处理 Legacy Completions API (/v1/completions)
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let log_mode = *state.prompt_log.read().await;
     info!(
-        "Received /v1/completions or /v1/responses payload: {:?}",
-        body
+        "Received /v1/completions or /v1/responses payload: {}",
+        prompt_log::describe_body(log_mode, &body, 1000)
     );
 
     let is_codex_style = body.get("input").is_some() && body.get("instructions").is_some();
@@ -472,30 +690,21 @@ pub async fn handle_completions(
         if let Some(obj) = body.as_object_mut() {
             obj.insert("messages".to_string(), json!(messages));
         }
-    } else if let Some(prompt_val) = body.get("prompt") {
-        // Legacy OpenAI Style: prompt -> Chat
-        let prompt_str = match prompt_val {
-            Value::String(s) => s.clone(),
-            Value::Array(arr) => arr
-                .iter()
-                .filter_map(|v| v.as_str())
-                .collect::<Vec<_>>()
-                .join("\n"),
-            _ => prompt_val.to_string(),
-        };
-        let messages = json!([ { "role": "user", "content": prompt_str } ]);
-        if let Some(obj) = body.as_object_mut() {
-            obj.remove("prompt");
-            obj.insert("messages".to_string(), messages);
-        }
     }
 
     // 2. Reuse handle_chat_completions logic (wrapping with custom handler or direct call)
     // Actually, due to SSE handling differences (Codex uses different event format), we replicate the loop here or abstract it.
     // For now, let's replicate the core loop but with Codex specific SSE mapping.
 
-    let mut openai_req: OpenAIRequest = serde_json::from_value(body.clone())
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    // Legacy OpenAI Style: prompt -> 单条 user 消息的 Chat 请求
+    let parsed = if !is_codex_style && body.get("prompt").is_some() {
+        serde_json::from_value::<LegacyCompletionRequest>(body).map(LegacyCompletionRequest::into_chat_request)
+    } else {
+        serde_json::from_value::<OpenAIRequest>(body)
+    };
+    let mut openai_req =
+        parsed.map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    validate_response_format(&openai_req)?;
 
     // Safety: Inject empty message if needed
     if openai_req.messages.is_empty() {
@@ -506,22 +715,37 @@ pub async fn handle_completions(
                 content: Some(crate::proxy::mappers::openai::OpenAIContent::String(
                     " ".to_string(),
                 )),
+                reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
             });
     }
 
+    let upstream_proxy = state.upstream_proxy.read().await.clone();
+    crate::proxy::mappers::openai::vision::inline_remote_images(&mut openai_req, Some(&upstream_proxy)).await;
+
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
+    // 只计算当前可调度的账号，避免在冷却中的账号上浪费重试次数
+    let pool_size = token_manager.available_len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
-    let mut last_error = String::new();
+    // 条件路由：别名按请求特征选择上游模型 (重试时沿用同一结果)
+    let route_model = model_routes::route_request(&openai_req.model, || {
+        RequestTraits::from_client_body(&serde_json::to_value(&openai_req).unwrap_or_default(), openai_req.stream)
+    })
+    .map_or_else(|| openai_req.model.clone(), |d| d.model);
+
+    let mut last_failure: Option<UpstreamFailure> = None;
 
-    for _attempt in 0..max_attempts {
+    for attempt in 0..max_attempts {
+        if attempt > 0 && !state.retry_budget.try_withdraw() {
+            tracing::warn!("Retry budget exhausted, giving up after {} attempt(s)", attempt);
+            return Ok(openai_retry_budget_response());
+        }
         let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
+            &route_model,
             &*state.custom_mapping.read().await,
             &*state.openai_mapping.read().await,
             &*state.anthropic_mapping.read().await,
@@ -537,25 +761,46 @@ pub async fn handle_completions(
             &mapped_model,
             &tools_val,
         );
+        validate_candidate_count(&openai_req, &config.final_model).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+        if let Err(e) = crate::proxy::key_scope::check_model(&openai_req.model, &mapped_model) {
+            return Ok(openai_model_not_allowed_response(&e));
+        }
 
+        let mut gemini_body = transform_openai_request(&openai_req, "", &mapped_model);
+        crate::proxy::common::sampling::apply_sampling_constraints(&mut gemini_body, &mapped_model);
+
+        // Prompt 体积预检 (选择账号前)
+        let size_check = crate::proxy::common::prompt_size::check_prompt_size(
+            &gemini_body,
+            &mapped_model,
+            openai_req.max_tokens,
+        );
+        crate::proxy::metrics::note_prompt_size(&mapped_model, &size_check.breakdown);
+        if !size_check.is_allowed() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                size_check.rejection_message(&mapped_model),
+            ));
+        }
+        let estimated_prompt_tokens = size_check.estimated_tokens;
+
+        // 仅在客户端显式传入 X-Session-Key 时启用会话粘性
+        let session_key = SessionManager::session_key_from_headers(&headers);
         let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, false, None).await {
+            match token_manager.get_token(&config.request_type, Some(&mapped_model), false, session_key.as_deref()).await {
                 Ok(t) => t,
-                Err(e) => {
-                    return Err((
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        format!("Token error: {}", e),
-                    ))
-                }
+                Err(e) => return Ok(openai_pool_error_response(&e)),
             };
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
+        gemini_body["project"] = json!(project_id);
 
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
-
-        // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径)
-        if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
-            debug!("[Codex-Request] Transformed Gemini Body:\n{}", body_json);
+        // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径，仅 preview 日志模式)
+        if prompt_log::content_logging_enabled(log_mode) {
+            if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
+                debug!("[Codex-Request] Transformed Gemini Body:\n{}", body_json);
+            }
         }
 
         let list_response = openai_req.stream;
@@ -566,34 +811,67 @@ pub async fn handle_completions(
         };
         let query_string = if list_response { Some("alt=sse") } else { None };
 
+        // 按模型覆盖请求超时 (思考类模型通常需要更长时间)
+        let timeout = crate::proxy::config::resolve_model_timeout(
+            &*state.model_timeouts.read().await,
+            *state.request_timeout.read().await,
+            &[&mapped_model, &openai_req.model],
+        );
+        let auth = AccountAuth {
+            token_manager: &token_manager,
+            email: &email,
+            access_token: &access_token,
+        };
         let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+            .call_v1_internal_with_auth_refresh(auth, method, gemini_body, query_string, Some(timeout))
             .await
         {
             Ok(r) => r,
             Err(e) => {
-                last_error = e.clone();
+                // 上游代理认证失败 (407)：换账号无意义，直接返回
+                if crate::proxy::upstream::client::is_proxy_auth_failure(&e) {
+                    return Err((StatusCode::BAD_GATEWAY, e));
+                }
+                let failure = UpstreamFailure::transport(e);
+                // TLS 证书校验失败 (多为 SSL 拦截代理)：换账号无意义，直接返回
+                if matches!(failure, UpstreamFailure::Tls(_)) {
+                    return Ok(openai_error_response(Some(&failure)));
+                }
+                last_failure = Some(failure);
                 continue;
             }
         };
 
         let status = response.status();
         if status.is_success() {
+            if let Some(key) = &session_key {
+                token_manager.bind_session(key, &email).await;
+            }
+            if attempt == 0 {
+                state.retry_budget.deposit();
+            }
             if list_response {
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
+                let gemini_stream = token_manager.meter_stream(&email, response.bytes_stream());
                 let body = if is_codex_style {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                     let s =
                         create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                    Body::from_stream(with_keepalive(s))
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
-                    let s =
-                        create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                    let include_usage = openai_req
+                        .stream_options
+                        .as_ref()
+                        .is_some_and(|o| o.include_usage);
+                    let s = create_legacy_sse_stream(
+                        Box::pin(gemini_stream),
+                        openai_req.model.clone(),
+                        include_usage,
+                    );
+                    Body::from_stream(with_keepalive(s))
                 };
 
                 return Ok(Response::builder()
@@ -609,6 +887,7 @@ pub async fn handle_completions(
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            token_manager.record_response_tokens(&email, &gemini_resp);
 
             let chat_resp = transform_openai_response(&gemini_resp);
 
@@ -625,12 +904,18 @@ pub async fn handle_completions(
                 })
             }).collect::<Vec<_>>();
 
+            let usage = chat_resp
+                .usage
+                .clone()
+                .unwrap_or_else(|| estimate_usage(estimated_prompt_tokens, &chat_resp));
+
             let legacy_resp = json!({
                 "id": chat_resp.id,
                 "object": "text_completion",
                 "created": chat_resp.created,
                 "model": chat_resp.model,
-                "choices": choices
+                "choices": choices,
+                "usage": usage
             });
 
             return Ok(axum::Json(legacy_resp).into_response());
@@ -638,19 +923,205 @@ pub async fn handle_completions(
 
         // Handle errors and retry
         let status_code = status.as_u16();
+        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_default();
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        if let Some(resp) = response_schema_rejection(&openai_req, status_code, &error_text) {
+            return Ok(resp);
+        }
+        last_failure = Some(UpstreamFailure::status(status_code, error_text, retry_after));
 
         if status_code == 429 || status_code == 403 || status_code == 401 {
             continue;
         }
-        return Err((status, error_text));
+        return Ok(openai_error_response(last_failure.as_ref()));
     }
 
-    Err((
-        StatusCode::TOO_MANY_REQUESTS,
-        format!("All attempts failed. Last error: {}", last_error),
-    ))
+    Ok(openai_error_response(last_failure.as_ref()))
+}
+
+/// Token 计数: POST /v1/count_tokens (OpenAI Chat 请求体)
+/// 只统计输入 Token，不生成内容
+pub async fn handle_count_tokens(
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let openai_req: OpenAIRequest = serde_json::from_value(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &openai_req.model,
+        &*state.custom_mapping.read().await,
+        &*state.openai_mapping.read().await,
+        &*state.anthropic_mapping.read().await,
+        false,  // OpenAI 请求不应用 Claude 家族映射
+    );
+    let tools_val: Option<Vec<Value>> = openai_req.tools.clone();
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(
+        &openai_req.model,
+        &mapped_model,
+        &tools_val,
+    );
+    if let Err(e) = crate::proxy::key_scope::check_model(&openai_req.model, &mapped_model) {
+        return Ok(openai_model_not_allowed_response(&e));
+    }
+
+    let input_tokens = super::common::count_input_tokens(&state, &config.request_type, &mapped_model, |project_id| {
+        Ok(transform_openai_request(&openai_req, project_id, &mapped_model))
+    })
+    .await?;
+
+    Ok(Json(json!({ "input_tokens": input_tokens })).into_response())
+}
+
+/// OpenAI Embeddings API: POST /v1/embeddings
+/// 转换为 Gemini embedContent / batchEmbedContents，复用账号轮换与重试逻辑
+pub async fn handle_embeddings(
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    use crate::proxy::mappers::openai::{
+        extract_embedding_inputs, transform_embedding_request, transform_embedding_response,
+        OpenAIEmbeddingRequest,
+    };
+
+    let embed_req: OpenAIEmbeddingRequest = serde_json::from_value(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    let inputs = extract_embedding_inputs(&embed_req).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    debug!(
+        "Received embeddings request: model={}, inputs={}",
+        embed_req.model,
+        inputs.len()
+    );
+
+    let upstream = state.upstream.clone();
+    let token_manager = state.token_manager;
+    // 只计算当前可调度的账号，避免在冷却中的账号上浪费重试次数
+    let pool_size = token_manager.available_len();
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+
+    let mapped_model = crate::proxy::common::model_mapping::resolve_embedding_model(
+        &embed_req.model,
+        &*state.custom_mapping.read().await,
+    );
+    // 附加 Key 的模型白名单 (路由解析后、选择账号前)
+    if let Err(e) = crate::proxy::key_scope::check_model(&embed_req.model, &mapped_model) {
+        return Ok(openai_model_not_allowed_response(&e));
+    }
+
+    let mut last_failure: Option<UpstreamFailure> = None;
+
+    for attempt in 0..max_attempts {
+        if attempt > 0 && !state.retry_budget.try_withdraw() {
+            tracing::warn!("Retry budget exhausted, giving up after {} attempt(s)", attempt);
+            return Ok(openai_retry_budget_response());
+        }
+        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
+        let (access_token, project_id, email) =
+            match token_manager.get_token("agent", Some(&mapped_model), attempt > 0, None).await {
+                Ok(t) => t,
+                Err(e) => return Ok(openai_pool_error_response(&e)),
+            };
+
+        info!("✓ Using account: {} (embeddings: {})", email, mapped_model);
+
+        let (method, gemini_body) =
+            transform_embedding_request(&embed_req, &inputs, &project_id, &mapped_model);
+
+        let auth = AccountAuth {
+            token_manager: &token_manager,
+            email: &email,
+            access_token: &access_token,
+        };
+        let response = match upstream
+            .call_v1_internal_with_auth_refresh(auth, method, gemini_body, None, None)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                // 上游代理认证失败 (407)：换账号无意义，直接返回
+                if crate::proxy::upstream::client::is_proxy_auth_failure(&e) {
+                    return Err((StatusCode::BAD_GATEWAY, e));
+                }
+                let failure = UpstreamFailure::transport(e.clone());
+                // TLS 证书校验失败 (多为 SSL 拦截代理)：换账号无意义，直接返回
+                if matches!(failure, UpstreamFailure::Tls(_)) {
+                    return Ok(openai_error_response(Some(&failure)));
+                }
+                last_failure = Some(failure);
+                debug!(
+                    "Embeddings request failed on attempt {}/{}: {}",
+                    attempt + 1,
+                    max_attempts,
+                    e
+                );
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            if attempt == 0 {
+                state.retry_budget.deposit();
+            }
+            let gemini_resp: Value = response
+                .json()
+                .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+
+            let openai_resp = transform_embedding_response(&gemini_resp, &embed_req, &inputs)
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+            return Ok(Json(openai_resp).into_response());
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+        let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_failure = Some(UpstreamFailure::status(status_code, error_text.clone(), retry_after.clone()));
+
+        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
+            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+
+            if error_text.contains("QUOTA_EXHAUSTED") {
+                error!(
+                    "Embeddings quota exhausted on account {} attempt {}/{}, stopping to protect pool.",
+                    email,
+                    attempt + 1,
+                    max_attempts
+                );
+                return Ok(openai_error_response(last_failure.as_ref()));
+            }
+
+            tracing::warn!(
+                "Embeddings upstream {} on {} attempt {}/{}, rotating account",
+                status_code,
+                email,
+                attempt + 1,
+                max_attempts
+            );
+            continue;
+        }
+
+        if status_code == 403 || status_code == 401 {
+            token_manager.model_access().learn_from_error(&email, &mapped_model, status_code, &error_text);
+            tracing::warn!(
+                "Embeddings upstream {} on account {} attempt {}/{}, rotating account",
+                status_code,
+                email,
+                attempt + 1,
+                max_attempts
+            );
+            continue;
+        }
+
+        error!(
+            "Embeddings upstream non-retryable error {} on account {}: {}",
+            status_code, email, error_text
+        );
+        return Ok(openai_error_response(last_failure.as_ref()));
+    }
+
+    Ok(openai_error_response(last_failure.as_ref()))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
@@ -661,15 +1132,10 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
         &state.custom_mapping,
         &state.anthropic_mapping,
     ).await;
+    // 附加 Key 只列出其可用的模型
+    let model_ids = super::common::filter_listed_models(&state, model_ids).await;
 
-    let data: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
-            "id": id,
-            "object": "model",
-            "created": 1706745600,
-            "owned_by": "antigravity"
-        })
-    }).collect();
+    let data: Vec<_> = model_ids.iter().map(|id| model_object(id)).collect();
 
     Json(json!({
         "object": "list",
@@ -677,17 +1143,138 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
+/// OpenAI 模型对象 (列表与单个查询共用)
+fn model_object(id: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "model",
+        "created": 1706745600,
+        "owned_by": "antigravity"
+    })
+}
+
+/// OpenAI Models API: GET /v1/models/:model_id
+/// 从与模型列表相同的来源中查找单个模型
+pub async fn handle_get_model(
+    State(state): State<AppState>,
+    Path(model_id): Path<String>,
+) -> impl IntoResponse {
+    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+
+    let model_ids = get_all_dynamic_models(
+        &state.openai_mapping,
+        &state.custom_mapping,
+        &state.anthropic_mapping,
+    ).await;
+    // 附加 Key 只列出其可用的模型
+    let model_ids = super::common::filter_listed_models(&state, model_ids).await;
+
+    if model_ids.iter().any(|id| id == &model_id) {
+        (StatusCode::OK, Json(model_object(&model_id)))
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": {
+                    "message": "Model not found",
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+    }
+}
+
+/// 单次图像请求允许的最大 n
+const MAX_IMAGES_PER_REQUEST: usize = 10;
+
+/// response_format=url 且启用本地文件模式时，返回 (输出目录, 对外基础地址)
+async fn image_file_base(
+    state: &AppState,
+    headers: &HeaderMap,
+    response_format: &str,
+) -> Option<(PathBuf, String)> {
+    if response_format != "url" {
+        return None;
+    }
+    let config = state.image_files.read().await.clone();
+    if !config.enabled {
+        return None;
+    }
+    let dir = match image_files::images_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            tracing::warn!("[Images] Local file mode unavailable, falling back to data URI: {}", e);
+            return None;
+        }
+    };
+    image_files::prune_images(
+        &dir,
+        std::time::Duration::from_secs(config.retention_secs),
+        std::time::SystemTime::now(),
+    );
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("127.0.0.1");
+    Some((dir, format!("http://{}", host)))
+}
+
+/// 构建单张图像的输出条目：b64_json 直接返回；url 优先写入本地文件，否则回退为 data URI
+fn image_output(
+    data: &str,
+    mime_type: &str,
+    response_format: &str,
+    file_base: Option<&(PathBuf, String)>,
+) -> Value {
+    if response_format != "url" {
+        return json!({ "b64_json": data });
+    }
+    if let Some((dir, base_url)) = file_base {
+        match image_files::save_image(dir, data, mime_type) {
+            Ok(name) => return json!({ "url": format!("{}/v1/images/files/{}", base_url, name) }),
+            Err(e) => tracing::warn!("[Images] Failed to save image file: {}", e),
+        }
+    }
+    json!({ "url": format!("data:{};base64,{}", mime_type, data) })
+}
+
+/// 本地文件模式下生成的图像
+/// GET /v1/images/files/:name
+pub async fn handle_image_file(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !state.image_files.read().await.enabled {
+        return Err((StatusCode::NOT_FOUND, "Image not found".to_string()));
+    }
+    let dir = image_files::images_dir().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let (bytes, mime_type) =
+        image_files::read_image(&dir, &name).map_err(|e| (StatusCode::NOT_FOUND, e))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime_type),
+            (header::CACHE_CONTROL, "private, max-age=86400"),
+        ],
+        bytes,
+    ))
+}
+
 /// OpenAI Images API: POST /v1/images/generations
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 1. 解析请求参数
-    let prompt = body.get("prompt").and_then(|v| v.as_str()).ok_or((
-        StatusCode::BAD_REQUEST,
-        "Missing 'prompt' field".to_string(),
-    ))?;
+    let prompt = body
+        .get("prompt")
+        .and_then(|v| v.as_str())
+        .filter(|p| !p.trim().is_empty())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Missing 'prompt' field".to_string(),
+        ))?;
 
     let model = body
         .get("model")
@@ -695,6 +1282,12 @@ pub async fn handle_images_generations(
         .unwrap_or("gemini-3-pro-image");
 
     let n = body.get("n").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+    if !(1..=MAX_IMAGES_PER_REQUEST).contains(&n) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("'n' must be between 1 and {}", MAX_IMAGES_PER_REQUEST),
+        ));
+    }
 
     let size = body
         .get("size")
@@ -705,6 +1298,12 @@ pub async fn handle_images_generations(
         .get("response_format")
         .and_then(|v| v.as_str())
         .unwrap_or("b64_json");
+    if !matches!(response_format, "b64_json" | "url") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid 'response_format': {} (expected 'b64_json' or 'url')", response_format),
+        ));
+    }
 
     let quality = body
         .get("quality")
@@ -715,42 +1314,41 @@ pub async fn handle_images_generations(
         .and_then(|v| v.as_str())
         .unwrap_or("vivid");
 
+    let log_mode = *state.prompt_log.read().await;
     info!(
-        "[Images] Received request: model={}, prompt={:.50}..., n={}, size={}, quality={}, style={}",
+        "[Images] Received request: model={}, prompt={}, n={}, size={}, quality={}, style={}",
         model,
-        prompt,
+        prompt_log::describe(log_mode, prompt, 50),
         n,
         size,
         quality,
         style
     );
 
-    // 2. 解析尺寸为宽高比
-    let aspect_ratio = match size {
-        "1792x768" | "2560x1080" => "21:9", // Ultra-wide
-        "1792x1024" | "1920x1080" => "16:9",
-        "1024x1792" | "1080x1920" => "9:16",
-        "1024x768" | "1280x960" => "4:3",
-        "768x1024" | "960x1280" => "3:4",
-        _ => "1:1", // 默认 1024x1024
-    };
+    // 2. 尺寸 / 质量映射为 imageConfig (与模型名后缀共用同一套逻辑)
+    let image_config =
+        crate::proxy::mappers::common_utils::image_config_from_openai(size, quality);
 
     // Prompt Enhancement
     let mut final_prompt = prompt.to_string();
-    if quality == "hd" {
-        final_prompt.push_str(", (high quality, highly detailed, 4k resolution, hdr)");
-    }
     match style {
         "vivid" => final_prompt.push_str(", (vivid colors, dramatic lighting, rich details)"),
         "natural" => final_prompt.push_str(", (natural lighting, realistic, photorealistic)"),
         _ => {}
     }
 
+    // 附加 Key 的模型白名单 (选择账号前)
+    if let Err(e) = crate::proxy::key_scope::check_model(model, model) {
+        return Ok(openai_model_not_allowed_response(&e));
+    }
+
+    let file_base = image_file_base(&state, &headers, response_format).await;
+
     // 3. 获取 Token
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
 
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email) = match token_manager.get_token("image_gen", None, false, None).await
     {
         Ok(t) => t,
         Err(e) => {
@@ -768,42 +1366,40 @@ pub async fn handle_images_generations(
 
     for _ in 0..n {
         let upstream = upstream.clone();
+        let token_manager = token_manager.clone();
+        let email = email.clone();
         let access_token = access_token.clone();
         let project_id = project_id.clone();
         let final_prompt = final_prompt.clone();
-        let aspect_ratio = aspect_ratio.to_string();
-        let _response_format = response_format.to_string();
+        let image_config = image_config.clone();
 
         tasks.push(tokio::spawn(async move {
-            let gemini_body = json!({
-                "project": project_id,
-                "requestId": format!("img-{}", uuid::Uuid::new_v4()),
-                "model": "gemini-3-pro-image",
-                "userAgent": "antigravity",
-                "requestType": "image_gen",
-                "request": {
+            let generation_config = GenerationConfig::new()
+                .candidate_count(1) // 强制单张
+                .image_config(image_config);
+            let gemini_body = V1InternalRequest::new(
+                &project_id,
+                "gemini-3-pro-image",
+                "image_gen",
+                json!({
                     "contents": [{
                         "role": "user",
                         "parts": [{"text": final_prompt}]
                     }],
-                    "generationConfig": {
-                        "candidateCount": 1, // 强制单张
-                        "imageConfig": {
-                            "aspectRatio": aspect_ratio
-                        }
-                    },
-                    "safetySettings": [
-                        { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
-                    ]
-                }
-            });
-
+                    "generationConfig": generation_config.to_value(),
+                    "safetySettings": safety_settings(),
+                }),
+            )
+            .with_request_id_prefix("img")
+            .into_value();
+
+            let auth = AccountAuth {
+                token_manager: &token_manager,
+                email: &email,
+                access_token: &access_token,
+            };
             match upstream
-                .call_v1_internal("generateContent", &access_token, gemini_body, None)
+                .call_v1_internal_with_auth_refresh(auth, "generateContent", gemini_body, None, None)
                 .await
             {
                 Ok(response) => {
@@ -842,19 +1438,16 @@ pub async fn handle_images_generations(
                             if let Some(img) = part.get("inlineData") {
                                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                                 if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
+                                    let mime_type = img
+                                        .get("mimeType")
+                                        .and_then(|v| v.as_str())
+                                        .unwrap_or("image/png");
+                                    images.push(image_output(
+                                        data,
+                                        mime_type,
+                                        response_format,
+                                        file_base.as_ref(),
+                                    ));
                                     tracing::debug!("[Images] Task {} succeeded", idx);
                                 }
                             }
@@ -906,11 +1499,12 @@ pub async fn handle_images_generations(
         "data": images
     });
 
-    Ok(Json(openai_response))
+    Ok(Json(openai_response).into_response())
 }
 
 pub async fn handle_images_edits(
     State(state): State<AppState>,
+    headers: HeaderMap,
     mut multipart: axum::extract::Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     tracing::info!("[Images] Received edit request");
@@ -975,10 +1569,11 @@ pub async fn handle_images_edits(
         return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
     }
 
+    let log_mode = *state.prompt_log.read().await;
     tracing::info!(
         "[Images] Edit Request: model={}, prompt={}, n={}, size={}, mask={}, response_format={}",
         model,
-        prompt,
+        prompt_log::describe(log_mode, &prompt, 50),
         n,
         size,
         mask_data.is_some(),
@@ -996,11 +1591,18 @@ pub async fn handle_images_edits(
     // But if users see raw text, it means client defaulted to 'url' or we defaulted to 'url'.
     // Let's keep the log to confirm.
 
+    // 附加 Key 的模型白名单 (选择账号前)
+    if let Err(e) = crate::proxy::key_scope::check_model(&model, &model) {
+        return Ok(openai_model_not_allowed_response(&e));
+    }
+
+    let file_base = image_file_base(&state, &headers, &response_format).await;
+
     // 1. 获取 Upstream
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, _email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email) = match token_manager.get_token("image_gen", None, false, None).await
     {
         Ok(t) => t,
         Err(e) => {
@@ -1037,44 +1639,45 @@ pub async fn handle_images_edits(
     }
 
     // 构造 Gemini 内网 API Body (Envelope Structure)
-    let gemini_body = json!({
-        "project": project_id,
-        "requestId": format!("img-edit-{}", uuid::Uuid::new_v4()),
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "image_gen",
-        "request": {
+    let generation_config = GenerationConfig::new()
+        .candidate_count(1)
+        .max_output_tokens(8192)
+        .stop_sequences(Vec::new())
+        .temperature(Some(1.0))
+        .top_p(Some(0.95))
+        .top_k(Some(40));
+    let gemini_body = V1InternalRequest::new(
+        &project_id,
+        &model,
+        "image_gen",
+        json!({
             "contents": [{
                 "role": "user",
                 "parts": contents_parts
             }],
-            "generationConfig": {
-                "candidateCount": 1,
-                "maxOutputTokens": 8192,
-                "stopSequences": [],
-                "temperature": 1.0,
-                "topP": 0.95,
-                "topK": 40
-            },
-            "safetySettings": [
-                { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
-            ]
-        }
-    });
+            "generationConfig": generation_config.to_value(),
+            "safetySettings": safety_settings(),
+        }),
+    )
+    .with_request_id_prefix("img-edit")
+    .into_value();
 
     let mut tasks = Vec::new();
     for _ in 0..n {
         let upstream = upstream.clone();
+        let token_manager = token_manager.clone();
+        let email = email.clone();
         let access_token = access_token.clone();
         let body = gemini_body.clone();
 
         tasks.push(tokio::spawn(async move {
+            let auth = AccountAuth {
+                token_manager: &token_manager,
+                email: &email,
+                access_token: &access_token,
+            };
             match upstream
-                .call_v1_internal("generateContent", &access_token, body, None)
+                .call_v1_internal_with_auth_refresh(auth, "generateContent", body, None, None)
                 .await
             {
                 Ok(response) => {
@@ -1112,19 +1715,16 @@ pub async fn handle_images_edits(
                             if let Some(img) = part.get("inlineData") {
                                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                                 if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
+                                    let mime_type = img
+                                        .get("mimeType")
+                                        .and_then(|v| v.as_str())
+                                        .unwrap_or("image/png");
+                                    images.push(image_output(
+                                        data,
+                                        mime_type,
+                                        &response_format,
+                                        file_base.as_ref(),
+                                    ));
                                     tracing::debug!("[Images] Task {} succeeded", idx);
                                 }
                             }
@@ -1178,5 +1778,48 @@ pub async fn handle_images_edits(
         "data": images
     });
 
-    Ok(Json(openai_response))
+    Ok(Json(openai_response).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::key_scope::{scope_model_access, ModelScope};
+
+    fn flash_only() -> ModelScope {
+        ModelScope {
+            label: "teammate".to_string(),
+            allowed_models: vec!["gemini-*flash*".to_string()],
+            check_resolved_model: false,
+            report_model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scoped_key_cannot_bypass_allow_list_via_embeddings_or_images() {
+        let body = json!({"model": "text-embedding-004", "input": "hello"});
+        let response = scope_model_access(Some(flash_only()), handle_embeddings(State(AppState::for_test()), Json(body)))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let body = json!({"model": "gemini-3-pro-image", "prompt": "a cat"});
+        let response = scope_model_access(
+            Some(flash_only()),
+            handle_images_generations(State(AppState::for_test()), HeaderMap::new(), Json(body)),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // 白名单内的模型继续向下处理 (空账号池返回 503 而不是 403)
+        let body = json!({"model": "gemini-2.5-flash-embedding", "input": "hello"});
+        let response = scope_model_access(Some(flash_only()), handle_embeddings(State(AppState::for_test()), Json(body)))
+            .await
+            .unwrap()
+            .into_response();
+        assert_ne!(response.status(), StatusCode::FORBIDDEN);
+    }
 }
@@ -0,0 +1,721 @@
+// WebSocket 端点
+// - GET /v1/ws: 单连接上顺序执行多次流式对话请求，复用 OpenAI / Anthropic 现有处理管线
+// - GET /v1/ws/chat/completions: 单次 OpenAI 流式对话 (SSE 被拦截时的替代方案)
+
+use axum::{
+    extract::{
+        ws::{close_code, rejection::WebSocketUpgradeRejection, CloseFrame, Message, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::sink::{Sink, SinkExt};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::WebSocketConfig;
+use crate::proxy::server::AppState;
+
+/// 当前活跃的 WebSocket 连接数
+static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// 错误响应体最大读取长度
+const MAX_ERROR_BODY_BYTES: usize = 1024 * 1024;
+
+/// 连接计数守卫，会话结束时自动释放名额
+struct ConnectionGuard;
+
+impl ConnectionGuard {
+    fn acquire(max_connections: usize) -> Option<Self> {
+        ACTIVE_CONNECTIONS
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current < max_connections).then_some(current + 1)
+            })
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// 请求帧中的协议格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RequestFormat {
+    #[serde(alias = "chat")]
+    Openai,
+    #[serde(alias = "claude")]
+    Anthropic,
+}
+
+/// 客户端发送的文本帧
+/// `{"format": "openai"|"anthropic", "request": {...}}` 或 `{"cancel": true}`
+#[derive(Debug, Deserialize)]
+struct ClientFrame {
+    #[serde(default)]
+    cancel: bool,
+    format: Option<RequestFormat>,
+    request: Option<Value>,
+}
+
+/// 升级后运行的会话类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionKind {
+    /// 帧内携带 format 的多请求会话 (/v1/ws)
+    Multiplexed,
+    /// 首帧即 OpenAI 请求体的单次会话 (/v1/ws/chat/completions)
+    ChatCompletions,
+}
+
+/// 处理 WebSocket 升级请求
+/// 鉴权与限流由全局中间件完成；端点默认关闭 (proxy.websocket.enabled)
+pub async fn handle_ws(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    accept_upgrade(state, headers, upgrade, SessionKind::Multiplexed).await
+}
+
+/// GET /v1/ws/chat/completions
+/// 客户端发送一个 OpenAI chat completions 请求体，服务端逐帧返回与 SSE data 相同结构的 chunk，
+/// 结束时发送 `{"done": true}` 并正常关闭；出错时发送 `{"error": {...}}` 后以对应关闭码关闭
+pub async fn handle_ws_chat_completions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    accept_upgrade(state, headers, upgrade, SessionKind::ChatCompletions).await
+}
+
+/// 握手校验与帧编解码 (掩码、RSV 位、分片、控制帧) 由 axum / tungstenite 完成
+async fn accept_upgrade(
+    state: AppState,
+    headers: HeaderMap,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    kind: SessionKind,
+) -> Response {
+    let config = state.websocket.read().await.clone();
+    if !config.enabled {
+        return (StatusCode::NOT_FOUND, "WebSocket endpoint is disabled").into_response();
+    }
+
+    let upgrade = match upgrade {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let Some(guard) = ConnectionGuard::acquire(config.max_connections) else {
+        tracing::warn!("[WS] 连接数已达上限 {}，拒绝新连接", config.max_connections);
+        return (StatusCode::SERVICE_UNAVAILABLE, "Too many WebSocket connections").into_response();
+    };
+
+    // 升级后的会话运行在独立任务中，需要带上当前 Key 的模型白名单
+    let model_scope = crate::proxy::key_scope::current_scope();
+    upgrade
+        .max_message_size(config.max_message_bytes)
+        .max_frame_size(config.max_message_bytes)
+        .on_failed_upgrade(|e| tracing::warn!("[WS] 协议升级失败: {}", e))
+        .on_upgrade(move |socket| {
+            crate::proxy::key_scope::with_captured_scope(model_scope, async move {
+                let _guard = guard;
+                let (sender, receiver) = socket.split();
+                match kind {
+                    SessionKind::Multiplexed => {
+                        let start = move |format, request| start_request(state.clone(), headers.clone(), format, request);
+                        run_session(sender, receiver, &config, start).await;
+                    }
+                    SessionKind::ChatCompletions => {
+                        let start = move |request| start_request(state, headers, RequestFormat::Openai, request);
+                        run_chat_session(sender, receiver, &config, start).await;
+                    }
+                }
+            })
+        })
+}
+
+/// 调用现有处理器并把响应转换为事件流 (每个 SSE data 对应一个 JSON)
+fn start_request(
+    state: AppState,
+    headers: HeaderMap,
+    format: RequestFormat,
+    mut request: Value,
+) -> BoxStream<'static, Value> {
+    let response = async move {
+        if let Some(obj) = request.as_object_mut() {
+            obj.insert("stream".to_string(), json!(true));
+        }
+        let handled = async move {
+            match format {
+                RequestFormat::Openai => {
+                    if let Some(obj) = request.as_object_mut() {
+                        obj.insert("stream_options".to_string(), json!({ "include_usage": true }));
+                    }
+                    crate::proxy::handlers::openai::handle_chat_completions(State(state), headers, Json(request))
+                        .await
+                        .into_response()
+                }
+                RequestFormat::Anthropic => {
+                    crate::proxy::handlers::claude::handle_messages(State(state), headers, Json(request)).await
+                }
+            }
+        };
+        // 会话任务不经过 HTTP 中间件，账号并发许可在这里建立上下文 (流式响应接管后随流释放)
+        let response = crate::proxy::account_concurrency::scope_account_permit(handled).await;
+        response_events(response)
+    };
+    stream::once(response).flatten().boxed()
+}
+
+/// 将处理器响应拆分为事件
+fn response_events(response: Response) -> BoxStream<'static, Value> {
+    let status = response.status();
+    let is_sse = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("text/event-stream"))
+        .unwrap_or(false);
+    let body = response.into_body();
+
+    if !status.is_success() || !is_sse {
+        return stream::once(async move {
+            let bytes = axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await.unwrap_or_default();
+            let detail = serde_json::from_slice::<Value>(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+            if status.is_success() {
+                detail
+            } else {
+                json!({ "error": { "status": status.as_u16(), "detail": detail } })
+            }
+        })
+        .boxed();
+    }
+
+    let mut parser = SseParser::default();
+    body.into_data_stream()
+        .flat_map(move |chunk| {
+            let events = match chunk {
+                Ok(bytes) => parser.push(&bytes),
+                Err(e) => vec![json!({ "error": { "type": "stream_error", "message": e.to_string() } })],
+            };
+            stream::iter(events)
+        })
+        .boxed()
+}
+
+/// SSE 增量解析器：只取 `data:` 行，跳过 [DONE] 与无法解析的行
+#[derive(Default)]
+struct SseParser {
+    buffer: String,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Value> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=pos).collect();
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data.is_empty() || data == "[DONE]" {
+                continue;
+            }
+            if let Ok(value) = serde_json::from_str::<Value>(data) {
+                events.push(value);
+            }
+        }
+        events
+    }
+}
+
+/// 合并事件中的 usage 字段 (OpenAI 末尾 usage 块 / Claude message_start + message_delta)
+fn merge_usage(acc: &mut Option<Value>, event: &Value) {
+    let usage = event
+        .get("usage")
+        .or_else(|| event.get("message").and_then(|m| m.get("usage")));
+    let Some(Value::Object(usage)) = usage else {
+        return;
+    };
+    let target = acc.get_or_insert_with(|| json!({}));
+    if let Some(obj) = target.as_object_mut() {
+        for (k, v) in usage {
+            if !v.is_null() {
+                obj.insert(k.clone(), v.clone());
+            }
+        }
+    }
+}
+
+fn error_frame(kind: &str, message: &str) -> Value {
+    json!({ "error": { "type": kind, "message": message } })
+}
+
+fn text_message(value: &Value) -> Message {
+    Message::Text(value.to_string())
+}
+
+fn close_message(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// 读取失败时的关闭码：消息超过 max_message_bytes 为 1009，其余协议错误为 1002
+/// axum::Error 内部包装的是 tungstenite::Error，沿 source 链查找容量错误
+fn read_error_close_code(error: &(dyn StdError + 'static)) -> u16 {
+    let mut current = Some(error);
+    while let Some(err) = current {
+        if let Some(tungstenite::Error::Capacity(_)) = err.downcast_ref::<tungstenite::Error>() {
+            return close_code::SIZE;
+        }
+        current = err.source();
+    }
+    close_code::PROTOCOL
+}
+
+/// 按顺序写出一组消息后统一 flush
+async fn send_all<W>(sender: &mut W, messages: Vec<Message>) -> Result<(), W::Error>
+where
+    W: Sink<Message> + Unpin,
+{
+    for message in messages {
+        sender.feed(message).await?;
+    }
+    sender.flush().await
+}
+
+/// 正在执行的请求
+struct ActiveRequest {
+    events: BoxStream<'static, Value>,
+    usage: Option<Value>,
+}
+
+/// 会话主循环
+/// 同一连接上请求顺序执行；执行期间的新请求直接拒绝，`{"cancel": true}` 中止当前请求。
+/// 服务端按 ping_interval 发送 Ping，超过 idle_timeout 未收到客户端任何帧 (含 Pong) 则关闭连接。
+/// 客户端 Ping 由底层自动回复 Pong，客户端 Close 也由底层回复
+async fn run_session<W, R, E, F>(mut sender: W, mut receiver: R, config: &WebSocketConfig, start: F)
+where
+    W: Sink<Message> + Unpin,
+    W::Error: Display,
+    R: Stream<Item = Result<Message, E>> + Unpin,
+    E: StdError + 'static,
+    F: Fn(RequestFormat, Value) -> BoxStream<'static, Value>,
+{
+    let idle_timeout = Duration::from_secs(config.idle_timeout_secs.max(1));
+    let mut ping = tokio::time::interval(Duration::from_secs(config.ping_interval_secs.max(1)));
+    ping.tick().await;
+    let mut last_seen = Instant::now();
+    let mut active: Option<ActiveRequest> = None;
+    let mut served: u32 = 0;
+
+    loop {
+        let outgoing: Vec<Message> = tokio::select! {
+            incoming = receiver.next() => {
+                last_seen = Instant::now();
+                match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = handle_client_frame(&text, &mut active, &mut served, config, &start);
+                        let limit_reached = served >= config.max_requests_per_connection && active.is_none();
+                        let mut out: Vec<Message> = reply.iter().map(text_message).collect();
+                        if limit_reached {
+                            out.push(close_message(close_code::POLICY, "request limit reached"));
+                        }
+                        out
+                    }
+                    Some(Ok(Message::Binary(_))) => {
+                        vec![text_message(&error_frame("invalid_request", "binary frames are not supported"))]
+                    }
+                    Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => continue,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        tracing::debug!("[WS] 读取客户端帧失败: {}", e);
+                        vec![close_message(read_error_close_code(&e), "protocol error")]
+                    }
+                }
+            }
+            event = next_event(&mut active) => match event {
+                Some(event) => {
+                    if let Some(req) = active.as_mut() {
+                        merge_usage(&mut req.usage, &event);
+                    }
+                    vec![text_message(&event)]
+                }
+                None => {
+                    let usage = active.take().and_then(|req| req.usage);
+                    let mut out = vec![text_message(&json!({ "done": true, "usage": usage }))];
+                    if served >= config.max_requests_per_connection {
+                        out.push(close_message(close_code::POLICY, "request limit reached"));
+                    }
+                    out
+                }
+            },
+            _ = ping.tick() => {
+                if last_seen.elapsed() >= idle_timeout {
+                    tracing::debug!("[WS] 连接空闲超时，关闭");
+                    vec![close_message(close_code::AWAY, "idle timeout")]
+                } else {
+                    vec![Message::Ping(Vec::new())]
+                }
+            }
+        };
+
+        if outgoing.is_empty() {
+            continue;
+        }
+        let closing = matches!(outgoing.last(), Some(Message::Close(_)));
+        if let Err(e) = send_all(&mut sender, outgoing).await {
+            tracing::debug!("[WS] 写入失败，连接关闭: {}", e);
+            break;
+        }
+        if closing {
+            break;
+        }
+    }
+
+    let _ = sender.close().await;
+}
+
+/// 错误事件对应的关闭码：客户端请求错误 (4xx) 为 1008，其余为 1011
+fn error_close_code(event: &Value) -> u16 {
+    match event["error"]["status"].as_u64() {
+        Some(status) if (400..500).contains(&status) => close_code::POLICY,
+        _ => close_code::ERROR,
+    }
+}
+
+/// 单次对话会话：等待首个文本帧作为请求体，转发事件后发送 done 并关闭
+/// 执行期间客户端关闭连接即中止请求
+async fn run_chat_session<W, R, E, F>(mut sender: W, mut receiver: R, config: &WebSocketConfig, start: F)
+where
+    W: Sink<Message> + Unpin,
+    W::Error: Display,
+    R: Stream<Item = Result<Message, E>> + Unpin,
+    E: StdError + 'static,
+    F: FnOnce(Value) -> BoxStream<'static, Value>,
+{
+    let idle_timeout = Duration::from_secs(config.idle_timeout_secs.max(1));
+    let request = loop {
+        let incoming = match tokio::time::timeout(idle_timeout, receiver.next()).await {
+            Ok(incoming) => incoming,
+            Err(_) => {
+                let _ = send_all(&mut sender, vec![close_message(close_code::AWAY, "idle timeout")]).await;
+                break None;
+            }
+        };
+        match incoming {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str::<Value>(&text) {
+                Ok(request) if request.is_object() => break Some(request),
+                _ => {
+                    let out = vec![
+                        text_message(&error_frame(
+                            "invalid_request",
+                            "First frame must be a JSON chat completions request",
+                        )),
+                        close_message(close_code::INVALID, "invalid request"),
+                    ];
+                    let _ = send_all(&mut sender, out).await;
+                    break None;
+                }
+            },
+            Some(Ok(Message::Binary(_))) => {
+                let out = vec![close_message(close_code::UNSUPPORTED, "binary frames are not supported")];
+                let _ = send_all(&mut sender, out).await;
+                break None;
+            }
+            Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+            Some(Ok(Message::Close(_))) | None => break None,
+            Some(Err(e)) => {
+                tracing::debug!("[WS] 读取客户端帧失败: {}", e);
+                let out = vec![close_message(read_error_close_code(&e), "protocol error")];
+                let _ = send_all(&mut sender, out).await;
+                break None;
+            }
+        }
+    };
+
+    if let Some(request) = request {
+        let mut events = start(request);
+        loop {
+            let outgoing = tokio::select! {
+                event = events.next() => match event {
+                    Some(event) if event.get("error").is_some() => {
+                        vec![text_message(&event), close_message(error_close_code(&event), "request failed")]
+                    }
+                    Some(event) => vec![text_message(&event)],
+                    None => vec![text_message(&json!({ "done": true })), close_message(close_code::NORMAL, "")],
+                },
+                incoming = receiver.next() => match incoming {
+                    // 客户端关闭或连接中断：丢弃事件流即中止请求
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // 请求执行中的其他帧忽略 (Ping 由底层回复)
+                    Some(Ok(_)) => continue,
+                },
+            };
+            let closing = matches!(outgoing.last(), Some(Message::Close(_)));
+            if let Err(e) = send_all(&mut sender, outgoing).await {
+                tracing::debug!("[WS] 写入失败，连接关闭: {}", e);
+                break;
+            }
+            if closing {
+                break;
+            }
+        }
+    }
+
+    let _ = sender.close().await;
+}
+
+/// 拉取当前请求的下一个事件；无请求时永远挂起 (交给 select 其他分支)
+async fn next_event(active: &mut Option<ActiveRequest>) -> Option<Value> {
+    match active.as_mut() {
+        Some(req) => req.events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 处理客户端文本帧，返回需要回复的 JSON (若有)
+fn handle_client_frame<F>(
+    text: &str,
+    active: &mut Option<ActiveRequest>,
+    served: &mut u32,
+    config: &WebSocketConfig,
+    start: &F,
+) -> Option<Value>
+where
+    F: Fn(RequestFormat, Value) -> BoxStream<'static, Value>,
+{
+    let frame: ClientFrame = match serde_json::from_str(text) {
+        Ok(frame) => frame,
+        Err(e) => return Some(error_frame("invalid_request", &format!("Invalid frame: {}", e))),
+    };
+
+    if frame.cancel {
+        return match active.take() {
+            Some(req) => Some(json!({ "done": true, "cancelled": true, "usage": req.usage })),
+            None => Some(error_frame("no_active_request", "No request in progress")),
+        };
+    }
+
+    let (Some(format), Some(request)) = (frame.format, frame.request) else {
+        return Some(error_frame(
+            "invalid_request",
+            "Frame must contain 'format' (openai|anthropic) and 'request'",
+        ));
+    };
+    if active.is_some() {
+        return Some(error_frame(
+            "concurrent_request",
+            "A request is already in progress on this connection",
+        ));
+    }
+    if *served >= config.max_requests_per_connection {
+        return Some(error_frame("request_limit_exceeded", "Per-connection request limit reached"));
+    }
+
+    *served += 1;
+    *active = Some(ActiveRequest {
+        events: start(format, request),
+        usage: None,
+    });
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+
+    /// 以内存通道模拟一条 WebSocket 连接的客户端一侧
+    struct TestClient {
+        tx: mpsc::Sender<Result<Message, axum::Error>>,
+        rx: mpsc::Receiver<Message>,
+    }
+
+    impl TestClient {
+        async fn send(&mut self, message: Result<Message, axum::Error>) {
+            self.tx.send(message).await.unwrap();
+        }
+
+        async fn send_text(&mut self, text: &str) {
+            self.send(Ok(Message::Text(text.to_string()))).await;
+        }
+
+        async fn read_json(&mut self) -> Value {
+            match self.rx.next().await {
+                Some(Message::Text(text)) => serde_json::from_str(&text).unwrap(),
+                other => panic!("expected text frame, got {:?}", other),
+            }
+        }
+
+        /// 读取关闭帧的关闭码
+        async fn read_close_code(&mut self) -> u16 {
+            match self.rx.next().await {
+                Some(Message::Close(Some(frame))) => frame.code,
+                other => panic!("expected close frame, got {:?}", other),
+            }
+        }
+    }
+
+    /// 返回 (客户端, 服务端发送端, 服务端接收端)
+    fn connect() -> (TestClient, mpsc::Sender<Message>, mpsc::Receiver<Result<Message, axum::Error>>) {
+        let (client_tx, server_rx) = mpsc::channel(16);
+        let (server_tx, client_rx) = mpsc::channel(16);
+        (TestClient { tx: client_tx, rx: client_rx }, server_tx, server_rx)
+    }
+
+    fn test_config() -> WebSocketConfig {
+        WebSocketConfig {
+            enabled: true,
+            max_requests_per_connection: 2,
+            ..WebSocketConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: message_start\ndata: {\"type\":\"mess").is_empty());
+        let events = parser.push(b"age_start\"}\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec![json!({"type": "message_start"})]);
+    }
+
+    #[test]
+    fn test_merge_usage_across_claude_events() {
+        let mut usage = None;
+        merge_usage(&mut usage, &json!({"type": "message_start", "message": {"usage": {"input_tokens": 10, "output_tokens": 0}}}));
+        merge_usage(&mut usage, &json!({"type": "content_block_delta", "delta": {"text": "hi"}}));
+        merge_usage(&mut usage, &json!({"type": "message_delta", "usage": {"output_tokens": 5}}));
+        assert_eq!(usage, Some(json!({"input_tokens": 10, "output_tokens": 5})));
+    }
+
+    #[test]
+    fn test_read_error_close_code_matches_capacity_errors() {
+        let too_long = tungstenite::error::CapacityError::MessageTooLong { size: 9, max_size: 8 };
+        assert_eq!(read_error_close_code(&axum::Error::new(tungstenite::Error::Capacity(too_long))), 1009);
+        assert_eq!(read_error_close_code(&axum::Error::new(tungstenite::Error::ConnectionClosed)), 1002);
+        // 只认类型，不匹配错误文本
+        assert_eq!(read_error_close_code(&std::io::Error::other("Space limit exceeded")), 1002);
+    }
+
+    #[tokio::test]
+    async fn test_session_streams_events_then_done() {
+        let (mut client, sender, receiver) = connect();
+        let config = test_config();
+        let session = tokio::spawn(async move {
+            run_session(sender, receiver, &config, |format, _request| {
+                assert_eq!(format, RequestFormat::Openai);
+                stream::iter(vec![
+                    json!({"choices": [{"delta": {"content": "hi"}}]}),
+                    json!({"choices": [], "usage": {"prompt_tokens": 3, "completion_tokens": 1}}),
+                ])
+                .boxed()
+            })
+            .await;
+        });
+
+        client.send_text(r#"{"format":"openai","request":{}}"#).await;
+        assert_eq!(client.read_json().await["choices"][0]["delta"]["content"], "hi");
+        assert!(client.read_json().await["usage"].is_object());
+        let done = client.read_json().await;
+        assert_eq!(done["done"], true);
+        assert_eq!(done["usage"]["completion_tokens"], 1);
+
+        // 客户端关闭后会话结束
+        client.send(Ok(Message::Close(None))).await;
+        session.await.unwrap();
+        assert!(client.rx.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_rejects_concurrent_and_supports_cancel() {
+        let (mut client, sender, receiver) = connect();
+        let config = test_config();
+        tokio::spawn(async move {
+            // 永不结束的流，模拟长时间生成
+            run_session(sender, receiver, &config, |_, _| stream::pending().boxed()).await;
+        });
+
+        let request = r#"{"format":"anthropic","request":{}}"#;
+        client.send_text(request).await;
+        client.send_text(request).await;
+        assert_eq!(client.read_json().await["error"]["type"], "concurrent_request");
+
+        client.send_text(r#"{"cancel":true}"#).await;
+        let cancelled = client.read_json().await;
+        assert_eq!(cancelled["done"], true);
+        assert_eq!(cancelled["cancelled"], true);
+
+        client.send(Ok(Message::Binary(vec![1, 2, 3]))).await;
+        assert_eq!(client.read_json().await["error"]["type"], "invalid_request");
+
+        // 超过 max_message_bytes 的消息以 1009 关闭
+        let too_long = tungstenite::error::CapacityError::MessageTooLong { size: 9, max_size: 8 };
+        client.send(Err(axum::Error::new(tungstenite::Error::Capacity(too_long)))).await;
+        assert_eq!(client.read_close_code().await, 1009);
+    }
+
+    #[tokio::test]
+    async fn test_chat_session_streams_chunks_then_done() {
+        let (mut client, sender, receiver) = connect();
+        let config = test_config();
+        let session = tokio::spawn(async move {
+            run_chat_session(sender, receiver, &config, |request| {
+                assert_eq!(request["model"], "gpt-4o");
+                stream::iter(vec![
+                    json!({"object": "chat.completion.chunk", "choices": [{"delta": {"content": "hi"}}]}),
+                    json!({"object": "chat.completion.chunk", "choices": [{"delta": {}, "finish_reason": "stop"}]}),
+                ])
+                .boxed()
+            })
+            .await;
+        });
+
+        client.send_text(r#"{"model":"gpt-4o","messages":[]}"#).await;
+        assert_eq!(client.read_json().await["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(client.read_json().await["choices"][0]["finish_reason"], "stop");
+        assert_eq!(client.read_json().await, json!({"done": true}));
+        assert_eq!(client.read_close_code().await, 1000);
+        session.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_chat_session_error_closes_with_code() {
+        let (mut client, sender, receiver) = connect();
+        let config = test_config();
+        tokio::spawn(async move {
+            run_chat_session(sender, receiver, &config, |_| {
+                stream::iter(vec![json!({"error": {"status": 429, "detail": "rate limited"}})]).boxed()
+            })
+            .await;
+        });
+        client.send_text(r#"{"model":"gpt-4o"}"#).await;
+        assert_eq!(client.read_json().await["error"]["status"], 429);
+        assert_eq!(client.read_close_code().await, 1008);
+
+        // 首帧不是 JSON 请求体
+        let (mut client, sender, receiver) = connect();
+        let config = test_config();
+        tokio::spawn(async move {
+            run_chat_session(sender, receiver, &config, |_| stream::empty().boxed()).await;
+        });
+        client.send_text("not json").await;
+        assert_eq!(client.read_json().await["error"]["type"], "invalid_request");
+        assert_eq!(client.read_close_code().await, 1007);
+    }
+}
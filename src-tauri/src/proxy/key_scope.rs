@@ -0,0 +1,224 @@
+// 按 API Key 限制可用模型
+// 认证中间件识别出附加 Key 后，在请求上下文中记录其模型白名单；
+// 处理器完成模型路由解析后、选择账号前调用 check_model 校验。
+// 不在认证中间件建立的上下文中 (例如派生任务未带上作用域) 时一律拒绝 (fail closed)
+
+use crate::proxy::config::{ReportModel, ScopedApiKey};
+
+/// 当前请求所用 Key 的模型白名单
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelScope {
+    /// Key 的备注名 (日志用)
+    pub label: String,
+    /// 允许的模型 (glob，支持 `*` / `?`)，为空表示不限制
+    pub allowed_models: Vec<String>,
+    /// true 时按路由解析后的模型名校验，否则按客户端请求的模型名
+    pub check_resolved_model: bool,
+    /// 该 Key 的响应 model 上报方式 (None 时沿用全局设置)
+    pub report_model: Option<ReportModel>,
+}
+
+/// 请求的模型不在白名单内
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelNotAllowed {
+    pub model: String,
+    pub allowed_models: Vec<String>,
+}
+
+impl ModelNotAllowed {
+    pub fn message(&self) -> String {
+        if self.allowed_models.is_empty() {
+            return format!(
+                "Model '{}' is not allowed: the request has no API key scope.",
+                self.model
+            );
+        }
+        format!(
+            "Model '{}' is not allowed for this API key. Allowed models: {}",
+            self.model,
+            self.allowed_models.join(", ")
+        )
+    }
+}
+
+impl ModelScope {
+    pub fn from_key(key: &ScopedApiKey, check_resolved_model: bool) -> Self {
+        Self {
+            label: key.label.clone(),
+            allowed_models: key.allowed_models.clone(),
+            check_resolved_model,
+            report_model: key.report_model,
+        }
+    }
+
+    fn allows(&self, model: &str) -> bool {
+        self.allowed_models.is_empty()
+            || self.allowed_models.iter().any(|pattern| glob_match(pattern, model))
+    }
+
+    /// 按配置选择请求名或解析后的模型名进行校验
+    pub fn check(&self, requested: &str, resolved: &str) -> Result<(), ModelNotAllowed> {
+        let model = if self.check_resolved_model { resolved } else { requested };
+        if self.allows(model) {
+            Ok(())
+        } else {
+            Err(ModelNotAllowed {
+                model: model.to_string(),
+                allowed_models: self.allowed_models.clone(),
+            })
+        }
+    }
+}
+
+/// 大小写不敏感的 glob 匹配 (`*` 匹配任意长度，`?` 匹配单个字符)
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 `*` 的位置及其当时对应的 name 下标，用于回溯
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+tokio::task_local! {
+    static CURRENT_SCOPE: Option<ModelScope>;
+}
+
+/// 当前任务不在认证中间件建立的请求上下文中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingScope;
+
+/// 在 Key 的模型白名单上下文中执行 future (None 表示主 Key 或未认证，不限制)
+pub async fn scope_model_access<F: std::future::Future>(scope: Option<ModelScope>, fut: F) -> F::Output {
+    CURRENT_SCOPE.scope(scope, fut).await
+}
+
+/// 将 current_scope 捕获的作用域带入派生任务 (WebSocket 会话、批处理条目等)
+/// 捕获时已缺失的作用域保持缺失，派生任务中的校验同样拒绝
+pub async fn with_captured_scope<F: std::future::Future>(
+    captured: Result<Option<ModelScope>, MissingScope>,
+    fut: F,
+) -> F::Output {
+    match captured {
+        Ok(scope) => scope_model_access(scope, fut).await,
+        Err(MissingScope) => fut.await,
+    }
+}
+
+/// 当前请求的模型白名单
+/// Ok(None) 表示主 Key 或未开启认证 (不限制)；不在请求上下文中时返回 Err，调用方应按拒绝处理
+pub fn current_scope() -> Result<Option<ModelScope>, MissingScope> {
+    CURRENT_SCOPE.try_with(|scope| scope.clone()).map_err(|_| MissingScope)
+}
+
+/// 校验当前请求是否可以使用该模型
+pub fn check_model(requested: &str, resolved: &str) -> Result<(), ModelNotAllowed> {
+    match current_scope() {
+        Ok(Some(scope)) => scope.check(requested, resolved).inspect_err(|e| {
+            tracing::warn!("[KeyScope] Key '{}' 请求了未授权的模型 {}", scope.label, e.model);
+        }),
+        Ok(None) => Ok(()),
+        Err(MissingScope) => {
+            tracing::warn!("[KeyScope] 请求 {} 缺少 Key 作用域上下文，已拒绝", requested);
+            Err(ModelNotAllowed {
+                model: requested.to_string(),
+                allowed_models: Vec::new(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::model_mapping::resolve_model_route;
+    use std::collections::HashMap;
+
+    fn scope(allowed: &[&str], check_resolved_model: bool) -> ModelScope {
+        ModelScope {
+            label: "teammate".to_string(),
+            allowed_models: allowed.iter().map(|s| s.to_string()).collect(),
+            check_resolved_model,
+            report_model: None,
+        }
+    }
+
+    #[test]
+    fn glob_matches_client_facing_names() {
+        assert!(glob_match("gemini-*", "gemini-2.5-flash"));
+        assert!(glob_match("*flash*", "Gemini-2.5-Flash-Lite"));
+        assert!(glob_match("gpt-4?", "gpt-4o"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("gemini-*", "claude-sonnet-4-5"));
+        assert!(!glob_match("gpt-4?", "gpt-4o-mini"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn alias_checks_requested_name_unless_configured() {
+        // claude-sonnet-4-5 经自定义映射落到 gemini 模型
+        let custom = HashMap::from([("claude-sonnet-4-5".to_string(), "gemini-2.5-flash".to_string())]);
+        let resolved = resolve_model_route("claude-sonnet-4-5", &custom, &HashMap::new(), &HashMap::new(), true);
+        assert_eq!(resolved, "gemini-2.5-flash");
+
+        // 默认按客户端请求的名字校验：gemini-* 不放行 claude 别名
+        let err = scope(&["gemini-*"], false).check("claude-sonnet-4-5", &resolved).unwrap_err();
+        assert_eq!(err.model, "claude-sonnet-4-5");
+        assert!(err.message().contains("gemini-*"));
+
+        // 开启 check_resolved_model 后按映射结果校验
+        assert!(scope(&["gemini-*"], true).check("claude-sonnet-4-5", &resolved).is_ok());
+        // 反过来：允许 claude-* 但映射到 gemini 时被拒绝
+        assert!(scope(&["claude-*"], false).check("claude-sonnet-4-5", &resolved).is_ok());
+        assert!(scope(&["claude-*"], true).check("claude-sonnet-4-5", &resolved).is_err());
+
+        // 空白名单不限制
+        assert!(scope(&[], false).check("anything", "anything").is_ok());
+    }
+
+    #[tokio::test]
+    async fn check_model_uses_request_scope() {
+        let denied = scope_model_access(Some(scope(&["*flash*"], false)), async {
+            check_model("claude-opus-4", "claude-opus-4-5-thinking")
+        })
+        .await;
+        assert!(denied.is_err());
+        let allowed = scope_model_access(None, async { check_model("claude-opus-4", "x") }).await;
+        assert!(allowed.is_ok());
+    }
+
+    #[tokio::test]
+    async fn missing_scope_fails_closed() {
+        // 未经认证中间件 (没有作用域上下文) 时拒绝
+        let err = check_model("claude-opus-4", "claude-opus-4").unwrap_err();
+        assert!(err.message().contains("no API key scope"));
+
+        // 派生任务：捕获到的作用域原样带入，缺失的作用域保持缺失
+        let captured = scope_model_access(Some(scope(&["*flash*"], false)), async { current_scope() }).await;
+        let denied = tokio::spawn(with_captured_scope(captured, async { check_model("claude-opus-4", "x") }));
+        assert!(denied.await.unwrap().is_err());
+
+        let captured = scope_model_access(None, async { current_scope() }).await;
+        let allowed = tokio::spawn(with_captured_scope(captured, async { check_model("claude-opus-4", "x") }));
+        assert!(allowed.await.unwrap().is_ok());
+
+        let missing = tokio::spawn(with_captured_scope(current_scope(), async { check_model("claude-opus-4", "x") }));
+        assert!(missing.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+// 维护类流量 (模型探测等后台任务)
+// - 独立的小并发预算，不占用用户请求的容量
+// - 用户请求繁忙时最先被拒绝 (shed)
+// - 上游 429 只记录不触发账号冷却
+// - 不计入用户统计，单独计数
+//
+// 后台任务只能通过 MaintenanceContext 访问上游：它不暴露冷却 / 熔断等会影响真实流量的方法，
+// 且每次调用都需要 MaintenancePermit，从类型上保证预算与降级规则生效。
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::proxy::config::MaintenanceConfig;
+use crate::proxy::model_access::ModelAccessCache;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceStats {
+    /// 维护请求总数
+    pub requests: u64,
+    /// 上游返回 429 的次数 (仅记录)
+    pub rate_limited: u64,
+    /// 因用户流量繁忙或预算耗尽被拒绝的次数
+    pub shed: u64,
+    /// 当前进行中的维护请求
+    pub in_flight: usize,
+    /// 当前进行中的用户请求
+    pub user_in_flight: usize,
+}
+
+/// 维护流量的并发预算与降级判定
+pub struct MaintenanceGate {
+    config: RwLock<MaintenanceConfig>,
+    in_use: AtomicUsize,
+    user_in_flight: AtomicUsize,
+    requests: AtomicU64,
+    rate_limited: AtomicU64,
+    shed: AtomicU64,
+}
+
+impl Default for MaintenanceGate {
+    fn default() -> Self {
+        Self::new(MaintenanceConfig::default())
+    }
+}
+
+impl MaintenanceGate {
+    pub fn new(config: MaintenanceConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            in_use: AtomicUsize::new(0),
+            user_in_flight: AtomicUsize::new(0),
+            requests: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn update_config(&self, config: MaintenanceConfig) {
+        if let Ok(mut guard) = self.config.write() {
+            *guard = config;
+        }
+    }
+
+    /// 申请一个维护请求名额；用户请求繁忙或预算耗尽时立即拒绝，不排队
+    pub fn try_acquire(&self) -> Result<MaintenancePermit<'_>, String> {
+        let config = self.config.read().map(|c| c.clone()).unwrap_or_default();
+
+        let user_in_flight = self.user_in_flight.load(Ordering::SeqCst);
+        if user_in_flight >= config.shed_above_in_flight.max(1) {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(format!(
+                "Maintenance traffic shed: {} user requests in flight",
+                user_in_flight
+            ));
+        }
+
+        let max = config.max_concurrent.max(1);
+        let acquired = self
+            .in_use
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < max).then_some(n + 1))
+            .is_ok();
+        if !acquired {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(format!("Maintenance budget exhausted ({} concurrent)", max));
+        }
+
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        Ok(MaintenancePermit { gate: self })
+    }
+
+    /// 标记一个进行中的用户请求，守卫释放时自动减一
+    pub fn track_user_request(self: &Arc<Self>) -> UserRequestGuard {
+        self.user_in_flight.fetch_add(1, Ordering::SeqCst);
+        UserRequestGuard { gate: self.clone() }
+    }
+
+    fn record_rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> MaintenanceStats {
+        MaintenanceStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            shed: self.shed.load(Ordering::Relaxed),
+            in_flight: self.in_use.load(Ordering::SeqCst),
+            user_in_flight: self.user_in_flight.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// 维护请求名额，Drop 时归还
+pub struct MaintenancePermit<'a> {
+    gate: &'a MaintenanceGate,
+}
+
+impl Drop for MaintenancePermit<'_> {
+    fn drop(&mut self) {
+        self.gate.in_use.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 进行中的用户请求
+pub struct UserRequestGuard {
+    gate: Arc<MaintenanceGate>,
+}
+
+impl Drop for UserRequestGuard {
+    fn drop(&mut self) {
+        self.gate.user_in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 统计进行中的用户 API 请求 (流式响应在 body 结束时才释放)，供维护流量降级判定
+pub async fn user_load_middleware(
+    State(gate): State<Arc<MaintenanceGate>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !(path.starts_with("/v1/") || path.starts_with("/v1beta/")) {
+        return next.run(request).await;
+    }
+
+    let guard = gate.track_user_request();
+    let (parts, body) = next.run(request).await.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 后台任务访问账号与上游的唯一入口
+pub struct MaintenanceContext<'a> {
+    token_manager: &'a TokenManager,
+    upstream: &'a UpstreamClient,
+}
+
+impl<'a> MaintenanceContext<'a> {
+    pub fn new(token_manager: &'a TokenManager, upstream: &'a UpstreamClient) -> Self {
+        Self {
+            token_manager,
+            upstream,
+        }
+    }
+
+    pub fn try_acquire(&self) -> Result<MaintenancePermit<'a>, String> {
+        self.token_manager.maintenance().try_acquire()
+    }
+
+    /// 账号是否在代理账号池中
+    pub fn has_account(&self, email: &str) -> bool {
+        self.token_manager.account_id_for_email(email).is_some()
+    }
+
+    /// 按邮箱获取账号凭证 (access_token, project_id, account_id)
+    pub async fn token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
+        self.token_manager.get_token_by_email(email).await
+    }
+
+    /// 账号是否处于冷却 (只读，维护流量不会修改冷却状态)
+    pub fn is_rate_limited(&self, account_id: &str) -> bool {
+        self.token_manager.is_rate_limited(account_id)
+    }
+
+    pub fn cooldown_seconds(&self, account_id: &str) -> u64 {
+        self.token_manager
+            .get_rate_limit_reset_seconds(account_id)
+            .unwrap_or(0)
+    }
+
+    pub fn model_access(&self) -> &'a ModelAccessCache {
+        self.token_manager.model_access()
+    }
+
+    /// 以维护身份调用 generateContent；429 只计数，不触发账号冷却
+    pub async fn generate_content(
+        &self,
+        _permit: &MaintenancePermit<'_>,
+        access_token: &str,
+        body: Value,
+    ) -> Result<reqwest::Response, String> {
+        let resp = self
+            .upstream
+            .call_v1_internal("generateContent", access_token, body, None)
+            .await?;
+        if resp.status().as_u16() == 429 {
+            self.token_manager.maintenance().record_rate_limited();
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate(max_concurrent: usize, shed_above_in_flight: usize) -> Arc<MaintenanceGate> {
+        Arc::new(MaintenanceGate::new(MaintenanceConfig {
+            max_concurrent,
+            shed_above_in_flight,
+        }))
+    }
+
+    #[test]
+    fn test_budget_is_separate_and_bounded() {
+        let gate = gate(2, 8);
+        let a = gate.try_acquire().unwrap();
+        let _b = gate.try_acquire().unwrap();
+        assert!(gate.try_acquire().is_err());
+
+        drop(a);
+        assert!(gate.try_acquire().is_ok());
+
+        let stats = gate.stats();
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.shed, 1);
+    }
+
+    #[test]
+    fn test_shed_first_under_user_load() {
+        let gate = gate(4, 2);
+        let _u1 = gate.track_user_request();
+        assert!(gate.try_acquire().is_ok());
+
+        let u2 = gate.track_user_request();
+        assert!(gate.try_acquire().err().unwrap().contains("shed"));
+        assert_eq!(gate.stats().user_in_flight, 2);
+
+        drop(u2);
+        assert!(gate.try_acquire().is_ok());
+        assert_eq!(gate.stats().shed, 1);
+    }
+}
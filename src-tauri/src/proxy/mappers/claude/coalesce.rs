@@ -0,0 +1,262 @@
+// Claude 流式增量合并 (可选)
+// 批处理客户端不需要逐字输出，按字符数 / 时间间隔合并 text/thinking delta，减少事件数量
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 未指定 flush_interval_ms 时的默认刷新间隔
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 200;
+
+/// 合并配置 (按请求开启: `extra.min_chunk_chars` / `extra.flush_interval_ms`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoalesceConfig {
+    pub min_chunk_chars: usize,
+    pub flush_interval: Duration,
+}
+
+impl CoalesceConfig {
+    /// 从请求体的 `extra` 字段解析，未设置或 min_chunk_chars 为 0 时返回 None (保持逐条输出)
+    pub fn from_request(body: &Value) -> Option<Self> {
+        let extra = body.get("extra")?;
+        let min_chunk_chars = extra.get("min_chunk_chars").and_then(|v| v.as_u64())?;
+        if min_chunk_chars == 0 {
+            return None;
+        }
+        let flush_interval_ms = extra
+            .get("flush_interval_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_MS);
+
+        Some(Self {
+            min_chunk_chars: min_chunk_chars as usize,
+            flush_interval: Duration::from_millis(flush_interval_ms),
+        })
+    }
+}
+
+/// 暂存中的增量 (仅同一块、同一类型的 delta 可以合并)
+struct PendingDelta {
+    index: u64,
+    delta_type: &'static str,
+    field: &'static str,
+    text: String,
+    chars: usize,
+    since: Instant,
+}
+
+/// 增量合并器
+/// 非 text/thinking delta 的事件 (块开始/结束、签名、工具参数、结束事件) 会先刷新暂存内容再原样输出，
+/// 因此不会改变事件顺序，也不会跨块合并
+pub struct DeltaCoalescer {
+    config: CoalesceConfig,
+    pending: Option<PendingDelta>,
+}
+
+impl DeltaCoalescer {
+    pub fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            pending: None,
+        }
+    }
+
+    /// 输入一个 SSE 事件，返回需要立即发送的事件
+    pub fn push(&mut self, event: Bytes, now: Instant) -> Vec<Bytes> {
+        let Some((index, delta_type, field, text)) = parse_mergeable_delta(&event) else {
+            let mut out: Vec<Bytes> = self.flush().into_iter().collect();
+            out.push(event);
+            return out;
+        };
+
+        let mut out = Vec::new();
+        let matches = self
+            .pending
+            .as_ref()
+            .is_some_and(|p| p.index == index && p.delta_type == delta_type);
+        if !matches {
+            out.extend(self.flush());
+        }
+
+        let pending = self.pending.get_or_insert_with(|| PendingDelta {
+            index,
+            delta_type,
+            field,
+            text: String::new(),
+            chars: 0,
+            since: now,
+        });
+        pending.chars += text.chars().count();
+        pending.text.push_str(&text);
+
+        if pending.chars >= self.config.min_chunk_chars
+            || now.duration_since(pending.since) >= self.config.flush_interval
+        {
+            out.extend(self.flush());
+        }
+        out
+    }
+
+    /// 输出暂存内容 (结束、出错或到达刷新时间时调用)
+    pub fn flush(&mut self) -> Option<Bytes> {
+        let pending = self.pending.take()?;
+        let mut delta = json!({ "type": pending.delta_type });
+        delta[pending.field] = json!(pending.text);
+        let data = json!({
+            "type": "content_block_delta",
+            "index": pending.index,
+            "delta": delta
+        });
+        Some(Bytes::from(format!(
+            "event: content_block_delta\ndata: {}\n\n",
+            serde_json::to_string(&data).unwrap_or_default()
+        )))
+    }
+
+    /// 暂存内容的刷新截止时间
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .as_ref()
+            .map(|p| p.since + self.config.flush_interval)
+    }
+}
+
+/// 解析可合并的 delta 事件: (块索引, delta 类型, 文本字段名, 文本)
+fn parse_mergeable_delta(event: &[u8]) -> Option<(u64, &'static str, &'static str, String)> {
+    let text = std::str::from_utf8(event).ok()?;
+    if !text.starts_with("event: content_block_delta\n") {
+        return None;
+    }
+    let data = text.lines().find_map(|l| l.strip_prefix("data: "))?;
+    let value: Value = serde_json::from_str(data).ok()?;
+    let index = value.get("index")?.as_u64()?;
+    let delta = value.get("delta")?;
+
+    let (delta_type, field) = match delta.get("type")?.as_str()? {
+        "text_delta" => ("text_delta", "text"),
+        "thinking_delta" => ("thinking_delta", "thinking"),
+        _ => return None,
+    };
+    let content = delta.get(field)?.as_str()?.to_string();
+    Some((index, delta_type, field, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(index: u64, delta_type: &str, field: &str, text: &str) -> Bytes {
+        let data = json!({
+            "type": "content_block_delta",
+            "index": index,
+            "delta": { "type": delta_type, field: text }
+        });
+        Bytes::from(format!("event: content_block_delta\ndata: {}\n\n", data))
+    }
+
+    fn block_stop(index: u64) -> Bytes {
+        Bytes::from(format!(
+            "event: content_block_stop\ndata: {}\n\n",
+            json!({"type": "content_block_stop", "index": index})
+        ))
+    }
+
+    fn config(min_chunk_chars: usize) -> CoalesceConfig {
+        CoalesceConfig {
+            min_chunk_chars,
+            flush_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// 按顺序提取 (index, type, text)
+    fn decode(events: &[Bytes]) -> Vec<(String, u64, String)> {
+        events
+            .iter()
+            .map(|e| {
+                let text = std::str::from_utf8(e).unwrap();
+                let data: Value =
+                    serde_json::from_str(text.lines().find_map(|l| l.strip_prefix("data: ")).unwrap())
+                        .unwrap();
+                let kind = data["delta"]["type"]
+                    .as_str()
+                    .unwrap_or_else(|| data["type"].as_str().unwrap())
+                    .to_string();
+                let content = data["delta"]["text"]
+                    .as_str()
+                    .or(data["delta"]["thinking"].as_str())
+                    .unwrap_or("")
+                    .to_string();
+                (kind, data["index"].as_u64().unwrap(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_request_config() {
+        assert!(CoalesceConfig::from_request(&json!({"model": "x"})).is_none());
+        assert!(CoalesceConfig::from_request(&json!({"extra": {"min_chunk_chars": 0}})).is_none());
+
+        let config =
+            CoalesceConfig::from_request(&json!({"extra": {"min_chunk_chars": 64, "flush_interval_ms": 50}}))
+                .unwrap();
+        assert_eq!(config.min_chunk_chars, 64);
+        assert_eq!(config.flush_interval, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_coalesce_preserves_order_and_block_boundaries() {
+        let input = vec![
+            delta(0, "thinking_delta", "thinking", "Let "),
+            delta(0, "thinking_delta", "thinking", "me think"),
+            block_stop(0),
+            delta(1, "text_delta", "text", "Hel"),
+            delta(1, "text_delta", "text", "lo, "),
+            delta(1, "text_delta", "text", "world"),
+            block_stop(1),
+            delta(2, "text_delta", "text", "!"),
+        ];
+
+        let mut coalescer = DeltaCoalescer::new(config(1000));
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for event in input.iter().cloned() {
+            out.extend(coalescer.push(event, now));
+        }
+        out.extend(coalescer.flush());
+
+        assert_eq!(
+            decode(&out),
+            vec![
+                ("thinking_delta".to_string(), 0, "Let me think".to_string()),
+                ("content_block_stop".to_string(), 0, String::new()),
+                ("text_delta".to_string(), 1, "Hello, world".to_string()),
+                ("content_block_stop".to_string(), 1, String::new()),
+                ("text_delta".to_string(), 2, "!".to_string()),
+            ]
+        );
+
+        // 合并前后的总内容一致
+        let joined = |events: &[Bytes]| decode(events).into_iter().map(|(_, _, t)| t).collect::<String>();
+        assert_eq!(joined(&input), joined(&out));
+    }
+
+    #[test]
+    fn test_coalesce_flushes_on_threshold_and_interval() {
+        let mut coalescer = DeltaCoalescer::new(config(5));
+        let now = Instant::now();
+        assert!(coalescer.push(delta(0, "text_delta", "text", "abc"), now).is_empty());
+        let out = coalescer.push(delta(0, "text_delta", "text", "def"), now);
+        assert_eq!(decode(&out), vec![("text_delta".to_string(), 0, "abcdef".to_string())]);
+        assert!(coalescer.next_deadline().is_none());
+
+        let mut coalescer = DeltaCoalescer::new(CoalesceConfig {
+            min_chunk_chars: 1000,
+            flush_interval: Duration::from_millis(100),
+        });
+        assert!(coalescer.push(delta(0, "text_delta", "text", "a"), now).is_empty());
+        assert_eq!(coalescer.next_deadline(), Some(now + Duration::from_millis(100)));
+        let out = coalescer.push(delta(0, "text_delta", "text", "b"), now + Duration::from_millis(150));
+        assert_eq!(decode(&out), vec![("text_delta".to_string(), 0, "ab".to_string())]);
+    }
+}
@@ -0,0 +1,190 @@
+// Claude 流式 "仅输出最终答案" (可选)
+// 部分 UI 不展示思考过程，希望在正式回答开始后才渲染，但之后仍保持流式输出。
+// 开启后 (extra.stream_final_only) 思考块被暂存，直到第一个非思考内容块开始时再一次性输出
+// (extra.hide_thinking 为 true 时直接丢弃思考块并重排块索引)；静默期间定期发送 ping 防止客户端超时。
+// message_start / message_delta / message_stop 不受影响，usage 与 stop_reason 保持原样。
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 静默期 ping 间隔
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 配置 (按请求开启: `extra.stream_final_only` / `extra.hide_thinking`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinalOnlyConfig {
+    pub hide_thinking: bool,
+    pub ping_interval: Duration,
+}
+
+impl FinalOnlyConfig {
+    /// 从请求体的 `extra` 字段解析，未开启 stream_final_only 时返回 None
+    pub fn from_request(body: &Value) -> Option<Self> {
+        let extra = body.get("extra")?;
+        if !extra.get("stream_final_only").and_then(|v| v.as_bool()).unwrap_or(false) {
+            return None;
+        }
+        Some(Self {
+            hide_thinking: extra.get("hide_thinking").and_then(|v| v.as_bool()).unwrap_or(false),
+            ping_interval: PING_INTERVAL,
+        })
+    }
+}
+
+/// 最终答案闸门
+/// 放行前：思考块事件暂存 (或丢弃)，message_start / ping 直接输出；
+/// 第一个非思考内容块开始、或出现其他事件 (结束 / 错误) 时放行并先输出暂存内容
+pub struct FinalOnlyGate {
+    config: FinalOnlyConfig,
+    released: bool,
+    held: Vec<Bytes>,
+    /// 已丢弃的思考块数量 (用于重排后续块的索引)
+    dropped_blocks: u64,
+    /// 当前块是否为被丢弃的思考块
+    hiding_block: bool,
+    last_output: Instant,
+}
+
+impl FinalOnlyGate {
+    pub fn new(config: FinalOnlyConfig, now: Instant) -> Self {
+        Self {
+            config,
+            released: false,
+            held: Vec::new(),
+            dropped_blocks: 0,
+            hiding_block: false,
+            last_output: now,
+        }
+    }
+
+    /// 输入一个 SSE 事件，返回需要立即发送的事件
+    pub fn push(&mut self, event: Bytes, now: Instant) -> Vec<Bytes> {
+        let Some((name, mut data)) = parse_event(&event) else {
+            return self.emit(vec![event], now);
+        };
+
+        match name.as_str() {
+            "content_block_start" => {
+                let block_type = data
+                    .pointer("/content_block/type")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("");
+                let is_thinking = matches!(block_type, "thinking" | "redacted_thinking");
+                if is_thinking && self.config.hide_thinking {
+                    self.hiding_block = true;
+                    self.dropped_blocks += 1;
+                    return Vec::new();
+                }
+                let event = self.reindex(&name, &mut data).unwrap_or(event);
+                if is_thinking && !self.released {
+                    self.held.push(event);
+                    return Vec::new();
+                }
+                self.release(event, now)
+            }
+            "content_block_delta" | "content_block_stop" => {
+                if self.hiding_block {
+                    if name == "content_block_stop" {
+                        self.hiding_block = false;
+                    }
+                    return Vec::new();
+                }
+                let event = self.reindex(&name, &mut data).unwrap_or(event);
+                if !self.released {
+                    self.held.push(event);
+                    return Vec::new();
+                }
+                self.emit(vec![event], now)
+            }
+            "message_start" | "ping" => self.emit(vec![event], now),
+            _ => self.release(event, now),
+        }
+    }
+
+    /// 静默期的下一次 ping 时间 (放行后为 None)
+    pub fn next_ping_deadline(&self) -> Option<Instant> {
+        (!self.released).then(|| self.last_output + self.config.ping_interval)
+    }
+
+    /// 生成 ping 事件
+    pub fn ping(&mut self, now: Instant) -> Bytes {
+        self.last_output = now;
+        Bytes::from(format!("event: ping\ndata: {}\n\n", json!({"type": "ping"})))
+    }
+
+    fn release(&mut self, event: Bytes, now: Instant) -> Vec<Bytes> {
+        self.released = true;
+        let mut out = std::mem::take(&mut self.held);
+        out.push(event);
+        self.emit(out, now)
+    }
+
+    fn emit(&mut self, out: Vec<Bytes>, now: Instant) -> Vec<Bytes> {
+        if !out.is_empty() {
+            self.last_output = now;
+        }
+        out
+    }
+
+    /// 丢弃过思考块后，后续块的索引需要前移
+    fn reindex(&self, name: &str, data: &mut Value) -> Option<Bytes> {
+        if self.dropped_blocks == 0 {
+            return None;
+        }
+        let index = data.get("index")?.as_u64()?;
+        data["index"] = json!(index.saturating_sub(self.dropped_blocks));
+        Some(Bytes::from(format!(
+            "event: {}\ndata: {}\n\n",
+            name,
+            serde_json::to_string(data).unwrap_or_default()
+        )))
+    }
+}
+
+/// 解析单个 SSE 事件: (事件名, data)
+fn parse_event(event: &[u8]) -> Option<(String, Value)> {
+    let text = std::str::from_utf8(event).ok()?;
+    let name = text.lines().find_map(|l| l.strip_prefix("event: "))?;
+    let data = text.lines().find_map(|l| l.strip_prefix("data: "))?;
+    Some((name.to_string(), serde_json::from_str(data).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(hide_thinking: bool) -> FinalOnlyConfig {
+        FinalOnlyConfig {
+            hide_thinking,
+            ping_interval: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_from_request() {
+        assert!(FinalOnlyConfig::from_request(&json!({})).is_none());
+        assert!(FinalOnlyConfig::from_request(&json!({"extra": {"stream_final_only": false}})).is_none());
+        assert_eq!(
+            FinalOnlyConfig::from_request(&json!({"extra": {"stream_final_only": true, "hide_thinking": true}})),
+            Some(config(true))
+        );
+    }
+
+    #[test]
+    fn test_ping_deadline_only_while_silent() {
+        let start = Instant::now();
+        let mut gate = FinalOnlyGate::new(config(false), start);
+        assert_eq!(gate.next_ping_deadline(), Some(start + Duration::from_secs(10)));
+
+        let later = start + Duration::from_secs(10);
+        let ping = gate.ping(later);
+        assert_eq!(ping, Bytes::from("event: ping\ndata: {\"type\":\"ping\"}\n\n"));
+        assert_eq!(gate.next_ping_deadline(), Some(later + Duration::from_secs(10)));
+
+        let text_start = json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}});
+        gate.push(Bytes::from(format!("event: content_block_start\ndata: {}\n\n", text_start)), later);
+        assert!(gate.next_ping_deadline().is_none());
+    }
+}
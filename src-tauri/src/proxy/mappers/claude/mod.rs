@@ -1,12 +1,17 @@
 // Claude mapper 模块
 // 负责 Claude ↔ Gemini 协议转换
 
+pub mod coalesce;
+pub mod final_only;
 pub mod models;
 pub mod request;
 pub mod response;
+pub mod resume;
 pub mod streaming;
 pub mod utils;
 
+pub use coalesce::{CoalesceConfig, DeltaCoalescer};
+pub use final_only::{FinalOnlyConfig, FinalOnlyGate};
 pub use models::*;
 pub use request::transform_claude_request_in;
 pub use response::transform_response;
@@ -16,21 +21,65 @@ use bytes::Bytes;
 use futures::Stream;
 use std::pin::Pin;
 
+use crate::proxy::common::stream_usage::UsageTicker;
+
 /// 创建从 Gemini SSE 流到 Claude SSE 流的转换
-pub fn create_claude_sse_stream(
-    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+/// `coalesce` 为 Some 时合并细碎的 text/thinking delta (批处理场景)
+/// `final_only` 为 Some 时暂存思考块，正式回答开始后再输出 (静默期发送 ping)
+/// 上游中途出错时输出 Anthropic `error` 事件并结束流 (不再补发 message_stop)
+/// `stop_sequences` 为客户端停止序列，用于在结束事件中报告 stop_reason = "stop_sequence"
+pub fn create_claude_sse_stream<E: std::fmt::Display + Send + 'static>(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
     trace_id: String,
     email: String,
+    estimated_input_tokens: u32,
+    coalesce: Option<CoalesceConfig>,
+    final_only: Option<FinalOnlyConfig>,
+    stop_sequences: Vec<String>,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     use async_stream::stream;
     use bytes::BytesMut;
     use futures::StreamExt;
+    use tokio::time::Instant;
 
     Box::pin(stream! {
-        let mut state = StreamingState::new();
+        let mut state = StreamingState::with_estimated_input_tokens(estimated_input_tokens)
+            .with_stop_sequences(stop_sequences)
+            .with_usage_ticker(UsageTicker::from_config(std::time::Instant::now()));
         let mut buffer = BytesMut::new();
+        let mut coalescer = coalesce.map(DeltaCoalescer::new);
+        let mut gate = final_only.map(|c| FinalOnlyGate::new(c, Instant::now()));
+
+        loop {
+            // 有暂存增量或处于静默期时，最多等待到最近的截止时间
+            let deadline = [
+                coalescer.as_ref().and_then(|c| c.next_deadline()),
+                gate.as_ref().and_then(|g| g.next_ping_deadline()),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, gemini_stream.next()).await {
+                    Ok(item) => item,
+                    Err(_) => {
+                        let now = Instant::now();
+                        if coalescer.as_ref().and_then(|c| c.next_deadline()).is_some_and(|d| d <= now) {
+                            let pending: Vec<Bytes> = coalescer.as_mut().and_then(|c| c.flush()).into_iter().collect();
+                            for out in pass_gate(&mut gate, pending) {
+                                yield Ok(out);
+                            }
+                        }
+                        if let Some(g) = gate.as_mut().filter(|g| g.next_ping_deadline().is_some_and(|d| d <= now)) {
+                            yield Ok(g.ping(now));
+                        }
+                        continue;
+                    }
+                },
+                None => gemini_stream.next().await,
+            };
+            let Some(chunk_result) = next else { break };
 
-        while let Some(chunk_result) = gemini_stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
                     buffer.extend_from_slice(&chunk);
@@ -44,26 +93,59 @@ pub fn create_claude_sse_stream(
 
                             if let Some(sse_chunks) = process_sse_line(line, &mut state, &trace_id, &email) {
                                 for sse_chunk in sse_chunks {
-                                    yield Ok(sse_chunk);
+                                    let outs = match coalescer.as_mut() {
+                                        Some(c) => c.push(sse_chunk, Instant::now()),
+                                        None => vec![sse_chunk],
+                                    };
+                                    for out in pass_gate(&mut gate, outs) {
+                                        yield Ok(out);
+                                    }
                                 }
                             }
                         }
                     }
                 }
                 Err(e) => {
-                    yield Err(format!("Stream error: {}", e));
-                    break;
+                    let mut outs: Vec<Bytes> = coalescer.as_mut().and_then(|c| c.flush()).into_iter().collect();
+                    tracing::warn!("[{}] Stream interrupted after output started: {}", trace_id, e);
+                    outs.push(stream_error_event(&format!("Stream error: {}", e)));
+                    for out in pass_gate(&mut gate, outs) {
+                        yield Ok(out);
+                    }
+                    return;
                 }
             }
         }
 
+        let mut outs: Vec<Bytes> = coalescer.as_mut().and_then(|c| c.flush()).into_iter().collect();
         // Ensure termination events are sent
-        for chunk in emit_force_stop(&mut state) {
-            yield Ok(chunk);
+        outs.extend(emit_force_stop(&mut state));
+        for out in pass_gate(&mut gate, outs) {
+            yield Ok(out);
         }
     })
 }
 
+/// 经过 "仅最终答案" 闸门 (未开启时原样返回)
+fn pass_gate(gate: &mut Option<FinalOnlyGate>, events: Vec<Bytes>) -> Vec<Bytes> {
+    match gate.as_mut() {
+        Some(g) => {
+            let now = tokio::time::Instant::now();
+            events.into_iter().flat_map(|e| g.push(e, now)).collect()
+        }
+        None => events,
+    }
+}
+
+/// Anthropic 流式错误事件
+pub fn stream_error_event(message: &str) -> Bytes {
+    let event = serde_json::json!({
+        "type": "error",
+        "error": {"type": "api_error", "message": message}
+    });
+    Bytes::from(format!("event: error\ndata: {}\n\n", event))
+}
+
 /// 处理单行 SSE 数据
 fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, email: &str) -> Option<Vec<Bytes>> {
     if !line.starts_with("data: ") {
@@ -94,6 +176,14 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     // 解包 response 字段 (如果存在)
     let raw_json = json_value.get("response").unwrap_or(&json_value);
 
+    // [NEW] 累积 usageMetadata (部分 chunk 才携带，且为累计值)
+    if let Some(u) = raw_json
+        .get("usageMetadata")
+        .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+    {
+        state.record_usage(u);
+    }
+
     // 发送 message_start
     if !state.message_start_sent {
         chunks.push(state.emit_message_start(raw_json));
@@ -130,6 +220,15 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     {
         for part_value in parts {
             if let Ok(part) = serde_json::from_value::<GeminiPart>(part_value.clone()) {
+                if let Some(text) = &part.text {
+                    state.record_output_text(text);
+                }
+                if let Some(fc) = &part.function_call {
+                    state.record_output_text(&fc.name);
+                    if let Some(args) = &fc.args {
+                        state.record_output_text(&args.to_string());
+                    }
+                }
                 let mut processor = PartProcessor::new(state);
                 chunks.extend(processor.process(&part));
             }
@@ -158,27 +257,27 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
             .get("usageMetadata")
             .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok());
 
-        if let Some(ref u) = usage {
-            let cached_tokens = u.cached_content_token_count.unwrap_or(0);
-            let cache_info = if cached_tokens > 0 {
-                format!(", Cached: {}", cached_tokens)
-            } else {
-                String::new()
-            };
-            
-             tracing::info!(
-                 "[{}] ✓ Stream completed | Account: {} | In: {} tokens | Out: {} tokens{}", 
-                 trace_id,
-                 email,
-                 u.prompt_token_count.unwrap_or(0).saturating_sub(cached_tokens), 
-                 u.candidates_token_count.unwrap_or(0),
-                 cache_info
-             );
-        }
+        let total_usage = state.current_usage();
+        let cache_info = match total_usage.cache_read_input_tokens {
+            Some(cached) => format!(", Cached: {}", cached),
+            None => String::new(),
+        };
+        tracing::info!(
+            "[{}] ✓ Stream completed | Account: {} | In: {} tokens | Out: {} tokens{}{}",
+            trace_id,
+            email,
+            total_usage.input_tokens,
+            total_usage.output_tokens,
+            cache_info,
+            if state.has_upstream_usage() { "" } else { " (estimated)" }
+        );
 
         chunks.extend(state.emit_finish(Some(finish_reason), usage.as_ref()));
     }
 
+    // 周期性用量 (结束事件之后不再输出)
+    chunks.extend(state.emit_usage_progress());
+
     if chunks.is_empty() {
         None
     } else {
@@ -341,6 +440,57 @@ mod tests {
         assert!(all_text.contains("message_stop"));
     }
 
+    #[test]
+    fn test_periodic_usage_deltas_match_final_usage() {
+        use crate::proxy::config::StreamUsageConfig;
+
+        let ticker = UsageTicker::new(
+            &StreamUsageConfig { enabled: true, interval_secs: 0, every_output_tokens: 2 },
+            std::time::Instant::now(),
+        );
+        let mut state = StreamingState::new().with_usage_ticker(ticker);
+        let lines = [
+            r#"data: {"candidates":[{"content":{"parts":[{"text":"Hello"}]}}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":2}}"#,
+            r#"data: {"candidates":[{"content":{"parts":[{"text":" there"}]}}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":3}}"#,
+            r#"data: {"candidates":[{"content":{"parts":[{"text":", friend"}]}}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":6}}"#,
+            r#"data: {"candidates":[{"content":{"parts":[{"text":"!"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":9}}"#,
+        ];
+        let events: Vec<serde_json::Value> = lines
+            .iter()
+            .flat_map(|line| process_sse_line(line, &mut state, "test_id", "test@example.com").unwrap_or_default())
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .filter_map(|event| {
+                let data = event.lines().find_map(|l| l.strip_prefix("data: "))?.to_string();
+                serde_json::from_str(&data).ok()
+            })
+            .filter(|v: &serde_json::Value| v["type"] == "message_delta")
+            .collect();
+
+        // 周期事件 stop_reason 为 null，最后一个为结束事件
+        let (last, periodic) = events.split_last().unwrap();
+        assert_eq!(last["delta"]["stop_reason"], "end_turn");
+        let periodic: Vec<u64> = periodic
+            .iter()
+            .inspect(|v| assert!(v["delta"]["stop_reason"].is_null()))
+            .map(|v| v["usage"]["output_tokens"].as_u64().unwrap())
+            .collect();
+        assert_eq!(periodic, vec![2, 6]);
+
+        let final_tokens = last["usage"]["output_tokens"].as_u64().unwrap();
+        let mut previous = 0;
+        let summed: u64 = periodic
+            .iter()
+            .chain([final_tokens].iter())
+            .map(|&v| {
+                let delta = v - previous;
+                previous = v;
+                delta
+            })
+            .sum();
+        assert_eq!(summed, final_tokens);
+        assert_eq!(final_tokens, 9);
+    }
+
     #[test]
     fn test_process_sse_line_with_text() {
         let mut state = StreamingState::new();
@@ -363,4 +513,364 @@ mod tests {
         assert!(all_text.contains("content_block_start"));
         assert!(all_text.contains("Hello"));
     }
+
+    #[test]
+    fn test_stream_usage_estimated_without_metadata() {
+        let mut state = StreamingState::with_estimated_input_tokens(42);
+
+        let first = r#"data: {"candidates":[{"content":{"parts":[{"text":"Hello world, this is a test"}]}}]}"#;
+        let start: String = process_sse_line(first, &mut state, "test_id", "test@example.com")
+            .unwrap()
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+        assert!(start.contains(r#""input_tokens":42"#));
+
+        let last = r#"data: {"candidates":[{"content":{"parts":[{"text":"!"}]},"finishReason":"STOP"}]}"#;
+        let finish: String = process_sse_line(last, &mut state, "test_id", "test@example.com")
+            .unwrap()
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+        assert!(finish.contains("message_delta"));
+        assert!(!finish.contains(r#""output_tokens":0"#));
+        assert_eq!(state.current_usage().input_tokens, 42);
+    }
+
+    #[test]
+    fn test_stream_usage_accumulates_metadata() {
+        let mut state = StreamingState::with_estimated_input_tokens(1);
+
+        let first = r#"data: {"candidates":[{"content":{"parts":[{"text":"Hi"}]}}],"usageMetadata":{"promptTokenCount":120}}"#;
+        process_sse_line(first, &mut state, "test_id", "test@example.com");
+
+        let last = r#"data: {"candidates":[{"content":{"parts":[{"text":"!"}]},"finishReason":"STOP"}],"usageMetadata":{"candidatesTokenCount":7}}"#;
+        let finish: String = process_sse_line(last, &mut state, "test_id", "test@example.com")
+            .unwrap()
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+        assert!(finish.contains(r#""input_tokens":120"#));
+        assert!(finish.contains(r#""output_tokens":7"#));
+    }
+
+    #[test]
+    fn test_concurrent_streams_capture_signatures_without_starvation() {
+        use crate::proxy::mappers::signature_store::{clear_thought_signature, get_thought_signature, test_guard};
+        use futures::StreamExt;
+        use serde_json::json;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let _guard = test_guard();
+        clear_thought_signature();
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            // 心跳任务：签名捕获若阻塞执行器，心跳将无法推进
+            let ticks = Arc::new(AtomicUsize::new(0));
+            let ticker = {
+                let ticks = ticks.clone();
+                tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                        ticks.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            };
+
+            let streams = (0..64).map(|i| {
+                tokio::spawn(async move {
+                    let signature = format!("sig-{}", "x".repeat(i));
+                    let chunks: Vec<Result<Bytes, reqwest::Error>> = (0..20)
+                        .map(|n| {
+                            let line = json!({
+                                "candidates": [{"content": {"parts": [{
+                                    "functionCall": {"name": format!("tool_{}", n), "args": {"n": n}},
+                                    "thoughtSignature": signature
+                                }]}}]
+                            });
+                            Ok(Bytes::from(format!("data: {}\n\n", line)))
+                        })
+                        .chain(std::iter::once(Ok(Bytes::from(
+                            "data: {\"candidates\":[{\"content\":{\"parts\":[]},\"finishReason\":\"STOP\"}]}\n\n",
+                        ))))
+                        .collect();
+
+                    let output: String = create_claude_sse_stream(
+                        Box::pin(futures::stream::iter(chunks)),
+                        format!("trace-{}", i),
+                        "test@example.com".to_string(),
+                        10,
+                        None,
+                        None,
+                        Vec::new(),
+                    )
+                    .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+                    .collect::<Vec<_>>()
+                    .await
+                    .concat();
+                    assert!(output.contains("message_stop"));
+                    assert_eq!(output.matches("\"type\":\"tool_use\"").count(), 20);
+                })
+            });
+
+            let all = futures::future::join_all(streams);
+            let results = tokio::time::timeout(std::time::Duration::from_secs(10), all)
+                .await
+                .expect("concurrent streams stalled");
+            for result in results {
+                result.unwrap();
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            assert!(ticks.load(Ordering::Relaxed) > 0, "heartbeat task was starved");
+            ticker.abort();
+        });
+
+        // 最长的签名胜出
+        assert_eq!(get_thought_signature(), Some(format!("sig-{}", "x".repeat(63))));
+        clear_thought_signature();
+    }
+
+    #[tokio::test]
+    async fn test_tool_use_round_trip_sse_sequence() {
+        use futures::StreamExt;
+        use serde_json::{json, Value};
+
+        // 第一轮 assistant 调用工具，第二轮 user 返回结果
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "user", "content": "What's the weather in Paris?"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "18C, cloudy"}]}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let body = {
+            let _guard = crate::proxy::mappers::signature_store::test_guard();
+            transform_claude_request_in(&req, "test-project").unwrap()
+        };
+        let contents = body["request"]["contents"].as_array().unwrap();
+        let call = &contents[1]["parts"][0]["functionCall"];
+        assert_eq!(call["name"], "get_weather");
+        assert_eq!(call["args"]["city"], "Paris");
+        let result = &contents[2]["parts"][0]["functionResponse"];
+        assert_eq!(result["name"], "get_weather");
+        assert_eq!(result["id"], "toolu_1");
+        assert_eq!(result["response"]["result"], "18C, cloudy");
+
+        // 上游再次返回 functionCall，转换为 Claude tool_use 事件
+        let upstream = json!({
+            "candidates": [{
+                "content": {"parts": [{"functionCall": {"id": "toolu_2", "name": "get_weather", "args": {"city": "Lyon"}}}]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 30, "candidatesTokenCount": 8}
+        });
+        let chunks: Vec<Result<Bytes, reqwest::Error>> =
+            vec![Ok(Bytes::from(format!("data: {}\n\n", upstream)))];
+        let output: String = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            10,
+            None,
+            None,
+            Vec::new(),
+        )
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        let events: Vec<Value> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect();
+        let types: Vec<&str> = events.iter().filter_map(|e| e["type"].as_str()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+
+        let block = &events[1]["content_block"];
+        assert_eq!(block["type"], "tool_use");
+        assert_eq!(block["id"], "toolu_2");
+        assert_eq!(block["name"], "get_weather");
+        assert_eq!(events[2]["delta"]["type"], "input_json_delta");
+        let input: Value = serde_json::from_str(events[2]["delta"]["partial_json"].as_str().unwrap()).unwrap();
+        assert_eq!(input, json!({"city": "Lyon"}));
+        assert_eq!(events[4]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[4]["usage"]["output_tokens"], 8);
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_after_content_emits_error_event() {
+        use futures::StreamExt;
+        let text = serde_json::json!({"candidates": [{"content": {"parts": [{"text": "Hello"}]}}]});
+        let chunks: Vec<Result<Bytes, String>> = vec![
+            Ok(Bytes::from(format!("data: {}\n\n", text))),
+            Err("connection reset".to_string()),
+        ];
+        let output: String = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            10,
+            None,
+            None,
+            Vec::new(),
+        )
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        assert!(output.contains("Hello"));
+        assert!(output.ends_with(
+            "event: error\ndata: {\"error\":{\"message\":\"Stream error: connection reset\",\"type\":\"api_error\"},\"type\":\"error\"}\n\n"
+        ));
+        assert!(!output.contains("message_stop"));
+    }
+
+    use serde_json::{json, Value};
+
+    /// 长思考 fixture：大量思考分片 → 正文 → 结束 (携带 usage)
+    fn long_thinking_fixture() -> (Vec<String>, Vec<String>) {
+        let thinking = (0..200)
+            .map(|i| {
+                let part = serde_json::json!({"candidates": [{"content": {"parts": [
+                    {"text": format!("step {} of a long chain of thought. ", i), "thought": true}
+                ]}}]});
+                format!("data: {}\n\n", part)
+            })
+            .collect();
+        let answer = vec![
+            format!("data: {}\n\n", serde_json::json!({"candidates": [{"content": {"parts": [{"text": "The answer"}]}}]})),
+            format!("data: {}\n\n", serde_json::json!({
+                "candidates": [{"content": {"parts": [{"text": " is 42."}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 30, "candidatesTokenCount": 900, "thoughtsTokenCount": 850}
+            })),
+        ];
+        (thinking, answer)
+    }
+
+    async fn run_stream(lines: Vec<String>, final_only: Option<FinalOnlyConfig>) -> Vec<(String, Value)> {
+        use futures::StreamExt;
+        let chunks: Vec<Result<Bytes, String>> = lines.into_iter().map(|l| Ok(Bytes::from(l))).collect();
+        let output: String = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            10,
+            None,
+            final_only,
+            Vec::new(),
+        )
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+        decode_events(&output)
+    }
+
+    fn decode_events(output: &str) -> Vec<(String, Value)> {
+        output
+            .split("\n\n")
+            .filter_map(|event| {
+                let name = event.lines().find_map(|l| l.strip_prefix("event: "))?;
+                let data = event.lines().find_map(|l| l.strip_prefix("data: "))?;
+                Some((name.to_string(), serde_json::from_str(data).ok()?))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_final_only_holds_thinking_until_answer_starts() {
+        use futures::StreamExt;
+        use std::time::Duration;
+
+        let (thinking, answer) = long_thinking_fixture();
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Bytes, String>>();
+        let config = FinalOnlyConfig { hide_thinking: false, ping_interval: Duration::from_millis(20) };
+        let mut stream = create_claude_sse_stream(Box::pin(rx), "trace".to_string(), "t@example.com".to_string(), 10, None, Some(config), Vec::new());
+
+        for line in &thinking {
+            tx.unbounded_send(Ok(Bytes::from(line.clone()))).unwrap();
+        }
+        // 静默期：只有 message_start 与 ping
+        let mut silent = String::new();
+        while let Ok(Some(chunk)) = tokio::time::timeout(Duration::from_millis(100), stream.next()).await {
+            silent.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+            if silent.matches("event: ping").count() >= 2 {
+                break;
+            }
+        }
+        let silent_events = decode_events(&silent);
+        assert_eq!(silent_events[0].0, "message_start");
+        assert!(silent_events[1..].iter().all(|(name, _)| name == "ping"));
+        assert!(silent_events.len() >= 3);
+
+        for line in &answer {
+            tx.unbounded_send(Ok(Bytes::from(line.clone()))).unwrap();
+        }
+        drop(tx);
+        let rest: String = stream
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let rest_events = decode_events(&rest);
+
+        // 放行后的输出与普通流式一致 (去掉 message_start)
+        let baseline = run_stream(thinking.into_iter().chain(answer).collect(), None).await;
+        assert_eq!(rest_events, baseline[1..].to_vec());
+        assert!(rest.contains("thinking_delta"));
+    }
+
+    #[tokio::test]
+    async fn test_final_only_hide_thinking_keeps_usage_and_indices() {
+        use std::time::Duration;
+
+        let (thinking, answer) = long_thinking_fixture();
+        let lines: Vec<String> = thinking.into_iter().chain(answer).collect();
+        let baseline = run_stream(lines.clone(), None).await;
+        let config = FinalOnlyConfig { hide_thinking: true, ping_interval: Duration::from_secs(10) };
+        let hidden = run_stream(lines, Some(config)).await;
+
+        assert!(hidden.iter().all(|(_, data)| {
+            data.pointer("/content_block/type") != Some(&json!("thinking"))
+                && data.pointer("/delta/type") != Some(&json!("thinking_delta"))
+        }));
+        let text_start = hidden.iter().find(|(name, _)| name == "content_block_start").unwrap();
+        assert_eq!(text_start.1["index"], 0);
+        assert_eq!(text_start.1["content_block"]["type"], "text");
+
+        let message_delta = |events: &[(String, Value)]| {
+            events.iter().find(|(name, _)| name == "message_delta").unwrap().1.clone()
+        };
+        assert_eq!(message_delta(&hidden), message_delta(&baseline));
+        assert_eq!(message_delta(&hidden)["delta"]["stop_reason"], "end_turn");
+        assert_eq!(hidden.last().unwrap().0, "message_stop");
+    }
 }
@@ -0,0 +1,419 @@
+// Claude 流式断线续传 (Last-Event-ID)
+// 客户端断线重连时可能带上 Last-Event-ID 期望续传；若当作新请求处理会重新生成一遍，
+// 重复消耗配额，客户端还会收到一个新的 message id。
+// - 默认不支持续传：带 Last-Event-ID 的 /v1/messages 请求直接返回 409
+// - 开启 stream_resume 后 (best-effort)：每个 SSE 事件附带 `id: <message_id>:<seq>`；
+//   生成过程与客户端连接解耦，由后台任务把事件写入按 message id 缓存的缓冲区，客户端从缓冲区读取，
+//   断开后生成仍会跑完。TTL 内重连从请求的事件之后重放，缓存已过期或已被挤出时返回 409。
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use super::stream_error_event;
+use crate::proxy::config::StreamResumeConfig;
+
+pub const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// 续传读取端等待新事件的最长时间 (超时视为生成已中断)
+const READER_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>;
+
+/// 续传请求无法满足的原因 (均以 409 返回)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResumeError {
+    /// 未开启 stream_resume
+    Unsupported,
+    /// Last-Event-ID 不是本代理发出的事件 id
+    InvalidId,
+    /// 缓存已过期或请求的事件已被挤出
+    Unavailable,
+}
+
+impl ResumeError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ResumeError::Unsupported => {
+                "Stream resumption (Last-Event-ID) is not supported by this proxy. \
+                 Resend the request without the Last-Event-ID header to start a new generation."
+            }
+            ResumeError::InvalidId => {
+                "Unrecognized Last-Event-ID; resumption is only possible for event ids issued by this proxy. \
+                 Resend the request without the Last-Event-ID header to start a new generation."
+            }
+            ResumeError::Unavailable => {
+                "The stream for this Last-Event-ID is no longer available (expired or evicted). \
+                 Resend the request without the Last-Event-ID header to start a new generation."
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum EntryState {
+    Streaming,
+    Done,
+    /// 生成任务异常结束 (未正常跑完)
+    Aborted,
+}
+
+struct EntryInner {
+    /// (seq, 含 id 行的完整事件)
+    events: VecDeque<(u64, Bytes)>,
+    next_seq: u64,
+    state: EntryState,
+    updated_at: Instant,
+}
+
+/// 一条消息的事件缓冲 (生成任务写入，任意数量的读取端读取)
+struct ResumeEntry {
+    inner: Mutex<EntryInner>,
+    notify: Notify,
+    max_events: usize,
+}
+
+impl ResumeEntry {
+    fn new(max_events: usize) -> Self {
+        Self {
+            inner: Mutex::new(EntryInner {
+                events: VecDeque::new(),
+                next_seq: 0,
+                state: EntryState::Streaming,
+                updated_at: Instant::now(),
+            }),
+            notify: Notify::new(),
+            max_events: max_events.max(1),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, EntryInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 追加一个事件；message_id 已知时写入 `id:` 行
+    fn append(&self, message_id: Option<&str>, event: &[u8]) {
+        {
+            let mut inner = self.lock();
+            let seq = inner.next_seq;
+            inner.next_seq += 1;
+            let bytes = match message_id {
+                Some(id) => {
+                    let mut buf = BytesMut::with_capacity(event.len() + id.len() + 16);
+                    buf.extend_from_slice(format!("id: {}:{}\n", id, seq).as_bytes());
+                    buf.extend_from_slice(event);
+                    buf.freeze()
+                }
+                None => Bytes::copy_from_slice(event),
+            };
+            inner.events.push_back((seq, bytes));
+            while inner.events.len() > self.max_events {
+                inner.events.pop_front();
+            }
+            inner.updated_at = Instant::now();
+        }
+        self.notify.notify_waiters();
+    }
+
+    fn finish(&self, state: EntryState) {
+        {
+            let mut inner = self.lock();
+            if inner.state == EntryState::Streaming {
+                inner.state = state;
+            }
+            inner.updated_at = Instant::now();
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+/// 生成任务异常退出 (panic / 运行时关闭) 时把缓冲标记为中断，避免读取端一直等待
+struct ProducerGuard(Arc<ResumeEntry>);
+
+impl Drop for ProducerGuard {
+    fn drop(&mut self) {
+        self.0.finish(EntryState::Aborted);
+    }
+}
+
+/// 从 `from_seq` 开始读取缓冲，生成结束后结束流
+fn read_entry(entry: Arc<ResumeEntry>, from_seq: u64) -> SseStream {
+    Box::pin(async_stream::stream! {
+        let mut next = from_seq;
+        loop {
+            let notified = entry.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let (batch, state, evicted) = {
+                let inner = entry.lock();
+                let first = inner.events.front().map(|(seq, _)| *seq).unwrap_or(inner.next_seq);
+                let batch: Vec<(u64, Bytes)> = inner
+                    .events
+                    .iter()
+                    .filter(|(seq, _)| *seq >= next)
+                    .cloned()
+                    .collect();
+                (batch, inner.state.clone(), next < first)
+            };
+
+            if evicted {
+                yield Ok(stream_error_event("Stream resumption failed: events were evicted from the resume buffer"));
+                return;
+            }
+            let progressed = !batch.is_empty();
+            for (seq, bytes) in batch {
+                next = seq + 1;
+                yield Ok(bytes);
+            }
+            match state {
+                EntryState::Done => return,
+                EntryState::Aborted => {
+                    yield Ok(stream_error_event("Generation was interrupted before completion"));
+                    return;
+                }
+                EntryState::Streaming => {}
+            }
+            if !progressed
+                && tokio::time::timeout(READER_IDLE_TIMEOUT, notified).await.is_err()
+            {
+                yield Ok(stream_error_event("Generation stalled; no new events"));
+                return;
+            }
+        }
+    })
+}
+
+/// 从 message_start 事件中提取 message id
+fn message_id_of(event: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(event).ok()?;
+    let data = text.lines().find_map(|l| l.strip_prefix("data: "))?;
+    let value: Value = serde_json::from_str(data).ok()?;
+    if value.get("type").and_then(|v| v.as_str()) != Some("message_start") {
+        return None;
+    }
+    value
+        .get("message")
+        .and_then(|m| m.get("id"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+/// 续传缓存
+pub struct ResumeStore {
+    config: Mutex<StreamResumeConfig>,
+    entries: Mutex<HashMap<String, Arc<ResumeEntry>>>,
+}
+
+impl ResumeStore {
+    pub fn new(config: StreamResumeConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn config(&self) -> StreamResumeConfig {
+        self.config.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn configure(&self, config: &StreamResumeConfig) {
+        *self.config.lock().unwrap_or_else(PoisonError::into_inner) = config.clone();
+        if !config.enabled {
+            self.entries.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config().enabled
+    }
+
+    /// 清理已结束且超过 TTL 的缓冲 (长时间无新事件的进行中缓冲同样清理)
+    fn prune(&self, entries: &mut HashMap<String, Arc<ResumeEntry>>) {
+        let ttl = Duration::from_secs(self.config().ttl_secs);
+        entries.retain(|_, entry| entry.lock().updated_at.elapsed() <= ttl);
+    }
+
+    fn register(&self, message_id: &str, entry: Arc<ResumeEntry>) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        self.prune(&mut entries);
+        entries.insert(message_id.to_string(), entry);
+    }
+
+    /// 在后台驱动转换后的 SSE 流并写入缓存，返回从头读取缓存的客户端流
+    pub fn spawn_resumable(self: &Arc<Self>, mut stream: SseStream) -> SseStream {
+        let entry = Arc::new(ResumeEntry::new(self.config().max_events));
+        let store = self.clone();
+        let producer = entry.clone();
+
+        tokio::spawn(async move {
+            let guard = ProducerGuard(producer.clone());
+            let mut buffer = BytesMut::new();
+            let mut message_id: Option<String> = None;
+
+            while let Some(item) = stream.next().await {
+                let chunk = match item {
+                    Ok(bytes) => bytes,
+                    Err(e) => stream_error_event(&e),
+                };
+                buffer.extend_from_slice(&chunk);
+                while let Some(pos) = buffer.windows(2).position(|w| w == b"\n\n") {
+                    let event = buffer.split_to(pos + 2);
+                    if message_id.is_none() {
+                        if let Some(id) = message_id_of(&event) {
+                            store.register(&id, producer.clone());
+                            message_id = Some(id);
+                        }
+                    }
+                    producer.append(message_id.as_deref(), &event);
+                }
+            }
+            if !buffer.is_empty() {
+                producer.append(message_id.as_deref(), &buffer);
+            }
+            producer.finish(EntryState::Done);
+            drop(guard);
+        });
+
+        read_entry(entry, 0)
+    }
+
+    /// 按 Last-Event-ID 续传：返回该事件之后的事件流
+    pub fn resume(&self, last_event_id: &str) -> Result<SseStream, ResumeError> {
+        if !self.is_enabled() {
+            return Err(ResumeError::Unsupported);
+        }
+        let (message_id, seq) = last_event_id
+            .trim()
+            .rsplit_once(':')
+            .and_then(|(id, seq)| Some((id, seq.parse::<u64>().ok()?)))
+            .ok_or(ResumeError::InvalidId)?;
+
+        let entry = {
+            let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+            self.prune(&mut entries);
+            entries.get(message_id).cloned().ok_or(ResumeError::Unavailable)?
+        };
+        {
+            let inner = entry.lock();
+            if seq >= inner.next_seq {
+                return Err(ResumeError::InvalidId);
+            }
+            let first = inner.events.front().map(|(s, _)| *s).unwrap_or(inner.next_seq);
+            if seq + 1 < first {
+                return Err(ResumeError::Unavailable);
+            }
+        }
+        Ok(read_entry(entry, seq + 1))
+    }
+}
+
+static STORE: Lazy<Arc<ResumeStore>> =
+    Lazy::new(|| Arc::new(ResumeStore::new(StreamResumeConfig::default())));
+
+/// 全局续传缓存
+pub fn store() -> &'static Arc<ResumeStore> {
+    &STORE
+}
+
+/// 应用续传配置 (启动与热更新共用)
+pub fn configure_stream_resume(config: &StreamResumeConfig) {
+    STORE.configure(config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(max_events: usize) -> Arc<ResumeStore> {
+        Arc::new(ResumeStore::new(StreamResumeConfig {
+            enabled: true,
+            max_events,
+            ttl_secs: 300,
+        }))
+    }
+
+    fn claude_events() -> SseStream {
+        let events = vec![
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"a\"}}\n\nevent: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"b\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ];
+        Box::pin(futures::stream::iter(
+            events.into_iter().map(|e| Ok(Bytes::from(e))),
+        ))
+    }
+
+    async fn collect(stream: SseStream) -> Vec<String> {
+        stream
+            .map(|b| String::from_utf8(b.unwrap().to_vec()).unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_resume_unsupported_when_disabled() {
+        let store = ResumeStore::new(StreamResumeConfig::default());
+        assert_eq!(store.resume("msg_1:0").err(), Some(ResumeError::Unsupported));
+    }
+
+    #[tokio::test]
+    async fn test_events_carry_ids_and_replay_after_last_event_id() {
+        let store = enabled(100);
+        let events = collect(store.spawn_resumable(claude_events())).await;
+        assert_eq!(events.len(), 4);
+        assert!(events[0].starts_with("id: msg_1:0\nevent: message_start"));
+        assert!(events[3].starts_with("id: msg_1:3\nevent: message_stop"));
+
+        let replay = collect(store.resume("msg_1:1").unwrap()).await;
+        assert_eq!(replay.len(), 2);
+        assert!(replay[0].starts_with("id: msg_1:2\n"));
+        assert!(replay[1].contains("message_stop"));
+
+        // 最后一个事件之后续传：没有剩余事件
+        assert!(collect(store.resume("msg_1:3").unwrap()).await.is_empty());
+
+        assert_eq!(store.resume("msg_2:0").err(), Some(ResumeError::Unavailable));
+        assert_eq!(store.resume("msg_1:9").err(), Some(ResumeError::InvalidId));
+        assert_eq!(store.resume("garbage").err(), Some(ResumeError::InvalidId));
+    }
+
+    #[tokio::test]
+    async fn test_evicted_events_cannot_be_resumed() {
+        let store = enabled(2);
+        let _ = collect(store.spawn_resumable(claude_events())).await;
+        assert_eq!(store.resume("msg_1:0").err(), Some(ResumeError::Unavailable));
+        let replay = collect(store.resume("msg_1:2").unwrap()).await;
+        assert_eq!(replay.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generation_continues_after_client_disconnect() {
+        let store = enabled(100);
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Bytes, String>>();
+        let mut client = store.spawn_resumable(Box::pin(rx));
+
+        tx.unbounded_send(Ok(Bytes::from(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_live\"}}\n\n",
+        )))
+        .unwrap();
+        let first = client.next().await.unwrap().unwrap();
+        assert!(first.starts_with(b"id: msg_live:0\n"));
+        // 客户端断开
+        drop(client);
+
+        tx.unbounded_send(Ok(Bytes::from("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n")))
+            .unwrap();
+        drop(tx);
+
+        let replay = collect(store.resume("msg_live:0").unwrap()).await;
+        assert_eq!(replay.len(), 1);
+        assert!(replay[0].contains("message_stop"));
+    }
+}
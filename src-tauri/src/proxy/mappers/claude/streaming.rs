@@ -2,8 +2,11 @@
 // 对应 StreamingState + PartProcessor
 
 use super::models::*;
-use super::utils::to_claude_usage;
-use crate::proxy::mappers::signature_store::store_thought_signature;
+use super::utils::{estimated_claude_usage, matched_stop_sequence, to_claude_usage};
+use crate::proxy::common::prompt_size::estimate_text_tokens;
+use crate::proxy::common::stream_usage::UsageTicker;
+use crate::proxy::mappers::inline_media::render_inline_data;
+use crate::proxy::mappers::signature_store::{store_thought_signature, store_tool_signature};
 use bytes::Bytes;
 use serde_json::json;
 
@@ -52,6 +55,15 @@ pub struct StreamingState {
     trailing_signature: Option<String>,
     pub web_search_query: Option<String>,
     pub grounding_chunks: Option<Vec<serde_json::Value>>,
+    // [NEW] 用量统计：累积上游 usageMetadata，缺失时按字符数估算
+    estimated_input_tokens: u32,
+    estimated_output_tokens: u64,
+    usage_metadata: Option<UsageMetadata>,
+    // 客户端 stop_sequences 与最近输出的文本尾部 (长度不超过最长停止序列)
+    stop_sequences: Vec<String>,
+    text_tail: String,
+    // 周期性用量事件 (未启用时为 None)
+    usage_ticker: Option<UsageTicker>,
 }
 
 impl StreamingState {
@@ -66,6 +78,112 @@ impl StreamingState {
             trailing_signature: None,
             web_search_query: None,
             grounding_chunks: None,
+            estimated_input_tokens: 0,
+            estimated_output_tokens: 0,
+            usage_metadata: None,
+            stop_sequences: Vec::new(),
+            text_tail: String::new(),
+            usage_ticker: None,
+        }
+    }
+
+    /// 创建带输入 Token 估算值的状态 (上游未返回 usageMetadata 时使用)
+    pub fn with_estimated_input_tokens(estimated_input_tokens: u32) -> Self {
+        Self {
+            estimated_input_tokens,
+            ..Self::new()
+        }
+    }
+
+    /// 设置客户端停止序列，用于在结束时还原 stop_reason = "stop_sequence"
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+
+    /// 启用周期性用量事件
+    pub fn with_usage_ticker(mut self, usage_ticker: Option<UsageTicker>) -> Self {
+        self.usage_ticker = usage_ticker;
+        self
+    }
+
+    /// 达到周期时输出额外的 message_delta (usage.output_tokens 为当前累计值，与结束事件同源)
+    pub fn emit_usage_progress(&mut self) -> Option<Bytes> {
+        if !self.message_start_sent || self.message_stop_sent {
+            return None;
+        }
+        let usage = self.current_usage();
+        let ticker = self.usage_ticker.as_mut()?;
+        if !ticker.should_emit(usage.output_tokens as u64, std::time::Instant::now()) {
+            return None;
+        }
+        Some(self.emit(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": null, "stop_sequence": null },
+                "usage": usage
+            }),
+        ))
+    }
+
+    /// 记录已输出的正文，只保留匹配停止序列所需的尾部
+    fn record_text(&mut self, text: &str) {
+        let max_len = self.stop_sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+        if max_len == 0 {
+            return;
+        }
+        self.text_tail.push_str(text);
+        if self.text_tail.len() > max_len {
+            let mut cut = self.text_tail.len() - max_len;
+            while !self.text_tail.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            self.text_tail.drain(..cut);
+        }
+    }
+
+    /// 合并上游 usageMetadata (后续 chunk 中的计数为累计值，按字段覆盖)
+    pub fn record_usage(&mut self, usage: UsageMetadata) {
+        match self.usage_metadata.as_mut() {
+            Some(current) => {
+                current.prompt_token_count = usage.prompt_token_count.or(current.prompt_token_count);
+                current.candidates_token_count =
+                    usage.candidates_token_count.or(current.candidates_token_count);
+                current.total_token_count = usage.total_token_count.or(current.total_token_count);
+                current.cached_content_token_count = usage
+                    .cached_content_token_count
+                    .or(current.cached_content_token_count);
+            }
+            None => self.usage_metadata = Some(usage),
+        }
+    }
+
+    /// 记录输出文本 (用于缺失 usageMetadata 时估算 output_tokens)
+    pub fn record_output_text(&mut self, text: &str) {
+        self.estimated_output_tokens += estimate_text_tokens(text);
+    }
+
+    /// 是否收到过上游 usageMetadata
+    pub fn has_upstream_usage(&self) -> bool {
+        self.usage_metadata.is_some()
+    }
+
+    /// 当前累计用量：优先使用上游数据，缺失字段用估算值补齐
+    pub fn current_usage(&self) -> Usage {
+        let estimated_output = self.estimated_output_tokens.min(u32::MAX as u64) as u32;
+        match &self.usage_metadata {
+            Some(u) => {
+                let mut usage = to_claude_usage(u);
+                if u.prompt_token_count.is_none() {
+                    usage.input_tokens = self.estimated_input_tokens;
+                }
+                if u.candidates_token_count.is_none() {
+                    usage.output_tokens = estimated_output;
+                }
+                usage
+            }
+            None => estimated_claude_usage(self.estimated_input_tokens, estimated_output),
         }
     }
 
@@ -85,12 +203,17 @@ impl StreamingState {
             return Bytes::new();
         }
 
-        let usage = raw_json
+        if let Some(u) = raw_json
             .get("usageMetadata")
             .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
-            .map(|u| to_claude_usage(&u));
+        {
+            self.record_usage(u);
+        }
+        // message_start 阶段尚无输出，只上报输入 Token
+        let mut usage = self.current_usage();
+        usage.output_tokens = 0;
 
-        let mut message = json!({
+        let message = json!({
             "id": raw_json.get("responseId")
                 .and_then(|v| v.as_str())
                 .unwrap_or_else(|| "msg_unknown"),
@@ -102,12 +225,9 @@ impl StreamingState {
                 .unwrap_or(""),
             "stop_reason": null,
             "stop_sequence": null,
+            "usage": usage,
         });
 
-        if let Some(u) = usage {
-            message["usage"] = json!(u);
-        }
-
         let result = self.emit(
             "message_start",
             json!({
@@ -267,30 +387,32 @@ impl StreamingState {
             }
         }
 
-        // 确定 stop_reason
+        // 确定 stop_reason (已发送的文本无法撤回，命中的停止序列只在 stop_sequence 中报告)
+        let stop_sequence = if !self.used_tool && finish_reason == Some("STOP") {
+            matched_stop_sequence(&self.text_tail, &self.stop_sequences).cloned()
+        } else {
+            None
+        };
         let stop_reason = if self.used_tool {
             "tool_use"
         } else if finish_reason == Some("MAX_TOKENS") {
             "max_tokens"
+        } else if stop_sequence.is_some() {
+            "stop_sequence"
         } else {
             "end_turn"
         };
 
-        let usage = usage_metadata
-            .map(|u| to_claude_usage(u))
-            .unwrap_or(Usage {
-                input_tokens: 0,
-                output_tokens: 0,
-                cache_read_input_tokens: None,
-                cache_creation_input_tokens: None,
-                server_tool_use: None,
-            });
+        if let Some(u) = usage_metadata {
+            self.record_usage(u.clone());
+        }
+        let usage = self.current_usage();
 
         chunks.push(self.emit(
             "message_delta",
             json!({
                 "type": "message_delta",
-                "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                "delta": { "stop_reason": stop_reason, "stop_sequence": stop_sequence },
                 "usage": usage
             }),
         ));
@@ -392,13 +514,10 @@ impl<'a> PartProcessor<'a> {
             }
         }
 
-        // 3. InlineData (Image) 处理
+        // 3. InlineData 处理 (按 mimeType 渲染为图片 / 链接)
         if let Some(img) = &part.inline_data {
-            let mime_type = &img.mime_type;
-            let data = &img.data;
-            if !data.is_empty() {
-                let markdown_img = format!("![image](data:{};base64,{})", mime_type, data);
-                chunks.extend(self.process_text(&markdown_img, None));
+            if let Some(markdown) = render_inline_data(&img.mime_type, &img.data) {
+                chunks.extend(self.process_text(&markdown, None));
             }
         }
 
@@ -498,6 +617,7 @@ impl<'a> PartProcessor<'a> {
                     .start_block(BlockType::Text, json!({ "type": "text", "text": "" })),
             );
             chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+            self.state.record_text(text);
             chunks.extend(self.state.end_block());
 
             // 输出空 thinking 块承载签名
@@ -531,6 +651,7 @@ impl<'a> PartProcessor<'a> {
         }
 
         chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+        self.state.record_text(text);
 
         chunks
     }
@@ -565,6 +686,7 @@ impl<'a> PartProcessor<'a> {
             tool_use["signature"] = json!(sig);
             // Store signature to global storage for replay in subsequent requests
             store_thought_signature(sig);
+            store_tool_signature(&tool_id, sig);
             tracing::info!(
                 "[Claude-SSE] Captured thought_signature for function call (length: {})",
                 sig.len()
@@ -659,4 +781,30 @@ mod tests {
         // 3. content_block_stop
         assert!(output.contains(r#""type":"content_block_stop""#));
     }
+
+    #[test]
+    fn test_finish_reports_matched_stop_sequence() {
+        let mut state = StreamingState::new().with_stop_sequences(vec!["</answer>".to_string()]);
+        {
+            let mut processor = PartProcessor::new(&mut state);
+            for text in ["<answer>42</ans", "wer>"] {
+                processor.process(&GeminiPart {
+                    text: Some(text.to_string()),
+                    function_call: None,
+                    inline_data: None,
+                    thought: None,
+                    thought_signature: None,
+                    function_response: None,
+                });
+            }
+        }
+
+        let output = state
+            .emit_finish(Some("STOP"), None)
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .collect::<String>();
+        assert!(output.contains(r#""stop_reason":"stop_sequence""#));
+        assert!(output.contains(r#""stop_sequence":"</answer>""#));
+    }
 }
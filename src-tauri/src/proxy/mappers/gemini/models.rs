@@ -1,16 +1,2 @@
-// Gemini v1internal 数据模型
-use serde::{Deserialize, Serialize};
-
-#[allow(dead_code)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct V1InternalRequest {
-    pub project: String,
-    #[serde(rename = "requestId")]
-    pub request_id: String,
-    pub request: serde_json::Value,
-    pub model: String,
-    #[serde(rename = "userAgent")]
-    pub user_agent: String,
-    #[serde(rename = "requestType")]
-    pub request_type: String,
-}
+// Gemini v1internal 数据模型 (实现位于 antigravity-protocol 库，此处保持原路径导出)
+pub use antigravity_protocol::gemini::*;
@@ -1,5 +1,5 @@
 // Gemini v1internal 包装/解包
-use serde_json::{json, Value};
+use serde_json::Value;
 
 /// 包装请求体为 v1internal 格式
 pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value {
@@ -19,6 +19,8 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
     // 深度清理 [undefined] 字符串 (Cherry Studio 等客户端常见注入)
     crate::proxy::mappers::common_utils::deep_clean_undefined(&mut inner_request);
 
+    crate::proxy::common::safety::apply_configured_safety_settings(&mut inner_request);
+
     // [FIX] Removed forced maxOutputTokens (64000) as it exceeds limits for Gemini 1.5 Flash/Pro standard models (8192).
     // This caused upstream to return empty/invalid responses, leading to 'NoneType' object has no attribute 'strip' in Python clients.
     // relying on upstream defaults or user provided values is safer.
@@ -34,7 +36,7 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
     // Clean tool declarations (remove forbidden Schema fields like multipleOf, and remove redundant search decls)
     if let Some(tools) = inner_request.get_mut("tools") {
         if let Some(tools_arr) = tools.as_array_mut() {
-            for tool in tools_arr {
+            for (tool_index, tool) in tools_arr.iter_mut().enumerate() {
                 if let Some(decls) = tool.get_mut("functionDeclarations") {
                     if let Some(decls_arr) = decls.as_array_mut() {
                         // 1. 过滤掉联网关键字函数
@@ -48,9 +50,20 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
                         });
 
                         // 2. 清洗剩余 Schema
-                        for decl in decls_arr {
+                        for (decl_index, decl) in decls_arr.iter_mut().enumerate() {
                             if let Some(params) = decl.get_mut("parameters") {
+                                let original = params.clone();
                                 crate::proxy::common::json_schema::clean_json_schema(params);
+                                if *params != original {
+                                    crate::proxy::common::warnings::record_warning(
+                                        crate::proxy::common::warnings::codes::TOOL_SCHEMA_SANITIZED,
+                                        "unsupported JSON Schema keywords removed from function declaration",
+                                        Some(format!(
+                                            "tools[{}].functionDeclarations[{}].parameters",
+                                            tool_index, decl_index
+                                        )),
+                                    );
+                                }
                             }
                         }
                     }
@@ -69,34 +82,11 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
 
     // Inject imageConfig if present (for image generation models)
     if let Some(image_config) = config.image_config {
-         if let Some(obj) = inner_request.as_object_mut() {
-             // 1. Remove tools (image generation does not support tools)
-             obj.remove("tools");
-             
-             // 2. Remove systemInstruction (image generation does not support system prompts)
-             obj.remove("systemInstruction");
-
-             // 3. Clean generationConfig (remove thinkingConfig, responseMimeType, responseModalities etc.)
-             let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
-             if let Some(gen_obj) = gen_config.as_object_mut() {
-                 gen_obj.remove("thinkingConfig");
-                 gen_obj.remove("responseMimeType"); 
-                 gen_obj.remove("responseModalities"); // Cherry Studio sends this, might conflict
-                 gen_obj.insert("imageConfig".to_string(), image_config);
-             }
-         }
+        crate::proxy::mappers::common_utils::apply_image_config(&mut inner_request, image_config);
     }
 
-    let final_request = json!({
-        "project": project_id,
-        "requestId": format!("agent-{}", uuid::Uuid::new_v4()), // 修正为 agent- 前缀
-        "request": inner_request,
-        "model": config.final_model,
-        "userAgent": "antigravity",
-        "requestType": config.request_type
-    });
-
-    final_request
+    super::models::V1InternalRequest::new(project_id, &config.final_model, &config.request_type, inner_request)
+        .into_value()
 }
 
 /// 解包响应（提取 response 字段）
@@ -107,6 +97,7 @@ pub fn unwrap_response(response: &Value) -> Value {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_wrap_request() {
@@ -133,4 +124,26 @@ mod tests {
         assert!(result.get("candidates").is_some());
         assert!(result.get("response").is_none());
     }
+
+    #[test]
+    fn test_image_model_drops_tool_config() {
+        let body = json!({
+            "contents": [{"role": "user", "parts": [{"text": "a cat"}]}],
+            "systemInstruction": {"parts": [{"text": "sys"}]},
+            "tools": [{"functionDeclarations": [{"name": "f"}]}],
+            "toolConfig": {"functionCallingConfig": {"mode": "AUTO"}},
+            "generationConfig": {"responseModalities": ["IMAGE"], "temperature": 1}
+        });
+
+        let result = wrap_request(&body, "p", "gemini-3-pro-image-16x9");
+        let request = &result["request"];
+        assert_eq!(result["model"], "gemini-3-pro-image");
+        assert!(request.get("tools").is_none());
+        assert!(request.get("toolConfig").is_none());
+        assert!(request.get("systemInstruction").is_none());
+        assert_eq!(
+            request["generationConfig"],
+            json!({"temperature": 1, "imageConfig": {"aspectRatio": "16:9"}})
+        );
+    }
 }
@@ -0,0 +1,33 @@
+// Gemini inlineData 输出处理 (OpenAI / Claude，流式与非流式共用)
+// 渲染规则位于 antigravity-protocol 库 (media 模块)，此处保存当前配置的输出模式，
+// strict 模式下丢弃的内容通过 record_warning 记录到当前请求。
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use antigravity_protocol::warnings::Warnings;
+
+use crate::proxy::common::warnings::record_all;
+use crate::proxy::config::InlineMediaMode;
+
+static STRICT_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 应用 inlineData 输出模式 (代理启动与配置热更新时调用)
+pub fn configure_inline_media(mode: &InlineMediaMode) {
+    STRICT_MODE.store(matches!(mode, InlineMediaMode::Strict), Ordering::Relaxed);
+}
+
+/// 当前配置的 inlineData 输出模式
+pub fn current_mode() -> InlineMediaMode {
+    if STRICT_MODE.load(Ordering::Relaxed) {
+        InlineMediaMode::Strict
+    } else {
+        InlineMediaMode::Extended
+    }
+}
+
+/// 按当前配置的模式渲染 inlineData
+pub fn render_inline_data(mime_type: &str, data: &str) -> Option<String> {
+    let mut warnings = Warnings::new();
+    let rendered = antigravity_protocol::media::render_inline_data(current_mode(), mime_type, data, &mut warnings);
+    record_all(warnings);
+    rendered
+}
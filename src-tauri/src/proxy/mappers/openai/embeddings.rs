@@ -0,0 +1,191 @@
+// OpenAI Embeddings ↔ Gemini embedContent / batchEmbedContents 转换
+use super::models::OpenAIEmbeddingRequest;
+use base64::Engine as _;
+use serde_json::{json, Value};
+
+/// 提取输入文本列表 (字符串或字符串数组)
+pub fn extract_embedding_inputs(req: &OpenAIEmbeddingRequest) -> Result<Vec<String>, String> {
+    let inputs = match &req.input {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(arr) => arr
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| "Token array inputs are not supported, please send text".to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err("'input' must be a string or an array of strings".to_string()),
+    };
+
+    if inputs.is_empty() {
+        return Err("'input' must not be empty".to_string());
+    }
+    Ok(inputs)
+}
+
+/// 构建单条 embed 请求
+fn build_embed_item(text: &str, model: &str, dimensions: Option<u32>) -> Value {
+    let mut item = json!({
+        "model": format!("models/{}", model),
+        "content": { "parts": [{ "text": text }] }
+    });
+    if let Some(dim) = dimensions {
+        item["outputDimensionality"] = json!(dim);
+    }
+    item
+}
+
+/// 转换为 Gemini v1internal 请求体
+/// 返回 (上游方法名, 请求体)：单条输入使用 embedContent，多条使用 batchEmbedContents
+pub fn transform_embedding_request(
+    req: &OpenAIEmbeddingRequest,
+    inputs: &[String],
+    project_id: &str,
+    mapped_model: &str,
+) -> (&'static str, Value) {
+    let (method, inner) = if inputs.len() == 1 {
+        ("embedContent", build_embed_item(&inputs[0], mapped_model, req.dimensions))
+    } else {
+        let requests: Vec<Value> = inputs
+            .iter()
+            .map(|text| build_embed_item(text, mapped_model, req.dimensions))
+            .collect();
+        ("batchEmbedContents", json!({ "requests": requests }))
+    };
+
+    let body = crate::proxy::mappers::gemini::models::V1InternalRequest::new(
+        project_id,
+        mapped_model,
+        "agent",
+        inner,
+    )
+    .with_request_id_prefix("embed")
+    .into_value();
+    (method, body)
+}
+
+/// 将 f32 向量编码为 OpenAI base64 格式 (小端序)
+fn encode_embedding_base64(values: &[f64]) -> String {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|v| (*v as f32).to_le_bytes())
+        .collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// 转换 Gemini embedding 响应为 OpenAI 格式
+pub fn transform_embedding_response(
+    gemini_resp: &Value,
+    req: &OpenAIEmbeddingRequest,
+    inputs: &[String],
+) -> Result<Value, String> {
+    let raw = gemini_resp.get("response").unwrap_or(gemini_resp);
+
+    // embedContent -> { embedding: { values } }；batchEmbedContents -> { embeddings: [{ values }] }
+    let vectors: Vec<Vec<f64>> = if let Some(list) = raw.get("embeddings").and_then(|v| v.as_array()) {
+        list.iter()
+            .map(|e| {
+                e.get("values")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|x| x.as_f64()).collect())
+                    .unwrap_or_default()
+            })
+            .collect()
+    } else if let Some(values) = raw
+        .get("embedding")
+        .and_then(|e| e.get("values"))
+        .and_then(|v| v.as_array())
+    {
+        vec![values.iter().filter_map(|x| x.as_f64()).collect()]
+    } else {
+        return Err("Upstream response contains no embeddings".to_string());
+    };
+
+    let use_base64 = req.encoding_format.as_deref() == Some("base64");
+    let data: Vec<Value> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, values)| {
+            let embedding = if use_base64 {
+                json!(encode_embedding_base64(values))
+            } else {
+                json!(values)
+            };
+            json!({
+                "object": "embedding",
+                "embedding": embedding,
+                "index": index
+            })
+        })
+        .collect();
+
+    let prompt_tokens: u64 = inputs
+        .iter()
+        .map(|s| crate::proxy::common::prompt_size::estimate_text_tokens(s))
+        .sum();
+
+    Ok(json!({
+        "object": "list",
+        "data": data,
+        "model": req.model,
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "total_tokens": prompt_tokens
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_req(input: Value) -> OpenAIEmbeddingRequest {
+        OpenAIEmbeddingRequest {
+            model: "text-embedding-3-small".to_string(),
+            input,
+            encoding_format: None,
+            dimensions: Some(256),
+            user: None,
+        }
+    }
+
+    #[test]
+    fn test_single_and_batch_request() {
+        let req = make_req(json!("hello"));
+        let inputs = extract_embedding_inputs(&req).unwrap();
+        let (method, body) = transform_embedding_request(&req, &inputs, "p", "gemini-embedding-001");
+        assert_eq!(method, "embedContent");
+        assert_eq!(body["request"]["content"]["parts"][0]["text"], "hello");
+        assert_eq!(body["request"]["outputDimensionality"], 256);
+
+        let req = make_req(json!(["a", "b"]));
+        let inputs = extract_embedding_inputs(&req).unwrap();
+        let (method, body) = transform_embedding_request(&req, &inputs, "p", "gemini-embedding-001");
+        assert_eq!(method, "batchEmbedContents");
+        assert_eq!(body["request"]["requests"].as_array().unwrap().len(), 2);
+
+        assert!(extract_embedding_inputs(&make_req(json!([[1, 2, 3]]))).is_err());
+    }
+
+    #[test]
+    fn test_response_shape() {
+        let req = make_req(json!(["a", "b"]));
+        let inputs = extract_embedding_inputs(&req).unwrap();
+        let resp = json!({
+            "response": {
+                "embeddings": [{"values": [0.1, 0.2]}, {"values": [0.3, 0.4]}]
+            }
+        });
+        let out = transform_embedding_response(&resp, &req, &inputs).unwrap();
+        assert_eq!(out["object"], "list");
+        assert_eq!(out["data"][1]["index"], 1);
+        assert_eq!(out["data"][0]["embedding"].as_array().unwrap().len(), 2);
+
+        let single = json!({"embedding": {"values": [1.0]}});
+        let mut req_b64 = make_req(json!("x"));
+        req_b64.encoding_format = Some("base64".to_string());
+        let out = transform_embedding_response(&single, &req_b64, &["x".to_string()]).unwrap();
+        assert_eq!(out["data"][0]["embedding"], encode_embedding_base64(&[1.0]));
+    }
+}
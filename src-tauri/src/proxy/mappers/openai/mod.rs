@@ -5,8 +5,11 @@ pub mod models;
 pub mod request;
 pub mod response;
 pub mod streaming;
+pub mod embeddings;
+pub mod vision;
 
 pub use models::*;
 pub use request::*;
 pub use response::*;
+pub use embeddings::*;
 // No public exports needed here if unused
@@ -3,12 +3,17 @@ use bytes::{Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use serde_json::{json, Value};
 use std::pin::Pin;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Mutex, OnceLock};
 use chrono::Utc;
 use uuid::Uuid;
 use tracing::debug;
 use rand::Rng;
 
+use super::response::{is_thought_part, reasoning_output_enabled, transform_usage};
+use crate::proxy::common::stream_usage::UsageTicker;
+use crate::proxy::mappers::inline_media::render_inline_data;
+
 // === 全局 ThoughtSignature 存储 ===
 // 用于在流式响应和后续请求之间传递签名，避免嵌入到用户可见的文本中
 static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
@@ -50,11 +55,34 @@ pub fn get_thought_signature() -> Option<String> {
     }
 }
 
-pub fn create_openai_sse_stream(
-    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+pub fn create_openai_sse_stream<E: std::fmt::Display + Send + 'static>(
+    gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
     model: String,
+    include_usage: bool,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    // 周期性用量 chunk 不在 OpenAI 协议内，仅 extended 兼容模式输出
+    let usage_ticker = UsageTicker::from_config(std::time::Instant::now())
+        .filter(|_| crate::proxy::common::warnings::extended_mode());
+    openai_sse_stream(gemini_stream, model, include_usage, usage_ticker)
+}
+
+fn openai_sse_stream<E: std::fmt::Display + Send + 'static>(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    model: String,
+    include_usage: bool,
+    mut usage_ticker: Option<UsageTicker>,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    // [NEW] 工具调用计数：OpenAI 流式 tool_calls 需要递增的 index (按候选分别计数)
+    let mut tool_call_counts: HashMap<usize, usize> = HashMap::new();
+    // [NEW] 累积 usageMetadata (stream_options.include_usage 时在结尾输出)
+    let mut usage_metadata = serde_json::Map::new();
+    // [NEW] 同一流的所有 chunk 共用 id 与 created (严格客户端按 id 聚合)
+    let stream_id = format!("chatcmpl-{}", Uuid::new_v4());
+    let created = Utc::now().timestamp();
+    let mut role_sent: HashSet<usize> = HashSet::new();
+    // 思维链以 delta.reasoning_content 输出 (strip_reasoning 时丢弃)
+    let emit_reasoning = reasoning_output_enabled();
     
     let stream = async_stream::stream! {
         while let Some(item) = gemini_stream.next().await {
@@ -88,113 +116,204 @@ pub fn create_openai_sse_stream(
                                         json
                                     };
 
-                                    // Extract components
-                                    let candidates = actual_data.get("candidates").and_then(|c| c.as_array());
-                                    let candidate = candidates.and_then(|c| c.get(0));
-                                    let parts = candidate.and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array());
+                                    if let Some(usage) = actual_data.get("usageMetadata").and_then(|u| u.as_object()) {
+                                        usage_metadata.extend(usage.clone());
+                                    }
+
+                                    // 每个候选 (candidateCount > 1 时有多个) 对应一个 choices[].index
+                                    let candidates = actual_data.get("candidates").and_then(|c| c.as_array()).map(Vec::as_slice).unwrap_or_default();
+                                    for (position, candidate) in candidates.iter().enumerate() {
+                                        let choice_index = candidate
+                                            .get("index")
+                                            .and_then(|v| v.as_u64())
+                                            .map_or(position, |v| v as usize);
+                                        let tool_call_count = tool_call_counts.entry(choice_index).or_insert(0);
+                                        let parts = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array());
 
-                                    let mut content_out = String::new();
+                                        let mut content_out = String::new();
+                                        let mut reasoning_out = String::new();
+                                        let mut tool_call_deltas: Vec<Value> = Vec::new();
                                     
-                                    if let Some(parts_list) = parts {
-                                        for part in parts_list {
-                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                content_out.push_str(text);
-                                            }
-                                            // Capture thought (Thinking Models)
-                                            if let Some(_thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                 // content_out.push_str(thought_text);
-                                            }
-                                            // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                                            if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                store_thought_signature(sig);
-                                            }
+                                        if let Some(parts_list) = parts {
+                                            for part in parts_list {
+                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                    if !is_thought_part(part) {
+                                                        content_out.push_str(text);
+                                                    } else if emit_reasoning {
+                                                        reasoning_out.push_str(text);
+                                                    }
+                                                }
+                                                // [NEW] functionCall -> delta.tool_calls (Gemini 每次下发完整参数，一次性输出)
+                                                if let Some(fc) = part.get("functionCall") {
+                                                    tool_call_deltas.push(build_tool_call_delta(fc, *tool_call_count));
+                                                    *tool_call_count += 1;
+                                                }
+                                                // 捕获 thoughtSignature (Gemini 3 工具调用必需)
+                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                    store_thought_signature(sig);
+                                                }
 
-                                            if let Some(img) = part.get("inlineData") {
-                                                let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
-                                                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                                if !data.is_empty() {
-                                                    content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
+                                                if let Some(img) = part.get("inlineData") {
+                                                    let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
+                                                    let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
+                                                    if let Some(markdown) = render_inline_data(mime_type, data) {
+                                                        content_out.push_str(&markdown);
+                                                    }
                                                 }
                                             }
                                         }
-                                    }
 
-                                    // 处理联网搜索引文 (Grounding Metadata) - 流式
-                                    if let Some(grounding) = candidate.and_then(|c| c.get("groundingMetadata")) {
-                                        let mut grounding_text = String::new();
-                                        if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
-                                            let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
-                                            if !query_list.is_empty() {
-                                                grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                                                grounding_text.push_str(&query_list.join(", "));
+                                        // 处理联网搜索引文 (Grounding Metadata) - 流式
+                                        if let Some(grounding) = candidate.get("groundingMetadata") {
+                                            let mut grounding_text = String::new();
+                                            if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
+                                                let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
+                                                if !query_list.is_empty() {
+                                                    grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
+                                                    grounding_text.push_str(&query_list.join(", "));
+                                                }
                                             }
-                                        }
 
-                                        if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
-                                            let mut links = Vec::new();
-                                            for (i, chunk) in chunks.iter().enumerate() {
-                                                if let Some(web) = chunk.get("web") {
-                                                    let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
-                                                    let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
-                                                    links.push(format!("[{}] [{}]({})", i + 1, title, uri));
+                                            if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
+                                                let mut links = Vec::new();
+                                                for (i, chunk) in chunks.iter().enumerate() {
+                                                    if let Some(web) = chunk.get("web") {
+                                                        let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
+                                                        let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
+                                                        links.push(format!("[{}] [{}]({})", i + 1, title, uri));
+                                                    }
+                                                }
+                                                if !links.is_empty() {
+                                                    grounding_text.push_str("\n\n**🌐 来源引文：**\n");
+                                                    grounding_text.push_str(&links.join("\n"));
                                                 }
                                             }
-                                            if !links.is_empty() {
-                                                grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                                                grounding_text.push_str(&links.join("\n"));
+                                            if !grounding_text.is_empty() {
+                                                content_out.push_str(&grounding_text);
                                             }
                                         }
-                                        if !grounding_text.is_empty() {
-                                            content_out.push_str(&grounding_text);
-                                        }
-                                    }
 
-                                    if content_out.is_empty() {
-                                        // Skip empty chunks if no text/grounding was found
-                                        if candidate.and_then(|c| c.get("finishReason")).is_none() {
-                                            continue;
+                                        if content_out.is_empty() && reasoning_out.is_empty() && tool_call_deltas.is_empty() {
+                                            // Skip empty chunks if no text/grounding was found
+                                            if candidate.get("finishReason").is_none() {
+                                                continue;
+                                            }
                                         }
-                                    }
                                         
-                                    // Extract finish reason
-                                    let finish_reason = candidate.and_then(|c| c.get("finishReason"))
-                                        .and_then(|f| f.as_str())
-                                        .map(|f| match f {
-                                            "STOP" => "stop",
-                                            "MAX_TOKENS" => "length",
-                                            "SAFETY" => "content_filter",
-                                            _ => f,
-                                        });
+                                        // Extract finish reason
+                                        let finish_reason = candidate.get("finishReason")
+                                            .and_then(|f| f.as_str())
+                                            .map(|f| match f {
+                                                // [NEW] 本次流中出现过工具调用时，正常结束应返回 tool_calls
+                                                "STOP" if *tool_call_count > 0 => "tool_calls",
+                                                "STOP" => "stop",
+                                                "MAX_TOKENS" => "length",
+                                                "SAFETY" => "content_filter",
+                                                _ => f,
+                                            });
 
-                                    // Construct OpenAI SSE chunk
-                                    let openai_chunk = json!({
-                                        "id": format!("chatcmpl-{}", Uuid::new_v4()),
-                                        "object": "chat.completion.chunk",
-                                        "created": Utc::now().timestamp(),
-                                        "model": model,
-                                        "choices": [
-                                            {
-                                                "index": 0,
-                                                "delta": {
-                                                    "content": content_out
-                                                },
-                                                "finish_reason": finish_reason
+                                        let mut delta = json!({});
+                                        if !reasoning_out.is_empty() {
+                                            delta["reasoning_content"] = json!(reasoning_out);
+                                        }
+                                        if !content_out.is_empty() || (tool_call_deltas.is_empty() && reasoning_out.is_empty()) {
+                                            delta["content"] = json!(content_out);
+                                        }
+                                        if !tool_call_deltas.is_empty() {
+                                            delta["tool_calls"] = json!(tool_call_deltas);
+                                        }
+
+                                        // [NEW] 首个 chunk 只携带 role (OpenAI 规范)
+                                        if role_sent.insert(choice_index) {
+                                            let mut role_chunk = json!({
+                                                "id": stream_id,
+                                                "object": "chat.completion.chunk",
+                                                "created": created,
+                                                "model": model,
+                                                "choices": [
+                                                    {
+                                                        "index": choice_index,
+                                                        "delta": { "role": "assistant", "content": "" },
+                                                        "finish_reason": null
+                                                    }
+                                                ]
+                                            });
+                                            if include_usage {
+                                                role_chunk["usage"] = Value::Null;
                                             }
-                                        ]
-                                    });
+                                            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", role_chunk)));
+                                        }
 
-                                    let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
-                                    yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                                        // Construct OpenAI SSE chunk
+                                        let mut openai_chunk = json!({
+                                            "id": stream_id,
+                                            "object": "chat.completion.chunk",
+                                            "created": created,
+                                            "model": model,
+                                            "choices": [
+                                                {
+                                                    "index": choice_index,
+                                                    "delta": delta,
+                                                    "finish_reason": finish_reason
+                                                }
+                                            ]
+                                        });
+                                        // [NEW] include_usage 时中间 chunk 的 usage 为 null (OpenAI 规范)，仅最后一个 chunk 携带统计
+                                        if include_usage {
+                                            openai_chunk["usage"] = Value::Null;
+                                        }
+
+                                        let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
+                                        yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                                    }
+
+                                    // 周期性用量：delta 为空、usage 为当前累计值 (与结尾 usage chunk 同源)；结束 chunk 之后不再输出
+                                    let finished = candidates.iter().any(|c| c.get("finishReason").is_some());
+                                    if let Some(ticker) = usage_ticker.as_mut().filter(|_| !finished) {
+                                        let usage = transform_usage(&Value::Object(usage_metadata.clone()));
+                                        if ticker.should_emit(usage.completion_tokens as u64, std::time::Instant::now()) {
+                                            let progress_chunk = json!({
+                                                "id": stream_id,
+                                                "object": "chat.completion.chunk",
+                                                "created": created,
+                                                "model": model,
+                                                "choices": [{"index": 0, "delta": {}, "finish_reason": null}],
+                                                "usage": usage
+                                            });
+                                            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", progress_chunk)));
+                                        }
+                                    }
                                 }
                             }
                         }
                     }
                 }
                 Err(e) => {
-                    yield Err(format!("Upstream error: {}", e));
+                    // 已输出部分内容后上游断开：输出错误 chunk 并结束 (不再发送 [DONE])
+                    let error_chunk = json!({
+                        "error": {
+                            "message": format!("Upstream error: {}", e),
+                            "type": "upstream_error",
+                            "code": null
+                        }
+                    });
+                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", error_chunk)));
+                    return;
                 }
             }
         }
+        // [NEW] stream_options.include_usage: 在 [DONE] 前输出 choices 为空的 usage chunk
+        if include_usage {
+            let usage_chunk = json!({
+                "id": stream_id,
+                "object": "chat.completion.chunk",
+                "created": created,
+                "model": model,
+                "choices": [],
+                "usage": transform_usage(&Value::Object(usage_metadata.clone()))
+            });
+            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", usage_chunk)));
+        }
         // End of stream signal for OpenAI
         yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
     };
@@ -202,11 +321,37 @@ pub fn create_openai_sse_stream(
     Box::pin(stream)
 }
 
+/// 将 Gemini functionCall 转换为 OpenAI 流式 tool_calls 片段
+fn build_tool_call_delta(fc: &Value, index: usize) -> Value {
+    let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+    let id = fc
+        .get("id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("call_{}", Uuid::new_v4().simple()));
+    let arguments = fc
+        .get("args")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "{}".to_string());
+
+    json!({
+        "index": index,
+        "id": id,
+        "type": "function",
+        "function": {
+            "name": name,
+            "arguments": arguments
+        }
+    })
+}
+
 pub fn create_legacy_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    include_usage: bool,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    let mut usage_metadata = serde_json::Map::new();
     
     // Generate constant alphanumeric ID (mimics OpenAI base62 format)
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -237,12 +382,16 @@ pub fn create_legacy_sse_stream(
 
                                 if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
                                     let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
+                                    if let Some(usage) = actual_data.get("usageMetadata").and_then(|u| u.as_object()) {
+                                        usage_metadata.extend(usage.clone());
+                                    }
                                     
                                     let mut content_out = String::new();
                                     if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
                                         if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
                                             for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                // 文本补全没有 reasoning 字段，思维链直接丢弃
+                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()).filter(|_| !is_thought_part(part)) {
                                                     content_out.push_str(text);
                                                 }
                                                 /* 禁用思维链输出到正文
@@ -299,6 +448,18 @@ pub fn create_legacy_sse_stream(
                 Err(e) => yield Err(format!("Upstream error: {}", e)),
             }
         }
+        // [NEW] stream_options.include_usage
+        if include_usage {
+            let usage_chunk = json!({
+                "id": &stream_id,
+                "object": "text_completion",
+                "created": created_ts,
+                "model": &model,
+                "choices": [],
+                "usage": transform_usage(&Value::Object(usage_metadata.clone()))
+            });
+            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", usage_chunk)));
+        }
         tracing::debug!("Stream finished. Yielding [DONE]");
         yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
         // Final flush delay
@@ -375,7 +536,8 @@ pub fn create_codex_sse_stream(
                                     if let Some(candidate) = candidates.get(0) {
                                         if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
                                             for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                // 思维链不进入 output_text
+                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()).filter(|_| !is_thought_part(part)) {
                                                     // Sanitize smart quotes to standard quotes for JSON compatibility
                                                     let clean_text = text.replace('“', "\"").replace('”', "\"");
                                                     delta_text.push_str(&clean_text);
@@ -773,3 +935,256 @@ pub fn create_codex_sse_stream(
 
     Box::pin(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_openai_stream_emits_tool_calls() {
+        let chunk = json!({
+            "response": {
+                "candidates": [{
+                    "content": {"parts": [{"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}]},
+                    "finishReason": "STOP"
+                }]
+            }
+        });
+        let raw = format!("data: {}\n\n", chunk);
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(vec![Ok(Bytes::from(raw))]));
+
+        let out: Vec<String> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), false)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        let first: Value = serde_json::from_str(out[1].trim_start_matches("data: ").trim()).unwrap();
+        let choice = &first["choices"][0];
+        assert_eq!(choice["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(choice["delta"]["tool_calls"][0]["function"]["name"], "get_weather");
+        assert_eq!(choice["delta"]["tool_calls"][0]["function"]["arguments"], r#"{"city":"Paris"}"#);
+        assert!(choice["delta"].get("content").is_none());
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(out.last().unwrap(), "data: [DONE]\n\n");
+        // 未请求 include_usage 时不输出 usage chunk
+        assert!(out.iter().all(|c| !c.contains("\"usage\"")));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_maps_candidate_index() {
+        let chunks = vec![
+            json!({"response": {"candidates": [
+                {"index": 0, "content": {"parts": [{"text": "A"}]}},
+                {"index": 1, "content": {"parts": [{"text": "B"}]}}
+            ]}}),
+            json!({"response": {"candidates": [
+                {"index": 1, "content": {"parts": [{"functionCall": {"name": "f", "args": {}}}]}, "finishReason": "STOP"}
+            ]}}),
+        ];
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> = Box::pin(futures::stream::iter(
+            chunks.into_iter().map(|c| Ok(Bytes::from(format!("data: {}\n\n", c)))).collect::<Vec<_>>(),
+        ));
+
+        let out: Vec<Value> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), false)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .filter(|c| futures::future::ready(!c.contains("[DONE]")))
+            .map(|c| serde_json::from_str(c.trim_start_matches("data: ").trim()).unwrap())
+            .collect()
+            .await;
+
+        // 每个候选单独发送 role chunk，内容进入对应的 choices[].index
+        let choices: Vec<&Value> = out.iter().map(|c| &c["choices"][0]).collect();
+        let roles: Vec<_> = choices.iter().filter(|c| c["delta"]["role"] == "assistant").map(|c| c["index"].clone()).collect();
+        assert_eq!(roles, vec![json!(0), json!(1)]);
+        assert!(choices.iter().any(|c| c["index"] == 1 && c["delta"]["content"] == "B"));
+        let last = choices.last().unwrap();
+        assert_eq!(last["index"], 1);
+        assert_eq!(last["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(last["finish_reason"], "tool_calls");
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_periodic_usage_matches_final() {
+        use crate::proxy::config::StreamUsageConfig;
+
+        let chunks = [
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"text": "Hel"}]}}],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 3}
+            }}),
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"text": "lo wor"}]}}],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 7}
+            }}),
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"text": "ld"}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 12, "totalTokenCount": 24}
+            }}),
+        ];
+        let raw: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(raw));
+        let ticker = UsageTicker::new(
+            &StreamUsageConfig { enabled: true, interval_secs: 0, every_output_tokens: 1 },
+            std::time::Instant::now(),
+        );
+
+        let out: Vec<Value> = openai_sse_stream(upstream, "gpt-4o".to_string(), true, ticker)
+            .filter_map(|r| async move {
+                let text = String::from_utf8(r.unwrap().to_vec()).unwrap();
+                serde_json::from_str(text.trim_start_matches("data: ").trim()).ok()
+            })
+            .collect()
+            .await;
+
+        let periodic: Vec<u64> = out
+            .iter()
+            .filter(|v| v["usage"].is_object() && v["choices"][0]["delta"] == json!({}))
+            .map(|v| v["usage"]["completion_tokens"].as_u64().unwrap())
+            .collect();
+        assert_eq!(periodic, vec![3, 7]);
+        let final_usage = &out.last().unwrap()["usage"];
+        assert_eq!(out.last().unwrap()["choices"], json!([]));
+        let final_tokens = final_usage["completion_tokens"].as_u64().unwrap();
+
+        // 周期增量之和 + 最后一段增量 = 最终值
+        let mut previous = 0;
+        let mut summed = 0;
+        for value in periodic.iter().copied().chain([final_tokens]) {
+            assert!(value >= previous);
+            summed += value - previous;
+            previous = value;
+        }
+        assert_eq!(summed, final_tokens);
+        assert_eq!(final_tokens, 12);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_include_usage() {
+        let chunks = [
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"text": "Hi"}]}}],
+                "usageMetadata": {"promptTokenCount": 12, "cachedContentTokenCount": 4}
+            }}),
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"text": "!"}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 5, "thoughtsTokenCount": 0, "cachedContentTokenCount": 4}
+            }}),
+        ];
+        let raw: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(raw));
+
+        let out: Vec<String> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), true)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(out.last().unwrap(), "data: [DONE]\n\n");
+        for chunk in &out[..out.len() - 2] {
+            let v: Value = serde_json::from_str(chunk.trim_start_matches("data: ").trim()).unwrap();
+            assert!(v["usage"].is_null() && v.get("usage").is_some(), "{}", chunk);
+        }
+        let usage_chunk: Value =
+            serde_json::from_str(out[out.len() - 2].trim_start_matches("data: ").trim()).unwrap();
+        assert_eq!(usage_chunk["choices"], json!([]));
+        let usage = &usage_chunk["usage"];
+        assert_eq!(usage["prompt_tokens"], 12);
+        assert_eq!(usage["completion_tokens"], 5);
+        assert_eq!(usage["prompt_tokens_details"]["cached_tokens"], 4);
+        // 上游明确返回 0 时才输出 0
+        assert_eq!(usage["completion_tokens_details"]["reasoning_tokens"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_role_chunk_and_stable_id() {
+        let chunks = [
+            json!({"response": {"candidates": [{"content": {"parts": [{"text": "Hel"}]}}]}}),
+            json!({"response": {"candidates": [{"content": {"parts": [{"text": "lo"}]}, "finishReason": "STOP"}]}}),
+        ];
+        let raw: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(raw));
+
+        let out: Vec<Value> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), true)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .filter(|c| futures::future::ready(c != "data: [DONE]\n\n"))
+            .map(|c| serde_json::from_str(c.trim_start_matches("data: ").trim()).unwrap())
+            .collect()
+            .await;
+
+        // role + 2 content + usage
+        assert_eq!(out.len(), 4);
+        assert_eq!(out[0]["choices"][0]["delta"], json!({"role": "assistant", "content": ""}));
+        assert_eq!(out[1]["choices"][0]["delta"]["content"], "Hel");
+        assert!(out[1]["choices"][0]["delta"].get("role").is_none());
+
+        let id = out[0]["id"].as_str().unwrap();
+        assert!(id.starts_with("chatcmpl-"));
+        assert!(out.iter().all(|c| c["id"] == id && c["created"] == out[0]["created"]));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_error_after_content_emits_error_chunk() {
+        let text = json!({"response": {"candidates": [{"content": {"parts": [{"text": "Hel"}]}}]}});
+        let raw: Vec<Result<Bytes, String>> = vec![
+            Ok(Bytes::from(format!("data: {}\n\n", text))),
+            Err("connection reset".to_string()),
+        ];
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> =
+            Box::pin(futures::stream::iter(raw));
+
+        let out: Vec<String> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), true)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        let last: Value = serde_json::from_str(out.last().unwrap().trim_start_matches("data: ").trim()).unwrap();
+        assert_eq!(last["error"]["type"], "upstream_error");
+        assert_eq!(last["error"]["message"], "Upstream error: connection reset");
+        assert!(!out.iter().any(|c| c.contains("[DONE]")));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_thoughts_go_to_reasoning_content() {
+        let chunks = [
+            json!({"response": {"candidates": [{"content": {"parts": [{"text": "Thinking hard", "thought": true}]}}]}}),
+            json!({"response": {"candidates": [{"content": {"parts": [
+                {"text": " more", "thought": true, "thoughtSignature": "sig-abcdefghijkl"},
+                {"text": "Answer"}
+            ]}, "finishReason": "STOP"}]}}),
+        ];
+        let raw: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+            Box::pin(futures::stream::iter(raw));
+
+        let out: Vec<Value> = create_openai_sse_stream(upstream, "gpt-4o".to_string(), false)
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .filter(|c| futures::future::ready(c != "data: [DONE]\n\n"))
+            .map(|c| serde_json::from_str(c.trim_start_matches("data: ").trim()).unwrap())
+            .collect()
+            .await;
+
+        let deltas: Vec<&Value> = out.iter().map(|c| &c["choices"][0]["delta"]).collect();
+        assert_eq!(deltas[1], &json!({"reasoning_content": "Thinking hard"}));
+        assert_eq!(deltas[2]["reasoning_content"], " more");
+        assert_eq!(deltas[2]["content"], "Answer");
+        // 思维链不得进入 content，也不透传 Gemini 私有字段
+        let content: String = deltas.iter().filter_map(|d| d["content"].as_str()).collect();
+        assert_eq!(content, "Answer");
+        assert!(deltas.iter().all(|d| d.get("thought").is_none() && d.get("thoughtSignature").is_none()));
+    }
+}
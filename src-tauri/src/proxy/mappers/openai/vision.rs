@@ -0,0 +1,152 @@
+// OpenAI 多模态图片预处理
+// http(s) 图片默认以 fileData 透传；启用 vision.fetch_remote_images 后由反代下载并转为 data URL，
+// 之后统一由 image_url_to_part 转成 inlineData (部分上游不接受外部 fileUri)
+
+use base64::Engine as _;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::models::{OpenAIContent, OpenAIContentBlock, OpenAIRequest};
+use crate::proxy::config::{UpstreamProxyConfig, VisionConfig};
+
+static CONFIG: Lazy<RwLock<VisionConfig>> = Lazy::new(|| RwLock::new(VisionConfig::default()));
+
+/// 应用图片预处理配置 (代理启动与配置热更新时调用)
+pub fn configure_vision(config: &VisionConfig) {
+    if let Ok(mut current) = CONFIG.write() {
+        *current = config.clone();
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// 将请求中的远程图片下载并替换为 data URL (原位替换，保持 part 顺序)
+/// 单张图片下载失败时保留原 URL，回退为 fileData
+pub async fn inline_remote_images(request: &mut OpenAIRequest, upstream_proxy: Option<&UpstreamProxyConfig>) {
+    let config = match CONFIG.read() {
+        Ok(c) if c.fetch_remote_images => c.clone(),
+        _ => return,
+    };
+
+    let mut urls: Vec<&mut String> = request
+        .messages
+        .iter_mut()
+        .filter_map(|msg| match msg.content.as_mut() {
+            Some(OpenAIContent::Array(blocks)) => Some(blocks),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|block| match block {
+            OpenAIContentBlock::ImageUrl { image_url } if is_remote(&image_url.url) => Some(&mut image_url.url),
+            _ => None,
+        })
+        .collect();
+    if urls.is_empty() {
+        return;
+    }
+
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(config.fetch_timeout_secs.max(1)));
+    let client = match crate::utils::http::apply_upstream_proxy(builder, upstream_proxy)
+        .and_then(|b| b.build().map_err(|e| e.to_string()))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("[Vision] 无法创建图片下载客户端: {}", e);
+            return;
+        }
+    };
+
+    // 同一请求内重复引用的图片只下载一次
+    let mut fetched: HashMap<String, Option<String>> = HashMap::new();
+    for url in urls.iter_mut() {
+        if !fetched.contains_key(url.as_str()) {
+            let data_url = match fetch_as_data_url(&client, url, config.max_image_bytes).await {
+                Ok(data_url) => Some(data_url),
+                Err(e) => {
+                    crate::proxy::common::warnings::record_warning(
+                        crate::proxy::common::warnings::codes::IMAGE_FETCH_FAILED,
+                        format!("failed to fetch {}, passed to upstream as fileData: {}", url, e),
+                        None,
+                    );
+                    None
+                }
+            };
+            fetched.insert(url.to_string(), data_url);
+        }
+        if let Some(Some(data_url)) = fetched.get(url.as_str()) {
+            **url = data_url.clone();
+        }
+    }
+}
+
+async fn fetch_as_data_url(client: &reqwest::Client, url: &str, max_bytes: usize) -> Result<String, String> {
+    let mut response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status().as_u16()));
+    }
+    if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+        return Err(format!("图片超过 {} 字节上限", max_bytes));
+    }
+    let mime_type = mime_from_content_type(
+        response.headers().get("content-type").and_then(|v| v.to_str().ok()),
+        url,
+    );
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(format!("图片超过 {} 字节上限", max_bytes));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    Ok(format!("data:{};base64,{}", mime_type, data))
+}
+
+/// 优先使用响应的 image/* Content-Type，否则按 URL 扩展名推断
+fn mime_from_content_type(content_type: Option<&str>, url: &str) -> String {
+    content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase())
+        .filter(|ct| ct.starts_with("image/"))
+        .unwrap_or_else(|| crate::proxy::mappers::common_utils::guess_image_mime(url).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mime_prefers_image_content_type() {
+        assert_eq!(mime_from_content_type(Some("image/webp; charset=binary"), "https://x/a.png"), "image/webp");
+        assert_eq!(mime_from_content_type(Some("application/octet-stream"), "https://x/a.png"), "image/png");
+        assert_eq!(mime_from_content_type(None, "https://x/a.gif?w=1"), "image/gif");
+    }
+
+    #[tokio::test]
+    async fn leaves_urls_untouched_when_disabled() {
+        configure_vision(&VisionConfig::default());
+        let mut req: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}},
+                    {"type": "text", "text": "what is this"}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        inline_remote_images(&mut req, None).await;
+        let Some(OpenAIContent::Array(blocks)) = &req.messages[0].content else {
+            panic!("expected content parts");
+        };
+        assert!(matches!(&blocks[0], OpenAIContentBlock::ImageUrl { image_url } if image_url.url == "https://example.com/cat.png"));
+        assert!(matches!(&blocks[1], OpenAIContentBlock::Text { text } if text == "what is this"));
+    }
+}
@@ -1,7 +1,14 @@
 // Global thought_signature storage shared by all endpoints
 // Used to capture and replay signatures for Gemini 3+ function calls when clients don't pass them back.
 
-use std::sync::{Mutex, OnceLock};
+// The store is a std Mutex whose critical sections never await and only compare/swap a String,
+// so it is safe to call directly from inside async stream pipelines without blocking the executor.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::SignatureCacheConfig;
 
 static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
 
@@ -9,58 +16,177 @@ fn get_thought_sig_storage() -> &'static Mutex<Option<String>> {
     GLOBAL_THOUGHT_SIG.get_or_init(|| Mutex::new(None))
 }
 
+/// Lock the storage, recovering from poisoning (a panicked writer must not disable capture forever).
+fn lock_storage() -> MutexGuard<'static, Option<String>> {
+    get_thought_sig_storage()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
 /// Store thought_signature to global storage.
 /// Only stores if the new signature is longer than the existing one,
 /// to avoid short/partial signatures overwriting valid ones.
 pub fn store_thought_signature(sig: &str) {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        let should_store = match &*guard {
+    let (stored, previous_len) = {
+        let mut guard = lock_storage();
+        let previous_len = guard.as_ref().map(|s| s.len());
+        let should_store = match previous_len {
             None => true,
-            Some(existing) => sig.len() > existing.len(),
+            Some(len) => sig.len() > len,
         };
-
         if should_store {
-            tracing::debug!(
-                "[ThoughtSig] Storing new signature (length: {}, replacing old length: {:?})",
-                sig.len(),
-                guard.as_ref().map(|s| s.len())
-            );
             *guard = Some(sig.to_string());
-        } else {
-            tracing::debug!(
-                "[ThoughtSig] Skipping shorter signature (new length: {}, existing length: {})",
-                sig.len(),
-                guard.as_ref().map(|s| s.len()).unwrap_or(0)
-            );
         }
+        (should_store, previous_len)
+    };
+
+    // Log outside the lock to keep the critical section minimal
+    if stored {
+        tracing::debug!(
+            "[ThoughtSig] Storing new signature (length: {}, replacing old length: {:?})",
+            sig.len(),
+            previous_len
+        );
+    } else {
+        tracing::debug!(
+            "[ThoughtSig] Skipping shorter signature (new length: {}, existing length: {})",
+            sig.len(),
+            previous_len.unwrap_or(0)
+        );
     }
 }
 
 /// Get the stored thought_signature without clearing it.
 pub fn get_thought_signature() -> Option<String> {
-    if let Ok(guard) = get_thought_sig_storage().lock() {
-        guard.clone()
-    } else {
-        None
-    }
+    lock_storage().clone()
 }
 
 /// Get and clear the stored thought_signature.
 #[allow(dead_code)]
 pub fn take_thought_signature() -> Option<String> {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        guard.take()
-    } else {
-        None
-    }
+    lock_storage().take()
 }
 
 /// Clear the stored thought_signature.
 #[allow(dead_code)]
 pub fn clear_thought_signature() {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        *guard = None;
+    *lock_storage() = None;
+}
+
+/// Signatures keyed by tool call id, bounded by entry count and TTL.
+/// Eviction is FIFO by first insertion; re-storing an id refreshes its value and timestamp.
+pub struct SignatureCache {
+    entries: HashMap<String, (String, Instant)>,
+    order: VecDeque<String>,
+    max_entries: usize,
+    ttl: Duration,
+    evicted_total: u64,
+}
+
+impl SignatureCache {
+    pub fn new(config: &SignatureCacheConfig) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            max_entries: config.max_entries.max(1),
+            ttl: Duration::from_secs(config.ttl_secs),
+            evicted_total: 0,
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn configure(&mut self, config: &SignatureCacheConfig) {
+        self.max_entries = config.max_entries.max(1);
+        self.ttl = Duration::from_secs(config.ttl_secs);
+        self.evict(Instant::now());
+    }
+
+    pub fn insert(&mut self, key: &str, sig: &str, now: Instant) {
+        if self
+            .entries
+            .insert(key.to_string(), (sig.to_string(), now))
+            .is_none()
+        {
+            self.order.push_back(key.to_string());
+        }
+        self.evict(now);
+    }
+
+    pub fn get(&self, key: &str, now: Instant) -> Option<String> {
+        self.entries
+            .get(key)
+            .filter(|(_, stored_at)| now.duration_since(*stored_at) < self.ttl)
+            .map(|(sig, _)| sig.clone())
     }
+
+    /// Drop expired entries from the front, then the oldest entries beyond capacity.
+    fn evict(&mut self, now: Instant) {
+        let mut expired = 0;
+        let mut overflow = 0;
+        while let Some(key) = self.order.front() {
+            let is_expired = match self.entries.get(key) {
+                Some((_, stored_at)) => now.duration_since(*stored_at) >= self.ttl,
+                None => true,
+            };
+            if is_expired {
+                expired += 1;
+            } else if self.entries.len() > self.max_entries {
+                overflow += 1;
+            } else {
+                break;
+            }
+            if let Some(key) = self.order.pop_front() {
+                self.entries.remove(&key);
+            }
+        }
+
+        if expired + overflow > 0 {
+            self.evicted_total += (expired + overflow) as u64;
+            tracing::debug!(
+                "[ThoughtSig] Evicted {} expired / {} over capacity (size: {}/{}, total evicted: {})",
+                expired,
+                overflow,
+                self.entries.len(),
+                self.max_entries,
+                self.evicted_total
+            );
+        }
+    }
+}
+
+static TOOL_SIGNATURES: OnceLock<Mutex<SignatureCache>> = OnceLock::new();
+
+fn lock_tool_signatures() -> MutexGuard<'static, SignatureCache> {
+    TOOL_SIGNATURES
+        .get_or_init(|| Mutex::new(SignatureCache::new(&SignatureCacheConfig::default())))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Apply capacity / TTL settings (on proxy start and config hot reload).
+pub fn configure_signature_cache(config: &SignatureCacheConfig) {
+    lock_tool_signatures().configure(config);
+}
+
+/// Store the thought_signature that accompanied a specific tool call.
+pub fn store_tool_signature(tool_id: &str, sig: &str) {
+    lock_tool_signatures().insert(tool_id, sig, Instant::now());
+}
+
+/// Look up the thought_signature for a tool call id (None if unknown or expired).
+pub fn get_tool_signature(tool_id: &str) -> Option<String> {
+    lock_tool_signatures().get(tool_id, Instant::now())
+}
+
+/// Serializes tests that touch the global storage.
+#[cfg(test)]
+pub(crate) fn test_guard() -> MutexGuard<'static, ()> {
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+    TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 #[cfg(test)]
@@ -69,6 +195,7 @@ mod tests {
 
     #[test]
     fn test_signature_storage() {
+        let _guard = test_guard();
         // Clear any existing state
         clear_thought_signature();
 
@@ -104,4 +231,41 @@ mod tests {
         );
         assert!(get_thought_signature().is_none());
     }
+
+    #[test]
+    fn test_signature_cache_stays_bounded() {
+        let config = SignatureCacheConfig {
+            max_entries: 100,
+            ttl_secs: 3600,
+        };
+        let mut cache = SignatureCache::new(&config);
+        let now = Instant::now();
+        for i in 0..250 {
+            cache.insert(&format!("toolu_{}", i), &format!("sig_{}", i), now);
+        }
+
+        assert_eq!(cache.len(), 100);
+        assert_eq!(cache.order.len(), 100);
+        assert!(cache.get("toolu_0", now).is_none());
+        assert_eq!(cache.get("toolu_249", now), Some("sig_249".to_string()));
+        assert_eq!(cache.evicted_total, 150);
+    }
+
+    #[test]
+    fn test_signature_cache_expires_entries() {
+        let config = SignatureCacheConfig {
+            max_entries: 10,
+            ttl_secs: 60,
+        };
+        let mut cache = SignatureCache::new(&config);
+        let start = Instant::now();
+        cache.insert("toolu_old", "sig_old", start);
+
+        let later = start + Duration::from_secs(61);
+        assert!(cache.get("toolu_old", later).is_none());
+
+        cache.insert("toolu_new", "sig_new", later);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("toolu_new", later), Some("sig_new".to_string()));
+    }
 }
@@ -0,0 +1,559 @@
+// Prometheus 指标导出 (`GET /metrics`)
+// - 指标通过 `metrics` 门面记录，由 metrics-exporter-prometheus 聚合与渲染；
+//   recorder 在 AxumServer::start 中安装 (进程内一次，代理重启后继续累计)，渲染句柄保存在 AppState
+// - 请求计数 / 耗时按路由模板 (MatchedPath) 聚合，避免把模型名等路径参数变成高基数标签；
+//   请求计数另带上游模型与账号标签 (handler 通过 note_prompt_size / get_token 写入请求级槽位)
+// - 重试按原因计数，流式中途出错单独计数
+// - 上游耗时 (到响应头)、在途流数量、输入 / 输出 Token 总数
+// - 账号健康分在抓取时从 TokenManager 现取并写入 gauge
+// - Prompt 体积 (systemInstruction / contents / 附件) 在转换后的上游请求体上统计，
+//   由 handler 通过 note_prompt_size 写入请求级槽位，请求结束时计入直方图与按模型 / 天汇总 (AppState.prompt_sizes)
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use futures::{Stream, StreamExt};
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Label};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle, PrometheusRecorder};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::proxy::common::prompt_size::{AttachmentSize, PromptSizeBreakdown};
+use crate::proxy::config::MetricsConfig;
+use crate::proxy::server::AppState;
+
+/// 请求耗时直方图的桶上界 (秒)
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+/// Prompt 字节数直方图的桶上界
+const PROMPT_BYTES_BUCKETS: &[f64] = &[
+    1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+/// Prompt Token 数直方图的桶上界
+const PROMPT_TOKENS_BUCKETS: &[f64] = &[
+    256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0,
+];
+/// 按模型 / 天汇总保留的天数
+const PROMPT_STATS_RETENTION_DAYS: i64 = 7;
+
+const REQUESTS_TOTAL: &str = "proxy_requests_total";
+const REQUEST_DURATION: &str = "proxy_request_duration_seconds";
+const PROMPT_BYTES: &str = "proxy_prompt_bytes";
+const PROMPT_TOKENS: &str = "proxy_prompt_tokens";
+const TOKEN_HEALTH: &str = "proxy_token_health";
+const RETRY_TOTAL: &str = "proxy_retry_total";
+const STREAM_ERRORS_TOTAL: &str = "proxy_stream_errors_total";
+const UPSTREAM_LATENCY: &str = "proxy_upstream_latency_seconds";
+const ACTIVE_STREAMS: &str = "proxy_active_streams";
+const TOKENS_TOTAL: &str = "proxy_tokens_total";
+
+/// 已安装的全局 recorder 的渲染句柄 (全局 recorder 每个进程只能设置一次)
+static HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
+
+/// 构建 recorder：直方图使用固定桶 (未配置桶的直方图会被渲染为 summary)
+fn build_recorder() -> PrometheusRecorder {
+    let histograms = [
+        (REQUEST_DURATION, DURATION_BUCKETS),
+        (UPSTREAM_LATENCY, DURATION_BUCKETS),
+        (PROMPT_BYTES, PROMPT_BYTES_BUCKETS),
+        (PROMPT_TOKENS, PROMPT_TOKENS_BUCKETS),
+    ];
+    histograms
+        .into_iter()
+        .fold(PrometheusBuilder::new(), |builder, (name, buckets)| {
+            builder
+                .set_buckets_for_metric(Matcher::Full(name.to_string()), buckets)
+                .expect("bucket bounds are non-empty")
+        })
+        .build_recorder()
+}
+
+/// 注册指标的 HELP 文本 (需在 recorder 生效后调用)
+fn describe_metrics() {
+    describe_counter!(REQUESTS_TOTAL, "Total proxied HTTP requests.");
+    describe_histogram!(REQUEST_DURATION, "Time until response headers were sent.");
+    describe_histogram!(PROMPT_BYTES, "Upstream prompt size in bytes by section.");
+    describe_histogram!(PROMPT_TOKENS, "Estimated upstream prompt tokens by section.");
+    describe_gauge!(TOKEN_HEALTH, "Account health score (0.0 - 1.0).");
+    describe_counter!(RETRY_TOTAL, "Upstream retries by reason.");
+    describe_counter!(STREAM_ERRORS_TOTAL, "Streams terminated with an error event.");
+    describe_histogram!(UPSTREAM_LATENCY, "Time until upstream response headers, by upstream model.");
+    describe_gauge!(ACTIVE_STREAMS, "Streaming responses currently being relayed.");
+    describe_counter!(TOKENS_TOTAL, "Tokens reported by upstream usage metadata.");
+}
+
+/// 安装全局 Prometheus recorder 并返回渲染句柄 (重复调用返回同一句柄)
+pub fn install_recorder() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            let recorder = build_recorder();
+            let handle = recorder.handle();
+            if let Err(e) = metrics::set_global_recorder(recorder) {
+                tracing::warn!("安装 Prometheus recorder 失败: {}", e);
+            }
+            describe_metrics();
+            handle
+        })
+        .clone()
+}
+
+/// 未安装到全局的独立 recorder 句柄 (测试用 AppState)
+#[cfg(test)]
+pub(crate) fn detached_handle() -> PrometheusHandle {
+    build_recorder().handle()
+}
+
+static ENDPOINT_ENABLED: AtomicBool = AtomicBool::new(true);
+static REQUIRE_AUTH: AtomicBool = AtomicBool::new(false);
+
+/// 应用指标端点配置 (代理启动与配置热更新时调用)
+pub fn configure_metrics(config: &MetricsConfig) {
+    ENDPOINT_ENABLED.store(config.enabled, Ordering::Relaxed);
+    REQUIRE_AUTH.store(config.require_auth, Ordering::Relaxed);
+}
+
+/// /metrics 是否需要 API Key 认证
+pub fn endpoint_requires_auth() -> bool {
+    REQUIRE_AUTH.load(Ordering::Relaxed)
+}
+
+/// 某个数值的累计与最大值
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    sum: u64,
+    max: u64,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: u64) {
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    fn summary(&self, count: u64) -> SizeSummary {
+        SizeSummary {
+            avg: self.sum.checked_div(count).unwrap_or(0),
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct PromptDaily {
+    requests: u64,
+    system_bytes: Accumulator,
+    contents_bytes: Accumulator,
+    tools_bytes: Accumulator,
+    estimated_tokens: Accumulator,
+    attachments: BTreeMap<String, AttachmentSize>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SizeSummary {
+    pub avg: u64,
+    pub max: u64,
+}
+
+/// `GET /stats/prompt-sizes` 的一行：某天某模型的 Prompt 体积汇总
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptSizeDailyStats {
+    pub date: String,
+    pub model: String,
+    pub requests: u64,
+    pub system_bytes: SizeSummary,
+    pub contents_bytes: SizeSummary,
+    pub tools_bytes: SizeSummary,
+    pub estimated_tokens: SizeSummary,
+    pub attachments: BTreeMap<String, AttachmentSize>,
+}
+
+/// 请求级 Prompt 体积记录 (由 metrics_middleware 写入响应 extensions，供监控日志读取)
+#[derive(Debug, Clone)]
+pub struct PromptSizeRecord {
+    pub model: String,
+    pub breakdown: PromptSizeBreakdown,
+}
+
+/// 请求计数的模型 / 账号标签 (未知时为空，不输出对应标签)
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestTarget {
+    pub model: String,
+    pub account: String,
+}
+
+/// 最终服务请求的账号与换号重试次数 (写入响应 extensions，供访问日志读取)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountRecord {
+    pub email: String,
+    pub retries: u32,
+}
+
+#[derive(Debug, Default)]
+struct RequestRecord {
+    prompt_size: Option<PromptSizeRecord>,
+    account: Option<String>,
+    /// 选号次数 (每次尝试各选一次)
+    attempts: u32,
+}
+
+type RequestSlot = Arc<Mutex<RequestRecord>>;
+
+tokio::task_local! {
+    static REQUEST_RECORD: RequestSlot;
+}
+
+/// 记录当前请求转换后的上游请求体体积 (重试时以最后一次为准；不在请求上下文中时忽略)
+pub fn note_prompt_size(model: &str, breakdown: &PromptSizeBreakdown) {
+    let _ = REQUEST_RECORD.try_with(|slot| {
+        slot.lock().unwrap().prompt_size = Some(PromptSizeRecord {
+            model: model.to_string(),
+            breakdown: breakdown.clone(),
+        });
+    });
+}
+
+/// 记录当前请求选中的账号 (换号重试时以最后一次为准；不在请求上下文中时忽略)
+pub fn note_account(email: &str) {
+    let _ = REQUEST_RECORD.try_with(|slot| {
+        let mut record = slot.lock().unwrap();
+        record.account = Some(email.to_string());
+        record.attempts += 1;
+    });
+}
+
+/// 在途流计数，Drop 时减一
+struct ActiveStream;
+
+impl ActiveStream {
+    fn new() -> Self {
+        gauge!(ACTIVE_STREAMS).increment(1.0);
+        Self
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        gauge!(ACTIVE_STREAMS).decrement(1.0);
+    }
+}
+
+/// 流存活期间计入 proxy_active_streams (流结束或被丢弃时减一)
+pub fn track_stream<S: Stream>(stream: S) -> impl Stream<Item = S::Item> {
+    let guard = ActiveStream::new();
+    stream.map(move |item| {
+        let _active = &guard;
+        item
+    })
+}
+
+/// 记录一次完成的请求 (耗时为到响应头返回为止，流式响应的传输时间不计入)
+pub fn record_request(path: &str, status: u16, elapsed: Duration, target: RequestTarget) {
+    let mut labels = vec![
+        Label::new("path", path.to_string()),
+        Label::new("status", status.to_string()),
+    ];
+    if !target.model.is_empty() {
+        labels.push(Label::new("model", target.model));
+    }
+    if !target.account.is_empty() {
+        labels.push(Label::new("account", target.account));
+    }
+    counter!(REQUESTS_TOTAL, labels).increment(1);
+    histogram!(REQUEST_DURATION, "path" => path.to_string()).record(elapsed.as_secs_f64());
+}
+
+/// 记录一次请求各部分的 Prompt 体积直方图
+pub fn record_prompt_size(breakdown: &PromptSizeBreakdown) {
+    let mut sections = vec![
+        ("system".to_string(), breakdown.system.bytes, breakdown.system.tokens),
+        ("contents".to_string(), breakdown.contents.bytes, breakdown.contents.tokens),
+        ("tools".to_string(), breakdown.tools.bytes, breakdown.tools.tokens),
+    ];
+    for (kind, size) in &breakdown.attachments {
+        sections.push((format!("attachment_{}", kind), size.bytes, size.tokens));
+    }
+    for (section, bytes, tokens) in sections {
+        histogram!(PROMPT_BYTES, "section" => section.clone()).record(bytes as f64);
+        histogram!(PROMPT_TOKENS, "section" => section).record(tokens as f64);
+    }
+}
+
+/// 记录一次重试 (reason 取值见 `retry_reason`，另有 network / stream_failover)
+pub fn record_retry(reason: &'static str) {
+    counter!(RETRY_TOTAL, "reason" => reason).increment(1);
+}
+
+/// 记录一次向客户端输出的流式错误
+pub fn record_stream_error() {
+    counter!(STREAM_ERRORS_TOTAL).increment(1);
+}
+
+/// 记录一次上游调用到响应头返回的耗时
+pub fn record_upstream_latency(model: &str, elapsed: Duration) {
+    histogram!(UPSTREAM_LATENCY, "model" => model.to_string()).record(elapsed.as_secs_f64());
+}
+
+/// 累计上游返回的输入 / 输出 Token 数 (输出含思考 Token)
+pub fn record_tokens(input: u64, output: u64) {
+    counter!(TOKENS_TOTAL, "direction" => "input").increment(input);
+    counter!(TOKENS_TOTAL, "direction" => "output").increment(output);
+}
+
+/// 写入账号健康分 (email -> 健康分)
+fn record_token_health(token_health: &HashMap<String, f64>) {
+    for (email, score) in token_health {
+        gauge!(TOKEN_HEALTH, "email" => email.clone()).set(*score);
+    }
+}
+
+/// 按模型 / 天的 Prompt 体积汇总 (`GET /stats/prompt-sizes`)
+#[derive(Debug, Default)]
+pub struct PromptSizeStats {
+    /// (日期, 模型) -> 汇总
+    daily: Mutex<BTreeMap<(String, String), PromptDaily>>,
+}
+
+impl PromptSizeStats {
+    /// 记录一次请求的 Prompt 体积 (date 为本地日期 YYYY-MM-DD)
+    pub fn record(&self, date: &str, model: &str, breakdown: &PromptSizeBreakdown) {
+        let mut daily = self.daily.lock().unwrap();
+        let entry = daily
+            .entry((date.to_string(), model.to_string()))
+            .or_default();
+        entry.requests += 1;
+        entry.system_bytes.add(breakdown.system.bytes);
+        entry.contents_bytes.add(breakdown.contents.bytes);
+        entry.tools_bytes.add(breakdown.tools.bytes);
+        entry.estimated_tokens.add(breakdown.total_tokens());
+        for (kind, size) in &breakdown.attachments {
+            let a = entry.attachments.entry(kind.clone()).or_default();
+            a.count += size.count;
+            a.bytes += size.bytes;
+            a.tokens += size.tokens;
+        }
+
+        // 只保留最近几天 (日期字符串按字典序即时间序)
+        if let Some(cutoff) = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.checked_sub_signed(chrono::Duration::days(PROMPT_STATS_RETENTION_DAYS)))
+        {
+            let cutoff = cutoff.format("%Y-%m-%d").to_string();
+            daily.retain(|(d, _), _| *d > cutoff);
+        }
+    }
+
+    /// 按模型 / 天的 Prompt 体积汇总 (日期倒序)
+    pub fn snapshot(&self) -> Vec<PromptSizeDailyStats> {
+        let daily = self.daily.lock().unwrap();
+        let mut stats: Vec<_> = daily
+            .iter()
+            .map(|((date, model), d)| PromptSizeDailyStats {
+                date: date.clone(),
+                model: model.clone(),
+                requests: d.requests,
+                system_bytes: d.system_bytes.summary(d.requests),
+                contents_bytes: d.contents_bytes.summary(d.requests),
+                tools_bytes: d.tools_bytes.summary(d.requests),
+                estimated_tokens: d.estimated_tokens.summary(d.requests),
+                attachments: d.attachments.clone(),
+            })
+            .collect();
+        stats.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.model.cmp(&b.model)));
+        stats
+    }
+}
+
+/// 按上游状态码归类重试原因
+pub fn retry_reason(status: u16) -> &'static str {
+    match status {
+        429 => "rate_limited",
+        401 | 403 => "auth",
+        400 => "invalid_request",
+        500..=599 => "server_error",
+        _ => "other",
+    }
+}
+
+/// 请求计数 / 耗时中间件 (以 route_layer 挂载，保证 MatchedPath 可用；未匹配的路由不计入)
+///
+/// 同时为 handler 提供请求级 Prompt 体积槽位，请求结束后计入统计并写入响应 extensions。
+pub async fn metrics_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    let start = Instant::now();
+    let slot = RequestSlot::default();
+    let mut response = REQUEST_RECORD.scope(slot.clone(), next.run(request)).await;
+    let RequestRecord { prompt_size, account, attempts } = std::mem::take(&mut *slot.lock().unwrap());
+    if let Some(path) = path.filter(|p| p != "/metrics") {
+        let target = RequestTarget {
+            model: prompt_size.as_ref().map(|r| r.model.clone()).unwrap_or_default(),
+            account: account.clone().unwrap_or_default(),
+        };
+        record_request(&path, response.status().as_u16(), start.elapsed(), target);
+    }
+    if let Some(email) = account {
+        response.extensions_mut().insert(AccountRecord {
+            email,
+            retries: attempts.saturating_sub(1),
+        });
+    }
+    if let Some(record) = prompt_size {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        record_prompt_size(&record.breakdown);
+        state.prompt_sizes.record(&today, &record.model, &record.breakdown);
+        response.extensions_mut().insert(record);
+    }
+    response
+}
+
+/// GET /metrics (配置关闭时返回 404)
+pub async fn metrics_handler(State(state): State<AppState>) -> Response {
+    if !ENDPOINT_ENABLED.load(Ordering::Relaxed) {
+        return axum::http::StatusCode::NOT_FOUND.into_response();
+    }
+    record_token_health(&state.token_manager.token_scores());
+    let body = state.metrics.render();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+/// GET /stats/prompt-sizes
+pub async fn prompt_size_stats_handler(State(state): State<AppState>) -> Response {
+    Json(state.prompt_sizes.snapshot()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在独立 recorder 上执行记录并返回渲染结果
+    fn render_with(f: impl FnOnce()) -> String {
+        let recorder = build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            describe_metrics();
+            f();
+        });
+        handle.render()
+    }
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let text = render_with(|| {
+            record_request("/v1/messages", 200, Duration::from_millis(300), RequestTarget::default());
+            record_request("/v1/messages", 200, Duration::from_secs(20), RequestTarget::default());
+            record_request("/v1/messages", 429, Duration::from_millis(50), RequestTarget::default());
+            record_retry(retry_reason(429));
+            record_retry(retry_reason(429));
+            record_stream_error();
+
+            let mut health = HashMap::new();
+            health.insert("a\"b@example.com".to_string(), 0.75);
+            record_token_health(&health);
+        });
+
+        assert!(text.contains("# TYPE proxy_requests_total counter\n"));
+        assert!(text.contains("proxy_requests_total{path=\"/v1/messages\",status=\"200\"} 2\n"));
+        assert!(text.contains("proxy_requests_total{path=\"/v1/messages\",status=\"429\"} 1\n"));
+        // 直方图桶是累计的
+        assert!(text.contains("# TYPE proxy_request_duration_seconds histogram\n"));
+        assert!(text.contains("proxy_request_duration_seconds_bucket{path=\"/v1/messages\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("proxy_request_duration_seconds_bucket{path=\"/v1/messages\",le=\"0.5\"} 2\n"));
+        assert!(text.contains("proxy_request_duration_seconds_bucket{path=\"/v1/messages\",le=\"30\"} 3\n"));
+        assert!(text.contains("proxy_request_duration_seconds_bucket{path=\"/v1/messages\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("proxy_request_duration_seconds_count{path=\"/v1/messages\"} 3\n"));
+        assert!(text.contains("proxy_token_health{email=\"a\\\"b@example.com\"} 0.75\n"));
+        assert!(text.contains("proxy_retry_total{reason=\"rate_limited\"} 2\n"));
+        assert!(text.contains("proxy_stream_errors_total 1\n"));
+    }
+
+    #[test]
+    fn test_render_model_account_latency_and_tokens() {
+        let text = render_with(|| {
+            let target = RequestTarget {
+                model: "gemini-2.5-flash".to_string(),
+                account: "a@example.com".to_string(),
+            };
+            record_request("/v1/chat/completions", 200, Duration::from_millis(300), target.clone());
+            record_request("/v1/chat/completions", 200, Duration::from_millis(900), target);
+            record_upstream_latency("gemini-2.5-flash", Duration::from_millis(200));
+            record_tokens(120, 30);
+            record_tokens(80, 20);
+        });
+
+        assert!(text.contains(
+            "proxy_requests_total{path=\"/v1/chat/completions\",status=\"200\",model=\"gemini-2.5-flash\",account=\"a@example.com\"} 2\n"
+        ));
+        assert!(text.contains("proxy_upstream_latency_seconds_bucket{model=\"gemini-2.5-flash\",le=\"0.25\"} 1\n"));
+        assert!(text.contains("proxy_tokens_total{direction=\"input\"} 200\n"));
+        assert!(text.contains("proxy_tokens_total{direction=\"output\"} 50\n"));
+    }
+
+    #[test]
+    fn test_active_streams_follow_stream_lifetime() {
+        let recorder = build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            let stream = track_stream(futures::stream::iter(vec![1, 2]));
+            assert!(handle.render().contains("proxy_active_streams 1\n"));
+            assert_eq!(futures::executor::block_on(stream.collect::<Vec<_>>()), vec![1, 2]);
+            assert!(handle.render().contains("proxy_active_streams 0\n"));
+        });
+    }
+
+    #[test]
+    fn test_prompt_size_stats() {
+        use crate::proxy::common::prompt_size::measure_request;
+        use serde_json::json;
+
+        let stats = PromptSizeStats::default();
+        let small = measure_request(&json!({
+            "systemInstruction": {"parts": [{"text": "a".repeat(100)}]},
+            "contents": [{"role": "user", "parts": [{"text": "hi"}]}]
+        }));
+        let big = measure_request(&json!({
+            "systemInstruction": {"parts": [{"text": "a".repeat(300_000)}]},
+            "contents": [{"role": "user", "parts": [
+                {"text": "hi"},
+                {"inlineData": {"mimeType": "image/png", "data": "AAAA"}}
+            ]}]
+        }));
+        stats.record("2026-01-01", "gemini-2.5-flash", &small);
+        stats.record("2026-01-01", "gemini-2.5-flash", &big);
+        stats.record("2026-01-02", "claude-sonnet-4-5", &small);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].date, "2026-01-02");
+        let flash = &snapshot[1];
+        assert_eq!(flash.requests, 2);
+        assert_eq!(flash.system_bytes.max, 300_000);
+        assert_eq!(flash.system_bytes.avg, 150_050);
+        assert_eq!(flash.attachments["image"].count, 1);
+
+        let text = render_with(|| {
+            record_prompt_size(&small);
+            record_prompt_size(&big);
+            record_prompt_size(&small);
+        });
+        assert!(text.contains("proxy_prompt_bytes_bucket{section=\"system\",le=\"1024\"} 2\n"));
+        assert!(text.contains("proxy_prompt_bytes_bucket{section=\"system\",le=\"262144\"} 2\n"));
+        assert!(text.contains("proxy_prompt_bytes_bucket{section=\"system\",le=\"1048576\"} 3\n"));
+        assert!(text.contains("proxy_prompt_tokens_count{section=\"attachment_image\"} 1\n"));
+
+        // 超出保留天数的汇总被清理
+        stats.record("2026-01-20", "gemini-2.5-flash", &small);
+        assert_eq!(stats.snapshot().len(), 1);
+    }
+}
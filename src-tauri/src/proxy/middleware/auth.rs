@@ -2,10 +2,12 @@
 use axum::{
     extract::State,
     extract::Request,
-    http::{header, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
+    Json,
 };
+use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -16,7 +18,7 @@ pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
     request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
     let method = request.method().clone();
     let path = request.uri().path().to_string();
 
@@ -29,55 +31,241 @@ pub async fn auth_middleware(
 
     // Allow CORS preflight regardless of auth policy.
     if method == axum::http::Method::OPTIONS {
-        return Ok(next.run(request).await);
+        return next.run(request).await;
     }
 
     let security = security.read().await.clone();
-    let effective_mode = security.effective_auth_mode();
+    let api_key = extract_request_api_key(&request);
+    match authorize(&security, &path, api_key) {
+        Ok(()) => {
+            // 附加 Key 在任何认证模式下都受模型白名单约束
+            let scope = api_key.and_then(|k| security.scope_for_key(k));
+            crate::proxy::key_scope::scope_model_access(scope, next.run(request)).await
+        }
+        Err(AuthError::Unauthorized(message)) => (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({
+                "error": {
+                    "type": "authentication_error",
+                    "message": message
+                }
+            })),
+        )
+            .into_response(),
+        Err(AuthError::Forbidden(message)) => (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": {
+                    "type": "permission_error",
+                    "message": message
+                }
+            })),
+        )
+            .into_response(),
+    }
+}
+
+/// 认证失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// 缺少或无效的 Key (401)
+    Unauthorized(&'static str),
+    /// Key 有效但无权访问该路径 (403)
+    Forbidden(&'static str),
+}
+
+/// 管理 / 调试 / 统计接口，只允许主 Key 访问
+fn is_admin_path(path: &str) -> bool {
+    ["/admin", "/debug", "/stats"]
+        .iter()
+        .any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+        || path == "/v1/internal/tokens"
+}
 
-    if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
+/// 判断请求是否通过认证
+/// 认证开启但未配置任何 Key 时一律拒绝 (fail closed)
+pub fn authorize(
+    security: &ProxySecurityConfig,
+    path: &str,
+    api_key: Option<&str>,
+) -> Result<(), AuthError> {
+    // 附加 Key 只能调用模型接口，任何认证模式下都不能访问管理接口
+    if is_admin_path(path) && api_key.is_some_and(|k| security.scope_for_key(k).is_some()) {
+        return Err(AuthError::Forbidden("This API key is not allowed to access admin endpoints."));
     }
 
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
+    // Prometheus 抓取端点默认不走 API Key 认证 (metrics.require_auth 可开启)
+    if path == "/metrics" && !crate::proxy::metrics::endpoint_requires_auth() {
+        return Ok(());
     }
-    
-    // 从 header 中提取 API key
-    let api_key = request
-        .headers()
+
+    match security.effective_auth_mode() {
+        ProxyAuthMode::Off => return Ok(()),
+        ProxyAuthMode::AllExceptHealth if path == "/healthz" => return Ok(()),
+        _ => {}
+    }
+
+    // 开启认证却未配置任何 Key 时拒绝请求，不能放行
+    if !security.has_keys() {
+        tracing::warn!("Proxy auth is enabled but no API key is configured; rejecting request");
+        return Err(AuthError::Unauthorized(
+            "Proxy auth is enabled but no API key is configured on the server.",
+        ));
+    }
+
+    // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
+    match api_key {
+        None => Err(AuthError::Unauthorized(
+            "Missing API key. Provide it via `Authorization: Bearer <key>`, `x-api-key` or `x-goog-api-key`.",
+        )),
+        Some(k) if security.is_known_key(k) => Ok(()),
+        Some(_) => Err(AuthError::Unauthorized("Invalid API key.")),
+    }
+}
+
+/// 从 Authorization (Bearer)、x-api-key 或 x-goog-api-key (Gemini SDK) 头中提取 API Key
+pub fn extract_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-api-key")
-                .and_then(|h| h.to_str().ok())
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+        .or_else(|| headers.get("x-goog-api-key").and_then(|h| h.to_str().ok()))
+}
+
+/// 在请求头之外，兼容 Gemini REST 风格的 `?key=<api_key>` 查询参数
+pub fn extract_request_api_key(request: &Request) -> Option<&str> {
+    extract_api_key(request.headers()).or_else(|| {
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("key="))
+            .filter(|k| !k.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn security(auth_mode: ProxyAuthMode, api_key: &str) -> ProxySecurityConfig {
+        ProxySecurityConfig {
+            auth_mode,
+            api_key: api_key.to_string(),
+            allow_lan_access: false,
+            scoped_keys: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_authorize_requires_matching_key() {
+        let s = security(ProxyAuthMode::Strict, "sk-test");
+        assert!(authorize(&s, "/v1/messages", Some("sk-test")).is_ok());
+        assert!(authorize(&s, "/v1/messages", Some("sk-wrong")).is_err());
+        assert!(authorize(&s, "/v1/messages", None).is_err());
+        // strict 模式下健康检查同样需要认证
+        assert!(authorize(&s, "/healthz", None).is_err());
+        assert!(authorize(&s, "/metrics", None).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_exemptions() {
+        let s = security(ProxyAuthMode::AllExceptHealth, "sk-test");
+        assert!(authorize(&s, "/healthz", None).is_ok());
+        assert!(authorize(&s, "/v1/models", None).is_err());
+
+        // 认证开启但未配置密钥时拒绝，而不是放行
+        let s = security(ProxyAuthMode::Strict, "");
+        assert!(matches!(authorize(&s, "/v1/messages", None), Err(AuthError::Unauthorized(_))));
+        assert!(authorize(&s, "/v1/messages", Some("anything")).is_err());
+        let s = security(ProxyAuthMode::AllExceptHealth, "");
+        assert!(authorize(&s, "/healthz", None).is_ok());
+
+        let s = security(ProxyAuthMode::Off, "sk-test");
+        assert!(authorize(&s, "/v1/messages", None).is_ok());
+    }
+
+    #[test]
+    fn test_scoped_keys_authorize_with_model_scope() {
+        let mut s = security(ProxyAuthMode::Strict, "sk-main");
+        s.scoped_keys.keys.push(crate::proxy::config::ScopedApiKey {
+            key: "sk-team".to_string(),
+            label: "teammate".to_string(),
+            allowed_models: vec!["gemini-*flash*".to_string()],
+            report_model: None,
         });
+        assert!(authorize(&s, "/v1/messages", Some("sk-team")).is_ok());
+        assert!(authorize(&s, "/v1/messages", Some("sk-other")).is_err());
+
+        assert!(s.scope_for_key("sk-main").is_none());
+        let scope = s.scope_for_key("sk-team").unwrap();
+        assert_eq!(scope.allowed_models, vec!["gemini-*flash*"]);
+        assert!(!scope.check_resolved_model);
 
-    if security.api_key.is_empty() {
-        tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
-        return Err(StatusCode::UNAUTHORIZED);
+        // 仅配置附加 Key 时同样要求认证
+        s.api_key.clear();
+        assert!(authorize(&s, "/v1/messages", None).is_err());
+        assert!(authorize(&s, "/v1/messages", Some("")).is_err());
     }
 
-    // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
-    let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
+    #[test]
+    fn test_scoped_keys_cannot_reach_admin_endpoints() {
+        let mut s = security(ProxyAuthMode::Strict, "sk-main");
+        s.scoped_keys.keys.push(crate::proxy::config::ScopedApiKey {
+            key: "sk-team".to_string(),
+            label: "teammate".to_string(),
+            allowed_models: vec!["gemini-*flash*".to_string()],
+            report_model: None,
+        });
+        let forbidden = Err(AuthError::Forbidden("This API key is not allowed to access admin endpoints."));
+        for path in [
+            "/admin/accounts/a@example.com/probe-models",
+            "/admin/bans",
+            "/debug/compat-report",
+            "/stats",
+            "/stats/a@example.com",
+            "/v1/internal/tokens",
+        ] {
+            assert_eq!(authorize(&s, path, Some("sk-team")), forbidden, "{}", path);
+            assert!(authorize(&s, path, Some("sk-main")).is_ok(), "{}", path);
+        }
+        assert!(authorize(&s, "/v1/messages", Some("sk-team")).is_ok());
+        assert!(authorize(&s, "/administrator", Some("sk-team")).is_ok());
 
-    if authorized {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+        // 关闭认证时附加 Key 同样不能访问管理接口
+        s.auth_mode = ProxyAuthMode::Off;
+        assert_eq!(authorize(&s, "/admin/bans", Some("sk-team")), forbidden);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // 移除未使用的 use super::*;
+    #[test]
+    fn test_extract_api_key() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer sk-abc".parse().unwrap());
+        assert_eq!(extract_api_key(&headers), Some("sk-abc"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "sk-xyz".parse().unwrap());
+        assert_eq!(extract_api_key(&headers), Some("sk-xyz"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-goog-api-key", "sk-goog".parse().unwrap());
+        assert_eq!(extract_api_key(&headers), Some("sk-goog"));
+    }
 
     #[test]
-    fn test_auth_placeholder() {
-        // Placeholder test
-        assert!(true);
+    fn test_extract_api_key_from_query() {
+        let request = Request::builder()
+            .uri("/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse&key=sk-query")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(extract_request_api_key(&request), Some("sk-query"));
+
+        let request = Request::builder()
+            .uri("/v1beta/models/gemini-2.5-flash:generateContent?alt=sse")
+            .header("x-goog-api-key", "sk-header")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(extract_request_api_key(&request), Some("sk-header"));
     }
 }
@@ -16,6 +16,7 @@ pub fn cors_layer() -> CorsLayer {
             Method::PATCH,
         ])
         .allow_headers(Any)
+        .expose_headers([super::request_id::REQUEST_ID_HEADER])
         .allow_credentials(false)
         .max_age(std::time::Duration::from_secs(3600))
 }
@@ -0,0 +1,478 @@
+// 入站请求防护中间件 (局域网暴露时抵御扫描器)
+// - 被临时封禁的 IP 直接拒绝
+// - 请求头总大小上限
+// - API 路由提前拒绝非 JSON Content-Type
+// - 统计每个 IP 的失败请求，超过阈值后临时封禁
+// - 纯文本的 4xx 错误统一转换为结构化 JSON
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::GuardConfig;
+
+/// 失败记录数量超过该值时清理过期条目
+const MAX_TRACKED_IPS: usize = 10_000;
+/// 转换为 JSON 时读取的纯文本错误体上限
+const MAX_WRAPPED_ERROR_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+struct FailureWindow {
+    count: u32,
+    started: Instant,
+}
+
+/// 封禁列表条目 (管理接口展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct BanEntry {
+    pub ip: String,
+    pub remaining_secs: u64,
+}
+
+/// 按 IP 统计失败请求并临时封禁
+pub struct AbuseGuard {
+    config: RwLock<GuardConfig>,
+    failures: DashMap<IpAddr, FailureWindow>,
+    bans: DashMap<IpAddr, Instant>, // IP -> 封禁截止时间
+}
+
+impl AbuseGuard {
+    pub fn new(config: GuardConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            failures: DashMap::new(),
+            bans: DashMap::new(),
+        }
+    }
+
+    pub fn update_config(&self, config: GuardConfig) {
+        if let Ok(mut guard) = self.config.write() {
+            *guard = config;
+        }
+        // 阈值变化后旧计数不再可靠；已有封禁保留到期
+        self.failures.clear();
+    }
+
+    fn config(&self) -> GuardConfig {
+        self.config.read().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// 若 IP 处于封禁期，返回剩余时长
+    pub fn banned_for(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        let until = *self.bans.get(&ip)?;
+        if until > now {
+            Some(until - now)
+        } else {
+            self.bans.remove(&ip);
+            None
+        }
+    }
+
+    /// 记录一次失败请求；达到阈值时封禁并返回 true
+    pub fn record_failure(&self, ip: IpAddr, now: Instant) -> bool {
+        let config = self.config();
+        let window = Duration::from_secs(config.failure_window_secs.max(1));
+
+        if self.failures.len() > MAX_TRACKED_IPS {
+            self.failures
+                .retain(|_, w| now.duration_since(w.started) < window);
+        }
+
+        let count = {
+            let mut entry = self.failures.entry(ip).or_insert(FailureWindow {
+                count: 0,
+                started: now,
+            });
+            if now.duration_since(entry.started) >= window {
+                entry.count = 0;
+                entry.started = now;
+            }
+            entry.count += 1;
+            entry.count
+        };
+
+        if count >= config.max_failures.max(1) {
+            self.failures.remove(&ip);
+            self.bans
+                .insert(ip, now + Duration::from_secs(config.ban_secs));
+            tracing::warn!(
+                "[Guard] {} banned for {}s after {} failed requests within {}s",
+                ip,
+                config.ban_secs,
+                count,
+                window.as_secs()
+            );
+            return true;
+        }
+        false
+    }
+
+    /// 当前有效的封禁列表
+    pub fn bans(&self, now: Instant) -> Vec<BanEntry> {
+        self.bans.retain(|_, until| *until > now);
+        let mut entries: Vec<BanEntry> = self
+            .bans
+            .iter()
+            .map(|e| BanEntry {
+                ip: e.key().to_string(),
+                remaining_secs: e.value().duration_since(now).as_secs(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.ip.cmp(&b.ip));
+        entries
+    }
+
+    /// 解除单个 IP 的封禁
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.failures.remove(&ip);
+        self.bans.remove(&ip).is_some()
+    }
+
+    /// 清空封禁列表，返回清除的数量
+    pub fn clear_bans(&self) -> usize {
+        let count = self.bans.len();
+        self.bans.clear();
+        self.failures.clear();
+        count
+    }
+}
+
+/// 计入失败统计的状态码 (扫描器典型特征，不含 429 与上游 5xx)
+fn is_counted_failure(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_REQUEST
+            | StatusCode::UNAUTHORIZED
+            | StatusCode::NOT_FOUND
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::PAYLOAD_TOO_LARGE
+            | StatusCode::UNSUPPORTED_MEDIA_TYPE
+            | StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            | StatusCode::UNPROCESSABLE_ENTITY
+    )
+}
+
+fn headers_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+/// API 路由上带请求体的方法必须使用 JSON (图像编辑允许 multipart)
+fn content_type_allowed(method: &Method, path: &str, headers: &HeaderMap) -> bool {
+    let is_api = path.starts_with("/v1/") || path.starts_with("/v1beta/");
+    let has_body = matches!(*method, Method::POST | Method::PUT | Method::PATCH);
+    if !is_api || !has_body || path.starts_with("/v1/api/event_logging") {
+        return true;
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase());
+    match content_type.as_deref() {
+        Some("application/json") => true,
+        Some(ct) if ct.starts_with("application/") && ct.ends_with("+json") => true,
+        Some("multipart/form-data") => path == "/v1/images/edits",
+        // 无请求体的 POST (如探测类接口) 不强制 Content-Type
+        None => headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim() == "0"),
+        _ => false,
+    }
+}
+
+fn error_response(status: StatusCode, error_type: &str, message: String) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "type": error_type,
+                "message": message
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn error_type_for(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        _ => "invalid_request_error",
+    }
+}
+
+/// 将纯文本 (或空) 的 4xx 错误体包装为结构化 JSON
+async fn into_structured_error(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    if !status.is_client_error() || is_json || status == StatusCode::TOO_MANY_REQUESTS {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let message = match axum::body::to_bytes(body, MAX_WRAPPED_ERROR_BYTES).await {
+        Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => status
+            .canonical_reason()
+            .unwrap_or("Request failed")
+            .to_string(),
+    };
+
+    let mut wrapped = error_response(status, error_type_for(status), message);
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            wrapped.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    wrapped
+}
+
+/// 入站防护中间件
+pub async fn guard_middleware(
+    State(guard): State<Arc<AbuseGuard>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = guard.config();
+    if !config.enabled || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let ip = addr.ip();
+    let tracked = !(config.exempt_loopback && ip.is_loopback());
+    let now = Instant::now();
+
+    if tracked {
+        if let Some(remaining) = guard.banned_for(ip, now) {
+            let retry_after = remaining.as_secs().max(1);
+            let mut resp = error_response(
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                format!("Too many failed requests, temporarily banned for {}s", retry_after),
+            );
+            if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+                resp.headers_mut().insert(header::RETRY_AFTER, value);
+            }
+            return resp;
+        }
+    }
+
+    let path = request.uri().path().to_string();
+    let rejection = if headers_size(request.headers()) > config.max_header_bytes {
+        Some(error_response(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "invalid_request_error",
+            format!("Request headers exceed {} bytes", config.max_header_bytes),
+        ))
+    } else if !content_type_allowed(request.method(), &path, request.headers()) {
+        Some(error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "invalid_request_error",
+            "Expected request with `Content-Type: application/json`".to_string(),
+        ))
+    } else {
+        None
+    };
+
+    let response = match rejection {
+        Some(resp) => resp,
+        None => into_structured_error(next.run(request).await).await,
+    };
+
+    if tracked && is_counted_failure(response.status()) {
+        tracing::debug!("[Guard] {} {} -> {}", ip, path, response.status());
+        guard.record_failure(ip, now);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use serde_json::Value;
+    use tower::Service;
+
+    fn guard_config() -> GuardConfig {
+        GuardConfig {
+            enabled: true,
+            max_header_bytes: 1024,
+            max_failures: 3,
+            failure_window_secs: 60,
+            ban_secs: 600,
+            exempt_loopback: true,
+        }
+    }
+
+    /// 使用真实映射器处理请求体的测试路由
+    fn app(guard: Arc<AbuseGuard>) -> Router {
+        use crate::proxy::mappers::{claude, openai};
+
+        Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(|Json(body): Json<Value>| async move {
+                    let req: openai::OpenAIRequest = serde_json::from_value(body)
+                        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+                    Ok::<_, (StatusCode, String)>(Json(openai::transform_openai_request(
+                        &req,
+                        "test-project",
+                        "gemini-2.5-flash",
+                    )))
+                }),
+            )
+            .route(
+                "/v1/messages",
+                post(|Json(body): Json<Value>| async move {
+                    let req: claude::ClaudeRequest = serde_json::from_value(body)
+                        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+                    claude::transform_claude_request_in(&req, "test-project")
+                        .map(Json)
+                        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(guard, guard_middleware))
+    }
+
+    fn request(ip: &str, path: &str, content_type: &str, body: impl Into<Body>) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body.into())
+            .unwrap();
+        let addr: SocketAddr = format!("{}:40000", ip).parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(addr));
+        req
+    }
+
+    async fn send(app: &mut Router, req: Request<Body>) -> (StatusCode, Value) {
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| panic!("non-JSON response for {}: {:?}", status, bytes));
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_rejects_non_json_and_oversized_headers() {
+        let mut app = app(Arc::new(AbuseGuard::new(guard_config())));
+
+        let (status, body) = send(&mut app, request("10.0.0.1", "/v1/chat/completions", "text/plain", "hi")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+
+        let mut req = request("10.0.0.2", "/v1/messages", "application/json", "{}");
+        req.headers_mut()
+            .insert("x-padding", HeaderValue::from_str(&"a".repeat(2048)).unwrap());
+        let (status, _) = send(&mut app, req).await;
+        assert_eq!(status, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_torture_bodies_return_structured_errors() {
+        let deep_array = format!("{}{}", "[".repeat(5000), "]".repeat(5000));
+        let deep_content = format!(
+            r#"{{"model":"m","messages":[{{"role":"user","content":{}1{}}}]}}"#,
+            "[".repeat(200),
+            "]".repeat(200)
+        );
+        let inputs: Vec<String> = vec![
+            String::new(),
+            "{".to_string(),
+            "null".to_string(),
+            "\"\\ud800\"".to_string(),
+            "1e999999".to_string(),
+            deep_array,
+            deep_content,
+            r#"{"model":1,"messages":"x"}"#.to_string(),
+            r#"{"model":"m","messages":[]}"#.to_string(),
+            r#"{"model":"m","messages":[{"role":"user","content":[{"type":"image_url","image_url":{"url":"data:;base64,"}}]}]}"#.to_string(),
+            r#"{"model":"m","messages":[{"role":"assistant","content":[{"type":"tool_use","id":"","name":"","input":null}]},{"role":"user","content":[{"type":"tool_result","tool_use_id":"x","content":{"a":[]}}]}]}"#.to_string(),
+            r##"{"model":"m","messages":[{"role":"tool","content":null,"tool_call_id":null}],"tools":[{"type":"function","function":{"name":"f","parameters":{"$ref":"#","anyOf":[{"$ref":"#"}]}}}]}"##.to_string(),
+        ];
+
+        // 回环地址豁免封禁，确保每个输入都真正到达处理器
+        let mut app = app(Arc::new(AbuseGuard::new(guard_config())));
+        for path in ["/v1/chat/completions", "/v1/messages"] {
+            for input in &inputs {
+                let (status, body) =
+                    send(&mut app, request("127.0.0.1", path, "application/json", input.clone())).await;
+                assert!(
+                    status.is_success() || status.is_client_error(),
+                    "{} {:?} -> {}",
+                    path,
+                    input.chars().take(60).collect::<String>(),
+                    status
+                );
+                if status.is_client_error() {
+                    assert!(body["error"]["message"].is_string(), "{} -> {}", path, body);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_failures_ban_ip_until_cleared() {
+        let guard = Arc::new(AbuseGuard::new(guard_config()));
+        let mut app = app(guard.clone());
+
+        for _ in 0..3 {
+            let (status, _) = send(&mut app, request("10.0.0.9", "/v1/messages", "application/json", "{")).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let (status, body) = send(&mut app, request("10.0.0.9", "/v1/messages", "application/json", "{}")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"]["type"], "permission_error");
+
+        let bans = guard.bans(Instant::now());
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].ip, "10.0.0.9");
+
+        assert!(guard.unban("10.0.0.9".parse().unwrap()));
+        assert!(guard.bans(Instant::now()).is_empty());
+        let (status, _) = send(&mut app, request("10.0.0.9", "/v1/messages", "application/json", "{")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_failure_window_and_ban_expiry() {
+        let guard = AbuseGuard::new(guard_config());
+        let ip: IpAddr = "10.0.0.3".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(!guard.record_failure(ip, now));
+        assert!(!guard.record_failure(ip, now));
+        // 窗口过期后重新计数
+        let later = now + Duration::from_secs(61);
+        assert!(!guard.record_failure(ip, later));
+        assert!(!guard.record_failure(ip, later));
+        assert!(guard.record_failure(ip, later));
+
+        assert!(guard.banned_for(ip, later).is_some());
+        assert!(guard.banned_for(ip, later + Duration::from_secs(601)).is_none());
+        assert_eq!(guard.clear_bans(), 0);
+    }
+}
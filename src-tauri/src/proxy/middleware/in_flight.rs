@@ -0,0 +1,112 @@
+// 在途请求计数
+// 请求进入时加一，响应体结束 (或被丢弃) 时减一，流式响应在整个流结束后才释放；
+// 停止服务时据此等待在途请求 (含 SSE 流) 完成，并向前端报告剩余数量
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub struct InFlightRequests {
+    count: watch::Sender<usize>,
+}
+
+impl Default for InFlightRequests {
+    fn default() -> Self {
+        Self {
+            count: watch::Sender::new(0),
+        }
+    }
+}
+
+impl InFlightRequests {
+    /// 当前在途请求数
+    pub fn count(&self) -> usize {
+        *self.count.borrow()
+    }
+
+    /// 订阅在途请求数变化
+    pub fn subscribe(&self) -> watch::Receiver<usize> {
+        self.count.subscribe()
+    }
+
+    /// 标记一个在途请求，守卫释放时自动减一
+    pub fn enter(self: &Arc<Self>) -> InFlightGuard {
+        self.count.send_modify(|n| *n += 1);
+        InFlightGuard { tracker: self.clone() }
+    }
+
+    /// 等待在途请求全部完成，超时返回仍未完成的数量 (全部完成时返回 0)
+    pub async fn wait_drained(&self, timeout: Duration) -> usize {
+        let mut count = self.subscribe();
+        let drained = tokio::time::timeout(timeout, count.wait_for(|n| *n == 0)).await.is_ok();
+        if drained {
+            0
+        } else {
+            self.count()
+        }
+    }
+}
+
+/// 在途请求守卫
+pub struct InFlightGuard {
+    tracker: Arc<InFlightRequests>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.tracker.count.send_modify(|n| *n = n.saturating_sub(1));
+    }
+}
+
+/// 统计所有在途请求 (守卫随响应体一起释放)
+pub async fn in_flight_middleware(
+    State(tracker): State<Arc<InFlightRequests>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let guard = tracker.enter();
+    let (parts, body) = next.run(request).await.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_holds_request_until_body_finishes() {
+        let tracker = Arc::new(InFlightRequests::default());
+        let mut app = axum::Router::new()
+            .route(
+                "/stream",
+                axum::routing::get(|| async {
+                    let chunks = async_stream::stream! {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        yield Ok::<_, std::io::Error>("data: done\n\n");
+                    };
+                    Body::from_stream(chunks)
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(tracker.clone(), in_flight_middleware));
+
+        let request = Request::get("/stream").body(Body::empty()).unwrap();
+        let response = tower::Service::call(&mut app, request).await.unwrap();
+        // 处理器已返回，但流尚未结束
+        assert_eq!(tracker.count(), 1);
+        assert_eq!(tracker.wait_drained(Duration::from_millis(10)).await, 1);
+
+        let body = tokio::spawn(axum::body::to_bytes(response.into_body(), usize::MAX));
+        assert_eq!(tracker.wait_drained(Duration::from_secs(2)).await, 0);
+        assert_eq!(&body.await.unwrap().unwrap()[..], b"data: done\n\n");
+    }
+}
@@ -1,11 +1,235 @@
 // 日志中间件
-// 直接使用 tower_http::trace::TraceLayer::new_for_http() 在路由中
+// 请求级日志直接使用 tower_http::trace::TraceLayer::new_for_http()；
+// 此处为调试用的请求/响应体记录 (debug.log_bodies)，以 TRACE 级别输出到 target = proxy_body
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::sync::RwLock;
+
+use crate::proxy::config::DebugConfig;
+
+/// 与 DefaultBodyLimit 保持一致，缓冲请求体时的上限
+const MAX_BUFFERED_BODY: usize = 100 * 1024 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+static CONFIG: Lazy<RwLock<DebugConfig>> = Lazy::new(|| RwLock::new(DebugConfig::default()));
+
+/// 应用调试日志配置 (启动与热更新共用)
+pub fn configure_body_logging(config: &DebugConfig) {
+    if let Ok(mut current) = CONFIG.write() {
+        *current = config.clone();
+    }
+}
+
+fn current_config() -> Option<DebugConfig> {
+    let config = CONFIG.read().ok()?;
+    if config.log_bodies && tracing::enabled!(target: "proxy_body", tracing::Level::TRACE) {
+        Some(config.clone())
+    } else {
+        None
+    }
+}
+
+/// 记录请求体与响应体 (非流式整体记录，SSE 逐个分片记录)
+pub async fn logging_middleware(request: Request, next: Next) -> Response {
+    let Some(config) = current_config() else {
+        return next.run(request).await;
+    };
+
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+
+    // 缓冲请求体后重新放回，保证下游处理器拿到完整内容
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BUFFERED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Failed to read request body: {}", e),
+            )
+                .into_response();
+        }
+    };
+    if !bytes.is_empty() {
+        tracing::trace!(
+            target: "proxy_body",
+            "--> {} {} {}",
+            method,
+            uri,
+            render_body(&bytes, &config)
+        );
+    }
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+
+    let status = response.status();
+    let is_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("text/event-stream"));
+
+    let (parts, body) = response.into_parts();
+    if is_stream {
+        let path = uri.path().to_string();
+        let stream = body.into_data_stream().inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                tracing::trace!(
+                    target: "proxy_body",
+                    "<-- {} {} [chunk] {}",
+                    status.as_u16(),
+                    path,
+                    render_sse_chunk(chunk, &config)
+                );
+            }
+        });
+        return Response::from_parts(parts, Body::from_stream(stream));
+    }
+
+    match axum::body::to_bytes(body, MAX_BUFFERED_BODY).await {
+        Ok(bytes) => {
+            tracing::trace!(
+                target: "proxy_body",
+                "<-- {} {} {}",
+                status.as_u16(),
+                uri.path(),
+                render_body(&bytes, &config)
+            );
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(e) => {
+            tracing::warn!("Failed to buffer response body for logging: {}", e);
+            (StatusCode::BAD_GATEWAY, "Failed to read response body").into_response()
+        }
+    }
+}
+
+/// 按调试配置的脱敏字段与长度上限处理正文 (访问日志 log_bodies 使用；非 JSON 原样截断)
+pub fn redact_body(text: &str) -> String {
+    let config = CONFIG.read().map(|c| c.clone()).unwrap_or_default();
+    let text = match serde_json::from_str::<Value>(text) {
+        Ok(mut json) => {
+            redact(&mut json, &config.redact_fields);
+            json.to_string()
+        }
+        Err(_) => text.to_string(),
+    };
+    truncate(&text, config.max_body_bytes)
+}
+
+/// 递归替换需要脱敏的字段
+fn redact(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if fields.iter().any(|f| f.eq_ignore_ascii_case(key)) {
+                    *v = Value::String(REDACTED.to_string());
+                } else {
+                    redact(v, fields);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact(item, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 按字节上限截断 (不切断 UTF-8 字符)
+fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...[truncated {} bytes]", &text[..end], text.len() - end)
+}
+
+fn render_body(bytes: &[u8], config: &DebugConfig) -> String {
+    if let Ok(mut json) = serde_json::from_slice::<Value>(bytes) {
+        redact(&mut json, &config.redact_fields);
+        return truncate(&json.to_string(), config.max_body_bytes);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => truncate(text, config.max_body_bytes),
+        Err(_) => format!("[binary {} bytes]", bytes.len()),
+    }
+}
+
+/// SSE 分片中的 data 行逐行脱敏
+fn render_sse_chunk(bytes: &[u8], config: &DebugConfig) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| match line.strip_prefix("data:") {
+            Some(data) => match serde_json::from_str::<Value>(data.trim()) {
+                Ok(mut json) => {
+                    redact(&mut json, &config.redact_fields);
+                    format!("data: {}", json)
+                }
+                Err(_) => line.to_string(),
+            },
+            None => line.to_string(),
+        })
+        .collect();
+    truncate(&lines.join("\n"), config.max_body_bytes)
+}
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn config(max_body_bytes: usize) -> DebugConfig {
+        DebugConfig {
+            log_bodies: true,
+            max_body_bytes,
+            redact_fields: vec!["access_token".to_string(), "data".to_string()],
+        }
+    }
+
     #[test]
     fn test_logging_middleware() {
         // Logging middleware 通过 tower_http::trace::TraceLayer::new_for_http() 直接使用
         assert!(true);
     }
+
+    #[test]
+    fn redacts_nested_fields() {
+        let body = br#"{"access_token":"ya29.secret","contents":[{"parts":[{"inlineData":{"mimeType":"image/png","data":"iVBORw0"}}]}]}"#;
+        let rendered = render_body(body, &config(4096));
+        assert!(!rendered.contains("ya29.secret"));
+        assert!(!rendered.contains("iVBORw0"));
+        assert!(rendered.contains("image/png"));
+        assert_eq!(rendered.matches(REDACTED).count(), 2);
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        let body = "你好世界".as_bytes();
+        assert_eq!(render_body(body, &config(4)), "你...[truncated 9 bytes]");
+        assert_eq!(render_body(&[0xff, 0xfe], &config(4)), "[binary 2 bytes]");
+    }
+
+    #[test]
+    fn redacts_sse_data_lines() {
+        let chunk = b"event: message\ndata: {\"access_token\":\"t\",\"text\":\"hi\"}\n\n";
+        let rendered = render_sse_chunk(chunk, &config(4096));
+        assert!(rendered.starts_with("event: message\n"));
+        assert!(rendered.contains(r#""access_token":"[REDACTED]""#));
+        assert!(rendered.contains(r#""text":"hi""#));
+    }
 }
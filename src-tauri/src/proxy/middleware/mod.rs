@@ -2,8 +2,15 @@
 
 pub mod auth;
 pub mod cors;
+pub mod guard;
+pub mod in_flight;
 pub mod logging;
 pub mod monitor;
+pub mod rate_limit;
+pub mod request_id;
+pub mod response_model;
+pub mod warnings;
 
 pub use auth::auth_middleware;
 pub use cors::cors_layer;
+pub use request_id::{current_request_id, next_upstream_attempt, request_id_middleware, upstream_request_id};
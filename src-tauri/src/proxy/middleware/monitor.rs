@@ -6,16 +6,23 @@ use axum::{
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::access_log::{self, RequestContext};
+use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog};
 use serde_json::Value;
 use futures::StreamExt;
 
+/// 写入监控日志与访问日志 (各自未启用时忽略)
+async fn finish(monitor: &ProxyMonitor, log: ProxyRequestLog, ctx: &RequestContext) {
+    access_log::record(&log, ctx);
+    monitor.log_request(log).await;
+}
+
 pub async fn monitor_middleware(
     State(state): State<AppState>,
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.monitor.is_enabled() {
+    if !state.monitor.is_enabled() && !access_log::is_enabled() {
         return next.run(request).await;
     }
 
@@ -23,7 +30,7 @@ pub async fn monitor_middleware(
     let method = request.method().to_string();
     let uri = request.uri().to_string();
     
-    if uri.contains("event_logging") {
+    if uri.contains("event_logging") || uri == "/metrics" {
         return next.run(request).await;
     }
     
@@ -36,21 +43,20 @@ pub async fn monitor_middleware(
         None
     };
 
+    let log_mode = *state.prompt_log.read().await;
     let request_body_str;
+    let mut prompt_hash = None;
     let request = if method == "POST" {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, 1024 * 1024).await {
             Ok(bytes) => {
-                if model.is_none() {
-                    model = serde_json::from_slice::<Value>(&bytes).ok().and_then(|v|
-                        v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
-                    );
+                if let Ok(json) = serde_json::from_slice::<Value>(&bytes) {
+                    if model.is_none() {
+                        model = json.get("model").and_then(|m| m.as_str()).map(|s| s.to_string());
+                    }
+                    prompt_hash = crate::proxy::common::prompt_log::prompt_hash_for_log(log_mode, &json);
                 }
-                request_body_str = if let Ok(s) = std::str::from_utf8(&bytes) {
-                    Some(s.to_string())
-                } else {
-                    Some("[Binary Request Data]".to_string())
-                };
+                request_body_str = crate::proxy::common::prompt_log::request_body_for_log(log_mode, &bytes);
                 Request::from_parts(parts, Body::from(bytes))
             }
             Err(_) => {
@@ -74,6 +80,15 @@ pub async fn monitor_middleware(
         .to_string();
 
     let monitor = state.monitor.clone();
+    let ctx = RequestContext {
+        request_id: crate::proxy::middleware::current_request_id(),
+        mapped_model: response
+            .extensions()
+            .get::<crate::proxy::metrics::PromptSizeRecord>()
+            .map(|r| r.model.clone()),
+        account: response.extensions().get::<crate::proxy::metrics::AccountRecord>().cloned(),
+        prompt_hash,
+    };
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
         timestamp: chrono::Utc::now().timestamp_millis(),
@@ -87,6 +102,25 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        prompt_size: response
+            .extensions()
+            .get::<crate::proxy::metrics::PromptSizeRecord>()
+            .map(|r| r.breakdown.clone()),
+        warnings: response
+            .extensions()
+            .get::<crate::proxy::common::warnings::ConversionWarnings>()
+            .map(|w| w.0.clone()),
+        // 上游模型不受 report_model 影响 (条件路由结果优先，否则取转换时记录的模型)
+        upstream_model: response
+            .extensions()
+            .get::<crate::proxy::common::model_routes::RoutedModel>()
+            .map(|r| r.0.clone())
+            .or_else(|| {
+                response
+                    .extensions()
+                    .get::<crate::proxy::metrics::PromptSizeRecord>()
+                    .map(|r| r.model.clone())
+            }),
     };
 
     if content_type.contains("text/event-stream") {
@@ -134,7 +168,7 @@ pub async fn monitor_middleware(
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
-            monitor.log_request(log).await;
+            finish(&monitor, log, &ctx).await;
         });
 
         Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
@@ -160,18 +194,18 @@ pub async fn monitor_middleware(
                 if log.status >= 400 {
                     log.error = log.response_body.clone();
                 }
-                monitor.log_request(log).await;
+                finish(&monitor, log, &ctx).await;
                 Response::from_parts(parts, Body::from(bytes))
             }
             Err(_) => {
                 log.response_body = Some("[Response too large]".to_string());
-                monitor.log_request(log).await;
+                finish(&monitor, log, &ctx).await;
                 Response::from_parts(parts, Body::empty())
             }
         }
     } else {
         log.response_body = Some(format!("[{}]", content_type));
-        monitor.log_request(log).await;
+        finish(&monitor, log, &ctx).await;
         response
     }
 }
@@ -0,0 +1,194 @@
+// 入站请求限流中间件 (按客户端 IP 的令牌桶)
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use serde_json::json;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::RateLimitConfig;
+use crate::proxy::ProxySecurityConfig;
+
+/// 桶数量超过该值时清理长时间空闲的条目
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+/// 空闲超过该时间的桶视为已回满，可直接移除
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// 令牌桶限流器；持有有效 API Key 的客户端使用独立的 (更高) 额度
+pub struct InboundRateLimiter {
+    config: RwLock<RateLimitConfig>,
+    buckets: DashMap<(IpAddr, bool), TokenBucket>, // (IP, 是否已认证) -> 令牌桶
+}
+
+impl InboundRateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            buckets: DashMap::new(),
+        }
+    }
+
+    pub fn update_config(&self, config: RateLimitConfig) {
+        if let Ok(mut guard) = self.config.write() {
+            *guard = config;
+        }
+        // 额度变化后旧桶状态不再可靠，直接重置
+        self.buckets.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.read().map(|c| c.enabled).unwrap_or(false)
+    }
+
+    /// 消耗一个令牌；额度不足时返回需要等待的时长
+    pub fn check(&self, ip: IpAddr, authenticated: bool, now: Instant) -> Result<(), Duration> {
+        let (rate, burst) = {
+            let config = self.config.read().map(|c| c.clone()).unwrap_or_default();
+            if authenticated {
+                (config.authenticated_requests_per_second, config.authenticated_burst)
+            } else {
+                (config.requests_per_second, config.burst)
+            }
+        };
+        let rate = rate.max(0.001);
+        let capacity = f64::from(burst.max(1));
+
+        if self.buckets.len() > MAX_TRACKED_BUCKETS {
+            self.buckets
+                .retain(|_, b| now.duration_since(b.last_refill) < IDLE_BUCKET_TTL);
+        }
+
+        let mut bucket = self
+            .buckets
+            .entry((ip, authenticated))
+            .or_insert_with(|| TokenBucket {
+                tokens: capacity,
+                last_refill: now,
+            });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitState {
+    pub limiter: Arc<InboundRateLimiter>,
+    pub security: Arc<tokio::sync::RwLock<ProxySecurityConfig>>,
+}
+
+/// 入站限流中间件
+pub async fn rate_limit_middleware(
+    State(state): State<RateLimitState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.limiter.is_enabled()
+        || request.method() == axum::http::Method::OPTIONS
+        || request.uri().path() == "/healthz"
+    {
+        return next.run(request).await;
+    }
+
+    let authenticated = {
+        let security = state.security.read().await;
+        super::auth::extract_request_api_key(&request).is_some_and(|k| security.is_known_key(k))
+    };
+
+    match state.limiter.check(addr.ip(), authenticated, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::warn!(
+                "[RateLimit] {} exceeded inbound limit (authenticated: {}), retry after {}s",
+                addr.ip(),
+                authenticated,
+                retry_after
+            );
+            let mut resp = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({
+                    "error": {
+                        "type": "rate_limit_error",
+                        "message": format!("Too many requests, retry after {}s", retry_after)
+                    }
+                })),
+            )
+                .into_response();
+            if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+                resp.headers_mut().insert(header::RETRY_AFTER, value);
+            }
+            resp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> InboundRateLimiter {
+        InboundRateLimiter::new(RateLimitConfig {
+            enabled: true,
+            requests_per_second: 1.0,
+            burst: 2,
+            authenticated_requests_per_second: 10.0,
+            authenticated_burst: 5,
+        })
+    }
+
+    #[test]
+    fn test_bucket_exhaustion_and_refill() {
+        let l = limiter();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(l.check(ip, false, now).is_ok());
+        assert!(l.check(ip, false, now).is_ok());
+        let wait = l.check(ip, false, now).unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+
+        // 其他 IP 不受影响
+        assert!(l.check("10.0.0.2".parse().unwrap(), false, now).is_ok());
+
+        // 1 秒后补充 1 个令牌
+        assert!(l.check(ip, false, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_authenticated_bucket_is_separate() {
+        let l = limiter();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            l.check(ip, false, now).unwrap();
+        }
+        assert!(l.check(ip, false, now).is_err());
+        for _ in 0..5 {
+            assert!(l.check(ip, true, now).is_ok());
+        }
+        assert!(l.check(ip, true, now).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// 请求 ID 中间件
+// 读取客户端传入的 X-Request-ID (缺失或非法时生成)，贯穿 handler → 上游客户端 → 响应：
+// - 写入 request extensions，供 handler / 监控日志读取
+// - 在当前任务内可通过 current_request_id() 获取，上游请求体的 requestId 使用该值
+// - 每次上游发送的序号 (next_upstream_attempt) 记录在 upstream{attempt=...} span 中，requestId 本身不变
+// - 所有日志都挂在 request{request_id=...} span 下，便于按请求 grep
+// - 原样写回响应头 X-Request-ID
+// 客户端传入 Idempotency-Key 时，上游 requestId 改用该值 (客户端自行重发同一请求时保持不变)
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::Instrument;
+
+use crate::proxy::upstream::cancel::DisconnectGuard;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// 客户端传入 ID 的最大长度，超出则重新生成
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 当前请求的 ID (存放在 request extensions 中)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// 复用客户端传入的 ID；仅接受可见 ASCII 字符，避免日志注入与超长值
+    pub fn from_header(value: Option<&HeaderValue>) -> Self {
+        client_supplied_id(value).map(Self).unwrap_or_else(Self::generate)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn client_supplied_id(value: Option<&HeaderValue>) -> Option<String> {
+    value
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= MAX_REQUEST_ID_LEN
+                && v.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: RequestId;
+    static CURRENT_IDEMPOTENCY_KEY: Option<String>;
+    /// 当前请求已发往上游的次数 (含重试与账号轮换)
+    static UPSTREAM_SENDS: AtomicU32;
+}
+
+/// 当前任务所属请求的 ID (不在请求上下文中时返回 None，例如后台探测任务)
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.0.clone()).ok()
+}
+
+/// 上游请求体使用的 requestId：优先客户端 Idempotency-Key，否则为请求 ID
+/// 同一客户端请求内的所有重试 / 账号轮换都复用该值，上游可据此识别重复提交
+pub fn upstream_request_id() -> Option<String> {
+    CURRENT_IDEMPOTENCY_KEY
+        .try_with(|key| key.clone())
+        .ok()
+        .flatten()
+        .or_else(current_request_id)
+}
+
+/// 为下一次上游发送分配序号 (从 1 开始，不在请求上下文中时返回 None)
+/// 只用于日志 span 字段；上游 requestId 始终为 upstream_request_id()，保证重试间不变
+pub fn next_upstream_attempt() -> Option<u32> {
+    UPSTREAM_SENDS
+        .try_with(|n| n.fetch_add(1, Ordering::Relaxed) + 1)
+        .ok()
+}
+
+/// 在请求上下文中执行 future (请求 ID 与可选的 Idempotency-Key)
+pub async fn scope_request<F: std::future::Future>(
+    request_id: RequestId,
+    idempotency_key: Option<String>,
+    fut: F,
+) -> F::Output {
+    let fut = UPSTREAM_SENDS.scope(AtomicU32::new(0), fut);
+    CURRENT_REQUEST_ID
+        .scope(request_id, CURRENT_IDEMPOTENCY_KEY.scope(idempotency_key, fut))
+        .await
+}
+
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = RequestId::from_header(request.headers().get(&REQUEST_ID_HEADER));
+    let idempotency_key = client_supplied_id(request.headers().get(&IDEMPOTENCY_KEY_HEADER));
+    request.extensions_mut().insert(request_id.clone());
+    // 客户端在响应头发出前断开时，handler future 被丢弃 (重试循环 / 上游请求随之中止)
+    let disconnect_guard = DisconnectGuard::new(
+        request_id.as_str(),
+        format!("{} {}", request.method(), request.uri().path()),
+    );
+
+    let span = tracing::info_span!("request", request_id = %request_id.as_str());
+    let mut response = scope_request(request_id.clone(), idempotency_key, next.run(request))
+        .instrument(span)
+        .await;
+    disconnect_guard.disarm();
+
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_header_reuses_valid_ids() {
+        let id = RequestId::from_header(Some(&HeaderValue::from_static("req-abc_123")));
+        assert_eq!(id.as_str(), "req-abc_123");
+
+        for bad in ["", "has space", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let value = HeaderValue::from_str(bad).unwrap();
+            let id = RequestId::from_header(Some(&value));
+            assert_ne!(id.as_str(), bad);
+            assert!(uuid::Uuid::parse_str(id.as_str()).is_ok());
+        }
+        assert!(uuid::Uuid::parse_str(RequestId::from_header(None).as_str()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id_is_scoped_to_request() {
+        assert!(current_request_id().is_none());
+        let id = RequestId("client-42".to_string());
+        let seen = CURRENT_REQUEST_ID
+            .scope(id, async {
+                tokio::task::yield_now().await;
+                current_request_id()
+            })
+            .await;
+        assert_eq!(seen.as_deref(), Some("client-42"));
+        assert!(current_request_id().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_upstream_request_id_prefers_idempotency_key() {
+        let id = || RequestId("client-42".to_string());
+        let without_key = scope_request(id(), None, async { upstream_request_id() }).await;
+        let with_key = scope_request(id(), Some("order-7".to_string()), async { upstream_request_id() }).await;
+        assert_eq!(without_key.as_deref(), Some("client-42"));
+        assert_eq!(with_key.as_deref(), Some("order-7"));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_sends_are_numbered_per_request() {
+        let sends = |key: Option<&str>| {
+            scope_request(RequestId("client-42".to_string()), key.map(str::to_string), async {
+                (0..3)
+                    .map(|_| (upstream_request_id().unwrap(), next_upstream_attempt().unwrap()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        // 序号逐次递增，requestId 保持不变
+        assert_eq!(
+            sends(None).await,
+            vec![("client-42".to_string(), 1), ("client-42".to_string(), 2), ("client-42".to_string(), 3)]
+        );
+        // 新的请求重新计数
+        assert_eq!(sends(None).await[0].1, 1);
+        assert!(sends(Some("order-7")).await.iter().all(|(id, _)| id == "order-7"));
+        assert!(next_upstream_attempt().is_none());
+    }
+}
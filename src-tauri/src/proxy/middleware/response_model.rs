@@ -0,0 +1,177 @@
+// 响应 model 改写中间件
+// requested 模式下 (见 common::response_model) 从请求中取出客户端请求的模型名，
+// 对 JSON 响应 (含错误体) 与 SSE 流的每个事件统一调用 rewrite_model；upstream 模式 (默认) 不做任何处理
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde_json::Value;
+
+use crate::proxy::common::model_routes::RoutedModel;
+use crate::proxy::common::response_model::{effective_mode, rewrite_model, SseModelRewriter};
+use crate::proxy::config::ReportModel;
+use crate::proxy::metrics::PromptSizeRecord;
+
+/// 读取请求体 / 非流式响应体时的缓冲上限
+const MAX_BUFFERED_BODY: usize = 100 * 1024 * 1024;
+
+pub async fn response_model_middleware(request: Request, next: Next) -> Response {
+    if effective_mode(request.headers()) != ReportModel::Requested {
+        return next.run(request).await;
+    }
+    let (requested, request) = match requested_model(request).await {
+        Ok(parts) => parts,
+        Err(response) => return response,
+    };
+    let response = next.run(request).await;
+    let Some(requested) = requested else {
+        return response;
+    };
+
+    // 条件路由命中时以路由结果为准，否则取转换时记录的上游模型
+    let upstream = response
+        .extensions()
+        .get::<RoutedModel>()
+        .map(|r| r.0.clone())
+        .or_else(|| response.extensions().get::<PromptSizeRecord>().map(|r| r.model.clone()));
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if content_type.contains("text/event-stream") {
+        rewrite_stream(response, requested, upstream)
+    } else if content_type.contains("application/json") {
+        rewrite_body(response, &requested, upstream.as_deref()).await
+    } else {
+        response
+    }
+}
+
+/// Gemini 原生路径中的模型名 (/v1beta/models/{model}:method)，其余协议取 JSON 请求体的 model
+async fn requested_model(request: Request) -> Result<(Option<String>, Request), Response> {
+    let from_path = request
+        .uri()
+        .path()
+        .split_once("/models/")
+        .and_then(|(_, rest)| rest.split(':').next())
+        .filter(|m| !m.is_empty())
+        .map(|m| m.to_string());
+    if from_path.is_some() || request.method() != axum::http::Method::POST {
+        return Ok((from_path, request));
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_BUFFERED_BODY)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to read request body: {}", e)).into_response())?;
+    let model = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|v| v.get("model").and_then(|m| m.as_str()).map(|m| m.to_string()));
+    Ok((model, Request::from_parts(parts, Body::from(bytes))))
+}
+
+async fn rewrite_body(response: Response, requested: &str, upstream: Option<&str>) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BUFFERED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("[ReportModel] 读取响应体失败，未改写 model: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if !rewrite_model(&mut json, requested, upstream) {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(json.to_string()))
+}
+
+fn rewrite_stream(response: Response, requested: String, upstream: Option<String>) -> Response {
+    let (parts, body) = response.into_parts();
+    let mut upstream_body = body.into_data_stream();
+    let mut rewriter = SseModelRewriter::new(requested, upstream);
+    let stream = async_stream::stream! {
+        while let Some(chunk) = upstream_body.next().await {
+            match chunk {
+                Ok(bytes) => {
+                    if let Some(out) = rewriter.push(&bytes) {
+                        yield Ok::<Bytes, axum::Error>(out);
+                    }
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        }
+        if let Some(rest) = rewriter.finish() {
+            yield Ok(rest);
+        }
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rewrites_json_and_stream_responses() {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, "60")
+            .body(Body::from(r#"{"error":{"message":"gemini-3-flash not found","code":404}}"#))
+            .unwrap();
+        let response = rewrite_body(response, "gpt-4o-mini", Some("gemini-3-flash")).await;
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let json: Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(json["error"]["message"], "gpt-4o-mini not found");
+
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from("data: {\"model\":\"gemini-3-fl")),
+            Ok(Bytes::from("ash\"}\n\ndata: [DONE]\n\n")),
+        ];
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        let text = body_text(rewrite_stream(response, "gpt-4o-mini".to_string(), None)).await;
+        assert_eq!(text, "data: {\"model\":\"gpt-4o-mini\"}\n\ndata: [DONE]\n\n");
+    }
+
+    #[tokio::test]
+    async fn reads_requested_model_from_path_or_body() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse")
+            .body(Body::from("{}"))
+            .unwrap();
+        let (model, _) = requested_model(request).await.unwrap();
+        assert_eq!(model.as_deref(), Some("gemini-2.5-flash"));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/messages")
+            .body(Body::from(r#"{"model":"claude-opus-4-1","max_tokens":10}"#))
+            .unwrap();
+        let (model, request) = requested_model(request).await.unwrap();
+        assert_eq!(model.as_deref(), Some("claude-opus-4-1"));
+        // 请求体原样交给处理器
+        assert!(body_text(Response::new(request.into_body())).await.contains("max_tokens"));
+    }
+}
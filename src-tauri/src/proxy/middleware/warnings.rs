@@ -0,0 +1,153 @@
+// 转换警告中间件
+// 为每个请求建立警告上下文 (common::warnings::record_warning 写入)，处理结束后：
+// - 警告列表写入响应 extensions，供监控日志记录
+// - extended 兼容模式下附加到响应体 (JSON 顶层 warnings / SSE 结束前的 warnings 事件)
+// 流式响应开始输出后才产生的警告不会再进入响应体
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use serde_json::Value;
+
+use crate::proxy::common::warnings::{self, ConversionWarning, ConversionWarnings};
+
+/// 非流式响应体改写时的缓冲上限 (超出时原样返回)
+const MAX_BUFFERED_BODY: usize = 64 * 1024 * 1024;
+
+pub async fn warnings_middleware(request: Request, next: Next) -> Response {
+    let (mut response, collected) = warnings::collect_warnings(next.run(request)).await;
+    if collected.is_empty() {
+        return response;
+    }
+    response
+        .extensions_mut()
+        .insert(ConversionWarnings(collected.clone()));
+    if !warnings::warnings_in_body() {
+        return response;
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if content_type.contains("text/event-stream") {
+        attach_to_stream(response, collected)
+    } else if content_type.contains("application/json") && response.status().is_success() {
+        attach_to_body(response, &collected).await
+    } else {
+        response
+    }
+}
+
+async fn attach_to_body(response: Response, collected: &[ConversionWarning]) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BUFFERED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("[Warnings] 读取响应体失败，未附加 warnings: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    warnings::attach_to_json(&mut json, collected);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(json.to_string()))
+}
+
+/// 终止事件 (OpenAI `[DONE]` / Anthropic message_stop) 之前插入 warnings 事件；
+/// 没有终止事件的协议 (Gemini) 追加到流末尾
+fn attach_to_stream(response: Response, collected: Vec<ConversionWarning>) -> Response {
+    let (parts, body) = response.into_parts();
+    let mut upstream = body.into_data_stream();
+    let event = Bytes::from(warnings::sse_event(&collected));
+    let stream = async_stream::stream! {
+        let mut pending = Some(event);
+        while let Some(chunk) = upstream.next().await {
+            if let Ok(bytes) = &chunk {
+                if is_terminal_chunk(bytes) {
+                    if let Some(event) = pending.take() {
+                        yield Ok(event);
+                    }
+                }
+            }
+            yield chunk;
+        }
+        if let Some(event) = pending.take() {
+            yield Ok(event);
+        }
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+fn is_terminal_chunk(bytes: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(bytes);
+    text.starts_with("data: [DONE]") || text.contains("event: message_stop")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning() -> Vec<ConversionWarning> {
+        vec![ConversionWarning {
+            code: warnings::codes::SAMPLING_ADJUSTED.to_string(),
+            message: "topK=40 stripped".to_string(),
+            path: Some("generationConfig.topK".to_string()),
+        }]
+    }
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn inserts_event_before_done() {
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from("data: {\"choices\":[]}\n\n")),
+            Ok(Bytes::from("data: [DONE]\n\n")),
+        ];
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        let text = body_text(attach_to_stream(response, warning())).await;
+        let warnings_at = text.find("event: warnings").unwrap();
+        assert!(warnings_at < text.find("data: [DONE]").unwrap());
+        assert!(text.contains("\"code\":\"sampling_adjusted\""));
+    }
+
+    #[tokio::test]
+    async fn appends_event_without_terminal_chunk() {
+        let chunks = vec![Ok::<_, std::io::Error>(Bytes::from("data: {\"candidates\":[]}\n\n"))];
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        let text = body_text(attach_to_stream(response, warning())).await;
+        assert!(text.ends_with("\n\n") && text.contains("event: warnings"));
+        assert!(text.starts_with("data: {\"candidates\""));
+    }
+
+    #[tokio::test]
+    async fn adds_top_level_array_to_json() {
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, "11")
+            .body(Body::from("{\"id\":\"x\"}"))
+            .unwrap();
+        let response = attach_to_body(response, &warning()).await;
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let json: Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(json["id"], "x");
+        assert_eq!(json["warnings"][0]["path"], "generationConfig.topK");
+    }
+}
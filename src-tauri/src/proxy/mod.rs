@@ -20,6 +20,18 @@ pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
+pub mod model_access;      // 账号 × 模型 可访问性缓存
+pub mod maintenance;       // 维护类流量 (后台探测的预算与降级)
+pub mod compat_report;     // 兼容性诊断报告 (不访问上游)
+pub mod break_in;          // 新账号磨合期 (降权与限速)
+pub mod metrics;           // Prometheus 指标导出
+pub mod telemetry;         // OpenTelemetry 链路追踪 (OTLP 导出)
+pub mod access_log;        // 结构化访问日志
+pub mod quota_state;       // 账号配额消耗状态持久化
+pub mod key_scope;         // 按 API Key 限制可用模型
+pub mod account_concurrency; // 账号级并发限制
+pub mod account_health;    // 后台账号健康探测
+pub mod account_schedule;  // 账号可用时段
 
 
 pub use config::ProxyConfig;
@@ -0,0 +1,354 @@
+// 账号 × 模型 可访问性缓存
+// 被动学习 (请求失败时记录) + 主动探测 (probe-models) 两种来源
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::proxy::maintenance::MaintenanceContext;
+use crate::proxy::mappers::gemini::models::{GenerationConfig, V1InternalRequest};
+
+/// 两次探测请求之间的间隔，避免短时间内打满账号配额
+const PROBE_INTERVAL_MS: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelAccess {
+    Accessible,
+    Denied,
+}
+
+/// 记录来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessProvenance {
+    /// 从真实请求的失败中学习
+    Learned,
+    /// 通过主动探测获得
+    Probed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelAccessEntry {
+    pub model: String,
+    pub access: ModelAccess,
+    pub provenance: AccessProvenance,
+    pub checked_at: i64,
+    pub detail: Option<String>,
+}
+
+/// 单个模型的探测结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStatus {
+    Accessible,
+    Denied,
+    /// 网络错误或上游 5xx，无法判定
+    Error,
+    /// 账号进入冷却或探测被取消，未执行
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub model: String,
+    pub status: ProbeStatus,
+    pub detail: Option<String>,
+}
+
+/// 探测无法开始的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    /// 该账号已有探测任务在运行
+    AlreadyRunning { email: String },
+    /// 账号处于限流冷却中
+    CoolingDown { email: String, retry_after_secs: u64 },
+    /// 账号不在代理账号池中
+    AccountNotFound { email: String },
+    /// Token 刷新或 project_id 获取失败
+    Unavailable(String),
+}
+
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::AlreadyRunning { email } => write!(f, "Model probe already running for {}", email),
+            ProbeError::CoolingDown { email, retry_after_secs } => {
+                write!(f, "Account {} is cooling down, retry in {}s", email, retry_after_secs)
+            }
+            ProbeError::AccountNotFound { email } => write!(f, "Account not found in proxy pool: {}", email),
+            ProbeError::Unavailable(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<ProbeError> for String {
+    fn from(e: ProbeError) -> Self {
+        e.to_string()
+    }
+}
+
+pub struct ModelAccessCache {
+    entries: DashMap<String, HashMap<String, ModelAccessEntry>>, // email -> (model -> entry)
+    probing: DashMap<String, Arc<AtomicBool>>,                   // email -> cancel flag
+}
+
+impl Default for ModelAccessCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelAccessCache {
+    pub fn new() -> Self {
+        Self {
+            entries: DashMap::new(),
+            probing: DashMap::new(),
+        }
+    }
+
+    pub fn record(
+        &self,
+        email: &str,
+        model: &str,
+        access: ModelAccess,
+        provenance: AccessProvenance,
+        detail: Option<String>,
+    ) {
+        let entry = ModelAccessEntry {
+            model: model.to_string(),
+            access,
+            provenance,
+            checked_at: chrono::Utc::now().timestamp(),
+            detail,
+        };
+        self.entries
+            .entry(email.to_string())
+            .or_default()
+            .insert(model.to_string(), entry);
+    }
+
+    /// 从上游错误中被动学习：403 视为该账号无权访问此模型
+    pub fn learn_from_error(&self, email: &str, model: &str, status: u16, error_text: &str) {
+        if status == 403 {
+            let detail: String = error_text.chars().take(200).collect();
+            self.record(email, model, ModelAccess::Denied, AccessProvenance::Learned, Some(detail));
+        }
+    }
+
+    /// 该账号是否已知无权访问此模型 (被动学习或主动探测得到)
+    pub fn is_denied(&self, email: &str, model: &str) -> bool {
+        self.entries
+            .get(email)
+            .and_then(|m| m.get(model).map(|e| e.access == ModelAccess::Denied))
+            .unwrap_or(false)
+    }
+
+    /// 获取某账号的全部记录 (按模型名排序)
+    pub fn entries_for(&self, email: &str) -> Vec<ModelAccessEntry> {
+        let mut list: Vec<ModelAccessEntry> = self
+            .entries
+            .get(email)
+            .map(|m| m.values().cloned().collect())
+            .unwrap_or_default();
+        list.sort_by(|a, b| a.model.cmp(&b.model));
+        list
+    }
+
+    /// 开始探测；同一账号同一时间只允许一个探测任务
+    pub fn begin_probe(&self, email: &str) -> Result<ProbeGuard<'_>, ProbeError> {
+        use dashmap::mapref::entry::Entry;
+        match self.probing.entry(email.to_string()) {
+            Entry::Occupied(_) => Err(ProbeError::AlreadyRunning { email: email.to_string() }),
+            Entry::Vacant(v) => {
+                let cancel = Arc::new(AtomicBool::new(false));
+                v.insert(cancel.clone());
+                Ok(ProbeGuard {
+                    cache: self,
+                    email: email.to_string(),
+                    cancel,
+                })
+            }
+        }
+    }
+
+    /// 取消正在进行的探测，返回是否存在该任务
+    pub fn cancel_probe(&self, email: &str) -> bool {
+        if let Some(flag) = self.probing.get(email) {
+            flag.store(true, Ordering::SeqCst);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 探测任务守卫，Drop 时自动释放账号的探测锁
+pub struct ProbeGuard<'a> {
+    cache: &'a ModelAccessCache,
+    email: String,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ProbeGuard<'_> {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ProbeGuard<'_> {
+    fn drop(&mut self) {
+        self.cache.probing.remove(&self.email);
+    }
+}
+
+/// 逐个模型探测指定账号的访问权限，并以 Probed 来源写入缓存
+/// 探测属于维护流量：占用独立预算，用户请求繁忙时停止，429 不会让账号进入冷却
+pub async fn probe_account_models(
+    ctx: &MaintenanceContext<'_>,
+    email: &str,
+) -> Result<Vec<ProbeResult>, ProbeError> {
+    let cache = ctx.model_access();
+    let guard = cache.begin_probe(email)?;
+
+    if !ctx.has_account(email) {
+        return Err(ProbeError::AccountNotFound { email: email.to_string() });
+    }
+    let (access_token, project_id, account_id) = ctx.token_by_email(email).await.map_err(ProbeError::Unavailable)?;
+    if ctx.is_rate_limited(&account_id) {
+        return Err(ProbeError::CoolingDown {
+            email: email.to_string(),
+            retry_after_secs: ctx.cooldown_seconds(&account_id),
+        });
+    }
+
+    let models: Vec<String> = crate::proxy::common::model_mapping::get_upstream_models()
+        .into_iter()
+        // 图像模型的最小请求也会生成图片，跳过
+        .filter(|m| !m.contains("image"))
+        .collect();
+
+    let mut results = Vec::with_capacity(models.len());
+    let mut stop_reason: Option<String> = None;
+
+    for (idx, model) in models.iter().enumerate() {
+        if stop_reason.is_none() {
+            if guard.is_cancelled() {
+                stop_reason = Some("Probe cancelled".to_string());
+            } else if ctx.is_rate_limited(&account_id) {
+                stop_reason = Some("Account entered cooldown".to_string());
+            }
+        }
+        if idx > 0 && stop_reason.is_none() {
+            tokio::time::sleep(tokio::time::Duration::from_millis(PROBE_INTERVAL_MS)).await;
+        }
+        let permit = match stop_reason.is_none().then(|| ctx.try_acquire()) {
+            Some(Ok(permit)) => Some(permit),
+            Some(Err(reason)) => {
+                stop_reason = Some(reason);
+                None
+            }
+            None => None,
+        };
+        if let Some(reason) = &stop_reason {
+            results.push(ProbeResult {
+                model: model.clone(),
+                status: ProbeStatus::Skipped,
+                detail: Some(reason.clone()),
+            });
+            continue;
+        }
+
+        let Some(permit) = permit else { continue };
+
+        let body = V1InternalRequest::new(
+            &project_id,
+            model,
+            "agent",
+            json!({
+                "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+                "generationConfig": GenerationConfig::new().max_output_tokens(1).to_value(),
+            }),
+        )
+        .with_request_id_prefix("probe")
+        .into_value();
+
+        let result = match ctx.generate_content(&permit, &access_token, body).await {
+            Ok(resp) => {
+                let status = resp.status().as_u16();
+                if resp.status().is_success() {
+                    cache.record(email, model, ModelAccess::Accessible, AccessProvenance::Probed, None);
+                    ProbeResult { model: model.clone(), status: ProbeStatus::Accessible, detail: None }
+                } else {
+                    let text = resp.text().await.unwrap_or_default();
+                    let detail: String = format!("HTTP {}: {}", status, text).chars().take(200).collect();
+                    match status {
+                        403 | 404 => {
+                            cache.record(email, model, ModelAccess::Denied, AccessProvenance::Probed, Some(detail.clone()));
+                            ProbeResult { model: model.clone(), status: ProbeStatus::Denied, detail: Some(detail) }
+                        }
+                        429 => {
+                            // 维护流量只记录限流，不让账号进入冷却；停止后续探测避免继续消耗配额
+                            stop_reason = Some("Upstream rate limited the probe".to_string());
+                            ProbeResult { model: model.clone(), status: ProbeStatus::Skipped, detail: Some(detail) }
+                        }
+                        _ => ProbeResult { model: model.clone(), status: ProbeStatus::Error, detail: Some(detail) },
+                    }
+                }
+            }
+            Err(e) => ProbeResult { model: model.clone(), status: ProbeStatus::Error, detail: Some(e) },
+        };
+
+        tracing::info!("[Probe] {} / {} -> {:?}", email, model, result.status);
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_guard_is_exclusive() {
+        let cache = ModelAccessCache::new();
+        let guard = cache.begin_probe("a@example.com").unwrap();
+        assert_eq!(
+            cache.begin_probe("a@example.com").err(),
+            Some(ProbeError::AlreadyRunning { email: "a@example.com".to_string() })
+        );
+        assert!(cache.begin_probe("b@example.com").is_ok());
+
+        assert!(cache.cancel_probe("a@example.com"));
+        assert!(guard.is_cancelled());
+
+        drop(guard);
+        assert!(!cache.cancel_probe("a@example.com"));
+        assert!(cache.begin_probe("a@example.com").is_ok());
+    }
+
+    #[test]
+    fn test_record_and_learn() {
+        let cache = ModelAccessCache::new();
+        cache.record("a@example.com", "gemini-2.5-pro", ModelAccess::Accessible, AccessProvenance::Probed, None);
+        cache.learn_from_error("a@example.com", "claude-opus-4-5-thinking", 403, "PERMISSION_DENIED");
+        cache.learn_from_error("a@example.com", "gemini-2.5-flash", 500, "boom");
+
+        let entries = cache.entries_for("a@example.com");
+        assert_eq!(entries.len(), 2);
+        let denied = entries.iter().find(|e| e.model == "claude-opus-4-5-thinking").unwrap();
+        assert_eq!(denied.access, ModelAccess::Denied);
+        assert_eq!(denied.provenance, AccessProvenance::Learned);
+        assert!(cache.is_denied("a@example.com", "claude-opus-4-5-thinking"));
+        assert!(!cache.is_denied("a@example.com", "gemini-2.5-pro"));
+        assert!(!cache.is_denied("b@example.com", "claude-opus-4-5-thinking"));
+
+        // 探测到可访问后覆盖之前学习到的拒绝
+        cache.record("a@example.com", "claude-opus-4-5-thinking", ModelAccess::Accessible, AccessProvenance::Probed, None);
+        assert!(!cache.is_denied("a@example.com", "claude-opus-4-5-thinking"));
+    }
+}
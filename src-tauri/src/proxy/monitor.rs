@@ -18,6 +18,15 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// 转换后上游请求体的体积构成 (未经过 Prompt 体积预检的请求为空)
+    #[serde(default)]
+    pub prompt_size: Option<crate::proxy::common::prompt_size::PromptSizeBreakdown>,
+    /// 处理请求时的非致命转换警告 (无论兼容模式如何都会记录)
+    #[serde(default)]
+    pub warnings: Option<Vec<crate::proxy::common::warnings::ConversionWarning>>,
+    /// 实际请求的上游模型 (条件路由选中的变体，否则为映射后的模型；不受 report_model 影响)
+    #[serde(default)]
+    pub upstream_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -51,6 +60,18 @@ impl ProxyMonitor {
         }
     }
 
+    /// 不初始化数据库的监控实例 (测试用)
+    #[cfg(test)]
+    pub(crate) fn detached() -> Self {
+        Self {
+            logs: RwLock::new(VecDeque::new()),
+            stats: RwLock::new(ProxyStats::default()),
+            max_logs: 0,
+            enabled: AtomicBool::new(false),
+            app_handle: None,
+        }
+    }
+
     pub fn set_enabled(&self, enabled: bool) {
         self.enabled.store(enabled, Ordering::Relaxed);
     }
@@ -84,9 +105,9 @@ impl ProxyMonitor {
             logs.push_front(log.clone());
         }
 
-        // Save to DB
+        // Save to DB (阻塞线程池执行，维护期间等待写锁也不影响请求处理)
         let log_to_save = log.clone();
-        tokio::spawn(async move {
+        tokio::task::spawn_blocking(move || {
             if let Err(e) = crate::modules::proxy_db::save_log(&log_to_save) {
                 tracing::error!("Failed to save proxy log to DB: {}", e);
             }
@@ -50,16 +50,10 @@ fn build_client(
     upstream_proxy: Option<crate::proxy::config::UpstreamProxyConfig>,
     timeout_secs: u64,
 ) -> Result<reqwest::Client, String> {
-    let mut builder = reqwest::Client::builder()
+    let builder = reqwest::Client::builder()
         .timeout(Duration::from_secs(timeout_secs.max(5)));
-
-    if let Some(config) = upstream_proxy {
-        if config.enabled && !config.url.is_empty() {
-            let proxy = reqwest::Proxy::all(&config.url)
-                .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
-            builder = builder.proxy(proxy);
-        }
-    }
+    let builder = crate::utils::http::apply_upstream_proxy(builder, upstream_proxy.as_ref())
+        .map_err(|e| format!("Invalid upstream proxy: {}", e))?;
 
     builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
 }
@@ -132,7 +126,7 @@ pub async fn forward_anthropic_json(
         Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
     };
 
-    let timeout_secs = state.request_timeout.max(5);
+    let timeout_secs = state.request_timeout.read().await.max(5);
     let upstream_proxy = state.upstream_proxy.read().await.clone();
     let client = match build_client(Some(upstream_proxy), timeout_secs) {
         Ok(c) => c,
@@ -0,0 +1,670 @@
+// 账号配额消耗状态持久化 (quota_state.json)
+// 重启后保留最近错误时间、连续失败次数与估算剩余配额，
+// 避免重启后健康分归零、对已耗尽的账号再次集中触发 429
+// 同时维护每个账号当日的请求数 / Token 数，用于执行每日硬上限 (account_caps)
+// 以及当日的成功 / 失败 (按原因) / 输入输出 Token 统计，供 GET /stats 与桌面端用量表展示
+
+use futures::Stream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::config::{AccountCapsConfig, QuotaStateConfig};
+
+pub const QUOTA_STATE_FILE: &str = "quota_state.json";
+
+/// 每日计数使用的本地日期
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// 下一次每日计数清零的时间 (本地 0 点，Unix 秒)
+pub fn next_rollover() -> i64 {
+    let now = chrono::Local::now();
+    now.date_naive()
+        .succ_opt()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+        .map(|t| t.timestamp())
+        .unwrap_or(now.timestamp() + 86_400)
+}
+
+/// 账号达到的每日上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DailyCap {
+    Requests,
+    Tokens,
+}
+
+impl DailyCap {
+    fn label(self) -> &'static str {
+        match self {
+            DailyCap::Requests => "request",
+            DailyCap::Tokens => "token",
+        }
+    }
+}
+
+/// 单个账号 (按 email) 的配额消耗状态
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenQuotaState {
+    /// 最近一次上游错误的时间 (Unix 秒)
+    #[serde(default)]
+    pub last_error_at: Option<i64>,
+    /// 连续失败次数 (成功后清零)
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// 估算剩余配额：非流式成功扣 1，流式按上游分片数扣减，429 时归零
+    #[serde(default)]
+    pub estimated_quota_remaining: i64,
+    /// 当日计数所属日期 (本地 YYYY-MM-DD)，跨日后计数清零
+    #[serde(default)]
+    pub day: String,
+    /// 当日请求数 (账号被选中时计入)
+    #[serde(default)]
+    pub daily_requests: u64,
+    /// 当日消耗的 Token 数 (上游 usageMetadata.totalTokenCount)
+    #[serde(default)]
+    pub daily_tokens: u64,
+    /// 当日成功的请求数
+    #[serde(default)]
+    pub daily_successes: u64,
+    /// 当日失败的请求数 (按原因，见 failure_reason)
+    #[serde(default)]
+    pub daily_failures: BTreeMap<String, u64>,
+    /// 当日输入 Token 数 (promptTokenCount)
+    #[serde(default)]
+    pub daily_input_tokens: u64,
+    /// 当日输出 Token 数 (candidatesTokenCount + thoughtsTokenCount)
+    #[serde(default)]
+    pub daily_output_tokens: u64,
+    /// 最近一次被调度的时间 (Unix 秒，跨日保留)
+    #[serde(default)]
+    pub last_used_at: Option<i64>,
+}
+
+/// 失败统计使用的原因分类
+pub fn failure_reason(status: u16) -> &'static str {
+    match status {
+        429 => "rate_limited",
+        401 => "unauthorized",
+        403 => "forbidden",
+        500..=599 => "server_error",
+        400..=499 => "client_error",
+        _ => "other",
+    }
+}
+
+/// 单个账号的当日用量统计 (GET /stats)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountUsageStats {
+    pub email: String,
+    pub day: String,
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: BTreeMap<String, u64>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<i64>,
+}
+
+impl TokenQuotaState {
+    /// 最近错误仍在冷却窗口内时，启动时使用的健康分 (越多连续失败越低，最高 `ceiling`)
+    pub fn restored_health(&self, now: i64, cooldown_secs: u64, penalty: f64, ceiling: f64) -> Option<f64> {
+        let last_error = self.last_error_at?;
+        if self.consecutive_failures == 0 || now - last_error >= cooldown_secs as i64 {
+            return None;
+        }
+        Some((1.0 - penalty * f64::from(self.consecutive_failures)).clamp(0.0, ceiling))
+    }
+
+    fn roll_over(&mut self, today: &str) {
+        if self.day != today {
+            self.day = today.to_string();
+            self.reset_daily();
+        }
+    }
+
+    fn reset_daily(&mut self) {
+        self.daily_requests = 0;
+        self.daily_tokens = 0;
+        self.daily_successes = 0;
+        self.daily_failures.clear();
+        self.daily_input_tokens = 0;
+        self.daily_output_tokens = 0;
+    }
+
+    /// 按 `today` 给出的当日统计 (记录属于更早的日期时计数为 0)
+    fn usage_stats(&self, email: &str, today: &str) -> AccountUsageStats {
+        let current = self.day == today;
+        let count = |n: u64| if current { n } else { 0 };
+        AccountUsageStats {
+            email: email.to_string(),
+            day: today.to_string(),
+            requests: count(self.daily_requests),
+            successes: count(self.daily_successes),
+            failures: if current { self.daily_failures.clone() } else { BTreeMap::new() },
+            input_tokens: count(self.daily_input_tokens),
+            output_tokens: count(self.daily_output_tokens),
+            total_tokens: count(self.daily_tokens),
+            last_used_at: self.last_used_at,
+        }
+    }
+
+    /// 已达到的每日上限 (上限为 0 表示不限制)，调用前需已按当日 roll_over
+    fn cap_reached(&self, (request_cap, token_cap): (u64, u64)) -> Option<DailyCap> {
+        if token_cap > 0 && self.daily_tokens >= token_cap {
+            Some(DailyCap::Tokens)
+        } else if request_cap > 0 && self.daily_requests >= request_cap {
+            Some(DailyCap::Requests)
+        } else {
+            None
+        }
+    }
+}
+
+/// 内存中的配额状态表，由 TokenManager 在请求结果上更新、后台定期落盘
+pub struct QuotaStateStore {
+    path: Mutex<Option<PathBuf>>,
+    initial_estimate: AtomicI64,
+    states: Mutex<HashMap<String, TokenQuotaState>>,
+    dirty: AtomicBool,
+    caps: Mutex<AccountCapsConfig>,
+}
+
+impl QuotaStateStore {
+    pub fn new() -> Self {
+        Self {
+            path: Mutex::new(None),
+            initial_estimate: AtomicI64::new(QuotaStateConfig::default().initial_estimate),
+            states: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+            caps: Mutex::new(AccountCapsConfig::default()),
+        }
+    }
+
+    /// 应用配置；未指定路径时使用数据目录下的 quota_state.json
+    pub fn configure(&self, config: &QuotaStateConfig, data_dir: &Path) {
+        let path = config
+            .path
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| data_dir.join(QUOTA_STATE_FILE));
+        *self.path.lock().unwrap_or_else(|p| p.into_inner()) = Some(path);
+        self.initial_estimate.store(config.initial_estimate, Ordering::Relaxed);
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.path.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// 读取落盘状态 (文件不存在视为空)，返回加载的账号数
+    pub fn load(&self) -> Result<usize, String> {
+        let Some(path) = self.path() else {
+            return Ok(0);
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("读取 {:?} 失败: {}", path, e)),
+        };
+        let loaded: HashMap<String, TokenQuotaState> =
+            serde_json::from_str(&content).map_err(|e| format!("解析 {:?} 失败: {}", path, e))?;
+        let count = loaded.len();
+        *self.states.lock().unwrap_or_else(|p| p.into_inner()) = loaded;
+        self.dirty.store(false, Ordering::Relaxed);
+        Ok(count)
+    }
+
+    /// 有变更时写入文件 (先写临时文件再改名，避免中途退出留下半个文件)
+    pub fn flush(&self) -> Result<bool, String> {
+        let Some(path) = self.path() else {
+            return Ok(false);
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let content = {
+            let states = self.states.lock().unwrap_or_else(|p| p.into_inner());
+            serde_json::to_string_pretty(&*states).map_err(|e| e.to_string())?
+        };
+        let tmp = path.with_extension("json.tmp");
+        let result = std::fs::write(&tmp, content)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| format!("写入 {:?} 失败: {}", path, e));
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result.map(|_| true)
+    }
+
+    fn update<R>(&self, email: &str, f: impl FnOnce(&mut TokenQuotaState, i64) -> R) -> R {
+        let initial = self.initial_estimate.load(Ordering::Relaxed);
+        let mut states = self.states.lock().unwrap_or_else(|p| p.into_inner());
+        let state = states.entry(email.to_string()).or_insert_with(|| TokenQuotaState {
+            estimated_quota_remaining: initial,
+            ..Default::default()
+        });
+        let result = f(state, initial);
+        self.dirty.store(true, Ordering::Relaxed);
+        result
+    }
+
+    /// 应用每日上限配置 (启动与配置热更新时调用)
+    pub fn set_caps(&self, caps: &AccountCapsConfig) {
+        *self.caps.lock().unwrap_or_else(|p| p.into_inner()) = caps.clone();
+    }
+
+    fn caps_for(&self, email: &str) -> (u64, u64) {
+        self.caps.lock().unwrap_or_else(|p| p.into_inner()).caps_for(email)
+    }
+
+    /// 账号被选中时调用：在同一把锁内检查上限并计入请求数，并发请求不会超出请求上限
+    /// (Token 在响应结束后才计入，最多超出仍在进行中的请求的用量)
+    pub fn try_reserve(&self, email: &str, today: &str) -> Result<(), DailyCap> {
+        let caps = self.caps_for(email);
+        let reached = self.update(email, |state, _| {
+            state.roll_over(today);
+            if let Some(cap) = state.cap_reached(caps) {
+                return Err(cap);
+            }
+            state.daily_requests += 1;
+            state.last_used_at = Some(chrono::Utc::now().timestamp());
+            Ok(state.cap_reached(caps))
+        })?;
+        if let Some(cap) = reached {
+            announce_cap(email, cap, caps);
+        }
+        Ok(())
+    }
+
+    /// 计入上游返回的 Token 用量
+    pub fn record_tokens(&self, email: &str, tokens: u64, today: &str) {
+        if tokens == 0 {
+            return;
+        }
+        let caps = self.caps_for(email);
+        let crossed = self.update(email, |state, _| {
+            state.roll_over(today);
+            let before = state.cap_reached(caps);
+            state.daily_tokens = state.daily_tokens.saturating_add(tokens);
+            before.is_none().then(|| state.cap_reached(caps)).flatten()
+        });
+        if let Some(cap) = crossed {
+            announce_cap(email, cap, caps);
+        }
+    }
+
+    /// 计入输入 / 输出 Token (仅用于统计，每日上限按 totalTokenCount 计)
+    pub fn record_io_tokens(&self, email: &str, input: u64, output: u64, today: &str) {
+        if input == 0 && output == 0 {
+            return;
+        }
+        self.update(email, |state, _| {
+            state.roll_over(today);
+            state.daily_input_tokens = state.daily_input_tokens.saturating_add(input);
+            state.daily_output_tokens = state.daily_output_tokens.saturating_add(output);
+        });
+    }
+
+    /// 账号当日已达到的上限 (未达到时为 None)
+    pub fn capped(&self, email: &str, today: &str) -> Option<DailyCap> {
+        let caps = self.caps_for(email);
+        let states = self.states.lock().unwrap_or_else(|p| p.into_inner());
+        states
+            .get(email)
+            .filter(|s| s.day == today)
+            .and_then(|s| s.cap_reached(caps))
+    }
+
+    pub fn record_failure(&self, email: &str, status: u16, now: i64) {
+        self.update(email, |state, _| {
+            state.roll_over(&today());
+            *state.daily_failures.entry(failure_reason(status).to_string()).or_default() += 1;
+            state.last_error_at = Some(now);
+            state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+            if status == 429 {
+                state.estimated_quota_remaining = 0;
+            }
+        });
+    }
+
+    /// 请求成功：清零连续失败并扣减 1；估算已耗尽时说明配额已恢复，重置为初始估算值
+    pub fn record_success(&self, email: &str) {
+        self.update(email, |state, initial| {
+            state.roll_over(&today());
+            state.daily_successes += 1;
+            state.consecutive_failures = 0;
+            if state.estimated_quota_remaining <= 0 {
+                state.estimated_quota_remaining = initial;
+            }
+            state.estimated_quota_remaining -= 1;
+        });
+    }
+
+    /// 额外扣减 (流式响应按分片计)
+    pub fn record_usage(&self, email: &str, units: u64) {
+        if units == 0 {
+            return;
+        }
+        self.update(email, |state, _| {
+            state.estimated_quota_remaining = state
+                .estimated_quota_remaining
+                .saturating_sub(units as i64)
+                .max(0);
+        });
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, TokenQuotaState> {
+        self.states.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// 所有账号的当日用量统计 (按邮箱排序)
+    pub fn usage_stats(&self, today: &str) -> Vec<AccountUsageStats> {
+        let states = self.states.lock().unwrap_or_else(|p| p.into_inner());
+        let mut stats: Vec<_> = states.iter().map(|(email, s)| s.usage_stats(email, today)).collect();
+        stats.sort_by(|a, b| a.email.cmp(&b.email));
+        stats
+    }
+
+    /// 清零账号的当日统计 (含每日上限计数；配额估算与失败状态不变)，账号无记录时返回 false
+    pub fn reset_usage(&self, email: &str) -> bool {
+        let mut states = self.states.lock().unwrap_or_else(|p| p.into_inner());
+        let Some(state) = states.get_mut(email) else {
+            return false;
+        };
+        state.reset_daily();
+        self.dirty.store(true, Ordering::Relaxed);
+        true
+    }
+}
+
+impl Default for QuotaStateStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn announce_cap(email: &str, cap: DailyCap, (request_cap, token_cap): (u64, u64)) {
+    let limit = match cap {
+        DailyCap::Requests => request_cap,
+        DailyCap::Tokens => token_cap,
+    };
+    tracing::warn!(
+        "[AccountCaps] {} 达到每日{}上限 ({})，今日不再调度该账号",
+        email,
+        if cap == DailyCap::Requests { "请求" } else { " Token " },
+        limit
+    );
+    crate::modules::notifier::notify_account_capped(email, cap.label(), limit);
+}
+
+const TOTAL_TOKENS_KEY: &[u8] = b"\"totalTokenCount\":";
+const PROMPT_TOKENS_KEY: &[u8] = b"\"promptTokenCount\":";
+const CANDIDATES_TOKENS_KEY: &[u8] = b"\"candidatesTokenCount\":";
+const THOUGHTS_TOKENS_KEY: &[u8] = b"\"thoughtsTokenCount\":";
+/// 跨分片保留的尾部长度 (足以覆盖被切断的 key 与数值)
+const USAGE_CARRY_LEN: usize = 48;
+
+/// 片段中所有 totalTokenCount 的最大值 (流式分片中的用量为累计值)
+fn scan_total_tokens(buf: &[u8]) -> Option<u64> {
+    scan_counter(buf, TOTAL_TOKENS_KEY)
+}
+
+/// 片段中指定 usageMetadata 计数的最大值
+fn scan_counter(buf: &[u8], key: &[u8]) -> Option<u64> {
+    let mut max = None;
+    let mut rest = buf;
+    while let Some(pos) = rest.windows(key.len()).position(|w| w == key) {
+        rest = &rest[pos + key.len()..];
+        let value = rest.iter().skip_while(|b| b.is_ascii_whitespace());
+        let digits: String = value.take_while(|b| b.is_ascii_digit()).map(|&b| b as char).collect();
+        if let Ok(n) = digits.parse::<u64>() {
+            max = max.max(Some(n));
+        }
+    }
+    max
+}
+
+/// 流结束 (或被丢弃) 时按分片数扣减 (首个分片已由 record_success 计入)，
+/// 并计入分片中出现的 usageMetadata.totalTokenCount；输入 / 输出 Token 同时计入 Prometheus 指标
+struct ChunkMeter {
+    store: Arc<QuotaStateStore>,
+    email: String,
+    chunks: u64,
+    tokens: u64,
+    /// promptTokenCount / candidatesTokenCount / thoughtsTokenCount 的最大值
+    usage: [u64; 3],
+    carry: Vec<u8>,
+}
+
+impl ChunkMeter {
+    fn tick(&mut self, bytes: Option<&[u8]>) {
+        self.chunks += 1;
+        let Some(bytes) = bytes else {
+            return;
+        };
+        self.carry.extend_from_slice(bytes);
+        if let Some(total) = scan_total_tokens(&self.carry) {
+            self.tokens = self.tokens.max(total);
+        }
+        for (slot, key) in self
+            .usage
+            .iter_mut()
+            .zip([PROMPT_TOKENS_KEY, CANDIDATES_TOKENS_KEY, THOUGHTS_TOKENS_KEY])
+        {
+            if let Some(count) = scan_counter(&self.carry, key) {
+                *slot = (*slot).max(count);
+            }
+        }
+        let keep_from = self.carry.len().saturating_sub(USAGE_CARRY_LEN);
+        self.carry.drain(..keep_from);
+    }
+}
+
+impl Drop for ChunkMeter {
+    fn drop(&mut self) {
+        self.store.record_usage(&self.email, self.chunks.saturating_sub(1));
+        let today = today();
+        self.store.record_tokens(&self.email, self.tokens, &today);
+        let [prompt, candidates, thoughts] = self.usage;
+        self.store.record_io_tokens(&self.email, prompt, candidates + thoughts, &today);
+        crate::proxy::metrics::record_tokens(prompt, candidates + thoughts);
+    }
+}
+
+/// 为上游流式响应计量配额消耗
+pub fn metered<S, B, E>(store: Arc<QuotaStateStore>, email: &str, stream: S) -> impl Stream<Item = Result<B, E>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    let mut meter = ChunkMeter {
+        store,
+        email: email.to_string(),
+        chunks: 0,
+        tokens: 0,
+        usage: [0; 3],
+        carry: Vec::new(),
+    };
+    stream.inspect(move |item| meter.tick(item.as_ref().ok().map(|b| b.as_ref())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path) -> QuotaStateStore {
+        let store = QuotaStateStore::new();
+        store.configure(
+            &QuotaStateConfig {
+                path: None,
+                flush_interval_secs: 30,
+                initial_estimate: 100,
+            },
+            dir,
+        );
+        store
+    }
+
+    #[tokio::test]
+    async fn tracks_usage_and_round_trips_through_file() {
+        let dir = std::env::temp_dir().join(format!("quota-state-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = Arc::new(store(&dir));
+        first.record_success("a@example.com");
+        let chunks = futures::stream::iter((1..=4).map(|i| Ok::<_, ()>(vec![i as u8])));
+        let consumed: Vec<Result<Vec<u8>, ()>> = metered(first.clone(), "a@example.com", chunks).collect().await;
+        assert_eq!(consumed.len(), 4);
+        first.record_failure("b@example.com", 429, 1_000);
+        first.record_failure("b@example.com", 429, 1_010);
+        assert!(first.flush().unwrap());
+        assert!(!first.flush().unwrap(), "nothing changed since last flush");
+
+        let second = store(&dir);
+        assert_eq!(second.load().unwrap(), 2);
+        let states = second.snapshot();
+        // 1 次非流式 + 4 个分片 (首个分片已计入成功)
+        assert_eq!(states["a@example.com"].estimated_quota_remaining, 100 - 1 - 3);
+        assert_eq!(states["b@example.com"].consecutive_failures, 2);
+        assert_eq!(states["b@example.com"].estimated_quota_remaining, 0);
+
+        // 耗尽后再次成功：重置估算值
+        second.record_success("b@example.com");
+        assert_eq!(second.snapshot()["b@example.com"].estimated_quota_remaining, 99);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn restores_degraded_health_only_within_cooldown() {
+        let state = TokenQuotaState {
+            last_error_at: Some(1_000),
+            consecutive_failures: 1,
+            estimated_quota_remaining: 0,
+            ..Default::default()
+        };
+        assert_eq!(state.restored_health(1_100, 300, 0.25, 0.5), Some(0.5));
+        assert_eq!(state.restored_health(1_300, 300, 0.25, 0.5), None);
+        let worse = TokenQuotaState {
+            consecutive_failures: 3,
+            ..state.clone()
+        };
+        assert_eq!(worse.restored_health(1_100, 300, 0.25, 0.5), Some(0.25));
+        let recovered = TokenQuotaState {
+            consecutive_failures: 0,
+            ..state
+        };
+        assert_eq!(recovered.restored_health(1_100, 300, 0.25, 0.5), None);
+    }
+
+    fn capped_store(request_cap: u64, token_cap: u64) -> QuotaStateStore {
+        let store = QuotaStateStore::new();
+        store.set_caps(&AccountCapsConfig {
+            daily_request_cap: request_cap,
+            daily_token_cap: token_cap,
+            overrides: HashMap::from([(
+                "vip@example.com".to_string(),
+                crate::proxy::config::AccountCapOverride {
+                    daily_request_cap: Some(0),
+                    daily_token_cap: None,
+                },
+            )]),
+        });
+        store
+    }
+
+    #[test]
+    fn concurrent_burst_never_exceeds_request_cap() {
+        let store = Arc::new(capped_store(50, 0));
+        let granted = std::sync::atomic::AtomicU64::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        if store.try_reserve("a@example.com", "2026-01-01").is_ok() {
+                            granted.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(granted.load(Ordering::Relaxed), 50);
+        assert_eq!(store.capped("a@example.com", "2026-01-01"), Some(DailyCap::Requests));
+        assert_eq!(store.try_reserve("a@example.com", "2026-01-01"), Err(DailyCap::Requests));
+
+        // 跨日后恢复；覆盖为 0 的账号不受全局上限限制
+        assert_eq!(store.capped("a@example.com", "2026-01-02"), None);
+        assert!(store.try_reserve("a@example.com", "2026-01-02").is_ok());
+        for _ in 0..60 {
+            assert!(store.try_reserve("vip@example.com", "2026-01-01").is_ok());
+        }
+    }
+
+    #[test]
+    fn token_cap_overshoot_is_bounded_by_in_flight_requests() {
+        let store = Arc::new(capped_store(0, 1_000));
+        // 4 个请求在达到上限前同时获得调度
+        for _ in 0..4 {
+            store.try_reserve("a@example.com", "2026-01-01").unwrap();
+        }
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| store.record_tokens("a@example.com", 400, "2026-01-01"));
+            }
+        });
+        let state = store.snapshot()["a@example.com"].clone();
+        assert_eq!(state.daily_tokens, 1_600);
+        assert_eq!(store.try_reserve("a@example.com", "2026-01-01"), Err(DailyCap::Tokens));
+    }
+
+    #[test]
+    fn usage_stats_count_outcomes_and_reset_per_account() {
+        let store = QuotaStateStore::new();
+        let day = today();
+        store.try_reserve("a@example.com", &day).unwrap();
+        store.try_reserve("a@example.com", &day).unwrap();
+        store.record_success("a@example.com");
+        store.record_failure("a@example.com", 429, 1_000);
+        store.record_io_tokens("a@example.com", 120, 30, &day);
+        store.record_tokens("a@example.com", 150, &day);
+        store.record_success("b@example.com");
+
+        let stats = store.usage_stats(&day);
+        assert_eq!(stats.iter().map(|s| s.email.as_str()).collect::<Vec<_>>(), vec!["a@example.com", "b@example.com"]);
+        let a = &stats[0];
+        assert_eq!((a.requests, a.successes, a.input_tokens, a.output_tokens, a.total_tokens), (2, 1, 120, 30, 150));
+        assert_eq!(a.failures, BTreeMap::from([("rate_limited".to_string(), 1)]));
+        assert!(a.last_used_at.is_some());
+
+        // 其它日期的记录计为 0，重置只影响指定账号
+        assert_eq!(store.usage_stats("2000-01-01")[0].requests, 0);
+        assert!(store.reset_usage("a@example.com"));
+        assert!(!store.reset_usage("c@example.com"));
+        let stats = store.usage_stats(&day);
+        assert_eq!((stats[0].requests, stats[0].successes), (0, 0));
+        assert!(stats[0].failures.is_empty() && stats[0].last_used_at.is_some());
+        assert_eq!(stats[1].successes, 1);
+    }
+
+    #[tokio::test]
+    async fn metered_stream_counts_usage_tokens_split_across_chunks() {
+        let store = Arc::new(QuotaStateStore::new());
+        let chunks = vec![
+            Ok::<_, ()>(b"data: {\"usageMetadata\":{\"totalTokenCount\": 12}}\n\ndata: {\"usageMetadata\":{\"totalTo".to_vec()),
+            Ok(b"kenCount\":15".to_vec()),
+            Ok(b"3}}\n\n".to_vec()),
+        ];
+        let _: Vec<_> = metered(store.clone(), "a@example.com", futures::stream::iter(chunks)).collect().await;
+        assert_eq!(store.snapshot()["a@example.com"].daily_tokens, 153);
+        assert_eq!(scan_total_tokens(b"{\"totalTokenCount\":7},{\"totalTokenCount\":9}"), Some(9));
+    }
+}
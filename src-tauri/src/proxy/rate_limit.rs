@@ -1,7 +1,10 @@
 use dashmap::DashMap;
+use std::sync::RwLock;
 use std::time::{SystemTime, Duration};
 use regex::Regex;
 
+use crate::proxy::config::CooldownConfig;
+
 /// 限流信息
 #[derive(Debug, Clone)]
 pub struct RateLimitInfo {
@@ -18,14 +21,28 @@ pub struct RateLimitInfo {
 /// 限流跟踪器
 pub struct RateLimitTracker {
     limits: DashMap<String, RateLimitInfo>,
+    config: RwLock<CooldownConfig>,
 }
 
 impl RateLimitTracker {
     pub fn new() -> Self {
         Self {
             limits: DashMap::new(),
+            config: RwLock::new(CooldownConfig::default()),
+        }
+    }
+
+    /// 更新默认冷却时长
+    pub fn set_config(&self, config: CooldownConfig) {
+        if let Ok(mut guard) = self.config.write() {
+            *guard = config;
         }
     }
+
+    /// 当前冷却配置
+    pub fn config(&self) -> CooldownConfig {
+        self.config.read().map(|c| c.clone()).unwrap_or_default()
+    }
     
     /// 获取账号剩余的等待时间(秒)
     pub fn get_remaining_wait(&self, account_id: &str) -> u64 {
@@ -72,6 +89,7 @@ impl RateLimitTracker {
         }
         
         // 3. 处理默认值与软避让逻辑
+        let defaults = self.config.read().map(|c| c.clone()).unwrap_or_default();
         let retry_sec = match retry_after_sec {
             Some(s) => {
                 // 引入 PR #28 的安全缓冲区：最小 2 秒，防止极高频无效重试
@@ -79,12 +97,12 @@ impl RateLimitTracker {
             },
             None => {
                 if status == 429 {
-                    tracing::debug!("无法解析 429 限流时间, 使用默认值 60秒");
-                    60
+                    tracing::debug!("无法解析 429 限流时间, 使用默认值 {}秒", defaults.rate_limit_secs);
+                    defaults.rate_limit_secs
                 } else {
-                    // 对于 5xx 错误，执行“软避让”：默认锁定 20 秒，强制切换账号
-                    tracing::warn!("检测到 5xx 错误 ({}), 执行 20s 软避让...", status);
-                    20
+                    // 对于 5xx 错误，执行“软避让”：默认锁定一段时间，强制切换账号
+                    tracing::warn!("检测到 5xx 错误 ({}), 执行 {}s 软避让...", status, defaults.server_error_secs);
+                    defaults.server_error_secs
                 }
             }
         };
@@ -114,24 +132,17 @@ impl RateLimitTracker {
         let trimmed = body.trim();
         if trimmed.starts_with('{') || trimmed.starts_with('[') {
             if let Ok(json) = serde_json::from_str::<serde_json::Value>(trimmed) {
-                // 1. Google 常见的 quotaResetDelay 格式 (如 "75.5s" 或 "500ms")
-                if let Some(delay_str) = json.get("error")
+                // 1. Google 的 RetryInfo.retryDelay 或 ErrorInfo 中的 quotaResetDelay (如 "75.5s" 或 "500ms")
+                let details = json.get("error")
                     .and_then(|e| e.get("details"))
-                    .and_then(|d| d.as_array())
-                    .and_then(|a| a.get(0))
-                    .and_then(|o| o.get("quotaResetDelay"))
-                    .and_then(|v| v.as_str()) {
-                    
-                    if let Ok(re) = Regex::new(r"(\d+(?:\.\d+)?)(ms|s)") {
-                        if let Some(caps) = re.captures(delay_str) {
-                            let val = caps[1].parse::<f64>().unwrap_or(0.0);
-                            let unit = &caps[2];
-                            return if unit == "s" {
-                                Some(val.ceil() as u64)
-                            } else {
-                                Some((val / 1000.0).ceil() as u64)
-                            };
-                        }
+                    .and_then(|d| d.as_array());
+                for detail in details.into_iter().flatten() {
+                    let delay_str = detail.get("retryDelay")
+                        .or_else(|| detail.get("quotaResetDelay"))
+                        .or_else(|| detail.get("metadata").and_then(|m| m.get("quotaResetDelay")))
+                        .and_then(|v| v.as_str());
+                    if let Some(secs) = delay_str.and_then(parse_delay_secs) {
+                        return Some(secs);
                     }
                 }
                 
@@ -256,6 +267,18 @@ impl RateLimitTracker {
     }
 }
 
+/// 解析 protobuf Duration 风格的延迟 ("34.07s" / "500ms")，向上取整为秒
+fn parse_delay_secs(delay: &str) -> Option<u64> {
+    let re = Regex::new(r"(\d+(?:\.\d+)?)(ms|s)").ok()?;
+    let caps = re.captures(delay)?;
+    let val = caps[1].parse::<f64>().ok()?;
+    Some(if &caps[2] == "s" {
+        val.ceil() as u64
+    } else {
+        (val / 1000.0).ceil() as u64
+    })
+}
+
 impl Default for RateLimitTracker {
     fn default() -> Self {
         Self::new()
@@ -288,6 +311,37 @@ mod tests {
         assert_eq!(time, Some(42));
     }
 
+    #[test]
+    fn test_parse_google_retry_info() {
+        let tracker = RateLimitTracker::new();
+        let body = r#"{
+            "error": {
+                "code": 429,
+                "status": "RESOURCE_EXHAUSTED",
+                "details": [
+                    { "@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "RATE_LIMIT_EXCEEDED" },
+                    { "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "34.074824224s" }
+                ]
+            }
+        }"#;
+        assert_eq!(tracker.parse_retry_time_from_body(body), Some(35));
+    }
+
+    #[test]
+    fn test_configured_default_cooldown() {
+        let tracker = RateLimitTracker::new();
+        tracker.set_config(CooldownConfig {
+            rate_limit_secs: 300,
+            server_error_secs: 5,
+        });
+        tracker.parse_from_error("acc1", 429, None, "RESOURCE_EXHAUSTED");
+        let wait = tracker.get_remaining_wait("acc1");
+        assert!(wait > 295 && wait <= 300);
+
+        tracker.parse_from_error("acc2", 503, None, "");
+        assert!(tracker.get_remaining_wait("acc2") <= 5);
+    }
+
     #[test]
     fn test_parse_retry_after_ignore_case() {
         let tracker = RateLimitTracker::new();
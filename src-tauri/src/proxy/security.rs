@@ -1,10 +1,14 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use crate::proxy::config::{ProxyAuthMode, ProxyConfig, ScopedKeysConfig};
+use crate::proxy::key_scope::ModelScope;
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
+    /// 监听地址是否对本机以外开放 (allow_lan_access 或非回环 host)
     pub allow_lan_access: bool,
+    /// 附加 API Key (带模型白名单)
+    pub scoped_keys: ScopedKeysConfig,
 }
 
 impl ProxySecurityConfig {
@@ -12,10 +16,34 @@ impl ProxySecurityConfig {
         Self {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
-            allow_lan_access: config.allow_lan_access,
+            allow_lan_access: config.is_exposed(),
+            scoped_keys: config.scoped_keys.clone(),
         }
     }
 
+    /// 是否配置了任何 API Key
+    pub fn has_keys(&self) -> bool {
+        !self.api_key.is_empty() || !self.scoped_keys.keys.is_empty()
+    }
+
+    /// 主 Key 或任一附加 Key
+    pub fn is_known_key(&self, key: &str) -> bool {
+        (!self.api_key.is_empty() && key == self.api_key)
+            || self.scoped_keys.keys.iter().any(|k| !k.key.is_empty() && k.key == key)
+    }
+
+    /// 附加 Key 对应的模型白名单 (主 Key 不受限制)
+    pub fn scope_for_key(&self, key: &str) -> Option<ModelScope> {
+        if key == self.api_key {
+            return None;
+        }
+        self.scoped_keys
+            .keys
+            .iter()
+            .find(|k| !k.key.is_empty() && k.key == key)
+            .map(|k| ModelScope::from_key(k, self.scoped_keys.check_resolved_model))
+    }
+
     pub fn effective_auth_mode(&self) -> ProxyAuthMode {
         match self.auth_mode {
             ProxyAuthMode::Auto => {
@@ -40,6 +68,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             allow_lan_access: false,
+            scoped_keys: ScopedKeysConfig::default(),
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
@@ -50,6 +79,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             allow_lan_access: true,
+            scoped_keys: ScopedKeysConfig::default(),
         };
         assert!(matches!(
             s.effective_auth_mode(),
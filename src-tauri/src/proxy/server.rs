@@ -3,7 +3,7 @@ use axum::{
     extract::DefaultBodyLimit,
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{any, get, post},
+    routing::{any, delete, get, post},
     Router,
 };
 use std::sync::Arc;
@@ -20,10 +20,8 @@ pub struct AppState {
     pub anthropic_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     pub openai_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
-    #[allow(dead_code)]
-    pub request_timeout: u64, // API 请求超时(秒)
-    #[allow(dead_code)]
-    pub thought_signature_map: Arc<tokio::sync::Mutex<std::collections::HashMap<String, String>>>, // 思维链签名映射 (ID -> Signature)
+    pub request_timeout: Arc<RwLock<u64>>, // API 请求超时(秒，可热更新)
+    pub model_timeouts: Arc<tokio::sync::RwLock<std::collections::HashMap<String, u64>>>, // 按模型覆盖的请求超时 (可热更新)
     #[allow(dead_code)]
     pub upstream_proxy: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
@@ -31,6 +29,44 @@ pub struct AppState {
     pub provider_rr: Arc<AtomicUsize>,
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
+    pub backoff: Arc<RwLock<crate::proxy::config::BackoffConfig>>, // 重试退避配置 (可热更新)
+    pub retry_budget: Arc<crate::proxy::upstream::retry::RetryBudget>, // 全局重试预算 (所有处理器共享)
+    pub websocket: Arc<RwLock<crate::proxy::config::WebSocketConfig>>, // WebSocket 端点配置 (可热更新)
+    pub guard: Arc<crate::proxy::middleware::guard::AbuseGuard>, // 入站防护 (封禁列表供管理接口使用)
+    pub image_files: Arc<RwLock<crate::proxy::config::ImageFilesConfig>>, // 图像本地文件输出 (可热更新)
+    pub prompt_log: Arc<RwLock<crate::proxy::config::PromptLogMode>>, // 对话内容日志模式 (可热更新)
+    pub metrics: metrics_exporter_prometheus::PrometheusHandle, // Prometheus 指标渲染句柄
+    pub prompt_sizes: Arc<crate::proxy::metrics::PromptSizeStats>, // 按模型 / 天的 Prompt 体积汇总
+}
+
+#[cfg(test)]
+impl AppState {
+    /// 默认配置、空账号池的状态 (处理器测试用，不启动服务)
+    pub(crate) fn for_test() -> Self {
+        use crate::proxy::config;
+        Self {
+            token_manager: Arc::new(TokenManager::new(std::env::temp_dir())),
+            anthropic_mapping: Default::default(),
+            openai_mapping: Default::default(),
+            custom_mapping: Default::default(),
+            request_timeout: Arc::new(RwLock::new(120)),
+            model_timeouts: Default::default(),
+            upstream_proxy: Default::default(),
+            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(None)),
+            zai: Default::default(),
+            provider_rr: Default::default(),
+            zai_vision_mcp: Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new()),
+            monitor: Arc::new(crate::proxy::monitor::ProxyMonitor::detached()),
+            backoff: Arc::new(RwLock::new(config::BackoffConfig::default())),
+            retry_budget: Arc::new(crate::proxy::upstream::retry::RetryBudget::new(&config::RetryBudgetConfig::default())),
+            websocket: Default::default(),
+            guard: Arc::new(crate::proxy::middleware::guard::AbuseGuard::new(config::GuardConfig::default())),
+            image_files: Default::default(),
+            prompt_log: Default::default(),
+            metrics: crate::proxy::metrics::detached_handle(),
+            prompt_sizes: Default::default(),
+        }
+    }
 }
 
 /// Axum 服务器实例
@@ -39,9 +75,22 @@ pub struct AxumServer {
     anthropic_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     openai_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    model_timeouts: Arc<tokio::sync::RwLock<std::collections::HashMap<String, u64>>>,
+    request_timeout: Arc<RwLock<u64>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    backoff_state: Arc<RwLock<crate::proxy::config::BackoffConfig>>,
+    retry_budget: Arc<crate::proxy::upstream::retry::RetryBudget>,
+    websocket_state: Arc<RwLock<crate::proxy::config::WebSocketConfig>>,
+    image_files_state: Arc<RwLock<crate::proxy::config::ImageFilesConfig>>,
+    prompt_log_state: Arc<RwLock<crate::proxy::config::PromptLogMode>>,
+    rate_limiter: Arc<crate::proxy::middleware::rate_limit::InboundRateLimiter>,
+    guard: Arc<crate::proxy::middleware::guard::AbuseGuard>,
+    upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
+    local_addr: std::net::SocketAddr, // 实际监听地址 (端口为 0 时由系统分配)
+    app: Router, // 已构建的路由 (重新绑定端口时复用，共享状态不变)
+    in_flight: Arc<crate::proxy::middleware::in_flight::InFlightRequests>, // 在途请求计数 (停止时等待其归零)
 }
 
 impl AxumServer {
@@ -61,13 +110,37 @@ impl AxumServer {
         tracing::debug!("模型映射 (Anthropic/OpenAI/Custom) 已全量热更新");
     }
 
+    /// 获取共享的上游客户端 (供 Tauri 命令复用连接池)
+    pub fn upstream(&self) -> Arc<crate::proxy::upstream::client::UpstreamClient> {
+        self.upstream.clone()
+    }
+
+    /// 实际监听地址
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
     /// 更新代理配置
     pub async fn update_proxy(&self, new_config: crate::proxy::config::UpstreamProxyConfig) {
         let mut proxy = self.proxy_state.write().await;
+        // 代理变更时重建共享上游客户端，使新代理对后续请求立即生效；未变更时保留连接池
+        if *proxy != new_config {
+            self.upstream.rebuild(Some(new_config.clone()));
+        }
         *proxy = new_config;
         tracing::info!("上游代理配置已热更新");
     }
 
+    /// 热更新请求超时与上游代理 (新请求立即生效，在途请求继续使用原客户端)
+    pub async fn update_runtime_config(&self, config: &crate::proxy::config::ProxyConfig) {
+        {
+            let mut timeout = self.request_timeout.write().await;
+            *timeout = config.request_timeout;
+        }
+        self.update_proxy(config.upstream_proxy.clone()).await;
+        tracing::debug!("请求超时与上游代理配置已热更新");
+    }
+
     pub async fn update_security(&self, config: &crate::proxy::config::ProxyConfig) {
         let mut sec = self.security_state.write().await;
         *sec = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
@@ -79,6 +152,62 @@ impl AxumServer {
         *zai = config.zai.clone();
         tracing::info!("z.ai 配置已热更新");
     }
+
+    pub async fn update_backoff(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut backoff = self.backoff_state.write().await;
+        *backoff = config.backoff.clone();
+        tracing::info!("重试退避配置已热更新");
+    }
+
+    pub fn update_retry_budget(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.retry_budget.configure(&config.retry_budget);
+        tracing::info!("重试预算配置已热更新 (当前余额 {})", self.retry_budget.balance());
+    }
+
+    pub async fn update_websocket(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut ws = self.websocket_state.write().await;
+        *ws = config.websocket.clone();
+        tracing::info!("WebSocket 端点配置已热更新");
+    }
+
+    pub async fn update_image_files(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut files = self.image_files_state.write().await;
+        *files = config.image_files.clone();
+        tracing::info!("图像文件输出配置已热更新");
+    }
+
+    pub async fn update_prompt_log(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut mode = self.prompt_log_state.write().await;
+        *mode = config.prompt_log_mode;
+        tracing::info!("对话内容日志模式已热更新");
+    }
+
+    pub async fn update_model_timeouts(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut timeouts = self.model_timeouts.write().await;
+        *timeouts = config.model_timeouts.clone();
+        tracing::info!("按模型请求超时配置已热更新");
+    }
+
+    pub fn update_rate_limit(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.rate_limiter.update_config(config.rate_limit.clone());
+        tracing::info!("入站限流配置已热更新");
+    }
+
+    pub fn update_guard(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.guard.update_config(config.guard.clone());
+        tracing::info!("入站防护配置已热更新");
+    }
+
+    /// 上游 TLS 配置变更后按当前代理重建共享上游客户端
+    pub async fn rebuild_upstream(&self) {
+        let proxy = self.proxy_state.read().await.clone();
+        self.upstream.rebuild(Some(proxy));
+        tracing::info!("上游 TLS 配置已热更新");
+    }
+
+    pub fn update_telemetry(&self, config: &crate::proxy::config::ProxyConfig) {
+        crate::proxy::telemetry::configure(&config.telemetry);
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
@@ -87,40 +216,72 @@ impl AxumServer {
         anthropic_mapping: std::collections::HashMap<String, String>,
         openai_mapping: std::collections::HashMap<String, String>,
         custom_mapping: std::collections::HashMap<String, String>,
-        _request_timeout: u64,
+        request_timeout: u64,
+        model_timeouts: std::collections::HashMap<String, u64>,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
-
+        backoff_config: crate::proxy::config::BackoffConfig,
+        retry_budget_config: crate::proxy::config::RetryBudgetConfig,
+        rate_limit_config: crate::proxy::config::RateLimitConfig,
+        websocket_config: crate::proxy::config::WebSocketConfig,
+        guard_config: crate::proxy::config::GuardConfig,
+        image_files_config: crate::proxy::config::ImageFilesConfig,
+        prompt_log_mode: crate::proxy::config::PromptLogMode,
+        telemetry_config: crate::proxy::config::TelemetryConfig,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
+        // 链路追踪按配置启用，未启用时不创建 span 也不启动导出任务
+        crate::proxy::telemetry::configure(&telemetry_config);
+
         let mapping_state = Arc::new(tokio::sync::RwLock::new(anthropic_mapping));
         let openai_mapping_state = Arc::new(tokio::sync::RwLock::new(openai_mapping));
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
+        let model_timeouts_state = Arc::new(tokio::sync::RwLock::new(model_timeouts));
+        let request_timeout_state = Arc::new(RwLock::new(request_timeout));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
 	        let zai_state = Arc::new(RwLock::new(zai_config));
+	        let backoff_state = Arc::new(RwLock::new(backoff_config));
+	        let retry_budget = Arc::new(crate::proxy::upstream::retry::RetryBudget::new(&retry_budget_config));
+	        let websocket_state = Arc::new(RwLock::new(websocket_config));
+	        let image_files_state = Arc::new(RwLock::new(image_files_config));
+        let prompt_log_state = Arc::new(RwLock::new(prompt_log_mode));
+	        let rate_limiter = Arc::new(
+	            crate::proxy::middleware::rate_limit::InboundRateLimiter::new(rate_limit_config),
+	        );
+	        let guard = Arc::new(crate::proxy::middleware::guard::AbuseGuard::new(guard_config));
 	        let provider_rr = Arc::new(AtomicUsize::new(0));
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
+	        let upstream = Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
+	            upstream_proxy.clone(),
+	        )));
+
+	        let in_flight = Arc::new(crate::proxy::middleware::in_flight::InFlightRequests::default());
+        let metrics_handle = crate::proxy::metrics::install_recorder();
 
-	        let state = AppState {
+        let state = AppState {
 	            token_manager: token_manager.clone(),
 	            anthropic_mapping: mapping_state.clone(),
 	            openai_mapping: openai_mapping_state.clone(),
 	            custom_mapping: custom_mapping_state.clone(),
-	            request_timeout: 300, // 5分钟超时
-            thought_signature_map: Arc::new(tokio::sync::Mutex::new(
-                std::collections::HashMap::new(),
-            )),
+	            request_timeout: request_timeout_state.clone(),
+            model_timeouts: model_timeouts_state.clone(),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: upstream.clone(),
             zai: zai_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
+            backoff: backoff_state.clone(),
+            retry_budget: retry_budget.clone(),
+            websocket: websocket_state.clone(),
+            guard: guard.clone(),
+            image_files: image_files_state.clone(),
+            prompt_log: prompt_log_state.clone(),
+            metrics: metrics_handle,
+            prompt_sizes: Default::default(),
         };
 
 
@@ -130,6 +291,7 @@ impl AxumServer {
         let app = Router::new()
             // OpenAI Protocol
             .route("/v1/models", get(handlers::openai::handle_list_models))
+            .route("/v1/models/:model_id", get(handlers::openai::handle_get_model))
             .route(
                 "/v1/chat/completions",
                 post(handlers::openai::handle_chat_completions),
@@ -139,6 +301,14 @@ impl AxumServer {
                 post(handlers::openai::handle_completions),
             )
             .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
+            .route(
+                "/v1/embeddings",
+                post(handlers::openai::handle_embeddings),
+            )
+            .route(
+                "/v1/count_tokens",
+                post(handlers::openai::handle_count_tokens),
+            )
             .route(
                 "/v1/images/generations",
                 post(handlers::openai::handle_images_generations),
@@ -147,16 +317,30 @@ impl AxumServer {
                 "/v1/images/edits",
                 post(handlers::openai::handle_images_edits),
             ) // 图像编辑 API
+            .route(
+                "/v1/images/files/:name",
+                get(handlers::openai::handle_image_file),
+            ) // 本地文件模式的图像输出
             // Claude Protocol
             .route("/v1/messages", post(handlers::claude::handle_messages))
             .route(
                 "/v1/messages/count_tokens",
                 post(handlers::claude::handle_count_tokens),
             )
+            .route(
+                "/v1/message_batches",
+                post(handlers::claude::handle_message_batches),
+            )
             .route(
                 "/v1/models/claude",
                 get(handlers::claude::handle_list_models),
             )
+            // WebSocket 流式对话 (可选，默认关闭)
+            .route("/v1/ws", get(handlers::ws::handle_ws))
+            .route(
+                "/v1/ws/chat/completions",
+                get(handlers::ws::handle_ws_chat_completions),
+            )
             // z.ai MCP (optional reverse-proxy)
             .route(
                 "/mcp/web_search_prime/mcp",
@@ -182,60 +366,160 @@ impl AxumServer {
                 post(handlers::gemini::handle_count_tokens),
             ) // Specific route priority
             .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+            .route("/v1/internal/tokens", get(handlers::admin::handle_list_tokens))
+            .route(
+                "/admin/accounts/conflicts",
+                get(handlers::admin::handle_account_conflicts),
+            )
+            .route(
+                "/admin/accounts/:email/probe-models",
+                post(handlers::admin::handle_probe_models),
+            )
+            .route(
+                "/admin/accounts/:email/rotate-session",
+                post(handlers::admin::handle_rotate_session),
+            )
+            .route(
+                "/admin/bans",
+                get(handlers::admin::handle_list_bans).delete(handlers::admin::handle_clear_bans),
+            )
+            .route("/admin/bans/:ip", delete(handlers::admin::handle_unban))
+            .route("/admin/maintenance", get(handlers::admin::handle_maintenance_stats))
+            .route("/admin/health", get(handlers::admin::handle_token_health))
+            .route("/admin/accounts/health", get(handlers::admin::handle_account_health))
+            .route("/debug/compat-report", post(handlers::admin::handle_compat_report))
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
+            .route("/metrics", get(crate::proxy::metrics::metrics_handler))
+            .route("/stats", get(handlers::admin::handle_usage_stats))
+            .route("/stats/:email", delete(handlers::admin::handle_reset_usage_stats))
+            .route("/stats/prompt-sizes", get(crate::proxy::metrics::prompt_size_stats_handler))
+            .route_layer(axum::middleware::from_fn(crate::proxy::common::model_routes::model_route_middleware))
+            .route_layer(axum::middleware::from_fn(crate::proxy::middleware::warnings::warnings_middleware))
+            .route_layer(axum::middleware::from_fn(crate::proxy::account_concurrency::account_permit_middleware))
+            .route_layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::metrics::metrics_middleware))
+            .route_layer(axum::middleware::from_fn(crate::proxy::middleware::response_model::response_model_middleware))
             .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+            .layer(axum::middleware::from_fn(crate::proxy::middleware::logging::logging_middleware))
+            .layer(axum::middleware::from_fn_with_state(
+                token_manager.maintenance().clone(),
+                crate::proxy::maintenance::user_load_middleware,
+            ))
             .layer(TraceLayer::new_for_http())
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
                 crate::proxy::middleware::auth_middleware,
             ))
+            .layer(axum::middleware::from_fn_with_state(
+                crate::proxy::middleware::rate_limit::RateLimitState {
+                    limiter: rate_limiter.clone(),
+                    security: security_state.clone(),
+                },
+                crate::proxy::middleware::rate_limit::rate_limit_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                guard.clone(),
+                crate::proxy::middleware::guard::guard_middleware,
+            ))
+            .layer(axum::middleware::from_fn(
+                crate::proxy::middleware::request_id_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                in_flight.clone(),
+                crate::proxy::middleware::in_flight::in_flight_middleware,
+            ))
             .layer(crate::proxy::middleware::cors_layer())
             .with_state(state);
 
-        // 绑定地址
-        let addr = format!("{}:{}", host, port);
-        let listener = tokio::net::TcpListener::bind(&addr)
-            .await
-            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
-
-        tracing::info!("反代服务器启动在 http://{}", addr);
+        let (listener, local_addr) = Self::bind(&host, port).await?;
+        tracing::info!("反代服务器启动在 http://{}", local_addr);
 
         // 创建关闭通道
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = Self::serve(listener, app.clone(), shutdown_rx);
 
         let server_instance = Self {
             shutdown_tx: Some(shutdown_tx),
             anthropic_mapping: mapping_state.clone(),
             openai_mapping: openai_mapping_state.clone(),
             custom_mapping: custom_mapping_state.clone(),
+            model_timeouts: model_timeouts_state,
+            request_timeout: request_timeout_state,
             proxy_state,
             security_state,
             zai_state,
+            backoff_state,
+            retry_budget,
+            websocket_state,
+            image_files_state,
+            prompt_log_state,
+            rate_limiter,
+            guard,
+            upstream,
+            local_addr,
+            app,
+            in_flight,
         };
 
-        // 在新任务中启动服务器
-        let handle = tokio::spawn(async move {
+        Ok((server_instance, handle))
+    }
+
+    /// 绑定监听地址
+    async fn bind(host: &str, port: u16) -> Result<(tokio::net::TcpListener, std::net::SocketAddr), String> {
+        let addr = format!("{}:{}", host, port);
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| format!("获取监听地址失败: {}", e))?;
+        Ok((listener, local_addr))
+    }
+
+    /// 在新任务中接收连接，收到关闭信号后停止监听，并通知已建立的连接处理完当前请求后关闭
+    fn serve(
+        listener: tokio::net::TcpListener,
+        app: Router,
+        mut shutdown_rx: oneshot::Receiver<()>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
             use hyper::server::conn::http1;
             use hyper_util::rt::TokioIo;
             use hyper_util::service::TowerToHyperService;
+            use tower::Service;
+
+            // 注入 ConnectInfo<SocketAddr>，供限流中间件获取客户端 IP
+            let mut make_service = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+            let (draining_tx, draining_rx) = tokio::sync::watch::channel(false);
 
             loop {
                 tokio::select! {
                     res = listener.accept() => {
                         match res {
-                            Ok((stream, _)) => {
+                            Ok((stream, remote_addr)) => {
                                 let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
+                                let router = match make_service.call(remote_addr).await {
+                                    Ok(router) => router,
+                                    Err(never) => match never {},
+                                };
+                                let service = TowerToHyperService::new(router);
+                                let mut draining = draining_rx.clone();
 
                                 tokio::task::spawn(async move {
-                                    if let Err(err) = http1::Builder::new()
+                                    let conn = http1::Builder::new()
                                         .serve_connection(io, service)
-                                        .with_upgrades() // 支持 WebSocket (如果以后需要)
-                                        .await
-                                    {
+                                        .with_upgrades(); // 支持 WebSocket (/v1/ws)
+                                    tokio::pin!(conn);
+                                    let result = loop {
+                                        tokio::select! {
+                                            res = conn.as_mut() => break res,
+                                            // 停止监听后不再接受该连接上的新请求 (keep-alive)，当前请求继续完成
+                                            _ = draining.wait_for(|d| *d) => conn.as_mut().graceful_shutdown(),
+                                        }
+                                    };
+                                    if let Err(err) = result {
                                         debug!("连接处理结束或出错: {:?}", err);
                                     }
                                 });
@@ -251,16 +535,42 @@ impl AxumServer {
                     }
                 }
             }
-        });
+            let _ = draining_tx.send(true);
+        })
+    }
 
-        Ok((server_instance, handle))
+    /// 订阅在途请求数变化 (停止服务时供前端展示等待进度)
+    pub fn subscribe_in_flight(&self) -> tokio::sync::watch::Receiver<usize> {
+        self.in_flight.subscribe()
+    }
+
+    /// 在新的地址上重新监听，共享状态 (映射、账号池、签名缓存等) 保持不变
+    /// 新地址绑定失败时继续使用原地址；旧地址上的连接处理完当前请求后关闭。返回新的服务任务
+    pub async fn restart(&mut self, new_port: u16, new_host: &str) -> Result<tokio::task::JoinHandle<()>, String> {
+        let (listener, local_addr) = Self::bind(new_host, new_port).await?;
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = Self::serve(listener, self.app.clone(), shutdown_rx);
+        tracing::info!("反代服务器从 {} 切换到 http://{}", self.local_addr, local_addr);
+        self.shutdown_tx = Some(shutdown_tx);
+        self.local_addr = local_addr;
+        Ok(handle)
     }
 
-    /// 停止服务器
-    pub fn stop(mut self) {
+    /// 停止监听并等待在途请求 (含流式响应) 完成，超时后不再等待
+    /// 返回超时时仍未完成的请求数 (全部完成时为 0)
+    pub async fn stop_and_wait(mut self, timeout: std::time::Duration) -> usize {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
+        let remaining = self.in_flight.wait_drained(timeout).await;
+        if remaining > 0 {
+            tracing::warn!("等待在途请求超时，仍有 {} 个请求未完成", remaining);
+        }
+        crate::proxy::telemetry::shutdown();
+        remaining
     }
 }
 
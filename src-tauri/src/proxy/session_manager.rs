@@ -6,7 +6,24 @@ use serde_json::Value;
 /// 会话管理器工具
 pub struct SessionManager;
 
+/// 客户端显式指定会话的请求头
+pub const SESSION_KEY_HEADER: &str = "x-session-key";
+
+/// 会话 key 的最大长度，超出时不采用
+const MAX_SESSION_KEY_LEN: usize = 256;
+
 impl SessionManager {
+    /// 读取客户端通过 X-Session-Key 显式指定的会话，优先于内容指纹
+    /// 加 key- 前缀，避免与 sid- 指纹或 metadata.user_id 冲突
+    pub fn session_key_from_headers(headers: &axum::http::HeaderMap) -> Option<String> {
+        headers
+            .get(SESSION_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|k| !k.is_empty() && k.len() <= MAX_SESSION_KEY_LEN)
+            .map(|k| format!("key-{}", k))
+    }
+
     /// 根据 Claude 请求生成稳定的会话指纹 (Session Fingerprint)
     pub fn extract_session_id(request: &ClaudeRequest) -> String {
         // 1. 优先使用 metadata 中的 user_id
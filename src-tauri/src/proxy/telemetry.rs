@@ -0,0 +1,317 @@
+// OpenTelemetry 链路追踪
+// - handler 根 span `proxy.handle_request` (model / stream / attempt)，上游调用子 span `gemini.request`
+//   (upstream_model / project_id)，失败时通过 otel.status_message 标记错误
+// - 日志初始化时装入 tracing-opentelemetry 层 (只采集上述 span 与 WARN 以上事件)；
+//   OTLP/HTTP JSON 批量导出器由 AxumServer::start 按配置创建，热更新时整体替换
+// - 未启用时不创建 span，也不存在导出器
+// - 入站请求带 W3C traceparent 头时沿用其 trace id，便于与调用方的链路拼接
+
+use axum::http::HeaderMap;
+use axum::response::Response;
+use once_cell::sync::Lazy;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{TraceResult, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{Protocol, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{BatchSpanProcessor, Span as SdkSpan, SpanProcessor, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{Instrument, Level, Metadata, Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::proxy::config::TelemetryConfig;
+
+/// handler 根 span 名称
+pub const REQUEST_SPAN: &str = "proxy.handle_request";
+/// 上游调用 span 名称
+pub const UPSTREAM_SPAN: &str = "gemini.request";
+
+type Exporter = BatchSpanProcessor<runtime::Tokio>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// 全局采集层结束 span 后交给此处的导出器；未启用时为 None，span 直接丢弃
+static EXPORTER: Lazy<Arc<RwLock<Option<Exporter>>>> = Lazy::new(|| Arc::new(RwLock::new(None)));
+
+/// 是否正在采集追踪数据
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 应用追踪配置 (AxumServer 启动与热更新共用，需在 tokio 运行时内调用)
+/// 每次调用都会替换导出器，旧导出器在后台线程中发出剩余 span 后关闭
+pub fn configure(config: &TelemetryConfig) {
+    let exporter = if config.enabled && !config.endpoint.trim().is_empty() {
+        match build_exporter(config) {
+            Ok(exporter) => Some(exporter),
+            Err(e) => {
+                tracing::warn!("OTLP 导出器创建失败，链路追踪保持关闭: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let enabled = exporter.is_some();
+    let previous = std::mem::replace(&mut *EXPORTER.write().unwrap(), exporter);
+    let was_enabled = ENABLED.swap(enabled, Ordering::SeqCst);
+    if let Some(previous) = previous {
+        shutdown_exporter(previous);
+    }
+
+    if enabled {
+        if !was_enabled {
+            tracing::info!("OTLP 链路追踪已启用: {}", config.endpoint);
+        }
+    } else if was_enabled {
+        tracing::info!("OTLP 链路追踪已关闭");
+    }
+}
+
+/// 停止采集 (反代服务停止时调用)，剩余 span 由导出器在关闭前发出
+pub fn shutdown() {
+    ENABLED.store(false, Ordering::SeqCst);
+    if let Some(previous) = EXPORTER.write().unwrap().take() {
+        shutdown_exporter(previous);
+    }
+}
+
+fn build_exporter(config: &TelemetryConfig) -> Result<Exporter, String> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(config.endpoint.trim())
+        .with_headers(config.headers.clone())
+        .with_timeout(std::time::Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut processor = BatchSpanProcessor::builder(exporter, runtime::Tokio).build();
+    processor.set_resource(&Resource::new([KeyValue::new("service.name", config.service_name.clone())]));
+    Ok(processor)
+}
+
+/// BatchSpanProcessor::shutdown 会阻塞等待导出完成，放到独立线程避免占用运行时
+fn shutdown_exporter(exporter: Exporter) {
+    std::thread::spawn(move || {
+        if let Err(e) = exporter.shutdown() {
+            tracing::debug!("OTLP 导出器关闭失败: {}", e);
+        }
+    });
+}
+
+/// 把结束的 span 转交给当前导出器 (全局 TracerProvider 的唯一处理器)
+#[derive(Debug)]
+struct ExporterSlot(Arc<RwLock<Option<Exporter>>>);
+
+impl SpanProcessor for ExporterSlot {
+    fn on_start(&self, _span: &mut SdkSpan, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        if let Some(exporter) = self.0.read().unwrap().as_ref() {
+            exporter.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        match self.0.read().unwrap().as_ref() {
+            Some(exporter) => exporter.force_flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        Ok(())
+    }
+}
+
+// ===== span 创建 =====
+
+/// 创建 handler 根 span；未启用时返回 `Span::none()`
+pub fn request_span(protocol: &'static str, model: &str, stream: bool, headers: &HeaderMap) -> Span {
+    if !is_enabled() {
+        return Span::none();
+    }
+    new_request_span(protocol, model, stream, headers)
+}
+
+fn new_request_span(protocol: &'static str, model: &str, stream: bool, headers: &HeaderMap) -> Span {
+    let request_id = crate::proxy::middleware::current_request_id().unwrap_or_default();
+    let span = tracing::info_span!(
+        "proxy.handle_request",
+        otel.kind = "server",
+        protocol,
+        model,
+        stream,
+        request_id = request_id.as_str(),
+        attempt = tracing::field::Empty,
+        http.status_code = tracing::field::Empty,
+        otel.status_message = tracing::field::Empty,
+    );
+    if headers.contains_key("traceparent") {
+        let remote = TraceContextPropagator::new().extract(&opentelemetry_http::HeaderExtractor(headers));
+        span.set_parent(remote);
+    }
+    span
+}
+
+/// 在根 span 下运行 handler，并记录最终状态码 (5xx 标记为错误)
+pub async fn instrument_handler<F>(span: Span, handler: F) -> Response
+where
+    F: Future<Output = Response>,
+{
+    let response = handler.instrument(span.clone()).await;
+    let status = response.status();
+    span.record("http.status_code", status.as_u16() as i64);
+    if status.is_server_error() {
+        span.record("otel.status_message", format!("HTTP {}", status.as_u16()).as_str());
+    }
+    response
+}
+
+/// 在当前根 span 上记录重试序号 (tracing-opentelemetry 只把 i64 映射为整数属性)
+pub fn record_attempt(attempt: usize) {
+    Span::current().record("attempt", attempt as i64);
+}
+
+/// 创建上游调用 span (model / project 取自 v1internal 包装后的请求体)
+pub fn upstream_span(method: &str, body: &Value) -> Span {
+    if !is_enabled() {
+        return Span::none();
+    }
+    new_upstream_span(method, body)
+}
+
+fn new_upstream_span(method: &str, body: &Value) -> Span {
+    let upstream_model = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
+    let project_id = body.get("project").and_then(|v| v.as_str()).unwrap_or("");
+    tracing::info_span!(
+        "gemini.request",
+        otel.kind = "client",
+        method,
+        upstream_model,
+        project_id,
+        http.status_code = tracing::field::Empty,
+        otel.status_message = tracing::field::Empty,
+    )
+}
+
+/// 记录上游调用结果：非 2xx 或请求失败时标记为错误
+pub fn record_upstream_result(span: &Span, result: &Result<reqwest::Response, String>) {
+    match result {
+        Ok(resp) => {
+            let status = resp.status();
+            span.record("http.status_code", status.as_u16() as i64);
+            if !status.is_success() {
+                span.record("otel.status_message", format!("HTTP {}", status.as_u16()).as_str());
+            }
+        }
+        Err(e) => {
+            span.record("otel.status_message", e.as_str());
+        }
+    }
+}
+
+// ===== 采集层 =====
+
+fn is_tracked(meta: &Metadata<'_>) -> bool {
+    if meta.is_span() {
+        meta.name() == REQUEST_SPAN || meta.name() == UPSTREAM_SPAN
+    } else {
+        // 只把 WARN / ERROR 事件挂到 span 上
+        *meta.level() <= Level::WARN
+    }
+}
+
+fn otel_layer<S>(provider: &TracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("antigravity-proxy"))
+        .with_filter(filter_fn(is_tracked))
+}
+
+/// 全局订阅器使用的采集层 (导出器由 configure 装入，未启用时不产生 span)
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let provider = TracerProvider::builder()
+        .with_span_processor(ExporterSlot(EXPORTER.clone()))
+        .build();
+    otel_layer(&provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use opentelemetry::trace::{SpanKind, Status};
+    use opentelemetry_sdk::export::trace::{ExportResult, SpanExporter};
+    use std::sync::Mutex;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Debug, Clone, Default)]
+    struct CollectingExporter(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for CollectingExporter {
+        fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[test]
+    fn test_layer_exports_span_tree() {
+        let exporter = CollectingExporter::default();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(otel_layer(&provider));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let root = new_request_span("claude", "claude-sonnet-4-5", true, &headers);
+            root.in_scope(|| {
+                record_attempt(1);
+                let child = new_upstream_span(
+                    "streamGenerateContent",
+                    &serde_json::json!({"model": "claude-sonnet-4-5", "project": "proj-1"}),
+                );
+                child.in_scope(|| tracing::warn!("upstream request failed"));
+                record_upstream_result(&child, &Err("connection reset".to_string()));
+                // 未追踪的 span 与 INFO 事件不被采集
+                tracing::info_span!("other").in_scope(|| tracing::info!("ignored"));
+            });
+        });
+
+        let spans = exporter.0.lock().unwrap().clone();
+        assert_eq!(spans.len(), 2);
+        let child = spans.iter().find(|s| s.name == UPSTREAM_SPAN).unwrap();
+        let root = spans.iter().find(|s| s.name == REQUEST_SPAN).unwrap();
+
+        assert_eq!(
+            root.span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(root.parent_span_id.to_string(), "00f067aa0ba902b7");
+        assert_eq!(child.span_context.trace_id(), root.span_context.trace_id());
+        assert_eq!(child.parent_span_id, root.span_context.span_id());
+        assert_eq!((root.span_kind.clone(), child.span_kind.clone()), (SpanKind::Server, SpanKind::Client));
+        assert!(root.attributes.contains(&KeyValue::new("attempt", 1i64)));
+        assert!(child.attributes.contains(&KeyValue::new("project_id", "proj-1")));
+        assert_eq!(root.status, Status::Unset);
+        assert_eq!(child.status, Status::error("connection reset"));
+        assert!(child.events.iter().any(|e| e.name == "upstream request failed"));
+    }
+}
@@ -2,12 +2,30 @@
 use dashmap::DashMap;
 use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
+
+use crate::proxy::account_concurrency::{self, AccountConcurrency};
+use crate::proxy::account_health::{self, AccountHealthChecker};
+use crate::proxy::account_schedule::{self, Schedule, ScheduleStatus};
+use crate::proxy::break_in::{break_in_status, Admission, BreakInStatus, BreakInThrottle};
+use crate::proxy::config::{
+    AccountCapsConfig, AccountConcurrencyConfig, AccountSchedulesConfig, AccountHealthCheckConfig, BreakInConfig, CircuitBreakerConfig, CooldownConfig, MaintenanceConfig, QuotaStateConfig,
+    TokenRefreshConfig,
+};
+use crate::proxy::maintenance::MaintenanceGate;
+use crate::proxy::model_access::ModelAccessCache;
+use crate::proxy::quota_state::{self, metered, DailyCap, QuotaStateStore};
 use crate::proxy::rate_limit::RateLimitTracker;
 use crate::proxy::sticky_config::StickySessionConfig;
 
+/// 累计请求数的后台写盘间隔
+const REQUEST_COUNT_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
     pub account_id: String,
@@ -19,16 +37,309 @@ pub struct ProxyToken {
     pub account_path: PathBuf,  // 账号文件路径，用于更新
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
+    pub created_at: i64,      // 账号加入时间 (Unix 秒)，用于磨合期判定
+    pub total_requests: u64,  // 累计成功请求数 (落盘到账号文件)
+    pub session_id: String,   // 上游 sessionId (落盘到账号文件 token.session_id)
+    pub session_id_issued_at: Instant, // 当前 sessionId 开始使用的时间，用于定期轮换
+}
+
+/// 熔断状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// 正常放行
+    Closed,
+    /// 熔断中，跳过该账号
+    Open,
+    /// 冷却结束，仅放行一次试探请求
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+struct CircuitEntry {
+    state: CircuitState,
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+    opened_at: Option<Instant>,
+    trial_started: Option<Instant>,
+}
+
+impl Default for CircuitEntry {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            last_failure: None,
+            opened_at: None,
+            trial_started: None,
+        }
+    }
+}
+
+/// 对外展示的熔断状态
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub email: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// 距上次失败的秒数
+    pub last_failure_secs_ago: Option<u64>,
+    /// 距离进入半开状态的剩余秒数 (仅 Open 状态)
+    pub reopens_in_secs: Option<u64>,
+}
+
+/// 按账号 email 统计连续 429/403 的熔断器
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    entries: HashMap<String, CircuitEntry>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: CircuitBreakerConfig) {
+        self.config = config;
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.cooldown_seconds)
+    }
+
+    /// 判断是否放行该账号；半开状态下只放行一次试探请求
+    pub fn allow(&mut self, email: &str, now: Instant) -> bool {
+        let cooldown = self.cooldown();
+        let Some(entry) = self.entries.get_mut(email) else {
+            return true;
+        };
+        match entry.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => {
+                if entry.opened_at.is_some_and(|t| now.duration_since(t) >= cooldown) {
+                    entry.state = CircuitState::HalfOpen;
+                    entry.trial_started = Some(now);
+                    tracing::info!("[Circuit] {} half-open, allowing one trial request", email);
+                    true
+                } else {
+                    false
+                }
+            }
+            CircuitState::HalfOpen => {
+                // 试探请求长时间无结果 (例如客户端断开)，允许再次试探，避免永久卡死
+                let stale = entry.trial_started.is_none_or(|t| now.duration_since(t) >= cooldown);
+                if stale {
+                    entry.trial_started = Some(now);
+                }
+                stale
+            }
+        }
+    }
+
+    pub fn record_success(&mut self, email: &str) {
+        if let Some(entry) = self.entries.get_mut(email) {
+            if entry.state != CircuitState::Closed {
+                tracing::info!("[Circuit] {} closed after successful request", email);
+            }
+            *entry = CircuitEntry::default();
+        }
+    }
+
+    /// 记录一次失败；仅 429/403 计入连续失败次数
+    pub fn record_failure(&mut self, email: &str, status: u16, now: Instant) {
+        let threshold = self.config.failure_threshold.max(1);
+        let entry = self.entries.entry(email.to_string()).or_default();
+
+        if status != 429 && status != 403 {
+            // 其他错误不改变熔断计数，但释放半开状态的试探名额
+            entry.trial_started = None;
+            return;
+        }
+
+        entry.consecutive_failures += 1;
+        entry.last_failure = Some(now);
+
+        let should_open = entry.state == CircuitState::HalfOpen
+            || (entry.state == CircuitState::Closed && entry.consecutive_failures >= threshold);
+        if should_open {
+            entry.state = CircuitState::Open;
+            entry.opened_at = Some(now);
+            entry.trial_started = None;
+            tracing::warn!(
+                "[Circuit] {} opened after {} consecutive failures (last status {})",
+                email,
+                entry.consecutive_failures,
+                status
+            );
+        }
+    }
+
+    pub fn status(&self, email: &str, now: Instant) -> CircuitStatus {
+        let entry = self.entries.get(email).cloned().unwrap_or_default();
+        let reopens_in_secs = match (entry.state, entry.opened_at) {
+            (CircuitState::Open, Some(t)) => {
+                Some(self.cooldown().saturating_sub(now.duration_since(t)).as_secs())
+            }
+            _ => None,
+        };
+        CircuitStatus {
+            email: email.to_string(),
+            state: entry.state,
+            consecutive_failures: entry.consecutive_failures,
+            last_failure_secs_ago: entry.last_failure.map(|t| now.duration_since(t).as_secs()),
+            reopens_in_secs,
+        }
+    }
+}
+
+/// 账号池条目概览 (供管理端点展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenStatus {
+    pub account_id: String,
+    pub email: String,
+    pub subscription_tier: Option<String>,
+    pub rate_limited: bool,
+    pub rate_limit_reset_seconds: Option<u64>,
+    pub circuit: CircuitStatus,
+    pub break_in: BreakInStatus,
+    pub health_score: f64,
+    /// 当日请求数 / Token 数 (每日硬上限的计数)
+    pub daily_requests: u64,
+    pub daily_tokens: u64,
+    /// 已达到的每日上限，达到后当日不再调度
+    pub capped: Option<DailyCap>,
+    /// 当前在途请求数
+    pub in_flight: usize,
+    /// 可用时段状态 (未配置时段的账号为 None)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ScheduleStatus>,
+}
+
+/// 429/403 时扣除的健康分
+const HEALTH_PENALTY: f64 = 0.25;
+/// 请求成功时恢复的健康分
+const HEALTH_REWARD: f64 = 0.05;
+/// 后台任务每分钟恢复的健康分
+const HEALTH_RECOVERY_PER_TICK: f64 = 0.1;
+const HEALTH_RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
+/// 重启时仍处于冷却窗口内的账号，恢复后的健康分上限
+const RESTORED_HEALTH_CEILING: f64 = 0.5;
+/// 加权抽取时的最小权重，健康分为 0 的账号在其他账号都不可用时仍可被选中
+const MIN_SELECTION_WEIGHT: f64 = 0.01;
+
+/// 按权重抽取下标，`r` 为 [0, 1) 的随机数
+fn weighted_index(weights: &[f64], r: f64) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut target = r * total;
+    for (i, w) in weights.iter().enumerate() {
+        if target < *w {
+            return i;
+        }
+        target -= w;
+    }
+    weights.len().saturating_sub(1)
+}
+
+/// 账号池整体无法提供账号时的原因，处理器据此返回不同的错误类型与状态码
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PoolStatus {
+    /// 没有加载任何账号 (未添加或全部被禁用)
+    Empty,
+    /// 所有账号都在限流冷却中，最早在 `until` (Unix 秒) 恢复
+    AllCoolingDown { until: i64 },
+    /// 所有账号熔断中，或 Token 刷新 / project_id 获取失败
+    AllUnhealthy,
+    /// 磨合期账号已达到每分钟请求上限
+    AllSaturated,
+    /// 所有可用账号的并发名额已满 (排队等待超时)
+    AllBusy,
+    /// 所有账号都已达到每日上限，`until` (Unix 秒) 跨日后恢复
+    AllCapped { until: i64 },
+    /// 所有账号都不在可用时段内，最早在 `until` (Unix 秒) 进入时段
+    AllOffSchedule { until: i64 },
+}
+
+impl PoolStatus {
+    /// 冷却中时距最早恢复的秒数 (至少 1 秒)
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            PoolStatus::AllCoolingDown { until }
+            | PoolStatus::AllCapped { until }
+            | PoolStatus::AllOffSchedule { until } => {
+                Some((until - chrono::Utc::now().timestamp()).max(1) as u64)
+            }
+            // 并发名额通常很快释放
+            PoolStatus::AllBusy => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// get_token 失败：账号池状态 + 面向日志/客户端的说明
+#[derive(Debug, Clone)]
+pub struct PoolUnavailable {
+    pub status: PoolStatus,
+    pub message: String,
+}
+
+impl std::fmt::Display for PoolUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<PoolUnavailable> for String {
+    fn from(e: PoolUnavailable) -> Self {
+        e.message
+    }
+}
+
+/// 会话与账号的绑定 (空闲超过 session_ttl_secs 后失效)
+#[derive(Debug, Clone)]
+struct SessionBinding {
+    account_id: String,
+    last_used: std::time::Instant,
+}
+
+impl SessionBinding {
+    fn new(account_id: String) -> Self {
+        Self {
+            account_id,
+            last_used: std::time::Instant::now(),
+        }
+    }
+
+    fn is_expired(&self, ttl_secs: u64) -> bool {
+        ttl_secs > 0 && self.last_used.elapsed().as_secs() >= ttl_secs
+    }
 }
 
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
-    current_index: Arc<AtomicUsize>,
+    health_scores: Arc<Mutex<HashMap<String, f64>>>, // 账号健康分 (account_id -> 0.0~1.0，缺省为 1.0)
     last_used_account: Arc<tokio::sync::Mutex<Option<(String, std::time::Instant)>>>,
     data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
-    session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    session_accounts: Arc<DashMap<String, SessionBinding>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    model_access: Arc<ModelAccessCache>, // 账号 × 模型 可访问性缓存
+    circuit_breaker: Arc<Mutex<CircuitBreaker>>, // 账号熔断器 (所有 handler 共享)
+    maintenance: Arc<MaintenanceGate>, // 维护类流量预算
+    break_in: Arc<Mutex<BreakInThrottle>>, // 新账号磨合期的降权与限速
+    pool_status: Arc<Mutex<Option<PoolStatus>>>, // 最近一次推送给前端的账号池状态 (None 表示可用)
+    quota_state: Arc<QuotaStateStore>, // 配额消耗状态 (按 email，定期写入 quota_state.json)
+    concurrency: Arc<AccountConcurrency>, // 账号并发名额与在途请求数
+    refresh_locks: Arc<DashMap<String, Arc<tokio::sync::Mutex<()>>>>, // 按账号串行化 token 刷新 (account_id -> 锁)
+    refresh_margin_secs: Arc<AtomicU64>, // 距离过期不足该秒数时提前刷新
+    session_rotation_secs: Arc<AtomicU64>, // 上游 sessionId 使用超过该秒数后更换 (0 关闭)
+    account_health: Arc<AccountHealthChecker>, // 后台健康探测结果 (探测失败的账号暂停调度)
+    schedules: Arc<RwLock<HashMap<String, Schedule>>>, // 账号可用时段 (email -> 时段，时段外不参与调度)
+    store_lock: Arc<tokio::sync::Mutex<()>>, // 串行化账号池的增删与重新加载
+    unsaved_request_counts: Arc<Mutex<HashSet<String>>>, // 累计请求数有变化、待后台写盘的账号 (account_id)
 }
 
 impl TokenManager {
@@ -36,35 +347,50 @@ impl TokenManager {
     pub fn new(data_dir: PathBuf) -> Self {
         Self {
             tokens: Arc::new(DashMap::new()),
-            current_index: Arc::new(AtomicUsize::new(0)),
+            health_scores: Arc::new(Mutex::new(HashMap::new())),
             last_used_account: Arc::new(tokio::sync::Mutex::new(None)),
             data_dir,
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
+            model_access: Arc::new(ModelAccessCache::new()),
+            circuit_breaker: Arc::new(Mutex::new(CircuitBreaker::new(CircuitBreakerConfig::default()))),
+            maintenance: Arc::new(MaintenanceGate::default()),
+            break_in: Arc::new(Mutex::new(BreakInThrottle::new(BreakInConfig::default()))),
+            pool_status: Arc::new(Mutex::new(None)),
+            quota_state: Arc::new(QuotaStateStore::new()),
+            concurrency: Arc::new(AccountConcurrency::default()),
+            refresh_locks: Arc::new(DashMap::new()),
+            refresh_margin_secs: Arc::new(AtomicU64::new(TokenRefreshConfig::default().early_refresh_secs)),
+            session_rotation_secs: Arc::new(AtomicU64::new(0)),
+            account_health: Arc::new(AccountHealthChecker::default()),
+            schedules: Arc::new(RwLock::new(HashMap::new())),
+            store_lock: Arc::new(tokio::sync::Mutex::new(())),
+            unsaved_request_counts: Arc::new(Mutex::new(HashSet::new())),
         }
     }
     
-    /// 从主应用账号目录加载所有账号
-    pub async fn load_accounts(&self) -> Result<usize, String> {
+    /// 从主应用账号目录加载所有账号 (启动时与账号变更时调用，无需重启代理)
+    /// 只增删有变化的账号，不会出现账号池短暂为空的窗口；
+    /// 已移除账号上的在途请求持有各自的 token 副本，可以正常完成
+    pub async fn reload_from_store(&self) -> Result<usize, String> {
         let accounts_dir = self.data_dir.join("accounts");
         
         if !accounts_dir.exists() {
             return Err(format!("账号目录不存在: {:?}", accounts_dir));
         }
 
-        // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
-        self.tokens.clear();
-        self.current_index.store(0, Ordering::SeqCst);
-        {
-            let mut last_used = self.last_used_account.lock().await;
-            *last_used = None;
+        let _store = self.store_lock.lock().await;
+
+        // 合并重复账号 (同一邮箱多条记录会被轮换当作独立容量)
+        if let Err(e) = crate::modules::account::dedupe_accounts_in(&self.data_dir) {
+            tracing::warn!("重复账号检测失败: {}", e);
         }
-        
+
         let entries = std::fs::read_dir(&accounts_dir)
             .map_err(|e| format!("读取账号目录失败: {}", e))?;
         
-        let mut count = 0;
+        let mut loaded = HashMap::new();
         
         for entry in entries {
             let entry = entry.map_err(|e| format!("读取目录项失败: {}", e))?;
@@ -77,9 +403,7 @@ impl TokenManager {
             // 尝试加载账号
             match self.load_single_account(&path).await {
                 Ok(Some(token)) => {
-                    let account_id = token.account_id.clone();
-                    self.tokens.insert(account_id, token);
-                    count += 1;
+                    loaded.insert(token.account_id.clone(), token);
                 },
                 Ok(None) => {
                     // 跳过无效账号
@@ -89,9 +413,76 @@ impl TokenManager {
                 }
             }
         }
+
+        // 已删除或被禁用的账号移出账号池
+        let removed: Vec<String> = self
+            .tokens
+            .iter()
+            .filter(|t| !loaded.contains_key(t.key()))
+            .map(|t| t.key().clone())
+            .collect();
+        for account_id in &removed {
+            self.detach_account(account_id).await;
+        }
+
+        let count = loaded.len();
+        for (account_id, mut token) in loaded {
+            // sessionId 未变化时沿用原来的开始时间，重新加载不会推迟轮换
+            if let Some(existing) = self.tokens.get(&account_id) {
+                if existing.session_id == token.session_id {
+                    token.session_id_issued_at = existing.session_id_issued_at;
+                }
+                // 累计请求数批量写盘，内存中的值可能领先于文件
+                token.total_requests = token.total_requests.max(existing.total_requests);
+            }
+            self.tokens.insert(account_id, token);
+        }
         
         Ok(count)
     }
+
+    /// 将单个账号加入账号池 (已存在时以磁盘内容更新)
+    /// 返回 false 表示该账号已被禁用，未加入 (并移出账号池)
+    pub async fn add_account(&self, account_id: &str) -> Result<bool, String> {
+        let path = self.data_dir.join("accounts").join(format!("{}.json", account_id));
+        let _store = self.store_lock.lock().await;
+        match self.load_single_account(&path).await? {
+            Some(mut token) => {
+                tracing::info!("账号已加入账号池: {}", token.email);
+                if let Some(existing) = self.tokens.get(&token.account_id) {
+                    token.total_requests = token.total_requests.max(existing.total_requests);
+                }
+                self.tokens.insert(token.account_id.clone(), token);
+                Ok(true)
+            }
+            None => {
+                self.detach_account(account_id).await;
+                Ok(false)
+            }
+        }
+    }
+
+    /// 将账号移出账号池：之后的 get_token 不再返回该账号，已取得 token 的在途请求不受影响
+    pub async fn remove_account(&self, account_id: &str) -> bool {
+        let _store = self.store_lock.lock().await;
+        self.detach_account(account_id).await
+    }
+
+    /// 移除账号及指向它的会话绑定与全局锁定
+    async fn detach_account(&self, account_id: &str) -> bool {
+        let Some((_, token)) = self.tokens.remove(account_id) else {
+            return false;
+        };
+        self.session_accounts.retain(|_, binding| binding.account_id != account_id);
+        {
+            let mut last_used = self.last_used_account.lock().await;
+            if last_used.as_ref().is_some_and(|(id, _)| id == account_id) {
+                *last_used = None;
+            }
+        }
+        tracing::info!("账号已移出账号池: {}", token.email);
+        true
+    }
     
     /// 加载单个账号
     async fn load_single_account(&self, path: &PathBuf) -> Result<Option<ProxyToken>, String> {
@@ -163,6 +554,25 @@ impl TokenManager {
             .and_then(|q| q.get("subscription_tier"))
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
+
+        let created_at = account.get("created_at")
+            .and_then(|v| v.as_i64())
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+        let total_requests = account.get("total_requests")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0);
+
+        // 上游 sessionId：账号文件中没有时生成并写回，重启后保持不变
+        let session_id = match token_obj.get("session_id").and_then(|v| v.as_str()) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => {
+                let id = new_session_id();
+                if let Err(e) = save_session_id(path, &id) {
+                    tracing::warn!("保存 sessionId 失败 ({}): {}", email, e);
+                }
+                id
+            }
+        };
         
         Ok(Some(ProxyToken {
             account_id,
@@ -174,31 +584,73 @@ impl TokenManager {
             account_path: path.clone(),
             project_id,
             subscription_tier,
+            created_at,
+            total_requests,
+            session_id,
+            session_id_issued_at: Instant::now(),
         }))
     }
     
     /// 获取当前可用的 Token（支持粘性会话与智能调度）
     /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
+    /// 参数 `model` 为路由解析后的上游模型名，已知无权访问该模型的账号会被跳过 (见 model_access)
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
-    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    /// 选中账号后取得其并发许可并保存在当前请求上下文中 (见 account_concurrency)
+    /// 所有账号满载时排队等待 queue_wait_secs，仍无空闲名额则返回 AllBusy
+    pub async fn get_token(
+        &self,
+        quota_group: &str,
+        model: Option<&str>,
+        force_rotate: bool,
+        session_id: Option<&str>,
+    ) -> Result<(String, String, String), PoolUnavailable> {
+        let deadline = Instant::now() + self.concurrency.queue_wait();
+        loop {
+            let result = self.select_token(quota_group, model, force_rotate, session_id).await;
+            if matches!(&result, Err(e) if e.status == PoolStatus::AllBusy) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !remaining.is_zero() {
+                    self.concurrency.wait_for_release(remaining).await;
+                    continue;
+                }
+            }
+            self.publish_pool_status(result.as_ref().err());
+            return result;
+        }
+    }
+
+    async fn select_token(
+        &self,
+        quota_group: &str,
+        model: Option<&str>,
+        force_rotate: bool,
+        session_id: Option<&str>,
+    ) -> Result<(String, String, String), PoolUnavailable> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        if tokens_snapshot.is_empty() {
+            crate::modules::notifier::notify_no_healthy_accounts(0, None);
+            return Err(PoolUnavailable {
+                status: PoolStatus::Empty,
+                message: "Token pool is empty".to_string(),
+            });
+        }
+
+        // 跳过已知无权访问该模型的账号 (粘性会话与加权抽取都只在剩余账号中进行)
+        // 全部被拒时不过滤：缓存可能已过期，交由上游返回真实错误并重新学习
+        if let Some(model) = model {
+            let allowed: Vec<ProxyToken> = tokens_snapshot
+                .iter()
+                .filter(|t| !self.model_access.is_denied(&t.email, model))
+                .cloned()
+                .collect();
+            if allowed.is_empty() {
+                tracing::warn!("[ModelAccess] 所有账号都被记录为无权访问 {}，忽略缓存", model);
+            } else {
+                tokens_snapshot = allowed;
+            }
+        }
         let total = tokens_snapshot.len();
-        if total == 0 {
-            return Err("Token pool is empty".to_string());
-        }
-
-        // ===== 【优化】根据订阅等级排序 (优先级: ULTRA > PRO > FREE) =====
-        // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
-        tokens_snapshot.sort_by(|a, b| {
-            let tier_priority = |tier: &Option<String>| match tier.as_deref() {
-                Some("ULTRA") => 0,
-                Some("PRO") => 1,
-                Some("FREE") => 2,
-                _ => 3,
-            };
-            tier_priority(&a.subscription_tier).cmp(&tier_priority(&b.subscription_tier))
-        });
 
         // 0. 读取当前调度配置
         let scheduling = self.sticky_config.read().await.clone();
@@ -217,8 +669,18 @@ impl TokenManager {
             if !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
                 let sid = session_id.unwrap();
                 
-                // 1. 检查会话是否已绑定账号
-                if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
+                // 1. 检查会话是否已绑定账号 (空闲超时的绑定视为失效)
+                let binding = self.session_accounts.get(sid).map(|v| v.clone());
+                let bound_id = match binding {
+                    Some(b) if b.is_expired(scheduling.session_ttl_secs) => {
+                        tracing::debug!("Sticky Session: Binding for session {} expired after idle TTL", sid);
+                        self.session_accounts.remove(sid);
+                        None
+                    }
+                    Some(b) => Some(b.account_id),
+                    None => None,
+                };
+                if let Some(bound_id) = bound_id {
                     // 2. 检查绑定的账号是否限流 (使用精准的剩余时间接口)
                     let reset_sec = self.rate_limit_tracker.get_remaining_wait(&bound_id);
                     if reset_sec > 0 {
@@ -239,9 +701,21 @@ impl TokenManager {
                         }
                     } else if !attempted.contains(&bound_id) {
                         // 3. 账号可用且未被标记为尝试失败，优先复用
-                        if let Some(found) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
+                        if let Some(found) = tokens_snapshot.iter().find(|t| {
+                            t.account_id == bound_id
+                                && self.concurrency.has_free_slot(&t.email)
+                                && self.circuit_allows(&t.email)
+                                && self.break_in_allows_reuse(t)
+                                && !self.is_capped(&t.email)
+                                && self.account_health.is_available(&t.email)
+                                && self.is_on_schedule(&t.email)
+                        }) {
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", found.email, sid);
                             target_token = Some(found.clone());
+                        } else {
+                            // 绑定账号已移出账号池或处于熔断：解除绑定，重新选择
+                            tracing::warn!("Sticky Session: Bound account {} for session {} is no longer healthy, rebinding", bound_id, sid);
+                            self.session_accounts.remove(sid);
                         }
                     }
                 }
@@ -253,63 +727,48 @@ impl TokenManager {
                 
                 // 尝试复用全局锁定账号
                 if let Some((account_id, last_time)) = &*last_used {
-                    if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
-                        if let Some(found) = tokens_snapshot.iter().find(|t| &t.account_id == account_id) {
+                    if last_time.elapsed().as_secs() < 60
+                        && !attempted.contains(account_id)
+                        && !self.is_rate_limited(account_id)
+                    {
+                        if let Some(found) = tokens_snapshot.iter().find(|t| {
+                            &t.account_id == account_id
+                                && self.concurrency.has_free_slot(&t.email)
+                                && self.circuit_allows(&t.email)
+                                && self.break_in_allows_reuse(t)
+                                && !self.is_capped(&t.email)
+                                && self.account_health.is_available(&t.email)
+                                && self.is_on_schedule(&t.email)
+                        }) {
                             tracing::debug!("60s Window: Force reusing last account: {}", found.email);
                             target_token = Some(found.clone());
                         }
                     }
                 }
                 
-                // 若无锁定，则轮询选择新账号
+                // 若无锁定，则按健康分加权随机选择新账号
                 if target_token.is_none() {
-                    let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
-                    for offset in 0..total {
-                        let idx = (start_idx + offset) % total;
-                        let candidate = &tokens_snapshot[idx];
-                        if attempted.contains(&candidate.account_id) {
-                            continue;
-                        }
-
-                        // 【新增】主动避开限流或 5xx 锁定的账号 (来自 PR #28 的高可用思路)
-                        if self.is_rate_limited(&candidate.account_id) {
-                            continue;
-                        }
+                    target_token = self.select_weighted(&tokens_snapshot, &attempted);
 
-                        target_token = Some(candidate.clone());
+                    if let Some(candidate) = &target_token {
                         *last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
-                        
+
                         // 如果是会话首次分配且需要粘性，在此建立绑定
                         if let Some(sid) = session_id {
                             if scheduling.mode != SchedulingMode::PerformanceFirst {
-                                self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
+                                self.session_accounts.insert(sid.to_string(), SessionBinding::new(candidate.account_id.clone()));
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
                         }
-                        break;
                     }
                 }
             } else if target_token.is_none() {
-                // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换
-                let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
-                for offset in 0..total {
-                    let idx = (start_idx + offset) % total;
-                    let candidate = &tokens_snapshot[idx];
-                    if attempted.contains(&candidate.account_id) {
-                        continue;
-                    }
-
-                    // 【新增】主动避开限流或 5xx 锁定的账号
-                    if self.is_rate_limited(&candidate.account_id) {
-                        continue;
-                    }
-
-                    target_token = Some(candidate.clone());
-                    
-                    if rotate {
+                // 模式 C: 无全局锁定 (image_gen) 或强制轮换：按健康分加权随机选择
+                target_token = self.select_weighted(&tokens_snapshot, &attempted);
+                if rotate {
+                    if let Some(candidate) = &target_token {
                         tracing::debug!("Force Rotation: Switched to account: {}", candidate.email);
                     }
-                    break;
                 }
             }
             
@@ -321,100 +780,309 @@ impl TokenManager {
                         .filter_map(|t| self.rate_limit_tracker.get_reset_seconds(&t.account_id))
                         .min()
                         .unwrap_or(60);
-                    
-                    return Err(format!("All accounts are currently limited or unhealthy. Please wait {}s.", min_wait));
+                    let status = self.classify_pool(&tokens_snapshot, &attempted);
+                    // 并发满载是短暂状态，不发送通知
+                    if status != PoolStatus::AllBusy {
+                        crate::modules::notifier::notify_no_healthy_accounts(total, Some(min_wait));
+                    }
+                    let message = match (&status, &last_error) {
+                        (PoolStatus::AllUnhealthy, Some(e)) => e.clone(),
+                        (PoolStatus::AllCoolingDown { .. }, _) => format!(
+                            "All accounts are rate-limited and cooling down. Please wait {}s.",
+                            status.retry_after_secs().unwrap_or(min_wait)
+                        ),
+                        (PoolStatus::AllSaturated, _) => {
+                            "All accounts have reached their per-minute request limit.".to_string()
+                        }
+                        (PoolStatus::AllBusy, _) => {
+                            "All accounts are at their concurrent request limit.".to_string()
+                        }
+                        (PoolStatus::AllCapped { .. }, _) => {
+                            "All accounts have reached their daily cap and resume after the daily rollover.".to_string()
+                        }
+                        (PoolStatus::AllOffSchedule { .. }, _) => format!(
+                            "All accounts are outside their availability windows. The next window opens in {}s.",
+                            status.retry_after_secs().unwrap_or(min_wait)
+                        ),
+                        _ => "All accounts are currently unhealthy (circuit open or re-authorization required).".to_string(),
+                    };
+                    return Err(PoolUnavailable { status, message });
                 }
             };
 
         
             // 3. 检查 token 是否过期（提前5分钟刷新）
-            let now = chrono::Utc::now().timestamp();
-            if now >= token.timestamp - 300 {
-                tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
-
-                // 调用 OAuth 刷新 token
-                match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
-                    Ok(token_response) => {
-                        tracing::debug!("Token 刷新成功！");
-
-                        // 更新本地内存对象供后续使用
-                        token.access_token = token_response.access_token.clone();
-                        token.expires_in = token_response.expires_in;
-                        token.timestamp = now + token_response.expires_in;
-
-                        // 同步更新跨线程共享的 DashMap
-                        if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
-                            entry.access_token = token.access_token.clone();
-                            entry.expires_in = token.expires_in;
-                            entry.timestamp = token.timestamp;
-                        }
+            if let Err(e) = self.refresh_token_if_needed(&mut token).await {
+                tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
+                // Avoid leaking account emails to API clients; details are still in logs.
+                last_error = Some(format!("Token refresh failed: {}", e));
+                attempted.insert(token.account_id.clone());
 
-                        // 同步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新）
-                        if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
-                            tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
-                        if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
-                            tracing::error!(
-                                "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
-                                token.email
-                            );
-                            let _ = self
-                                .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
-                                .await;
-                            self.tokens.remove(&token.account_id);
-                        }
-                        // Avoid leaking account emails to API clients; details are still in logs.
-                        last_error = Some(format!("Token refresh failed: {}", e));
-                        attempted.insert(token.account_id.clone());
-
-                        // 如果当前账号被锁定复用，刷新失败后必须解除锁定，避免下一次仍选中同一账号
-                        if quota_group != "image_gen" {
-                            let mut last_used = self.last_used_account.lock().await;
-                            if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
-                                *last_used = None;
-                            }
-                        }
-                        continue;
+                // 如果当前账号被锁定复用，刷新失败后必须解除锁定，避免下一次仍选中同一账号
+                if quota_group != "image_gen" {
+                    let mut last_used = self.last_used_account.lock().await;
+                    if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
+                        *last_used = None;
                     }
                 }
+                continue;
             }
 
             // 4. 确保有 project_id
-            let project_id = if let Some(pid) = &token.project_id {
-                pid.clone()
-            } else {
-                tracing::debug!("账号 {} 缺少 project_id，尝试获取...", token.email);
-                match crate::proxy::project_resolver::fetch_project_id(&token.access_token).await {
-                    Ok(pid) => {
-                        if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
-                            entry.project_id = Some(pid.clone());
-                        }
-                        let _ = self.save_project_id(&token.account_id, &pid).await;
-                        pid
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to fetch project_id for {}: {}", token.email, e);
-                        last_error = Some(format!("Failed to fetch project_id for {}: {}", token.email, e));
-                        attempted.insert(token.account_id.clone());
-
-                        if quota_group != "image_gen" {
-                            let mut last_used = self.last_used_account.lock().await;
-                            if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
-                                *last_used = None;
-                            }
+            let project_id = match self.ensure_project_id(&token).await {
+                Ok(pid) => pid,
+                Err(e) => {
+                    last_error = Some(e);
+                    attempted.insert(token.account_id.clone());
+
+                    if quota_group != "image_gen" {
+                        let mut last_used = self.last_used_account.lock().await;
+                        if matches!(&*last_used, Some((id, _)) if id == &token.account_id) {
+                            *last_used = None;
                         }
-                        continue;
                     }
+                    continue;
                 }
             };
 
+            // 5. 并发名额：选择时已过滤满载账号，这里正式占用 (并发竞争失败时换号)
+            let Some(permit) = self.concurrency.try_acquire(&token.email) else {
+                tracing::debug!("账号 {} 并发名额已满，尝试下一个账号", token.email);
+                attempted.insert(token.account_id.clone());
+                continue;
+            };
+
+            // 6. 每日硬上限：检查与计数在同一把锁内完成，并发请求不会越过请求上限
+            if let Err(cap) = self.quota_state.try_reserve(&token.email, &quota_state::today()) {
+                tracing::debug!("账号 {} 已达到每日上限 ({:?})，尝试下一个账号", token.email, cap);
+                attempted.insert(token.account_id.clone());
+                continue;
+            }
+
+            account_concurrency::hold_permit(permit);
+            crate::proxy::metrics::note_account(&token.email);
+            self.rotate_session_if_due(&token.account_id);
             return Ok((token.access_token, project_id, token.email));
         }
 
-        Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
+        Err(PoolUnavailable {
+            status: PoolStatus::AllUnhealthy,
+            message: last_error.unwrap_or_else(|| "All accounts failed".to_string()),
+        })
+    }
+
+    /// 没有可选账号时判断原因：全部不在时段内 > 全部达到每日上限 > 全部冷却 > 磨合期限速 > 并发满载 > 部分冷却 > 不健康
+    /// 时段外的账号不计入其余各项
+    fn classify_pool(&self, tokens: &[ProxyToken], attempted: &HashSet<String>) -> PoolStatus {
+        if tokens.is_empty() {
+            return PoolStatus::Empty;
+        }
+        let now_utc = chrono::Utc::now();
+        let off_schedule: Vec<ScheduleStatus> = {
+            let schedules = self.schedules.read().unwrap_or_else(|p| p.into_inner());
+            tokens
+                .iter()
+                .filter_map(|t| schedules.get(&t.email))
+                .map(|s| s.status_at(now_utc))
+                .filter(|s| !s.available)
+                .collect()
+        };
+        if off_schedule.len() == tokens.len() {
+            let until = off_schedule.iter().filter_map(|s| s.until).min();
+            return PoolStatus::AllOffSchedule {
+                until: until.unwrap_or(now_utc.timestamp() + 3600),
+            };
+        }
+        let now = Instant::now();
+        let throttle = self.break_in.lock().unwrap_or_else(|p| p.into_inner());
+        let mut cooling = 0usize;
+        let mut saturated = 0usize;
+        let mut busy = 0usize;
+        let mut min_wait: Option<u64> = None;
+        let capped = tokens.iter().filter(|t| self.is_capped(&t.email)).count();
+        if capped == tokens.len() {
+            return PoolStatus::AllCapped {
+                until: quota_state::next_rollover(),
+            };
+        }
+        for t in tokens {
+            if attempted.contains(&t.account_id) || !self.is_on_schedule(&t.email) {
+                continue;
+            }
+            if self.is_rate_limited(&t.account_id) {
+                cooling += 1;
+                let wait = self.rate_limit_tracker.get_reset_seconds(&t.account_id).unwrap_or(60);
+                min_wait = Some(min_wait.map_or(wait, |m| m.min(wait)));
+            } else if throttle.throttled_for(&t.account_id, now).is_some() {
+                saturated += 1;
+            } else if !self.concurrency.has_free_slot(&t.email) {
+                busy += 1;
+            }
+        }
+
+        let cooling_down = || PoolStatus::AllCoolingDown {
+            until: chrono::Utc::now().timestamp() + min_wait.unwrap_or(60) as i64,
+        };
+        if cooling == tokens.len() - off_schedule.len() {
+            cooling_down()
+        } else if saturated > 0 {
+            PoolStatus::AllSaturated
+        } else if busy > 0 {
+            PoolStatus::AllBusy
+        } else if cooling > 0 {
+            cooling_down()
+        } else {
+            PoolStatus::AllUnhealthy
+        }
+    }
+
+    /// 账号池状态变化时推送给前端 (同一状态不重复推送)
+    fn publish_pool_status(&self, error: Option<&PoolUnavailable>) {
+        let status = error.map(|e| e.status.clone());
+        let mut last = self.pool_status.lock().unwrap_or_else(|p| p.into_inner());
+        let changed = match (&*last, &status) {
+            (None, None) => false,
+            (Some(a), Some(b)) => std::mem::discriminant(a) != std::mem::discriminant(b),
+            _ => true,
+        };
+        if !changed {
+            return;
+        }
+        *last = status;
+        drop(last);
+        crate::modules::notifier::emit_pool_status(error);
+    }
+
+    /// token 是否已进入提前刷新窗口
+    fn needs_refresh(&self, token: &ProxyToken, now: i64) -> bool {
+        let margin = self.refresh_margin_secs.load(Ordering::Relaxed) as i64;
+        now >= token.timestamp - margin
+    }
+
+    /// 检查 token 是否即将过期（提前 early_refresh_secs 秒），必要时刷新并同步到内存与磁盘
+    async fn refresh_token_if_needed(&self, token: &mut ProxyToken) -> Result<(), String> {
+        if !self.needs_refresh(token, chrono::Utc::now().timestamp()) {
+            return Ok(());
+        }
+        self.refresh_token_single_flight(token, false).await
+    }
+
+    /// 上游返回 401 时强制刷新指定账号的 access_token，返回新的 access_token
+    /// `stale_access_token` 为被拒绝的旧 token：其他请求已刷新过时直接复用，不重复刷新
+    pub async fn force_refresh_token(&self, email: &str, stale_access_token: &str) -> Result<String, String> {
+        let mut token = self
+            .tokens
+            .iter()
+            .find(|e| e.value().email == email)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| format!("Account not found in proxy pool: {}", email))?;
+        token.access_token = stale_access_token.to_string();
+        self.refresh_token_single_flight(&mut token, true).await?;
+        Ok(token.access_token)
+    }
+
+    /// 同一账号同时只允许一个刷新请求，其余请求等待并复用刷新结果
+    /// 遇到 invalid_grant 时会禁用账号、将其移出账号池并通知前端重新授权
+    async fn refresh_token_single_flight(&self, token: &mut ProxyToken, force: bool) -> Result<(), String> {
+        let lock = self
+            .refresh_locks
+            .entry(token.account_id.clone())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        // 等锁期间其他请求可能已完成刷新 (或账号已因 invalid_grant 被移出账号池)
+        let now = chrono::Utc::now().timestamp();
+        {
+            let Some(entry) = self.tokens.get(&token.account_id) else {
+                return Err(format!("Account {} was removed from the proxy pool", token.email));
+            };
+            let refreshed_elsewhere = entry.access_token != token.access_token;
+            if refreshed_elsewhere || (!force && !self.needs_refresh(&entry, now)) {
+                token.access_token = entry.access_token.clone();
+                token.expires_in = entry.expires_in;
+                token.timestamp = entry.timestamp;
+                return Ok(());
+            }
+        }
+        tracing::debug!("账号 {} 的 token 即将过期或已失效，正在刷新...", token.email);
+
+        // 调用 OAuth 刷新 token
+        match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
+            Ok(token_response) => {
+                tracing::debug!("Token 刷新成功！");
+
+                // 更新本地内存对象供后续使用
+                token.access_token = token_response.access_token.clone();
+                token.expires_in = token_response.expires_in;
+                token.timestamp = now + token_response.expires_in;
+
+                // 同步更新跨线程共享的 DashMap
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.access_token = token.access_token.clone();
+                    entry.expires_in = token.expires_in;
+                    entry.timestamp = token.timestamp;
+                }
+
+                // 同步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新）
+                if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
+                    tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
+                    tracing::error!(
+                        "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
+                        token.email
+                    );
+                    let _ = self
+                        .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
+                        .await;
+                    self.tokens.remove(&token.account_id);
+                    crate::modules::notifier::notify_account_reauth_required(&token.email);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 确保账号有 project_id，缺失时在线获取并落盘
+    async fn ensure_project_id(&self, token: &ProxyToken) -> Result<String, String> {
+        if let Some(pid) = &token.project_id {
+            return Ok(pid.clone());
+        }
+
+        tracing::debug!("账号 {} 缺少 project_id，尝试获取...", token.email);
+        match crate::proxy::project_resolver::fetch_project_id(&token.access_token).await {
+            Ok(pid) => {
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.project_id = Some(pid.clone());
+                }
+                let _ = self.save_project_id(&token.account_id, &pid).await;
+                Ok(pid)
+            }
+            Err(e) => {
+                tracing::error!("Failed to fetch project_id for {}: {}", token.email, e);
+                Err(format!("Failed to fetch project_id for {}: {}", token.email, e))
+            }
+        }
+    }
+
+    /// 按邮箱获取指定账号的可用 Token (不参与调度，用于探测等管理操作)
+    /// 返回 (access_token, project_id, account_id)
+    pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
+        let mut token = self
+            .tokens
+            .iter()
+            .find(|e| e.value().email == email)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| format!("Account not found in proxy pool: {}", email))?;
+
+        self.refresh_token_if_needed(&mut token).await?;
+        let project_id = self.ensure_project_id(&token).await?;
+
+        Ok((token.access_token, project_id, token.account_id))
     }
 
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
@@ -490,25 +1158,126 @@ impl TokenManager {
     pub fn len(&self) -> usize {
         self.tokens.len()
     }
+
+    /// 账号 × 模型 可访问性缓存
+    pub fn model_access(&self) -> &ModelAccessCache {
+        &self.model_access
+    }
+
+    pub fn maintenance(&self) -> &Arc<MaintenanceGate> {
+        &self.maintenance
+    }
+
+    pub fn update_maintenance_config(&self, config: MaintenanceConfig) {
+        self.maintenance.update_config(config);
+    }
     
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
+    /// handler 只持有邮箱，这里统一换算为账号 ID，保证 get_token 的冷却检查能命中
+    pub(crate) fn account_id_for_email(&self, email: &str) -> Option<String> {
+        self.tokens
+            .iter()
+            .find(|e| e.value().email == email)
+            .map(|e| e.value().account_id.clone())
+    }
+
     pub fn mark_rate_limited(
         &self,
-        account_id: &str,
+        email: &str,
         status: u16,
         retry_after_header: Option<&str>,
         error_body: &str,
     ) {
+        // 网络状态重置后的宽限期：5xx 多为旧连接所致，不作软避让
+        if status != 429 && crate::proxy::upstream::network_reset::in_grace_period() {
+            tracing::debug!("{} 的 {} 发生在网络重置宽限期内，不计入冷却", email, status);
+            return;
+        }
+        let account_id = self
+            .account_id_for_email(email)
+            .unwrap_or_else(|| email.to_string());
         self.rate_limit_tracker.parse_from_error(
-            account_id,
+            &account_id,
             status,
             retry_after_header,
             error_body,
         );
     }
     
+    /// 更新默认冷却时长
+    pub fn update_cooldown_config(&self, config: CooldownConfig) {
+        self.rate_limit_tracker.set_config(config);
+    }
+
+    /// 更新新账号磨合期配置
+    pub fn update_break_in_config(&self, config: BreakInConfig) {
+        if let Ok(mut throttle) = self.break_in.lock() {
+            throttle.set_config(config);
+        }
+    }
+
+    fn break_in_status_of(&self, token: &ProxyToken) -> BreakInStatus {
+        let throttle = self.break_in.lock().unwrap_or_else(|p| p.into_inner());
+        break_in_status(throttle.config(), token.created_at, token.total_requests, chrono::Utc::now().timestamp())
+    }
+
+    /// 轮询选择时的磨合期判定；因权重让出的账号记录到 `deferred`，供无其他可用账号时兜底
+    fn break_in_admits(&self, token: &ProxyToken, deferred: &mut Option<ProxyToken>) -> bool {
+        if !self.break_in_status_of(token).is_breaking_in() {
+            return true;
+        }
+        let mut throttle = self.break_in.lock().unwrap_or_else(|p| p.into_inner());
+        match throttle.admit(&token.account_id, Instant::now()) {
+            Admission::Admit => true,
+            Admission::Deferred => {
+                if deferred.is_none() {
+                    *deferred = Some(token.clone());
+                }
+                false
+            }
+            Admission::Throttled => false,
+        }
+    }
+
+    /// 其他账号均不可用时，使用因权重让出的磨合期账号
+    fn break_in_fallback(&self, deferred: Option<ProxyToken>) -> Option<ProxyToken> {
+        let token = deferred?;
+        let mut throttle = self.break_in.lock().unwrap_or_else(|p| p.into_inner());
+        throttle.record_fallback(&token.account_id);
+        tracing::debug!("Break-in: no other account available, using {}", token.email);
+        Some(token)
+    }
+
+    /// 复用已选账号 (会话粘性 / 60s 锁定) 时仅检查磨合期每分钟限额
+    fn break_in_allows_reuse(&self, token: &ProxyToken) -> bool {
+        if !self.break_in_status_of(token).is_breaking_in() {
+            return true;
+        }
+        let mut throttle = self.break_in.lock().unwrap_or_else(|p| p.into_inner());
+        throttle.admit_reuse(&token.account_id, Instant::now()) == Admission::Admit
+    }
+
+    /// 当前可调度的账号数量 (排除冷却中与熔断未到期的账号)，用于确定重试次数
+    pub fn available_len(&self) -> usize {
+        let now = Instant::now();
+        let breaker = self.circuit_breaker.lock().unwrap_or_else(|p| p.into_inner());
+        self.tokens
+            .iter()
+            .filter(|e| {
+                let t = e.value();
+                let circuit = breaker.status(&t.email, now);
+                let circuit_open =
+                    circuit.state == CircuitState::Open && circuit.reopens_in_secs.unwrap_or(0) > 0;
+                !self.is_rate_limited(&t.account_id)
+                    && !circuit_open
+                    && self.account_health.is_available(&t.email)
+                    && self.is_on_schedule(&t.email)
+            })
+            .count()
+    }
+
     /// 检查账号是否在限流中
     pub fn is_rate_limited(&self, account_id: &str) -> bool {
         self.rate_limit_tracker.is_rate_limited(account_id)
@@ -532,9 +1301,482 @@ impl TokenManager {
         self.rate_limit_tracker.clear(account_id)
     }
 
-    // ===== 调度配置相关方法 =====
+    // ===== 熔断管理方法 =====
 
-    /// 获取当前调度配置
+    /// 更新熔断配置
+    pub fn update_circuit_breaker_config(&self, config: CircuitBreakerConfig) {
+        if let Ok(mut breaker) = self.circuit_breaker.lock() {
+            breaker.set_config(config);
+        }
+    }
+
+    /// 熔断器是否放行该账号
+    fn circuit_allows(&self, email: &str) -> bool {
+        self.circuit_breaker
+            .lock()
+            .map(|mut b| b.allow(email, Instant::now()))
+            .unwrap_or(true)
+    }
+
+    /// 按健康分加权随机选择账号 (跳过已尝试、冷却中、达到每日上限的账号；磨合期账号按权重让出)
+    fn select_weighted(&self, candidates: &[ProxyToken], attempted: &HashSet<String>) -> Option<ProxyToken> {
+        use rand::Rng;
+
+        let mut pool: Vec<&ProxyToken> = candidates
+            .iter()
+            .filter(|t| {
+                !attempted.contains(&t.account_id)
+                    && !self.is_rate_limited(&t.account_id)
+                    && !self.is_capped(&t.email)
+                    && self.concurrency.has_free_slot(&t.email)
+                    && self.account_health.is_available(&t.email)
+                    && self.is_on_schedule(&t.email)
+            })
+            .collect();
+        let mut deferred: Option<ProxyToken> = None;
+        while !pool.is_empty() {
+            let weights: Vec<f64> = {
+                let scores = self.health_scores.lock().unwrap_or_else(|p| p.into_inner());
+                pool.iter()
+                    .map(|t| scores.get(&t.account_id).copied().unwrap_or(1.0).max(MIN_SELECTION_WEIGHT))
+                    .collect()
+            };
+            let idx = weighted_index(&weights, rand::thread_rng().gen::<f64>());
+            let candidate = pool.swap_remove(idx);
+
+            // 熔断检查放在抽中之后，避免半开试探名额被未选中的账号消耗
+            if !self.circuit_allows(&candidate.email) {
+                continue;
+            }
+            // 磨合期账号按权重让出
+            if !self.break_in_admits(candidate, &mut deferred) {
+                continue;
+            }
+            return Some(candidate.clone());
+        }
+        self.break_in_fallback(deferred)
+    }
+
+    fn adjust_health(&self, email: &str, delta: f64) {
+        let Some(account_id) = self.account_id_for_email(email) else {
+            return;
+        };
+        let mut scores = self.health_scores.lock().unwrap_or_else(|p| p.into_inner());
+        let score = scores.entry(account_id).or_insert(1.0);
+        *score = (*score + delta).clamp(0.0, 1.0);
+    }
+
+    /// 健康分快照 (email -> score)，供管理端点展示
+    pub fn token_scores(&self) -> HashMap<String, f64> {
+        let scores = self.health_scores.lock().unwrap_or_else(|p| p.into_inner());
+        self.tokens
+            .iter()
+            .map(|e| {
+                let t = e.value();
+                (t.email.clone(), scores.get(&t.account_id).copied().unwrap_or(1.0))
+            })
+            .collect()
+    }
+
+    /// 所有账号的健康分向 1.0 恢复一步 (由后台任务每分钟调用)
+    pub fn restore_health(&self) {
+        let mut scores = self.health_scores.lock().unwrap_or_else(|p| p.into_inner());
+        for score in scores.values_mut() {
+            *score = (*score + HEALTH_RECOVERY_PER_TICK).min(1.0);
+        }
+        scores.retain(|_, score| *score < 1.0);
+    }
+
+    /// 启动健康分恢复任务 (TokenManager 释放后自动退出)
+    pub fn spawn_health_recovery(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(HEALTH_RECOVERY_INTERVAL);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else { break };
+                manager.restore_health();
+            }
+        });
+    }
+
+    /// 读取 quota_state.json，最近错误仍在冷却窗口内的账号以降低的健康分启动 (需在 reload_from_store 之后调用)
+    pub fn restore_quota_state(&self, config: &QuotaStateConfig) -> Result<usize, String> {
+        self.quota_state.configure(config, &self.data_dir);
+        let count = self.quota_state.load()?;
+
+        let cooldown_secs = self.rate_limit_tracker.config().rate_limit_secs;
+        let now = chrono::Utc::now().timestamp();
+        let mut degraded = 0;
+        let mut scores = self.health_scores.lock().unwrap_or_else(|p| p.into_inner());
+        for (email, state) in self.quota_state.snapshot() {
+            let Some(score) = state.restored_health(now, cooldown_secs, HEALTH_PENALTY, RESTORED_HEALTH_CEILING) else {
+                continue;
+            };
+            let Some(account_id) = self.account_id_for_email(&email) else {
+                continue;
+            };
+            scores.insert(account_id, score);
+            degraded += 1;
+        }
+        if degraded > 0 {
+            tracing::info!("已恢复 {} 个账号的配额状态，其中 {} 个仍在冷却窗口内，降权启动", count, degraded);
+        }
+        Ok(count)
+    }
+
+    /// 立即写入 quota_state.json (无变更时跳过)
+    pub fn flush_quota_state(&self) {
+        if let Err(e) = self.quota_state.flush() {
+            tracing::warn!("保存配额状态失败: {}", e);
+        }
+    }
+
+    /// 后台健康探测结果
+    pub fn account_health(&self) -> &AccountHealthChecker {
+        &self.account_health
+    }
+
+    pub fn update_account_health_config(&self, config: &AccountHealthCheckConfig) {
+        self.account_health.update_config(config.clone());
+    }
+
+    /// 启动后台账号健康探测任务 (未启用时空转；TokenManager 释放后自动退出)
+    pub fn spawn_account_health_checks(self: &Arc<Self>, upstream: Arc<crate::proxy::upstream::client::UpstreamClient>) {
+        let manager = Arc::downgrade(self);
+        let checker = self.account_health.clone();
+        tokio::spawn(async move {
+            loop {
+                if !checker.wait_next_round().await {
+                    continue;
+                }
+                let Some(manager) = manager.upgrade() else { break };
+                if !checker.config().enabled {
+                    continue;
+                }
+                let emails: Vec<String> = manager.tokens.iter().map(|e| e.value().email.clone()).collect();
+                checker.retain(&emails.iter().cloned().collect());
+                // 时段外的账号不发送探测
+                let emails: Vec<String> = emails.into_iter().filter(|e| manager.is_on_schedule(e)).collect();
+                let ctx = crate::proxy::maintenance::MaintenanceContext::new(&manager, &upstream);
+                account_health::run_round(&ctx, &checker, &emails).await;
+            }
+        });
+    }
+
+    /// 启动配额状态定期写盘任务 (TokenManager 释放后自动退出)
+    pub fn spawn_quota_state_persistence(self: &Arc<Self>, interval_secs: u64) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else { break };
+                let store = manager.quota_state.clone();
+                drop(manager);
+                if let Ok(Err(e)) = tokio::task::spawn_blocking(move || store.flush()).await {
+                    tracing::warn!("保存配额状态失败: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 为上游流式响应计量配额消耗 (按分片数扣减估算剩余配额，并计入 Token 用量)
+    /// 同时接管当前请求持有的并发许可，流结束时才释放
+    pub fn meter_stream<S, B, E>(&self, email: &str, stream: S) -> impl futures::Stream<Item = Result<B, E>>
+    where
+        S: futures::Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+    {
+        let permit = account_concurrency::take_held_permit();
+        crate::proxy::metrics::track_stream(account_concurrency::hold_for_stream(
+            metered(self.quota_state.clone(), email, stream),
+            permit,
+        ))
+    }
+
+    /// 更新账号并发限制配置
+    pub fn update_concurrency_config(&self, config: &AccountConcurrencyConfig) {
+        self.concurrency.set_config(config);
+    }
+
+    /// 更新 token 提前刷新时间
+    pub fn update_token_refresh_config(&self, config: &TokenRefreshConfig) {
+        self.refresh_margin_secs.store(config.early_refresh_secs, Ordering::Relaxed);
+    }
+
+    /// 应用 sessionId 轮换间隔 (秒，0 关闭)
+    pub fn update_session_rotation(&self, interval_secs: u64) {
+        self.session_rotation_secs.store(interval_secs, Ordering::Relaxed);
+    }
+
+    /// 账号当前的上游 sessionId
+    pub fn session_id(&self, email: &str) -> Option<String> {
+        self.tokens
+            .iter()
+            .find(|t| t.email == email)
+            .map(|t| t.session_id.clone())
+    }
+
+    /// 请求体未携带 sessionId 时填入账号当前的 sessionId (客户端提供的 metadata.user_id 优先)
+    pub fn apply_session_id(&self, email: &str, body: &mut serde_json::Value) {
+        let Some(request) = body.get_mut("request").and_then(|r| r.as_object_mut()) else {
+            return;
+        };
+        if request.contains_key("sessionId") {
+            return;
+        }
+        if let Some(session_id) = self.session_id(email) {
+            request.insert("sessionId".to_string(), serde_json::Value::String(session_id));
+        }
+    }
+
+    /// sessionId 使用超过轮换间隔时更换 (调度选中账号后调用)
+    fn rotate_session_if_due(&self, account_id: &str) {
+        let interval = self.session_rotation_secs.load(Ordering::Relaxed);
+        if interval == 0 {
+            return;
+        }
+        let rotated = self.tokens.get_mut(account_id).and_then(|mut token| {
+            if token.session_id_issued_at.elapsed() < Duration::from_secs(interval) {
+                return None;
+            }
+            Self::replace_session_id(&mut token);
+            Some(token.email.clone())
+        });
+        if let Some(email) = rotated {
+            tracing::debug!("账号 {} 的 sessionId 已使用超过 {} 秒，已更换", email, interval);
+            self.persist_session_id(account_id);
+        }
+    }
+
+    /// 立即更换指定账号的 sessionId (管理接口调用)，返回新的 sessionId
+    pub fn rotate_session(&self, email: &str) -> Result<String, String> {
+        let (account_id, session_id) = self
+            .tokens
+            .iter_mut()
+            .find(|t| t.email == email)
+            .map(|mut token| {
+                Self::replace_session_id(&mut token);
+                (token.account_id.clone(), token.session_id.clone())
+            })
+            .ok_or_else(|| format!("Account not found in proxy pool: {}", email))?;
+        tracing::info!("账号 {} 的 sessionId 已手动更换", email);
+        self.persist_session_id(&account_id);
+        Ok(session_id)
+    }
+
+    fn replace_session_id(token: &mut ProxyToken) {
+        token.session_id = new_session_id();
+        token.session_id_issued_at = Instant::now();
+    }
+
+    /// 在后台线程回写账号当前的 sessionId (写入时读取最新值，多次轮换乱序完成也不会写回旧值)
+    fn persist_session_id(&self, account_id: &str) {
+        let tokens = self.tokens.clone();
+        let account_id = account_id.to_string();
+        spawn_account_write(move || {
+            let Some((email, path, session_id)) = tokens
+                .get(&account_id)
+                .map(|t| (t.email.clone(), t.account_path.clone(), t.session_id.clone()))
+            else {
+                return;
+            };
+            if let Err(e) = save_session_id(&path, &session_id) {
+                tracing::warn!("保存 sessionId 失败 ({}): {}", email, e);
+            }
+        });
+    }
+
+    /// 各账号当前的在途请求数 (email -> 数量)
+    pub fn in_flight_counts(&self) -> HashMap<String, usize> {
+        self.concurrency.in_flight_counts()
+    }
+
+    /// 计入非流式响应 usageMetadata 中的 Token 用量 (兼容 v1internal 的 response 包装)
+    pub fn record_response_tokens(&self, email: &str, gemini_resp: &serde_json::Value) {
+        let usage = gemini_resp.get("response").unwrap_or(gemini_resp).get("usageMetadata");
+        let count = |key: &str| usage.and_then(|u| u.get(key)).and_then(|v| v.as_u64()).unwrap_or(0);
+        let today = quota_state::today();
+        let (input, output) = (
+            count("promptTokenCount"),
+            count("candidatesTokenCount") + count("thoughtsTokenCount"),
+        );
+        self.quota_state.record_tokens(email, count("totalTokenCount"), &today);
+        self.quota_state.record_io_tokens(email, input, output, &today);
+        crate::proxy::metrics::record_tokens(input, output);
+    }
+
+    /// 各账号当日用量统计 (请求数、成功 / 失败、输入输出 Token)
+    pub fn usage_stats(&self) -> Vec<quota_state::AccountUsageStats> {
+        self.quota_state.usage_stats(&quota_state::today())
+    }
+
+    /// 清零账号当日用量统计并立即落盘
+    pub fn reset_usage_stats(&self, email: &str) -> bool {
+        let reset = self.quota_state.reset_usage(email);
+        if reset {
+            self.flush_quota_state();
+        }
+        reset
+    }
+
+    /// 应用账号每日上限配置
+    pub fn update_account_caps(&self, config: &AccountCapsConfig) {
+        self.quota_state.set_caps(config);
+    }
+
+    /// 应用账号可用时段配置 (无法解析的时段记录错误，该账号暂停调度)
+    pub fn update_account_schedules(&self, config: &AccountSchedulesConfig) {
+        let parsed: HashMap<String, Schedule> = config
+            .accounts
+            .iter()
+            .filter(|(_, schedule)| !schedule.windows.is_empty())
+            .map(|(email, schedule)| {
+                let parsed = Schedule::parse(schedule).unwrap_or_else(|e| {
+                    tracing::error!("账号 {} 的可用时段配置无效，暂停调度: {}", email, e);
+                    account_schedule::never_available()
+                });
+                (email.clone(), parsed)
+            })
+            .collect();
+        if let Ok(mut guard) = self.schedules.write() {
+            *guard = parsed;
+        }
+    }
+
+    /// 账号当前是否处于可用时段 (未配置时段的账号始终可用)
+    pub fn is_on_schedule(&self, email: &str) -> bool {
+        self.schedules
+            .read()
+            .map(|s| s.get(email).is_none_or(|s| s.is_available_at(chrono::Utc::now())))
+            .unwrap_or(true)
+    }
+
+    /// 账号当日是否已达到请求 / Token 上限
+    pub fn is_capped(&self, email: &str) -> bool {
+        self.quota_state.capped(email, &quota_state::today()).is_some()
+    }
+
+    /// 记录账号请求成功，关闭熔断并累计请求数 (由后台任务批量落盘，见 flush_request_counts)
+    pub fn report_success(&self, email: &str) {
+        if let Ok(mut breaker) = self.circuit_breaker.lock() {
+            breaker.record_success(email);
+        }
+        self.adjust_health(email, HEALTH_REWARD);
+        self.quota_state.record_success(email);
+
+        let Some(mut entry) = self.tokens.iter_mut().find(|e| e.value().email == email) else {
+            return;
+        };
+        let was_breaking_in = self.break_in_status_of(entry.value()).is_breaking_in();
+        entry.total_requests += 1;
+        let token = entry.value().clone();
+        drop(entry);
+        if let Ok(mut unsaved) = self.unsaved_request_counts.lock() {
+            unsaved.insert(token.account_id.clone());
+        }
+
+        let graduated = was_breaking_in && !self.break_in_status_of(&token).is_breaking_in();
+        if graduated {
+            tracing::info!("账号 {} 磨合期结束 (累计 {} 次请求)，恢复正常轮换", token.email, token.total_requests);
+            if let Ok(mut throttle) = self.break_in.lock() {
+                throttle.forget(&token.account_id);
+            }
+        }
+    }
+
+    /// 将有变化的累计请求数写回账号文件 (同步文件 IO，由写盘任务在阻塞线程中调用，停止服务时再调用一次)
+    pub fn flush_request_counts(&self) {
+        let account_ids: Vec<String> = match self.unsaved_request_counts.lock() {
+            Ok(mut unsaved) => unsaved.drain().collect(),
+            Err(_) => return,
+        };
+        for account_id in account_ids {
+            let Some((email, path, total_requests)) = self
+                .tokens
+                .get(&account_id)
+                .map(|t| (t.email.clone(), t.account_path.clone(), t.total_requests))
+            else {
+                continue;
+            };
+            if let Err(e) = save_total_requests(&path, total_requests) {
+                tracing::debug!("保存累计请求数失败 ({}): {}", email, e);
+            }
+        }
+    }
+
+    /// 启动累计请求数的定期写盘任务 (TokenManager 释放后自动退出)
+    pub fn spawn_request_count_persistence(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(REQUEST_COUNT_FLUSH_INTERVAL);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else { break };
+                let _ = tokio::task::spawn_blocking(move || manager.flush_request_counts()).await;
+            }
+        });
+    }
+
+    /// 记录账号请求失败 (仅 429/403 计入熔断与健康分，5xx 计入系统通知统计)
+    pub fn report_failure(&self, email: &str, status: u16) {
+        if status != 429 && crate::proxy::upstream::network_reset::in_grace_period() {
+            return;
+        }
+        if status >= 500 {
+            crate::modules::notifier::record_upstream_error(email, status);
+        }
+        if let Ok(mut breaker) = self.circuit_breaker.lock() {
+            breaker.record_failure(email, status, Instant::now());
+        }
+        self.quota_state.record_failure(email, status, chrono::Utc::now().timestamp());
+        if status == 429 || status == 403 {
+            self.adjust_health(email, -HEALTH_PENALTY);
+        }
+    }
+
+    /// 获取账号池概览 (含限流与熔断状态)
+    pub fn token_statuses(&self) -> Vec<TokenStatus> {
+        let now = Instant::now();
+        let breaker = self.circuit_breaker.lock().unwrap_or_else(|p| p.into_inner());
+        let scores = self.token_scores();
+        let today = quota_state::today();
+        let usage = self.quota_state.snapshot();
+        let schedules = self.schedules.read().unwrap_or_else(|p| p.into_inner());
+        let now_utc = chrono::Utc::now();
+        let mut list: Vec<TokenStatus> = self
+            .tokens
+            .iter()
+            .map(|e| {
+                let t = e.value();
+                TokenStatus {
+                    account_id: t.account_id.clone(),
+                    email: t.email.clone(),
+                    subscription_tier: t.subscription_tier.clone(),
+                    rate_limited: self.is_rate_limited(&t.account_id),
+                    rate_limit_reset_seconds: self.rate_limit_tracker.get_reset_seconds(&t.account_id),
+                    circuit: breaker.status(&t.email, now),
+                    break_in: self.break_in_status_of(t),
+                    health_score: scores.get(&t.email).copied().unwrap_or(1.0),
+                    daily_requests: usage.get(&t.email).filter(|s| s.day == today).map_or(0, |s| s.daily_requests),
+                    daily_tokens: usage.get(&t.email).filter(|s| s.day == today).map_or(0, |s| s.daily_tokens),
+                    capped: self.quota_state.capped(&t.email, &today),
+                    in_flight: self.concurrency.in_flight(&t.email),
+                    schedule: schedules.get(&t.email).map(|s| s.status_at(now_utc)),
+                }
+            })
+            .collect();
+        list.sort_by(|a, b| a.email.cmp(&b.email));
+        list
+    }
+
+    // ===== 调度配置相关方法 =====
+
+    /// 获取当前调度配置
     pub async fn get_sticky_config(&self) -> StickySessionConfig {
         self.sticky_config.read().await.clone()
     }
@@ -546,6 +1788,23 @@ impl TokenManager {
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// 请求成功后将会话绑定到实际服务的账号 (重试换号后绑定随之迁移)，并刷新空闲计时
+    pub async fn bind_session(&self, session_id: &str, email: &str) {
+        let scheduling = self.sticky_config.read().await.clone();
+        if scheduling.mode == crate::proxy::sticky_config::SchedulingMode::PerformanceFirst {
+            return;
+        }
+        let Some(account_id) = self.account_id_for_email(email) else {
+            return;
+        };
+
+        // 顺带清理过期绑定，避免长时间运行后映射表无限增长
+        self.session_accounts
+            .retain(|_, binding| !binding.is_expired(scheduling.session_ttl_secs));
+        self.session_accounts
+            .insert(session_id.to_string(), SessionBinding::new(account_id));
+    }
+
     /// 清除特定会话的粘性映射
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
@@ -558,6 +1817,37 @@ impl TokenManager {
     }
 }
 
+/// 将累计请求数写回账号文件 (经账号模块的文件锁，与应用内的账号写入串行)
+fn save_total_requests(path: &std::path::Path, total_requests: u64) -> Result<(), String> {
+    crate::modules::account::update_account_file(path, |content| {
+        content["total_requests"] = serde_json::json!(total_requests);
+    })
+}
+
+fn new_session_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// 将 sessionId 写回账号文件 (经账号模块的文件锁，与应用内的账号写入串行)
+fn save_session_id(path: &std::path::Path, session_id: &str) -> Result<(), String> {
+    crate::modules::account::update_account_file(path, |content| {
+        content["token"]["session_id"] = serde_json::json!(session_id);
+    })
+}
+
+/// 账号文件回写放到阻塞线程池执行，不阻塞请求路径上的异步运行时 (不在运行时中时直接执行)
+fn spawn_account_write<F>(write: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(write);
+        }
+        Err(_) => write(),
+    }
+}
+
 fn truncate_reason(reason: &str, max_len: usize) -> String {
     if reason.chars().count() <= max_len {
         return reason.to_string();
@@ -566,3 +1856,515 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown_seconds: 60,
+        })
+    }
+
+    #[test]
+    fn test_circuit_opens_after_threshold() {
+        let mut cb = breaker();
+        let now = Instant::now();
+        cb.record_failure("a@example.com", 429, now);
+        assert!(cb.allow("a@example.com", now));
+        cb.record_failure("a@example.com", 500, now); // 非 429/403 不计数
+        assert!(cb.allow("a@example.com", now));
+        cb.record_failure("a@example.com", 403, now);
+        assert!(!cb.allow("a@example.com", now));
+        assert_eq!(cb.status("a@example.com", now).state, CircuitState::Open);
+        assert!(cb.allow("b@example.com", now));
+    }
+
+    #[test]
+    fn test_circuit_half_open_trial() {
+        let mut cb = breaker();
+        let start = Instant::now();
+        cb.record_failure("a@example.com", 429, start);
+        cb.record_failure("a@example.com", 429, start);
+
+        // 冷却结束：仅放行一次试探
+        let later = start + Duration::from_secs(61);
+        assert!(cb.allow("a@example.com", later));
+        assert!(!cb.allow("a@example.com", later));
+
+        // 试探失败 -> 重新熔断
+        cb.record_failure("a@example.com", 429, later);
+        assert_eq!(cb.status("a@example.com", later).state, CircuitState::Open);
+
+        // 再次冷却后试探成功 -> 关闭
+        let much_later = later + Duration::from_secs(61);
+        assert!(cb.allow("a@example.com", much_later));
+        cb.record_success("a@example.com");
+        assert_eq!(cb.status("a@example.com", much_later).state, CircuitState::Closed);
+        assert!(cb.allow("a@example.com", much_later));
+    }
+
+    fn test_token(account_id: &str, email: &str) -> ProxyToken {
+        ProxyToken {
+            account_id: account_id.to_string(),
+            access_token: format!("at-{}", account_id),
+            refresh_token: String::new(),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: email.to_string(),
+            account_path: PathBuf::new(),
+            project_id: Some("test-project".to_string()),
+            subscription_tier: None,
+            created_at: chrono::Utc::now().timestamp() - 30 * 86400,
+            total_requests: 1000,
+            session_id: format!("session-{}", account_id),
+            session_id_issued_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_request_counts_are_flushed_in_batches() {
+        let path = std::env::temp_dir().join(format!("ag-total-requests-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"email":"a@example.com","total_requests":1000}"#).unwrap();
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut token = test_token("id-a", "a@example.com");
+        token.account_path = path.clone();
+        manager.tokens.insert("id-a".to_string(), token);
+
+        let on_disk = || {
+            let content: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            content["total_requests"].as_u64().unwrap()
+        };
+        for _ in 0..3 {
+            manager.report_success("a@example.com");
+        }
+        // 请求路径不写盘，由写盘任务统一落盘
+        assert_eq!(on_disk(), 1000);
+        manager.flush_request_counts();
+        assert_eq!(on_disk(), 1003);
+        assert!(manager.unsaved_request_counts.lock().unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn write_account_file(dir: &std::path::Path, id: &str, email: &str, disabled: bool) {
+        let account = serde_json::json!({
+            "id": id,
+            "email": email,
+            "disabled": disabled,
+            "created_at": chrono::Utc::now().timestamp() - 30 * 86400,
+            "token": {
+                "access_token": format!("at-{}", id),
+                "refresh_token": "rt",
+                "expires_in": 3600,
+                "expiry_timestamp": chrono::Utc::now().timestamp() + 3600,
+                "project_id": "test-project"
+            }
+        });
+        std::fs::write(dir.join(format!("{}.json", id)), account.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accounts_added_and_removed_at_runtime() {
+        let data_dir = std::env::temp_dir().join(format!("ag-pool-{}", uuid::Uuid::new_v4().simple()));
+        let accounts_dir = data_dir.join("accounts");
+        std::fs::create_dir_all(&accounts_dir).unwrap();
+        write_account_file(&accounts_dir, "id-a", "a@example.com", false);
+        write_account_file(&accounts_dir, "id-b", "b@example.com", false);
+
+        let manager = TokenManager::new(data_dir.clone());
+        assert_eq!(manager.reload_from_store().await.unwrap(), 2);
+        manager.bind_session("conv-1", "b@example.com").await;
+
+        // 移除后不再被调度，会话绑定一并解除；已取得的 token 仍由调用方持有
+        let (held_token, _, _) = manager.get_token_by_email("b@example.com").await.unwrap();
+        assert!(manager.remove_account("id-b").await);
+        assert!(!manager.remove_account("id-b").await);
+        assert_eq!(held_token, "at-id-b");
+        assert_eq!(manager.len(), 1);
+        assert!(manager.session_accounts.get("conv-1").is_none());
+        for _ in 0..3 {
+            let (_, _, email) = manager.get_token("claude", None, true, Some("conv-1")).await.unwrap();
+            assert_eq!(email, "a@example.com");
+        }
+
+        write_account_file(&accounts_dir, "id-c", "c@example.com", false);
+        assert!(manager.add_account("id-c").await.unwrap());
+        write_account_file(&accounts_dir, "id-d", "d@example.com", true);
+        assert!(!manager.add_account("id-d").await.unwrap());
+        assert_eq!(manager.len(), 2);
+        assert_eq!(manager.available_len(), 2);
+
+        // 重新加载以磁盘为准：删除的文件移出，仍存在的文件重新加入
+        std::fs::remove_file(accounts_dir.join("id-a.json")).unwrap();
+        assert_eq!(manager.reload_from_store().await.unwrap(), 2);
+        let mut ids: Vec<String> = manager.tokens.iter().map(|t| t.key().clone()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["id-b", "id-c"]);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn test_session_id_rotates_after_interval() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("id-a".to_string(), test_token("id-a", "a@example.com"));
+        let mut body = serde_json::json!({"request": {"contents": []}});
+        manager.apply_session_id("a@example.com", &mut body);
+        assert_eq!(body["request"]["sessionId"], "session-id-a");
+        // 客户端已提供 sessionId 时不覆盖
+        let mut body = serde_json::json!({"request": {"sessionId": "user-1"}});
+        manager.apply_session_id("a@example.com", &mut body);
+        assert_eq!(body["request"]["sessionId"], "user-1");
+
+        // 未启用轮换时保持不变
+        manager.tokens.get_mut("id-a").unwrap().session_id_issued_at = Instant::now() - Duration::from_secs(7200);
+        manager.get_token("claude", None, false, None).await.unwrap();
+        assert_eq!(manager.session_id("a@example.com").unwrap(), "session-id-a");
+
+        manager.update_session_rotation(3600);
+        manager.get_token("claude", None, false, None).await.unwrap();
+        let rotated = manager.session_id("a@example.com").unwrap();
+        assert_ne!(rotated, "session-id-a");
+        // 间隔内再次调度不会更换
+        manager.get_token("claude", None, false, None).await.unwrap();
+        assert_eq!(manager.session_id("a@example.com").unwrap(), rotated);
+
+        let forced = manager.rotate_session("a@example.com").unwrap();
+        assert_ne!(forced, rotated);
+        assert_eq!(manager.session_id("a@example.com").unwrap(), forced);
+        assert!(manager.rotate_session("missing@example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_account_is_skipped() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+        assert_eq!(manager.available_len(), 2);
+
+        // handler 以邮箱标记冷却，调度按账号 ID 检查
+        manager.mark_rate_limited("a@example.com", 429, None, r#"{"error":{"details":[{"retryDelay":"120s"}]}}"#);
+        assert!(manager.is_rate_limited("id-a"));
+        assert_eq!(manager.available_len(), 1);
+
+        for _ in 0..4 {
+            let (_, _, email) = manager.get_token("claude", None, false, None).await.unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_account_denied_for_model_is_skipped() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+        manager.model_access().learn_from_error("a@example.com", "claude-opus-4-5-thinking", 403, "PERMISSION_DENIED");
+
+        // 会话已绑定到被拒账号时同样改选其他账号
+        manager.bind_session("conv-denied", "a@example.com").await;
+        for _ in 0..4 {
+            let (_, _, email) = manager
+                .get_token("claude", Some("claude-opus-4-5-thinking"), false, Some("conv-denied"))
+                .await
+                .unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+
+        // 其他模型不受影响；所有账号都被拒时忽略缓存
+        manager.model_access().learn_from_error("b@example.com", "claude-opus-4-5-thinking", 403, "PERMISSION_DENIED");
+        assert!(manager.get_token("claude", Some("claude-opus-4-5-thinking"), true, None).await.is_ok());
+        let mut seen = HashSet::new();
+        for _ in 0..32 {
+            let (_, _, email) = manager.get_token("claude", Some("gemini-2.5-flash"), true, None).await.unwrap();
+            seen.insert(email);
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_session_binding_follows_success_and_expires() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+        let bound = |sid: &str| manager.session_accounts.get(sid).map(|b| b.account_id.clone());
+
+        // 成功响应后绑定到实际服务的账号，后续请求复用
+        manager.bind_session("key-conv-1", "b@example.com").await;
+        assert_eq!(bound("key-conv-1").as_deref(), Some("id-b"));
+        for _ in 0..3 {
+            let (_, _, email) = manager.get_token("claude", None, false, Some("key-conv-1")).await.unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+
+        // 绑定账号冷却 (平衡模式)：解除绑定并换号
+        manager.mark_rate_limited("b@example.com", 429, None, r#"{"error":{"details":[{"retryDelay":"120s"}]}}"#);
+        let (_, _, email) = manager.get_token("claude", None, false, Some("key-conv-1")).await.unwrap();
+        assert_eq!(email, "a@example.com");
+
+        // 空闲超时后绑定失效
+        let mut config = manager.get_sticky_config().await;
+        config.session_ttl_secs = 1;
+        manager.update_sticky_config(config).await;
+        manager.session_accounts.insert(
+            "key-stale".to_string(),
+            SessionBinding {
+                account_id: "id-b".to_string(),
+                last_used: std::time::Instant::now() - std::time::Duration::from_secs(5),
+            },
+        );
+        manager.bind_session("key-fresh", "a@example.com").await;
+        assert!(bound("key-stale").is_none());
+        assert_eq!(bound("key-fresh").as_deref(), Some("id-a"));
+    }
+
+    #[tokio::test]
+    async fn test_break_in_account_share_is_capped_until_graduation() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("id-old".to_string(), test_token("id-old", "old@example.com"));
+        let mut fresh = test_token("id-new", "new@example.com");
+        fresh.created_at = chrono::Utc::now().timestamp();
+        fresh.total_requests = 150;
+        manager.tokens.insert("id-new".to_string(), fresh);
+        manager.update_break_in_config(BreakInConfig {
+            enabled: true,
+            hours: 48,
+            requests: 200,
+            share_percent: 25,
+            max_requests_per_minute: 0,
+        });
+
+        async fn new_share(manager: &TokenManager) -> usize {
+            let mut count = 0;
+            for _ in 0..100 {
+                let (_, _, email) = manager.get_token("claude", None, true, None).await.unwrap();
+                if email == "new@example.com" {
+                    count += 1;
+                }
+            }
+            count
+        }
+
+        // 新账号每次被抽中只按 25% 的份额放行，最多占 100 次中的 25 次
+        assert!(new_share(&manager).await <= 25);
+
+        // 成功请求累计到阈值前一次仍在磨合期，达到阈值时毕业
+        for _ in 0..49 {
+            manager.report_success("new@example.com");
+        }
+        let status = |manager: &TokenManager| {
+            manager.token_statuses().into_iter().find(|s| s.email == "new@example.com").unwrap().break_in
+        };
+        assert!(matches!(
+            status(&manager),
+            BreakInStatus::BreakingIn { requests: 199, required_requests: 200, .. }
+        ));
+        manager.report_success("new@example.com");
+        assert_eq!(status(&manager), BreakInStatus::Graduated);
+        assert!(new_share(&manager).await > 25);
+    }
+
+    #[test]
+    fn test_weighted_index() {
+        let weights = [1.0, 0.0, 3.0];
+        assert_eq!(weighted_index(&weights, 0.0), 0);
+        assert_eq!(weighted_index(&weights, 0.24), 0);
+        assert_eq!(weighted_index(&weights, 0.25), 2);
+        assert_eq!(weighted_index(&weights, 0.999), 2);
+        assert_eq!(weighted_index(&[0.5], 0.7), 0);
+    }
+
+    #[tokio::test]
+    async fn test_health_score_steers_selection_and_recovers() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+
+        // 连续 429 后健康分降到 0.25，成功后回升
+        for _ in 0..3 {
+            manager.report_failure("b@example.com", 429);
+        }
+        manager.report_failure("b@example.com", 500); // 5xx 不影响健康分
+        assert_eq!(manager.token_scores()["b@example.com"], 0.25);
+        manager.report_success("b@example.com");
+        assert!((manager.token_scores()["b@example.com"] - 0.30).abs() < 1e-9);
+        assert_eq!(manager.token_scores()["a@example.com"], 1.0);
+
+        // 健康账号承接大部分流量 (期望约 77%)
+        let mut healthy = 0;
+        for _ in 0..400 {
+            let (_, _, email) = manager.get_token("claude", None, true, None).await.unwrap();
+            if email == "a@example.com" {
+                healthy += 1;
+            }
+        }
+        assert!(healthy > 240, "healthy account served {} / 400", healthy);
+
+        // 后台恢复逐步回到 1.0，恢复后不再保留记录
+        for _ in 0..8 {
+            manager.restore_health();
+        }
+        assert_eq!(manager.token_scores()["b@example.com"], 1.0);
+        assert!(manager.health_scores.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pool_status_distinguishes_empty_cooling_unhealthy_saturated() {
+        let status_of = |r: Result<(String, String, String), PoolUnavailable>| r.unwrap_err().status;
+
+        // 未添加账号
+        let manager = TokenManager::new(std::env::temp_dir());
+        assert_eq!(status_of(manager.get_token("claude", None, false, None).await), PoolStatus::Empty);
+
+        // 唯一账号冷却中：返回最早恢复时间
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("id-a".to_string(), test_token("id-a", "a@example.com"));
+        manager.mark_rate_limited("a@example.com", 429, None, r#"{"error":{"details":[{"retryDelay":"120s"}]}}"#);
+        let status = status_of(manager.get_token("claude", None, false, None).await);
+        assert!(matches!(status, PoolStatus::AllCoolingDown { .. }));
+        assert!((110..=120).contains(&status.retry_after_secs().unwrap()));
+
+        // 熔断中
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("id-a".to_string(), test_token("id-a", "a@example.com"));
+        manager.update_circuit_breaker_config(CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown_seconds: 600,
+        });
+        manager.report_failure("a@example.com", 403);
+        assert_eq!(status_of(manager.get_token("claude", None, false, None).await), PoolStatus::AllUnhealthy);
+
+        // 磨合期账号达到每分钟上限
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut fresh = test_token("id-new", "new@example.com");
+        fresh.created_at = chrono::Utc::now().timestamp();
+        fresh.total_requests = 0;
+        manager.tokens.insert("id-new".to_string(), fresh);
+        manager.update_break_in_config(BreakInConfig {
+            enabled: true,
+            hours: 48,
+            requests: 200,
+            share_percent: 100,
+            max_requests_per_minute: 1,
+        });
+        assert!(manager.get_token("claude", None, true, None).await.is_ok());
+        assert_eq!(status_of(manager.get_token("claude", None, true, None).await), PoolStatus::AllSaturated);
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_probe_result_skips_account_until_recovered() {
+        use crate::proxy::account_health::ProbeOutcome;
+
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+        manager.update_account_health_config(&AccountHealthCheckConfig {
+            enabled: true,
+            failure_threshold: 1,
+            ..Default::default()
+        });
+        manager
+            .account_health()
+            .record("a@example.com", &ProbeOutcome::Failed("HTTP 403".to_string()), 0);
+        assert_eq!(manager.available_len(), 1);
+        for _ in 0..10 {
+            let (_, _, email) = manager.get_token("claude", None, true, None).await.unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+
+        manager
+            .account_health()
+            .record("a@example.com", &ProbeOutcome::Healthy { latency_ms: 50 }, 1);
+        assert_eq!(manager.available_len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_spreads_then_queues() {
+        use crate::proxy::account_concurrency::{scope_account_permit, take_held_permit, AccountPermit};
+
+        // 模拟一个进行中的请求：取得账号后持有许可
+        async fn start_request(manager: &TokenManager) -> (Result<(String, String, String), PoolUnavailable>, Option<AccountPermit>) {
+            scope_account_permit(async {
+                let result = manager.get_token("claude", None, false, None).await;
+                (result, take_held_permit())
+            })
+            .await
+        }
+
+        let manager = TokenManager::new(std::env::temp_dir());
+        for (id, email) in [("id-a", "a@example.com"), ("id-b", "b@example.com")] {
+            manager.tokens.insert(id.to_string(), test_token(id, email));
+        }
+        manager.update_concurrency_config(&AccountConcurrencyConfig {
+            max_concurrent_per_account: 1,
+            queue_wait_secs: 0,
+        });
+
+        // 60s 锁定账号满载时改用另一个账号
+        let (first, first_permit) = start_request(&manager).await;
+        let (second, _second_permit) = start_request(&manager).await;
+        assert_ne!(first.unwrap().2, second.unwrap().2);
+        assert!(manager.in_flight_counts().values().all(|n| *n == 1));
+        assert!(manager.token_statuses().iter().all(|s| s.in_flight == 1));
+
+        // 全部满载且不排队：立即返回 AllBusy (429 + Retry-After)
+        let (busy, permit) = start_request(&manager).await;
+        let busy = busy.unwrap_err();
+        assert_eq!(busy.status, PoolStatus::AllBusy);
+        assert_eq!(busy.status.retry_after_secs(), Some(1));
+        assert!(permit.is_none());
+
+        // 允许排队时等待名额释放
+        manager.update_concurrency_config(&AccountConcurrencyConfig {
+            max_concurrent_per_account: 1,
+            queue_wait_secs: 5,
+        });
+        drop(first_permit);
+        // set_config 重建信号量，重新占满两个账号
+        let (first, first_permit) = start_request(&manager).await;
+        let (_, _second_permit) = start_request(&manager).await;
+        let released_email = first.unwrap().2;
+        let first_permit = first_permit.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(first_permit);
+        });
+        let (queued, _) = start_request(&manager).await;
+        assert_eq!(queued.unwrap().2, released_email);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_margin_and_single_flight_reuse() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let now = chrono::Utc::now().timestamp();
+
+        // 默认提前 300 秒刷新；调大提前量后同一 token 进入刷新窗口
+        let mut token = test_token("id-a", "a@example.com");
+        token.timestamp = now + 600;
+        assert!(!manager.needs_refresh(&token, now));
+        manager.update_token_refresh_config(&TokenRefreshConfig { early_refresh_secs: 900 });
+        assert!(manager.needs_refresh(&token, now));
+
+        // 共享条目已被其他请求刷新：等锁后直接复用，不再调用 OAuth
+        manager.tokens.insert("id-a".to_string(), test_token("id-a", "a@example.com"));
+        manager.update_token_refresh_config(&TokenRefreshConfig::default());
+        let mut stale = test_token("id-a", "a@example.com");
+        stale.access_token = "expired".to_string();
+        stale.timestamp = now - 10;
+        manager.refresh_token_if_needed(&mut stale).await.unwrap();
+        assert_eq!(stale.access_token, "at-id-a");
+        assert_eq!(manager.force_refresh_token("a@example.com", "expired").await.unwrap(), "at-id-a");
+
+        // 账号已被移出账号池 (如 invalid_grant) 时不再刷新
+        manager.tokens.remove("id-a");
+        token.timestamp = now - 10;
+        assert!(manager.refresh_token_if_needed(&mut token).await.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// 客户端断开检测
+// 下游连接关闭时 axum 会丢弃响应体 (流式) 或 handler future (非流式 / 重试循环中)，
+// 随之丢弃 reqwest 响应流，上游连接被关闭，不再继续消耗账号配额。
+// 这里的包装只负责识别 "未正常结束就被丢弃" 的情况并记录日志，便于确认取消确实生效。
+use bytes::Bytes;
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type BoxedSseStream = Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>;
+
+/// 感知客户端断开的 SSE 响应流
+pub struct DisconnectAware {
+    inner: BoxedSseStream,
+    trace_id: String,
+    chunks: usize,
+    finished: bool,
+}
+
+impl DisconnectAware {
+    pub fn new(inner: BoxedSseStream, trace_id: impl Into<String>) -> Self {
+        Self {
+            inner,
+            trace_id: trace_id.into(),
+            chunks: 0,
+            finished: false,
+        }
+    }
+}
+
+impl Stream for DisconnectAware {
+    type Item = Result<Bytes, String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(_)) => self.chunks += 1,
+            Poll::Ready(None) => self.finished = true,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl Drop for DisconnectAware {
+    fn drop(&mut self) {
+        if !self.finished {
+            tracing::info!(
+                "[{}] client disconnected, aborting upstream after {} chunks",
+                self.trace_id,
+                self.chunks
+            );
+        }
+    }
+}
+
+/// 请求处理阶段的断开检测 (响应头发出前：重试循环、非流式请求)
+/// 正常得到响应后调用 `disarm`；未 disarm 就被丢弃说明 handler future 因客户端断开被取消
+pub struct DisconnectGuard {
+    trace_id: String,
+    route: String,
+    armed: bool,
+}
+
+impl DisconnectGuard {
+    pub fn new(trace_id: impl Into<String>, route: impl Into<String>) -> Self {
+        Self {
+            trace_id: trace_id.into(),
+            route: route.into(),
+            armed: true,
+        }
+    }
+
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            tracing::info!(
+                "[{}] client disconnected before response, aborting upstream request ({})",
+                self.trace_id,
+                self.route
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_dropping_stream_drops_upstream() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        // 上游流被丢弃时置位
+        struct DropFlag(Arc<AtomicBool>);
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(dropped.clone());
+        let upstream: BoxedSseStream = Box::pin(async_stream::stream! {
+            let _flag = flag;
+            for i in 0..1000 {
+                yield Ok(Bytes::from(format!("data: {}\n\n", i)));
+            }
+        });
+
+        let mut stream = DisconnectAware::new(upstream, "trace");
+        for _ in 0..3 {
+            stream.next().await;
+        }
+        assert_eq!(stream.chunks, 3);
+        assert!(!stream.finished);
+        drop(stream);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_finished_stream_is_not_reported_as_disconnect() {
+        let upstream: BoxedSseStream = Box::pin(futures::stream::iter(vec![Ok(Bytes::from("data: 1\n\n"))]));
+        let mut stream = DisconnectAware::new(upstream, "trace");
+        while stream.next().await.is_some() {}
+        assert!(stream.finished);
+        assert_eq!(stream.chunks, 1);
+    }
+}
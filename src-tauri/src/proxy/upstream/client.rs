@@ -4,6 +4,35 @@
 use reqwest::{header, Client, Response, StatusCode};
 use serde_json::Value;
 use tokio::time::Duration;
+use tracing::Instrument;
+
+use super::idempotency::{self, AttemptOutcome};
+use super::network_reset;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 上游代理认证失败 (407) 的错误前缀，调用方据此区分错误类别 (换账号重试无意义)
+pub const PROXY_AUTH_ERROR_PREFIX: &str = "[proxy_auth]";
+
+/// 判断 call_v1_internal 返回的错误是否为上游代理认证失败
+pub fn is_proxy_auth_failure(error: &str) -> bool {
+    error.starts_with(PROXY_AUTH_ERROR_PREFIX)
+}
+
+/// 上游 TLS 证书校验失败的错误前缀 (常见于 SSL 拦截代理，换账号重试无意义)
+pub const TLS_ERROR_PREFIX: &str = "[tls]";
+
+/// 判断 call_v1_internal 返回的错误是否为 TLS 证书 / 握手错误
+pub fn is_tls_failure(error: &str) -> bool {
+    error.starts_with(TLS_ERROR_PREFIX)
+}
+
+/// 请求超时的错误前缀 (用于向客户端返回 504)
+pub const TIMEOUT_ERROR_PREFIX: &str = "[timeout]";
+
+/// 判断 call_v1_internal 返回的错误是否为请求超时
+pub fn is_timeout_failure(error: &str) -> bool {
+    error.starts_with(TIMEOUT_ERROR_PREFIX)
+}
 
 // Cloud Code v1internal endpoints (fallback order: prod → daily)
 // 优先使用稳定的 prod 端点，避免影响缓存命中率
@@ -14,13 +43,86 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
     V1_INTERNAL_BASE_URL_DAILY,  // 备用测试环境（新功能）
 ];
 
+/// 压测工具 (feature = "bench") 将 v1internal 请求指向进程内的模拟上游
+#[cfg(feature = "bench")]
+static BASE_URL_OVERRIDE: once_cell::sync::OnceCell<[&'static str; 1]> = once_cell::sync::OnceCell::new();
+
+/// 设置唯一的 v1internal 端点 (进程内只能设置一次)
+#[cfg(feature = "bench")]
+pub fn override_base_url(base_url: String) {
+    let _ = BASE_URL_OVERRIDE.set([Box::leak(base_url.into_boxed_str())]);
+}
+
+/// 按顺序尝试的 v1internal 端点
+fn v1_internal_base_urls() -> &'static [&'static str] {
+    #[cfg(feature = "bench")]
+    if let Some(urls) = BASE_URL_OVERRIDE.get() {
+        return urls;
+    }
+    &V1_INTERNAL_BASE_URL_FALLBACKS
+}
+
+/// 发起上游请求所用的账号凭据 (401 时据此刷新 access_token)
+pub struct AccountAuth<'a> {
+    pub token_manager: &'a crate::proxy::TokenManager,
+    pub email: &'a str,
+    pub access_token: &'a str,
+}
+
+/// 上游客户端 (服务启动时创建一次，所有请求共享连接池)
 pub struct UpstreamClient {
-    http_client: Client,
+    // 代理配置变更时整体替换；reqwest::Client 内部为 Arc，clone 开销极小
+    http_client: std::sync::RwLock<Client>,
+    // 网络状态重置后按当前代理配置重建连接池
+    proxy_config: std::sync::RwLock<Option<crate::proxy::config::UpstreamProxyConfig>>,
+    pool_generation: AtomicU64,
 }
 
 impl UpstreamClient {
     pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
-        let mut builder = Client::builder()
+        Self {
+            http_client: std::sync::RwLock::new(Self::build_http_client(proxy_config.clone())),
+            proxy_config: std::sync::RwLock::new(proxy_config),
+            pool_generation: AtomicU64::new(network_reset::pool_generation()),
+        }
+    }
+
+    /// 使用新的代理配置重建连接池 (热更新上游代理时调用)
+    pub fn rebuild(&self, proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) {
+        self.replace_client(proxy_config);
+        tracing::info!("UpstreamClient rebuilt with updated proxy config");
+    }
+
+    fn replace_client(&self, proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) {
+        let client = Self::build_http_client(proxy_config.clone());
+        match self.http_client.write() {
+            Ok(mut guard) => *guard = client,
+            Err(poisoned) => *poisoned.into_inner() = client,
+        }
+        match self.proxy_config.write() {
+            Ok(mut guard) => *guard = proxy_config,
+            Err(poisoned) => *poisoned.into_inner() = proxy_config,
+        }
+    }
+
+    /// 获取当前共享的 HTTP 客户端 (网络状态重置后先丢弃旧连接池)
+    fn client(&self) -> Client {
+        let generation = network_reset::pool_generation();
+        if self.pool_generation.swap(generation, Ordering::Relaxed) != generation {
+            let proxy_config = match self.proxy_config.read() {
+                Ok(guard) => guard.clone(),
+                Err(poisoned) => poisoned.into_inner().clone(),
+            };
+            self.replace_client(proxy_config);
+        }
+        match self.http_client.read() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn build_http_client(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Client {
+        let builder = Client::builder()
             // Connection settings (优化连接复用，减少建立开销)
             .connect_timeout(Duration::from_secs(20))
             .pool_max_idle_per_host(16)                  // 每主机最多 16 个空闲连接
@@ -29,18 +131,21 @@ impl UpstreamClient {
             .timeout(Duration::from_secs(600))
             .user_agent("antigravity/1.11.9 windows/amd64");
 
-        if let Some(config) = proxy_config {
-            if config.enabled && !config.url.is_empty() {
-                if let Ok(proxy) = reqwest::Proxy::all(&config.url) {
-                    builder = builder.proxy(proxy);
-                    tracing::info!("UpstreamClient enabled proxy: {}", config.url);
-                }
+        crate::utils::http::log_proxy_source(proxy_config.as_ref());
+        let builder = match crate::utils::http::apply_upstream_proxy(builder, proxy_config.as_ref()) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::error!("UpstreamClient proxy config invalid, falling back to direct: {}", e);
+                Client::builder()
+                    .connect_timeout(Duration::from_secs(20))
+                    .timeout(Duration::from_secs(600))
+                    .user_agent("antigravity/1.11.9 windows/amd64")
             }
-        }
+        };
 
-        let http_client = builder.build().expect("Failed to create HTTP client");
-
-        Self { http_client }
+        crate::utils::http::apply_upstream_tls(builder)
+            .build()
+            .expect("Failed to create HTTP client")
     }
 
     /// 构建 v1internal URL
@@ -78,6 +183,112 @@ impl UpstreamClient {
         body: Value,
         query_string: Option<&str>,
     ) -> Result<Response, String> {
+        self.call_v1_internal_with_timeout(method, access_token, body, query_string, None)
+            .await
+    }
+
+    /// 调用 v1internal API，并可为本次请求覆盖超时 (按模型配置的超时)
+    ///
+    /// timeout 只限制等待响应头的时间，流式响应体的读取不受其约束 (整体仍受连接池默认的 600 秒上限)
+    pub async fn call_v1_internal_with_timeout(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        // 链路追踪子 span (未启用时为 Span::none)
+        let span = crate::proxy::telemetry::upstream_span(method, &body);
+        let model = body.get("model").and_then(|m| m.as_str()).unwrap_or("unknown").to_string();
+        let started = std::time::Instant::now();
+        let result = self
+            .send_v1_internal(method, access_token, body, query_string, timeout)
+            .instrument(span.clone())
+            .await;
+        crate::proxy::telemetry::record_upstream_result(&span, &result);
+        if result.is_ok() {
+            crate::proxy::metrics::record_upstream_latency(&model, started.elapsed());
+        }
+        result
+    }
+
+    /// 调用 v1internal API；上游返回 401 时强制刷新该账号的 access_token，并在同一账号上重发一次
+    ///
+    /// 刷新失败 (含 invalid_grant) 时返回原始 401 响应，由调用方按原逻辑换号
+    pub async fn call_v1_internal_with_auth_refresh(
+        &self,
+        auth: AccountAuth<'_>,
+        method: &str,
+        mut body: Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        auth.token_manager.apply_session_id(auth.email, &mut body);
+        let response = self
+            .call_v1_internal_with_timeout(method, auth.access_token, body.clone(), query_string, timeout)
+            .await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        match auth
+            .token_manager
+            .force_refresh_token(auth.email, auth.access_token)
+            .await
+        {
+            Ok(access_token) => {
+                tracing::warn!("[Upstream] 401 on {}, retrying once with refreshed access_token", auth.email);
+                self.call_v1_internal_with_timeout(method, &access_token, body, query_string, timeout)
+                    .await
+            }
+            Err(e) => {
+                tracing::warn!("[Upstream] 401 on {} and token refresh failed: {}", auth.email, e);
+                Ok(response)
+            }
+        }
+    }
+
+    async fn send_v1_internal(
+        &self,
+        method: &str,
+        access_token: &str,
+        mut body: Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        // 请求上下文中使用 Idempotency-Key / 入站 X-Request-ID 作为上游 requestId，
+        // 同一客户端请求的重试保持不变，并按 requestId 记录发送结果；发送序号只记录在 span 中
+        let request_id = idempotency::stamp_request_id(&mut body);
+        let span = tracing::info_span!(
+            "upstream",
+            upstream_request_id = request_id.as_deref().unwrap_or(""),
+            attempt = tracing::field::Empty
+        );
+        if let Some(attempt) = crate::proxy::middleware::next_upstream_attempt() {
+            span.record("attempt", attempt);
+        }
+        self.send_with_fallback(method, access_token, body, query_string, timeout, request_id)
+            .instrument(span)
+            .await
+    }
+
+    /// 依次尝试各 v1internal 端点
+    async fn send_with_fallback(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+        request_id: Option<String>,
+    ) -> Result<Response, String> {
+        let record = |outcome| {
+            if let Some(id) = request_id.as_deref() {
+                idempotency::record_attempt(id, outcome);
+            }
+        };
+
         // 构建 Headers (所有端点复用)
         let mut headers = header::HeaderMap::new();
         headers.insert(
@@ -96,22 +307,42 @@ impl UpstreamClient {
 
         let mut last_err: Option<String> = None;
 
+        let http_client = self.client();
         // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        let base_urls = v1_internal_base_urls();
+        for (idx, base_url) in base_urls.iter().enumerate() {
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
-
-            let response = self
-                .http_client
-                .post(&url)
-                .headers(headers.clone())
-                .json(&body)
-                .send()
-                .await;
+            let has_next = idx + 1 < base_urls.len();
+
+            let request = http_client.post(&url).headers(headers.clone()).json(&body);
+            let response = match send_within(request, timeout).await {
+                Ok(response) => response,
+                Err(_) => {
+                    // 超时前未收到响应头，无法确定请求是否已到达上游
+                    record(AttemptOutcome::Unknown);
+                    let msg = format!(
+                        "{} No response headers from {} within {:?}",
+                        TIMEOUT_ERROR_PREFIX,
+                        base_url,
+                        timeout.unwrap_or_default()
+                    );
+                    tracing::debug!("{}", msg);
+                    last_err = Some(msg);
+                    if !has_next {
+                        break;
+                    }
+                    continue;
+                }
+            };
 
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    record(if status.is_success() {
+                        AttemptOutcome::Succeeded
+                    } else {
+                        AttemptOutcome::Rejected(status.as_u16())
+                    });
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -119,7 +350,7 @@ impl UpstreamClient {
                                 base_url,
                                 status,
                                 idx + 1,
-                                V1_INTERNAL_BASE_URL_FALLBACKS.len()
+                                base_urls.len()
                             );
                         } else {
                             tracing::debug!("✓ Upstream request succeeded | Endpoint: {} | Status: {}", base_url, status);
@@ -143,7 +374,33 @@ impl UpstreamClient {
                     return Ok(resp);
                 }
                 Err(e) => {
-                    let msg = format!("HTTP request failed at {}: {}", base_url, e);
+                    // 连接建立前失败的请求不会到达上游
+                    if !e.is_connect() {
+                        record(AttemptOutcome::Unknown);
+                    }
+                    if crate::utils::http::is_proxy_auth_error(&e) {
+                        let msg = format!(
+                            "{} 上游代理认证失败 (407)，请检查代理用户名/密码: {}",
+                            PROXY_AUTH_ERROR_PREFIX, e
+                        );
+                        tracing::error!("{}", msg);
+                        return Err(msg);
+                    }
+                    // 多个账号短时间内连续遇到连接重置：视为网络状态变化 (如休眠唤醒)
+                    if crate::utils::http::is_connection_reset_error(&e) {
+                        let account = access_token.get(access_token.len().saturating_sub(8)..).unwrap_or(access_token);
+                        network_reset::record_connection_reset(account);
+                    }
+                    if let Some(kind) = crate::utils::http::classify_tls_error(&e) {
+                        let msg = format!("{} {} ({}): {}", TLS_ERROR_PREFIX, kind.hint(), base_url, e);
+                        tracing::error!("{}", msg);
+                        return Err(msg);
+                    }
+                    let msg = if e.is_timeout() {
+                        format!("{} HTTP request failed at {}: {}", TIMEOUT_ERROR_PREFIX, base_url, e)
+                    } else {
+                        format!("HTTP request failed at {}: {}", base_url, e)
+                    };
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
 
@@ -199,12 +456,13 @@ impl UpstreamClient {
 
         let mut last_err: Option<String> = None;
 
+        let http_client = self.client();
         // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        let base_urls = v1_internal_base_urls();
+        for (idx, base_url) in base_urls.iter().enumerate() {
             let url = Self::build_url(base_url, "fetchAvailableModels", None);
 
-            let response = self
-                .http_client
+            let response = http_client
                 .post(&url)
                 .headers(headers.clone())
                 .json(&serde_json::json!({}))
@@ -232,7 +490,7 @@ impl UpstreamClient {
                     }
 
                     // 如果有下一个端点且当前错误可重试，则切换
-                    let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+                    let has_next = idx + 1 < base_urls.len();
                     if has_next && Self::should_try_next_endpoint(status) {
                         tracing::warn!(
                             "fetchAvailableModels returned {} at {}, trying next endpoint",
@@ -252,7 +510,7 @@ impl UpstreamClient {
                     last_err = Some(msg);
 
                     // 如果是最后一个端点，退出循环
-                    if idx + 1 >= V1_INTERNAL_BASE_URL_FALLBACKS.len() {
+                    if idx + 1 >= base_urls.len() {
                         break;
                     }
                     continue;
@@ -262,11 +520,104 @@ impl UpstreamClient {
 
         Err(last_err.unwrap_or_else(|| "All endpoints failed".to_string()))
     }
+
+    /// 调用 v1internal:countTokens 统计输入 Token (不生成内容)
+    ///
+    /// `generate_body` 为已转换好的 generateContent 请求体，复用其中的 contents / systemInstruction
+    pub async fn count_tokens(&self, access_token: &str, generate_body: &Value) -> Result<u64, String> {
+        let body = count_tokens_body(generate_body)?;
+        let response = self.call_v1_internal("countTokens", access_token, body, None).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("countTokens returned {}: {}", status.as_u16(), text));
+        }
+        let json: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse json failed: {}", e))?;
+        // proto3 JSON 省略 0 值
+        Ok(json.get("totalTokens").and_then(Value::as_u64).unwrap_or(0))
+    }
+}
+
+/// 由 generateContent 请求体构造 countTokens 请求体
+/// countTokens 只接受 contents，systemInstruction 作为首条 user 消息计入
+fn count_tokens_body(generate_body: &Value) -> Result<Value, String> {
+    let model = generate_body
+        .get("model")
+        .and_then(|v| v.as_str())
+        .ok_or("request body has no model")?;
+    let request = generate_body.get("request").ok_or("request body has no request")?;
+
+    let mut contents = Vec::new();
+    if let Some(parts) = request.get("systemInstruction").and_then(|s| s.get("parts")) {
+        contents.push(serde_json::json!({ "role": "user", "parts": parts }));
+    }
+    if let Some(list) = request.get("contents").and_then(|c| c.as_array()) {
+        contents.extend(list.iter().cloned());
+    }
+
+    Ok(serde_json::json!({
+        "request": {
+            "model": format!("models/{}", model),
+            "contents": contents
+        }
+    }))
+}
+
+/// 发送请求；timeout 只限制等待响应头的时间，已开始的流式响应不会因此被中断
+async fn send_within(
+    request: reqwest::RequestBuilder,
+    timeout: Option<Duration>,
+) -> Result<reqwest::Result<Response>, tokio::time::error::Elapsed> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request.send()).await,
+        None => Ok(request.send().await),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::StreamExt;
+
+    /// 本地测试服务：延迟 header_delay 后返回响应头，之后每隔 chunk_delay 输出一个 SSE 事件
+    async fn spawn_stream_server(header_delay: Duration, chunk_delay: Duration) -> String {
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::post(move || async move {
+                tokio::time::sleep(header_delay).await;
+                let chunks = futures::stream::iter(0..3).then(move |i| async move {
+                    tokio::time::sleep(chunk_delay).await;
+                    Ok::<_, std::io::Error>(format!("data: {}\n\n", i))
+                });
+                axum::body::Body::from_stream(chunks)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_stream_outlives_request_timeout() {
+        let client = Client::builder().no_proxy().build().unwrap();
+        let timeout = Some(Duration::from_millis(300));
+
+        // 响应头立即返回，流持续约 600ms，超过 timeout 仍能完整读取
+        let url = spawn_stream_server(Duration::ZERO, Duration::from_millis(200)).await;
+        let started = std::time::Instant::now();
+        let response = send_within(client.post(&url), timeout).await.unwrap().unwrap();
+        let body = response.text().await.unwrap();
+        assert!(started.elapsed() > Duration::from_millis(300));
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+
+        // 超时前没有响应头则放弃该端点
+        let url = spawn_stream_server(Duration::from_secs(5), Duration::ZERO).await;
+        assert!(send_within(client.post(&url), timeout).await.is_err());
+    }
 
     #[test]
     fn test_build_url() {
@@ -285,4 +636,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_count_tokens_body_folds_system_instruction() {
+        let generate_body = serde_json::json!({
+            "project": "p",
+            "requestId": "agent-1",
+            "model": "gemini-2.5-flash",
+            "request": {
+                "systemInstruction": { "role": "user", "parts": [{ "text": "be brief" }] },
+                "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+                "generationConfig": { "maxOutputTokens": 64000 }
+            }
+        });
+        let body = count_tokens_body(&generate_body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "request": {
+                    "model": "models/gemini-2.5-flash",
+                    "contents": [
+                        { "role": "user", "parts": [{ "text": "be brief" }] },
+                        { "role": "user", "parts": [{ "text": "hi" }] }
+                    ]
+                }
+            })
+        );
+        assert!(count_tokens_body(&serde_json::json!({ "request": {} })).is_err());
+    }
+
 }
@@ -0,0 +1,572 @@
+// 上游错误映射
+// 重试/轮换耗尽后，将最后一次上游失败转换为 OpenAI / Anthropic 协议规定的状态码与错误结构
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+
+use crate::proxy::key_scope::ModelNotAllowed;
+use crate::proxy::token_manager::{PoolStatus, PoolUnavailable};
+
+/// 上游未给出任何重试提示时返回给客户端的 Retry-After (秒)
+const DEFAULT_RETRY_AFTER_SECS: u64 = 30;
+
+/// 一次上游调用的失败结果 (在重试循环中保留最后一次)
+#[derive(Debug, Clone)]
+pub enum UpstreamFailure {
+    /// 上游返回了非 2xx 响应
+    Status {
+        status: u16,
+        body: String,
+        retry_after: Option<String>,
+    },
+    /// 请求超时
+    Timeout(String),
+    /// TLS 证书校验 / 握手失败 (多为 SSL 拦截代理)
+    Tls(String),
+    /// 其它网络错误 (连接失败、TLS 等)
+    Network(String),
+}
+
+impl UpstreamFailure {
+    pub fn status(status: u16, body: impl Into<String>, retry_after: Option<String>) -> Self {
+        UpstreamFailure::Status {
+            status,
+            body: body.into(),
+            retry_after,
+        }
+    }
+
+    /// 根据 call_v1_internal 返回的错误文本区分超时与其它网络错误
+    pub fn transport(error: impl Into<String>) -> Self {
+        let error = error.into();
+        if crate::proxy::upstream::client::is_timeout_failure(&error) {
+            UpstreamFailure::Timeout(error)
+        } else if crate::proxy::upstream::client::is_tls_failure(&error) {
+            UpstreamFailure::Tls(error)
+        } else {
+            UpstreamFailure::Network(error)
+        }
+    }
+
+    /// 面向客户端的错误信息：优先取 Google 错误体中的 error.message
+    pub fn message(&self) -> String {
+        match self {
+            UpstreamFailure::Status { status, body, .. } => {
+                extract_google_message(body).unwrap_or_else(|| {
+                    if body.trim().is_empty() {
+                        format!("Upstream returned HTTP {}", status)
+                    } else {
+                        body.clone()
+                    }
+                })
+            }
+            UpstreamFailure::Timeout(e) => format!("Upstream request timed out: {}", e),
+            UpstreamFailure::Network(e) => format!("Upstream request failed: {}", e),
+            UpstreamFailure::Tls(e) => {
+                let detail = e.trim_start_matches(crate::proxy::upstream::client::TLS_ERROR_PREFIX).trim();
+                format!("Upstream TLS verification failed: {}", detail)
+            }
+        }
+    }
+
+    /// 429 时返回给客户端的 Retry-After：上游响应头 > 错误体 RetryInfo > 默认值
+    fn retry_after_secs(&self) -> Option<u64> {
+        let UpstreamFailure::Status {
+            status: 429,
+            body,
+            retry_after,
+        } = self
+        else {
+            return None;
+        };
+        let from_header = retry_after
+            .as_deref()
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        let from_body = || {
+            crate::proxy::upstream::retry::parse_retry_delay(body).map(|ms| ms.div_ceil(1000).max(1))
+        };
+        Some(from_header.or_else(from_body).unwrap_or(DEFAULT_RETRY_AFTER_SECS))
+    }
+}
+
+/// 单个失败在两种协议下的状态码与错误类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ErrorMapping {
+    openai_status: StatusCode,
+    openai_type: &'static str,
+    openai_code: Option<&'static str>,
+    anthropic_status: StatusCode,
+    anthropic_type: &'static str,
+}
+
+fn mapping_for(failure: Option<&UpstreamFailure>) -> ErrorMapping {
+    let status = match failure {
+        Some(UpstreamFailure::Status { status, .. }) => *status,
+        Some(UpstreamFailure::Timeout(_)) => {
+            return ErrorMapping {
+                openai_status: StatusCode::GATEWAY_TIMEOUT,
+                openai_type: "server_error",
+                openai_code: Some("timeout"),
+                anthropic_status: StatusCode::GATEWAY_TIMEOUT,
+                anthropic_type: "api_error",
+            }
+        }
+        Some(UpstreamFailure::Tls(_)) => {
+            return ErrorMapping {
+                openai_status: StatusCode::BAD_GATEWAY,
+                openai_type: "server_error",
+                openai_code: Some("upstream_tls_error"),
+                anthropic_status: StatusCode::BAD_GATEWAY,
+                anthropic_type: "api_error",
+            }
+        }
+        Some(UpstreamFailure::Network(_)) => {
+            return ErrorMapping {
+                openai_status: StatusCode::BAD_GATEWAY,
+                openai_type: "server_error",
+                openai_code: None,
+                anthropic_status: StatusCode::BAD_GATEWAY,
+                anthropic_type: "api_error",
+            }
+        }
+        // 没有任何上游响应 (例如账号全部不可用)
+        None => 503,
+    };
+
+    let same = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let (openai_type, openai_code, anthropic_type) = match status {
+        400 => ("invalid_request_error", None, "invalid_request_error"),
+        401 => ("authentication_error", Some("invalid_api_key"), "authentication_error"),
+        403 => ("permission_error", None, "permission_error"),
+        404 => ("invalid_request_error", Some("model_not_found"), "not_found_error"),
+        413 => ("invalid_request_error", Some("context_length_exceeded"), "request_too_large"),
+        429 => ("rate_limit_error", Some("rate_limit_exceeded"), "rate_limit_error"),
+        503 | 529 => {
+            return ErrorMapping {
+                openai_status: StatusCode::SERVICE_UNAVAILABLE,
+                openai_type: "server_error",
+                openai_code: Some("overloaded"),
+                // Anthropic 以 529 表示过载
+                anthropic_status: StatusCode::from_u16(529).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+                anthropic_type: "overloaded_error",
+            }
+        }
+        s if (400..500).contains(&s) => ("invalid_request_error", None, "invalid_request_error"),
+        _ => ("server_error", None, "api_error"),
+    };
+    ErrorMapping {
+        openai_status: same,
+        openai_type,
+        openai_code,
+        anthropic_status: same,
+        anthropic_type,
+    }
+}
+
+/// Google 错误体: {"error": {"code", "message", "status"}}，流式端点可能包在数组中
+fn extract_google_message(body: &str) -> Option<String> {
+    let json: Value = serde_json::from_str(body).ok()?;
+    let error = match &json {
+        Value::Array(items) => items.first()?.get("error")?,
+        _ => json.get("error")?,
+    };
+    error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .filter(|m| !m.is_empty())
+        .map(|m| m.to_string())
+}
+
+fn with_retry_after(mut resp: Response, failure: Option<&UpstreamFailure>) -> Response {
+    if let Some(secs) = failure.and_then(|f| f.retry_after_secs()) {
+        if let Ok(value) = HeaderValue::from_str(&secs.to_string()) {
+            resp.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+    }
+    resp
+}
+
+/// 按 OpenAI 错误结构返回最后一次上游失败
+pub fn openai_error_response(failure: Option<&UpstreamFailure>) -> Response {
+    let mapping = mapping_for(failure);
+    let message = failure
+        .map(|f| f.message())
+        .unwrap_or_else(|| "No available accounts to serve the request".to_string());
+    let body = json!({
+        "error": {
+            "message": message,
+            "type": mapping.openai_type,
+            "param": null,
+            "code": mapping.openai_code,
+        }
+    });
+    with_retry_after((mapping.openai_status, Json(body)).into_response(), failure)
+}
+
+/// 按 Anthropic Messages API 错误结构返回最后一次上游失败
+pub fn anthropic_error_response(failure: Option<&UpstreamFailure>) -> Response {
+    let mapping = mapping_for(failure);
+    let message = failure
+        .map(|f| f.message())
+        .unwrap_or_else(|| "No available accounts to serve the request".to_string());
+    let body = json!({
+        "type": "error",
+        "error": {
+            "type": mapping.anthropic_type,
+            "message": message,
+        }
+    });
+    with_retry_after((mapping.anthropic_status, Json(body)).into_response(), failure)
+}
+
+/// 账号池不可用时在两种协议下的状态码与错误类型 (Empty / 冷却 / 不健康 / 限速各不相同)
+fn pool_mapping(status: &PoolStatus) -> ErrorMapping {
+    let overloaded = StatusCode::from_u16(529).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    match status {
+        PoolStatus::Empty => ErrorMapping {
+            openai_status: StatusCode::SERVICE_UNAVAILABLE,
+            openai_type: "server_error",
+            openai_code: Some("no_accounts_configured"),
+            anthropic_status: StatusCode::SERVICE_UNAVAILABLE,
+            anthropic_type: "api_error",
+        },
+        PoolStatus::AllCoolingDown { .. } => ErrorMapping {
+            openai_status: StatusCode::TOO_MANY_REQUESTS,
+            openai_type: "rate_limit_error",
+            openai_code: Some("accounts_cooling_down"),
+            anthropic_status: StatusCode::TOO_MANY_REQUESTS,
+            anthropic_type: "rate_limit_error",
+        },
+        PoolStatus::AllUnhealthy => ErrorMapping {
+            openai_status: StatusCode::SERVICE_UNAVAILABLE,
+            openai_type: "server_error",
+            openai_code: Some("accounts_unhealthy"),
+            anthropic_status: StatusCode::SERVICE_UNAVAILABLE,
+            anthropic_type: "overloaded_error",
+        },
+        PoolStatus::AllSaturated => ErrorMapping {
+            openai_status: StatusCode::SERVICE_UNAVAILABLE,
+            openai_type: "server_error",
+            openai_code: Some("accounts_saturated"),
+            anthropic_status: overloaded,
+            anthropic_type: "overloaded_error",
+        },
+        PoolStatus::AllBusy => ErrorMapping {
+            openai_status: StatusCode::TOO_MANY_REQUESTS,
+            openai_type: "rate_limit_error",
+            openai_code: Some("accounts_busy"),
+            anthropic_status: StatusCode::TOO_MANY_REQUESTS,
+            anthropic_type: "rate_limit_error",
+        },
+        PoolStatus::AllCapped { .. } => ErrorMapping {
+            openai_status: StatusCode::TOO_MANY_REQUESTS,
+            openai_type: "rate_limit_error",
+            openai_code: Some("accounts_daily_capped"),
+            anthropic_status: StatusCode::TOO_MANY_REQUESTS,
+            anthropic_type: "rate_limit_error",
+        },
+        PoolStatus::AllOffSchedule { .. } => ErrorMapping {
+            openai_status: StatusCode::SERVICE_UNAVAILABLE,
+            openai_type: "server_error",
+            openai_code: Some("accounts_off_schedule"),
+            anthropic_status: StatusCode::SERVICE_UNAVAILABLE,
+            anthropic_type: "api_error",
+        },
+    }
+}
+
+/// 面向客户端的账号池说明 (不包含账号邮箱等内部细节)
+fn pool_message(error: &PoolUnavailable) -> String {
+    match &error.status {
+        PoolStatus::Empty => "No accounts are configured in the proxy. Add an account in Antigravity Manager to start serving requests.".to_string(),
+        PoolStatus::AllCoolingDown { .. } => format!(
+            "All accounts are rate-limited and cooling down. Retry after {}s.",
+            error.status.retry_after_secs().unwrap_or(1)
+        ),
+        PoolStatus::AllUnhealthy if error.message.contains("invalid_grant") => {
+            "All accounts are unavailable: OAuth refresh failed (invalid_grant); reauthorize account(s) to restore service.".to_string()
+        }
+        PoolStatus::AllUnhealthy => "All accounts are currently unhealthy (circuit open or token refresh failed). Check account status in Antigravity Manager.".to_string(),
+        PoolStatus::AllSaturated => "All accounts have reached their per-minute request limit. Retry shortly.".to_string(),
+        PoolStatus::AllBusy => "All accounts are at their concurrent request limit. Retry shortly.".to_string(),
+        PoolStatus::AllCapped { .. } => format!(
+            "All accounts have reached their configured daily cap. Service resumes after the daily rollover in {}s.",
+            error.status.retry_after_secs().unwrap_or(1)
+        ),
+        PoolStatus::AllOffSchedule { .. } => format!(
+            "All accounts are outside their configured availability windows. The next window opens in {}s.",
+            error.status.retry_after_secs().unwrap_or(1)
+        ),
+    }
+}
+
+fn with_pool_retry_after(mut resp: Response, status: &PoolStatus) -> Response {
+    if let Some(secs) = status.retry_after_secs() {
+        if let Ok(value) = HeaderValue::from_str(&secs.to_string()) {
+            resp.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+    }
+    resp
+}
+
+/// 按 OpenAI 错误结构返回账号池不可用
+pub fn openai_pool_error_response(error: &PoolUnavailable) -> Response {
+    let mapping = pool_mapping(&error.status);
+    let body = json!({
+        "error": {
+            "message": pool_message(error),
+            "type": mapping.openai_type,
+            "param": null,
+            "code": mapping.openai_code,
+        }
+    });
+    with_pool_retry_after((mapping.openai_status, Json(body)).into_response(), &error.status)
+}
+
+/// 按 Anthropic 错误结构返回账号池不可用
+pub fn anthropic_pool_error_response(error: &PoolUnavailable) -> Response {
+    let mapping = pool_mapping(&error.status);
+    let body = json!({
+        "type": "error",
+        "error": {
+            "type": mapping.anthropic_type,
+            "message": pool_message(error),
+        }
+    });
+    with_pool_retry_after((mapping.anthropic_status, Json(body)).into_response(), &error.status)
+}
+
+const RETRY_BUDGET_MESSAGE: &str =
+    "The proxy retry budget is exhausted due to sustained upstream errors. Retry shortly.";
+
+fn with_budget_retry_after(mut resp: Response) -> Response {
+    resp.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+    resp
+}
+
+/// 全局重试预算耗尽 (OpenAI 错误结构，503)
+pub fn openai_retry_budget_response() -> Response {
+    let body = json!({
+        "error": {
+            "message": RETRY_BUDGET_MESSAGE,
+            "type": "server_error",
+            "param": null,
+            "code": "retry_budget_exhausted",
+        }
+    });
+    with_budget_retry_after((StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response())
+}
+
+/// 全局重试预算耗尽 (Anthropic 错误结构，503)
+pub fn anthropic_retry_budget_response() -> Response {
+    let body = json!({
+        "type": "error",
+        "error": {
+            "type": "overloaded_error",
+            "message": RETRY_BUDGET_MESSAGE,
+        }
+    });
+    with_budget_retry_after((StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response())
+}
+
+/// API Key 无权使用请求的模型 (OpenAI 错误结构，403)
+pub fn openai_model_not_allowed_response(error: &ModelNotAllowed) -> Response {
+    let body = json!({
+        "error": {
+            "message": error.message(),
+            "type": "permission_error",
+            "param": "model",
+            "code": "model_not_allowed",
+        }
+    });
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
+/// API Key 无权使用请求的模型 (Anthropic 错误结构，403)
+pub fn anthropic_model_not_allowed_response(error: &ModelNotAllowed) -> Response {
+    let body = json!({
+        "type": "error",
+        "error": {
+            "type": "permission_error",
+            "message": error.message(),
+        }
+    });
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
+/// 处理器本地产生的错误 (参数校验等) 也统一为 OpenAI 错误结构，状态码保持不变
+pub fn openai_error_for_status(status: StatusCode, message: String) -> Response {
+    let mapping = mapping_for(Some(&UpstreamFailure::status(status.as_u16(), "", None)));
+    let body = json!({
+        "error": {
+            "message": message,
+            "type": mapping.openai_type,
+            "param": null,
+            "code": mapping.openai_code,
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (上游失败, OpenAI 状态码, OpenAI 类型, Anthropic 状态码, Anthropic 类型, Retry-After)
+    type Case = (UpstreamFailure, u16, &'static str, u16, &'static str, Option<&'static str>);
+
+    async fn body_json(resp: Response) -> Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn maps_upstream_failures_to_protocol_errors() {
+        let quota_body = r#"{"error":{"code":429,"message":"Resource has been exhausted","status":"RESOURCE_EXHAUSTED","details":[{"@type":"type.googleapis.com/google.rpc.RetryInfo","retryDelay":"7.2s"}]}}"#;
+        let cases: Vec<Case> = vec![
+            (
+                UpstreamFailure::status(400, r#"{"error":{"code":400,"message":"Invalid JSON payload","status":"INVALID_ARGUMENT"}}"#, None),
+                400, "invalid_request_error", 400, "invalid_request_error", None,
+            ),
+            (
+                UpstreamFailure::status(401, r#"{"error":{"code":401,"message":"Request had invalid authentication credentials","status":"UNAUTHENTICATED"}}"#, None),
+                401, "authentication_error", 401, "authentication_error", None,
+            ),
+            (
+                UpstreamFailure::status(403, r#"[{"error":{"code":403,"message":"The caller does not have permission","status":"PERMISSION_DENIED"}}]"#, None),
+                403, "permission_error", 403, "permission_error", None,
+            ),
+            (
+                UpstreamFailure::status(429, quota_body, None),
+                429, "rate_limit_error", 429, "rate_limit_error", Some("8"),
+            ),
+            (
+                UpstreamFailure::status(429, quota_body, Some("12".to_string())),
+                429, "rate_limit_error", 429, "rate_limit_error", Some("12"),
+            ),
+            (
+                UpstreamFailure::status(429, "Too Many Requests", None),
+                429, "rate_limit_error", 429, "rate_limit_error", Some("30"),
+            ),
+            (
+                UpstreamFailure::status(503, r#"{"error":{"code":503,"message":"The service is currently unavailable","status":"UNAVAILABLE"}}"#, None),
+                503, "server_error", 529, "overloaded_error", None,
+            ),
+            (
+                UpstreamFailure::status(500, "internal", None),
+                500, "server_error", 500, "api_error", None,
+            ),
+            (
+                UpstreamFailure::transport(format!(
+                    "{} HTTP request failed at https://cloudcode-pa.googleapis.com/v1internal: operation timed out",
+                    crate::proxy::upstream::client::TIMEOUT_ERROR_PREFIX
+                )),
+                504, "server_error", 504, "api_error", None,
+            ),
+            (
+                UpstreamFailure::transport("HTTP request failed at https://cloudcode-pa.googleapis.com/v1internal: connection refused"),
+                502, "server_error", 502, "api_error", None,
+            ),
+            (
+                UpstreamFailure::transport(format!(
+                    "{} upstream certificate is not trusted: invalid peer certificate: UnknownIssuer",
+                    crate::proxy::upstream::client::TLS_ERROR_PREFIX
+                )),
+                502, "server_error", 502, "api_error", None,
+            ),
+        ];
+
+        for (failure, oa_status, oa_type, an_status, an_type, retry_after) in cases {
+            let label = format!("{:?}", failure);
+
+            let resp = openai_error_response(Some(&failure));
+            assert_eq!(resp.status().as_u16(), oa_status, "openai status: {}", label);
+            assert_eq!(
+                resp.headers().get(header::RETRY_AFTER).and_then(|v| v.to_str().ok()),
+                retry_after,
+                "openai retry-after: {}",
+                label
+            );
+            let body = body_json(resp).await;
+            assert_eq!(body["error"]["type"], oa_type, "openai type: {}", label);
+            assert!(body["error"]["message"].as_str().is_some_and(|m| !m.is_empty()));
+
+            let resp = anthropic_error_response(Some(&failure));
+            assert_eq!(resp.status().as_u16(), an_status, "anthropic status: {}", label);
+            assert_eq!(
+                resp.headers().get(header::RETRY_AFTER).and_then(|v| v.to_str().ok()),
+                retry_after,
+                "anthropic retry-after: {}",
+                label
+            );
+            let body = body_json(resp).await;
+            assert_eq!(body["type"], "error");
+            assert_eq!(body["error"]["type"], an_type, "anthropic type: {}", label);
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_pool_states_for_both_protocols() {
+        let until = chrono::Utc::now().timestamp() + 42;
+        // (状态, OpenAI 状态码, OpenAI code, Anthropic 状态码, Anthropic 类型, 是否带 Retry-After)
+        let cases = [
+            (PoolStatus::Empty, 503, "no_accounts_configured", 503, "api_error", false),
+            (PoolStatus::AllCoolingDown { until }, 429, "accounts_cooling_down", 429, "rate_limit_error", true),
+            (PoolStatus::AllUnhealthy, 503, "accounts_unhealthy", 503, "overloaded_error", false),
+            (PoolStatus::AllSaturated, 503, "accounts_saturated", 529, "overloaded_error", false),
+            (PoolStatus::AllBusy, 429, "accounts_busy", 429, "rate_limit_error", true),
+        ];
+
+        let mut messages = std::collections::HashSet::new();
+        for (status, oa_status, oa_code, an_status, an_type, has_retry_after) in cases {
+            let error = PoolUnavailable {
+                status: status.clone(),
+                message: "Failed to fetch project_id for user@example.com".to_string(),
+            };
+
+            let resp = openai_pool_error_response(&error);
+            assert_eq!(resp.status().as_u16(), oa_status, "{:?}", status);
+            let retry_after = resp
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            assert_eq!(retry_after.is_some(), has_retry_after, "{:?}", status);
+            if let Some(secs) = retry_after {
+                let expected = if status == PoolStatus::AllBusy { 1..=1 } else { 41..=42 };
+                assert!(expected.contains(&secs), "{:?}", status);
+            }
+            let body = body_json(resp).await;
+            assert_eq!(body["error"]["code"], oa_code);
+            let message = body["error"]["message"].as_str().unwrap().to_string();
+            assert!(!message.contains("user@example.com"));
+            messages.insert(message);
+
+            let resp = anthropic_pool_error_response(&error);
+            assert_eq!(resp.status().as_u16(), an_status, "{:?}", status);
+            assert_eq!(resp.headers().contains_key(header::RETRY_AFTER), has_retry_after);
+            let body = body_json(resp).await;
+            assert_eq!(body["type"], "error");
+            assert_eq!(body["error"]["type"], an_type, "{:?}", status);
+        }
+        // 每种状态的提示文本都不同
+        assert_eq!(messages.len(), 5);
+    }
+
+    #[test]
+    fn prefers_google_error_message() {
+        let failure = UpstreamFailure::status(
+            400,
+            r#"{"error":{"code":400,"message":"Invalid JSON payload","status":"INVALID_ARGUMENT"}}"#,
+            None,
+        );
+        assert_eq!(failure.message(), "Invalid JSON payload");
+        let tls = UpstreamFailure::transport("[tls] certificate is not trusted: UnknownIssuer");
+        assert_eq!(tls.message(), "Upstream TLS verification failed: certificate is not trusted: UnknownIssuer");
+        assert_eq!(UpstreamFailure::status(502, "", None).message(), "Upstream returned HTTP 502");
+    }
+}
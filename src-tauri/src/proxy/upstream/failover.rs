@@ -0,0 +1,286 @@
+// 流式响应中途故障转移
+// 上游 SSE 流在输出任何内容 token 之前断开时 (例如只收到了首包元数据)，换下一个账号重新发起请求，
+// 并把新流接在同一个客户端 SSE 会话后面；已经输出内容后再断开则无法无缝衔接，交由调用方输出错误事件。
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::{AccountAuth, UpstreamClient};
+
+/// 上游原始 SSE 字节流
+pub type UpstreamStream<E> = Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>;
+
+/// 重新发起请求的结果 (参数为第几次故障转移，从 1 开始)
+pub type ReopenFuture<E> = Pin<Box<dyn Future<Output = Result<UpstreamStream<E>, String>> + Send>>;
+
+/// 包装上游流：按整行转发，在首个内容 token 之前出错时调用 `reopen` 换号重试，最多 `max_failovers` 次
+///
+/// 只转发完整行，断流时残留的半行会被丢弃，保证下游解析器不会拼接出损坏的 JSON。
+pub fn with_stream_failover<E, F>(
+    first: UpstreamStream<E>,
+    max_failovers: usize,
+    mut reopen: F,
+) -> UpstreamStream<String>
+where
+    E: Display + Send + 'static,
+    F: FnMut(usize) -> ReopenFuture<E> + Send + 'static,
+{
+    Box::pin(async_stream::stream! {
+        let mut current = first;
+        let mut buffer = BytesMut::new();
+        let mut content_started = false;
+        let mut failovers = 0;
+
+        loop {
+            match current.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line = buffer.split_to(pos + 1).freeze();
+                        if !content_started && line_has_content(&line) {
+                            content_started = true;
+                        }
+                        yield Ok(line);
+                    }
+                }
+                Some(Err(e)) => {
+                    if content_started || failovers >= max_failovers {
+                        crate::proxy::metrics::record_stream_error();
+                        yield Err(e.to_string());
+                        break;
+                    }
+                    failovers += 1;
+                    buffer.clear();
+                    tracing::warn!(
+                        "Upstream stream died before any content ({}), failing over to next account ({}/{})",
+                        e, failovers, max_failovers
+                    );
+                    crate::proxy::metrics::record_retry("stream_failover");
+                    match reopen(failovers).await {
+                        Ok(next) => current = next,
+                        Err(reopen_err) => {
+                            crate::proxy::metrics::record_stream_error();
+                            yield Err(format!("{}; failover failed: {}", e, reopen_err));
+                            break;
+                        }
+                    }
+                }
+                None => {
+                    if !buffer.is_empty() {
+                        yield Ok(buffer.split().freeze());
+                    }
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// 换号重新发起流式请求所需的上下文 (由 handler 在首次请求成功后构造)
+pub struct StreamReopenContext {
+    pub token_manager: Arc<TokenManager>,
+    pub upstream: Arc<UpstreamClient>,
+    pub request_type: String,
+    /// 路由解析后的上游模型名，重新选账号时跳过无权访问的账号
+    pub model: String,
+    pub session_id: String,
+    pub timeout: Duration,
+    pub trace_id: String,
+}
+
+impl StreamReopenContext {
+    /// 生成 `with_stream_failover` 使用的 reopen 闭包
+    /// `build_body` 根据新账号的 project_id 重新构造 v1internal 请求体
+    pub fn into_reopen<B>(self, build_body: B) -> impl FnMut(usize) -> ReopenFuture<reqwest::Error> + Send + 'static
+    where
+        B: Fn(&str) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        // 流在请求任务之外被消费，task-local 的请求 ID 此时已不可用，提前取出
+        let request_id = crate::proxy::middleware::upstream_request_id();
+        let ctx = Arc::new(self);
+        let build_body = Arc::new(build_body);
+        move |attempt| {
+            let ctx = ctx.clone();
+            let build_body = build_body.clone();
+            let request_id = request_id.clone();
+            let span = tracing::info_span!(
+                "reopen",
+                upstream_request_id = request_id.as_deref().unwrap_or(""),
+                reopen_attempt = attempt
+            );
+            // 续传发生在请求任务之外，单独建立并发许可上下文，由 meter_stream 交给新的上游流
+            Box::pin(crate::proxy::account_concurrency::scope_account_permit(async move {
+                let (access_token, project_id, email) = ctx
+                    .token_manager
+                    .get_token(&ctx.request_type, Some(&ctx.model), true, Some(&ctx.session_id))
+                    .await?;
+                let mut body = build_body(&project_id)?;
+                // 续传沿用同一 requestId，续传序号记录在 reopen span 中
+                if let Some(id) = request_id {
+                    body["requestId"] = Value::String(id);
+                }
+
+                let auth = AccountAuth {
+                    token_manager: &ctx.token_manager,
+                    email: &email,
+                    access_token: &access_token,
+                };
+                let response = ctx
+                    .upstream
+                    .call_v1_internal_with_auth_refresh(
+                        auth,
+                        "streamGenerateContent",
+                        body,
+                        Some("alt=sse"),
+                        Some(ctx.timeout),
+                    )
+                    .await?;
+                let status = response.status();
+                if !status.is_success() {
+                    ctx.token_manager.report_failure(&email, status.as_u16());
+                    return Err(format!("HTTP {} on failover account {}", status.as_u16(), email));
+                }
+
+                tracing::info!("[{}] Stream failover: continuing on account {}", ctx.trace_id, email);
+                ctx.token_manager.report_success(&email);
+                ctx.token_manager.bind_session(&ctx.session_id, &email).await;
+                Ok(Box::pin(ctx.token_manager.meter_stream(&email, response.bytes_stream())) as UpstreamStream<reqwest::Error>)
+            }.instrument(span))) as ReopenFuture<reqwest::Error>
+        }
+    }
+}
+
+/// 该 SSE 行是否携带会转发给客户端的内容 (文本 / 思考 / 工具调用 / 图片等)
+fn line_has_content(line: &[u8]) -> bool {
+    let Some(data) = std::str::from_utf8(line)
+        .ok()
+        .and_then(|l| l.trim().strip_prefix("data:"))
+        .map(str::trim)
+    else {
+        return false;
+    };
+    let Ok(json) = serde_json::from_str::<Value>(data) else {
+        return false;
+    };
+    let raw = json.get("response").unwrap_or(&json);
+    raw.get("candidates")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.pointer("/content/parts").and_then(|p| p.as_array()))
+        .flatten()
+        .any(|part| part.get("text").and_then(|t| t.as_str()).is_none_or(|t| !t.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const METADATA: &str = "data: {\"response\":{\"usageMetadata\":{\"promptTokenCount\":12}}}\n\n";
+
+    fn text_line(text: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            serde_json::json!({"response": {"candidates": [{"content": {"parts": [{"text": text}]}}]}})
+        )
+    }
+
+    /// 模拟上游：输出给定的 chunk 后断开连接
+    fn dropping_upstream(chunks: Vec<String>) -> UpstreamStream<String> {
+        let items: Vec<Result<Bytes, String>> = chunks
+            .into_iter()
+            .map(|c| Ok(Bytes::from(c)))
+            .chain(std::iter::once(Err("connection reset".to_string())))
+            .collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    fn healthy_upstream(chunks: Vec<String>) -> UpstreamStream<String> {
+        Box::pin(futures::stream::iter(chunks.into_iter().map(|c| Ok(Bytes::from(c)))))
+    }
+
+    async fn collect(stream: UpstreamStream<String>) -> (String, Option<String>) {
+        let mut out = String::new();
+        let mut err = None;
+        let items: Vec<_> = stream.collect().await;
+        for item in items {
+            match item {
+                Ok(b) => out.push_str(std::str::from_utf8(&b).unwrap()),
+                Err(e) => err = Some(e),
+            }
+        }
+        (out, err)
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_when_stream_dies_before_content() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_in = calls.clone();
+        // 首包元数据后断开，且残留半行
+        let first = dropping_upstream(vec![METADATA.to_string(), "data: {\"resp".to_string()]);
+        let stream = with_stream_failover(first, 2, move |n| {
+            calls_in.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(n, 1);
+            Box::pin(async { Ok(healthy_upstream(vec![text_line("hello"), text_line(" world")])) })
+        });
+
+        let (out, err) = collect(stream).await;
+        assert!(err.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(out.starts_with(METADATA.trim_end()));
+        assert!(!out.contains("{\"resp\n"));
+        assert!(out.contains("hello") && out.contains(" world"));
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_after_content_was_sent() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_in = calls.clone();
+        let first = dropping_upstream(vec![METADATA.to_string(), text_line("partial")]);
+        let stream = with_stream_failover(first, 2, move |_| {
+            calls_in.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(healthy_upstream(vec![text_line("should not appear")])) })
+        });
+
+        let (out, err) = collect(stream).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(out.contains("partial"));
+        assert!(!out.contains("should not appear"));
+        assert_eq!(err.as_deref(), Some("connection reset"));
+    }
+
+    #[tokio::test]
+    async fn test_failover_budget_is_bounded() {
+        let stream = with_stream_failover(dropping_upstream(vec![]), 1, |_| {
+            Box::pin(async { Ok(dropping_upstream(vec![METADATA.to_string()])) })
+        });
+        let (_, err) = collect(stream).await;
+        assert_eq!(err.as_deref(), Some("connection reset"));
+
+        let stream = with_stream_failover(dropping_upstream(vec![]), 3, |_| {
+            Box::pin(async { Err::<UpstreamStream<String>, _>("No available accounts".to_string()) })
+        });
+        let (_, err) = collect(stream).await;
+        assert_eq!(err.as_deref(), Some("connection reset; failover failed: No available accounts"));
+    }
+
+    #[test]
+    fn test_line_has_content() {
+        assert!(!line_has_content(METADATA.as_bytes()));
+        assert!(!line_has_content(text_line("").as_bytes()));
+        assert!(line_has_content(text_line("hi").as_bytes()));
+        assert!(line_has_content(
+            b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"functionCall\":{\"name\":\"f\",\"args\":{}}}]}}]}\n"
+        ));
+        assert!(!line_has_content(b"data: [DONE]\n"));
+    }
+}
@@ -0,0 +1,167 @@
+// 上游提交记录 (重复生成检测)
+// 同一客户端请求的所有重试复用同一个上游 requestId (见 middleware::upstream_request_id)。
+// 传输层错误 (超时 / 连接中断) 时无法确定上游是否已处理该请求，之后同一 requestId 再次成功
+// 即可能产生了重复生成与重复配额消耗；这里按 requestId 记录每次发送结果，发现时输出告警
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 记录保留时长 (秒)，超过后视为新的请求
+const ENTRY_TTL_SECS: i64 = 600;
+/// 超过该条目数时清理过期记录
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// 单次上游发送的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    /// 上游返回 2xx
+    Succeeded,
+    /// 上游返回错误状态码 (请求未被处理)
+    Rejected(u16),
+    /// 传输层错误，上游可能已处理
+    Unknown,
+}
+
+/// 同一 requestId 的发送统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttemptRecord {
+    pub succeeded: u32,
+    pub rejected: u32,
+    pub unknown: u32,
+    last_seen: i64,
+}
+
+impl AttemptRecord {
+    pub fn attempts(&self) -> u32 {
+        self.succeeded + self.rejected + self.unknown
+    }
+
+    /// 成功次数超过 1，或在结果未知的发送之后又成功：上游可能已生成多次
+    pub fn possible_duplicate(&self) -> bool {
+        self.succeeded > 1 || (self.succeeded == 1 && self.unknown > 0)
+    }
+}
+
+pub struct AttemptLedger {
+    entries: Mutex<HashMap<String, AttemptRecord>>,
+}
+
+impl AttemptLedger {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录一次发送结果，返回该 requestId 更新后的统计
+    pub fn record(&self, request_id: &str, outcome: AttemptOutcome, now: i64) -> AttemptRecord {
+        let mut entries = self.entries.lock().unwrap_or_else(|p| p.into_inner());
+        if entries.len() >= PRUNE_THRESHOLD {
+            entries.retain(|_, r| now - r.last_seen < ENTRY_TTL_SECS);
+        }
+        let record = entries.entry(request_id.to_string()).or_default();
+        if now - record.last_seen >= ENTRY_TTL_SECS {
+            *record = AttemptRecord::default();
+        }
+        match outcome {
+            AttemptOutcome::Succeeded => record.succeeded += 1,
+            AttemptOutcome::Rejected(_) => record.rejected += 1,
+            AttemptOutcome::Unknown => record.unknown += 1,
+        }
+        record.last_seen = now;
+        record.clone()
+    }
+}
+
+impl Default for AttemptLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static LEDGER: Lazy<AttemptLedger> = Lazy::new(AttemptLedger::new);
+
+/// 将上游请求体中的 requestId 替换为当前请求的值，返回最终使用的值
+/// 只处理已带 requestId 的请求体 (countTokens 等不接受该字段)；
+/// 不在请求上下文中时 (例如流式续传闭包) 保留调用方已写入的值
+pub fn stamp_request_id(body: &mut Value) -> Option<String> {
+    let obj = body.as_object_mut()?;
+    if !obj.contains_key("requestId") {
+        return None;
+    }
+    if let Some(request_id) = crate::proxy::middleware::upstream_request_id() {
+        obj.insert("requestId".to_string(), Value::String(request_id));
+    }
+    obj.get("requestId").and_then(|v| v.as_str()).map(str::to_string)
+}
+
+/// 记录一次上游发送结果；疑似重复生成时输出告警 (requestId 与发送序号由外层 upstream span 携带)
+pub fn record_attempt(request_id: &str, outcome: AttemptOutcome) {
+    let record = LEDGER.record(request_id, outcome, chrono::Utc::now().timestamp());
+    tracing::debug!(?outcome, attempts = record.attempts(), "[Idempotency] 上游发送结果已记录");
+    if outcome == AttemptOutcome::Succeeded && record.possible_duplicate() {
+        tracing::warn!(
+            succeeded = record.succeeded,
+            unknown = record.unknown,
+            "[Idempotency] 同一请求多次成功或在结果未知后成功，上游可能重复生成并重复消耗配额"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::middleware::request_id::{scope_request, RequestId};
+
+    #[test]
+    fn flags_success_after_unknown_outcome() {
+        let ledger = AttemptLedger::new();
+        assert!(!ledger.record("r1", AttemptOutcome::Rejected(429), 100).possible_duplicate());
+        assert!(!ledger.record("r1", AttemptOutcome::Succeeded, 101).possible_duplicate());
+
+        ledger.record("r2", AttemptOutcome::Unknown, 100);
+        let record = ledger.record("r2", AttemptOutcome::Succeeded, 102);
+        assert!(record.possible_duplicate());
+        assert_eq!(record.attempts(), 2);
+
+        // 过期后重新计数
+        assert!(!ledger.record("r2", AttemptOutcome::Succeeded, 102 + ENTRY_TTL_SECS).possible_duplicate());
+    }
+
+    #[tokio::test]
+    async fn request_id_is_stable_across_rotation_attempts() {
+        let ids = scope_request(RequestId("client-1".to_string()), None, async {
+            // 每次轮换账号都会重新构造请求体 (各自带随机 requestId)
+            (0..3)
+                .map(|attempt| {
+                    let mut body = serde_json::json!({
+                        "project": format!("project-{}", attempt),
+                        "requestId": format!("agent-{}", uuid::Uuid::new_v4()),
+                    });
+                    stamp_request_id(&mut body)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        assert_eq!(ids, vec![Some("client-1".to_string()); 3]);
+
+        let keyed = scope_request(RequestId::generate(), Some("idem-9".to_string()), async {
+            let mut body = serde_json::json!({ "requestId": "agent-x" });
+            stamp_request_id(&mut body)
+        })
+        .await;
+        assert_eq!(keyed.as_deref(), Some("idem-9"));
+
+        // 请求上下文之外保留调用方写入的值
+        let mut body = serde_json::json!({ "requestId": "reopen-1" });
+        assert_eq!(stamp_request_id(&mut body).as_deref(), Some("reopen-1"));
+
+        // 不带 requestId 的请求体 (countTokens) 保持原样
+        let mut body = serde_json::json!({ "request": {} });
+        let stamped = scope_request(RequestId::generate(), None, async { stamp_request_id(&mut body) }).await;
+        assert!(stamped.is_none());
+        assert!(body.get("requestId").is_none());
+    }
+}
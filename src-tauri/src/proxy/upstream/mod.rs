@@ -1,6 +1,11 @@
 // Upstream 模块 - 上游客户端
 // 对应上游通讯接口
 
+pub mod cancel;
 pub mod client;
+pub mod errors;
+pub mod failover;
+pub mod idempotency;
 pub mod retry;
 pub mod models;
+pub mod network_reset;
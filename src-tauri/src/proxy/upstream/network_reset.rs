@@ -0,0 +1,202 @@
+// 网络状态重置 (休眠唤醒后的旧连接)
+// 笔记本休眠唤醒后，连接池中的连接已被对端关闭，最初几个请求会以连接重置失败，
+// 随后的 5xx / 冷却逻辑还可能把正常账号暂时移出调度。检测到网络状态变化时：
+// - 递增连接池代数，UpstreamClient 下次取用客户端时重建连接池
+// - 进入宽限期：期间除 429 外的失败不触发冷却、熔断与健康分惩罚
+// - 只输出一行 "network state reset" 日志 (宽限期内不会重复触发)
+// 检测方式：窗口内多个账号的连接重置突发，或后台心跳发现系统曾挂起
+use once_cell::sync::Lazy;
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::proxy::config::NetworkResetConfig;
+
+/// 后台心跳间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+static DETECTOR: Lazy<NetworkResetDetector> = Lazy::new(|| NetworkResetDetector::new(NetworkResetConfig::default()));
+static WATCH_RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub struct NetworkResetDetector {
+    config: Mutex<NetworkResetConfig>,
+    /// 窗口内的连接重置 (时间, 账号)
+    failures: Mutex<VecDeque<(Instant, String)>>,
+    grace_until: Mutex<Option<Instant>>,
+    /// 每次重置递增，UpstreamClient 据此判断是否需要重建连接池
+    generation: AtomicU64,
+}
+
+impl NetworkResetDetector {
+    pub fn new(config: NetworkResetConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            failures: Mutex::new(VecDeque::new()),
+            grace_until: Mutex::new(None),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_config(&self, config: &NetworkResetConfig) {
+        *self.config.lock().unwrap_or_else(|p| p.into_inner()) = config.clone();
+    }
+
+    fn config(&self) -> NetworkResetConfig {
+        self.config.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// 记录一次连接重置，达到突发阈值时触发重置 (返回是否触发)
+    pub fn record_connection_reset(&self, account: &str, now: Instant) -> bool {
+        let config = self.config();
+        if !config.enabled || self.in_grace(now) {
+            return false;
+        }
+        let window = Duration::from_secs(config.burst_window_secs);
+        let burst = {
+            let mut failures = self.failures.lock().unwrap_or_else(|p| p.into_inner());
+            while failures.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+                failures.pop_front();
+            }
+            failures.push_back((now, account.to_string()));
+            let accounts: HashSet<&str> = failures.iter().map(|(_, a)| a.as_str()).collect();
+            failures.len() >= config.burst_failures.max(1) as usize
+                && accounts.len() >= config.min_accounts.max(1) as usize
+        };
+        if burst {
+            self.trigger("connection resets across accounts", now);
+        }
+        burst
+    }
+
+    /// 心跳间隔比预期多出 resume_gap_secs 以上时视为系统曾挂起 (返回是否触发)
+    pub fn check_heartbeat(&self, expected: Duration, wall_elapsed: Duration, mono_elapsed: Duration, now: Instant) -> bool {
+        let config = self.config();
+        if !config.enabled || config.resume_gap_secs == 0 || self.in_grace(now) {
+            return false;
+        }
+        // 部分平台的单调时钟在挂起期间不前进，取两者较大值
+        let elapsed = wall_elapsed.max(mono_elapsed);
+        if elapsed.saturating_sub(expected) < Duration::from_secs(config.resume_gap_secs) {
+            return false;
+        }
+        self.trigger("system resume", now);
+        true
+    }
+
+    /// 刷新连接池并进入宽限期
+    pub fn trigger(&self, reason: &str, now: Instant) {
+        let grace_secs = self.config().grace_secs;
+        *self.grace_until.lock().unwrap_or_else(|p| p.into_inner()) = Some(now + Duration::from_secs(grace_secs));
+        self.failures.lock().unwrap_or_else(|p| p.into_inner()).clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "[Network] network state reset ({}): upstream connection pool flushed, cooldown penalties paused for {}s",
+            reason,
+            grace_secs
+        );
+    }
+
+    pub fn in_grace(&self, now: Instant) -> bool {
+        self.grace_until
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .is_some_and(|until| now < until)
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+/// 应用网络状态重置配置 (代理启动与配置热更新时调用)
+pub fn configure_network_reset(config: &NetworkResetConfig) {
+    DETECTOR.set_config(config);
+}
+
+/// 记录一次上游连接重置 (account 为区分账号的标识)
+pub fn record_connection_reset(account: &str) -> bool {
+    DETECTOR.record_connection_reset(account, Instant::now())
+}
+
+/// 当前是否处于网络重置后的宽限期
+pub fn in_grace_period() -> bool {
+    DETECTOR.in_grace(Instant::now())
+}
+
+/// 当前连接池代数
+pub fn pool_generation() -> u64 {
+    DETECTOR.generation()
+}
+
+/// 启动后台心跳，检测系统挂起后恢复 (进程内只启动一次)
+pub fn spawn_resume_watch() {
+    if WATCH_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            let wall = SystemTime::now();
+            let mono = Instant::now();
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+            let wall_elapsed = wall.elapsed().unwrap_or_default();
+            DETECTOR.check_heartbeat(HEARTBEAT_INTERVAL, wall_elapsed, mono.elapsed(), Instant::now());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> NetworkResetDetector {
+        NetworkResetDetector::new(NetworkResetConfig::default())
+    }
+
+    #[test]
+    fn test_reset_burst_across_accounts_triggers_once() {
+        let d = detector();
+        let start = Instant::now();
+        // 单个账号的连续重置不触发
+        for i in 0..5 {
+            assert!(!d.record_connection_reset("a", start + Duration::from_millis(i * 100)));
+        }
+        assert!(d.record_connection_reset("b", start + Duration::from_secs(1)));
+        assert_eq!(d.generation(), 1);
+        assert!(d.in_grace(start + Duration::from_secs(30)));
+
+        // 宽限期内不重复触发，结束后恢复惩罚
+        assert!(!d.record_connection_reset("c", start + Duration::from_secs(2)));
+        assert_eq!(d.generation(), 1);
+        assert!(!d.in_grace(start + Duration::from_secs(32)));
+    }
+
+    #[test]
+    fn test_failures_outside_window_do_not_accumulate() {
+        let d = detector();
+        let start = Instant::now();
+        assert!(!d.record_connection_reset("a", start));
+        assert!(!d.record_connection_reset("b", start + Duration::from_secs(11)));
+        assert!(!d.record_connection_reset("a", start + Duration::from_secs(22)));
+        assert_eq!(d.generation(), 0);
+
+        d.set_config(&NetworkResetConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        for account in ["a", "b", "c", "d"] {
+            assert!(!d.record_connection_reset(account, start + Duration::from_secs(23)));
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_gap_detects_resume() {
+        let d = detector();
+        let now = Instant::now();
+        let tick = Duration::from_secs(5);
+        assert!(!d.check_heartbeat(tick, Duration::from_secs(6), tick, now));
+        // 挂起期间单调时钟未前进，墙上时间跳过 10 分钟
+        assert!(d.check_heartbeat(tick, Duration::from_secs(600), tick, now));
+        assert!(d.in_grace(now + Duration::from_secs(1)));
+    }
+}
@@ -3,6 +3,11 @@
 
 use regex::Regex;
 use once_cell::sync::Lazy;
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::proxy::config::{BackoffConfig, RetryBudgetConfig};
 
 static DURATION_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"([\d.]+)\s*(ms|s|m|h)").unwrap()
@@ -66,10 +71,135 @@ pub fn parse_retry_delay(error_text: &str) -> Option<u64> {
     None
 }
 
+/// 计算第 attempt 次重试前的退避时间 (attempt 从 0 开始)
+/// 退避只按单次请求内的尝试次数计算，请求成功后自然归零，不影响后续请求
+pub fn backoff_delay(config: &BackoffConfig, attempt: usize) -> Duration {
+    let base = config.initial_delay_ms as f64 * config.multiplier.max(1.0).powi(attempt as i32);
+    let jitter_ratio = config.jitter.clamp(0.0, 1.0);
+    let jitter = if jitter_ratio > 0.0 {
+        base * rand::thread_rng().gen_range(0.0..jitter_ratio)
+    } else {
+        0.0
+    };
+    let delay_ms = (base + jitter).min(config.max_delay_ms as f64);
+    Duration::from_millis(delay_ms.round() as u64)
+}
+
+/// 按退避配置等待后再进入下一次尝试
+pub async fn sleep_backoff(config: &BackoffConfig, attempt: usize) {
+    let delay = backoff_delay(config, attempt);
+    tracing::debug!("[Backoff] attempt {} waiting {}ms", attempt + 1, delay.as_millis());
+    tokio::time::sleep(delay).await;
+}
+
+/// 全局重试预算 (参考 Finagle RetryBudget)
+/// 所有处理器共享一个令牌余额：重试前取出 1 个，首次尝试即成功的请求存回 1 个；
+/// 持续限流时余额很快耗尽，后续请求不再重试，避免对上游形成重试风暴
+pub struct RetryBudget {
+    balance: AtomicU32,
+    capacity: AtomicU32,
+    enabled: AtomicBool,
+}
+
+impl RetryBudget {
+    pub fn new(config: &RetryBudgetConfig) -> Self {
+        Self {
+            balance: AtomicU32::new(config.initial.min(config.capacity)),
+            capacity: AtomicU32::new(config.capacity),
+            enabled: AtomicBool::new(config.enabled),
+        }
+    }
+
+    /// 热更新上限与开关 (余额超出新上限时截断)
+    pub fn configure(&self, config: &RetryBudgetConfig) {
+        self.capacity.store(config.capacity, Ordering::Relaxed);
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.balance.fetch_min(config.capacity, Ordering::Relaxed);
+    }
+
+    /// 尝试为一次重试取出令牌；余额为 0 时返回 false
+    pub fn try_withdraw(&self) -> bool {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return true;
+        }
+        self.balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |b| b.checked_sub(1))
+            .is_ok()
+    }
+
+    /// 首次尝试即成功的请求归还 1 个令牌 (不超过上限)
+    pub fn deposit(&self) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let _ = self
+            .balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |b| (b < capacity).then_some(b + 1));
+    }
+
+    pub fn balance(&self) -> u32 {
+        self.balance.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(&RetryBudgetConfig::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(&RetryBudgetConfig {
+            enabled: true,
+            capacity: 3,
+            initial: 2,
+        });
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw(), "empty budget refuses retries");
+
+        for _ in 0..5 {
+            budget.deposit();
+        }
+        assert_eq!(budget.balance(), 3, "deposits are capped");
+
+        budget.configure(&RetryBudgetConfig {
+            enabled: true,
+            capacity: 1,
+            initial: 1,
+        });
+        assert_eq!(budget.balance(), 1);
+
+        budget.configure(&RetryBudgetConfig {
+            enabled: false,
+            capacity: 0,
+            initial: 0,
+        });
+        assert!(budget.try_withdraw(), "disabled budget never blocks");
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let config = BackoffConfig {
+            initial_delay_ms: 100,
+            multiplier: 2.0,
+            max_delay_ms: 1000,
+            jitter: 0.0,
+        };
+        assert_eq!(backoff_delay(&config, 0), Duration::from_millis(100));
+        assert_eq!(backoff_delay(&config, 2), Duration::from_millis(400));
+        assert_eq!(backoff_delay(&config, 10), Duration::from_millis(1000));
+
+        let jittered = BackoffConfig { jitter: 0.5, ..config };
+        for _ in 0..20 {
+            let d = backoff_delay(&jittered, 1).as_millis();
+            assert!((200..=300).contains(&d));
+        }
+    }
+
     #[test]
     fn test_parse_duration_ms() {
         assert_eq!(parse_duration_ms("1.5s"), Some(1500));
@@ -8,14 +8,10 @@ use crate::proxy::ZaiConfig;
 const ZAI_PAAZ_CHAT_COMPLETIONS_URL: &str = "https://api.z.ai/api/paas/v4/chat/completions";
 
 fn build_client(upstream_proxy: UpstreamProxyConfig, timeout_secs: u64) -> Result<reqwest::Client, String> {
-    let mut builder = reqwest::Client::builder()
+    let builder = reqwest::Client::builder()
         .timeout(Duration::from_secs(timeout_secs.max(5)));
-
-    if upstream_proxy.enabled && !upstream_proxy.url.is_empty() {
-        let proxy = reqwest::Proxy::all(&upstream_proxy.url)
-            .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
-        builder = builder.proxy(proxy);
-    }
+    let builder = crate::utils::http::apply_upstream_proxy(builder, Some(&upstream_proxy))
+        .map_err(|e| format!("Invalid upstream proxy: {}", e))?;
 
     builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
 }
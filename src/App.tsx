@@ -80,6 +80,19 @@ function App() {
       })
     );
 
+    // 关键事件系统通知 (限流与静默由后端处理)
+    unlistenPromises.push(
+      listen<{ title: string; body: string }>('notification://critical', async (event) => {
+        if (!('Notification' in window)) return;
+        if (Notification.permission === 'default') {
+          await Notification.requestPermission();
+        }
+        if (Notification.permission === 'granted') {
+          new Notification(event.payload.title, { body: event.payload.body });
+        }
+      })
+    );
+
     // Cleanup
     return () => {
       Promise.all(unlistenPromises).then(unlisteners => {
@@ -1,6 +1,7 @@
-import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, ToggleLeft, ToggleRight } from 'lucide-react';
+import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, Sprout, ToggleLeft, ToggleRight } from 'lucide-react';
 import { Account } from '../../types/account';
-import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor } from '../../utils/format';
+import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor, getBreakInProgress } from '../../utils/format';
+import { useConfigStore } from '../../stores/useConfigStore';
 import { cn } from '../../utils/cn';
 import { useTranslation } from 'react-i18next';
 
@@ -27,6 +28,8 @@ function AccountCard({ account, selected, onSelect, isCurrent, isRefreshing, isS
     const geminiImageModel = account.quota?.models.find(m => m.name === 'gemini-3-pro-image');
     const claudeModel = account.quota?.models.find(m => m.name === 'claude-sonnet-4-5-thinking');
     const isDisabled = Boolean(account.disabled);
+    const { config } = useConfigStore();
+    const breakIn = getBreakInProgress(account, config?.proxy.break_in);
 
     const getColorClass = (percentage: number) => {
         const color = getQuotaColor(percentage);
@@ -88,6 +91,18 @@ function AccountCard({ account, selected, onSelect, isCurrent, isRefreshing, isS
                                     {t('accounts.disabled').toUpperCase()}
                                 </span>
                             )}
+                            {breakIn && (
+                                <span
+                                    className="px-1.5 py-0.5 rounded-md bg-sky-100 dark:bg-sky-900/40 text-sky-700 dark:text-sky-300 text-[9px] font-bold flex items-center gap-1 shadow-sm border border-sky-200/50"
+                                    title={t('accounts.breaking_in_tooltip')}
+                                >
+                                    <Sprout className="w-2.5 h-2.5" />
+                                    <span>{breakIn.required > 0
+                                        ? t('accounts.breaking_in', { requests: breakIn.requests, required: breakIn.required })
+                                        : t('accounts.breaking_in_short')}</span>
+                                </span>
+                            )}
+
                             {account.quota?.is_forbidden && (
                                 <span className="px-1.5 py-0.5 rounded-md bg-red-100 dark:bg-red-900/40 text-red-600 dark:text-red-400 text-[9px] font-bold flex items-center gap-1 shadow-sm border border-red-200/50" title={t('accounts.forbidden_tooltip')}>
                                     <Lock className="w-2.5 h-2.5" />
@@ -1,6 +1,7 @@
-import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, ToggleLeft, ToggleRight } from 'lucide-react';
+import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, Sprout, ToggleLeft, ToggleRight } from 'lucide-react';
 import { Account } from '../../types/account';
-import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor } from '../../utils/format';
+import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor, getBreakInProgress } from '../../utils/format';
+import { useConfigStore } from '../../stores/useConfigStore';
 import { cn } from '../../utils/cn';
 import { useTranslation } from 'react-i18next';
 
@@ -28,6 +29,8 @@ function AccountRow({ account, selected, onSelect, isCurrent, isRefreshing, isSw
     const geminiImageModel = account.quota?.models.find(m => m.name.toLowerCase() === 'gemini-3-pro-image');
     const claudeModel = account.quota?.models.find(m => m.name.toLowerCase() === 'claude-sonnet-4-5-thinking');
     const isDisabled = Boolean(account.disabled);
+    const { config } = useConfigStore();
+    const breakIn = getBreakInProgress(account, config?.proxy.break_in);
 
     // 颜色映射，避免动态类名被 Tailwind purge
     const getColorClass = (percentage: number) => {
@@ -103,6 +106,18 @@ function AccountRow({ account, selected, onSelect, isCurrent, isRefreshing, isSw
                             </span>
                         )}
 
+                        {breakIn && (
+                            <span
+                                className="px-2 py-0.5 rounded-md bg-sky-100 dark:bg-sky-900/50 text-sky-700 dark:text-sky-300 text-[10px] font-bold flex items-center gap-1 shadow-sm border border-sky-200/50"
+                                title={t('accounts.breaking_in_tooltip')}
+                            >
+                                <Sprout className="w-2.5 h-2.5" />
+                                <span>{breakIn.required > 0
+                                    ? t('accounts.breaking_in', { requests: breakIn.requests, required: breakIn.required })
+                                    : t('accounts.breaking_in_short')}</span>
+                            </span>
+                        )}
+
                         {account.quota?.is_forbidden && (
                             <span className="px-2 py-0.5 rounded-md bg-red-100 dark:bg-red-900/50 text-red-600 dark:text-red-400 text-[10px] font-bold flex items-center gap-1 shadow-sm border border-red-200/50" title={t('accounts.forbidden_tooltip')}>
                                 <Lock className="w-2.5 h-2.5" />
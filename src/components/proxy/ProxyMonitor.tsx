@@ -5,7 +5,7 @@ import { useTranslation } from 'react-i18next';
 import { request as invoke } from '../../utils/request';
 import { Trash2, Search, X } from 'lucide-react';
 import { AppConfig } from '../../types/config';
-import { formatCompactNumber } from '../../utils/format';
+import { formatBytes, formatCompactNumber } from '../../utils/format';
 
 interface ProxyRequestLog {
     id: string;
@@ -20,6 +20,30 @@ interface ProxyRequestLog {
     response_body?: string;
     input_tokens?: number;
     output_tokens?: number;
+    prompt_size?: PromptSizeBreakdown;
+    warnings?: ConversionWarning[];
+    // 条件路由选中的上游模型
+    upstream_model?: string;
+}
+
+// 非致命转换警告 (被忽略的参数、截断的预算等)
+interface ConversionWarning {
+    code: string;
+    message: string;
+    path?: string;
+}
+
+interface SectionSize {
+    bytes: number;
+    tokens: number;
+}
+
+// 转换后上游请求体的体积构成
+interface PromptSizeBreakdown {
+    system: SectionSize;
+    contents: SectionSize;
+    tools: SectionSize;
+    attachments: Record<string, { count: number; bytes: number; tokens: number }>;
 }
 
 interface ProxyStats {
@@ -255,7 +279,33 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                 <div className="mt-5 pt-5 border-t border-gray-200 dark:border-slate-700">
                                     <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest mb-2">{t('monitor.details.model')}</span>
                                     <span className="font-mono font-black text-blue-600 dark:text-blue-400 break-all text-sm">{selectedLog.model || '-'}</span>
+                                    {selectedLog.upstream_model && (
+                                        <span className="font-mono font-semibold text-gray-600 dark:text-slate-300 break-all text-xs ml-2">→ {selectedLog.upstream_model}</span>
+                                    )}
                                 </div>
+                                {selectedLog.prompt_size && (
+                                    <div className="mt-5 pt-5 border-t border-gray-200 dark:border-slate-700">
+                                        <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest mb-2">{t('monitor.details.prompt_size')}</span>
+                                        <div className="font-mono text-[11px] flex flex-wrap gap-2 text-gray-700 dark:text-gray-300">
+                                            <span>system: {formatBytes(selectedLog.prompt_size.system.bytes)} / {formatCompactNumber(selectedLog.prompt_size.system.tokens)}t</span>
+                                            <span>contents: {formatBytes(selectedLog.prompt_size.contents.bytes)} / {formatCompactNumber(selectedLog.prompt_size.contents.tokens)}t</span>
+                                            <span>tools: {formatBytes(selectedLog.prompt_size.tools.bytes)} / {formatCompactNumber(selectedLog.prompt_size.tools.tokens)}t</span>
+                                            {Object.entries(selectedLog.prompt_size.attachments).map(([kind, a]) => (
+                                                <span key={kind}>{kind} ×{a.count}: {formatBytes(a.bytes)} / {formatCompactNumber(a.tokens)}t</span>
+                                            ))}
+                                        </div>
+                                    </div>
+                                )}
+                                {selectedLog.warnings && selectedLog.warnings.length > 0 && (
+                                    <div className="mt-5 pt-5 border-t border-gray-200 dark:border-slate-700">
+                                        <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest mb-2">{t('monitor.details.warnings')}</span>
+                                        <div className="font-mono text-[11px] flex flex-col gap-1 text-amber-700 dark:text-amber-400">
+                                            {selectedLog.warnings.map((w, i) => (
+                                                <span key={i}>[{w.code}]{w.path ? ` ${w.path}` : ''}: {w.message}</span>
+                                            ))}
+                                        </div>
+                                    </div>
+                                )}
                             </div>
 
                             {/* Payloads */}
@@ -2,6 +2,7 @@ import { useState, useEffect, useMemo } from 'react';
 import { useTranslation } from 'react-i18next';
 import { useNavigate } from 'react-router-dom';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import {
     Power,
     Copy,
@@ -36,6 +37,21 @@ interface ProxyStatus {
     active_accounts: number;
 }
 
+// 账号池状态 (proxy://pool-status)，status 为空表示账号池已恢复
+type PoolState = 'empty' | 'all_cooling_down' | 'all_unhealthy' | 'all_saturated' | 'all_busy' | 'all_capped' | 'all_off_schedule';
+
+// 停止服务时剩余的在途请求数 (proxy://shutdown-progress)
+interface ShutdownProgressEvent {
+    active_requests: number;
+}
+
+interface PoolStatusEvent {
+    status: { state: PoolState; until?: number } | null;
+    message: string | null;
+    retry_after_secs: number | null;
+    timestamp: number;
+}
+
 
 interface CollapsibleCardProps {
     title: string;
@@ -209,6 +225,8 @@ export default function ApiProxy() {
         base_url: '',
         active_accounts: 0,
     });
+    const [poolStatus, setPoolStatus] = useState<PoolStatusEvent | null>(null);
+    const [drainingRequests, setDrainingRequests] = useState(0);
 
     const [appConfig, setAppConfig] = useState<AppConfig | null>(null);
     const [loading, setLoading] = useState(false);
@@ -243,6 +261,16 @@ export default function ApiProxy() {
         return () => clearInterval(interval);
     }, []);
 
+    // 账号池状态：区分未配置账号 / 全部冷却 / 不健康 / 限速
+    useEffect(() => {
+        const unlisten = listen<PoolStatusEvent>('proxy://pool-status', (event) => {
+            setPoolStatus(event.payload.status ? event.payload : null);
+        });
+        return () => {
+            unlisten.then(fn => fn());
+        };
+    }, []);
+
     const loadConfig = async () => {
         try {
             const config = await invoke<AppConfig>('load_config');
@@ -489,7 +517,16 @@ export default function ApiProxy() {
         setLoading(true);
         try {
             if (status.running) {
-                await invoke('stop_proxy_service');
+                // 停止期间展示仍在等待完成的请求数
+                const unlisten = await listen<ShutdownProgressEvent>('proxy://shutdown-progress', (event) => {
+                    setDrainingRequests(event.payload.active_requests);
+                });
+                try {
+                    await invoke('stop_proxy_service');
+                } finally {
+                    unlisten();
+                    setDrainingRequests(0);
+                }
             } else {
                 // 使用当前的 appConfig.proxy 启动
                 await invoke('start_proxy_service', { config: appConfig.proxy });
@@ -647,6 +684,16 @@ print(response.text)`;
                                             : t('proxy.status.stopped')}
                                     </span>
                                 </div>
+                                {status.running && poolStatus?.status && (
+                                    <span
+                                        className="text-xs font-medium text-amber-600"
+                                        title={poolStatus.message || undefined}
+                                    >
+                                        {t(`proxy.status.pool.${poolStatus.status.state}`, {
+                                            seconds: poolStatus.retry_after_secs ?? 0,
+                                        })}
+                                    </span>
+                                )}
                             </div>
 
                             {/* 控制按钮 */}
@@ -669,7 +716,11 @@ print(response.text)`;
                                         } ${(loading || !appConfig) ? 'opacity-50 cursor-not-allowed' : ''}`}
                                 >
                                     <Power size={14} />
-                                    {loading ? t('proxy.status.processing') : (status.running ? t('proxy.action.stop') : t('proxy.action.start'))}
+                                    {loading
+                                        ? (drainingRequests > 0
+                                            ? t('proxy.status.draining', { count: drainingRequests })
+                                            : t('proxy.status.processing'))
+                                        : (status.running ? t('proxy.action.stop') : t('proxy.action.start'))}
                                 </button>
                             </div>
                         </div>
@@ -775,6 +826,22 @@ print(response.text)`;
                                                 ? t('proxy.config.allow_lan_access_hint_enabled')
                                                 : t('proxy.config.allow_lan_access_hint_disabled')}
                                         </p>
+                                        <div>
+                                            <label className="block text-xs font-medium text-gray-700 dark:text-gray-300 mb-1">
+                                                {t('proxy.config.host')}
+                                            </label>
+                                            <input
+                                                type="text"
+                                                value={appConfig.proxy.host ?? '127.0.0.1'}
+                                                onChange={(e) => updateProxyConfig({ host: e.target.value.trim() })}
+                                                placeholder="127.0.0.1"
+                                                disabled={status.running}
+                                                className="w-full px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs font-mono text-gray-900 dark:text-base-content focus:ring-2 focus:ring-blue-500 focus:border-transparent disabled:opacity-50 disabled:cursor-not-allowed"
+                                            />
+                                            <p className="mt-0.5 text-[10px] text-gray-500 dark:text-gray-400">
+                                                {t('proxy.config.host_hint')}
+                                            </p>
+                                        </div>
                                         {(appConfig.proxy.allow_lan_access || false) && (
                                             <p className="text-[10px] text-amber-600 dark:text-amber-500">
                                                 {t('proxy.config.allow_lan_access_warning')}
@@ -12,6 +12,7 @@ export interface Account {
     proxy_disabled_at?: number;
     created_at: number;
     last_used: number;
+    total_requests?: number; // 反代累计成功请求数
 }
 
 export interface TokenData {
@@ -1,23 +1,339 @@
 export interface UpstreamProxyConfig {
     enabled: boolean;
     url: string;
+    username?: string;
+    password?: string;
+    no_proxy?: string[];
+}
+
+// 企业 SSL 拦截代理：extra_ca_bundle 为 PEM 文件路径
+export interface UpstreamTlsConfig {
+    extra_ca_bundle?: string;
+    danger_accept_invalid_certs?: boolean;
+}
+
+// 休眠唤醒 / 连接突发重置后刷新上游连接池，grace_secs 内除 429 外的失败不计入冷却
+export interface NetworkResetConfig {
+    enabled: boolean;
+    burst_failures: number;
+    min_accounts: number;
+    burst_window_secs: number;
+    grace_secs: number;
+    resume_gap_secs: number; // 0 表示不检测系统挂起
 }
 
 export interface ProxyConfig {
     enabled: boolean;
     allow_lan_access?: boolean;
+    host?: string;
     auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
     port: number;
     api_key: string;
+    scoped_keys?: ScopedKeysConfig;
     auto_start: boolean;
     anthropic_mapping?: Record<string, string>;
     openai_mapping?: Record<string, string>;
     custom_mapping?: Record<string, string>;
     request_timeout: number;
+    prompt_log_mode?: 'preview' | 'hash' | 'off'; // 日志中对话内容的呈现方式 (默认 preview)
+    model_timeouts?: Record<string, number>; // 按模型覆盖的请求超时 (秒)
     enable_logging: boolean;
     upstream_proxy: UpstreamProxyConfig;
+    upstream_tls?: UpstreamTlsConfig;
+    network_reset?: NetworkResetConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    backoff?: BackoffConfig;
+    retry_budget?: RetryBudgetConfig;
+    circuit_breaker?: CircuitBreakerConfig;
+    rate_limit?: RateLimitConfig;
+    websocket?: WebSocketConfig;
+    signature_cache?: SignatureCacheConfig;
+    guard?: GuardConfig;
+    image_files?: ImageFilesConfig;
+    inline_media?: 'extended' | 'strict';
+    maintenance?: MaintenanceConfig;
+    cooldown?: CooldownConfig;
+    notifications?: NotificationConfig;
+    break_in?: BreakInConfig;
+    telemetry?: TelemetryConfig;
+    metrics?: MetricsConfig;
+    stream_resume?: StreamResumeConfig;
+    debug?: DebugConfig;
+    access_log?: AccessLogConfig;
+    storage?: StorageConfig;
+    quota_state?: QuotaStateConfig;
+    vision?: VisionConfig;
+    account_caps?: AccountCapsConfig;
+    account_schedules?: AccountSchedulesConfig;
+    thinking?: ThinkingBudgetConfig;
+    message_batches?: MessageBatchConfig;
+    openai_reasoning?: OpenAIReasoningConfig;
+    // extended 时非致命转换警告附加到响应体 (顶层 warnings / 流式 warnings 事件)
+    compat_mode?: 'strict' | 'extended';
+    report_model?: ReportModel; // 响应体 model 上报上游模型或请求的模型 (X-Report-Model 请求头可覆盖)
+    account_concurrency?: AccountConcurrencyConfig;
+    token_refresh?: TokenRefreshConfig;
+    model_routes?: ModelRouteRule[];
+    safety_settings?: SafetySetting[];
+    account_health_check?: AccountHealthCheckConfig;
+    stream_usage?: StreamUsageConfig;
+    sse_keepalive_secs?: number; // SSE 流空闲多少秒后发送 keepalive 注释，0 关闭 (默认 15)
+    session_rotation_interval?: number; // 账号上游 sessionId 使用多少秒后更换，0 关闭 (默认 0)
+}
+
+// 流式周期性用量事件；interval_secs / every_output_tokens 为 0 时不按该条件触发
+export interface StreamUsageConfig {
+    enabled: boolean;
+    interval_secs: number;
+    every_output_tokens: number;
+}
+
+// 后台账号健康探测：连续失败 failure_threshold 次的账号暂停调度，恢复后自动重新启用
+export interface AccountHealthCheckConfig {
+    enabled: boolean;
+    interval_secs: number;
+    probe_model: string;
+    failure_threshold: number;
+}
+
+// 透传到 Gemini safetySettings；未设置时所有类别为 OFF
+export interface SafetySetting {
+    category: string;
+    threshold: string;
+}
+
+// 账号并发限制，0 表示不限 / 不排队
+export interface AccountConcurrencyConfig {
+    max_concurrent_per_account: number;
+    queue_wait_secs: number;
+}
+
+// 条件路由：请求 alias 时按顺序匹配 variants，第一个满足 when 的变体生效
+export interface ModelRouteRule {
+    alias: string;
+    variants: ModelRouteVariant[];
+}
+
+export interface ModelRouteVariant {
+    model: string;
+    when?: RoutePredicate;
+}
+
+// 未设置的条件不参与判断；Token 范围为闭区间
+export interface RoutePredicate {
+    min_prompt_tokens?: number;
+    max_prompt_tokens?: number;
+    has_tools?: boolean;
+    has_images?: boolean;
+    stream?: boolean;
+}
+
+// access_token 距离过期不足 early_refresh_secs 秒时提前刷新
+export interface TokenRefreshConfig {
+    early_refresh_secs: number;
+}
+
+// OpenAI 协议思维链以 reasoning_content 输出；strip_reasoning 时完全不输出
+export interface OpenAIReasoningConfig {
+    strip_reasoning: boolean;
+}
+
+// Anthropic Message Batches (/v1/message_batches) 同步处理
+export interface MessageBatchConfig {
+    max_concurrency: number;
+    max_requests: number;
+}
+
+// 附加 API Key，allowed_models 为 glob 列表 (为空表示不限制)
+export interface ScopedKeysConfig {
+    keys: ScopedApiKey[];
+    check_resolved_model: boolean; // 按路由解析后的模型名校验 (默认按请求的模型名)
+}
+
+export interface ScopedApiKey {
+    key: string;
+    label: string;
+    allowed_models: string[];
+    report_model?: ReportModel | null; // 覆盖全局 report_model
+}
+
+export type ReportModel = 'upstream' | 'requested';
+
+// Anthropic extended thinking 映射 (未给出 budget_tokens 时的默认预算)
+export interface ThinkingBudgetConfig {
+    default_budget: number;
+}
+
+// 账号每日硬上限 (0 表示不限制)；达到后当日不再调度该账号，本地 0 点恢复
+export interface AccountCapsConfig {
+    daily_request_cap: number;
+    daily_token_cap: number;
+    overrides: Record<string, AccountCapOverride>; // 按账号邮箱覆盖
+}
+
+export interface AccountCapOverride {
+    daily_request_cap?: number | null;
+    daily_token_cap?: number | null;
+}
+
+// 账号可用时段 (按账号邮箱)；时段外不参与调度，但不计为不健康
+export interface AccountSchedulesConfig {
+    accounts: Record<string, AccountSchedule>;
+}
+
+export interface AccountSchedule {
+    timezone: string; // "local"、"UTC" 或 "+08:00"
+    windows: ScheduleWindow[];
+}
+
+export interface ScheduleWindow {
+    start: string; // HH:MM，end 早于 start 时跨越午夜
+    end: string;
+    days?: string[]; // 如 ["mon-fri"]，为空表示每天
+}
+
+// OpenAI image_url 远程图片：开启后由反代下载并内联 (关闭时以 fileData 透传 URL)
+export interface VisionConfig {
+    fetch_remote_images: boolean;
+    max_image_bytes: number;
+    fetch_timeout_secs: number;
+}
+
+// 账号配额消耗状态持久化 (重启后恢复冷却中账号的降权)
+export interface QuotaStateConfig {
+    path?: string | null; // 留空时使用数据目录下的 quota_state.json
+    flush_interval_secs: number;
+    initial_estimate: number;
+}
+
+// 请求日志库定期维护 (超过保留期的请求按天汇总，碎片整理，每周完整性检查)
+export interface StorageConfig {
+    enabled: boolean;
+    retention_days: number;
+    vacuum_threshold_percent: number;
+    interval_hours: number;
+}
+
+// 调试：以 TRACE 级别记录请求/响应体 (字段脱敏后)
+export interface DebugConfig {
+    log_bodies: boolean;
+    max_body_bytes: number;
+    redact_fields: string[];
+}
+
+// 结构化访问日志 (数据目录 logs/access.*.log)；log_bodies 开启时才写入请求 / 响应正文
+export interface AccessLogConfig {
+    enabled: boolean;
+    rotation: 'hourly' | 'daily';
+    max_files: number;
+    log_bodies: boolean;
+}
+
+// Claude 流式断线续传 (Last-Event-ID)，关闭时重连请求返回 409
+export interface StreamResumeConfig {
+    enabled: boolean;
+    max_events: number;
+    ttl_secs: number;
+}
+
+// OpenTelemetry 链路追踪 (OTLP/HTTP JSON 导出)
+export interface TelemetryConfig {
+    enabled: boolean;
+    endpoint: string;
+    headers?: Record<string, string>;
+    service_name: string;
+}
+
+// Prometheus 指标端点 (GET /metrics)：关闭时返回 404；require_auth 为 true 时抓取同样需要 API Key
+export interface MetricsConfig {
+    enabled: boolean;
+    require_auth: boolean;
+}
+
+// 新账号磨合期：前 hours 小时 / requests 次请求内降低调度权重 (任一达到即毕业，0 表示不启用该条件)
+export interface BreakInConfig {
+    enabled: boolean;
+    hours: number;
+    requests: number;
+    share_percent: number;
+    max_requests_per_minute: number;
+}
+
+export interface CooldownConfig {
+    rate_limit_secs: number;
+    server_error_secs: number;
+}
+
+export type NotificationKind = 'server_crashed' | 'no_healthy_accounts' | 'quota_low' | 'upstream_errors' | 'account_capped' | 'account_reauth_required';
+
+export interface NotificationConfig {
+    quiet: boolean;
+    events: NotificationKind[];
+    cooldown_secs: number;
+    quota_threshold_percent: number;
+    upstream_error_threshold: number;
+    upstream_error_window_secs: number;
+}
+
+export interface MaintenanceConfig {
+    max_concurrent: number;
+    shed_above_in_flight: number;
+}
+
+export interface ImageFilesConfig {
+    enabled: boolean;
+    retention_secs: number;
+}
+
+export interface GuardConfig {
+    enabled: boolean;
+    max_header_bytes: number;
+    max_failures: number;
+    failure_window_secs: number;
+    ban_secs: number;
+    exempt_loopback: boolean;
+}
+
+export interface SignatureCacheConfig {
+    max_entries: number;
+    ttl_secs: number;
+}
+
+export interface WebSocketConfig {
+    enabled: boolean;
+    max_connections: number;
+    max_requests_per_connection: number;
+    max_message_bytes: number;
+    ping_interval_secs: number;
+    idle_timeout_secs: number;
+}
+
+export interface RateLimitConfig {
+    enabled: boolean;
+    requests_per_second: number;
+    burst: number;
+    authenticated_requests_per_second: number;
+    authenticated_burst: number;
+}
+
+export interface CircuitBreakerConfig {
+    failure_threshold: number;
+    cooldown_seconds: number;
+}
+
+export interface BackoffConfig {
+    initial_delay_ms: number;
+    multiplier: number;
+    max_delay_ms: number;
+    jitter: number;
+}
+
+// 全局重试预算：重试消耗令牌，首次成功的请求归还令牌，耗尽时直接返回 503
+export interface RetryBudgetConfig {
+    enabled: boolean;
+    capacity: number;
+    initial: number;
 }
 
 export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
@@ -25,6 +341,7 @@ export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    session_ttl_secs?: number; // 会话绑定空闲过期时间 (秒)，0 表示不过期
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -1,5 +1,7 @@
 import { formatDistanceToNow } from 'date-fns';
 import { zhCN, enUS } from 'date-fns/locale';
+import { Account } from '../types/account';
+import { BreakInConfig } from '../types/config';
 
 export function formatRelativeTime(timestamp: number, language: string = 'zh-CN'): string {
     const locale = language === 'zh-CN' ? zhCN : enUS;
@@ -78,6 +80,18 @@ export function formatDate(timestamp: string | number | undefined | null): strin
     });
 }
 
+/**
+ * 计算新账号磨合进度 (与后端 break_in_status 一致)，已毕业或未启用时返回 null
+ */
+export function getBreakInProgress(account: Account, breakIn?: BreakInConfig): { requests: number; required: number } | null {
+    if (!breakIn?.enabled || (breakIn.hours === 0 && breakIn.requests === 0)) return null;
+    const requests = account.total_requests ?? 0;
+    const ageSecs = Date.now() / 1000 - account.created_at;
+    if (breakIn.hours > 0 && ageSecs >= breakIn.hours * 3600) return null;
+    if (breakIn.requests > 0 && requests >= breakIn.requests) return null;
+    return { requests, required: breakIn.requests };
+}
+
 export function formatCompactNumber(num: number): string {
     if (num === 0) return '0';
     if (num < 1000 && num > -1000) return num.toString();